@@ -0,0 +1,2050 @@
+use anyhow::{Error, anyhow, bail};
+use async_cell::sync::{AsyncCell, TakeRef};
+use futures_lite::future::block_on;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rayon::{ThreadPool, ThreadPoolBuilder, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering::Relaxed},
+        mpsc::sync_channel,
+    },
+    thread::sleep,
+    time::{Duration, Instant},
+};
+use weakref::{Ref, pin};
+
+use crate::model::{ModuleSource, TensorInfo};
+use crate::notify;
+use crate::slice::{TensorSlice, read_slice};
+use ggml_base::GgmlTypeId;
+
+pub struct Analysis {
+    pub tensor: TensorInfo,
+    /// Analyze only this part of the tensor, read without loading the rest
+    pub slice: Option<TensorSlice>,
+    pub max_bin_count: usize,
+    /// Bin the signed histogram over just this range of values, instead of estimating a range
+    pub range: Option<(f32, f32)>,
+    /// Too large to hold in memory, so only the statistics and signed histogram are computed
+    pub streaming: bool,
+    /// Finish once the stages requested up front are done, instead of waiting for the UI to ask
+    /// for more
+    pub batch: bool,
+    /// Percent complete of the current stage (reading the tensor, then SVD)
+    pub progress: AtomicU64,
+    /// Percent complete of re-quantization, which can run alongside the SVD
+    pub quant_error_progress: AtomicU64,
+    pub stats: OnceLock<Stats>,
+    pub preview: OnceLock<Vec<f32>>,
+    pub histogram_go: AtomicBool,
+    pub histogram: OnceLock<Histogram>,
+    pub magnitude: OnceLock<Magnitude>,
+    pub init_fit: OnceLock<InitFit>,
+    pub channels: OnceLock<Channels>,
+    /// Only computed for tensors with three or more axes
+    pub slice_norms: OnceLock<SliceNorms>,
+    /// Percent read of the whole tensor, when only a slice of it was loaded for the rest
+    pub slice_norms_progress: AtomicU64,
+    pub spectrum_go: AtomicBool,
+    pub spectrum: OnceLock<Spectrum>,
+    pub quant_error_go: AtomicBool,
+    pub quant_errors: OnceLock<Vec<QuantError>>,
+    pub error: OnceLock<Error>,
+    pub timings: Timings,
+}
+
+impl Analysis {
+    /// Fills in whatever `other` has finished, so a tensor analyzed before shows its results
+    /// right away
+    pub fn copy_results(&self, other: &Analysis) {
+        fn copy<T: Clone>(to: &OnceLock<T>, from: &OnceLock<T>) {
+            if let Some(value) = from.get() {
+                let _ = to.set(value.clone());
+            }
+        }
+        copy(&self.stats, &other.stats);
+        copy(&self.preview, &other.preview);
+        copy(&self.histogram, &other.histogram);
+        copy(&self.magnitude, &other.magnitude);
+        copy(&self.init_fit, &other.init_fit);
+        copy(&self.channels, &other.channels);
+        copy(&self.slice_norms, &other.slice_norms);
+        copy(&self.spectrum, &other.spectrum);
+        copy(&self.quant_errors, &other.quant_errors);
+    }
+}
+
+/// When an analysis started and how long each of its stages took
+#[derive(Debug, Default)]
+pub struct Timings {
+    started: OnceLock<Instant>,
+    running: Mutex<Option<&'static str>>,
+    stages: Mutex<Vec<(&'static str, Duration)>>,
+}
+
+impl Timings {
+    fn start(&self) {
+        let _ = self.started.set(Instant::now());
+    }
+
+    /// Time since the analysis started, or `None` while it is still queued
+    pub fn elapsed(&self) -> Option<Duration> {
+        Some(self.started.get()?.elapsed())
+    }
+
+    /// The stage being worked on right now, if any
+    pub fn running(&self) -> Option<&'static str> {
+        *self.running.lock().unwrap()
+    }
+
+    /// How long `stage` took, once it has finished
+    pub fn stage(&self, stage: &str) -> Option<Duration> {
+        let stages = self.stages.lock().unwrap();
+        stages
+            .iter()
+            .find(|(name, _)| *name == stage)
+            .map(|(_, time)| *time)
+    }
+
+    /// Records that `stage` finished after `time`, and wakes the UI to show its result
+    fn finish(&self, stage: &'static str, time: Duration) {
+        *self.running.lock().unwrap() = None;
+        self.stages.lock().unwrap().push((stage, time));
+        notify::notify();
+    }
+}
+
+/// Runs `work` as `stage`, recording how long it took
+fn timed<T>(timings: Ref<Timings>, stage: &'static str, work: impl FnOnce() -> T) -> T {
+    timings.inspect(|t| *t.running.lock().unwrap() = Some(stage));
+    let start = Instant::now();
+    let result = work();
+    timings.inspect(|t| t.finish(stage, start.elapsed()));
+    result
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BarChart {
+    pub bins: Vec<usize>,
+    pub left: f32,
+    pub right: f32,
+    pub continues_past_left: bool,
+    pub continues_past_right: bool,
+}
+
+impl Default for BarChart {
+    fn default() -> Self {
+        BarChart {
+            bins: vec![0],
+            left: 0.0,
+            right: 1.0,
+            continues_past_left: true,
+            continues_past_right: true,
+        }
+    }
+}
+
+const QUARTILE_SAMPLES: usize = 200;
+
+/// How values are sampled to estimate histogram ranges and percentiles
+#[derive(Debug, Clone, Copy)]
+pub struct Sampling {
+    /// Draw every sample from this seed, so the same tensor always gets the same ranges
+    pub seed: Option<u64>,
+    /// Tensors with at most this many values skip sampling and use exact quantiles
+    pub exact_limit: usize,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Sampling {
+            seed: None,
+            exact_limit: QUARTILE_SAMPLES,
+        }
+    }
+}
+
+static SAMPLING: OnceLock<Sampling> = OnceLock::new();
+
+/// Chooses how every later analysis samples values, which can only be set once at startup
+pub fn set_sampling(sampling: Sampling) -> Result<(), Error> {
+    SAMPLING
+        .set(sampling)
+        .map_err(|_| anyhow!("sampling was already configured"))
+}
+
+fn sampling() -> Sampling {
+    SAMPLING.get().copied().unwrap_or_default()
+}
+
+/// A generator for one sample, seeded if [`Sampling::seed`] is set
+fn sample_rng() -> StdRng {
+    match sampling().seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+/// Small enough to show every value in the analysis panel
+pub fn is_previewable(tensor: &TensorInfo) -> bool {
+    match tensor.shape.as_slice() {
+        [] => true,
+        &[n] => n <= 1024,
+        &[h, w] => h <= 64 && w <= 64,
+        shape => shape.iter().product::<u64>() <= 64 * 64,
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Stats {
+    pub mean: f64,
+    pub std: f64,
+    pub min: f32,
+    pub max: f32,
+    pub l1_norm: f64,
+    pub l2_norm: f64,
+    pub zero_fraction: f64,
+    pub nan_count: usize,
+    pub inf_count: usize,
+    #[serde(default)]
+    pub skewness: f64,
+    /// Zero for a Gaussian, and large when outliers dominate the distribution
+    #[serde(default)]
+    pub excess_kurtosis: f64,
+    /// RMS distance, in standard deviations, between the sorted values and the quantiles of a
+    /// Gaussian with the same mean and std
+    #[serde(default)]
+    pub qq_deviation: f64,
+}
+
+/// At most this many evenly spaced values are sorted to compare against a Gaussian
+const QQ_SAMPLES: usize = 1000;
+/// Excess kurtosis above this counts as heavy-tailed, which tends to quantize poorly
+const HEAVY_TAIL_KURTOSIS: f64 = 10.0;
+/// QQ deviation above this counts as heavy-tailed
+const HEAVY_TAIL_QQ_DEVIATION: f64 = 0.5;
+
+/// The `p` quantile of the standard normal distribution, from Acklam's rational approximation
+fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e1,
+        2.209460984245205e2,
+        -2.759285104469687e2,
+        1.38357751867269e2,
+        -3.066479806614716e1,
+        2.506628277459239,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1,
+        1.615858368580409e2,
+        -1.556989798598866e2,
+        6.680131188771972e1,
+        -1.328068155288572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3,
+        -3.223964580411365e-1,
+        -2.400758277161838,
+        -2.549732539343734,
+        4.374664141464968,
+        2.938163982698783,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-3,
+        3.224671290700398e-1,
+        2.445134137142996,
+        3.754408661907416,
+    ];
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    if p < 0.02425 {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - 0.02425 {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+/// The standard normal CDF, using the approximation of erf from Abramowitz and Stegun 7.1.26
+fn normal_cdf(z: f64) -> f64 {
+    let x = z.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let poly = ((((1.061405429 * t - 1.453152027) * t + 1.421413741) * t - 0.284496736) * t
+        + 0.254829592)
+        * t;
+    let erf = 1.0 - poly * (-x * x).exp();
+    0.5 * (1.0 + erf.copysign(z))
+}
+
+/// Compares evenly spaced finite values of `data` against a Gaussian fit with `mean` and `std`
+fn qq_deviation(data: &[f32], mean: f64, std: f64) -> f64 {
+    if std <= 0.0 {
+        return 0.0;
+    }
+    let step = data.len().div_ceil(QQ_SAMPLES).max(1);
+    let mut sample: Vec<f64> = data
+        .iter()
+        .step_by(step)
+        .filter(|x| x.is_finite())
+        .map(|&x| (x as f64 - mean) / std)
+        .collect();
+    if sample.is_empty() {
+        return 0.0;
+    }
+    sample.sort_unstable_by(f64::total_cmp);
+    let n = sample.len() as f64;
+    let sum_sq: f64 = sample
+        .iter()
+        .enumerate()
+        .map(|(i, &z)| (z - normal_quantile((i as f64 + 0.5) / n)).powi(2))
+        .sum();
+    (sum_sq / n).sqrt()
+}
+
+impl Stats {
+    pub fn new(data: &[f32]) -> Stats {
+        let mut finite = 0usize;
+        let mut zeros = 0usize;
+        let mut nan_count = 0usize;
+        let mut inf_count = 0usize;
+        let mut min = f32::INFINITY;
+        let mut max = f32::NEG_INFINITY;
+        let mut sum = 0.0f64;
+        let mut l1 = 0.0f64;
+        let mut l2 = 0.0f64;
+        for &x in data {
+            if x.is_nan() {
+                nan_count += 1;
+                continue;
+            }
+            if x.is_infinite() {
+                inf_count += 1;
+                continue;
+            }
+            if x == 0.0 {
+                zeros += 1;
+            }
+            finite += 1;
+            min = min.min(x);
+            max = max.max(x);
+            let x = x as f64;
+            sum += x;
+            l1 += x.abs();
+            l2 += x * x;
+        }
+
+        // Second pass for numerically stable central moments
+        let mean = if finite > 0 { sum / finite as f64 } else { 0.0 };
+        let (mut m2, mut m3, mut m4) = (0.0f64, 0.0f64, 0.0f64);
+        for x in data.iter().filter(|x| x.is_finite()) {
+            let d = *x as f64 - mean;
+            let d2 = d * d;
+            m2 += d2;
+            m3 += d2 * d;
+            m4 += d2 * d2;
+        }
+        let n = finite.max(1) as f64;
+        let variance = m2 / n;
+        let (skewness, excess_kurtosis) = if variance > 0.0 {
+            (m3 / n / variance.powf(1.5), m4 / n / variance.powi(2) - 3.0)
+        } else {
+            (0.0, 0.0)
+        };
+
+        Stats {
+            mean,
+            std: variance.sqrt(),
+            min,
+            max,
+            l1_norm: l1,
+            l2_norm: l2.sqrt(),
+            zero_fraction: zeros as f64 / data.len().max(1) as f64,
+            nan_count,
+            inf_count,
+            skewness,
+            excess_kurtosis,
+            qq_deviation: qq_deviation(data, mean, variance.sqrt()),
+        }
+    }
+
+    /// Whether the tails are far heavier than a Gaussian's
+    pub fn is_heavy_tailed(&self) -> bool {
+        self.excess_kurtosis > HEAVY_TAIL_KURTOSIS || self.qq_deviation > HEAVY_TAIL_QQ_DEVIATION
+    }
+
+    /// Combines the statistics of two disjoint runs of `len` and `other_len` values
+    pub fn merge(&self, len: usize, other: &Stats, other_len: usize) -> Stats {
+        let n_a = (len - self.nan_count - self.inf_count) as f64;
+        let n_b = (other_len - other.nan_count - other.inf_count) as f64;
+        let n = n_a + n_b;
+        // Sums of powers of deviations from the mean, recovered from each side's moments
+        let moments = |stats: &Stats, n: f64| {
+            let variance = stats.std.powi(2);
+            (
+                variance * n,
+                stats.skewness * variance.powf(1.5) * n,
+                (stats.excess_kurtosis + 3.0) * variance.powi(2) * n,
+            )
+        };
+        let (m2_a, m3_a, m4_a) = moments(self, n_a);
+        let (m2_b, m3_b, m4_b) = moments(other, n_b);
+        let (mean, variance, skewness, excess_kurtosis, qq_deviation) = if n > 0.0 {
+            let delta = other.mean - self.mean;
+            let m2 = m2_a + m2_b + delta.powi(2) * n_a * n_b / n;
+            let m3 = m3_a
+                + m3_b
+                + delta.powi(3) * n_a * n_b * (n_a - n_b) / n.powi(2)
+                + 3.0 * delta * (n_a * m2_b - n_b * m2_a) / n;
+            let m4 = m4_a
+                + m4_b
+                + delta.powi(4) * n_a * n_b * (n_a * n_a - n_a * n_b + n_b * n_b) / n.powi(3)
+                + 6.0 * delta.powi(2) * (n_a * n_a * m2_b + n_b * n_b * m2_a) / n.powi(2)
+                + 4.0 * delta * (n_a * m3_b - n_b * m3_a) / n;
+            let variance = m2 / n;
+            let (skewness, excess_kurtosis) = if variance > 0.0 {
+                (m3 / n / variance.powf(1.5), m4 / n / variance.powi(2) - 3.0)
+            } else {
+                (0.0, 0.0)
+            };
+            // Only an approximation, since each side was compared against its own fit
+            let qq_deviation = (self.qq_deviation * n_a + other.qq_deviation * n_b) / n;
+            (
+                self.mean + delta * n_b / n,
+                variance,
+                skewness,
+                excess_kurtosis,
+                qq_deviation,
+            )
+        } else {
+            (0.0, 0.0, 0.0, 0.0, 0.0)
+        };
+        let zeros = self.zero_fraction * len as f64 + other.zero_fraction * other_len as f64;
+        Stats {
+            mean,
+            std: variance.sqrt(),
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            l1_norm: self.l1_norm + other.l1_norm,
+            l2_norm: self.l2_norm.hypot(other.l2_norm),
+            zero_fraction: zeros / (len + other_len).max(1) as f64,
+            nan_count: self.nan_count + other.nan_count,
+            inf_count: self.inf_count + other.inf_count,
+            skewness,
+            excess_kurtosis,
+            qq_deviation,
+        }
+    }
+}
+
+/// Folds the stats of `chunk` into a running total of stats and value count
+fn merge_chunk(total: &mut Option<(Stats, usize)>, chunk: &[f32]) {
+    let chunk_stats = Stats::new(chunk);
+    *total = Some(match total.take() {
+        Some((stats, len)) => (
+            stats.merge(len, &chunk_stats, chunk.len()),
+            len + chunk.len(),
+        ),
+        None => (chunk_stats, chunk.len()),
+    });
+}
+
+/// Integer histograms with at most this many distinct values list each value's count
+const VALUE_COUNT_LIMIT: usize = 8;
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Histogram {
+    pub min: f32,
+    pub max: f32,
+    pub chart: BarChart,
+    /// Every bin covers a whole number of integers, centered on them
+    #[serde(default)]
+    pub integer: bool,
+    /// Values outside the chart are left out, rather than counted in the edge bins
+    #[serde(default)]
+    pub zoomed: bool,
+}
+
+/// How the signed histogram of a tensor is binned
+#[derive(Debug, Clone, Copy)]
+struct Binning {
+    max_bin_count: usize,
+    range: Option<(f32, f32)>,
+}
+
+impl Histogram {
+    pub fn new(
+        data: &[f32],
+        max_bin_count: usize,
+        force_min_zero: bool,
+        cancel: Ref<()>,
+    ) -> Result<Histogram, Error> {
+        if data.is_empty() {
+            bail!("tensor is empty");
+        }
+
+        // For large datasets, use random sampling to estimate quantiles
+        let sample_data = if data.len() > QUARTILE_SAMPLES.max(sampling().exact_limit) {
+            let mut rng = sample_rng();
+            data.choose_multiple(&mut rng, QUARTILE_SAMPLES)
+                .copied()
+                .collect()
+        } else {
+            data.to_vec()
+        };
+        if !cancel.is_alive() {
+            bail!("canceled");
+        }
+
+        // Find actual min/max from full dataset
+        let min = data.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = data.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+
+        let mut histogram = Histogram::with_range(
+            sample_data,
+            min,
+            max,
+            data.len(),
+            max_bin_count,
+            force_min_zero,
+        );
+        histogram.add(data);
+        Ok(histogram)
+    }
+
+    /// Counts integer data exactly, one value per bin when they fit in `max_bin_count`
+    pub fn integer(data: &[f32], max_bin_count: usize) -> Result<Histogram, Error> {
+        let min = data.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = data.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let mut histogram = Histogram::with_integer_range(min, max, max_bin_count)?;
+        histogram.add(data);
+        Ok(histogram)
+    }
+
+    /// Creates empty bins spanning `min..=max` which never split an integer between two bins
+    fn with_integer_range(min: f32, max: f32, max_bin_count: usize) -> Result<Histogram, Error> {
+        if !min.is_finite() || !max.is_finite() {
+            bail!("tensor is empty");
+        }
+        let span = (max - min) as f64 + 1.0;
+        let width = (span / max_bin_count.max(1) as f64).ceil();
+        let bin_count = (span / width).ceil() as usize;
+        let left = min - 0.5;
+        Ok(Histogram {
+            min,
+            max,
+            chart: BarChart {
+                bins: vec![0usize; bin_count],
+                left,
+                right: left + (bin_count as f64 * width) as f32,
+                continues_past_left: false,
+                continues_past_right: false,
+            },
+            integer: true,
+            zoomed: false,
+        })
+    }
+
+    /// Creates empty bins spanning exactly `left..right`, for data ranging over `min..=max`
+    pub fn zoomed(min: f32, max: f32, (left, right): (f32, f32), max_bin_count: usize) -> Self {
+        Histogram {
+            min,
+            max,
+            chart: BarChart {
+                bins: vec![0usize; max_bin_count.max(1)],
+                left,
+                right,
+                continues_past_left: min < left,
+                continues_past_right: max > right,
+            },
+            integer: false,
+            zoomed: true,
+        }
+    }
+
+    /// Each value and how often it occurs, when the bins are one integer wide and only a few
+    /// are occupied
+    pub fn value_counts(&self) -> Option<Vec<(i64, usize)>> {
+        let chart = &self.chart;
+        let width = (chart.right - chart.left) / chart.bins.len() as f32;
+        if !self.integer || width != 1.0 {
+            return None;
+        }
+        let first = (chart.left + 0.5) as i64;
+        let counts: Vec<_> = chart
+            .bins
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(i, &count)| (first + i as i64, count))
+            .collect();
+        (counts.len() <= VALUE_COUNT_LIMIT).then_some(counts)
+    }
+
+    /// Creates empty bins spanning the display range estimated from a sample of the data
+    fn with_range(
+        mut sorted_sample: Vec<f32>,
+        min: f32,
+        max: f32,
+        len: usize,
+        max_bin_count: usize,
+        force_min_zero: bool,
+    ) -> Histogram {
+        sorted_sample.retain(|x| x.is_finite());
+        sorted_sample.sort_unstable_by(f32::total_cmp);
+
+        // Calculate display range
+        let mut left = if force_min_zero { 0.0 } else { min };
+        let mut right = max;
+
+        if sorted_sample.len() >= QUARTILE_SAMPLES {
+            // Use 5% and 95% percentiles to estimate range
+            let p05_idx = ((sorted_sample.len() - 1) as f32 * 0.05) as usize;
+            let p95_idx = ((sorted_sample.len() - 1) as f32 * 0.95) as usize;
+            let q05 = sorted_sample[p05_idx];
+            let q95 = sorted_sample[p95_idx];
+
+            if !force_min_zero {
+                // Estimate 0% from 5% and 95% percentiles
+                left = q05 - 0.05 * (q95 - q05) / 0.90;
+            }
+
+            // Estimate 100% from 5% and 95% percentiles, then add 15%
+            right = q95 + 0.05 * (q95 - q05) / 0.90;
+            right += 0.15 * right / 0.85;
+        }
+
+        let bin_count = (len / 5).clamp(5, max_bin_count);
+
+        // Determine continues_past flags based on range estimation
+        let continues_past_left = !force_min_zero && sorted_sample.len() >= QUARTILE_SAMPLES;
+        let continues_past_right = sorted_sample.len() >= QUARTILE_SAMPLES;
+
+        Histogram {
+            min,
+            max,
+            chart: BarChart {
+                bins: vec![0usize; bin_count],
+                left,
+                right,
+                continues_past_left,
+                continues_past_right,
+            },
+            integer: false,
+            zoomed: false,
+        }
+    }
+
+    fn add(&mut self, data: &[f32]) {
+        let BarChart {
+            bins, left, right, ..
+        } = &mut self.chart;
+        let bins_end = (bins.len() - 1) as f32;
+        let mut scale = bins.len() as f32 / (*right - *left);
+        scale = if scale.is_finite() { scale } else { 1.0 };
+
+        for x in data {
+            if self.zoomed && !(*left..=*right).contains(x) {
+                continue;
+            }
+            let bin = ((x - *left) * scale).clamp(0.0, bins_end);
+            if !bin.is_finite() {
+                continue;
+            }
+            let bin = bin as usize;
+            bins[bin] += 1;
+        }
+    }
+}
+
+/// Uniform random sample of a stream of values (Algorithm R)
+struct Reservoir {
+    seen: usize,
+    values: Vec<f32>,
+}
+
+impl Reservoir {
+    fn new() -> Self {
+        Reservoir {
+            seen: 0,
+            values: Vec::with_capacity(QUARTILE_SAMPLES),
+        }
+    }
+
+    fn add(&mut self, data: &[f32], rng: &mut impl rand::Rng) {
+        for &x in data {
+            self.seen += 1;
+            if self.values.len() < QUARTILE_SAMPLES {
+                self.values.push(x);
+            } else {
+                let i = rng.gen_range(0..self.seen);
+                if i < QUARTILE_SAMPLES {
+                    self.values[i] = x;
+                }
+            }
+        }
+    }
+}
+
+const PERCENTILE_SAMPLES: usize = 1 << 20;
+
+/// Distribution of |x|, where outliers that matter for quantization become visible
+#[derive(Default, Debug, Clone)]
+pub struct Magnitude {
+    pub histogram: Histogram,
+    /// Histogram of log10|x| over the nonzero values, if there are any
+    pub log_histogram: Option<Histogram>,
+    pub p50: f32,
+    pub p99: f32,
+    pub p999: f32,
+}
+
+impl Magnitude {
+    pub fn new(data: &[f32], max_bin_count: usize, cancel: Ref<()>) -> Result<Magnitude, Error> {
+        let magnitudes: Vec<f32> = data
+            .iter()
+            .filter(|x| x.is_finite())
+            .map(|x| x.abs())
+            .collect();
+        let histogram = Histogram::new(&magnitudes, max_bin_count, true, cancel)?;
+        let logs: Vec<f32> = magnitudes
+            .iter()
+            .filter(|&&x| x > 0.0)
+            .map(|x| x.log10())
+            .collect();
+        let log_histogram = if logs.is_empty() {
+            None
+        } else {
+            Some(Histogram::new(&logs, max_bin_count, false, cancel)?)
+        };
+
+        // Tail percentiles need far more samples than the display range estimate
+        let mut sample: Vec<f32> =
+            if magnitudes.len() > PERCENTILE_SAMPLES.max(sampling().exact_limit) {
+                let mut rng = sample_rng();
+                magnitudes
+                    .choose_multiple(&mut rng, PERCENTILE_SAMPLES)
+                    .copied()
+                    .collect()
+            } else {
+                magnitudes
+            };
+        sample.sort_unstable_by(f32::total_cmp);
+        let percentile = |p: f64| sample[((sample.len() - 1) as f64 * p).round() as usize];
+
+        Ok(Magnitude {
+            histogram,
+            log_histogram,
+            p50: percentile(0.5),
+            p99: percentile(0.99),
+            p999: percentile(0.999),
+        })
+    }
+}
+
+/// Norms below this fraction of the median count as dead channels
+const DEAD_CHANNEL_RATIO: f32 = 1e-6;
+/// Norms above this multiple of the median count as outlier channels
+const OUTLIER_CHANNEL_RATIO: f32 = 5.0;
+
+/// Bins of the comparison against init distributions, spread evenly over `INIT_FIT_TAILS`
+/// standard deviations of the Gaussian fit on either side of zero
+const INIT_FIT_BINS: usize = 64;
+const INIT_FIT_TAILS: f64 = 4.0;
+/// A tensor this close to an init distribution, in KL divergence and scale, is likely untrained
+const INIT_MATCH_KL: f64 = 0.01;
+const INIT_MATCH_SCALE: f64 = 0.05;
+
+/// How closely a tensor's values match a zero-mean Gaussian and the distributions that common
+/// initializations draw weights from, given the fans of its shape
+#[derive(Debug, Clone)]
+pub struct InitFit {
+    /// Std of the zero-mean Gaussian fit, which is the RMS of the values
+    pub rms: f64,
+    /// KL divergence of the values from the Gaussian fit, in nats
+    pub gaussian_kl: f64,
+    /// Empty unless the tensor has at least two axes to take fans from
+    pub references: Vec<InitReference>,
+}
+
+#[derive(Debug, Clone)]
+pub struct InitReference {
+    pub name: &'static str,
+    pub std: f64,
+    /// `rms / std`, near 1 for a tensor still at this init
+    pub scale_ratio: f64,
+    /// KL divergence of the values from this distribution, in nats
+    pub kl: f64,
+}
+
+impl InitFit {
+    /// `None` if there are no finite nonzero values to fit
+    pub fn new(data: &[f32], shape: &[u64]) -> Option<InitFit> {
+        let (count, sum_sq) = data
+            .iter()
+            .filter(|x| x.is_finite())
+            .fold((0usize, 0.0), |(n, s), &x| (n + 1, s + (x as f64).powi(2)));
+        if count == 0 || sum_sq == 0.0 {
+            return None;
+        }
+        let rms = (sum_sq / count as f64).sqrt();
+
+        // The first and last bins catch the tails beyond the rest
+        let width = INIT_FIT_TAILS * rms;
+        let step = 2.0 * width / INIT_FIT_BINS as f64;
+        let mut counts = vec![0usize; INIT_FIT_BINS + 2];
+        for &x in data.iter().filter(|x| x.is_finite()) {
+            let x = x as f64;
+            let bin = if x < -width {
+                0
+            } else {
+                (1 + ((x + width) / step) as usize).min(INIT_FIT_BINS + 1)
+            };
+            counts[bin] += 1;
+        }
+        let kl = |cdf: &dyn Fn(f64) -> f64| -> f64 {
+            let mut below = 0.0;
+            let mut total = 0.0;
+            for (i, &hits) in counts.iter().enumerate() {
+                let above = if i > INIT_FIT_BINS {
+                    1.0
+                } else {
+                    cdf(-width + i as f64 * step)
+                };
+                let q = (above - below).max(1e-12);
+                below = above;
+                if hits > 0 {
+                    let p = hits as f64 / count as f64;
+                    total += p * (p / q).ln();
+                }
+            }
+            total.max(0.0)
+        };
+        let gaussian_kl = kl(&|x| normal_cdf(x / rms));
+
+        let mut references = Vec::new();
+        if let [fan_out, fan_in, kernel @ ..] = shape {
+            let receptive = kernel.iter().product::<u64>() as f64;
+            let fan_in = *fan_in as f64 * receptive;
+            let fan_out = *fan_out as f64 * receptive;
+            let normal = |std: f64| move |x: f64| normal_cdf(x / std);
+            let uniform = |bound: f64| move |x: f64| ((x + bound) / (2.0 * bound)).clamp(0.0, 1.0);
+            let mut add = |name, std: f64, cdf: &dyn Fn(f64) -> f64| {
+                references.push(InitReference {
+                    name,
+                    std,
+                    scale_ratio: rms / std,
+                    kl: kl(cdf),
+                })
+            };
+            let kaiming = (2.0 / fan_in).sqrt();
+            add("Kaiming normal", kaiming, &normal(kaiming));
+            let xavier = (2.0 / (fan_in + fan_out)).sqrt();
+            add("Xavier normal", xavier, &normal(xavier));
+            // Xavier uniform draws from ±√3 std to match Xavier normal
+            let bound = xavier * 3f64.sqrt();
+            add("Xavier uniform", xavier, &uniform(bound));
+            // kaiming_uniform_(a=√5), the default of PyTorch's Linear and Conv layers
+            let bound = 1.0 / fan_in.sqrt();
+            add("PyTorch default", bound / 3f64.sqrt(), &uniform(bound));
+            add("N(0, 0.02)", 0.02, &normal(0.02));
+        }
+        Some(InitFit {
+            rms,
+            gaussian_kl,
+            references,
+        })
+    }
+
+    /// The reference distribution the values are closest to
+    pub fn closest(&self) -> Option<&InitReference> {
+        self.references.iter().min_by(|a, b| a.kl.total_cmp(&b.kl))
+    }
+
+    /// Whether the values still look drawn from one of the reference distributions
+    pub fn at_init(&self) -> bool {
+        self.closest().is_some_and(|closest| {
+            closest.kl < INIT_MATCH_KL && (closest.scale_ratio - 1.0).abs() < INIT_MATCH_SCALE
+        })
+    }
+}
+
+/// Distribution of the L2 norms of every row or column of a matrix
+#[derive(Default, Debug, Clone)]
+pub struct ChannelNorms {
+    pub histogram: Histogram,
+    pub median: f32,
+    pub dead: Vec<usize>,
+    pub outliers: Vec<usize>,
+}
+
+impl ChannelNorms {
+    pub fn new(norms: &[f32], max_bin_count: usize, cancel: Ref<()>) -> Result<Self, Error> {
+        let histogram = Histogram::new(norms, max_bin_count, true, cancel)?;
+        let mut sorted = norms.to_vec();
+        sorted.sort_unstable_by(f32::total_cmp);
+        let median = sorted[sorted.len() / 2];
+        let dead = (0..norms.len())
+            .filter(|&i| norms[i] <= median * DEAD_CHANNEL_RATIO)
+            .collect();
+        let outliers = (0..norms.len())
+            .filter(|&i| norms[i] > median * OUTLIER_CHANNEL_RATIO)
+            .collect();
+        Ok(ChannelNorms {
+            histogram,
+            median,
+            dead,
+            outliers,
+        })
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct Channels {
+    pub rows: ChannelNorms,
+    pub columns: ChannelNorms,
+}
+
+impl Channels {
+    pub fn new(
+        data: &[f32],
+        h: usize,
+        w: usize,
+        max_bin_count: usize,
+        cancel: Ref<()>,
+    ) -> Result<Self, Error> {
+        let mut rows = vec![0.0f64; h];
+        let mut columns = vec![0.0f64; w];
+        for (i, row) in data.chunks_exact(w).enumerate() {
+            for (j, &x) in row.iter().enumerate() {
+                let x = (x as f64).powi(2);
+                rows[i] += x;
+                columns[j] += x;
+            }
+        }
+        let norms = |sums: Vec<f64>| -> Vec<f32> { sums.iter().map(|x| x.sqrt() as f32).collect() };
+        Ok(Channels {
+            rows: ChannelNorms::new(&norms(rows), max_bin_count, cancel)?,
+            columns: ChannelNorms::new(&norms(columns), max_bin_count, cancel)?,
+        })
+    }
+}
+
+/// L2 norm of every slice of a tensor along one axis, like each output channel of a conv
+/// kernel or each block of a stacked QKV projection
+#[derive(Default, Debug, Clone)]
+pub struct SliceNorms {
+    pub axis: usize,
+    pub norms: Vec<f32>,
+}
+
+/// Sums the squares of each slice, from `data` if the whole tensor is already in memory or
+/// else by streaming it from `source`
+fn compute_slice_norms(
+    source: &Mutex<dyn ModuleSource + Send>,
+    tensor: TensorInfo,
+    axis: usize,
+    data: Option<&[f32]>,
+    progress: Ref<AtomicU64>,
+    out: Ref<OnceLock<SliceNorms>>,
+) -> Result<(), Error> {
+    let len = tensor.shape[axis] as usize;
+    let stride = tensor.shape[axis + 1..].iter().product::<u64>() as usize;
+    let mut sums = vec![0.0f64; len];
+    let mut seen = 0;
+    let mut add = |chunk: &[f32]| -> Result<(), Error> {
+        for (k, &x) in chunk.iter().enumerate() {
+            sums[(seen + k) / stride % len] += (x as f64).powi(2);
+        }
+        seen += chunk.len();
+        Ok(())
+    };
+    match data {
+        Some(data) => add(data)?,
+        None => read_chunks(source, tensor, progress, &mut add)?,
+    }
+    let norms = sums.iter().map(|x| x.sqrt() as f32).collect();
+    {
+        let _ = out
+            .get(&pin())
+            .ok_or(anyhow!("cancelled"))?
+            .set(SliceNorms { axis, norms });
+    }
+    Ok(())
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Spectrum {
+    pub chart: BarChart,
+    pub summary: SpectralSummary,
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct SpectralSummary {
+    pub top: f32,
+    /// ‖A‖_F² / σ₁²
+    pub stable_rank: f64,
+    /// exp of the entropy of the normalized singular values
+    pub effective_rank: f64,
+    pub condition_number: f64,
+}
+
+impl SpectralSummary {
+    /// Expects singular values in descending order
+    pub fn new(values: &[f32]) -> Self {
+        let top = values.first().copied().unwrap_or(0.0) as f64;
+        let bottom = values.last().copied().unwrap_or(0.0) as f64;
+        let frobenius_sq: f64 = values.iter().map(|&s| (s as f64).powi(2)).sum();
+        let total: f64 = values.iter().map(|&s| s as f64).sum();
+        let entropy: f64 = values
+            .iter()
+            .map(|&s| s as f64 / total)
+            .filter(|&p| p > 0.0)
+            .map(|p| -p * p.ln())
+            .sum();
+        SpectralSummary {
+            top: top as f32,
+            stable_rank: if top > 0.0 {
+                frobenius_sq / top.powi(2)
+            } else {
+                0.0
+            },
+            effective_rank: if total > 0.0 { entropy.exp() } else { 0.0 },
+            condition_number: top / bottom,
+        }
+    }
+}
+
+/// Whether the UI has asked for a stage of the analysis yet
+/// Whether the result was already filled in, e.g. from the cache
+fn is_set<T>(out: Ref<OnceLock<T>>) -> bool {
+    out.inspect(|out| out.get().is_some()).unwrap_or(false)
+}
+
+fn is_requested(go: Ref<AtomicBool>) -> Result<bool, Error> {
+    match go.get(&pin()) {
+        Some(go) => Ok(go.load(Relaxed)),
+        None => bail!("cancelled"),
+    }
+}
+
+fn compute_histogram(
+    info: TensorInfo,
+    data: &[f32],
+    binning: Binning,
+    out: Ref<OnceLock<Histogram>>,
+    magnitude_out: Ref<OnceLock<Magnitude>>,
+    init_fit_out: Ref<OnceLock<InitFit>>,
+    channels_out: Ref<OnceLock<Channels>>,
+) -> Result<(), Error> {
+    let bin_count = binning.max_bin_count;
+    let histogram = match binning.range {
+        Some(range) => {
+            let stats = Stats::new(data);
+            let mut histogram = Histogram::zoomed(stats.min, stats.max, range, bin_count);
+            histogram.add(data);
+            histogram
+        }
+        None if info.ty.is_integer() => Histogram::integer(data, bin_count)?,
+        None => Histogram::new(data, bin_count, false, out.map(|_| &()))?,
+    };
+    {
+        let _ = out.get(&pin()).ok_or(anyhow!("cancelled"))?.set(histogram);
+    }
+    if let &[h, w] = info.shape.as_slice()
+        && h > 0
+        && w > 0
+    {
+        let channels = Channels::new(data, h as usize, w as usize, bin_count, out.map(|_| &()))?;
+        let _ = channels_out
+            .get(&pin())
+            .ok_or(anyhow!("cancelled"))?
+            .set(channels);
+    }
+    if !data.iter().any(|x| x.is_finite()) {
+        return Ok(());
+    }
+    // Integer tensors hold indices and masks rather than learned weights
+    let fit = if info.ty.is_float() {
+        InitFit::new(data, &info.shape)
+    } else {
+        None
+    };
+    if let Some(fit) = fit {
+        init_fit_out.inspect(|out| {
+            let _ = out.set(fit);
+        });
+    }
+    let magnitude = Magnitude::new(data, bin_count, out.map(|_| &()))?;
+    {
+        let _ = magnitude_out
+            .get(&pin())
+            .ok_or(anyhow!("cancelled"))?
+            .set(magnitude);
+    }
+    Ok(())
+}
+
+fn compute_spectrum(
+    info: TensorInfo,
+    data: &[f32],
+    bin_count: usize,
+    progress: Ref<AtomicU64>,
+    out: Ref<OnceLock<Spectrum>>,
+) -> Result<(), Error> {
+    if data.is_empty() {
+        let _ = out.get(&pin()).ok_or(anyhow!("cancelled"))?.set(Spectrum {
+            chart: BarChart::default(),
+            summary: SpectralSummary::default(),
+        });
+        bail!("tensor is empty");
+    }
+
+    let &[h, w] = info.shape.as_slice() else {
+        return Ok(());
+    };
+    let values = singular_values(data, h as usize, w as usize, progress)?;
+    let histogram = Histogram::new(&values, bin_count, true, out.map(|_| &()))?;
+    {
+        let _ = out.get(&pin()).ok_or(anyhow!("cancelled"))?.set(Spectrum {
+            chart: histogram.chart,
+            summary: SpectralSummary::new(&values),
+        });
+    }
+    Ok(())
+}
+
+/// Candidate types for the quantization error table, roughly from smallest to largest
+pub const QUANT_ERROR_TYPES: [GgmlTypeId; 10] = [
+    ggml_base::Q2_K,
+    ggml_base::Q3_K,
+    ggml_base::Q4_0,
+    ggml_base::Q4_1,
+    ggml_base::Q4_K,
+    ggml_base::Q5_0,
+    ggml_base::Q5_1,
+    ggml_base::Q5_K,
+    ggml_base::Q6_K,
+    ggml_base::Q8_0,
+];
+
+/// Round-trip error of storing a tensor as a particular ggml type
+#[derive(Debug, Clone)]
+pub struct QuantError {
+    pub ty: GgmlTypeId,
+    pub bits_per_weight: f64,
+    pub rmse: f64,
+    pub max_error: f32,
+}
+
+fn compute_quant_errors(
+    info: TensorInfo,
+    data: &[f32],
+    progress: Ref<AtomicU64>,
+    out: Ref<OnceLock<Vec<QuantError>>>,
+) -> Result<(), Error> {
+    if data.is_empty() {
+        bail!("tensor is empty");
+    }
+
+    let mut errors = Vec::new();
+    for (i, ty) in QUANT_ERROR_TYPES.into_iter().enumerate() {
+        // Types whose block size doesn't divide the rows, or which this build can't
+        // quantize, are skipped
+        if ggml_base::can_quantize(ty) && ggml_base::validate_shape(ty, &info.shape).is_ok() {
+            let bytes = ggml_base::quantize(ty, &info.shape, data)?;
+            let restored = ggml_base::dequantize(ty, &info.shape, &bytes)?;
+            let mut sum_sq = 0.0f64;
+            let mut max_error = 0.0f32;
+            let mut count = 0usize;
+            for (&x, &y) in data.iter().zip(&restored) {
+                if !x.is_finite() {
+                    continue;
+                }
+                let err = (x - y).abs();
+                sum_sq += (err as f64).powi(2);
+                max_error = max_error.max(err);
+                count += 1;
+            }
+            errors.push(QuantError {
+                ty,
+                bits_per_weight: (bytes.len() * 8) as f64 / data.len() as f64,
+                rmse: (sum_sq / count.max(1) as f64).sqrt(),
+                max_error,
+            });
+        }
+        progress
+            .inspect(|p| p.store(((i + 1) * 100 / QUANT_ERROR_TYPES.len()) as u64, Relaxed))
+            .ok_or(anyhow!("cancelled"))?;
+        notify::notify();
+    }
+
+    {
+        let _ = out.get(&pin()).ok_or(anyhow!("cancelled"))?.set(errors);
+    }
+    Ok(())
+}
+
+const GRAM_BLOCK_ROWS: usize = 256;
+
+/// Computes singular values from the eigenvalues of the Gram matrix, which is accumulated in
+/// row blocks so that the work can be cancelled and report progress.
+pub(crate) fn singular_values(
+    data: &[f32],
+    h: usize,
+    w: usize,
+    progress: Ref<AtomicU64>,
+) -> Result<Vec<f32>, Error> {
+    let mut matrix = faer::MatRef::from_row_major_slice(data, h, w);
+    if h < w {
+        matrix = matrix.transpose();
+    }
+    let (n, k) = (matrix.nrows(), matrix.ncols());
+
+    let mut gram = faer::Mat::<f64>::zeros(k, k);
+    let mut start = 0;
+    while start < n {
+        let rows = GRAM_BLOCK_ROWS.min(n - start);
+        let block = matrix.subrows(start, rows);
+        let block = faer::Mat::<f64>::from_fn(rows, k, |i, j| block[(i, j)] as f64);
+        faer::linalg::matmul::matmul(
+            gram.as_mut(),
+            faer::Accum::Add,
+            block.transpose(),
+            block.as_ref(),
+            1.0,
+            faer::get_global_parallelism(),
+        );
+        start += rows;
+        progress
+            .inspect(|p| p.store((start * 100 / n) as u64, Relaxed))
+            .ok_or(anyhow!("cancelled"))?;
+        notify::notify();
+    }
+
+    let eigenvalues = gram
+        .self_adjoint_eigenvalues(faer::Side::Lower)
+        .map_err(|err| anyhow!("could not perform SVD: {err:?}"))?;
+    Ok(eigenvalues
+        .into_iter()
+        .rev()
+        .map(|x| x.max(0.0).sqrt() as f32)
+        .collect())
+}
+
+const ANALYSIS_THREADS: usize = 4;
+
+/// Shared by every analysis so that a slow stage can't starve the others of threads
+fn analysis_pool() -> &'static ThreadPool {
+    static POOL: OnceLock<ThreadPool> = OnceLock::new();
+    POOL.get_or_init(|| {
+        ThreadPoolBuilder::new()
+            .num_threads(ANALYSIS_THREADS)
+            .thread_name(|i| format!("analysis-{i}"))
+            .build()
+            .expect("could not start analysis threads")
+    })
+}
+
+/// Like `ModuleSource::tensor_chunks_f32`, but the source is only locked while reading so that
+/// other analyses can do I/O while `visit` runs
+fn read_chunks(
+    source: &Mutex<dyn ModuleSource + Send>,
+    tensor: TensorInfo,
+    progress: Ref<AtomicU64>,
+    visit: &mut dyn FnMut(&[f32]) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let (sender, receiver) = sync_channel::<Vec<f32>>(1);
+    std::thread::scope(|s| {
+        let reader = s.spawn(move || {
+            source
+                .lock()
+                .unwrap()
+                .tensor_chunks_f32(tensor, progress, &mut |chunk| {
+                    sender
+                        .send(chunk.to_vec())
+                        .map_err(|_| anyhow!("cancelled"))
+                })
+        });
+        let visited = receiver.iter().try_for_each(|chunk| visit(&chunk));
+        // Hang up so the reader stops early if `visit` failed
+        drop(receiver);
+        let read = reader.join().unwrap();
+        visited.and(read)
+    })
+}
+
+/// Reads the tensor twice in chunks: once for statistics and a sample of the values, then again
+/// to fill the histogram once it is requested
+fn do_streaming_analysis(
+    source: &Mutex<dyn ModuleSource + Send>,
+    tensor: TensorInfo,
+    binning: Binning,
+    request: Ref<Analysis>,
+) -> Result<(), Error> {
+    let progress;
+    let stats_out;
+    let histogram_go;
+    let histogram_out;
+    let timings;
+    let batch;
+    {
+        let guard = pin();
+        progress = request.map_with(|req| &req.progress, &guard);
+        stats_out = request.map_with(|req| &req.stats, &guard);
+        histogram_go = request.map_with(|req| &req.histogram_go, &guard);
+        histogram_out = request.map_with(|req| &req.histogram, &guard);
+        timings = request.map_with(|req| &req.timings, &guard);
+        batch = request.get(&guard).is_some_and(|req| req.batch);
+    }
+    if is_set(stats_out) && is_set(histogram_out) {
+        return Ok(());
+    }
+    let mut stats: Option<(Stats, usize)> = None;
+    let mut sample = Reservoir::new();
+    let mut rng = sample_rng();
+    timed(timings, "read", || {
+        read_chunks(source, tensor.clone(), progress, &mut |chunk| {
+            merge_chunk(&mut stats, chunk);
+            sample.add(chunk, &mut rng);
+            Ok(())
+        })
+    })?;
+    let Some((stats, len)) = stats else {
+        bail!("tensor is empty");
+    };
+    {
+        let _ = stats_out
+            .get(&pin())
+            .ok_or(anyhow!("cancelled"))?
+            .set(stats.clone());
+    }
+
+    if batch && !is_requested(histogram_go)? {
+        return Ok(());
+    }
+    while !is_requested(histogram_go)? {
+        sleep(Duration::from_millis(100));
+    }
+    progress.inspect(|p| p.store(0, Relaxed));
+    let max_bin_count = binning.max_bin_count;
+    let mut histogram = match binning.range {
+        Some(range) => Histogram::zoomed(stats.min, stats.max, range, max_bin_count),
+        None if tensor.ty.is_integer() => {
+            Histogram::with_integer_range(stats.min, stats.max, max_bin_count)?
+        }
+        None => Histogram::with_range(
+            sample.values,
+            stats.min,
+            stats.max,
+            len,
+            max_bin_count,
+            false,
+        ),
+    };
+    let start = Instant::now();
+    read_chunks(source, tensor, progress, &mut |chunk| {
+        histogram.add(chunk);
+        Ok(())
+    })?;
+    {
+        let _ = histogram_out
+            .get(&pin())
+            .ok_or(anyhow!("cancelled"))?
+            .set(histogram);
+    }
+    timings.inspect(|t| t.finish("histogram", start.elapsed()));
+    Ok(())
+}
+
+fn do_analysis(
+    source: &Mutex<dyn ModuleSource + Send>,
+    request: Ref<Analysis>,
+) -> Result<(), Error> {
+    let mut tensor;
+    let slice;
+    let max_bin_count;
+    let binning;
+    let streaming;
+    let batch;
+    let progress;
+    let stats;
+    let preview;
+    let histogram;
+    let magnitude;
+    let init_fit;
+    let channels;
+    let slice_norms;
+    let slice_norms_progress;
+    let spectrum;
+    let spectrum_go;
+    let histogram_go;
+    let quant_errors;
+    let quant_error_go;
+    let quant_error_progress;
+    let error;
+    let timings;
+    {
+        let guard = pin();
+        progress = request.map_with(|req| &req.progress, &guard);
+        stats = request.map_with(|req| &req.stats, &guard);
+        preview = request.map_with(|req| &req.preview, &guard);
+        histogram = request.map_with(|req| &req.histogram, &guard);
+        magnitude = request.map_with(|req| &req.magnitude, &guard);
+        init_fit = request.map_with(|req| &req.init_fit, &guard);
+        channels = request.map_with(|req| &req.channels, &guard);
+        slice_norms = request.map_with(|req| &req.slice_norms, &guard);
+        slice_norms_progress = request.map_with(|req| &req.slice_norms_progress, &guard);
+        spectrum = request.map_with(|req| &req.spectrum, &guard);
+        histogram_go = request.map_with(|req| &req.histogram_go, &guard);
+        spectrum_go = request.map_with(|req| &req.spectrum_go, &guard);
+        quant_errors = request.map_with(|req| &req.quant_errors, &guard);
+        quant_error_go = request.map_with(|req| &req.quant_error_go, &guard);
+        quant_error_progress = request.map_with(|req| &req.quant_error_progress, &guard);
+        error = request.map_with(|req| &req.error, &guard);
+        timings = request.map_with(|req| &req.timings, &guard);
+        let request = request.get(&guard).ok_or(anyhow!("cancelled"))?;
+        request.timings.start();
+        tensor = request.tensor.clone();
+        slice = request.slice.clone();
+        max_bin_count = request.max_bin_count;
+        binning = Binning {
+            max_bin_count,
+            range: request.range,
+        };
+        streaming = request.streaming;
+        batch = request.batch;
+    }
+    if streaming {
+        return do_streaming_analysis(source, tensor, binning, request);
+    }
+    let whole = tensor.clone();
+    let mut slice_norms_axis = match &slice {
+        _ if whole.shape.len() < 3 => None,
+        Some(slice) => Some(slice.axis()),
+        None => Some(0),
+    };
+    let data = timed(timings, "read", || -> Result<_, Error> {
+        let data = {
+            let mut source = source.lock().unwrap();
+            match &slice {
+                Some(slice) => {
+                    let data = read_slice(&mut *source, &tensor, slice)?;
+                    tensor = slice.tensor(&tensor);
+                    data
+                }
+                None => source.tensor_f32(tensor.clone(), progress)?,
+            }
+        };
+        let _ = stats
+            .get(&pin())
+            .ok_or(anyhow!("cancelled"))?
+            .set(Stats::new(&data));
+        if is_previewable(&tensor) {
+            let _ = preview
+                .get(&pin())
+                .ok_or(anyhow!("cancelled"))?
+                .set(data.clone());
+        }
+        Ok(data)
+    })?;
+
+    let fail = move |err: Error| {
+        error.inspect(|e| {
+            let _ = e.set(err);
+        });
+        notify::notify();
+    };
+
+    // Start each remaining stage on the pool once the UI asks for it, so they run concurrently
+    let data = &data;
+    analysis_pool().in_place_scope(|scope| {
+        let mut histogram_pending = true;
+        let mut spectrum_pending = !is_set(spectrum);
+        let mut quant_error_pending = true;
+        while histogram_pending
+            || spectrum_pending
+            || quant_error_pending
+            || slice_norms_axis.is_some()
+        {
+            let mut idle = true;
+            if histogram_pending && is_requested(histogram_go)? {
+                let tensor = tensor.clone();
+                scope.spawn(move |_| {
+                    timed(timings, "histogram", || {
+                        compute_histogram(
+                            tensor, data, binning, histogram, magnitude, init_fit, channels,
+                        )
+                    })
+                    .unwrap_or_else(fail)
+                });
+                histogram_pending = false;
+                idle = false;
+            }
+            match slice_norms_axis {
+                Some(axis) if is_requested(histogram_go)? => {
+                    let whole = whole.clone();
+                    // A slice leaves the rest of the tensor unread
+                    let data = slice.is_none().then_some(data.as_slice());
+                    scope.spawn(move |_| {
+                        timed(timings, "slice norms", || {
+                            compute_slice_norms(
+                                source,
+                                whole,
+                                axis,
+                                data,
+                                slice_norms_progress,
+                                slice_norms,
+                            )
+                        })
+                        .unwrap_or_else(fail)
+                    });
+                    slice_norms_axis = None;
+                    idle = false;
+                }
+                _ => {}
+            }
+            if spectrum_pending && is_requested(spectrum_go)? {
+                let tensor = tensor.clone();
+                progress.inspect(|p| p.store(0, Relaxed));
+                scope.spawn(move |_| {
+                    timed(timings, "spectrum", || {
+                        compute_spectrum(tensor, data, max_bin_count, progress, spectrum)
+                    })
+                    .unwrap_or_else(fail)
+                });
+                spectrum_pending = false;
+                idle = false;
+            }
+            if quant_error_pending && is_requested(quant_error_go)? {
+                let tensor = tensor.clone();
+                scope.spawn(move |_| {
+                    timed(timings, "quantization", || {
+                        compute_quant_errors(tensor, data, quant_error_progress, quant_errors)
+                    })
+                    .unwrap_or_else(fail)
+                });
+                quant_error_pending = false;
+                idle = false;
+            }
+            if idle && batch {
+                break;
+            }
+            if idle {
+                sleep(Duration::from_millis(100));
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Aggregate analysis over every tensor inside a module
+pub struct ModuleAnalysis {
+    /// Tensors grouped by the direct child of the module which contains them
+    pub children: Vec<(String, Vec<(String, TensorInfo)>)>,
+    pub max_bin_count: usize,
+    pub total: usize,
+    pub done: AtomicUsize,
+    /// Percent read of the tensor currently being scanned
+    pub progress: AtomicU64,
+    pub stats: OnceLock<Stats>,
+    /// Statistics over all the float tensors in each child, or `None` if it has none
+    pub child_stats: OnceLock<Vec<(String, Option<Stats>)>>,
+    pub histogram_go: AtomicBool,
+    pub histogram: OnceLock<Histogram>,
+    pub error: OnceLock<Error>,
+}
+
+impl ModuleAnalysis {
+    pub fn new(
+        children: Vec<(String, Vec<(String, TensorInfo)>)>,
+        max_bin_count: usize,
+        histogram_go: bool,
+    ) -> Self {
+        let total = children.iter().map(|(_, tensors)| tensors.len()).sum();
+        ModuleAnalysis {
+            children,
+            max_bin_count,
+            total,
+            done: AtomicUsize::new(0),
+            progress: AtomicU64::new(0),
+            stats: OnceLock::new(),
+            child_stats: OnceLock::new(),
+            histogram_go: histogram_go.into(),
+            histogram: OnceLock::new(),
+            error: OnceLock::new(),
+        }
+    }
+}
+
+/// Streams every float tensor in the module twice, like `do_streaming_analysis`
+fn do_module_analysis(
+    source: &Mutex<dyn ModuleSource + Send>,
+    request: Ref<ModuleAnalysis>,
+) -> Result<(), Error> {
+    let children;
+    let max_bin_count;
+    let progress;
+    {
+        let guard = pin();
+        progress = request.map_with(|req| &req.progress, &guard);
+        let request = request.get(&guard).ok_or(anyhow!("cancelled"))?;
+        children = request.children.clone();
+        max_bin_count = request.max_bin_count;
+    }
+    let float_tensors = |tensors: &[(String, TensorInfo)]| {
+        tensors
+            .iter()
+            .filter(|(_, tensor)| tensor.ty.is_float())
+            .map(|(_, tensor)| tensor.clone())
+            .collect::<Vec<_>>()
+    };
+    let mark_done = || {
+        notify::notify();
+        request
+            .inspect(|req| req.done.fetch_add(1, Relaxed))
+            .ok_or(anyhow!("cancelled"))
+    };
+
+    let mut stats: Option<(Stats, usize)> = None;
+    let mut sample = Reservoir::new();
+    let mut rng = sample_rng();
+    let mut child_stats = Vec::with_capacity(children.len());
+    for (child, tensors) in &children {
+        let mut child_total: Option<(Stats, usize)> = None;
+        for tensor in float_tensors(tensors) {
+            read_chunks(source, tensor, progress, &mut |chunk| {
+                let chunk_stats = Stats::new(chunk);
+                child_total = Some(match child_total.take() {
+                    Some((total, len)) => (
+                        total.merge(len, &chunk_stats, chunk.len()),
+                        len + chunk.len(),
+                    ),
+                    None => (chunk_stats.clone(), chunk.len()),
+                });
+                stats = Some(match stats.take() {
+                    Some((total, len)) => (
+                        total.merge(len, &chunk_stats, chunk.len()),
+                        len + chunk.len(),
+                    ),
+                    None => (chunk_stats, chunk.len()),
+                });
+                sample.add(chunk, &mut rng);
+                Ok(())
+            })?;
+            mark_done()?;
+        }
+        child_stats.push((child.clone(), child_total.map(|(stats, _)| stats)));
+    }
+    let Some((stats, len)) = stats else {
+        bail!("module has no float tensors");
+    };
+    request
+        .inspect(|req| {
+            let _ = req.stats.set(stats.clone());
+            let _ = req.child_stats.set(child_stats);
+        })
+        .ok_or(anyhow!("cancelled"))?;
+    notify::notify();
+
+    while !is_requested(request.map(|req| &req.histogram_go))? {
+        sleep(Duration::from_millis(100));
+    }
+    request.inspect(|req| req.done.store(0, Relaxed));
+    let mut histogram = Histogram::with_range(
+        sample.values,
+        stats.min,
+        stats.max,
+        len,
+        max_bin_count,
+        false,
+    );
+    for (_, tensors) in &children {
+        for tensor in float_tensors(tensors) {
+            read_chunks(source, tensor, progress, &mut |chunk| {
+                histogram.add(chunk);
+                Ok(())
+            })?;
+            mark_done()?;
+        }
+    }
+    request
+        .inspect(|req| {
+            let _ = req.histogram.set(histogram);
+        })
+        .ok_or(anyhow!("cancelled"))?;
+    Ok(())
+}
+
+pub enum AnalysisRequest {
+    Tensor(Ref<Analysis>),
+    Module(Ref<ModuleAnalysis>),
+}
+
+pub type AnalysisCell = AsyncCell<AnalysisRequest>;
+
+pub fn run_analysis_loop(source: Arc<Mutex<dyn ModuleSource + Send>>, requests: Ref<AnalysisCell>) {
+    loop {
+        let Some(request) = block_on(TakeRef(requests)) else {
+            return;
+        };
+        match request {
+            AnalysisRequest::Tensor(request) => {
+                if let Err(err) = do_analysis(&*source, request) {
+                    request.inspect(|r| {
+                        let _ = r.error.set(err);
+                    });
+                }
+            }
+            AnalysisRequest::Module(request) => {
+                if let Err(err) = do_module_analysis(&*source, request) {
+                    request.inspect(|r| {
+                        let _ = r.error.set(err);
+                    });
+                }
+            }
+        }
+        notify::notify();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TensorHealth {
+    Ok,
+    AllZero,
+    NonFinite { nan_count: usize, inf_count: usize },
+    HeavyTailed { excess_kurtosis: f64 },
+    Error(String),
+}
+
+impl TensorHealth {
+    pub fn from_stats(stats: &Stats) -> Self {
+        if stats.nan_count > 0 || stats.inf_count > 0 {
+            TensorHealth::NonFinite {
+                nan_count: stats.nan_count,
+                inf_count: stats.inf_count,
+            }
+        } else if stats.zero_fraction >= 1.0 {
+            TensorHealth::AllZero
+        } else if stats.is_heavy_tailed() {
+            TensorHealth::HeavyTailed {
+                excess_kurtosis: stats.excess_kurtosis,
+            }
+        } else {
+            TensorHealth::Ok
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        matches!(self, TensorHealth::Ok)
+    }
+}
+
+impl std::fmt::Display for TensorHealth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TensorHealth::Ok => write!(f, "ok"),
+            TensorHealth::AllZero => write!(f, "all zero"),
+            TensorHealth::NonFinite {
+                nan_count,
+                inf_count,
+            } => write!(f, "{nan_count} NaN, {inf_count} Inf"),
+            TensorHealth::HeavyTailed { excess_kurtosis } => {
+                write!(f, "heavy-tailed (kurtosis {excess_kurtosis:.1})")
+            }
+            TensorHealth::Error(err) => write!(f, "error: {err}"),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct HealthScan {
+    pub total: usize,
+    pub done: AtomicUsize,
+    /// Percent read of the tensor currently being scanned
+    pub progress: AtomicU64,
+    pub results: Mutex<HashMap<String, TensorHealth>>,
+    pub stats: Mutex<HashMap<String, Stats>>,
+}
+
+impl HealthScan {
+    pub fn new(total: usize) -> Self {
+        HealthScan {
+            total,
+            ..Default::default()
+        }
+    }
+
+    pub fn health(&self, name: &str) -> Option<TensorHealth> {
+        self.results.lock().unwrap().get(name).cloned()
+    }
+
+    pub fn stats(&self, name: &str) -> Option<Stats> {
+        self.stats.lock().unwrap().get(name).cloned()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.done.load(Relaxed) >= self.total
+    }
+}
+
+pub fn start_health_scan(
+    source: Arc<Mutex<dyn ModuleSource + Send>>,
+    tensors: Vec<(String, TensorInfo)>,
+    scan: Ref<HealthScan>,
+) {
+    notify::spawn(move || {
+        let scan_tensor = |(name, tensor): (String, TensorInfo)| {
+            if !scan.is_alive() {
+                return;
+            }
+            // Merge stats a chunk at a time, so scanning never holds a whole tensor as f32
+            let mut total = None;
+            let result = source.lock().unwrap().tensor_chunks_f32(
+                tensor,
+                scan.map(|scan| &scan.progress),
+                &mut |chunk| {
+                    merge_chunk(&mut total, chunk);
+                    Ok(())
+                },
+            );
+            let (health, stats) = match result {
+                Ok(()) => {
+                    let stats = total.map_or_else(|| Stats::new(&[]), |(stats, _)| stats);
+                    (TensorHealth::from_stats(&stats), Some(stats))
+                }
+                Err(err) => (TensorHealth::Error(err.to_string()), None),
+            };
+            scan.inspect(|scan| {
+                if let Some(stats) = stats {
+                    scan.stats.lock().unwrap().insert(name.clone(), stats);
+                }
+                scan.results.lock().unwrap().insert(name, health);
+                scan.done.fetch_add(1, Relaxed);
+                notify::notify();
+            });
+        };
+        analysis_pool().install(|| tensors.into_par_iter().for_each(scan_tensor));
+    });
+}
+
+/// The values of the file's scalar tensors, read in the background for the tree to show
+#[derive(Debug, Default)]
+pub struct ScalarValues {
+    pub progress: AtomicU64,
+    pub values: Mutex<HashMap<String, f64>>,
+}
+
+/// Reads the one value of each tensor in `tensors`, which should all have shape `[]`
+pub fn start_scalar_values(
+    source: Arc<Mutex<dyn ModuleSource + Send>>,
+    tensors: Vec<(String, TensorInfo)>,
+    scalars: Ref<ScalarValues>,
+) {
+    notify::spawn(move || {
+        for (name, tensor) in tensors {
+            if !scalars.is_alive() {
+                return;
+            }
+            let value = source
+                .lock()
+                .unwrap()
+                .tensor_f64(tensor, scalars.map(|scalars| &scalars.progress));
+            // Types which can't be read as numbers are just left out
+            if let Ok(&[value]) = value.as_deref() {
+                scalars.inspect(|scalars| scalars.values.lock().unwrap().insert(name, value));
+            }
+        }
+    });
+}
+
+/// How the shapes of two compared tensors line up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShapeMatch {
+    Same,
+    /// Both are matrices, and one is the transpose of the other
+    Transposed,
+    /// Same number of elements, compared in storage order
+    SameCount,
+    Different,
+}
+
+impl ShapeMatch {
+    pub fn of(left: &[u64], right: &[u64]) -> Self {
+        let count = |shape: &[u64]| shape.iter().product::<u64>();
+        if left == right {
+            ShapeMatch::Same
+        } else if let ([a, b], [c, d]) = (left, right)
+            && a == d
+            && b == c
+        {
+            ShapeMatch::Transposed
+        } else if count(left) == count(right) {
+            ShapeMatch::SameCount
+        } else {
+            ShapeMatch::Different
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ShapeMatch::Same => "identical",
+            ShapeMatch::Transposed => "transposed",
+            ShapeMatch::SameCount => "same element count",
+            ShapeMatch::Different => "incompatible",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PairMetrics {
+    pub cosine: f64,
+    pub l2_distance: f64,
+    /// `l2_distance` divided by the norm of the marked tensor
+    pub relative_distance: f64,
+    pub max_abs_diff: f64,
+}
+
+/// Pairwise metrics between a marked tensor and another
+pub struct Comparison {
+    pub left: TensorInfo,
+    pub right: TensorInfo,
+    pub shapes: ShapeMatch,
+    /// Percent read of whichever tensor is being loaded
+    pub progress: AtomicU64,
+    pub metrics: OnceLock<PairMetrics>,
+    pub error: OnceLock<Error>,
+}
+
+impl Comparison {
+    pub fn new(left: TensorInfo, right: TensorInfo) -> Self {
+        Comparison {
+            shapes: ShapeMatch::of(&left.shape, &right.shape),
+            left,
+            right,
+            progress: AtomicU64::new(0),
+            metrics: OnceLock::new(),
+            error: OnceLock::new(),
+        }
+    }
+}
+
+fn do_comparison(
+    source: &Mutex<dyn ModuleSource + Send>,
+    request: Ref<Comparison>,
+) -> Result<(), Error> {
+    let (left, right, shapes) = request
+        .inspect(|req| (req.left.clone(), req.right.clone(), req.shapes))
+        .ok_or(anyhow!("cancelled"))?;
+    if shapes == ShapeMatch::Different {
+        bail!("the tensors have different element counts");
+    }
+    let progress = request.map(|req| &req.progress);
+    let a = source.lock().unwrap().tensor_f32(left, progress)?;
+    let b = source.lock().unwrap().tensor_f32(right.clone(), progress)?;
+    let metrics = pair_metrics(&a, b, shapes, &right.shape);
+    request
+        .inspect(|req| {
+            let _ = req.metrics.set(metrics);
+        })
+        .ok_or(anyhow!("cancelled"))
+}
+
+/// Compares two tensors' values, transposing `b` (of shape `b_shape`) first if the shapes
+/// call for it
+pub fn pair_metrics(
+    a: &[f32],
+    mut b: Vec<f32>,
+    shapes: ShapeMatch,
+    b_shape: &[u64],
+) -> PairMetrics {
+    if let (ShapeMatch::Transposed, &[rows, cols]) = (shapes, b_shape) {
+        let (rows, cols) = (rows as usize, cols as usize);
+        b = (0..rows * cols)
+            .map(|i| b[(i % rows) * cols + i / rows])
+            .collect();
+    }
+
+    let (mut dot, mut a_sq, mut b_sq, mut diff_sq, mut max_abs_diff) = (0.0, 0.0, 0.0, 0.0, 0.0f64);
+    for (&x, &y) in a.iter().zip(&b) {
+        let (x, y) = (x as f64, y as f64);
+        dot += x * y;
+        a_sq += x * x;
+        b_sq += y * y;
+        diff_sq += (x - y) * (x - y);
+        max_abs_diff = max_abs_diff.max((x - y).abs());
+    }
+    let l2_distance = diff_sq.sqrt();
+    PairMetrics {
+        cosine: dot / (a_sq.sqrt() * b_sq.sqrt()),
+        l2_distance,
+        relative_distance: l2_distance / a_sq.sqrt(),
+        max_abs_diff,
+    }
+}
+
+pub fn start_comparison(source: Arc<Mutex<dyn ModuleSource + Send>>, comparison: Ref<Comparison>) {
+    notify::spawn(move || {
+        if let Err(err) = do_comparison(&*source, comparison) {
+            comparison.inspect(|c| {
+                let _ = c.error.set(err);
+            });
+        }
+    });
+}
+
+/// Tensors analyzed one after another, each finishing once the stages it starts with are done
+pub struct BatchAnalysis {
+    pub tensors: Vec<(String, Analysis)>,
+    /// How many have finished, successfully or not
+    pub done: AtomicUsize,
+}
+
+/// Works through the batch in order on its own thread, alongside the analysis loop
+pub fn start_batch_analysis(
+    source: Arc<Mutex<dyn ModuleSource + Send>>,
+    batch: Ref<BatchAnalysis>,
+) {
+    notify::spawn(move || {
+        let count = batch.inspect(|b| b.tensors.len()).unwrap_or(0);
+        for i in 0..count {
+            let analysis = batch.map_with(|b| &b.tensors[i].1, &pin());
+            if let Err(err) = do_analysis(&*source, analysis) {
+                analysis.inspect(|a| {
+                    let _ = a.error.set(err);
+                });
+            }
+            if batch.inspect(|b| b.done.fetch_add(1, Relaxed)).is_none() {
+                return;
+            }
+            notify::notify();
+        }
+    });
+}
+
+/// Analyzes one tensor on its own thread, alongside whatever the analysis loop is working on
+pub fn start_tensor_analysis(source: Arc<Mutex<dyn ModuleSource + Send>>, analysis: Ref<Analysis>) {
+    notify::spawn(move || {
+        if let Err(err) = do_analysis(&*source, analysis) {
+            analysis.inspect(|a| {
+                let _ = a.error.set(err);
+            });
+        }
+    });
+}
+
+pub fn start_analysis_thread(source: Arc<Mutex<dyn ModuleSource + Send>>, cell: Ref<AnalysisCell>) {
+    notify::spawn(move || {
+        run_analysis_loop(source, cell);
+    });
+}
@@ -0,0 +1,179 @@
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::model::{PathSplit, TensorInfo, as_lazy_array};
+
+/// Name fragments of the token embedding matrix across common model families
+pub(crate) const EMBEDDING_NAMES: [&str; 5] = [
+    "embed_tokens",
+    "tok_embeddings",
+    "token_embd",
+    "wte",
+    "embed",
+];
+/// Name fragments of the first feed-forward projection
+const FFN_NAMES: [&str; 6] = ["up_proj", "ffn_up", "fc1", "c_fc", "w1", "dense_h_to_4h"];
+/// Path components which number the transformer blocks
+const LAYER_NAMES: [&str; 5] = ["layers", "layer", "blocks", "blk", "h"];
+
+/// One labelled line of an architecture summary
+pub type SummaryLine = (&'static str, String);
+
+/// A human-readable overview of the model: read from GGUF hyperparameters when the file has
+/// them, otherwise guessed from tensor names and shapes
+pub fn summarize(
+    metadata: &Value,
+    tensors: &[(String, TensorInfo)],
+    split: &PathSplit,
+) -> Vec<SummaryLine> {
+    let mut lines = Vec::new();
+    match metadata.get("general.architecture").and_then(Value::as_str) {
+        Some(arch) => summarize_gguf(&mut lines, metadata, tensors, arch),
+        None => summarize_shapes(&mut lines, tensors, split),
+    }
+    if !lines.is_empty() {
+        lines.push(("Quantization", quant_mix(tensors)));
+    }
+    lines
+}
+
+fn summarize_gguf(
+    lines: &mut Vec<SummaryLine>,
+    metadata: &Value,
+    tensors: &[(String, TensorInfo)],
+    arch: &str,
+) {
+    let get = |key: &str| metadata.get(format!("{arch}.{key}"));
+    let number = |key: &str| get(key).and_then(Value::as_u64);
+    lines.push(("Architecture", arch.to_string()));
+    if let Some(name) = metadata.get("general.name").and_then(Value::as_str) {
+        lines.push(("Name", name.to_string()));
+    }
+    if let Some(size) = metadata.get("general.size_label").and_then(Value::as_str) {
+        lines.push(("Size", size.to_string()));
+    }
+    if let Some(layers) = number("block_count") {
+        lines.push(("Layers", layers.to_string()));
+    }
+    if let Some(hidden) = number("embedding_length") {
+        lines.push(("Hidden size", hidden.to_string()));
+    }
+    if let Some(ffn) = number("feed_forward_length") {
+        lines.push(("FFN size", ffn.to_string()));
+    }
+    if let Some(heads) = number("attention.head_count") {
+        let heads = match number("attention.head_count_kv") {
+            Some(kv) if kv != heads => format!("{heads} ({kv} KV)"),
+            _ => heads.to_string(),
+        };
+        lines.push(("Attention heads", heads));
+    }
+    if let Some(context) = number("context_length") {
+        lines.push(("Context length", context.to_string()));
+    }
+    if let Some(experts) = number("expert_count") {
+        let experts = match number("expert_used_count") {
+            Some(used) => format!("{experts} ({used} active)"),
+            None => experts.to_string(),
+        };
+        lines.push(("Experts", experts));
+    }
+    if let Some(base) = get("rope.freq_base").and_then(Value::as_f64) {
+        lines.push(("RoPE base", base.to_string()));
+    }
+    if let Some(vocab) = metadata
+        .get("tokenizer.ggml.tokens")
+        .and_then(|tokens| match as_lazy_array(tokens) {
+            Some((_, range)) => Some(range.len()),
+            None => tokens.as_array().map(Vec::len),
+        })
+        .map(|len| len as u64)
+        .or_else(|| number("vocab_size"))
+        .or_else(|| embedding_shape(tensors).map(|(vocab, _)| vocab))
+    {
+        lines.push(("Vocabulary", vocab.to_string()));
+    }
+}
+
+fn summarize_shapes(
+    lines: &mut Vec<SummaryLine>,
+    tensors: &[(String, TensorInfo)],
+    split: &PathSplit,
+) {
+    let mut layers = BTreeSet::new();
+    for (name, _) in tensors {
+        let parts = split.split(name.as_str().into());
+        let index = parts
+            .windows(2)
+            .filter(|pair| LAYER_NAMES.contains(&&*pair[0]))
+            .find_map(|pair| pair[1].parse::<u64>().ok());
+        layers.extend(index);
+    }
+    if !layers.is_empty() {
+        lines.push(("Layers", layers.len().to_string()));
+    }
+
+    let embedding = embedding_shape(tensors);
+    let hidden = embedding.map(|(_, hidden)| hidden);
+    if let Some((vocab, hidden)) = embedding {
+        lines.push(("Hidden size", hidden.to_string()));
+        lines.push(("Vocabulary", vocab.to_string()));
+    }
+    let ffn = tensors
+        .iter()
+        .find(|(name, tensor)| {
+            tensor.shape.len() == 2 && FFN_NAMES.iter().any(|part| name.contains(part))
+        })
+        .and_then(|(_, tensor)| {
+            let (&a, &b) = (tensor.shape.first()?, tensor.shape.last()?);
+            Some(if Some(a) == hidden { b } else { a.max(b) })
+        });
+    if let Some(ffn) = ffn {
+        lines.push(("FFN size", ffn.to_string()));
+    }
+
+    // Grouped-query attention shows up as k/v projections narrower than q
+    let out_features = |fragment: &str| {
+        tensors
+            .iter()
+            .find(|(name, tensor)| tensor.shape.len() == 2 && name.contains(fragment))
+            .map(|(_, tensor)| tensor.shape[0])
+    };
+    match (out_features("q_proj"), out_features("k_proj")) {
+        (Some(q), Some(k)) if k > 0 && q != k && q % k == 0 => {
+            lines.push(("Query groups", format!("{} queries per KV head", q / k)));
+        }
+        _ => {}
+    }
+}
+
+/// `(vocabulary, hidden size)` from the token embedding matrix
+fn embedding_shape(tensors: &[(String, TensorInfo)]) -> Option<(u64, u64)> {
+    EMBEDDING_NAMES.iter().find_map(|part| {
+        tensors
+            .iter()
+            .filter(|(_, tensor)| tensor.shape.len() == 2)
+            .find(|(name, _)| name.contains(part))
+            .map(|(_, tensor)| {
+                let (a, b) = (tensor.shape[0], tensor.shape[1]);
+                (a.max(b), a.min(b))
+            })
+    })
+}
+
+/// Share of parameters stored as each data type, most common first
+fn quant_mix(tensors: &[(String, TensorInfo)]) -> String {
+    let mut by_type: BTreeMap<String, u64> = BTreeMap::new();
+    for (_, tensor) in tensors {
+        *by_type.entry(tensor.ty.to_string()).or_default() += tensor.shape.iter().product::<u64>();
+    }
+    let total = by_type.values().sum::<u64>().max(1) as f64;
+    let mut by_type: Vec<_> = by_type.into_iter().collect();
+    by_type.sort_by_key(|&(_, params)| Reverse(params));
+    by_type
+        .iter()
+        .map(|(ty, params)| format!("{ty} {:.1}%", *params as f64 / total * 100.0))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
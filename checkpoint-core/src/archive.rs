@@ -0,0 +1,166 @@
+use anyhow::{Error, anyhow, bail};
+use std::fs;
+use std::io;
+use std::io::{Read, Seek};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::storage::{FileStorage, Storage};
+
+/// Separates an archive from the member inside it, as in `bundle.tar!/model.safetensors`
+const MEMBER_SEPARATOR: &str = "!/";
+
+/// Splits `archive.zip!/member` into the archive path and the member name
+pub fn split_member_path(path: &Path) -> Option<(PathBuf, String)> {
+    let (archive, member) = path.to_str()?.split_once(MEMBER_SEPARATOR)?;
+    Some((PathBuf::from(archive), member.to_string()))
+}
+
+/// Where a member's bytes sit inside a zip or tar archive. Only members stored without
+/// compression have such a range, which luckily covers `torch.save` zips and plain tarballs.
+fn find_member(archive: &Path, member: &str) -> Result<Range<u64>, Error> {
+    let mut file = fs::File::open(archive)?;
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic)?;
+    file.rewind()?;
+    if &magic == b"PK\x03\x04" {
+        let mut zip = zip::ZipArchive::new(file)?;
+        // Raw access, since a compressed member can't even be opened without deflate support
+        let index = zip
+            .index_for_name(member)
+            .ok_or_else(|| anyhow!("{member} is not in {}", archive.display()))?;
+        let entry = zip.by_index_raw(index)?;
+        if entry.compression() != zip::CompressionMethod::Stored {
+            bail!(
+                "{member} is compressed inside {}, so extract it before opening",
+                archive.display()
+            );
+        }
+        let start = entry.data_start();
+        return Ok(start..start + entry.size());
+    }
+
+    let mut tar = tar::Archive::new(file);
+    for entry in tar.entries_with_seek()? {
+        let entry = entry?;
+        let path = entry.path()?;
+        if path.strip_prefix("./").unwrap_or(&path) == Path::new(member) {
+            let start = entry.raw_file_position();
+            return Ok(start..start + entry.size());
+        }
+    }
+    bail!("{member} is not in {}", archive.display())
+}
+
+/// A checkpoint inside a zip or tar archive, read in place without extracting it. Members
+/// can't be resized, so every write fails up front.
+pub struct MemberStorage {
+    archive: FileStorage,
+    name: String,
+    range: Range<u64>,
+    reader: Option<MemberReader>,
+}
+
+impl MemberStorage {
+    pub fn open(archive: PathBuf, member: String) -> Result<Self, Error> {
+        let range = find_member(&archive, &member)?;
+        let name = format!("{}{MEMBER_SEPARATOR}{member}", archive.display());
+        Ok(MemberStorage {
+            archive: FileStorage::new(archive),
+            name,
+            range,
+            reader: None,
+        })
+    }
+
+    fn read_only(&self) -> Error {
+        anyhow!("{} is inside an archive, which can't be edited", self.name)
+    }
+}
+
+impl Storage for MemberStorage {
+    type Reader = MemberReader;
+
+    fn display(&self) -> String {
+        self.name.clone()
+    }
+
+    fn reader(&mut self) -> Result<&mut Self::Reader, Error> {
+        if self.reader.is_none() {
+            let mut file = io::BufReader::new(fs::File::open(self.archive.path())?);
+            file.seek(io::SeekFrom::Start(self.range.start))?;
+            self.reader = Some(MemberReader {
+                file,
+                range: self.range.clone(),
+                pos: self.range.start,
+            });
+        }
+        Ok(self.reader.as_mut().unwrap())
+    }
+
+    fn read_range(&self, offset: u64, len: usize) -> Result<Vec<u8>, Error> {
+        let start = self.range.start + offset;
+        if start + len as u64 > self.range.end {
+            bail!("can't read past the end of {}", self.name);
+        }
+        self.archive.read_range(start, len)
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>, Error> {
+        self.read_range(0, (self.range.end - self.range.start) as usize)
+    }
+
+    fn write(&mut self, _bytes: &[u8]) -> Result<(), Error> {
+        Err(self.read_only())
+    }
+
+    fn splice(&mut self, _range: Range<usize>, _bytes: &[u8]) -> Result<(), Error> {
+        Err(self.read_only())
+    }
+
+    fn rebuild(&mut self, _header: &[u8], _ranges: &[Range<u64>]) -> Result<(), Error> {
+        Err(self.read_only())
+    }
+
+    fn overwrite(&mut self, _offset: usize, _bytes: &[u8]) -> Result<(), Error> {
+        Err(self.read_only())
+    }
+
+    fn check_writable(&self) -> Result<(), Error> {
+        Err(self.read_only())
+    }
+}
+
+/// Sequential access to one archive member, which looks like a whole file to its reader
+pub struct MemberReader {
+    file: io::BufReader<fs::File>,
+    range: Range<u64>,
+    /// Position in the archive, within `range`
+    pos: u64,
+}
+
+impl Read for MemberReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = (self.range.end - self.pos) as usize;
+        let len = buf.len().min(left);
+        let n = self.file.read(&mut buf[..len])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for MemberReader {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let len = self.range.end - self.range.start;
+        let offset = match pos {
+            io::SeekFrom::Start(offset) => Some(offset),
+            io::SeekFrom::End(delta) => len.checked_add_signed(delta),
+            io::SeekFrom::Current(delta) => (self.pos - self.range.start).checked_add_signed(delta),
+        };
+        let offset =
+            offset.ok_or_else(|| io::Error::other("seek before the start of the member"))?;
+        self.pos = self.range.start + offset.min(len);
+        self.file.seek(io::SeekFrom::Start(self.pos))?;
+        Ok(offset)
+    }
+}
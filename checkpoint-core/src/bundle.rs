@@ -0,0 +1,175 @@
+use crate::integrity::Problem;
+use crate::model::{LE, MetadataType, ModuleInfo, ModuleSource, PathSplit, TensorInfo};
+use crate::storage::{Storage, read_with_progress};
+use anyhow::{Error, Result, anyhow, bail};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::atomic::AtomicU64;
+use weakref::{Own, Ref};
+
+/// A read-only checkpoint whose arrays were found by walking some container format, such as
+/// the `.npy` members of an `.npz` archive or the nested dicts of a Flax msgpack file. Each
+/// [`TensorInfo::offset`] is from the start of the file, and the data is little-endian.
+pub struct Bundle<S> {
+    storage: S,
+    /// Name of the container format, for error messages
+    format: &'static str,
+    tensors: Vec<(String, TensorInfo)>,
+    /// Values stored alongside the arrays, like a training step counter
+    metadata: Map<String, Value>,
+    /// Whether tensor names join nested dict keys with `/`
+    nested: bool,
+    problems: Vec<Problem>,
+}
+
+impl<S: Storage> Bundle<S> {
+    pub(crate) fn new(
+        storage: S,
+        format: &'static str,
+        tensors: Vec<(String, TensorInfo)>,
+        metadata: Map<String, Value>,
+        nested: bool,
+    ) -> Self {
+        Bundle {
+            storage,
+            format,
+            tensors,
+            metadata,
+            nested,
+            problems: Vec::new(),
+        }
+    }
+
+    /// Records something found while walking the container which [`ModuleSource::check`]
+    /// should report
+    pub(crate) fn with_problems(mut self, problems: Vec<Problem>) -> Self {
+        self.problems = problems;
+        self
+    }
+
+    fn read_only(&self) -> Error {
+        anyhow!(
+            "{} is a {} checkpoint, which can't be edited",
+            self.storage.display(),
+            self.format
+        )
+    }
+}
+
+/// The declared type of a metadata value, judged from its JSON form
+fn value_type(value: &Value) -> MetadataType {
+    match value {
+        Value::Bool(_) => MetadataType::Bool,
+        Value::Number(number) if number.is_u64() => MetadataType::U64,
+        Value::Number(number) if number.is_i64() => MetadataType::I64,
+        Value::Number(_) => MetadataType::F64,
+        Value::Array(items) => {
+            MetadataType::Array(items.first().map(|item| Box::new(value_type(item))))
+        }
+        _ => MetadataType::String,
+    }
+}
+
+impl<S: Storage> ModuleSource for Bundle<S> {
+    fn module(&mut self, split: &PathSplit) -> Result<ModuleInfo> {
+        let tensors = self.tensors.iter().cloned();
+        if self.nested {
+            let split = PathSplit::Nested(Box::new(split.clone()));
+            return Ok(ModuleInfo::build_from_tensors(tensors, &split));
+        }
+        Ok(ModuleInfo::build_from_tensors(tensors, split))
+    }
+
+    fn metadata(&mut self) -> Result<Value> {
+        Ok(self.metadata.clone().into())
+    }
+
+    fn metadata_array(&mut self, key: &str, _range: Range<usize>) -> Result<Vec<Value>> {
+        bail!("{key} is not an array")
+    }
+
+    fn string_metadata(&mut self) -> Result<HashMap<String, String>> {
+        Ok(self
+            .metadata
+            .iter()
+            .map(|(k, v)| match v {
+                Value::String(text) => (k.clone(), text.clone()),
+                other => (k.clone(), other.to_string()),
+            })
+            .collect())
+    }
+
+    fn metadata_types(&mut self) -> Result<HashMap<String, MetadataType>> {
+        Ok(self
+            .metadata
+            .iter()
+            .map(|(k, v)| (k.clone(), value_type(v)))
+            .collect())
+    }
+
+    fn write_metadata(&mut self, _metadata: &Value) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn rename_tensors(&mut self, _renames: &[(String, String)]) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn delete_tensors(&mut self, _names: &[String]) -> Result<()> {
+        Err(self.read_only())
+    }
+
+    fn tensor_data(&mut self, tensor: TensorInfo, progress: Ref<AtomicU64>) -> Result<Vec<u8>> {
+        read_with_progress(&self.storage, tensor.offset, tensor.size, progress)
+    }
+
+    fn tensor_byte_range(&mut self, tensor: &TensorInfo, range: Range<usize>) -> Result<Vec<u8>> {
+        let progress = Own::new_box(AtomicU64::new(0));
+        read_with_progress(
+            &self.storage,
+            tensor.offset + range.start as u64,
+            range.len(),
+            progress.refer(),
+        )
+    }
+
+    fn tensor_f32(&mut self, tensor: TensorInfo, progress: Ref<AtomicU64>) -> Result<Vec<f32>> {
+        let bytes = read_with_progress(&self.storage, tensor.offset, tensor.size, progress)?;
+        tensor.read_f32::<LE>(&bytes)
+    }
+
+    fn tensor_f64(&mut self, tensor: TensorInfo, progress: Ref<AtomicU64>) -> Result<Vec<f64>> {
+        let bytes = read_with_progress(&self.storage, tensor.offset, tensor.size, progress)?;
+        tensor.read_f64::<LE>(&bytes)
+    }
+
+    fn tensor_chunks_f32(
+        &mut self,
+        tensor: TensorInfo,
+        progress: Ref<AtomicU64>,
+        visit: &mut dyn FnMut(&[f32]) -> Result<(), Error>,
+    ) -> Result<()> {
+        tensor.read_chunks_f32::<LE>(&self.storage, tensor.offset, progress, visit)
+    }
+
+    fn check(&mut self) -> Result<Vec<Problem>> {
+        let mut problems = self.problems.clone();
+        for (name, tensor) in &self.tensors {
+            let Some(element_size) = tensor.ty.element_size() else {
+                continue;
+            };
+            let expected = tensor.shape.iter().product::<u64>() * element_size as u64;
+            if expected != tensor.size as u64 {
+                problems.push(Problem::tensor(
+                    name,
+                    format!(
+                        "takes {} bytes, but its dtype and shape need {expected}",
+                        tensor.size
+                    ),
+                ));
+            }
+        }
+        Ok(problems)
+    }
+}
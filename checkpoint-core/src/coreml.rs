@@ -0,0 +1,562 @@
+//! Reads Core ML models converted by coremltools. An `.mlpackage` directory holds the model
+//! spec next to a `weight.bin` of raw blobs, and the spec's ML Program points into the blobs
+//! from its `const` operations, so tensors are named after the values those operations
+//! produce. A bare `weight.bin` can be opened too, with its blobs named by offset.
+
+use crate::bundle::Bundle;
+use crate::integrity::Problem;
+use crate::model::{TensorInfo, TensorTy};
+use crate::protobuf::{
+    Wire, messages, read_entries, read_fields, read_map, read_string, read_varints,
+};
+use crate::storage::{AnyStorage, SegmentStorage, Storage};
+use anyhow::{Result, anyhow, bail};
+use serde_json::{Map, Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Marks the start of every blob's metadata in a weight file
+const BLOB_SENTINEL: u32 = 0xDEADBEEF;
+/// Weight files start with a blob count and version, padded to this size, and every blob's
+/// metadata is padded to it as well
+const BLOB_ALIGN: u64 = 64;
+/// Placeholder in blob file names for the directory holding the model spec
+const MODEL_PATH: &str = "@model_path";
+/// `Model.mlProgram`, the only model type whose weights are listed
+const ML_PROGRAM_FIELD: u64 = 502;
+
+/// Whether the header is that of a version 2 weight file: a blob count, the version, and
+/// reserved zeros
+pub(crate) fn is_weight_file(header: &[u8]) -> bool {
+    header.len() == BLOB_ALIGN as usize
+        && header[4..8] == 2u32.to_le_bytes()
+        && header[8..].iter().all(|&byte| byte == 0)
+}
+
+/// Where one blob's bytes are in its weight file
+struct Blob {
+    data_type: u32,
+    offset: u64,
+    size: u64,
+}
+
+fn read_blob(storage: &impl Storage, at: u64) -> Result<Blob> {
+    let metadata = storage.read_range(at, BLOB_ALIGN as usize)?;
+    let u32_at = |i: usize| u32::from_le_bytes(metadata[i..i + 4].try_into().unwrap());
+    let u64_at = |i: usize| u64::from_le_bytes(metadata[i..i + 8].try_into().unwrap());
+    if u32_at(0) != BLOB_SENTINEL {
+        bail!("there is no blob at offset {at}");
+    }
+    Ok(Blob {
+        data_type: u32_at(4),
+        size: u64_at(8),
+        offset: u64_at(16),
+    })
+}
+
+/// The tensor type of a blob, as `MILBlob::Blob::BlobDataType`
+fn blob_ty(data_type: u32) -> TensorTy {
+    use TensorTy::*;
+    match data_type {
+        1 => F16,
+        2 => F32,
+        3 => U8,
+        4 => I8,
+        5 => BF16,
+        6 => I16,
+        7 => U16,
+        8 => Unknown("INT4".into()),
+        14 => I32,
+        15 => U32,
+        other => Unknown(format!("BlobDataType({other})")),
+    }
+}
+
+/// Opens a bare `weight.bin`, naming each blob `blob_{offset}` after the offset of its
+/// metadata, which is the offset the model spec uses to refer to it
+pub fn open_weights<S: Storage>(mut storage: S) -> Result<Bundle<S>> {
+    let header = storage.read_range(0, BLOB_ALIGN as usize)?;
+    if !is_weight_file(&header) {
+        bail!("{} is not a Core ML weight file", storage.display());
+    }
+    let count = u32::from_le_bytes(header[..4].try_into()?);
+    // Every blob has at least one aligned block of metadata after the header
+    let len = storage.reader()?.seek(SeekFrom::End(0))?;
+    if count as u64 > len / BLOB_ALIGN {
+        bail!(
+            "{} claims {count} blobs, more than fit in the file",
+            storage.display()
+        );
+    }
+    let mut tensors = Vec::with_capacity(count as usize);
+    let mut problems = Vec::new();
+    let mut at = BLOB_ALIGN;
+    for _ in 0..count {
+        let blob = read_blob(&storage, at)?;
+        let name = format!("blob_{at}");
+        let ty = blob_ty(blob.data_type);
+        let numel = match ty.element_size() {
+            Some(element_size) => blob.size / element_size as u64,
+            None => {
+                problems.push(Problem::tensor(
+                    &name,
+                    format!("has Core ML blob type {ty}, which can't be read"),
+                ));
+                blob.size
+            }
+        };
+        tensors.push((
+            name,
+            TensorInfo {
+                ty,
+                shape: vec![numel],
+                size: blob.size as usize,
+                offset: blob.offset,
+            },
+        ));
+        at = blob
+            .offset
+            .checked_add(blob.size)
+            .and_then(|end| end.checked_next_multiple_of(BLOB_ALIGN))
+            .ok_or_else(|| anyhow!("blob at offset {at} runs past the end of the file"))?;
+    }
+    Ok(Bundle::new(storage, "coreml", tensors, Map::new(), false).with_problems(problems))
+}
+
+/// Whether `dir` is an `.mlpackage`
+pub fn is_package(dir: &Path) -> bool {
+    dir.join("Manifest.json").is_file() && dir.join("Data").is_dir()
+}
+
+/// Opens an `.mlpackage` directory through the root model named in its manifest
+pub fn open_package(dir: &Path) -> Result<Bundle<SegmentStorage>> {
+    let manifest: Value = serde_json::from_slice(&fs::read(dir.join("Manifest.json"))?)?;
+    let root = manifest["rootModelIdentifier"]
+        .as_str()
+        .and_then(|id| manifest["itemInfoEntries"][id]["path"].as_str())
+        .unwrap_or("com.apple.CoreML/model.mlmodel");
+    let model = dir.join("Data").join(root);
+    read_model(AnyStorage::open(&model, false)?, dir.display().to_string())
+}
+
+/// Opens a `.mlmodel` spec. Weights are only found if it is inside a package, next to the
+/// `weights` directory its ML Program points to.
+pub fn open_model(storage: AnyStorage) -> Result<Bundle<SegmentStorage>> {
+    let name = storage.display();
+    read_model(storage, name)
+}
+
+/// A value of the ML Program stored in a weight file
+struct Constant {
+    name: String,
+    data_type: u64,
+    shape: Option<Vec<u64>>,
+    file: String,
+    offset: u64,
+}
+
+fn read_model(mut storage: AnyStorage, name: String) -> Result<Bundle<SegmentStorage>> {
+    let path = storage.display();
+    let mut metadata = Map::new();
+    let mut constants = Vec::new();
+    let mut problems = Vec::new();
+    {
+        let reader = storage.reader()?;
+        let len = reader.seek(SeekFrom::End(0))?;
+        let model = read_fields(reader, 0..len)?;
+        let mut model_type = None;
+        for (number, value) in &model {
+            match (*number, value) {
+                (1, Wire::Varint(version)) => {
+                    metadata.insert("specification_version".into(), (*version).into());
+                }
+                (2, Wire::Bytes(range)) => read_description(reader, range.clone(), &mut metadata)?,
+                (10, Wire::Varint(updatable)) => {
+                    metadata.insert("is_updatable".into(), (*updatable != 0).into());
+                }
+                (ML_PROGRAM_FIELD, Wire::Bytes(range)) => {
+                    model_type = Some("mlProgram".to_string());
+                    read_program(reader, range.clone(), &mut metadata, &mut constants)?;
+                }
+                // The model type is a oneof of every kind of model, starting at field 200
+                (200.., _) => {
+                    model_type = Some(match number {
+                        500 => "neuralNetwork".to_string(),
+                        other => format!("field {other}"),
+                    });
+                }
+                _ => {}
+            }
+        }
+        let Some(model_type) = model_type else {
+            bail!("{path} has no model, so it is not a Core ML spec");
+        };
+        if model_type != "mlProgram" {
+            problems.push(Problem::file(format!(
+                "only ML Program weights are listed, and this is a {model_type} model"
+            )));
+        }
+        metadata.insert("model_type".into(), model_type.into());
+    }
+
+    let mut dtypes = BTreeMap::<String, u64>::new();
+    for constant in &constants {
+        *dtypes
+            .entry(data_type_name(constant.data_type))
+            .or_default() += 1;
+    }
+    // Compute units are picked when the model is loaded, but the weight types show the
+    // precision it was converted with
+    metadata.insert("weight_dtypes".into(), json!(dtypes));
+
+    let model_dir = Path::new(&path)
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let mut segments = SegmentStorage::new(name);
+    let mut bases: HashMap<String, Result<u64, String>> = HashMap::new();
+    let mut tensors = Vec::with_capacity(constants.len());
+    for constant in constants {
+        let base = bases.entry(constant.file.clone()).or_insert_with(|| {
+            let file = match constant.file.strip_prefix(MODEL_PATH) {
+                Some(rest) => model_dir.join(rest.trim_start_matches('/')),
+                None => PathBuf::from(&constant.file),
+            };
+            AnyStorage::open(&file, false)
+                .and_then(|storage| segments.push_file(storage))
+                .map(|range| range.start)
+                .map_err(|err| err.to_string())
+        });
+        let base = match base {
+            Ok(base) => *base,
+            Err(err) => {
+                problems.push(Problem::tensor(
+                    &constant.name,
+                    format!("is in {}, which can't be read: {err}", constant.file),
+                ));
+                continue;
+            }
+        };
+        let blob = match read_blob(&segments, base + constant.offset) {
+            Ok(blob) => blob,
+            Err(err) => {
+                problems.push(Problem::tensor(&constant.name, err.to_string()));
+                continue;
+            }
+        };
+        let ty = data_type_ty(constant.data_type);
+        if let TensorTy::Unknown(name) = &ty {
+            problems.push(Problem::tensor(
+                &constant.name,
+                format!("has Core ML type {name}, which can't be read"),
+            ));
+        }
+        let shape = match constant.shape {
+            Some(shape) => shape,
+            None => {
+                problems.push(Problem::tensor(
+                    &constant.name,
+                    "has a dynamic shape, so it is shown flat",
+                ));
+                let element_size = ty.element_size().unwrap_or(1) as u64;
+                vec![blob.size / element_size]
+            }
+        };
+        tensors.push((
+            constant.name,
+            TensorInfo {
+                ty,
+                shape,
+                size: blob.size as usize,
+                offset: base + blob.offset,
+            },
+        ));
+    }
+    Ok(Bundle::new(segments, "coreml", tensors, metadata, false).with_problems(problems))
+}
+
+/// Reads `ModelDescription`: the inputs, outputs, and author metadata
+fn read_description(
+    reader: &mut (impl Read + Seek + ?Sized),
+    range: Range<u64>,
+    metadata: &mut Map<String, Value>,
+) -> Result<()> {
+    let fields = read_fields(reader, range)?;
+    let mut inputs = Vec::new();
+    for range in messages(&fields, 1) {
+        inputs.push(read_feature(reader, range)?);
+    }
+    let mut outputs = Vec::new();
+    for range in messages(&fields, 10) {
+        outputs.push(read_feature(reader, range)?);
+    }
+    metadata.insert("inputs".into(), inputs.into());
+    metadata.insert("outputs".into(), outputs.into());
+    for range in messages(&fields, 100) {
+        let fields = read_fields(reader, range)?;
+        for (number, value) in &fields {
+            let key = match number {
+                1 => "short_description",
+                2 => "version",
+                3 => "author",
+                4 => "license",
+                _ => continue,
+            };
+            if let Wire::Bytes(range) = value {
+                metadata.insert(key.into(), read_string(reader, range)?.into());
+            }
+        }
+        // coremltools records its version and the framework the model came from here
+        for (key, value) in read_entries(reader, &fields, 100)? {
+            metadata.insert(key, value.into());
+        }
+    }
+    Ok(())
+}
+
+/// Describes a `FeatureDescription`, with the shape and type of multi-arrays and the size
+/// of images
+fn read_feature(reader: &mut (impl Read + Seek + ?Sized), range: Range<u64>) -> Result<Value> {
+    let mut feature = Map::new();
+    for (number, value) in read_fields(reader, range)? {
+        match (number, value) {
+            (1, Wire::Bytes(range)) => {
+                feature.insert("name".into(), read_string(reader, &range)?.into());
+            }
+            (3, Wire::Bytes(range)) => {
+                for (number, value) in read_fields(reader, range)? {
+                    let Wire::Bytes(range) = value else {
+                        continue;
+                    };
+                    let kind = match number {
+                        1 => "int64",
+                        2 => "double",
+                        3 => "string",
+                        4 => "image",
+                        5 => "multiArray",
+                        6 => "dictionary",
+                        7 => "sequence",
+                        8 => "state",
+                        _ => continue,
+                    };
+                    feature.insert("type".into(), kind.into());
+                    let fields = read_fields(reader, range)?;
+                    match number {
+                        4 => {
+                            for (number, value) in fields {
+                                match (number, value) {
+                                    (1, Wire::Varint(width)) => {
+                                        feature.insert("width".into(), width.into());
+                                    }
+                                    (2, Wire::Varint(height)) => {
+                                        feature.insert("height".into(), height.into());
+                                    }
+                                    _ => {}
+                                }
+                            }
+                        }
+                        5 => {
+                            feature
+                                .insert("shape".into(), read_varints(reader, &fields, 1)?.into());
+                            let dtype = fields.iter().find_map(|(number, value)| match value {
+                                Wire::Varint(code) if *number == 2 => Some(*code),
+                                _ => None,
+                            });
+                            let dtype = match dtype {
+                                Some(65552) => "FLOAT16",
+                                Some(65568) => "FLOAT32",
+                                Some(65600) => "DOUBLE",
+                                Some(131104) => "INT32",
+                                _ => "INVALID",
+                            };
+                            feature.insert("dtype".into(), dtype.into());
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(feature.into())
+}
+
+/// Reads an ML Program, collecting every value stored in a weight file
+fn read_program(
+    reader: &mut (impl Read + Seek + ?Sized),
+    range: Range<u64>,
+    metadata: &mut Map<String, Value>,
+    constants: &mut Vec<Constant>,
+) -> Result<()> {
+    let fields = read_fields(reader, range)?;
+    let mut opsets = Map::new();
+    for (function, range) in read_map(reader, &fields, 2)? {
+        let fields = read_fields(reader, range)?;
+        let opset = match messages(&fields, 2).next() {
+            Some(range) => read_string(reader, &range)?,
+            None => String::new(),
+        };
+        // Values of any function but main are named after it
+        let prefix = match function.as_str() {
+            "main" => String::new(),
+            _ => format!("{function}/"),
+        };
+        for (specialization, block) in read_map(reader, &fields, 3)? {
+            if specialization == opset {
+                read_block(reader, block, &prefix, constants)?;
+            }
+        }
+        opsets.insert(function, opset.into());
+    }
+    metadata.insert("functions".into(), opsets.into());
+    Ok(())
+}
+
+fn read_block(
+    reader: &mut (impl Read + Seek + ?Sized),
+    range: Range<u64>,
+    prefix: &str,
+    constants: &mut Vec<Constant>,
+) -> Result<()> {
+    let fields = read_fields(reader, range)?;
+    for operation in messages(&fields, 3) {
+        let fields = read_fields(reader, operation)?;
+        let output = match messages(&fields, 3).next() {
+            Some(output) => {
+                let fields = read_fields(reader, output)?;
+                match messages(&fields, 1).next() {
+                    Some(name) => read_string(reader, &name)?,
+                    None => String::new(),
+                }
+            }
+            None => String::new(),
+        };
+        for (attribute, value) in read_map(reader, &fields, 5)? {
+            // `val` holds the output of a `const`, and other attributes hold the pieces of
+            // compressed weights, like the lookup table of `constexpr_lut_to_dense`
+            let name = match attribute.as_str() {
+                "val" => format!("{prefix}{output}"),
+                _ => format!("{prefix}{output}/{attribute}"),
+            };
+            if let Some(constant) = read_blob_value(reader, value, name)? {
+                constants.push(constant);
+            }
+        }
+        for block in messages(&fields, 4) {
+            read_block(reader, block, prefix, constants)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads a MIL `Value`, if it is stored in a weight file
+fn read_blob_value(
+    reader: &mut (impl Read + Seek + ?Sized),
+    range: Range<u64>,
+    name: String,
+) -> Result<Option<Constant>> {
+    let fields = read_fields(reader, range)?;
+    let Some(blob) = messages(&fields, 5).next() else {
+        return Ok(None);
+    };
+    let mut file = String::new();
+    let mut offset = 0;
+    for (number, value) in read_fields(reader, blob)? {
+        match (number, value) {
+            (1, Wire::Bytes(range)) => file = read_string(reader, &range)?,
+            (2, Wire::Varint(value)) => offset = value,
+            _ => {}
+        }
+    }
+    let mut data_type = 0;
+    let mut shape = None;
+    let tensor_type = match messages(&fields, 2).next() {
+        Some(value_type) => messages(&read_fields(reader, value_type)?, 1).next(),
+        None => None,
+    };
+    if let Some(tensor_type) = tensor_type {
+        let fields = read_fields(reader, tensor_type)?;
+        for (number, value) in &fields {
+            if let (1, Wire::Varint(code)) = (number, value) {
+                data_type = *code;
+            }
+        }
+        let mut dims = Vec::new();
+        for dimension in messages(&fields, 3) {
+            let fields = read_fields(reader, dimension)?;
+            let constant = messages(&fields, 1)
+                .next()
+                .map(|constant| read_fields(reader, constant))
+                .transpose()?;
+            let size = constant.and_then(|fields| {
+                fields.iter().find_map(|(number, value)| match value {
+                    Wire::Varint(size) if *number == 1 => Some(*size),
+                    _ => None,
+                })
+            });
+            dims.push(size);
+        }
+        shape = dims.into_iter().collect();
+    }
+    if file.is_empty() {
+        return Err(anyhow!("{name} is stored in a blob file with no name"));
+    }
+    Ok(Some(Constant {
+        name,
+        data_type,
+        shape,
+        file,
+        offset,
+    }))
+}
+
+/// The name MIL gives a `DataType`
+fn data_type_name(code: u64) -> String {
+    match code {
+        1 => "BOOL",
+        2 => "STRING",
+        10 => "FLOAT16",
+        11 => "FLOAT32",
+        12 => "FLOAT64",
+        13 => "BFLOAT16",
+        21 => "INT8",
+        22 => "INT16",
+        23 => "INT32",
+        24 => "INT64",
+        25 => "INT4",
+        31 => "UINT8",
+        32 => "UINT16",
+        33 => "UINT32",
+        34 => "UINT64",
+        35 => "UINT4",
+        36 => "UINT2",
+        37 => "UINT1",
+        38 => "UINT6",
+        39 => "UINT3",
+        other => return format!("DataType({other})"),
+    }
+    .to_string()
+}
+
+fn data_type_ty(code: u64) -> TensorTy {
+    use TensorTy::*;
+    match code {
+        1 => BOOL,
+        10 => F16,
+        11 => F32,
+        12 => F64,
+        13 => BF16,
+        21 => I8,
+        22 => I16,
+        23 => I32,
+        24 => I64,
+        31 => U8,
+        32 => U16,
+        33 => U32,
+        34 => U64,
+        other => Unknown(data_type_name(other)),
+    }
+}
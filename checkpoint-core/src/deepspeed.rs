@@ -0,0 +1,284 @@
+//! DeepSpeed checkpoint directories, as saved by `engine.save_checkpoint` or Lightning's
+//! DeepSpeed strategy. Every `*_model_states.pt` and `*_optim_states.pt` file is listed side by
+//! side under its own name, and when the ZeRO optimizer shards are all present, the flattened
+//! fp32 partitions are stitched back into whole parameters under `fp32`.
+
+use crate::bundle::Bundle;
+use crate::integrity::Problem;
+use crate::model::{TensorInfo, TensorTy};
+use crate::pytorch::{Flattened, TorchPickle};
+use crate::storage::{AnyStorage, SegmentStorage};
+use anyhow::{Error, Result, anyhow, bail};
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Names of the files DeepSpeed writes for each data-parallel (`zero_pp_rank`) and
+/// model-parallel (`mp_rank`) rank
+const STATES_PATTERN: &str =
+    r"^(?:bf16_)?(?:zero_pp_rank_(\d+)_)?mp_rank_(\d+)_(model|optim)_states\.pt$";
+
+struct StatesFile {
+    path: PathBuf,
+    /// File name without `.pt`, which names its module in the tree
+    stem: String,
+    dp_rank: u64,
+    mp_rank: u64,
+    optim: bool,
+}
+
+/// A states file read into memory, with `base` where it starts in the [`SegmentStorage`]
+struct Shard {
+    file: StatesFile,
+    pickle: TorchPickle,
+    base: u64,
+}
+
+/// The ZeRO stage and the whole fp32 parameters rebuilt from the optimizer partitions
+struct Reassembled {
+    stage: i64,
+    weights: Vec<(String, TensorInfo)>,
+}
+
+/// The directory holding the state files: `dir` itself, or the `checkpoint` directory inside
+/// a Lightning `.ckpt` directory
+pub fn checkpoint_dir(dir: &Path) -> Option<PathBuf> {
+    [dir.to_path_buf(), dir.join("checkpoint")]
+        .into_iter()
+        .find(|dir| list_states_files(dir).is_ok_and(|files| files.iter().any(|f| !f.optim)))
+}
+
+fn list_states_files(dir: &Path) -> Result<Vec<StatesFile>> {
+    let pattern = Regex::new(STATES_PATTERN).unwrap();
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some(found) = pattern.captures(&name) else {
+            continue;
+        };
+        let rank = |i: usize| found.get(i).map_or(Ok(0), |rank| rank.as_str().parse());
+        files.push(StatesFile {
+            path: entry.path(),
+            stem: name.trim_end_matches(".pt").to_string(),
+            dp_rank: rank(1)?,
+            mp_rank: rank(2)?,
+            optim: &found[3] == "optim",
+        });
+    }
+    files.sort_by_key(|file| (file.mp_rank, file.optim, file.dp_rank));
+    Ok(files)
+}
+
+/// Opens a DeepSpeed checkpoint directory, or a Lightning `.ckpt` directory holding one
+pub fn open(dir: &Path) -> Result<Bundle<SegmentStorage>> {
+    let dir = checkpoint_dir(dir)
+        .ok_or_else(|| anyhow!("{} is not a DeepSpeed checkpoint", dir.display()))?;
+    let mut storage = SegmentStorage::new(dir.display().to_string());
+    let mut tensors = Vec::new();
+    let mut metadata = Map::new();
+    let mut problems = Vec::new();
+    let mut shards = Vec::new();
+    for file in list_states_files(&dir)? {
+        let mut file_storage = AnyStorage::open(&file.path, false)?;
+        let pickle = TorchPickle::read(&mut file_storage)?;
+        let base = storage.push_file(file_storage)?.start;
+
+        let Flattened {
+            tensors: file_tensors,
+            metadata: file_metadata,
+            problems: file_problems,
+        } = pickle.flatten();
+        let stem = &file.stem;
+        tensors.extend(file_tensors.into_iter().map(|(name, mut tensor)| {
+            tensor.offset += base;
+            (format!("{stem}/{name}"), tensor)
+        }));
+        problems.extend(
+            file_problems
+                .into_iter()
+                .map(|problem| match problem.tensor {
+                    Some(tensor) => Problem::tensor(&format!("{stem}/{tensor}"), problem.message),
+                    None => Problem::file(format!("{stem}: {}", problem.message)),
+                }),
+        );
+        metadata.insert(stem.clone(), file_metadata.into());
+        shards.push(Shard { file, pickle, base });
+    }
+
+    if shards.iter().any(|shard| shard.file.mp_rank > 0) {
+        problems.push(Problem::file(
+            "only model-parallel rank 0 is reassembled into fp32 weights",
+        ));
+    }
+    match reassemble(&shards, &mut storage) {
+        Ok(Some(Reassembled { stage, weights })) => {
+            metadata.insert("zero_stage".into(), Value::from(stage));
+            tensors.extend(weights);
+        }
+        Ok(None) => {}
+        Err(err) => problems.push(Problem::file(format!(
+            "the ZeRO shards could not be reassembled into fp32 weights: {err}"
+        ))),
+    }
+    Ok(Bundle::new(storage, "deepspeed", tensors, metadata, true).with_problems(problems))
+}
+
+/// Elements of a flat fp32 partition, as its byte offset in the [`SegmentStorage`] and its
+/// number of elements
+type Partition = (u64, u64);
+
+/// Byte ranges covering `len` elements starting at element `start` of the concatenation of
+/// `partitions`
+fn slice_partitions(partitions: &[Partition], start: u64, len: u64) -> Result<Vec<Range<u64>>> {
+    let mut ranges = Vec::new();
+    let mut skip = start;
+    let mut left = len;
+    for &(offset, numel) in partitions {
+        if left == 0 {
+            break;
+        }
+        if skip >= numel {
+            skip -= numel;
+            continue;
+        }
+        let take = left.min(numel - skip);
+        let from = offset + skip * 4;
+        ranges.push(from..from + take * 4);
+        left -= take;
+        skip = 0;
+    }
+    if left > 0 {
+        bail!("the partitions hold fewer elements than the parameters need");
+    }
+    Ok(ranges)
+}
+
+/// Undoes ZeRO partitioning following the same rules as DeepSpeed's `zero_to_fp32.py`,
+/// returning the stage and the whole parameters, or `None` without any optimizer shards
+fn reassemble(shards: &[Shard], storage: &mut SegmentStorage) -> Result<Option<Reassembled>> {
+    let optim: Vec<_> = shards
+        .iter()
+        .filter(|shard| shard.file.optim && shard.file.mp_rank == 0)
+        .collect();
+    if optim.is_empty() {
+        return Ok(None);
+    }
+    let Some(model) = shards.iter().find(|shard| !shard.file.optim) else {
+        bail!("there is no model states file");
+    };
+    let model = &model.pickle;
+    let shapes = model
+        .get(model.root(), "param_shapes")
+        .ok_or_else(|| anyhow!("the model states have no param_shapes"))?;
+    // One ordered dict of name to shape for each parameter group
+    let groups = match model.elements(shapes) {
+        [] => vec![shapes],
+        groups => groups.to_vec(),
+    };
+    let mut params = Vec::new();
+    for group in groups {
+        let mut group_params = Vec::new();
+        for (name, shape) in model.items(group) {
+            let shape = model
+                .shape(shape)
+                .ok_or_else(|| anyhow!("{name} has an invalid shape"))?;
+            group_params.push((name, shape));
+        }
+        params.push(group_params);
+    }
+
+    let mut stage = None;
+    let mut ranks = Vec::new();
+    for Shard { file, pickle, base } in optim {
+        let stem = &file.stem;
+        let state = pickle
+            .get(pickle.root(), "optimizer_state_dict")
+            .ok_or_else(|| anyhow!("{stem} has no optimizer_state_dict"))?;
+        let zero_stage = pickle
+            .get(state, "zero_stage")
+            .and_then(|stage| pickle.int(stage))
+            .ok_or_else(|| anyhow!("{stem} has no zero_stage"))?;
+        let key = match zero_stage {
+            ..=2 => "single_partition_of_fp32_groups",
+            _ => "fp32_flat_groups",
+        };
+        let flat = pickle
+            .get(state, key)
+            .ok_or_else(|| anyhow!("{stem} has no {key}"))?;
+        let mut partitions = Vec::new();
+        for &group in pickle.elements(flat) {
+            let tensor = pickle.tensor(group)?;
+            if !matches!(tensor.ty, TensorTy::F32) {
+                bail!("{stem} holds {} partitions instead of F32", tensor.ty);
+            }
+            partitions.push((tensor.offset + base, tensor.size as u64 / 4));
+        }
+        if stage.is_some_and(|stage| stage != zero_stage) {
+            bail!("the shards disagree about the ZeRO stage");
+        }
+        stage = Some(zero_stage);
+        ranks.push(partitions);
+    }
+    let Some(stage) = stage else {
+        return Ok(None);
+    };
+
+    let world_size = ranks.len() as u64;
+    let mut weights = Vec::new();
+    let mut push = |name: &str, shape: Vec<u64>, ranges: Vec<Range<u64>>| {
+        let numel = shape.iter().product::<u64>();
+        let offset = storage.append_ranges(&ranges)?;
+        weights.push((
+            format!("fp32/{name}"),
+            TensorInfo {
+                ty: TensorTy::F32,
+                shape,
+                size: (numel * 4) as usize,
+                offset,
+            },
+        ));
+        Ok::<_, Error>(())
+    };
+    match stage {
+        // Each rank holds one contiguous slice of every group, and the parameters of a group
+        // are packed back to back across the slices
+        1 | 2 => {
+            for (i, group) in params.into_iter().enumerate() {
+                let partitions = ranks
+                    .iter()
+                    .map(|rank| rank.get(i).copied())
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| anyhow!("a shard is missing parameter group {i}"))?;
+                let mut offset = 0;
+                for (name, shape) in group {
+                    let numel = shape.iter().product::<u64>();
+                    push(&name, shape, slice_partitions(&partitions, offset, numel)?)?;
+                    offset += numel;
+                }
+            }
+        }
+        // Each parameter is padded and split evenly across the ranks, and every rank packs
+        // its pieces of all the parameters back to back
+        3 => {
+            let mut offset = 0;
+            for (name, shape) in params.into_iter().flatten() {
+                let numel = shape.iter().product::<u64>();
+                let part = numel.div_ceil(world_size);
+                let mut ranges = Vec::new();
+                let mut left = numel;
+                for rank in &ranks {
+                    let take = part.min(left);
+                    ranges.extend(slice_partitions(rank, offset, take)?);
+                    left -= take;
+                }
+                push(&name, shape, ranges)?;
+                offset += part;
+            }
+        }
+        other => bail!("ZeRO stage {other} is not supported"),
+    }
+    Ok(Some(Reassembled { stage, weights }))
+}
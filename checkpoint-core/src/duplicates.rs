@@ -0,0 +1,141 @@
+use anyhow::{Error, anyhow};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hasher};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed};
+use std::sync::{Arc, Mutex, OnceLock};
+use weakref::{Ref, pin};
+
+use crate::manifest::visit_tensor_bytes;
+use crate::model::{ModuleSource, TensorInfo};
+use crate::notify;
+
+/// Tensors holding exactly the same bytes
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    pub names: Vec<String>,
+    /// Whether they all point at the same place in the file, as tied weights can, rather than
+    /// each storing a copy
+    pub shared: bool,
+}
+
+/// Finds byte-identical tensors in the background, hashing only those whose sizes match
+pub struct DuplicateScan {
+    pub tensors: Vec<(String, TensorInfo)>,
+    /// How many tensors need hashing
+    pub total: usize,
+    pub done: AtomicUsize,
+    /// Percent read of the tensor currently being hashed
+    pub progress: AtomicU64,
+    pub groups: OnceLock<Vec<DuplicateGroup>>,
+    pub error: OnceLock<Error>,
+}
+
+/// Tensors with a size no other tensor has can't have a duplicate, so they're never read
+fn candidates(tensors: &[(String, TensorInfo)]) -> Vec<&(String, TensorInfo)> {
+    let mut by_size: HashMap<usize, usize> = HashMap::new();
+    for (_, tensor) in tensors {
+        *by_size.entry(tensor.size).or_default() += 1;
+    }
+    tensors
+        .iter()
+        .filter(|(_, tensor)| tensor.size > 0 && by_size[&tensor.size] > 1)
+        .collect()
+}
+
+impl DuplicateScan {
+    pub fn new(tensors: Vec<(String, TensorInfo)>) -> Self {
+        DuplicateScan {
+            total: candidates(&tensors).len(),
+            tensors,
+            done: AtomicUsize::new(0),
+            progress: AtomicU64::new(0),
+            groups: OnceLock::new(),
+            error: OnceLock::new(),
+        }
+    }
+
+    /// The group `name` belongs to, if it has any duplicates
+    pub fn group(&self, name: &str) -> Option<&DuplicateGroup> {
+        self.groups
+            .get()?
+            .iter()
+            .find(|group| group.names.iter().any(|other| other == name))
+    }
+}
+
+fn hash_tensor(
+    source: &Mutex<dyn ModuleSource + Send>,
+    tensor: &TensorInfo,
+    progress: Ref<AtomicU64>,
+) -> Result<u64, Error> {
+    let mut hasher = DefaultHasher::new();
+    visit_tensor_bytes(source, tensor, progress, &mut |bytes| hasher.write(bytes))?;
+    Ok(hasher.finish())
+}
+
+fn do_duplicate_scan(
+    source: &Mutex<dyn ModuleSource + Send>,
+    request: Ref<DuplicateScan>,
+) -> Result<(), Error> {
+    let tensors = request
+        .inspect(|req| req.tensors.clone())
+        .ok_or(anyhow!("cancelled"))?;
+    let progress = request.map(|req| &req.progress);
+
+    // Tensors at the same offset share their bytes, so each place only needs hashing once
+    let mut hashes: HashMap<(u64, usize), u64> = HashMap::new();
+    let mut by_content: HashMap<(usize, u64), Vec<&(String, TensorInfo)>> = HashMap::new();
+    for entry in candidates(&tensors) {
+        let (_, tensor) = entry;
+        let place = (tensor.offset, tensor.size);
+        let hash = match hashes.get(&place) {
+            Some(&hash) => hash,
+            None => {
+                let hash = hash_tensor(source, tensor, progress)?;
+                hashes.insert(place, hash);
+                hash
+            }
+        };
+        by_content
+            .entry((tensor.size, hash))
+            .or_default()
+            .push(entry);
+        request
+            .inspect(|req| req.done.fetch_add(1, Relaxed))
+            .ok_or(anyhow!("cancelled"))?;
+        notify::notify();
+    }
+
+    let mut groups: Vec<DuplicateGroup> = by_content
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .map(|group| DuplicateGroup {
+            shared: group
+                .iter()
+                .all(|(_, tensor)| tensor.offset == group[0].1.offset),
+            names: group.into_iter().map(|(name, _)| name.clone()).collect(),
+        })
+        .collect();
+    for group in &mut groups {
+        group.names.sort();
+    }
+    groups.sort_by(|a, b| a.names.cmp(&b.names));
+    {
+        let _ = request
+            .map(|req| &req.groups)
+            .get(&pin())
+            .ok_or(anyhow!("cancelled"))?
+            .set(groups);
+    }
+    Ok(())
+}
+
+pub fn start_duplicate_scan(source: Arc<Mutex<dyn ModuleSource + Send>>, scan: Ref<DuplicateScan>) {
+    notify::spawn(move || {
+        if let Err(err) = do_duplicate_scan(&*source, scan) {
+            scan.inspect(|s| {
+                let _ = s.error.set(err);
+            });
+        }
+    });
+}
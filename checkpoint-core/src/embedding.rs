@@ -0,0 +1,322 @@
+use anyhow::{Error, anyhow, bail};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hasher};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed};
+use std::sync::{Arc, Mutex, OnceLock};
+use weakref::Ref;
+
+use crate::arch::EMBEDDING_NAMES;
+use crate::model::{ModuleSource, TensorInfo};
+use crate::notify;
+
+/// Rows with less than this fraction of the median norm are likely untrained
+const LOW_NORM_FRACTION: f32 = 0.1;
+
+/// Why a row of the embedding looks like it was never trained
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RowFlag {
+    Zero,
+    NonFinite,
+    /// Far below the median norm
+    LowNorm,
+    /// Exactly equal to `count - 1` other rows, as when added tokens all start from the mean
+    /// embedding
+    Duplicate {
+        of: u32,
+        count: usize,
+    },
+}
+
+impl RowFlag {
+    pub fn label(&self) -> String {
+        match self {
+            RowFlag::Zero => "zero".to_string(),
+            RowFlag::NonFinite => "NaN/inf".to_string(),
+            RowFlag::LowNorm => "low norm".to_string(),
+            RowFlag::Duplicate { of, count: 2 } => format!("same as {of}"),
+            RowFlag::Duplicate { of, count } => format!("same as {of} (+{})", count - 2),
+        }
+    }
+}
+
+/// The norm of every token's row, and the rows that look untrained
+pub struct EmbeddingStats {
+    pub norms: Vec<f32>,
+    pub median: f32,
+    pub flags: Vec<Option<RowFlag>>,
+}
+
+impl EmbeddingStats {
+    /// Ids of every flagged row, in order
+    pub fn flagged(&self) -> Vec<u32> {
+        (0..self.flags.len() as u32)
+            .filter(|&id| self.flags[id as usize].is_some())
+            .collect()
+    }
+
+    /// How many rows have a flag matching `pred`
+    pub fn count(&self, pred: impl Fn(&RowFlag) -> bool) -> usize {
+        self.flags
+            .iter()
+            .flatten()
+            .filter(|flag| pred(flag))
+            .count()
+    }
+}
+
+/// Per-token statistics of the embedding matrix that goes with a vocabulary, read in the
+/// background
+pub struct EmbeddingAnalysis {
+    pub name: String,
+    pub tensor: TensorInfo,
+    pub progress: AtomicU64,
+    pub stats: OnceLock<EmbeddingStats>,
+    pub error: OnceLock<Error>,
+}
+
+impl EmbeddingAnalysis {
+    pub fn new(name: String, tensor: TensorInfo) -> Self {
+        EmbeddingAnalysis {
+            name,
+            tensor,
+            progress: AtomicU64::new(0),
+            stats: OnceLock::new(),
+            error: OnceLock::new(),
+        }
+    }
+
+    pub fn dim(&self) -> usize {
+        self.tensor.shape.get(1).copied().unwrap_or(0) as usize
+    }
+}
+
+/// The rows most similar to one token's row, by cosine similarity
+pub struct Neighbors {
+    pub token: u32,
+    pub count: usize,
+    /// 1 while finding the token's own row, 2 while comparing every row against it
+    pub pass: AtomicUsize,
+    pub progress: AtomicU64,
+    pub nearest: OnceLock<Vec<(u32, f32)>>,
+    pub error: OnceLock<Error>,
+}
+
+impl Neighbors {
+    pub fn new(token: u32, count: usize) -> Self {
+        Neighbors {
+            token,
+            count,
+            pass: AtomicUsize::new(1),
+            progress: AtomicU64::new(0),
+            nearest: OnceLock::new(),
+            error: OnceLock::new(),
+        }
+    }
+}
+
+/// The tensor holding one row per token of a `vocab_size` vocabulary, preferring the usual
+/// names of input embeddings over output heads of the same shape
+pub fn find_embedding(
+    tensors: &[(String, TensorInfo)],
+    vocab_size: usize,
+) -> Option<(String, TensorInfo)> {
+    let candidates: Vec<_> = tensors
+        .iter()
+        .filter(|(_, tensor)| tensor.ty.is_float() && tensor.shape.len() == 2)
+        .filter(|(_, tensor)| tensor.shape[0] as usize == vocab_size)
+        .collect();
+    EMBEDDING_NAMES
+        .iter()
+        .find_map(|part| candidates.iter().find(|(name, _)| name.contains(part)))
+        .or(candidates.first())
+        .map(|&(name, tensor)| (name.clone(), tensor.clone()))
+}
+
+/// Calls `visit` with each row of a 2-D tensor in turn, however the reader splits it into chunks
+fn visit_rows(
+    source: &Mutex<dyn ModuleSource + Send>,
+    tensor: &TensorInfo,
+    progress: Ref<AtomicU64>,
+    mut visit: impl FnMut(usize, &[f32]) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let dim = tensor.shape.get(1).copied().unwrap_or(0) as usize;
+    if dim == 0 {
+        bail!("embedding has no columns");
+    }
+    let mut row = Vec::with_capacity(dim);
+    let mut index = 0;
+    source
+        .lock()
+        .unwrap()
+        .tensor_chunks_f32(tensor.clone(), progress, &mut |mut chunk| {
+            while !chunk.is_empty() {
+                let take = (dim - row.len()).min(chunk.len());
+                row.extend_from_slice(&chunk[..take]);
+                chunk = &chunk[take..];
+                if row.len() == dim {
+                    visit(index, &row)?;
+                    index += 1;
+                    row.clear();
+                }
+            }
+            Ok(())
+        })
+}
+
+fn do_embedding(
+    source: &Mutex<dyn ModuleSource + Send>,
+    request: Ref<EmbeddingAnalysis>,
+) -> Result<(), Error> {
+    let tensor = request
+        .inspect(|req| req.tensor.clone())
+        .ok_or(anyhow!("cancelled"))?;
+    let progress = request.map(|req| &req.progress);
+    let mut norms = Vec::new();
+    let mut flags = Vec::new();
+    let mut by_hash: HashMap<u64, Vec<u32>> = HashMap::new();
+    visit_rows(source, &tensor, progress, |index, row| {
+        let norm = row.iter().map(|&x| x as f64 * x as f64).sum::<f64>().sqrt() as f32;
+        norms.push(norm);
+        flags.push(if !norm.is_finite() {
+            Some(RowFlag::NonFinite)
+        } else if norm == 0.0 {
+            Some(RowFlag::Zero)
+        } else {
+            let mut hasher = DefaultHasher::new();
+            row.iter().for_each(|x| hasher.write_u32(x.to_bits()));
+            by_hash
+                .entry(hasher.finish())
+                .or_default()
+                .push(index as u32);
+            None
+        });
+        Ok(())
+    })?;
+
+    let mut finite: Vec<f32> = norms.iter().copied().filter(|x| x.is_finite()).collect();
+    let median = if finite.is_empty() {
+        0.0
+    } else {
+        let middle = finite.len() / 2;
+        *finite.select_nth_unstable_by(middle, f32::total_cmp).1
+    };
+    for (index, &norm) in norms.iter().enumerate() {
+        if flags[index].is_none() && norm < median * LOW_NORM_FRACTION {
+            flags[index] = Some(RowFlag::LowNorm);
+        }
+    }
+    // A duplicate says more about how a row was made than a low norm does
+    for group in by_hash.values().filter(|group| group.len() > 1) {
+        for &id in group {
+            let of = if id == group[0] { group[1] } else { group[0] };
+            flags[id as usize] = Some(RowFlag::Duplicate {
+                of,
+                count: group.len(),
+            });
+        }
+    }
+
+    request
+        .inspect(|req| {
+            let _ = req.stats.set(EmbeddingStats {
+                norms,
+                median,
+                flags,
+            });
+        })
+        .ok_or(anyhow!("cancelled"))
+}
+
+pub fn start_embedding(
+    source: Arc<Mutex<dyn ModuleSource + Send>>,
+    analysis: Ref<EmbeddingAnalysis>,
+) {
+    notify::spawn(move || {
+        if let Err(err) = do_embedding(&*source, analysis) {
+            analysis.inspect(|a| {
+                let _ = a.error.set(err);
+            });
+        }
+    });
+}
+
+fn do_neighbors(
+    source: &Mutex<dyn ModuleSource + Send>,
+    tensor: &TensorInfo,
+    request: Ref<Neighbors>,
+) -> Result<(), Error> {
+    let (token, count) = request
+        .inspect(|req| (req.token as usize, req.count))
+        .ok_or(anyhow!("cancelled"))?;
+    let progress = request.map(|req| &req.progress);
+
+    // Stops reading as soon as the row turns up, rather than going through the whole matrix
+    let mut query = None;
+    let found = visit_rows(source, tensor, progress, |index, row| {
+        if index == token {
+            query = Some(row.to_vec());
+            bail!("found");
+        }
+        Ok(())
+    });
+    let query = match (query, found) {
+        (Some(query), _) => query,
+        (None, Err(err)) => return Err(err),
+        (None, Ok(())) => bail!("token {token} has no row in the embedding"),
+    };
+    let query_norm = query
+        .iter()
+        .map(|&x| x as f64 * x as f64)
+        .sum::<f64>()
+        .sqrt();
+    if query_norm == 0.0 || !query_norm.is_finite() {
+        bail!("token {token} has a zero or non-finite embedding");
+    }
+
+    request
+        .inspect(|req| req.pass.store(2, Relaxed))
+        .ok_or(anyhow!("cancelled"))?;
+    notify::notify();
+    let mut nearest: Vec<(u32, f32)> = Vec::with_capacity(count + 1);
+    visit_rows(source, tensor, progress, |index, row| {
+        if index == token {
+            return Ok(());
+        }
+        let (mut dot, mut norm) = (0.0, 0.0);
+        for (&a, &b) in row.iter().zip(&query) {
+            dot += a as f64 * b as f64;
+            norm += a as f64 * a as f64;
+        }
+        if norm == 0.0 || !norm.is_finite() {
+            return Ok(());
+        }
+        let similarity = (dot / (norm.sqrt() * query_norm)) as f32;
+        if nearest.len() < count || similarity > nearest[nearest.len() - 1].1 {
+            let at = nearest.partition_point(|&(_, s)| s >= similarity);
+            nearest.insert(at, (index as u32, similarity));
+            nearest.truncate(count);
+        }
+        Ok(())
+    })?;
+
+    request
+        .inspect(|req| {
+            let _ = req.nearest.set(nearest);
+        })
+        .ok_or(anyhow!("cancelled"))
+}
+
+/// Finds the rows of `tensor` closest to the row of `neighbors.token`, reading it twice
+pub fn start_neighbors(
+    source: Arc<Mutex<dyn ModuleSource + Send>>,
+    tensor: TensorInfo,
+    neighbors: Ref<Neighbors>,
+) {
+    notify::spawn(move || {
+        if let Err(err) = do_neighbors(&*source, &tensor, neighbors) {
+            neighbors.inspect(|n| {
+                let _ = n.error.set(err);
+            });
+        }
+    });
+}
@@ -0,0 +1,290 @@
+use crate::bundle::Bundle;
+use crate::integrity::Problem;
+use crate::model::{TensorInfo, TensorTy};
+use crate::storage::Storage;
+use anyhow::{Result, bail};
+use serde_json::{Map, Value};
+use std::io::{Read, Seek, SeekFrom};
+
+/// Extension type `flax.serialization` uses for numpy arrays
+const EXT_NDARRAY: i8 = 1;
+/// Extension type `flax.serialization` uses for numpy scalars, stored like 0-d arrays
+const EXT_NPSCALAR: i8 = 3;
+/// Key marking an array which `flax.serialization` split into pieces to stay under the
+/// msgpack size limit
+const CHUNKED_ARRAY_KEY: &str = "__msgpack_chunked_array__";
+/// How deeply maps and arrays may nest before the file is taken to be corrupt, well past
+/// any real model but short of overflowing the stack
+const MAX_DEPTH: usize = 128;
+
+/// Opens a checkpoint written by `flax.serialization.to_bytes` (or `msgpack_serialize`),
+/// a msgpack map of nested dicts with numpy arrays at the leaves. Tensor names join the
+/// dict keys with `/`, as in `params/Dense_0/kernel`, and every other leaf becomes metadata.
+pub fn open<S: Storage>(mut storage: S) -> Result<Bundle<S>> {
+    let mut walk = Walk {
+        tensors: Vec::new(),
+        metadata: Map::new(),
+        problems: Vec::new(),
+    };
+    {
+        let reader = storage.reader()?;
+        reader.seek(SeekFrom::Start(0))?;
+        match read_head(reader)? {
+            Head::Map(len) => walk.map(reader, "", len, 0)?,
+            _ => bail!("{} does not hold a msgpack map", storage.display()),
+        }
+    }
+    let Walk {
+        tensors,
+        metadata,
+        problems,
+    } = walk;
+    Ok(Bundle::new(storage, "flax", tensors, metadata, true).with_problems(problems))
+}
+
+/// The first part of a msgpack value, with any payload still unread
+enum Head {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(u64),
+    Bin(u64),
+    Array(u64),
+    Map(u64),
+    Ext(i8, u64),
+}
+
+fn read_bytes<const N: usize>(reader: &mut (impl Read + ?Sized)) -> Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_head(reader: &mut (impl Read + ?Sized)) -> Result<Head> {
+    let [marker] = read_bytes(reader)?;
+    let u8 = |reader: &mut _| read_bytes::<1>(reader).map(|b| b[0] as u64);
+    let u16 = |reader: &mut _| read_bytes(reader).map(|b| u16::from_be_bytes(b) as u64);
+    let u32 = |reader: &mut _| read_bytes(reader).map(|b| u32::from_be_bytes(b) as u64);
+    let ext = |reader: &mut _, len| Ok(Head::Ext(read_bytes::<1>(reader)?[0] as i8, len));
+    Ok(match marker {
+        0x00..=0x7f => Head::UInt(marker as u64),
+        0x80..=0x8f => Head::Map((marker & 0x0f) as u64),
+        0x90..=0x9f => Head::Array((marker & 0x0f) as u64),
+        0xa0..=0xbf => Head::Str((marker & 0x1f) as u64),
+        0xc0 => Head::Nil,
+        0xc2 => Head::Bool(false),
+        0xc3 => Head::Bool(true),
+        0xc4 => Head::Bin(u8(reader)?),
+        0xc5 => Head::Bin(u16(reader)?),
+        0xc6 => Head::Bin(u32(reader)?),
+        0xc7 => {
+            let len = u8(reader)?;
+            return ext(reader, len);
+        }
+        0xc8 => {
+            let len = u16(reader)?;
+            return ext(reader, len);
+        }
+        0xc9 => {
+            let len = u32(reader)?;
+            return ext(reader, len);
+        }
+        0xca => Head::Float(f32::from_be_bytes(read_bytes(reader)?) as f64),
+        0xcb => Head::Float(f64::from_be_bytes(read_bytes(reader)?)),
+        0xcc => Head::UInt(u8(reader)?),
+        0xcd => Head::UInt(u16(reader)?),
+        0xce => Head::UInt(u32(reader)?),
+        0xcf => Head::UInt(u64::from_be_bytes(read_bytes(reader)?)),
+        0xd0 => Head::Int(i8::from_be_bytes(read_bytes(reader)?) as i64),
+        0xd1 => Head::Int(i16::from_be_bytes(read_bytes(reader)?) as i64),
+        0xd2 => Head::Int(i32::from_be_bytes(read_bytes(reader)?) as i64),
+        0xd3 => Head::Int(i64::from_be_bytes(read_bytes(reader)?)),
+        0xd4..=0xd8 => return ext(reader, 1 << (marker - 0xd4)),
+        0xd9 => Head::Str(u8(reader)?),
+        0xda => Head::Str(u16(reader)?),
+        0xdb => Head::Str(u32(reader)?),
+        0xdc => Head::Array(u16(reader)?),
+        0xdd => Head::Array(u32(reader)?),
+        0xde => Head::Map(u16(reader)?),
+        0xdf => Head::Map(u32(reader)?),
+        0xe0..=0xff => Head::Int(marker as i8 as i64),
+        0xc1 => bail!("invalid msgpack marker 0xc1"),
+    })
+}
+
+fn read_string(reader: &mut (impl Read + ?Sized), len: u64) -> Result<String> {
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        bail!("msgpack string runs past the end of the file");
+    }
+    Ok(String::from_utf8(bytes)?)
+}
+
+fn skip(reader: &mut (impl Seek + ?Sized), len: u64) -> Result<()> {
+    reader.seek(SeekFrom::Current(len.try_into()?))?;
+    Ok(())
+}
+
+/// Reads a whole value as JSON, skipping over binary payloads rather than loading them
+fn read_value(reader: &mut (impl Read + Seek + ?Sized), depth: usize) -> Result<Value> {
+    let head = read_head(reader)?;
+    value_from_head(reader, head, depth)
+}
+
+/// Reads the rest of a value whose head was already read, `depth` containers down
+fn value_from_head(
+    reader: &mut (impl Read + Seek + ?Sized),
+    head: Head,
+    depth: usize,
+) -> Result<Value> {
+    if depth > MAX_DEPTH {
+        bail!("msgpack is nested more than {MAX_DEPTH} levels deep");
+    }
+    Ok(match head {
+        Head::Nil => Value::Null,
+        Head::Bool(value) => value.into(),
+        Head::Int(value) => value.into(),
+        Head::UInt(value) => value.into(),
+        Head::Float(value) => value.into(),
+        Head::Str(len) => read_string(reader, len)?.into(),
+        Head::Bin(len) | Head::Ext(_, len) => {
+            skip(reader, len)?;
+            format!("<{len} bytes>").into()
+        }
+        Head::Array(len) => (0..len)
+            .map(|_| read_value(reader, depth + 1))
+            .collect::<Result<Vec<_>>>()?
+            .into(),
+        Head::Map(len) => {
+            let mut map = Map::new();
+            for _ in 0..len {
+                let key = key_string(read_value(reader, depth + 1)?)?;
+                map.insert(key, read_value(reader, depth + 1)?);
+            }
+            map.into()
+        }
+    })
+}
+
+/// Flax writes dict keys as strings, but list indices may show up as integers
+fn key_string(key: Value) -> Result<String> {
+    match key {
+        Value::String(key) => Ok(key),
+        Value::Number(key) => Ok(key.to_string()),
+        other => bail!("unsupported msgpack map key {other}"),
+    }
+}
+
+struct Walk {
+    tensors: Vec<(String, TensorInfo)>,
+    metadata: Map<String, Value>,
+    problems: Vec<Problem>,
+}
+
+impl Walk {
+    fn map(
+        &mut self,
+        reader: &mut (impl Read + Seek + ?Sized),
+        path: &str,
+        len: u64,
+        depth: usize,
+    ) -> Result<()> {
+        if depth > MAX_DEPTH {
+            bail!("msgpack is nested more than {MAX_DEPTH} levels deep");
+        }
+        for _ in 0..len {
+            let key = key_string(read_value(reader, depth + 1)?)?;
+            let name = match path {
+                "" => key.clone(),
+                _ => format!("{path}/{key}"),
+            };
+            if key == CHUNKED_ARRAY_KEY {
+                self.problems.push(Problem::tensor(
+                    path,
+                    "was split into chunks to fit in msgpack, so each chunk is shown separately",
+                ));
+            }
+            match read_head(reader)? {
+                Head::Map(len) => self.map(reader, &name, len, depth + 1)?,
+                Head::Ext(EXT_NDARRAY | EXT_NPSCALAR, len) => {
+                    let end = reader.stream_position()? + len;
+                    let tensor = self.array(reader, &name)?;
+                    self.tensors.push((name, tensor));
+                    reader.seek(SeekFrom::Start(end))?;
+                }
+                head => {
+                    let value = value_from_head(reader, head, depth + 1)?;
+                    self.metadata.insert(name, value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the `(shape, dtype, buffer)` triple inside an array extension, leaving the
+    /// buffer itself unread
+    fn array(
+        &mut self,
+        reader: &mut (impl Read + Seek + ?Sized),
+        name: &str,
+    ) -> Result<TensorInfo> {
+        let Head::Array(3) = read_head(reader)? else {
+            bail!("{name} is not a (shape, dtype, buffer) triple");
+        };
+        let shape = match read_value(reader, 0)? {
+            Value::Array(dims) => dims
+                .iter()
+                .map(|dim| dim.as_u64())
+                .collect::<Option<Vec<_>>>(),
+            _ => None,
+        };
+        let Some(shape) = shape else {
+            bail!("{name} has an invalid shape");
+        };
+        let Value::String(dtype) = read_value(reader, 0)? else {
+            bail!("{name} has an invalid dtype");
+        };
+        let Head::Bin(size) = read_head(reader)? else {
+            bail!("{name} has no data buffer");
+        };
+        let ty = dtype_ty(&dtype);
+        if let TensorTy::Unknown(_) = ty {
+            self.problems.push(Problem::tensor(
+                name,
+                format!("has numpy dtype {dtype}, which can't be read"),
+            ));
+        }
+        Ok(TensorInfo {
+            ty,
+            shape,
+            size: size as usize,
+            offset: reader.stream_position()?,
+        })
+    }
+}
+
+/// The tensor type of a numpy dtype name, as `flax.serialization` stores them
+fn dtype_ty(name: &str) -> TensorTy {
+    use TensorTy::*;
+    match name {
+        "bool" => BOOL,
+        "uint8" => U8,
+        "int8" => I8,
+        "uint16" => U16,
+        "int16" => I16,
+        "uint32" => U32,
+        "int32" => I32,
+        "uint64" => U64,
+        "int64" => I64,
+        "float16" => F16,
+        "bfloat16" => BF16,
+        "float32" => F32,
+        "float64" => F64,
+        "float8_e4m3fn" => F8_E4M3,
+        "float8_e5m2" => F8_E5M2,
+        other => Unknown(other.to_string()),
+    }
+}
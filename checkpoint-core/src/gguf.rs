@@ -0,0 +1,434 @@
+use crate::integrity::{Problem, Region, check_regions};
+use crate::model::{
+    LE, METADATA_PAGE, MetadataType, ModuleInfo, ModuleSource, PathSplit, TensorInfo, TensorTy,
+    as_lazy_array, lazy_array,
+};
+use crate::storage::{Storage, read_with_progress};
+use anyhow::{Error, Result, anyhow, bail};
+use ggml_base::{GgmlTensorInfo, GgmlTypeId, GgufFile, GgufValue};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{Seek, SeekFrom};
+use std::ops::Range;
+use std::sync::atomic::AtomicU64;
+use weakref::{Own, Ref};
+
+/// Hyperparameters llama.cpp refuses to load these architectures without, each prefixed by
+/// the architecture name
+const REQUIRED_KEYS: &[(&[&str], &[&str])] = &[
+    (
+        &[
+            "llama",
+            "mistral",
+            "qwen2",
+            "qwen2moe",
+            "qwen3",
+            "qwen3moe",
+            "gemma",
+            "gemma2",
+            "gemma3",
+            "phi2",
+            "phi3",
+            "falcon",
+            "gpt2",
+            "starcoder2",
+            "command-r",
+            "deepseek2",
+            "olmo",
+            "stablelm",
+        ],
+        &[
+            "context_length",
+            "embedding_length",
+            "block_count",
+            "attention.head_count",
+        ],
+    ),
+    (
+        &["bert", "nomic-bert"],
+        &["context_length", "embedding_length", "block_count"],
+    ),
+];
+
+pub struct Gguf<S> {
+    storage: S,
+    inner: GgufFile,
+}
+
+impl<S: Storage> Gguf<S> {
+    pub fn open(mut storage: S) -> std::result::Result<Self, Error> {
+        let inner = GgufFile::read(&mut storage.reader()?)?;
+        Ok(Gguf { storage, inner })
+    }
+
+    fn tensor_bytes(
+        &mut self,
+        offset: u64,
+        nbytes: usize,
+        progress: Ref<AtomicU64>,
+    ) -> Result<Vec<u8>> {
+        // The size comes from the header, so check it against the file before allocating
+        let file_len = self.storage.reader()?.seek(SeekFrom::End(0))?;
+        let start = offset
+            .checked_add(self.inner.data_start)
+            .filter(|start| {
+                start
+                    .checked_add(nbytes as u64)
+                    .is_some_and(|end| end <= file_len)
+            })
+            .ok_or_else(|| anyhow!("tensor runs past the end of the file"))?;
+        read_with_progress(&self.storage, start, nbytes, progress)
+    }
+}
+
+unsafe impl<S: Storage> Send for Gguf<S> where S: Send {}
+
+impl<S: Storage> ModuleSource for Gguf<S> {
+    fn module(&mut self, split: &PathSplit) -> Result<ModuleInfo> {
+        let tensors = &self.inner.tensors;
+        Ok(ModuleInfo::build_from_tensors(
+            tensors
+                .iter()
+                .map(|tensor| (tensor.name.clone(), TensorInfo::from(tensor))),
+            split,
+        ))
+    }
+
+    fn metadata(&mut self) -> Result<Value> {
+        let mut map = serde_json::value::Map::new();
+        for (k, v) in &self.inner.metadata {
+            let v = match v {
+                GgufValue::Array(arr) if arr.len() > METADATA_PAGE => lazy_array(k, 0..arr.len()),
+                v => v.into(),
+            };
+            map.insert(k.clone(), v);
+        }
+        Ok(map.into())
+    }
+
+    fn metadata_array(&mut self, key: &str, range: Range<usize>) -> Result<Vec<Value>> {
+        let Some(GgufValue::Array(values)) = self.inner.metadata.get(key) else {
+            bail!("{key} is not an array");
+        };
+        let values = values
+            .get(range)
+            .ok_or_else(|| anyhow!("{key} has only {} values", values.len()))?;
+        Ok(values.iter().map(Value::from).collect())
+    }
+
+    fn string_metadata(&mut self) -> Result<HashMap<String, String>> {
+        // Strings are stored as-is, everything else as its JSON encoding
+        let mut map = HashMap::with_capacity(self.inner.metadata.len());
+        for (k, v) in &self.inner.metadata {
+            let v = match v {
+                GgufValue::String(s) => s.clone(),
+                v => Value::from(v).to_string(),
+            };
+            map.insert(k.clone(), v);
+        }
+        Ok(map)
+    }
+
+    fn metadata_types(&mut self) -> Result<HashMap<String, MetadataType>> {
+        let metadata = &self.inner.metadata;
+        Ok(metadata
+            .iter()
+            .map(|(k, v)| (k.clone(), MetadataType::from(v)))
+            .collect())
+    }
+
+    fn write_metadata(&mut self, _metadata: &Value) -> std::result::Result<(), Error> {
+        bail!("editing gguf files is not yet supported")
+    }
+
+    fn rename_tensors(&mut self, _renames: &[(String, String)]) -> std::result::Result<(), Error> {
+        bail!("editing gguf files is not yet supported")
+    }
+
+    fn delete_tensors(&mut self, _names: &[String]) -> std::result::Result<(), Error> {
+        bail!("editing gguf files is not yet supported")
+    }
+
+    fn tensor_data(
+        &mut self,
+        tensor: TensorInfo,
+        progress: Ref<AtomicU64>,
+    ) -> std::result::Result<Vec<u8>, Error> {
+        self.tensor_bytes(tensor.offset, tensor.size, progress)
+    }
+
+    fn tensor_byte_range(
+        &mut self,
+        tensor: &TensorInfo,
+        range: Range<usize>,
+    ) -> std::result::Result<Vec<u8>, Error> {
+        let progress = Own::new_box(AtomicU64::new(0));
+        self.tensor_bytes(
+            tensor.offset + range.start as u64,
+            range.len(),
+            progress.refer(),
+        )
+    }
+
+    fn tensor_f32(
+        &mut self,
+        tensor: TensorInfo,
+        progress: Ref<AtomicU64>,
+    ) -> std::result::Result<Vec<f32>, Error> {
+        tensor.read_f32::<LE>(&self.tensor_bytes(tensor.offset, tensor.size, progress)?)
+    }
+
+    fn tensor_f64(
+        &mut self,
+        tensor: TensorInfo,
+        progress: Ref<AtomicU64>,
+    ) -> std::result::Result<Vec<f64>, Error> {
+        tensor.read_f64::<LE>(&self.tensor_bytes(tensor.offset, tensor.size, progress)?)
+    }
+
+    fn check(&mut self) -> Result<Vec<Problem>> {
+        let mut problems = Vec::new();
+        let alignment = match self.inner.metadata.get("general.alignment") {
+            None => self.inner.alignment(),
+            Some(GgufValue::Uint32(a)) if a.is_power_of_two() => *a as u64,
+            Some(other) => {
+                problems.push(Problem::file(format!(
+                    "general.alignment must be a power of two stored as a uint32, not {}",
+                    Value::from(other)
+                )));
+                1
+            }
+        };
+
+        match self.inner.metadata.get("general.architecture") {
+            Some(GgufValue::String(arch)) => {
+                let required = REQUIRED_KEYS
+                    .iter()
+                    .filter(|(arches, _)| arches.contains(&arch.as_str()))
+                    .flat_map(|(_, keys)| keys.iter());
+                for key in required {
+                    let key = format!("{arch}.{key}");
+                    if !self.inner.metadata.contains_key(&key) {
+                        problems.push(Problem::file(format!("{arch} models need {key}")));
+                    }
+                }
+            }
+            Some(_) => problems.push(Problem::file("general.architecture is not a string")),
+            None => problems.push(Problem::file("general.architecture is missing")),
+        }
+
+        let file_len = self.storage.reader()?.seek(SeekFrom::End(0))?;
+        let Some(data_len) = file_len.checked_sub(self.inner.data_start) else {
+            problems.push(Problem::file(format!(
+                "tensor data should start at byte {}, past the end of the {file_len} byte file",
+                self.inner.data_start
+            )));
+            return Ok(problems);
+        };
+        // The size is worked out from the type and shape, so it can't disagree with them
+        let regions = self
+            .inner
+            .tensors
+            .iter()
+            .map(|tensor| Region {
+                name: tensor.name.clone(),
+                bytes: tensor.offset..tensor.offset.saturating_add(tensor.nbytes as u64),
+                expected_size: None,
+            })
+            .collect();
+        problems.extend(check_regions(regions, data_len, alignment));
+        Ok(problems)
+    }
+
+    fn tensor_chunks_f32(
+        &mut self,
+        tensor: TensorInfo,
+        progress: Ref<AtomicU64>,
+        visit: &mut dyn FnMut(&[f32]) -> std::result::Result<(), Error>,
+    ) -> std::result::Result<(), Error> {
+        let offset = tensor
+            .offset
+            .checked_add(self.inner.data_start)
+            .ok_or_else(|| anyhow!("tensor runs past the end of the file"))?;
+        tensor.read_chunks_f32::<LE>(&self.storage, offset, progress, visit)
+    }
+}
+
+/// Every metadata value of `source` as the type it's declared with, for writing to a gguf
+/// file. Strings are copied as stored, so the JSON in a safetensors `__metadata__` block
+/// stays text.
+pub fn gguf_metadata(source: &mut dyn ModuleSource) -> Result<HashMap<String, GgufValue>> {
+    let types = source.metadata_types()?;
+    let mut strings = source.string_metadata()?;
+    let Value::Object(values) = source.metadata()? else {
+        bail!("metadata is not a map");
+    };
+    let mut metadata = HashMap::with_capacity(values.len());
+    for (key, value) in values {
+        let value = match types.get(&key) {
+            None | Some(MetadataType::String) => match strings.remove(&key) {
+                Some(text) => GgufValue::String(text),
+                None => GgufValue::String(value.to_string()),
+            },
+            Some(ty) => {
+                let value = match as_lazy_array(&value) {
+                    Some((_, range)) => Value::Array(source.metadata_array(&key, range)?),
+                    None => value,
+                };
+                gguf_value(&value, ty).map_err(|err| anyhow!("can't store {key}: {err}"))?
+            }
+        };
+        metadata.insert(key, value);
+    }
+    Ok(metadata)
+}
+
+fn gguf_value(value: &Value, ty: &MetadataType) -> Result<GgufValue> {
+    fn integer<T: TryFrom<i128>>(value: &Value, ty: &MetadataType) -> Result<T> {
+        let wide = value
+            .as_i64()
+            .map(i128::from)
+            .or(value.as_u64().map(i128::from));
+        wide.and_then(|x| T::try_from(x).ok())
+            .ok_or_else(|| anyhow!("{value} is not a valid {ty}"))
+    }
+    // NaN and infinity have no JSON encoding, so they come through as null
+    let float = || match value {
+        Value::Null => Ok(f64::NAN),
+        value => value
+            .as_f64()
+            .ok_or_else(|| anyhow!("{value} is not a valid {ty}")),
+    };
+    Ok(match ty {
+        MetadataType::U8 => GgufValue::Uint8(integer(value, ty)?),
+        MetadataType::I8 => GgufValue::Int8(integer(value, ty)?),
+        MetadataType::U16 => GgufValue::Uint16(integer(value, ty)?),
+        MetadataType::I16 => GgufValue::Int16(integer(value, ty)?),
+        MetadataType::U32 => GgufValue::Uint32(integer(value, ty)?),
+        MetadataType::I32 => GgufValue::Int32(integer(value, ty)?),
+        MetadataType::U64 => GgufValue::Uint64(integer(value, ty)?),
+        MetadataType::I64 => GgufValue::Int64(integer(value, ty)?),
+        MetadataType::F32 => GgufValue::Float32(float()? as f32),
+        MetadataType::F64 => GgufValue::Float64(float()?),
+        MetadataType::Bool => GgufValue::Bool(
+            value
+                .as_bool()
+                .ok_or_else(|| anyhow!("{value} is not a valid {ty}"))?,
+        ),
+        MetadataType::String => GgufValue::String(match value {
+            Value::String(text) => text.clone(),
+            value => value.to_string(),
+        }),
+        MetadataType::Array(element) => {
+            let Value::Array(items) = value else {
+                bail!("{value} is not an array");
+            };
+            let items = match element {
+                Some(element) => items
+                    .iter()
+                    .map(|item| gguf_value(item, element))
+                    .collect::<Result<_>>()?,
+                None => Vec::new(),
+            };
+            GgufValue::Array(items)
+        }
+    })
+}
+
+pub fn ggml_type(ty: &TensorTy) -> Result<GgmlTypeId> {
+    Ok(match ty {
+        TensorTy::I8 => ggml_base::I8,
+        TensorTy::I16 => ggml_base::I16,
+        TensorTy::I32 => ggml_base::I32,
+        TensorTy::I64 => ggml_base::I64,
+        TensorTy::F16 => ggml_base::F16,
+        TensorTy::BF16 => ggml_base::BF16,
+        TensorTy::F32 => ggml_base::F32,
+        TensorTy::F64 => ggml_base::F64,
+        TensorTy::Ggml(ty) => *ty,
+        other => bail!("{other} tensors cannot be stored in gguf files"),
+    })
+}
+
+impl From<&'_ GgufValue> for MetadataType {
+    fn from(value: &GgufValue) -> Self {
+        match value {
+            GgufValue::Uint8(_) => MetadataType::U8,
+            GgufValue::Int8(_) => MetadataType::I8,
+            GgufValue::Uint16(_) => MetadataType::U16,
+            GgufValue::Int16(_) => MetadataType::I16,
+            GgufValue::Uint32(_) => MetadataType::U32,
+            GgufValue::Int32(_) => MetadataType::I32,
+            GgufValue::Uint64(_) => MetadataType::U64,
+            GgufValue::Int64(_) => MetadataType::I64,
+            GgufValue::Float32(_) => MetadataType::F32,
+            GgufValue::Float64(_) => MetadataType::F64,
+            GgufValue::Bool(_) => MetadataType::Bool,
+            GgufValue::String(_) => MetadataType::String,
+            GgufValue::Array(values) => {
+                MetadataType::Array(values.first().map(|v| Box::new(MetadataType::from(v))))
+            }
+        }
+    }
+}
+
+impl From<&'_ GgmlTensorInfo> for TensorInfo {
+    fn from(value: &GgmlTensorInfo) -> Self {
+        TensorInfo {
+            ty: match value.ty {
+                ggml_base::I8 => TensorTy::I8,
+                ggml_base::I16 => TensorTy::I16,
+                ggml_base::I32 => TensorTy::I32,
+                ggml_base::I64 => TensorTy::I64,
+                ggml_base::F16 => TensorTy::F16,
+                ggml_base::BF16 => TensorTy::BF16,
+                ggml_base::F32 => TensorTy::F32,
+                ggml_base::F64 => TensorTy::F64,
+                _ => TensorTy::Ggml(value.ty),
+            },
+            shape: value.shape.clone(),
+            size: value.nbytes,
+            offset: value.offset,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FileStorage;
+    use std::fs;
+
+    /// Opens a file with no tensors whose only metadata is `general.alignment`, and checks it
+    fn check_alignment(alignment: u32) -> Vec<Problem> {
+        let key = b"general.alignment";
+        let mut bytes = b"GGUF".to_vec();
+        bytes.extend_from_slice(&3u32.to_le_bytes());
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+        bytes.extend_from_slice(&(key.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(key);
+        bytes.extend_from_slice(&4u32.to_le_bytes());
+        bytes.extend_from_slice(&alignment.to_le_bytes());
+        let name = format!("alignment{alignment}.{}.gguf", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, bytes).unwrap();
+
+        let problems = Gguf::open(FileStorage::new(path.clone())).and_then(|mut file| file.check());
+        fs::remove_file(&path).unwrap();
+        problems.unwrap()
+    }
+
+    #[test]
+    fn bad_alignment_is_a_problem() {
+        for alignment in [0, 3] {
+            let problems = check_alignment(alignment);
+            assert!(
+                problems.iter().any(|p| p
+                    .message
+                    .starts_with("general.alignment must be a power of two")),
+                "{alignment}: {problems:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,729 @@
+//! A minimal HDF5 reader for Keras `.h5` weight files. It understands the layouts h5py writes
+//! by default: version 0–3 superblocks, version 1 and 2 object headers, groups stored as
+//! symbol tables or compact links, and contiguous or compact datasets. Chunked datasets are
+//! listed but can't be read, and groups using dense (fractal heap) link storage are skipped.
+
+use crate::bundle::Bundle;
+use crate::integrity::Problem;
+use crate::model::{TensorInfo, TensorTy};
+use crate::storage::Storage;
+use anyhow::{Result, anyhow, bail};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::io::{Seek, SeekFrom};
+
+const SIGNATURE: &[u8] = b"\x89HDF\r\n\x1a\n";
+/// Addresses and lengths that are all ones mean "not allocated"
+const UNDEFINED: u64 = u64::MAX;
+/// How deep groups may nest, in case of a malformed file with a cycle of hard links
+const MAX_DEPTH: usize = 64;
+
+const MSG_DATASPACE: u16 = 0x01;
+const MSG_LINK_INFO: u16 = 0x02;
+const MSG_DATATYPE: u16 = 0x03;
+const MSG_LINK: u16 = 0x06;
+const MSG_LAYOUT: u16 = 0x08;
+const MSG_ATTRIBUTE: u16 = 0x0c;
+const MSG_CONTINUATION: u16 = 0x10;
+const MSG_SYMBOL_TABLE: u16 = 0x11;
+
+/// Opens a Keras `.h5` file, or any other HDF5 file, with one tensor per dataset named by its
+/// path through the groups, as in `model_weights/dense/dense/kernel:0`. Attributes of the root
+/// group become metadata, and those of other objects are gathered under the object's path.
+pub fn open<S: Storage>(mut storage: S) -> Result<Bundle<S>> {
+    let len = storage.reader()?.seek(SeekFrom::End(0))?;
+    let mut file = File::open(&storage, len)?;
+    let root = file.root;
+    file.walk(root, "", 0)?;
+    let File {
+        tensors,
+        metadata,
+        problems,
+        ..
+    } = file;
+    Ok(Bundle::new(storage, "hdf5", tensors, metadata, true).with_problems(problems))
+}
+
+/// Reads fields from a buffer, with offsets and lengths sized as the superblock says
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    offset_size: usize,
+    length_size: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + n)
+            .ok_or_else(|| anyhow!("HDF5 structure is truncated"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn uint(&mut self, n: usize) -> Result<u64> {
+        let mut value = [0; 8];
+        value[..n].copy_from_slice(self.take(n)?);
+        Ok(u64::from_le_bytes(value))
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(self.uint(2)? as u16)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(self.uint(4)? as u32)
+    }
+
+    fn offset(&mut self) -> Result<u64> {
+        let value = self.uint(self.offset_size)?;
+        Ok(match value == u64::MAX >> (64 - 8 * self.offset_size) {
+            true => UNDEFINED,
+            false => value,
+        })
+    }
+
+    fn length(&mut self) -> Result<u64> {
+        self.uint(self.length_size)
+    }
+
+    fn skip(&mut self, n: usize) -> Result<()> {
+        self.take(n).map(|_| ())
+    }
+
+    fn align(&mut self, to: usize) {
+        self.pos = self.pos.next_multiple_of(to);
+    }
+}
+
+#[derive(Clone)]
+struct Datatype {
+    class: u8,
+    size: usize,
+    little_endian: bool,
+    signed: bool,
+    /// For variable-length types, whether it is a string rather than a sequence
+    vlen_string: bool,
+}
+
+impl Datatype {
+    fn parse(cursor: &mut Cursor) -> Result<Self> {
+        let class = cursor.u8()? & 0x0f;
+        let bits = cursor.take(3)?;
+        let size = cursor.u32()? as usize;
+        Ok(Datatype {
+            class,
+            size,
+            little_endian: bits[0] & 1 == 0,
+            signed: bits[0] & 0x08 != 0,
+            vlen_string: bits[0] & 0x0f == 1,
+        })
+    }
+
+    fn tensor_ty(&self) -> TensorTy {
+        use TensorTy::*;
+        match (self.class, self.size, self.signed) {
+            (0, 1, false) => U8,
+            (0, 1, true) => I8,
+            (0, 2, false) => U16,
+            (0, 2, true) => I16,
+            (0, 4, false) => U32,
+            (0, 4, true) => I32,
+            (0, 8, false) => U64,
+            (0, 8, true) => I64,
+            (1, 2, _) => F16,
+            (1, 4, _) => F32,
+            (1, 8, _) => F64,
+            (3, _, _) => Unknown("string".into()),
+            (9, _, _) => Unknown("vlen".into()),
+            (class, size, _) => Unknown(format!("class {class} ({size} bytes)")),
+        }
+    }
+}
+
+enum Layout {
+    /// Stored in one piece at an absolute file offset, which compact datasets are too
+    Contiguous {
+        address: u64,
+        size: u64,
+    },
+    Chunked,
+}
+
+/// The messages of one object header that matter for reading groups and datasets
+#[derive(Default)]
+struct Object {
+    attributes: Vec<(String, Value)>,
+    shape: Option<Vec<u64>>,
+    datatype: Option<Datatype>,
+    layout: Option<Layout>,
+    /// Hard links to children, from link messages
+    links: Vec<(String, u64)>,
+    /// B-tree and local heap addresses of an old-style group
+    symbol_table: Option<(u64, u64)>,
+    dense_links: bool,
+}
+
+struct File<'s, S> {
+    storage: &'s S,
+    /// The length of the whole file, which no structure can point past
+    len: u64,
+    base: u64,
+    offset_size: usize,
+    length_size: usize,
+    root: u64,
+    visited: HashSet<u64>,
+    tensors: Vec<(String, TensorInfo)>,
+    metadata: Map<String, Value>,
+    problems: Vec<Problem>,
+}
+
+impl<'s, S: Storage> File<'s, S> {
+    fn open(storage: &'s S, len: u64) -> Result<Self> {
+        // A user block may come first, in which case the superblock is at a power of two
+        let mut start = 0;
+        let header = loop {
+            let header = storage.read_range(start, 96).or_else(|_| {
+                // Small files might not have 96 bytes past the signature
+                storage.read_range(start, 48)
+            })?;
+            if header.starts_with(SIGNATURE) {
+                break header;
+            }
+            start = if start == 0 { 512 } else { start * 2 };
+            if start > 1 << 30 {
+                bail!("{} is not an HDF5 file", storage.display());
+            }
+        };
+        let version = header[8];
+        let (offset_size, length_size) = match version {
+            0 | 1 => (header[13] as usize, header[14] as usize),
+            2 | 3 => (header[9] as usize, header[10] as usize),
+            version => bail!("unsupported HDF5 superblock version {version}"),
+        };
+        if ![2, 4, 8].contains(&offset_size) || ![2, 4, 8].contains(&length_size) {
+            bail!("{} has unsupported HDF5 field sizes", storage.display());
+        }
+        let mut file = File {
+            storage,
+            len,
+            base: start,
+            offset_size,
+            length_size,
+            root: 0,
+            visited: HashSet::new(),
+            tensors: Vec::new(),
+            metadata: Map::new(),
+            problems: Vec::new(),
+        };
+        match version {
+            0 | 1 => {
+                let mut cursor = file.cursor(&header);
+                cursor.skip(if version == 0 { 24 } else { 28 })?;
+                let base = cursor.offset()?;
+                // Free space, end of file, and driver info addresses
+                cursor.skip(3 * file.offset_size)?;
+                // The root group's symbol table entry starts with its link name offset
+                cursor.skip(file.offset_size)?;
+                file.base = base;
+                file.root = cursor.offset()?;
+            }
+            _ => {
+                let mut cursor = file.cursor(&header);
+                cursor.skip(12)?;
+                let base = cursor.offset()?;
+                // Superblock extension and end of file addresses
+                cursor.skip(2 * file.offset_size)?;
+                file.base = base;
+                file.root = cursor.offset()?;
+            }
+        }
+        Ok(file)
+    }
+
+    fn cursor<'a>(&self, bytes: &'a [u8]) -> Cursor<'a> {
+        Cursor {
+            bytes,
+            pos: 0,
+            offset_size: self.offset_size,
+            length_size: self.length_size,
+        }
+    }
+
+    fn read(&self, address: u64, len: usize) -> Result<Vec<u8>> {
+        if address == UNDEFINED {
+            bail!("HDF5 structure points to an unallocated address");
+        }
+        let start = self.base.checked_add(address);
+        match start {
+            Some(start)
+                if start
+                    .checked_add(len as u64)
+                    .is_some_and(|end| end <= self.len) =>
+            {
+                self.storage.read_range(start, len)
+            }
+            _ => bail!("HDF5 structure points past the end of the file"),
+        }
+    }
+
+    /// Reads the object header at `address`, following continuation blocks
+    fn object(&self, address: u64) -> Result<Object> {
+        let mut object = Object::default();
+        let prefix = self.read(address, 16)?;
+        if prefix.starts_with(b"OHDR") {
+            let flags = prefix[5];
+            let mut skip = 6;
+            if flags & 0x20 != 0 {
+                skip += 16;
+            }
+            if flags & 0x10 != 0 {
+                skip += 4;
+            }
+            let size_len = 1 << (flags & 3);
+            let head = self.read(address, skip + size_len)?;
+            let mut cursor = self.cursor(&head);
+            cursor.skip(skip)?;
+            let chunk_len = cursor.uint(size_len)? as usize;
+            let mut blocks = vec![(address + (skip + size_len) as u64, chunk_len)];
+            let mut seen = HashSet::new();
+            while let Some((start, len)) = blocks.pop() {
+                if !seen.insert(start) {
+                    bail!("HDF5 object header at {address} continues in a loop");
+                }
+                let block = self.read(start, len)?;
+                self.messages_v2(&block, start, flags, &mut object, &mut blocks)?;
+            }
+        } else if prefix[0] == 1 {
+            let len = u32::from_le_bytes(prefix[8..12].try_into()?) as usize;
+            let mut blocks = vec![(address + 16, len)];
+            let mut seen = HashSet::new();
+            while let Some((start, len)) = blocks.pop() {
+                if !seen.insert(start) {
+                    bail!("HDF5 object header at {address} continues in a loop");
+                }
+                let block = self.read(start, len)?;
+                self.messages_v1(&block, start, &mut object, &mut blocks)?;
+            }
+        } else {
+            bail!("unsupported HDF5 object header at {address}");
+        }
+        Ok(object)
+    }
+
+    fn messages_v1(
+        &self,
+        block: &[u8],
+        start: u64,
+        object: &mut Object,
+        blocks: &mut Vec<(u64, usize)>,
+    ) -> Result<()> {
+        let mut cursor = self.cursor(block);
+        while cursor.pos + 8 <= block.len() {
+            let ty = cursor.u16()?;
+            let size = cursor.u16()? as usize;
+            let flags = cursor.u8()?;
+            cursor.skip(3)?;
+            let at = start + cursor.pos as u64;
+            let data = cursor.take(size)?;
+            cursor.align(8);
+            self.message(ty, flags, data, at, object, blocks)?;
+        }
+        Ok(())
+    }
+
+    fn messages_v2(
+        &self,
+        block: &[u8],
+        start: u64,
+        header_flags: u8,
+        object: &mut Object,
+        blocks: &mut Vec<(u64, usize)>,
+    ) -> Result<()> {
+        // Continuation blocks are framed by a signature and a checksum, and any block may end
+        // with a gap too small to hold a message
+        let (block, start) = match block.strip_prefix(b"OCHK") {
+            Some(inner) => (&inner[..inner.len().saturating_sub(4)], start + 4),
+            None => (block, start),
+        };
+        let mut cursor = self.cursor(block);
+        let header_len = if header_flags & 0x04 != 0 { 6 } else { 4 };
+        while cursor.pos + header_len <= block.len() {
+            let ty = cursor.u8()? as u16;
+            let size = cursor.u16()? as usize;
+            let flags = cursor.u8()?;
+            if header_flags & 0x04 != 0 {
+                cursor.skip(2)?;
+            }
+            let at = start + cursor.pos as u64;
+            let Ok(data) = cursor.take(size) else {
+                break;
+            };
+            self.message(ty, flags, data, at, object, blocks)?;
+        }
+        Ok(())
+    }
+
+    /// Handles one header message whose data starts at file offset `at`
+    fn message(
+        &self,
+        ty: u16,
+        flags: u8,
+        data: &[u8],
+        at: u64,
+        object: &mut Object,
+        blocks: &mut Vec<(u64, usize)>,
+    ) -> Result<()> {
+        // Shared messages point elsewhere, which Keras files don't need
+        if flags & 0x02 != 0 {
+            return Ok(());
+        }
+        let mut cursor = self.cursor(data);
+        match ty {
+            MSG_DATASPACE => object.shape = Some(self.dataspace(&mut cursor)?),
+            MSG_DATATYPE => object.datatype = Some(Datatype::parse(&mut cursor)?),
+            MSG_LAYOUT => object.layout = Some(self.layout(&mut cursor, at)?),
+            MSG_ATTRIBUTE => object.attributes.push(self.attribute(&mut cursor)?),
+            MSG_LINK => {
+                if let Some(link) = self.link(&mut cursor)? {
+                    object.links.push(link);
+                }
+            }
+            MSG_LINK_INFO => {
+                cursor.skip(1)?;
+                let link_flags = cursor.u8()?;
+                if link_flags & 1 != 0 {
+                    cursor.skip(8)?;
+                }
+                object.dense_links = cursor.offset()? != UNDEFINED;
+            }
+            MSG_SYMBOL_TABLE => {
+                let btree = cursor.offset()?;
+                let heap = cursor.offset()?;
+                object.symbol_table = Some((btree, heap));
+            }
+            MSG_CONTINUATION => {
+                let address = cursor.offset()?;
+                let len = cursor.length()?;
+                if len > self.len {
+                    bail!("HDF5 continuation block is longer than the file");
+                }
+                blocks.push((address, len as usize));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn dataspace(&self, cursor: &mut Cursor) -> Result<Vec<u64>> {
+        let version = cursor.u8()?;
+        let rank = cursor.u8()? as usize;
+        cursor.skip(if version == 1 { 6 } else { 2 })?;
+        (0..rank).map(|_| cursor.length()).collect()
+    }
+
+    fn layout(&self, cursor: &mut Cursor, at: u64) -> Result<Layout> {
+        match cursor.u8()? {
+            3 | 4 => match cursor.u8()? {
+                0 => {
+                    let size = cursor.u16()? as u64;
+                    let address = at + cursor.pos as u64;
+                    Ok(Layout::Contiguous { address, size })
+                }
+                1 => {
+                    let address = cursor.offset()?;
+                    let size = cursor.length()?;
+                    Ok(Layout::Contiguous { address, size })
+                }
+                _ => Ok(Layout::Chunked),
+            },
+            1 | 2 => {
+                let rank = cursor.u8()? as usize;
+                let class = cursor.u8()?;
+                cursor.skip(5)?;
+                match class {
+                    0 => {
+                        cursor.skip(4 * rank)?;
+                        let size = cursor.u32()? as u64;
+                        let address = at + cursor.pos as u64;
+                        Ok(Layout::Contiguous { address, size })
+                    }
+                    1 => {
+                        let address = cursor.offset()?;
+                        // The last dimension is the element size
+                        let dims = (0..rank)
+                            .map(|_| cursor.u32().map(|dim| dim as u64))
+                            .collect::<Result<Vec<_>>>()?;
+                        let size = dims.iter().product();
+                        Ok(Layout::Contiguous { address, size })
+                    }
+                    _ => Ok(Layout::Chunked),
+                }
+            }
+            version => bail!("unsupported HDF5 layout version {version}"),
+        }
+    }
+
+    fn attribute(&self, cursor: &mut Cursor) -> Result<(String, Value)> {
+        let version = cursor.u8()?;
+        cursor.skip(1)?;
+        let name_len = cursor.u16()? as usize;
+        let datatype_len = cursor.u16()? as usize;
+        let dataspace_len = cursor.u16()? as usize;
+        if version >= 3 {
+            cursor.skip(1)?;
+        }
+        let pad = |len: usize| {
+            if version == 1 {
+                len.next_multiple_of(8)
+            } else {
+                len
+            }
+        };
+        let name = cursor.take(pad(name_len))?;
+        let name = String::from_utf8_lossy(&name[..name_len.saturating_sub(1)]).into_owned();
+        let datatype = Datatype::parse(&mut self.cursor(cursor.take(pad(datatype_len))?))?;
+        let shape = self.dataspace(&mut self.cursor(cursor.take(pad(dataspace_len))?))?;
+        let data = &cursor.bytes[cursor.pos..];
+        // Every element takes at least a byte, so a count the data can't hold is corrupt
+        let count = shape
+            .iter()
+            .try_fold(1u64, |acc, &dim| acc.checked_mul(dim))
+            .and_then(|count| usize::try_from(count).ok())
+            .filter(|&count| {
+                count
+                    .checked_mul(datatype.size.max(1))
+                    .is_some_and(|size| size <= data.len())
+            })
+            .ok_or_else(|| anyhow!("attribute {name} is truncated"))?;
+        let mut values = Vec::with_capacity(count);
+        for i in 0..count {
+            let element = data
+                .get(i * datatype.size..(i + 1) * datatype.size)
+                .ok_or_else(|| anyhow!("attribute {name} is truncated"))?;
+            values.push(self.value(&datatype, element)?);
+        }
+        let value = match (shape.len(), values.len()) {
+            (0, 1) => values.pop().unwrap(),
+            _ => values.into(),
+        };
+        Ok((name, value))
+    }
+
+    /// Decodes one element of an attribute
+    fn value(&self, datatype: &Datatype, bytes: &[u8]) -> Result<Value> {
+        let int = |bytes: &[u8]| {
+            let mut value = [0; 8];
+            if datatype.little_endian {
+                value[..bytes.len()].copy_from_slice(bytes);
+                u64::from_le_bytes(value)
+            } else {
+                value[8 - bytes.len()..].copy_from_slice(bytes);
+                u64::from_be_bytes(value)
+            }
+        };
+        Ok(match (datatype.class, datatype.size) {
+            (0, size) if datatype.signed => {
+                let shift = 64 - 8 * size as u32;
+                (((int(bytes) << shift) as i64) >> shift).into()
+            }
+            (0, _) => int(bytes).into(),
+            (1, 4) => (f32::from_bits(int(bytes) as u32) as f64).into(),
+            (1, 8) => f64::from_bits(int(bytes)).into(),
+            (3, _) => {
+                let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+                String::from_utf8_lossy(&bytes[..end]).trim_end().into()
+            }
+            (9, _) if datatype.vlen_string => {
+                let mut cursor = self.cursor(bytes);
+                let len = cursor.u32()? as usize;
+                let collection = cursor.offset()?;
+                let index = cursor.u32()?;
+                let text = self.global_heap_object(collection, index)?;
+                String::from_utf8_lossy(&text[..len.min(text.len())]).into()
+            }
+            _ => datatype.tensor_ty().to_string().into(),
+        })
+    }
+
+    /// Finds object `index` of the global heap collection at `address`, where h5py keeps
+    /// variable-length strings
+    fn global_heap_object(&self, address: u64, index: u32) -> Result<Vec<u8>> {
+        let head = self.read(address, 8 + self.length_size)?;
+        if !head.starts_with(b"GCOL") {
+            bail!("missing HDF5 global heap at {address}");
+        }
+        let mut cursor = self.cursor(&head);
+        cursor.skip(8)?;
+        let size = cursor.length()? as usize;
+        let collection = self.read(address, size)?;
+        let mut cursor = self.cursor(&collection);
+        cursor.skip(8 + self.length_size)?;
+        while cursor.pos + 8 + self.length_size <= collection.len() {
+            let id = cursor.u16()?;
+            cursor.skip(6)?;
+            let len = cursor.length()? as usize;
+            if id == 0 {
+                break;
+            }
+            let data = cursor.take(len)?;
+            if id as u32 == index {
+                return Ok(data.to_vec());
+            }
+            cursor.align(8);
+        }
+        bail!("HDF5 global heap object {index} is missing")
+    }
+
+    /// Reads a link message, returning the name and address of a hard link
+    fn link(&self, cursor: &mut Cursor) -> Result<Option<(String, u64)>> {
+        cursor.skip(1)?;
+        let flags = cursor.u8()?;
+        let link_type = match flags & 0x08 {
+            0 => 0,
+            _ => cursor.u8()?,
+        };
+        if flags & 0x04 != 0 {
+            cursor.skip(8)?;
+        }
+        if flags & 0x10 != 0 {
+            cursor.skip(1)?;
+        }
+        let name_len = cursor.uint(1 << (flags & 3))? as usize;
+        let name = String::from_utf8_lossy(cursor.take(name_len)?).into_owned();
+        if link_type != 0 {
+            return Ok(None);
+        }
+        Ok(Some((name, cursor.offset()?)))
+    }
+
+    /// Lists the children of an old-style group from its B-tree and local heap
+    fn symbol_table(&self, btree: u64, heap: u64) -> Result<Vec<(String, u64)>> {
+        let head = self.read(heap, 8 + 2 * self.length_size + self.offset_size)?;
+        if !head.starts_with(b"HEAP") {
+            bail!("missing HDF5 local heap at {heap}");
+        }
+        let mut cursor = self.cursor(&head);
+        cursor.skip(8)?;
+        let heap_len = cursor.length()? as usize;
+        cursor.length()?;
+        let heap = self.read(cursor.offset()?, heap_len)?;
+        let name = |offset: u64| {
+            let bytes = heap.get(offset as usize..).unwrap_or_default();
+            let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            String::from_utf8_lossy(&bytes[..end]).into_owned()
+        };
+
+        let mut children = Vec::new();
+        let mut nodes = vec![btree];
+        while let Some(node) = nodes.pop() {
+            let head = self.read(node, 8)?;
+            if !head.starts_with(b"TREE") {
+                bail!("missing HDF5 B-tree node at {node}");
+            }
+            let level = head[5];
+            let entries = u16::from_le_bytes([head[6], head[7]]) as usize;
+            let len = 8 + 2 * self.offset_size + entries * (self.length_size + self.offset_size);
+            let bytes = self.read(node, len + self.length_size)?;
+            let mut cursor = self.cursor(&bytes);
+            cursor.skip(8 + 2 * self.offset_size)?;
+            for _ in 0..entries {
+                cursor.length()?;
+                let child = cursor.offset()?;
+                if level > 0 {
+                    nodes.push(child);
+                    continue;
+                }
+                let snod = self.read(child, 8)?;
+                if !snod.starts_with(b"SNOD") {
+                    bail!("missing HDF5 symbol table node at {child}");
+                }
+                let symbols = u16::from_le_bytes([snod[6], snod[7]]) as usize;
+                let entry_len = 2 * self.offset_size + 24;
+                let bytes = self.read(child, 8 + symbols * entry_len)?;
+                let mut cursor = self.cursor(&bytes);
+                cursor.skip(8)?;
+                for _ in 0..symbols {
+                    let name_offset = cursor.offset()?;
+                    let address = cursor.offset()?;
+                    cursor.skip(24)?;
+                    children.push((name(name_offset), address));
+                }
+            }
+        }
+        Ok(children)
+    }
+
+    fn walk(&mut self, address: u64, path: &str, depth: usize) -> Result<()> {
+        if depth > MAX_DEPTH || !self.visited.insert(address) {
+            return Ok(());
+        }
+        let object = self.object(address)?;
+        let attributes: Map<String, Value> = object.attributes.into_iter().collect();
+        if path.is_empty() {
+            self.metadata.extend(attributes);
+        } else if !attributes.is_empty() {
+            self.metadata.insert(path.to_string(), attributes.into());
+        }
+
+        if let Some(layout) = object.layout {
+            let shape = object.shape.unwrap_or_default();
+            let datatype = object
+                .datatype
+                .ok_or_else(|| anyhow!("dataset {path} has no datatype"))?;
+            let mut ty = datatype.tensor_ty();
+            if !datatype.little_endian && datatype.size > 1 {
+                self.problems
+                    .push(Problem::tensor(path, "is big-endian, which can't be read"));
+                ty = TensorTy::Unknown(format!("big-endian {ty}"));
+            }
+            let (offset, size) = match layout {
+                Layout::Contiguous { address, size } if address != UNDEFINED => {
+                    (self.base + address, size)
+                }
+                Layout::Contiguous { .. } => (0, 0),
+                Layout::Chunked => {
+                    self.problems.push(Problem::tensor(
+                        path,
+                        "is stored in chunks, which can't be read yet",
+                    ));
+                    ty = TensorTy::Unknown(format!("chunked {ty}"));
+                    (0, 0)
+                }
+            };
+            self.tensors.push((
+                path.to_string(),
+                TensorInfo {
+                    ty,
+                    shape,
+                    size: size as usize,
+                    offset,
+                },
+            ));
+            return Ok(());
+        }
+
+        if object.dense_links {
+            self.problems.push(Problem::file(format!(
+                "group {path:?} stores its links in a fractal heap, so its children are skipped"
+            )));
+        }
+        let mut children = object.links;
+        if let Some((btree, heap)) = object.symbol_table {
+            children.extend(self.symbol_table(btree, heap)?);
+        }
+        for (name, child) in children {
+            let child_path = match path {
+                "" => name,
+                _ => format!("{path}/{name}"),
+            };
+            self.walk(child, &child_path, depth + 1)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+use anyhow::{Error, anyhow, bail};
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed};
+use std::sync::{Arc, Mutex, OnceLock};
+use weakref::Ref;
+
+use crate::analysis::{SpectralSummary, singular_values};
+use crate::model::{ModuleSource, TensorInfo};
+use crate::notify;
+
+/// Name fragments of projections holding Q, K, and V stacked one after another on one axis
+const FUSED_NAMES: [&str; 5] = ["qkv", "c_attn", "query_key_value", "Wqkv", "in_proj"];
+/// Name fragments of the attention output projection, whose heads are its input features
+const OUTPUT_NAMES: [&str; 6] = [
+    "o_proj",
+    "out_proj",
+    "attn_output",
+    "attn.c_proj",
+    ".wo.",
+    "attention.dense",
+];
+/// Name fragments of key and value projections, which have fewer heads with grouped-query
+/// attention
+const KV_NAMES: [&str; 8] = [
+    "k_proj", "v_proj", "attn_k", "attn_v", ".wk.", ".wv.", "key", "value",
+];
+
+/// Heads with less than this fraction of the median norm are likely dead
+const DEAD_FRACTION: f64 = 0.2;
+/// Heads with more than this multiple of the median norm dominate the layer
+const DOMINANT_MULTIPLE: f64 = 3.0;
+
+/// The query and key/value head counts from GGUF hyperparameters
+pub fn attention_heads(metadata: &Value) -> Option<(usize, usize)> {
+    let arch = metadata.get("general.architecture")?.as_str()?;
+    let number = |key: &str| {
+        let value = metadata.get(format!("{arch}.attention.{key}"))?.as_u64()?;
+        Some(value as usize)
+    };
+    let heads = number("head_count")?;
+    Some((heads, number("head_count_kv").unwrap_or(heads)))
+}
+
+/// How a projection weight divides into heads
+#[derive(Debug, Clone)]
+pub struct HeadLayout {
+    /// The axis split between heads
+    pub axis: usize,
+    pub head_dim: usize,
+    /// Names of the heads in the order they're stored, like `3` or, for fused projections, `k3`
+    pub labels: Vec<String>,
+}
+
+impl HeadLayout {
+    /// Guesses from the tensor's name which axis holds the heads and how many there are,
+    /// falling back to the other axis if the usual one doesn't divide evenly
+    pub fn new(name: &str, shape: &[u64], heads: usize, kv_heads: usize) -> Result<Self, Error> {
+        let &[rows, cols] = shape else {
+            bail!("only 2-D projection weights split into heads");
+        };
+        if heads == 0 || kv_heads == 0 {
+            bail!("head counts must be positive");
+        }
+        let (groups, preferred) = if FUSED_NAMES.iter().any(|part| name.contains(part)) {
+            (vec![("q", heads), ("k", kv_heads), ("v", kv_heads)], 0)
+        } else if OUTPUT_NAMES.iter().any(|part| name.contains(part)) {
+            (vec![("", heads)], 1)
+        } else if KV_NAMES.iter().any(|part| name.contains(part)) {
+            (vec![("", kv_heads)], 0)
+        } else {
+            (vec![("", heads)], 0)
+        };
+        let total: usize = groups.iter().map(|(_, count)| count).sum();
+        let lengths = [rows as usize, cols as usize];
+        let Some(axis) = [preferred, 1 - preferred]
+            .into_iter()
+            .find(|&axis| lengths[axis].is_multiple_of(total))
+        else {
+            bail!("neither axis of {rows}×{cols} splits into {total} heads");
+        };
+        let labels = groups
+            .iter()
+            .flat_map(|&(prefix, count)| (0..count).map(move |i| format!("{prefix}{i}")))
+            .collect();
+        Ok(HeadLayout {
+            axis,
+            head_dim: lengths[axis] / total,
+            labels,
+        })
+    }
+}
+
+/// Whether a head stands out from the rest of its projection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeadFlag {
+    Dead,
+    Dominant,
+}
+
+/// The norm and singular values of one head's slice of a projection
+#[derive(Debug, Clone)]
+pub struct HeadStats {
+    pub norm: f64,
+    /// In descending order
+    pub singular_values: Vec<f32>,
+    pub spectrum: SpectralSummary,
+}
+
+/// Every head of one projection weight, decomposed in the background
+pub struct HeadAnalysis {
+    pub name: String,
+    pub tensor: TensorInfo,
+    pub layout: HeadLayout,
+    /// Percent read of the tensor
+    pub progress: AtomicU64,
+    /// Heads decomposed so far, once the tensor is read
+    pub done: AtomicUsize,
+    pub heads: OnceLock<Vec<HeadStats>>,
+    pub error: OnceLock<Error>,
+}
+
+impl HeadAnalysis {
+    pub fn new(name: String, tensor: TensorInfo, layout: HeadLayout) -> Self {
+        HeadAnalysis {
+            name,
+            tensor,
+            layout,
+            progress: AtomicU64::new(0),
+            done: AtomicUsize::new(0),
+            heads: OnceLock::new(),
+            error: OnceLock::new(),
+        }
+    }
+
+    pub fn median_norm(&self) -> f64 {
+        let Some(heads) = self.heads.get() else {
+            return 0.0;
+        };
+        let mut norms: Vec<f64> = heads.iter().map(|head| head.norm).collect();
+        norms.sort_by(f64::total_cmp);
+        norms.get(norms.len() / 2).copied().unwrap_or(0.0)
+    }
+
+    /// Heads far from the median norm, which are dead or dominating
+    pub fn flag(&self, index: usize) -> Option<HeadFlag> {
+        let norm = self.heads.get()?.get(index)?.norm;
+        let median = self.median_norm();
+        if norm < median * DEAD_FRACTION {
+            Some(HeadFlag::Dead)
+        } else if norm > median * DOMINANT_MULTIPLE {
+            Some(HeadFlag::Dominant)
+        } else {
+            None
+        }
+    }
+}
+
+fn do_heads(
+    source: &Mutex<dyn ModuleSource + Send>,
+    request: Ref<HeadAnalysis>,
+) -> Result<(), Error> {
+    let (tensor, layout) = request
+        .inspect(|req| (req.tensor.clone(), req.layout.clone()))
+        .ok_or(anyhow!("cancelled"))?;
+    let progress = request.map(|req| &req.progress);
+    let data = source
+        .lock()
+        .unwrap()
+        .tensor_f32(tensor.clone(), progress)?;
+    let cols = tensor.shape[1] as usize;
+    let rows = data.len() / cols.max(1);
+    let dim = layout.head_dim;
+
+    // Once the tensor is read, `progress` tracks each head's decomposition in turn
+    let mut heads = Vec::with_capacity(layout.labels.len());
+    for index in 0..layout.labels.len() {
+        let (slice, h, w) = if layout.axis == 0 {
+            (data[index * dim * cols..][..dim * cols].to_vec(), dim, cols)
+        } else {
+            let slice = data
+                .chunks(cols)
+                .flat_map(|row| &row[index * dim..][..dim])
+                .copied()
+                .collect();
+            (slice, rows, dim)
+        };
+        let norm = slice
+            .iter()
+            .map(|&x| x as f64 * x as f64)
+            .sum::<f64>()
+            .sqrt();
+        let values = singular_values(&slice, h, w, progress)?;
+        heads.push(HeadStats {
+            norm,
+            spectrum: SpectralSummary::new(&values),
+            singular_values: values,
+        });
+        request
+            .inspect(|req| req.done.fetch_add(1, Relaxed))
+            .ok_or(anyhow!("cancelled"))?;
+    }
+    request
+        .inspect(|req| {
+            let _ = req.heads.set(heads);
+        })
+        .ok_or(anyhow!("cancelled"))
+}
+
+pub fn start_heads(source: Arc<Mutex<dyn ModuleSource + Send>>, analysis: Ref<HeadAnalysis>) {
+    notify::spawn(move || {
+        if let Err(err) = do_heads(&*source, analysis) {
+            analysis.inspect(|a| {
+                let _ = a.error.set(err);
+            });
+        }
+    });
+}
@@ -0,0 +1,119 @@
+use serde::Serialize;
+use std::fmt;
+use std::ops::Range;
+
+/// Something wrong with how a checkpoint is laid out, which other readers may reject or
+/// silently misread
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Problem {
+    /// The tensor at fault, or `None` for the file as a whole
+    pub tensor: Option<String>,
+    pub message: String,
+}
+
+impl Problem {
+    pub fn file(message: impl Into<String>) -> Self {
+        Problem {
+            tensor: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn tensor(name: &str, message: impl Into<String>) -> Self {
+        Problem {
+            tensor: Some(name.to_string()),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.tensor {
+            Some(tensor) => write!(f, "{tensor}: {}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Where one tensor's bytes are stored, relative to the start of the data section
+pub struct Region {
+    pub name: String,
+    pub bytes: Range<u64>,
+    /// What the dtype and shape say the size should be, if that can be worked out
+    pub expected_size: Option<u64>,
+}
+
+/// Checks that every region is in bounds, sized to match its dtype and shape, and packed
+/// against the one before it without overlapping.
+///
+/// Each region may be padded out to `alignment` bytes, which is 1 for formats that allow no
+/// gaps at all.
+pub fn check_regions(mut regions: Vec<Region>, data_len: u64, alignment: u64) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    regions.sort_by_key(|region| (region.bytes.start, region.bytes.end));
+
+    let mut previous: Option<&Region> = None;
+    let mut end = 0;
+    for region in &regions {
+        let Range { start, end: stop } = region.bytes.clone();
+        if stop < start {
+            problems.push(Problem::tensor(
+                &region.name,
+                format!("ends at byte {stop}, before it starts at {start}"),
+            ));
+            continue;
+        }
+        match region.expected_size {
+            Some(expected) if expected != stop - start => problems.push(Problem::tensor(
+                &region.name,
+                format!(
+                    "takes {} bytes, but its dtype and shape need {expected}",
+                    stop - start
+                ),
+            )),
+            _ => {}
+        }
+        if stop > data_len {
+            problems.push(Problem::tensor(
+                &region.name,
+                format!("ends at byte {stop}, past the {data_len} bytes of data (truncated?)"),
+            ));
+        }
+        if start % alignment != 0 {
+            problems.push(Problem::tensor(
+                &region.name,
+                format!("starts at byte {start}, which is not a multiple of {alignment}"),
+            ));
+        }
+        match previous {
+            Some(previous) if start < end => problems.push(Problem::tensor(
+                &region.name,
+                format!("overlaps {} by {} bytes", previous.name, end - start),
+            )),
+            // A corrupt region can end so near `u64::MAX` that padding it would overflow
+            _ if start > end.checked_next_multiple_of(alignment).unwrap_or(u64::MAX) => {
+                let after = match previous {
+                    Some(previous) => format!("after {}", previous.name),
+                    None => "at the start of the data".to_string(),
+                };
+                problems.push(Problem::tensor(
+                    &region.name,
+                    format!("follows a gap of {} unused bytes {after}", start - end),
+                ));
+            }
+            _ => {}
+        }
+        if stop >= end {
+            end = stop;
+            previous = Some(region);
+        }
+    }
+    if data_len > end.checked_next_multiple_of(alignment).unwrap_or(u64::MAX) {
+        problems.push(Problem::file(format!(
+            "{} unused bytes after the last tensor",
+            data_len - end
+        )));
+    }
+    problems
+}
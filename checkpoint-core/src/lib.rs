@@ -0,0 +1,63 @@
+//! Reading, editing, and analyzing model checkpoints
+//!
+//! Every supported format implements [`model::ModuleSource`], which exposes the checkpoint as a
+//! tree of [`model::ModuleInfo`] with a [`model::TensorInfo`] at each leaf, plus methods to read
+//! tensor data as `f32`. Open a file with [`safetensors::Safetensors::open`] or
+//! [`gguf::Gguf::open`] over any [`storage::Storage`], then hand the source to the functions in
+//! [`analysis`] to compute statistics, histograms, and spectra in the background.
+//!
+//! [`registry::SourceRegistry`] picks the right format for a path, and accepts new formats
+//! through [`registry::SourceFormat`]. [`lora`] pairs up the halves of LoRA adapters, and
+//! [`optim`] folds optimizer state under the parameters it belongs to, and [`similarity`]
+//! compares the weights of matching layers, while [`progression`] charts their norms by depth
+//! and [`heads`] splits attention projections into per-head norms and spectra. [`duplicates`]
+//! finds tensors stored twice,
+//! [`manifest`] hashes every tensor so checkpoints can be compared by content, and
+//! [`integrity`] checks that every tensor's bytes are where the header says they are.
+//! [`arch`] summarizes the model's hyperparameters, [`tokenizer`] reads the vocabulary embedded in GGUF files,
+//! and [`embedding`] finds untrained rows and nearest neighbors in the token embeddings that go with it.
+//!
+//! Besides safetensors and GGUF, [`npz`] reads the `.npz` bundles saved by numpy and MLX, and
+//! [`flax`] reads Flax msgpack checkpoints, both as a read-only [`bundle::Bundle`]. Keras `.h5`
+//! files are read the same way with the `hdf5` feature. [`pytorch`] reads `torch.save` and
+//! Lightning checkpoints without running any of their pickled code, and [`deepspeed`] stitches
+//! ZeRO shards back into whole fp32 parameters. [`onnx`] lists the initializers and signature of
+//! ONNX models, reading external data files in place, and [`tensorrt`] recognizes engines.
+//! [`coreml`] reads `.mlpackage` directories and their `weight.bin` blobs.
+//!
+//! Checkpoints can also be read from inside zip and tar archives, and with the `object-store`
+//! feature, straight from `s3://` and `gs://` URLs.
+
+pub mod analysis;
+pub mod arch;
+pub mod archive;
+pub mod bundle;
+pub mod coreml;
+pub mod deepspeed;
+pub mod duplicates;
+pub mod embedding;
+pub mod flax;
+pub mod gguf;
+#[cfg(feature = "hdf5")]
+pub mod hdf5;
+pub mod heads;
+pub mod integrity;
+pub mod lora;
+pub mod manifest;
+pub mod model;
+pub mod notify;
+pub mod npz;
+#[cfg(feature = "object-store")]
+pub mod object_storage;
+pub mod onnx;
+pub mod optim;
+pub mod progression;
+mod protobuf;
+pub mod pytorch;
+pub mod registry;
+pub mod safetensors;
+pub mod similarity;
+pub mod slice;
+pub mod storage;
+pub mod tensorrt;
+pub mod tokenizer;
@@ -0,0 +1,181 @@
+use anyhow::{Error, anyhow, bail};
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex, OnceLock};
+use weakref::{Ref, pin};
+
+use crate::analysis::{Histogram, SpectralSummary, Spectrum};
+use crate::model::{ModuleSource, TensorInfo};
+use crate::notify;
+
+/// Names of the down (A) and up (B) projections, as written by PEFT and kohya-ss respectively
+const LORA_NAMES: [(&str, &str); 2] = [("lora_A", "lora_B"), ("lora_down", "lora_up")];
+
+/// The two halves of one low-rank update, ΔW = scale · B·A
+#[derive(Debug, Clone)]
+pub struct LoraPair {
+    /// The name shared by both tensors, up to the LoRA part
+    pub base: String,
+    pub down: (String, TensorInfo),
+    pub up: (String, TensorInfo),
+    /// A scalar `alpha` tensor stored next to the pair, if there is one
+    pub alpha_tensor: Option<(String, TensorInfo)>,
+    pub alpha: Option<f32>,
+}
+
+impl LoraPair {
+    pub fn rank(&self) -> u64 {
+        self.down.1.shape.first().copied().unwrap_or(0)
+    }
+
+    /// Shape of ΔW, with any convolution kernel dimensions folded into the columns
+    pub fn delta_shape(&self) -> [u64; 2] {
+        let rows = self.up.1.shape.first().copied().unwrap_or(0);
+        let cols = self.down.1.shape.iter().skip(1).product();
+        [rows, cols]
+    }
+
+    /// `alpha / rank`, or 1 when no alpha was saved
+    pub fn scale(&self) -> f32 {
+        match self.alpha {
+            Some(alpha) if self.rank() > 0 => alpha / self.rank() as f32,
+            _ => 1.0,
+        }
+    }
+}
+
+/// Pairs up every LoRA down projection with its up projection
+pub fn find_lora_pairs(tensors: &[(String, TensorInfo)]) -> Vec<LoraPair> {
+    let by_name: HashMap<&str, &TensorInfo> = tensors
+        .iter()
+        .map(|(name, tensor)| (name.as_str(), tensor))
+        .collect();
+    let mut pairs = Vec::new();
+    for (name, tensor) in tensors {
+        for (down, up) in LORA_NAMES {
+            let Some(at) = name.rfind(down) else {
+                continue;
+            };
+            let up_name = format!("{}{up}{}", &name[..at], &name[at + down.len()..]);
+            let Some(&up_tensor) = by_name.get(up_name.as_str()) else {
+                continue;
+            };
+            let base = name[..at].trim_end_matches(['.', '_']).to_string();
+            let alpha_name = format!("{base}.alpha");
+            let alpha_tensor = by_name
+                .get(alpha_name.as_str())
+                .map(|&alpha| (alpha_name, alpha.clone()));
+            pairs.push(LoraPair {
+                base,
+                down: (name.clone(), tensor.clone()),
+                up: (up_name, up_tensor.clone()),
+                alpha_tensor,
+                alpha: None,
+            });
+        }
+    }
+    pairs.sort_by(|a, b| a.base.cmp(&b.base));
+    pairs
+}
+
+/// Reads the scalar alpha tensors, which are tiny enough to load up front
+pub fn load_alphas(
+    source: &mut dyn ModuleSource,
+    pairs: &mut [LoraPair],
+    progress: Ref<AtomicU64>,
+) -> Result<(), Error> {
+    for pair in pairs {
+        if let Some((_, tensor)) = &pair.alpha_tensor {
+            pair.alpha = source
+                .tensor_f32(tensor.clone(), progress)?
+                .first()
+                .copied();
+        }
+    }
+    Ok(())
+}
+
+/// The spectrum of one merged update, computed in the background
+pub struct LoraAnalysis {
+    pub pair: LoraPair,
+    pub max_bin_count: usize,
+    pub progress: AtomicU64,
+    pub spectrum: OnceLock<Spectrum>,
+    pub error: OnceLock<Error>,
+}
+
+impl LoraAnalysis {
+    pub fn new(pair: LoraPair, max_bin_count: usize) -> Self {
+        LoraAnalysis {
+            pair,
+            max_bin_count,
+            progress: AtomicU64::new(0),
+            spectrum: OnceLock::new(),
+            error: OnceLock::new(),
+        }
+    }
+}
+
+/// Singular values of scale · B·A without forming the full matrix: with B = Q₁R₁ and
+/// Aᵀ = Q₂R₂, B·A = Q₁(R₁R₂ᵀ)Q₂ᵀ, so only the rank × rank core needs an SVD
+fn merged_singular_values(pair: &LoraPair, down: &[f32], up: &[f32]) -> Result<Vec<f32>, Error> {
+    let rank = pair.rank() as usize;
+    let [rows, cols] = pair.delta_shape().map(|n| n as usize);
+    if rank == 0 || down.len() != rank * cols || up.len() != rows * rank {
+        bail!("LoRA tensors have mismatched shapes");
+    }
+    let a = faer::Mat::<f64>::from_fn(cols, rank, |i, j| down[j * cols + i] as f64);
+    let b = faer::Mat::<f64>::from_fn(rows, rank, |i, j| up[i * rank + j] as f64);
+    let r_b = b.qr().thin_R().to_owned();
+    let r_a = a.qr().thin_R().to_owned();
+    let core = r_b * r_a.transpose();
+    let values = core
+        .singular_values()
+        .map_err(|err| anyhow!("could not perform SVD: {err:?}"))?;
+    let scale = pair.scale().abs() as f64;
+    Ok(values.into_iter().map(|s| (s * scale) as f32).collect())
+}
+
+fn do_lora_analysis(
+    source: &Mutex<dyn ModuleSource + Send>,
+    request: Ref<LoraAnalysis>,
+) -> Result<(), Error> {
+    let (pair, max_bin_count) = request
+        .inspect(|req| (req.pair.clone(), req.max_bin_count))
+        .ok_or(anyhow!("cancelled"))?;
+    let progress = request.map(|req| &req.progress);
+    let down = source
+        .lock()
+        .unwrap()
+        .tensor_f32(pair.down.1.clone(), progress)?;
+    let up = source
+        .lock()
+        .unwrap()
+        .tensor_f32(pair.up.1.clone(), progress)?;
+    let values = merged_singular_values(&pair, &down, &up)?;
+    let histogram = Histogram::new(&values, max_bin_count, true, request.map(|_| &()))?;
+    {
+        let _ = request
+            .map(|req| &req.spectrum)
+            .get(&pin())
+            .ok_or(anyhow!("cancelled"))?
+            .set(Spectrum {
+                chart: histogram.chart,
+                summary: SpectralSummary::new(&values),
+            });
+    }
+    Ok(())
+}
+
+pub fn start_lora_analysis(
+    source: Arc<Mutex<dyn ModuleSource + Send>>,
+    analysis: Ref<LoraAnalysis>,
+) {
+    notify::spawn(move || {
+        if let Err(err) = do_lora_analysis(&*source, analysis) {
+            analysis.inspect(|a| {
+                let _ = a.error.set(err);
+            });
+        }
+    });
+}
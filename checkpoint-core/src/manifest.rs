@@ -0,0 +1,137 @@
+use anyhow::{Error, anyhow};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed};
+use std::sync::{Arc, Mutex, OnceLock};
+use weakref::{Ref, pin};
+
+use crate::model::{ModuleSource, TensorInfo};
+use crate::notify;
+
+/// Bytes read from storage at once while hashing a tensor
+const HASH_CHUNK: usize = 1 << 26;
+
+/// Passes the raw bytes of `tensor` to `visit` a chunk at a time, so hashing never holds a
+/// whole tensor in memory
+pub(crate) fn visit_tensor_bytes(
+    source: &Mutex<dyn ModuleSource + Send>,
+    tensor: &TensorInfo,
+    progress: Ref<AtomicU64>,
+    visit: &mut dyn FnMut(&[u8]),
+) -> Result<(), Error> {
+    let mut start = 0;
+    while start < tensor.size {
+        let end = (start + HASH_CHUNK).min(tensor.size);
+        let bytes = source
+            .lock()
+            .unwrap()
+            .tensor_byte_range(tensor, start..end)?;
+        visit(&bytes);
+        start = end;
+        progress
+            .inspect(|p| p.store((start * 100 / tensor.size) as u64, Relaxed))
+            .ok_or(anyhow!("cancelled"))?;
+        notify::notify();
+    }
+    Ok(())
+}
+
+/// SHA-256 of the tensor's payload as stored, in lowercase hex
+pub fn tensor_sha256(
+    source: &Mutex<dyn ModuleSource + Send>,
+    tensor: &TensorInfo,
+    progress: Ref<AtomicU64>,
+) -> Result<String, Error> {
+    let mut hasher = Sha256::new();
+    visit_tensor_bytes(source, tensor, progress, &mut |bytes| hasher.update(bytes))?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+/// Identifies one tensor by its content, so two checkpoints can be compared by manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub name: String,
+    pub dtype: String,
+    pub shape: Vec<u64>,
+    pub sha256: String,
+}
+
+/// Hashes a list of tensors in the background
+pub struct ManifestJob {
+    pub tensors: Vec<(String, TensorInfo)>,
+    pub done: AtomicUsize,
+    /// Percent read of the tensor currently being hashed
+    pub progress: AtomicU64,
+    pub entries: OnceLock<Vec<ManifestEntry>>,
+    pub error: OnceLock<Error>,
+}
+
+impl ManifestJob {
+    pub fn new(tensors: Vec<(String, TensorInfo)>) -> Self {
+        ManifestJob {
+            tensors,
+            done: AtomicUsize::new(0),
+            progress: AtomicU64::new(0),
+            entries: OnceLock::new(),
+            error: OnceLock::new(),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.entries.get().is_some() || self.error.get().is_some()
+    }
+
+    /// The hash of `name`, once the whole job is done
+    pub fn sha256(&self, name: &str) -> Option<&str> {
+        self.entries
+            .get()?
+            .iter()
+            .find(|entry| entry.name == name)
+            .map(|entry| entry.sha256.as_str())
+    }
+}
+
+fn do_manifest(
+    source: &Mutex<dyn ModuleSource + Send>,
+    request: Ref<ManifestJob>,
+) -> Result<(), Error> {
+    let tensors = request
+        .inspect(|req| req.tensors.clone())
+        .ok_or(anyhow!("cancelled"))?;
+    let progress = request.map(|req| &req.progress);
+    let mut entries = Vec::with_capacity(tensors.len());
+    for (name, tensor) in tensors {
+        entries.push(ManifestEntry {
+            sha256: tensor_sha256(source, &tensor, progress)?,
+            dtype: tensor.ty.to_string(),
+            shape: tensor.shape,
+            name,
+        });
+        request
+            .inspect(|req| req.done.fetch_add(1, Relaxed))
+            .ok_or(anyhow!("cancelled"))?;
+        notify::notify();
+    }
+    {
+        let _ = request
+            .map(|req| &req.entries)
+            .get(&pin())
+            .ok_or(anyhow!("cancelled"))?
+            .set(entries);
+    }
+    Ok(())
+}
+
+pub fn start_manifest(source: Arc<Mutex<dyn ModuleSource + Send>>, job: Ref<ManifestJob>) {
+    notify::spawn(move || {
+        if let Err(err) = do_manifest(&*source, job) {
+            job.inspect(|j| {
+                let _ = j.error.set(err);
+            });
+        }
+    });
+}
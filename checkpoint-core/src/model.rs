@@ -0,0 +1,1120 @@
+use anyhow::{Error, bail};
+use owning_ref::ArcRef;
+use regex::Regex;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::{cmp, fmt, hash, mem, ops};
+use weakref::Ref;
+
+use crate::integrity::Problem;
+use crate::notify;
+use crate::storage::Storage;
+
+#[derive(Debug, Clone)]
+#[allow(non_camel_case_types)]
+pub enum TensorTy {
+    BOOL,
+    U8,
+    I8,
+    F8_E5M2,
+    F8_E4M3,
+    I16,
+    U16,
+    I32,
+    U32,
+    F16,
+    BF16,
+    F32,
+    F64,
+    I64,
+    U64,
+    Ggml(ggml_base::GgmlTypeId),
+    Unknown(String),
+}
+
+impl fmt::Display for TensorTy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use TensorTy::*;
+        let text = match self {
+            BOOL => "BOOL",
+            U8 => "U8",
+            I8 => "I8",
+            F8_E5M2 => "F8_E5M2",
+            F8_E4M3 => "F8_E4M3",
+            I16 => "I16",
+            U16 => "U16",
+            I32 => "I32",
+            U32 => "U32",
+            F16 => "F16",
+            BF16 => "BF16",
+            F32 => "F32",
+            F64 => "F64",
+            I64 => "I64",
+            U64 => "U64",
+            Ggml(ty) => ggml_base::get_type_name(*ty).expect("{ty} is not a valid ggml type"),
+            Unknown(text) => text,
+        };
+        write!(f, "{}", text,)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TensorInfo {
+    pub ty: TensorTy,
+    pub shape: Vec<u64>,
+    pub size: usize,
+    pub offset: u64,
+}
+
+pub trait ByteOrder {
+    const IS_NATIVE: bool;
+
+    fn toggle_native(bytes: &mut [u8]) {
+        if !Self::IS_NATIVE {
+            bytes.reverse();
+        }
+    }
+}
+
+pub enum LE {}
+
+impl ByteOrder for LE {
+    #[cfg(target_endian = "big")]
+    const IS_NATIVE: bool = false;
+    #[cfg(target_endian = "little")]
+    const IS_NATIVE: bool = true;
+}
+
+pub enum BE {}
+
+impl ByteOrder for BE {
+    #[cfg(target_endian = "big")]
+    const IS_NATIVE: bool = true;
+    #[cfg(target_endian = "little")]
+    const IS_NATIVE: bool = false;
+}
+
+pub enum NE {}
+
+impl ByteOrder for NE {
+    const IS_NATIVE: bool = true;
+}
+
+fn convertbytes<T, S, O: ByteOrder>(bytes: &[u8], map: impl Fn(T) -> S) -> Vec<S>
+where
+    T: zerocopy::AsBytes + zerocopy::FromBytes,
+{
+    let stride = std::mem::size_of::<T>();
+    let len = bytes.len() / stride;
+    let mut out = Vec::with_capacity(len);
+    let mut i = 0;
+    while i < bytes.len() {
+        let mut this: T = T::new_zeroed();
+        let dest = this.as_bytes_mut();
+        dest.copy_from_slice(&bytes[i..][..stride]);
+        O::toggle_native(dest);
+        out.push(map(this));
+        i += stride;
+    }
+    out
+}
+
+/// Like `convertbytes`, but only holds `VISIT_ELEMENTS` converted values at a time
+fn visitbytes<T, O: ByteOrder>(
+    bytes: &[u8],
+    map: impl Fn(T) -> f32,
+    visit: &mut dyn FnMut(&[f32]) -> Result<(), Error>,
+) -> Result<(), Error>
+where
+    T: zerocopy::AsBytes + zerocopy::FromBytes,
+{
+    let stride = std::mem::size_of::<T>();
+    let mut out = Vec::with_capacity(VISIT_ELEMENTS);
+    for value in bytes.chunks_exact(stride) {
+        let mut this: T = T::new_zeroed();
+        let dest = this.as_bytes_mut();
+        dest.copy_from_slice(value);
+        O::toggle_native(dest);
+        out.push(map(this));
+        if out.len() == VISIT_ELEMENTS {
+            visit(&out)?;
+            out.clear();
+        }
+    }
+    if !out.is_empty() {
+        visit(&out)?;
+    }
+    Ok(())
+}
+
+fn convertvalues<T, S, O: ByteOrder>(values: &[T], map: impl Fn(&T) -> S) -> Vec<u8>
+where
+    S: zerocopy::AsBytes,
+{
+    let stride = std::mem::size_of::<S>();
+    let mut out = Vec::with_capacity(values.len() * stride);
+    for x in values {
+        let start = out.len();
+        out.extend_from_slice(map(x).as_bytes());
+        O::toggle_native(&mut out[start..]);
+    }
+    out
+}
+
+impl TensorTy {
+    /// Bytes per element, or `None` for block-quantized ggml types
+    pub fn element_size(&self) -> Option<usize> {
+        use TensorTy::*;
+        Some(match self {
+            BOOL | U8 | I8 | F8_E5M2 | F8_E4M3 => 1,
+            I16 | U16 | F16 | BF16 => 2,
+            I32 | U32 | F32 => 4,
+            I64 | U64 | F64 => 8,
+            Ggml(_) | Unknown(_) => return None,
+        })
+    }
+
+    /// Formats each element in `bytes`, which must hold a whole number of elements
+    pub fn format_elements<O: ByteOrder>(&self, bytes: &[u8]) -> Option<Vec<String>> {
+        use TensorTy::*;
+        Some(match self {
+            BOOL => bytes.iter().map(|&b| (b != 0).to_string()).collect(),
+            U8 => convertbytes::<u8, _, O>(bytes, |x| x.to_string()),
+            I8 => convertbytes::<i8, _, O>(bytes, |x| x.to_string()),
+            I16 => convertbytes::<i16, _, O>(bytes, |x| x.to_string()),
+            U16 => convertbytes::<u16, _, O>(bytes, |x| x.to_string()),
+            I32 => convertbytes::<i32, _, O>(bytes, |x| x.to_string()),
+            U32 => convertbytes::<u32, _, O>(bytes, |x| x.to_string()),
+            I64 => convertbytes::<i64, _, O>(bytes, |x| x.to_string()),
+            U64 => convertbytes::<u64, _, O>(bytes, |x| x.to_string()),
+            F32 => convertbytes::<f32, _, O>(bytes, |x| format!("{x:.6e}")),
+            F64 => convertbytes::<f64, _, O>(bytes, |x| format!("{x:.6e}")),
+            F16 => convertbytes::<half::f16, _, O>(bytes, |x| format!("{:.4e}", f32::from(x))),
+            BF16 => convertbytes::<half::bf16, _, O>(bytes, |x| format!("{:.3e}", f32::from(x))),
+            F8_E4M3 => {
+                convertbytes::<float8::F8E4M3, _, O>(bytes, |x| format!("{:.2e}", f32::from(x)))
+            }
+            F8_E5M2 => {
+                convertbytes::<float8::F8E5M2, _, O>(bytes, |x| format!("{:.2e}", f32::from(x)))
+            }
+            Ggml(_) | Unknown(_) => return None,
+        })
+    }
+
+    pub fn is_float(&self) -> bool {
+        use TensorTy::*;
+        matches!(self, F8_E5M2 | F8_E4M3 | F16 | BF16 | F32 | F64 | Ggml(_))
+    }
+
+    /// Integer and bool types, which are read as whole-numbered floats
+    pub fn is_integer(&self) -> bool {
+        use TensorTy::*;
+        matches!(self, BOOL | U8 | I8 | U16 | I16 | U32 | I32 | U64 | I64)
+    }
+
+    pub fn write_f32<O: ByteOrder>(&self, values: &[f32]) -> Result<Vec<u8>, Error> {
+        use TensorTy::*;
+        Ok(match self {
+            F32 => convertvalues::<f32, _, O>(values, |&x| x),
+            F64 => convertvalues::<f32, _, O>(values, |&x| x as f64),
+            F16 => convertvalues::<f32, _, O>(values, |&x| half::f16::from_f32(x)),
+            BF16 => convertvalues::<f32, _, O>(values, |&x| half::bf16::from_f32(x)),
+            other => bail!("cannot convert to tensor type {other}"),
+        })
+    }
+}
+
+/// Roughly how many values are read from storage at once when streaming a tensor
+const CHUNK_ELEMENTS: usize = 1 << 24;
+
+/// Roughly how many values are converted to f32 at once when streaming a tensor, so a chunk
+/// of BF16 or quantized data never has to be expanded all at once
+const VISIT_ELEMENTS: usize = 1 << 16;
+
+impl TensorInfo {
+    /// Decodes the tensor from `storage`, starting at `offset`, a few rows at a time and passes
+    /// each run of values to `visit`
+    pub fn read_chunks_f32<O: ByteOrder>(
+        &self,
+        storage: &impl Storage,
+        offset: u64,
+        progress: Ref<AtomicU64>,
+        visit: &mut dyn FnMut(&[f32]) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let numel = self.shape.iter().product::<u64>() as usize;
+        if numel == 0 {
+            return Ok(());
+        }
+        // Quantized blocks never straddle rows, so only split on whole rows
+        let unit = match self.ty {
+            TensorTy::Ggml(_) => self.shape.last().copied().unwrap_or(1) as usize,
+            _ => 1,
+        }
+        .max(1);
+        let units = numel / unit;
+        let unit_bytes = self.size / units;
+        let per_chunk = (CHUNK_ELEMENTS / unit).max(1);
+
+        let mut done = 0;
+        while done < units {
+            if !progress.is_alive() {
+                bail!("cancelled");
+            }
+            let count = per_chunk.min(units - done);
+            let bytes =
+                storage.read_range(offset + (done * unit_bytes) as u64, count * unit_bytes)?;
+            let chunk = TensorInfo {
+                ty: self.ty.clone(),
+                shape: vec![count as u64, unit as u64],
+                size: bytes.len(),
+                offset: 0,
+            };
+            chunk.visit_f32::<O>(&bytes, visit)?;
+            done += count;
+            progress.inspect(|p| p.store((done * 100 / units) as u64, Relaxed));
+            notify::notify();
+        }
+        Ok(())
+    }
+
+    /// Decodes `bytes` of this tensor like [`TensorInfo::read_f32`], but passes the values to
+    /// `visit` a few at a time instead of collecting them
+    pub fn visit_f32<O: ByteOrder>(
+        &self,
+        bytes: &[u8],
+        visit: &mut dyn FnMut(&[f32]) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        use TensorTy::*;
+        match self.ty {
+            F32 => visitbytes::<f32, O>(bytes, |x| x, visit),
+            F64 => visitbytes::<f64, O>(bytes, |x| x as f32, visit),
+            F16 => visitbytes::<half::f16, O>(bytes, |x| x.into(), visit),
+            BF16 => visitbytes::<half::bf16, O>(bytes, |x| x.into(), visit),
+            F8_E4M3 => visitbytes::<float8::F8E4M3, O>(bytes, |x| x.into(), visit),
+            F8_E5M2 => visitbytes::<float8::F8E5M2, O>(bytes, |x| x.into(), visit),
+            BOOL => visitbytes::<u8, O>(bytes, |x| (x != 0) as u8 as f32, visit),
+            U8 => visitbytes::<u8, O>(bytes, |x| x as f32, visit),
+            I8 => visitbytes::<i8, O>(bytes, |x| x as f32, visit),
+            U16 => visitbytes::<u16, O>(bytes, |x| x as f32, visit),
+            I16 => visitbytes::<i16, O>(bytes, |x| x as f32, visit),
+            U32 => visitbytes::<u32, O>(bytes, |x| x as f32, visit),
+            I32 => visitbytes::<i32, O>(bytes, |x| x as f32, visit),
+            U64 => visitbytes::<u64, O>(bytes, |x| x as f32, visit),
+            I64 => visitbytes::<i64, O>(bytes, |x| x as f32, visit),
+            Ggml(ty) => {
+                // Blocks never straddle rows, so dequantize a few whole rows at a time
+                let numel = self.shape.iter().product::<u64>() as usize;
+                let row = self.shape.last().copied().unwrap_or(1).max(1) as usize;
+                if numel == 0 {
+                    return Ok(());
+                }
+                let row_bytes = (bytes.len() / (numel / row)).max(1);
+                let rows = (VISIT_ELEMENTS / row).max(1);
+                for part in bytes.chunks(rows * row_bytes) {
+                    let shape = [(part.len() / row_bytes) as u64, row as u64];
+                    visit(&ggml_base::dequantize(ty, &shape, part)?)?;
+                }
+                Ok(())
+            }
+            ref other => bail!("unsupported tensor type {other:?}"),
+        }
+    }
+
+    pub fn read_f32<O: ByteOrder>(&self, bytes: &[u8]) -> Result<Vec<f32>, Error> {
+        use TensorTy::*;
+        Ok(match self.ty {
+            F32 => convertbytes::<f32, _, O>(bytes, |x| x),
+            F64 => convertbytes::<f64, _, O>(bytes, |x| x as f32),
+            F16 => convertbytes::<half::f16, _, O>(bytes, |x| x.into()),
+            BF16 => convertbytes::<half::bf16, _, O>(bytes, |x| x.into()),
+            F8_E4M3 => convertbytes::<float8::F8E4M3, _, O>(bytes, |x| x.into()),
+            F8_E5M2 => convertbytes::<float8::F8E5M2, _, O>(bytes, |x| x.into()),
+            BOOL => convertbytes::<u8, _, O>(bytes, |x| (x != 0) as u8 as f32),
+            U8 => convertbytes::<u8, _, O>(bytes, |x| x as f32),
+            I8 => convertbytes::<i8, _, O>(bytes, |x| x as f32),
+            U16 => convertbytes::<u16, _, O>(bytes, |x| x as f32),
+            I16 => convertbytes::<i16, _, O>(bytes, |x| x as f32),
+            U32 => convertbytes::<u32, _, O>(bytes, |x| x as f32),
+            I32 => convertbytes::<i32, _, O>(bytes, |x| x as f32),
+            U64 => convertbytes::<u64, _, O>(bytes, |x| x as f32),
+            I64 => convertbytes::<i64, _, O>(bytes, |x| x as f32),
+            Ggml(ty) => ggml_base::dequantize(ty, &self.shape, bytes)?,
+            ref other => bail!("unsupported tensor type {other:?}"),
+        })
+    }
+
+    pub fn read_f64<O: ByteOrder>(&self, bytes: &[u8]) -> Result<Vec<f64>, Error> {
+        use TensorTy::*;
+        Ok(match self.ty {
+            F32 => convertbytes::<f32, _, O>(bytes, |x| x as f64),
+            F64 => convertbytes::<f64, _, O>(bytes, |x| x),
+            F16 => convertbytes::<half::f16, _, O>(bytes, |x| x.into()),
+            BF16 => convertbytes::<half::bf16, _, O>(bytes, |x| x.into()),
+            F8_E4M3 => convertbytes::<float8::F8E4M3, _, O>(bytes, |x| x.into()),
+            F8_E5M2 => convertbytes::<float8::F8E5M2, _, O>(bytes, |x| x.into()),
+            BOOL => convertbytes::<u8, _, O>(bytes, |x| (x != 0) as u8 as f64),
+            U8 => convertbytes::<u8, _, O>(bytes, |x| x as f64),
+            I8 => convertbytes::<i8, _, O>(bytes, |x| x as f64),
+            U16 => convertbytes::<u16, _, O>(bytes, |x| x as f64),
+            I16 => convertbytes::<i16, _, O>(bytes, |x| x as f64),
+            U32 => convertbytes::<u32, _, O>(bytes, |x| x as f64),
+            I32 => convertbytes::<i32, _, O>(bytes, |x| x as f64),
+            U64 => convertbytes::<u64, _, O>(bytes, |x| x as f64),
+            I64 => convertbytes::<i64, _, O>(bytes, |x| x as f64),
+            Ggml(ty) => ggml_base::dequantize(ty, &self.shape, bytes)?
+                .into_iter()
+                .map(|x| x as f64)
+                .collect(),
+            ref other => bail!("unsupported tensor type {other:?}"),
+        })
+    }
+}
+
+/// How tensor names are broken into module paths
+#[derive(Debug, Clone)]
+pub enum PathSplit {
+    Delim(char),
+    /// Splits wherever the pattern matches, dropping the match
+    Regex(Regex),
+    /// Splits with the inner strategy, then again between digits and non-digits so that
+    /// `blocks10attn` becomes `blocks`, `10`, `attn`
+    Digits(Box<PathSplit>),
+    /// Splits on `/` first, then each part with the inner strategy, for formats like Flax
+    /// msgpack where `/` joins the keys of nested dicts
+    Nested(Box<PathSplit>),
+}
+
+impl Default for PathSplit {
+    fn default() -> Self {
+        PathSplit::Delim('.')
+    }
+}
+
+impl PathSplit {
+    /// Names accepted by [`PathSplit::from_str`] besides single characters and `regex:` patterns
+    pub const PRESETS: [&str; 3] = ["pytorch", "flax", "tf"];
+
+    pub fn split(&self, fullname: Arc<str>) -> Vec<Key> {
+        self.ranges(&fullname)
+            .into_iter()
+            .map(|range| Key::new(fullname.clone(), range))
+            .collect()
+    }
+
+    /// Where the first part at or after byte `at` of `name` starts, past any delimiter matched
+    /// there
+    pub fn next_part(&self, name: &str, at: usize) -> usize {
+        self.ranges(name)
+            .into_iter()
+            .map(|range| range.start)
+            .find(|&start| start >= at)
+            .unwrap_or(at)
+    }
+
+    fn ranges(&self, name: &str) -> Vec<ops::Range<usize>> {
+        let mut parts = Vec::new();
+        let mut at = 0;
+        match self {
+            &PathSplit::Delim(d) => {
+                while let Some(off) = name[at..].find(d) {
+                    parts.push(at..at + off);
+                    at += off + d.len_utf8();
+                }
+            }
+            PathSplit::Regex(pattern) => {
+                for found in pattern.find_iter(name) {
+                    if found.is_empty() && found.start() == at {
+                        continue;
+                    }
+                    parts.push(at..found.start());
+                    at = found.end();
+                }
+            }
+            PathSplit::Digits(inner) => {
+                for range in inner.ranges(name) {
+                    let mut start = range.start;
+                    let mut last_digit = None;
+                    for (i, c) in name[range.clone()].char_indices() {
+                        let digit = c.is_ascii_digit();
+                        if last_digit.is_some_and(|last| last != digit) {
+                            parts.push(start..range.start + i);
+                            start = range.start + i;
+                        }
+                        last_digit = Some(digit);
+                    }
+                    parts.push(start..range.end);
+                }
+                return parts;
+            }
+            PathSplit::Nested(inner) => {
+                for part in PathSplit::Delim('/').ranges(name) {
+                    for range in inner.ranges(&name[part.clone()]) {
+                        parts.push(part.start + range.start..part.start + range.end);
+                    }
+                }
+                return parts;
+            }
+        }
+        parts.push(at..name.len());
+        parts
+    }
+}
+
+impl FromStr for PathSplit {
+    type Err = Error;
+
+    /// Parses a preset (`pytorch` splits on `.`, `flax` on `/`, and `tf` on `/` and `:`), a
+    /// single delimiter character, or `regex:PATTERN`, any of which can end in `+digits`
+    fn from_str(text: &str) -> Result<Self, Error> {
+        if let Some(inner) = text.strip_suffix("+digits") {
+            return Ok(PathSplit::Digits(Box::new(inner.parse()?)));
+        }
+        if let Some(pattern) = text.strip_prefix("regex:") {
+            return Ok(PathSplit::Regex(Regex::new(pattern)?));
+        }
+        let mut chars = text.chars();
+        Ok(match (text, chars.next(), chars.next()) {
+            ("pytorch", _, _) => PathSplit::Delim('.'),
+            ("flax", _, _) => PathSplit::Delim('/'),
+            ("tf", _, _) => PathSplit::Regex(Regex::new("[/:]")?),
+            (_, Some(delim), None) => PathSplit::Delim(delim),
+            _ => bail!(
+                "unknown module split {text:?}, expected one of {:?}, a single character, or regex:PATTERN",
+                PathSplit::PRESETS
+            ),
+        })
+    }
+}
+
+/// A tensor below a module whose children haven't been built yet, with the rest of its path
+/// reversed so the next key is last
+type PendingTensor = (Vec<Key>, TensorInfo);
+
+#[derive(Default, Debug)]
+pub struct ModuleInfo {
+    pub full_name: Key,
+    pub tensor_info: Option<TensorInfo>,
+    /// Built from `pending` the first time they are needed, so opening a checkpoint with millions
+    /// of tensors only splits their names
+    children: OnceLock<BTreeMap<Key, ModuleInfo>>,
+    pending: Mutex<Vec<PendingTensor>>,
+    /// How many levels down children start being flattened as they are built, if at all, see
+    /// [`ModuleInfo::flatten_single_children`]
+    flatten: Option<usize>,
+    pub total_tensors: u64,
+    pub total_params: u64,
+    pub total_bytes: u64,
+}
+
+impl ModuleInfo {
+    pub fn new(full_name: Key) -> Self {
+        Self {
+            full_name,
+            ..Default::default()
+        }
+    }
+
+    pub fn build_from_tensors(
+        tensors: impl IntoIterator<Item = (String, TensorInfo)>,
+        split: &PathSplit,
+    ) -> Self {
+        let mut root = ModuleInfo::default();
+        let pending = root.pending.get_mut().unwrap();
+        for (name, info) in tensors.into_iter() {
+            root.total_params += info.shape.iter().copied().product::<u64>();
+            root.total_bytes += info.size as u64;
+            root.total_tensors += 1;
+
+            let mut parts = split.split(name.into());
+            if parts.is_empty() {
+                root.tensor_info = Some(info);
+                continue;
+            }
+            parts.reverse();
+            pending.push((parts, info));
+        }
+        root
+    }
+
+    pub fn children(&self) -> &BTreeMap<Key, ModuleInfo> {
+        self.children.get_or_init(|| {
+            let pending = mem::take(&mut *self.pending.lock().unwrap());
+            let flatten = self.flatten.map(|depth| depth.saturating_sub(1));
+            let children = build_children(pending, flatten);
+            if self.flatten != Some(0) {
+                return children;
+            }
+            children
+                .into_iter()
+                .map(|(key, child)| child.flatten_pending(key))
+                .collect()
+        })
+    }
+
+    pub fn children_mut(&mut self) -> &mut BTreeMap<Key, ModuleInfo> {
+        self.children();
+        self.children.get_mut().unwrap()
+    }
+
+    /// Whether this module has any children, without building them
+    pub fn has_children(&self) -> bool {
+        // Empty once taken by `children`, which then blocks until they are built
+        let pending = self.pending.lock().unwrap();
+        if !pending.is_empty() {
+            return true;
+        }
+        drop(pending);
+        !self.children().is_empty()
+    }
+
+    pub fn find(&self, full_name: &str) -> Option<&ModuleInfo> {
+        if &*self.full_name == full_name {
+            return Some(self);
+        }
+        // Grouping can move tensors under modules of any name, but only after building them
+        if self.children.get().is_none() && !full_name.starts_with(&*self.full_name) {
+            return None;
+        }
+        self.children()
+            .values()
+            .find_map(|child| child.find(full_name))
+    }
+
+    pub fn is_tensor(&self) -> bool {
+        self.tensor_info.is_some()
+    }
+
+    pub fn tensors(&self) -> Vec<(String, TensorInfo)> {
+        let mut tensors = Vec::new();
+        let mut stack = vec![self];
+        while let Some(module) = stack.pop() {
+            if let Some(info) = &module.tensor_info {
+                tensors.push((module.full_name.to_string(), info.clone()));
+            }
+            let pending = module.pending.lock().unwrap();
+            if pending.is_empty() {
+                drop(pending);
+                stack.extend(module.children().values());
+            } else {
+                tensors.extend(
+                    pending.iter().map(|(parts, info)| {
+                        (parts[0].clone().absolute().to_string(), info.clone())
+                    }),
+                );
+            }
+        }
+        tensors
+    }
+
+    /// Merges each child which has exactly one child of its own into it, so `a.b.c` shows as one
+    /// item when `a` and `b` hold nothing else. Children not built yet are merged once they are.
+    ///
+    /// Modules less than `depth` levels below this one are left as they are, so that the top
+    /// of the hierarchy keeps one level per part of the path.
+    pub fn flatten_single_children(&mut self, depth: usize) {
+        self.flatten = Some(depth);
+        let Some(children) = self.children.get_mut() else {
+            return;
+        };
+        *children = mem::take(children)
+            .into_iter()
+            .map(|(k, mut v)| {
+                v.flatten_single_children(depth.saturating_sub(1));
+                if depth > 0 || v.is_tensor() || v.children().len() != 1 {
+                    return (k, v);
+                }
+                let (ck, cv) = mem::take(v.children_mut()).into_iter().next().unwrap();
+                (k.join(ck), cv)
+            })
+            .collect();
+    }
+
+    /// Follows a chain of modules with only one child down from this freshly built one, which
+    /// is reached through `key`, without building anything off the chain
+    fn flatten_pending(mut self, mut key: Key) -> (Key, ModuleInfo) {
+        loop {
+            let pending = self.pending.get_mut().unwrap();
+            let Some((first, _)) = pending.first() else {
+                break;
+            };
+            let next = first.last().unwrap();
+            if self.tensor_info.is_some()
+                || pending.iter().any(|(parts, _)| parts.last() != Some(next))
+            {
+                break;
+            }
+            let (child_key, child) = build_children(mem::take(pending), Some(0))
+                .into_iter()
+                .next()
+                .unwrap();
+            key = key.join(child_key);
+            self = child;
+        }
+        (key, self)
+    }
+
+    /// Replaces numbered children (`layers.0` … `layers.31`) with a virtual `layers[*]` module
+    /// holding the structure they share, so `layers[*].attn.q_proj.weight` has the weight of
+    /// each layer as its children. Needs at least two numbered children to do anything.
+    pub fn group_numbered_children(&mut self) {
+        let children = self.children_mut();
+        *children = mem::take(children)
+            .into_iter()
+            .map(|(k, mut v)| {
+                v.group_numbered_children();
+                let numbered = v.children().keys().filter(|key| is_index(key)).count();
+                if numbered < 2 {
+                    return (k, v);
+                }
+                let full: Arc<str> = format!("{}[*]", v.full_name).into();
+                let key = Key::new(full.clone(), full.len() - k.len() - 3..full.len());
+                let mut grouped = ModuleInfo::new(Key::new(full.clone(), 0..full.len()));
+                for (ck, cv) in mem::take(v.children_mut()) {
+                    if is_index(&ck) {
+                        grouped.insert_by_index(ck, cv);
+                    } else {
+                        grouped.insert_child(ck, cv);
+                    }
+                }
+                (key, grouped)
+            })
+            .collect();
+    }
+
+    fn insert_child(&mut self, key: Key, child: ModuleInfo) {
+        self.total_tensors += child.total_tensors;
+        self.total_params += child.total_params;
+        self.total_bytes += child.total_bytes;
+        self.children_mut().insert(key, child);
+    }
+
+    /// Merges the contents of one numbered child into the shared structure, with each
+    /// tensor moved under a module of the same name and keyed by `index`
+    fn insert_by_index(&mut self, index: Key, mut layer: ModuleInfo) {
+        if layer.is_tensor() {
+            self.insert_child(index, layer);
+            return;
+        }
+        self.total_tensors += layer.total_tensors;
+        self.total_params += layer.total_params;
+        self.total_bytes += layer.total_bytes;
+        for (key, child) in mem::take(layer.children_mut()) {
+            let shared = match self.children_mut().get_mut(&key) {
+                Some(shared) => shared,
+                None => {
+                    let full: Arc<str> = format!("{}.{key}", self.full_name).into();
+                    let name = Key::new(full.clone(), full.len() - key.len()..full.len());
+                    let module = ModuleInfo::new(Key::new(full.clone(), 0..full.len()));
+                    self.children_mut().entry(name).or_insert(module)
+                }
+            };
+            shared.insert_by_index(index.clone(), child);
+        }
+    }
+}
+
+/// Sorts tensors into the children of their module by the next key of each path
+fn build_children(
+    pending: Vec<PendingTensor>,
+    flatten: Option<usize>,
+) -> BTreeMap<Key, ModuleInfo> {
+    let mut children = BTreeMap::new();
+    for (mut parts, info) in pending {
+        let key = parts.pop().unwrap();
+        let child = children
+            .entry(key.clone())
+            .or_insert_with(|| ModuleInfo::new(key.absolute()));
+        child.flatten = flatten;
+        child.total_params += info.shape.iter().copied().product::<u64>();
+        child.total_bytes += info.size as u64;
+        child.total_tensors += 1;
+        if parts.is_empty() {
+            child.tensor_info = Some(info);
+        } else {
+            child.pending.get_mut().unwrap().push((parts, info));
+        }
+    }
+    children
+}
+
+/// Finds tensor paths which the module tree would quietly merge: names that split into the
+/// same path, tensors which are also the module of other tensors, siblings that end up with
+/// the same key once single children are flattened, and names which differ only by case
+pub fn path_conflicts(tensors: &[(String, TensorInfo)], split: &PathSplit) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut root = PathNode::default();
+    let mut ranges = Vec::with_capacity(tensors.len());
+    let mut lowercase = HashMap::new();
+    for (index, (name, _)) in tensors.iter().enumerate() {
+        let parts = split.ranges(name);
+        let mut node = &mut root;
+        for part in &parts {
+            node = node
+                .children
+                .entry(name[part.clone()].to_string())
+                .or_default();
+            node.under = index;
+        }
+        match node.tensor {
+            Some(other) if tensors[other].0 == *name => {
+                problems.push(Problem::tensor(name, "appears more than once"));
+            }
+            Some(other) => problems.push(Problem::tensor(
+                name,
+                format!("splits into the same path as {}", tensors[other].0),
+            )),
+            None => node.tensor = Some(index),
+        }
+        ranges.push(parts);
+        match lowercase.insert(name.to_lowercase(), index) {
+            Some(other) if tensors[other].0 != *name => problems.push(Problem::tensor(
+                name,
+                format!("differs from {} only by case", tensors[other].0),
+            )),
+            _ => {}
+        }
+    }
+    root.conflicts(0, tensors, &ranges, &mut problems);
+    problems
+}
+
+/// One step of the paths in [`path_conflicts`], built the same way as [`build_children`]
+#[derive(Default)]
+struct PathNode {
+    tensor: Option<usize>,
+    /// The last tensor inserted under this node, whose name spells out flattened keys
+    under: usize,
+    children: BTreeMap<String, PathNode>,
+}
+
+impl PathNode {
+    fn conflicts(
+        &self,
+        depth: usize,
+        tensors: &[(String, TensorInfo)],
+        ranges: &[Vec<ops::Range<usize>>],
+        problems: &mut Vec<Problem>,
+    ) {
+        match self.tensor {
+            Some(index) if !self.children.is_empty() => problems.push(Problem::tensor(
+                &tensors[index].0,
+                "is also a module holding other tensors",
+            )),
+            _ => {}
+        }
+        let mut keys = HashMap::new();
+        for child in self.children.values() {
+            // Follow the chain which `flatten_single_children` merges into one key
+            let (mut end, mut end_depth) = (child, depth);
+            while end.tensor.is_none() && end.children.len() == 1 {
+                end = end.children.values().next().unwrap();
+                end_depth += 1;
+            }
+            let name = &tensors[end.under].0;
+            let parts = &ranges[end.under];
+            let key = &name[parts[depth].start..parts[end_depth].end];
+            if let Some(other) = keys.insert(key, end.under) {
+                problems.push(Problem::tensor(
+                    name,
+                    format!(
+                        "is under {key:?} in the tree, a key shared with {}",
+                        tensors[other].0
+                    ),
+                ));
+            }
+            end.conflicts(end_depth + 1, tensors, ranges, problems);
+        }
+    }
+}
+
+fn is_index(key: &str) -> bool {
+    key.parse::<u64>().is_ok()
+}
+
+pub trait ModuleSource {
+    fn module(&mut self, split: &PathSplit) -> Result<ModuleInfo, Error>;
+    fn metadata(&mut self) -> Result<Value, Error>;
+    /// Part of a metadata array which [`ModuleSource::metadata`] left as a [`lazy_array`]
+    fn metadata_array(&mut self, key: &str, range: ops::Range<usize>) -> Result<Vec<Value>, Error>;
+    /// Metadata as flat string pairs, the form stored in a safetensors `__metadata__` block
+    fn string_metadata(&mut self) -> Result<HashMap<String, String>, Error>;
+    /// How each top-level metadata value is stored, so edits can keep the declared type
+    /// instead of guessing it from the text
+    fn metadata_types(&mut self) -> Result<HashMap<String, MetadataType>, Error>;
+    fn write_metadata(&mut self, metadata: &Value) -> Result<(), Error>;
+    fn rename_tensors(&mut self, renames: &[(String, String)]) -> Result<(), Error>;
+    fn delete_tensors(&mut self, names: &[String]) -> Result<(), Error>;
+    fn tensor_data(
+        &mut self,
+        tensor: TensorInfo,
+        progress: Ref<AtomicU64>,
+    ) -> Result<Vec<u8>, Error>;
+    /// Reads part of a tensor's raw bytes, with `range` relative to the start of the tensor
+    fn tensor_byte_range(
+        &mut self,
+        tensor: &TensorInfo,
+        range: ops::Range<usize>,
+    ) -> Result<Vec<u8>, Error>;
+    fn tensor_f32(
+        &mut self,
+        tensor: TensorInfo,
+        progress: Ref<AtomicU64>,
+    ) -> Result<Vec<f32>, Error>;
+    fn tensor_f64(
+        &mut self,
+        tensor: TensorInfo,
+        progress: Ref<AtomicU64>,
+    ) -> Result<Vec<f64>, Error>;
+    /// Like `tensor_f32`, but never holds more than a chunk of the tensor in memory
+    fn tensor_chunks_f32(
+        &mut self,
+        tensor: TensorInfo,
+        progress: Ref<AtomicU64>,
+        visit: &mut dyn FnMut(&[f32]) -> Result<(), Error>,
+    ) -> Result<(), Error>;
+    /// Looks for tensors stored out of bounds, overlapping, or with the wrong size, and for
+    /// missing metadata, which other readers would fail on or misread
+    fn check(&mut self) -> Result<Vec<Problem>, Error> {
+        Ok(Vec::new())
+    }
+}
+
+/// The type a metadata value is declared with in the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    F32,
+    F64,
+    Bool,
+    String,
+    /// An array of one element type, which is unknown when the array is empty
+    Array(Option<Box<MetadataType>>),
+}
+
+impl MetadataType {
+    /// Parses edited text as this type, refusing anything that doesn't fit rather than
+    /// changing the type, like `-1` for a `u32`
+    pub fn parse(&self, text: &str) -> Result<Value, Error> {
+        fn number<T: FromStr + Into<Value>>(text: &str, ty: &MetadataType) -> Result<Value, Error> {
+            match text.parse::<T>() {
+                Ok(value) => Ok(value.into()),
+                Err(_) => bail!("{text:?} is not a valid {ty}"),
+            }
+        }
+        fn float(value: f64) -> Result<Value, Error> {
+            match serde_json::Number::from_f64(value) {
+                Some(number) => Ok(Value::Number(number)),
+                None => bail!("{value} can't be stored as metadata"),
+            }
+        }
+        use MetadataType::*;
+        match self {
+            U8 => number::<u8>(text, self),
+            I8 => number::<i8>(text, self),
+            U16 => number::<u16>(text, self),
+            I16 => number::<i16>(text, self),
+            U32 => number::<u32>(text, self),
+            I32 => number::<i32>(text, self),
+            U64 => number::<u64>(text, self),
+            I64 => number::<i64>(text, self),
+            F32 => match text.parse::<f32>() {
+                Ok(value) => float(value as f64),
+                Err(_) => bail!("{text:?} is not a valid {self}"),
+            },
+            F64 => match text.parse::<f64>() {
+                Ok(value) => float(value),
+                Err(_) => bail!("{text:?} is not a valid {self}"),
+            },
+            Bool => match text {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ => bail!("{text:?} is not true or false"),
+            },
+            String => Ok(Value::String(text.to_string())),
+            Array(_) => bail!("arrays can't be edited as text"),
+        }
+    }
+}
+
+impl fmt::Display for MetadataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use MetadataType::*;
+        let name = match self {
+            U8 => "u8",
+            I8 => "i8",
+            U16 => "u16",
+            I16 => "i16",
+            U32 => "u32",
+            I32 => "i32",
+            U64 => "u64",
+            I64 => "i64",
+            F32 => "f32",
+            F64 => "f64",
+            Bool => "bool",
+            String => "string",
+            Array(Some(element)) => return write!(f, "[{element}]"),
+            Array(None) => "array",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Arrays longer than this are left out of [`ModuleSource::metadata`] in favor of a
+/// [`lazy_array`] placeholder, and are paged in at most this many values at a time
+pub const METADATA_PAGE: usize = 100;
+
+const LAZY_ARRAY_KEY: &str = "$lazy_array";
+
+/// Stands in for `key[range]` of a metadata array until [`page_lazy_array`] reads it
+pub fn lazy_array(key: &str, range: ops::Range<usize>) -> Value {
+    serde_json::json!({ LAZY_ARRAY_KEY: key, "start": range.start, "end": range.end })
+}
+
+/// The key and range of a [`lazy_array`] placeholder
+pub fn as_lazy_array(value: &Value) -> Option<(&str, ops::Range<usize>)> {
+    let key = value.get(LAZY_ARRAY_KEY)?.as_str()?;
+    let start = value.get("start")?.as_u64()? as usize;
+    let end = value.get("end")?.as_u64()? as usize;
+    Some((key, start..end))
+}
+
+/// Replaces a [`lazy_array`] placeholder with an object of its values keyed by index, or, when
+/// there are more than [`METADATA_PAGE`] of them, with placeholders for up to that many pages
+pub fn page_lazy_array(
+    source: &mut dyn ModuleSource,
+    key: &str,
+    range: ops::Range<usize>,
+) -> Result<Value, Error> {
+    let mut map = serde_json::Map::new();
+    if range.len() <= METADATA_PAGE {
+        let values = source.metadata_array(key, range.clone())?;
+        for (i, value) in range.zip(values) {
+            map.insert(format!("[{i}]"), value);
+        }
+        return Ok(map.into());
+    }
+    let mut step = METADATA_PAGE;
+    while range.len().div_ceil(step) > METADATA_PAGE {
+        step *= METADATA_PAGE;
+    }
+    for start in range.clone().step_by(step) {
+        let end = (start + step).min(range.end);
+        map.insert(format!("[{start}..{end}]"), lazy_array(key, start..end));
+    }
+    Ok(map.into())
+}
+
+pub fn shorten_value(value: &Value) -> bool {
+    use Value::*;
+    match value {
+        String(text) if text.len() > 10_000 || text.starts_with("data:image/") => return true,
+        _ => false,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Key {
+    full: Arc<str>,
+    start: usize,
+    end: usize,
+}
+
+impl Key {
+    /// The part of `full` inside `range`
+    pub fn new(full: Arc<str>, range: ops::Range<usize>) -> Self {
+        Key {
+            full,
+            start: range.start,
+            end: range.end,
+        }
+    }
+
+    /// Where this key sits in the full name
+    pub fn range(&self) -> ops::Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn absolute(mut self) -> Self {
+        self.start = 0;
+        self
+    }
+
+    pub fn join(&self, mut child: Key) -> Self {
+        assert_eq!(self.full[..self.end], child.full[..self.end]);
+        child.start = self.start;
+        child
+    }
+}
+
+impl ops::Deref for Key {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.full[self.start..self.end]
+    }
+}
+
+impl From<Key> for ArcRef<str> {
+    fn from(Key { full, start, end }: Key) -> Self {
+        ArcRef::from(full).map(|s| &s[start..end])
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        <str as fmt::Display>::fmt(self, f)
+    }
+}
+
+impl cmp::PartialOrd for Key {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(<Self as cmp::Ord>::cmp(self, other))
+    }
+}
+
+impl cmp::Ord for Key {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        <str as cmp::Ord>::cmp(self, other)
+        /*match (self.parse::<u64>(), other.parse::<u64>()) {
+            (Some(a), Some(b)) => <str as cmp::Ord>::cmp(self, other),
+            (None, Some(_)) => cmp::Ordering::Less,
+            (Some(_), None) => cmp::Ordering::Greater,
+            (None, None) => <str as cmp::Ord>::cmp(self, other),
+        }*/
+    }
+}
+
+impl cmp::PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        (Arc::ptr_eq(&self.full, &other.full) && self.start == other.start && self.end == other.end)
+            || <str as cmp::PartialEq>::eq(self, other)
+    }
+}
+
+impl cmp::Eq for Key {}
+
+impl hash::Hash for Key {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        <str as hash::Hash>::hash(self, state)
+    }
+}
+
+impl Default for Key {
+    fn default() -> Self {
+        Key {
+            full: Arc::from(""),
+            start: 0,
+            end: 0,
+        }
+    }
+}
@@ -0,0 +1,27 @@
+//! Lets a frontend sleep until background work has something new to show, instead of checking
+//! on every analysis in a loop
+
+use std::sync::OnceLock;
+
+static WAKER: OnceLock<Box<dyn Fn() + Send + Sync>> = OnceLock::new();
+
+/// Calls `waker` whenever a background thread makes progress or finishes. Only the first waker
+/// set is kept.
+pub fn set_waker(waker: impl Fn() + Send + Sync + 'static) {
+    let _ = WAKER.set(Box::new(waker));
+}
+
+/// Wakes the frontend, from any thread
+pub fn notify() {
+    if let Some(waker) = WAKER.get() {
+        waker();
+    }
+}
+
+/// Runs `work` on a new thread, waking the frontend once it finishes
+pub fn spawn(work: impl FnOnce() + Send + 'static) {
+    std::thread::spawn(move || {
+        work();
+        notify();
+    });
+}
@@ -0,0 +1,163 @@
+use crate::bundle::Bundle;
+use crate::integrity::Problem;
+use crate::model::{TensorInfo, TensorTy};
+use crate::storage::Storage;
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use serde_json::Map;
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Opens an `.npz` archive of `.npy` arrays, as written by `numpy.savez` or MLX's
+/// `mx.savez`, where each member is one tensor. The arrays are read in place, so archives
+/// from `numpy.savez_compressed` have to be re-saved without compression first.
+pub fn open<S: Storage>(mut storage: S) -> Result<Bundle<S>> {
+    let path = storage.display();
+    let mut members = Vec::new();
+    {
+        let mut zip = zip::ZipArchive::new(&mut *storage.reader()?)?;
+        for i in 0..zip.len() {
+            let entry = zip.by_index_raw(i)?;
+            if entry.is_dir() {
+                continue;
+            }
+            if entry.compression() != zip::CompressionMethod::Stored {
+                bail!(
+                    "{} is compressed inside {}, so save it with savez instead of savez_compressed",
+                    entry.name(),
+                    path
+                );
+            }
+            members.push((entry.name().to_string(), entry.data_start(), entry.size()));
+        }
+    }
+
+    let mut tensors = Vec::with_capacity(members.len());
+    let mut problems = Vec::new();
+    for (member, start, size) in members {
+        let name = member.strip_suffix(".npy").unwrap_or(&member).to_string();
+        let header = read_npy_header(&storage, start, size)
+            .with_context(|| format!("could not read {member} in {path}"))?;
+        if header.fortran_order {
+            problems.push(Problem::tensor(
+                &name,
+                "is stored in Fortran order, so it is shown transposed",
+            ));
+        }
+        if let TensorTy::Unknown(_) = header.ty {
+            problems.push(Problem::tensor(
+                &name,
+                format!("has numpy dtype {:?}, which can't be read", header.descr),
+            ));
+        }
+        tensors.push((
+            name,
+            TensorInfo {
+                ty: header.ty,
+                shape: header.shape,
+                size: (size - header.len) as usize,
+                offset: start + header.len,
+            },
+        ));
+    }
+    Ok(Bundle::new(storage, "npz", tensors, Map::new(), false).with_problems(problems))
+}
+
+struct NpyHeader {
+    descr: String,
+    ty: TensorTy,
+    fortran_order: bool,
+    /// In C order, so reversed for arrays stored in Fortran order
+    shape: Vec<u64>,
+    /// Bytes before the array data, including the magic string
+    len: u64,
+}
+
+/// Parses the header of the `.npy` member at `start`, a Python dict literal such as
+/// `{'descr': '<f4', 'fortran_order': False, 'shape': (3, 4), }`
+fn read_npy_header(storage: &impl Storage, start: u64, size: u64) -> Result<NpyHeader> {
+    if size < 10 {
+        bail!("too short to be a .npy array");
+    }
+    let prefix = storage.read_range(start, 12.min(size as usize))?;
+    if !prefix.starts_with(NPY_MAGIC) {
+        bail!("not a .npy array");
+    }
+    let (text_start, text_len) = match prefix[6] {
+        1 => (10, u16::from_le_bytes([prefix[8], prefix[9]]) as u64),
+        2 | 3 if prefix.len() == 12 => (12, u32::from_le_bytes(prefix[8..12].try_into()?) as u64),
+        version => bail!("unsupported .npy version {version}"),
+    };
+    let len = text_start + text_len;
+    if len > size {
+        bail!("header runs past the end of the array");
+    }
+    let text = storage.read_range(start + text_start, text_len as usize)?;
+    let text = String::from_utf8_lossy(&text);
+
+    let field = |pattern: &str| {
+        Regex::new(pattern)
+            .unwrap()
+            .captures(&text)
+            .map(|found| found[1].to_string())
+    };
+    let Some(descr) = field(r"'descr':\s*'([^']*)'") else {
+        bail!("header has no descr");
+    };
+    let fortran_order = field(r"'fortran_order':\s*(True|False)").as_deref() == Some("True");
+    let Some(dims) = field(r"'shape':\s*\(([^)]*)\)") else {
+        bail!("header has no shape");
+    };
+    let mut shape = dims
+        .split(',')
+        .map(str::trim)
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| dim.trim_end_matches('L').parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()?;
+    if fortran_order {
+        shape.reverse();
+    }
+    let ty = descr_ty(&descr);
+    if let Some(element_size) = ty.element_size() {
+        let data_size = shape
+            .iter()
+            .try_fold(element_size as u64, |acc, &dim| acc.checked_mul(dim));
+        if data_size != Some(size - len) {
+            bail!(
+                "shape {shape:?} of {descr} doesn't match the {} bytes of data",
+                size - len
+            );
+        }
+    }
+    Ok(NpyHeader {
+        ty,
+        descr,
+        fortran_order,
+        shape,
+        len,
+    })
+}
+
+/// The tensor type of a numpy type string like `<f4`, which must be little-endian
+fn descr_ty(descr: &str) -> TensorTy {
+    use TensorTy::*;
+    let kind = match descr.strip_prefix(['<', '|', '=']) {
+        Some(kind) => kind,
+        None => return Unknown(descr.to_string()),
+    };
+    match kind {
+        "b1" => BOOL,
+        "u1" => U8,
+        "i1" => I8,
+        "u2" => U16,
+        "i2" => I16,
+        "u4" => U32,
+        "i4" => I32,
+        "u8" => U64,
+        "i8" => I64,
+        "f2" => F16,
+        "f4" => F32,
+        "f8" => F64,
+        _ => Unknown(descr.to_string()),
+    }
+}
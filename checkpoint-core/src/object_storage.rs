@@ -0,0 +1,176 @@
+use anyhow::{Error, anyhow, bail};
+use object_store::aws::AmazonS3Builder;
+use object_store::gcp::GoogleCloudStorageBuilder;
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, ObjectStoreScheme};
+use std::io;
+use std::ops::Range;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+use url::Url;
+
+use crate::storage::Storage;
+
+/// How much the sequential reader fetches per request, since every read is a round trip
+const READ_AHEAD: usize = 1024 * 1024;
+
+/// One object, read with ranged GETs
+struct Remote {
+    store: Box<dyn ObjectStore>,
+    path: ObjectPath,
+    runtime: Runtime,
+    size: u64,
+}
+
+impl Remote {
+    fn get(&self, offset: u64, len: usize) -> Result<Vec<u8>, Error> {
+        let end = offset + len as u64;
+        if end > self.size {
+            bail!(
+                "can't read past the end of the object ({end} > {})",
+                self.size
+            );
+        }
+        let bytes = self
+            .runtime
+            .block_on(self.store.get_range(&self.path, offset..end))?;
+        Ok(bytes.to_vec())
+    }
+}
+
+/// A checkpoint in S3 or Google Cloud Storage, with credentials discovered from the
+/// environment the same way the cloud SDKs do. Object storage can't be edited in place, so
+/// every write fails up front.
+pub struct ObjectStorage {
+    url: String,
+    remote: Arc<Remote>,
+    reader: Option<ObjectReader>,
+}
+
+impl ObjectStorage {
+    pub fn open(url: &str) -> Result<Self, Error> {
+        let parsed = Url::parse(url)?;
+        let (scheme, path) = ObjectStoreScheme::parse(&parsed)?;
+        let store: Box<dyn ObjectStore> = match scheme {
+            ObjectStoreScheme::AmazonS3 => {
+                Box::new(AmazonS3Builder::from_env().with_url(url).build()?)
+            }
+            ObjectStoreScheme::GoogleCloudStorage => Box::new(
+                GoogleCloudStorageBuilder::from_env()
+                    .with_url(url)
+                    .build()?,
+            ),
+            other => bail!("{url} is in unsupported object storage ({other:?})"),
+        };
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .enable_all()
+            .build()?;
+        let size = runtime
+            .block_on(store.head(&path))
+            .map_err(|err| anyhow!("could not open {url}: {err}"))?
+            .size;
+        Ok(ObjectStorage {
+            url: url.to_string(),
+            remote: Arc::new(Remote {
+                store,
+                path,
+                runtime,
+                size,
+            }),
+            reader: None,
+        })
+    }
+
+    fn read_only(&self) -> Error {
+        anyhow!("{} is in object storage, which can't be edited", self.url)
+    }
+}
+
+impl Storage for ObjectStorage {
+    type Reader = ObjectReader;
+
+    fn display(&self) -> String {
+        self.url.clone()
+    }
+
+    fn reader(&mut self) -> Result<&mut Self::Reader, Error> {
+        let remote = &self.remote;
+        Ok(self.reader.get_or_insert_with(|| ObjectReader {
+            remote: remote.clone(),
+            pos: 0,
+            buffer: Vec::new(),
+            buffer_start: 0,
+        }))
+    }
+
+    fn read_range(&self, offset: u64, len: usize) -> Result<Vec<u8>, Error> {
+        self.remote.get(offset, len)
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>, Error> {
+        self.remote.get(0, self.remote.size as usize)
+    }
+
+    fn write(&mut self, _bytes: &[u8]) -> Result<(), Error> {
+        Err(self.read_only())
+    }
+
+    fn splice(&mut self, _range: Range<usize>, _bytes: &[u8]) -> Result<(), Error> {
+        Err(self.read_only())
+    }
+
+    fn rebuild(&mut self, _header: &[u8], _ranges: &[Range<u64>]) -> Result<(), Error> {
+        Err(self.read_only())
+    }
+
+    fn overwrite(&mut self, _offset: usize, _bytes: &[u8]) -> Result<(), Error> {
+        Err(self.read_only())
+    }
+
+    fn check_writable(&self) -> Result<(), Error> {
+        Err(self.read_only())
+    }
+}
+
+/// Sequential access to an object, fetched [`READ_AHEAD`] bytes at a time
+pub struct ObjectReader {
+    remote: Arc<Remote>,
+    pos: u64,
+    buffer: Vec<u8>,
+    buffer_start: u64,
+}
+
+impl io::Read for ObjectReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.remote.size || buf.is_empty() {
+            return Ok(0);
+        }
+        let buffered = self.buffer_start..self.buffer_start + self.buffer.len() as u64;
+        if !buffered.contains(&self.pos) {
+            let len = (self.remote.size - self.pos).min(READ_AHEAD.max(buf.len()) as u64);
+            self.buffer = self
+                .remote
+                .get(self.pos, len as usize)
+                .map_err(io::Error::other)?;
+            self.buffer_start = self.pos;
+        }
+        let start = (self.pos - self.buffer_start) as usize;
+        let n = buf.len().min(self.buffer.len() - start);
+        buf[..n].copy_from_slice(&self.buffer[start..start + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl io::Seek for ObjectReader {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            io::SeekFrom::Start(pos) => Some(pos),
+            io::SeekFrom::End(delta) => self.remote.size.checked_add_signed(delta),
+            io::SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+        self.pos = pos.ok_or_else(|| io::Error::other("seek before the start of the object"))?;
+        Ok(self.pos)
+    }
+}
@@ -0,0 +1,387 @@
+//! Reads ONNX models with a minimal protobuf wire-format reader. The initializers become
+//! tensors, whether their bytes are inside the model or in external data files next to it, and
+//! the graph's inputs and outputs are listed in the metadata. Nodes are only counted.
+
+use crate::bundle::Bundle;
+use crate::integrity::Problem;
+use crate::model::{TensorInfo, TensorTy};
+use crate::protobuf::{Wire, read_entries, read_fields, read_string, read_varints};
+use crate::storage::{AnyStorage, SegmentStorage, Storage};
+use anyhow::{Result, bail};
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Component, Path};
+
+/// `TensorProto.data_location` of tensors whose bytes live in another file
+const DATA_LOCATION_EXTERNAL: u64 = 1;
+
+/// The name ONNX gives a `TensorProto.DataType`
+fn data_type_name(code: u64) -> String {
+    const NAMES: [&str; 24] = [
+        "UNDEFINED",
+        "FLOAT",
+        "UINT8",
+        "INT8",
+        "UINT16",
+        "INT16",
+        "INT32",
+        "INT64",
+        "STRING",
+        "BOOL",
+        "FLOAT16",
+        "DOUBLE",
+        "UINT32",
+        "UINT64",
+        "COMPLEX64",
+        "COMPLEX128",
+        "BFLOAT16",
+        "FLOAT8E4M3FN",
+        "FLOAT8E4M3FNUZ",
+        "FLOAT8E5M2",
+        "FLOAT8E5M2FNUZ",
+        "UINT4",
+        "INT4",
+        "FLOAT4E2M1",
+    ];
+    match NAMES.get(code as usize) {
+        Some(name) => name.to_string(),
+        None => format!("DataType({code})"),
+    }
+}
+
+fn data_type_ty(code: u64) -> TensorTy {
+    use TensorTy::*;
+    match code {
+        1 => F32,
+        2 => U8,
+        3 => I8,
+        4 => U16,
+        5 => I16,
+        6 => I32,
+        7 => I64,
+        9 => BOOL,
+        10 => F16,
+        11 => F64,
+        12 => U32,
+        13 => U64,
+        16 => BF16,
+        17 => F8_E4M3,
+        19 => F8_E5M2,
+        other => Unknown(data_type_name(other)),
+    }
+}
+
+/// Where an initializer's bytes are
+enum Data {
+    /// Inside the model file, in `raw_data` or a packed `float_data` or `double_data`
+    Inline(Range<u64>),
+    External {
+        location: String,
+        offset: u64,
+        length: Option<u64>,
+    },
+    /// Stored in a way that can't be read in place, for the given reason
+    Unreadable(String),
+}
+
+struct Initializer {
+    name: String,
+    data_type: u64,
+    dims: Vec<u64>,
+    data: Data,
+}
+
+fn read_initializer(
+    reader: &mut (impl Read + Seek + ?Sized),
+    range: Range<u64>,
+) -> Result<Initializer> {
+    let fields = read_fields(reader, range)?;
+    let dims = read_varints(reader, &fields, 1)?;
+    let mut name = String::new();
+    let mut data_type = 0;
+    let mut external = false;
+    let mut data = None;
+    for (number, value) in &fields {
+        match (number, value) {
+            (2, Wire::Varint(code)) => data_type = *code,
+            (8, Wire::Bytes(range)) => name = read_string(reader, range)?,
+            (14, Wire::Varint(location)) => external = *location == DATA_LOCATION_EXTERNAL,
+            (9, Wire::Bytes(range)) => data = Some(Data::Inline(range.clone())),
+            (4, Wire::Bytes(range)) if data_type == 1 => data = Some(Data::Inline(range.clone())),
+            (10, Wire::Bytes(range)) if data_type == 11 => data = Some(Data::Inline(range.clone())),
+            (4 | 5 | 6 | 7 | 10 | 11, _) => {
+                let field = match number {
+                    4 => "float_data",
+                    5 => "int32_data",
+                    6 => "string_data",
+                    7 => "int64_data",
+                    10 => "double_data",
+                    _ => "uint64_data",
+                };
+                data = Some(Data::Unreadable(format!(
+                    "is stored element by element in {field}, which can't be read in place"
+                )));
+            }
+            _ => {}
+        }
+    }
+    if external {
+        let mut location = String::new();
+        let mut offset = 0;
+        let mut length = None;
+        for (key, value) in read_entries(reader, &fields, 13)? {
+            match key.as_str() {
+                "location" => location = value,
+                "offset" => offset = value.parse()?,
+                "length" => length = Some(value.parse()?),
+                _ => {}
+            }
+        }
+        data = Some(Data::External {
+            location,
+            offset,
+            length,
+        });
+    }
+    Ok(Initializer {
+        name,
+        data_type,
+        dims,
+        // A tensor with no elements has no data fields at all
+        data: data.unwrap_or(Data::Inline(0..0)),
+    })
+}
+
+/// Describes a graph input or output, with symbolic dimensions by name
+fn read_value_info(reader: &mut (impl Read + Seek + ?Sized), range: Range<u64>) -> Result<Value> {
+    let mut name = String::new();
+    let mut dtype = Value::Null;
+    let mut shape = Value::Null;
+    for (number, value) in read_fields(reader, range)? {
+        match (number, value) {
+            (1, Wire::Bytes(range)) => name = read_string(reader, &range)?,
+            // TypeProto, whose tensor_type holds the element type and shape
+            (2, Wire::Bytes(range)) => {
+                for (number, value) in read_fields(reader, range)? {
+                    let (1, Wire::Bytes(range)) = (number, value) else {
+                        continue;
+                    };
+                    for (number, value) in read_fields(reader, range)? {
+                        match (number, value) {
+                            (1, Wire::Varint(code)) => dtype = data_type_name(code).into(),
+                            (2, Wire::Bytes(range)) => shape = read_shape(reader, range)?,
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(json!({ "name": name, "dtype": dtype, "shape": shape }))
+}
+
+fn read_shape(reader: &mut (impl Read + Seek + ?Sized), range: Range<u64>) -> Result<Value> {
+    let mut dims = Vec::new();
+    for (number, value) in read_fields(reader, range)? {
+        let (1, Wire::Bytes(range)) = (number, value) else {
+            continue;
+        };
+        let mut dim = Value::Null;
+        for (number, value) in read_fields(reader, range)? {
+            match (number, value) {
+                (1, Wire::Varint(size)) => dim = (size as i64).into(),
+                (2, Wire::Bytes(range)) => dim = read_string(reader, &range)?.into(),
+                _ => {}
+            }
+        }
+        dims.push(dim);
+    }
+    Ok(dims.into())
+}
+
+/// Opens an `.onnx` model. External data files are looked up relative to the model, as
+/// `onnx.load` does, so they are opened wherever the model itself was.
+pub fn open(mut storage: AnyStorage) -> Result<Bundle<SegmentStorage>> {
+    let path = storage.display();
+    let mut metadata = Map::new();
+    let mut initializers = Vec::new();
+    let mut problems = Vec::new();
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    {
+        let reader = storage.reader()?;
+        let len = reader.seek(SeekFrom::End(0))?;
+        let model = read_fields(reader, 0..len)?;
+        let mut graph = None;
+        let mut opsets = Map::new();
+        for (number, value) in &model {
+            let key = match number {
+                1 => "ir_version",
+                2 => "producer_name",
+                3 => "producer_version",
+                4 => "domain",
+                5 => "model_version",
+                6 => "doc_string",
+                7 => {
+                    graph = Some(value.clone());
+                    continue;
+                }
+                8 => {
+                    let Wire::Bytes(range) = value else {
+                        continue;
+                    };
+                    let mut domain = String::new();
+                    let mut version = 0;
+                    for (number, value) in read_fields(reader, range.clone())? {
+                        match (number, value) {
+                            (1, Wire::Bytes(range)) => domain = read_string(reader, &range)?,
+                            (2, Wire::Varint(value)) => version = value,
+                            _ => {}
+                        }
+                    }
+                    if domain.is_empty() {
+                        domain = "ai.onnx".into();
+                    }
+                    opsets.insert(domain, version.into());
+                    continue;
+                }
+                _ => continue,
+            };
+            let value = match value {
+                Wire::Varint(value) => Value::from(*value),
+                Wire::Bytes(range) => read_string(reader, range)?.into(),
+                Wire::Fixed => continue,
+            };
+            metadata.insert(key.into(), value);
+        }
+        metadata.insert("opset_import".into(), opsets.into());
+        let props: Map<String, Value> = read_entries(reader, &model, 14)?
+            .into_iter()
+            .map(|(key, value)| (key, value.into()))
+            .collect();
+        if !props.is_empty() {
+            metadata.insert("metadata_props".into(), props.into());
+        }
+
+        let Some(Wire::Bytes(graph)) = graph else {
+            bail!("{path} has no graph, so it is not an ONNX model");
+        };
+        let mut nodes = 0;
+        let mut sparse = 0;
+        for (number, value) in read_fields(reader, graph)? {
+            match (number, value) {
+                (1, _) => nodes += 1,
+                (2, Wire::Bytes(range)) => {
+                    metadata.insert("graph.name".into(), read_string(reader, &range)?.into());
+                }
+                (5, Wire::Bytes(range)) => initializers.push(read_initializer(reader, range)?),
+                (11, Wire::Bytes(range)) => inputs.push(read_value_info(reader, range)?),
+                (12, Wire::Bytes(range)) => outputs.push(read_value_info(reader, range)?),
+                (15, _) => sparse += 1,
+                _ => {}
+            }
+        }
+        metadata.insert("graph.node_count".into(), nodes.into());
+        if sparse > 0 {
+            problems.push(Problem::file(format!(
+                "{sparse} sparse initializers are not shown"
+            )));
+        }
+    }
+    // Models before IR version 4 also list every initializer as an input
+    inputs.retain(|input| {
+        !initializers
+            .iter()
+            .any(|init| input["name"].as_str() == Some(&init.name))
+    });
+    metadata.insert("graph.inputs".into(), inputs.into());
+    metadata.insert("graph.outputs".into(), outputs.into());
+
+    let mut segments = SegmentStorage::new(path.clone());
+    segments.push_file(storage)?;
+    let mut external_files: HashMap<String, Result<Range<u64>, String>> = HashMap::new();
+    let mut tensors = Vec::with_capacity(initializers.len());
+    for init in initializers {
+        let ty = data_type_ty(init.data_type);
+        let numel = init
+            .dims
+            .iter()
+            .try_fold(1u64, |acc, &dim| acc.checked_mul(dim));
+        let range = match init.data {
+            Data::Inline(range) => range,
+            Data::External {
+                location,
+                offset,
+                length,
+            } => {
+                let file = external_files.entry(location.clone()).or_insert_with(|| {
+                    // Like onnx itself, only follow locations inside the model's directory
+                    let inside = Path::new(&location)
+                        .components()
+                        .all(|part| matches!(part, Component::Normal(_) | Component::CurDir));
+                    if !inside {
+                        return Err("it is outside the model's directory".to_string());
+                    }
+                    let file = Path::new(&path).with_file_name(&location);
+                    AnyStorage::open(&file, false)
+                        .and_then(|file| segments.push_file(file))
+                        .map_err(|err| err.to_string())
+                });
+                let file = match file {
+                    Ok(file) => file.clone(),
+                    Err(err) => {
+                        problems.push(Problem::tensor(
+                            &init.name,
+                            format!("has its data in {location}, which can't be read: {err}"),
+                        ));
+                        continue;
+                    }
+                };
+                let element_size = ty.element_size().unwrap_or(0) as u64;
+                let length = length.or(numel.and_then(|numel| numel.checked_mul(element_size)));
+                // Files are mapped back to back, so a range past the end of this one would
+                // silently read from the next
+                let file_len = file.end - file.start;
+                match length {
+                    Some(length)
+                        if offset
+                            .checked_add(length)
+                            .is_some_and(|end| end <= file_len) =>
+                    {
+                        file.start + offset..file.start + offset + length
+                    }
+                    _ => {
+                        problems.push(Problem::tensor(
+                            &init.name,
+                            format!("runs past the end of the {file_len} bytes of {location}"),
+                        ));
+                        continue;
+                    }
+                }
+            }
+            Data::Unreadable(reason) => {
+                problems.push(Problem::tensor(&init.name, reason));
+                continue;
+            }
+        };
+        if let TensorTy::Unknown(name) = &ty {
+            problems.push(Problem::tensor(
+                &init.name,
+                format!("has ONNX type {name}, which can't be read"),
+            ));
+        }
+        tensors.push((
+            init.name,
+            TensorInfo {
+                ty,
+                shape: init.dims,
+                size: (range.end - range.start) as usize,
+                offset: range.start,
+            },
+        ));
+    }
+    Ok(Bundle::new(segments, "onnx", tensors, metadata, false).with_problems(problems))
+}
@@ -0,0 +1,231 @@
+use anyhow::{Error, anyhow};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex, OnceLock};
+use weakref::Ref;
+
+use crate::model::{Key, ModuleInfo, ModuleSource, PathSplit, TensorInfo};
+use crate::notify;
+
+/// Per-parameter buffers kept by the common PyTorch optimizers
+const STATE_NAMES: [&str; 6] = [
+    "exp_avg",
+    "exp_avg_sq",
+    "max_exp_avg_sq",
+    "momentum_buffer",
+    "square_avg",
+    "acc_delta",
+];
+
+/// Leading path components put in front of state tensors when an optimizer is saved alongside
+/// the model
+const STATE_PREFIXES: [&[&str]; 3] = [&["optimizer", "state"], &["optimizer_state"], &["state"]];
+
+/// Splits an optimizer state tensor name into its parameter and the kind of state, so
+/// `optimizer.state.model.fc.weight.exp_avg` gives `("model.fc.weight", "exp_avg")`
+pub fn split_state_name(name: &str, split: &PathSplit) -> Option<(String, String)> {
+    let mut parts = split.split(name.into());
+    let prefix = STATE_PREFIXES.iter().find(|prefix| {
+        prefix.len() < parts.len() && prefix.iter().zip(&parts).all(|(a, b)| *a == &**b)
+    });
+    if let Some(prefix) = prefix {
+        parts.drain(..prefix.len());
+    }
+    let at = match parts.iter().position(|part| STATE_NAMES.contains(&&**part)) {
+        Some(at) => at,
+        None if prefix.is_some() && parts.len() > 1 => parts.len() - 1,
+        None => return None,
+    };
+    // The state has to come first or last for the rest to be a single parameter name
+    if at != 0 && at != parts.len() - 1 {
+        return None;
+    }
+    let state = parts.remove(at).to_string();
+    let (first, last) = (parts.first()?, parts.last()?);
+    Some((
+        name[first.range().start..last.range().end].to_string(),
+        state,
+    ))
+}
+
+/// Moves every optimizer state tensor under the parameter it belongs to, returning how many
+/// were moved. States whose parameter isn't in the file (such as PyTorch's numbered
+/// `state.0.exp_avg`) stay where they are.
+pub fn group_optimizer_state(root: &mut ModuleInfo, split: &PathSplit) -> usize {
+    let tensors = root.tensors();
+    let names: HashSet<&str> = tensors.iter().map(|(name, _)| name.as_str()).collect();
+    let mut grouped: BTreeMap<String, Vec<(String, String, TensorInfo)>> = BTreeMap::new();
+    let mut rest = Vec::new();
+    for (name, tensor) in &tensors {
+        match split_state_name(name, split) {
+            Some((param, state)) if names.contains(param.as_str()) => grouped
+                .entry(param)
+                .or_default()
+                .push((state, name.clone(), tensor.clone())),
+            _ => rest.push((name.clone(), tensor.clone())),
+        }
+    }
+    if grouped.is_empty() {
+        return 0;
+    }
+
+    *root = ModuleInfo::build_from_tensors(rest, split);
+    let mut count = 0;
+    for (param, states) in grouped {
+        for (state, name, tensor) in states {
+            let params = tensor.shape.iter().copied().product::<u64>();
+            let bytes = tensor.size as u64;
+            let mut current = &mut *root;
+            for key in split.split(param.clone().into()) {
+                current.total_params += params;
+                current.total_bytes += bytes;
+                current.total_tensors += 1;
+                current = current.children_mut().get_mut(&key).unwrap();
+            }
+            current.total_params += params;
+            current.total_bytes += bytes;
+            current.total_tensors += 1;
+
+            let full: Arc<str> = name.into();
+            let at = full.rfind(&state).unwrap_or(0);
+            let mut child = ModuleInfo::new(Key::new(full.clone(), 0..full.len()));
+            child.tensor_info = Some(tensor);
+            child.total_params = params;
+            child.total_bytes = bytes;
+            child.total_tensors = 1;
+            current
+                .children_mut()
+                .insert(Key::new(full, at..at + state.len()), child);
+            count += 1;
+        }
+    }
+    count
+}
+
+/// The norm of one state buffer next to its parameter's
+#[derive(Debug, Clone)]
+pub struct StateNorm {
+    pub state: String,
+    pub norm: f64,
+    pub rms: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct OptimizerReport {
+    pub weight_norm: f64,
+    pub weight_rms: f64,
+    pub states: Vec<StateNorm>,
+    /// ‖update‖ / ‖W‖ for a learning rate of 1, where the update is m / (√v + ε) for Adam-style
+    /// states or the momentum buffer for SGD
+    pub update_ratio: Option<f64>,
+}
+
+/// Norms of a parameter and its optimizer state, computed in the background
+pub struct OptimizerAnalysis {
+    pub param: TensorInfo,
+    /// The kind of each state (`exp_avg`, ...) with its tensor
+    pub states: Vec<(String, TensorInfo)>,
+    pub progress: AtomicU64,
+    pub report: OnceLock<OptimizerReport>,
+    pub error: OnceLock<Error>,
+}
+
+impl OptimizerAnalysis {
+    pub fn new(param: TensorInfo, states: Vec<(String, TensorInfo)>) -> Self {
+        OptimizerAnalysis {
+            param,
+            states,
+            progress: AtomicU64::new(0),
+            report: OnceLock::new(),
+            error: OnceLock::new(),
+        }
+    }
+}
+
+fn norm(values: &[f32]) -> f64 {
+    values
+        .iter()
+        .map(|&x| x as f64 * x as f64)
+        .sum::<f64>()
+        .sqrt()
+}
+
+fn rms(norm: f64, len: usize) -> f64 {
+    if len == 0 {
+        0.0
+    } else {
+        norm / (len as f64).sqrt()
+    }
+}
+
+const ADAM_EPS: f64 = 1e-8;
+
+fn do_optimizer_analysis(
+    source: &Mutex<dyn ModuleSource + Send>,
+    request: Ref<OptimizerAnalysis>,
+) -> Result<(), Error> {
+    let (param, states) = request
+        .inspect(|req| (req.param.clone(), req.states.clone()))
+        .ok_or(anyhow!("cancelled"))?;
+    let progress = request.map(|req| &req.progress);
+    let weights = source.lock().unwrap().tensor_f32(param, progress)?;
+    let weight_norm = norm(&weights);
+
+    let mut norms = Vec::new();
+    let mut data = BTreeMap::new();
+    for (state, tensor) in states {
+        let values = source.lock().unwrap().tensor_f32(tensor, progress)?;
+        let state_norm = norm(&values);
+        norms.push(StateNorm {
+            rms: rms(state_norm, values.len()),
+            norm: state_norm,
+            state: state.clone(),
+        });
+        data.insert(state, values);
+    }
+
+    let same_len = |values: &&Vec<f32>| values.len() == weights.len();
+    let update_norm = match (
+        data.get("exp_avg").filter(same_len),
+        data.get("exp_avg_sq").filter(same_len),
+        data.get("momentum_buffer").filter(same_len),
+    ) {
+        (Some(m), Some(v), _) => Some(
+            m.iter()
+                .zip(v)
+                .map(|(&m, &v)| {
+                    let u = m as f64 / ((v as f64).max(0.0).sqrt() + ADAM_EPS);
+                    u * u
+                })
+                .sum::<f64>()
+                .sqrt(),
+        ),
+        (_, _, Some(buf)) => Some(norm(buf)),
+        _ => None,
+    };
+
+    let report = OptimizerReport {
+        weight_norm,
+        weight_rms: rms(weight_norm, weights.len()),
+        states: norms,
+        update_ratio: update_norm.map(|u| u / weight_norm),
+    };
+    request
+        .inspect(|req| {
+            let _ = req.report.set(report);
+        })
+        .ok_or(anyhow!("cancelled"))
+}
+
+pub fn start_optimizer_analysis(
+    source: Arc<Mutex<dyn ModuleSource + Send>>,
+    analysis: Ref<OptimizerAnalysis>,
+) {
+    notify::spawn(move || {
+        if let Err(err) = do_optimizer_analysis(&*source, analysis) {
+            analysis.inspect(|a| {
+                let _ = a.error.set(err);
+            });
+        }
+    });
+}
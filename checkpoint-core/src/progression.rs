@@ -0,0 +1,175 @@
+use anyhow::{Error, anyhow};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed};
+use std::sync::{Arc, Mutex, OnceLock};
+use weakref::Ref;
+
+use crate::model::{ModuleSource, TensorInfo};
+use crate::notify;
+
+/// Power iteration stops after this many steps even if the estimate is still moving
+const POWER_ITERATIONS: usize = 100;
+/// Power iteration stops once an estimate changes by less than this fraction
+const POWER_TOLERANCE: f64 = 1e-6;
+
+/// A number computed from the whole of each tensor in a family, to chart against layer index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LayerMetric {
+    #[default]
+    L2Norm,
+    MeanAbs,
+    /// The largest singular value, with every axis after the first flattened into columns
+    SpectralNorm,
+}
+
+impl LayerMetric {
+    pub fn next(self) -> Self {
+        match self {
+            LayerMetric::L2Norm => LayerMetric::MeanAbs,
+            LayerMetric::MeanAbs => LayerMetric::SpectralNorm,
+            LayerMetric::SpectralNorm => LayerMetric::L2Norm,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LayerMetric::L2Norm => "L2 norm",
+            LayerMetric::MeanAbs => "mean |x|",
+            LayerMetric::SpectralNorm => "spectral norm",
+        }
+    }
+}
+
+/// One metric of every tensor in a family like `layers.*.mlp.up_proj.weight`, read in the
+/// background one layer at a time
+pub struct Progression {
+    pub metric: LayerMetric,
+    /// Each tensor with the layer it belongs to, in layer order
+    pub tensors: Vec<(u64, String, TensorInfo)>,
+    /// Tensors read so far
+    pub done: AtomicUsize,
+    /// Percent read of the current tensor
+    pub progress: AtomicU64,
+    /// The metric of each tensor read so far, in the order of `tensors`
+    pub values: Mutex<Vec<f64>>,
+    pub error: OnceLock<Error>,
+}
+
+impl Progression {
+    pub fn new(metric: LayerMetric, mut tensors: Vec<(u64, String, TensorInfo)>) -> Self {
+        tensors.sort_by_key(|(layer, _, _)| *layer);
+        Progression {
+            metric,
+            tensors,
+            done: AtomicUsize::new(0),
+            progress: AtomicU64::new(0),
+            values: Mutex::new(Vec::new()),
+            error: OnceLock::new(),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.done.load(Relaxed) == self.tensors.len() || self.error.get().is_some()
+    }
+}
+
+/// The largest singular value of a row-major `rows × cols` matrix, by power iteration on AᵀA
+fn spectral_norm(data: &[f32], cols: usize) -> f64 {
+    if cols == 0 {
+        return 0.0;
+    }
+    // Uneven so that it is unlikely to be orthogonal to the top singular vector
+    let mut v: Vec<f64> = (0..cols).map(|j| 1.0 + (j % 7) as f64 * 0.1).collect();
+    let mut sigma = 0.0;
+    for _ in 0..POWER_ITERATIONS {
+        let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if norm == 0.0 || !norm.is_finite() {
+            return 0.0;
+        }
+        v.iter_mut().for_each(|x| *x /= norm);
+        let u: Vec<f64> = data
+            .chunks(cols)
+            .map(|row| row.iter().zip(&v).map(|(&a, &b)| a as f64 * b).sum())
+            .collect();
+        let next = u.iter().map(|x| x * x).sum::<f64>().sqrt();
+        let mut w = vec![0.0; cols];
+        for (row, &ui) in data.chunks(cols).zip(&u) {
+            for (w, &a) in w.iter_mut().zip(row) {
+                *w += a as f64 * ui;
+            }
+        }
+        v = w;
+        if (next - sigma).abs() <= POWER_TOLERANCE * next {
+            return next;
+        }
+        sigma = next;
+    }
+    sigma
+}
+
+fn measure(
+    source: &Mutex<dyn ModuleSource + Send>,
+    metric: LayerMetric,
+    tensor: &TensorInfo,
+    progress: Ref<AtomicU64>,
+) -> Result<f64, Error> {
+    if metric == LayerMetric::SpectralNorm {
+        let data = source
+            .lock()
+            .unwrap()
+            .tensor_f32(tensor.clone(), progress)?;
+        let rows = tensor.shape.first().copied().unwrap_or(1).max(1) as usize;
+        return Ok(spectral_norm(&data, data.len() / rows));
+    }
+    let (mut sum, mut count) = (0.0, 0usize);
+    source
+        .lock()
+        .unwrap()
+        .tensor_chunks_f32(tensor.clone(), progress, &mut |chunk| {
+            for &x in chunk.iter().filter(|x| x.is_finite()) {
+                sum += match metric {
+                    LayerMetric::MeanAbs => x.abs() as f64,
+                    _ => (x as f64).powi(2),
+                };
+                count += 1;
+            }
+            Ok(())
+        })?;
+    Ok(match metric {
+        LayerMetric::MeanAbs => sum / count.max(1) as f64,
+        _ => sum.sqrt(),
+    })
+}
+
+fn do_progression(
+    source: &Mutex<dyn ModuleSource + Send>,
+    request: Ref<Progression>,
+) -> Result<(), Error> {
+    let (metric, tensors) = request
+        .inspect(|req| (req.metric, req.tensors.clone()))
+        .ok_or(anyhow!("cancelled"))?;
+    let progress = request.map(|req| &req.progress);
+    for (_, _, tensor) in &tensors {
+        let value = measure(source, metric, tensor, progress)?;
+        request
+            .inspect(|req| {
+                req.values.lock().unwrap().push(value);
+                req.done.fetch_add(1, Relaxed);
+            })
+            .ok_or(anyhow!("cancelled"))?;
+        notify::notify();
+    }
+    Ok(())
+}
+
+pub fn start_progression(
+    source: Arc<Mutex<dyn ModuleSource + Send>>,
+    progression: Ref<Progression>,
+) {
+    notify::spawn(move || {
+        if let Err(err) = do_progression(&*source, progression) {
+            progression.inspect(|p| {
+                let _ = p.error.set(err);
+            });
+        }
+    });
+}
@@ -0,0 +1,168 @@
+//! Just enough of the protobuf wire format to walk the messages of ONNX and Core ML models
+//! without generated code. Fields are listed with their payloads left in the file, so tensor
+//! data is never read while parsing.
+
+use anyhow::{Result, bail};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+
+/// Payloads up to this size are read and dropped rather than seeked over, which would throw
+/// away the reader's buffer
+const SKIP_READ_LIMIT: u64 = 4096;
+
+/// The value of one protobuf field, with length-delimited payloads left unread
+#[derive(Clone)]
+pub(crate) enum Wire {
+    Varint(u64),
+    /// A fixed 32 or 64 bit value, which ONNX never uses for the fields read here
+    Fixed,
+    Bytes(Range<u64>),
+}
+
+pub(crate) fn read_varint(reader: &mut (impl Read + ?Sized)) -> Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    bail!("protobuf varint is too long")
+}
+
+/// Lists the fields of the message in `range` by number, skipping over their payloads
+pub(crate) fn read_fields(
+    reader: &mut (impl Read + Seek + ?Sized),
+    range: Range<u64>,
+) -> Result<Vec<(u64, Wire)>> {
+    reader.seek(SeekFrom::Start(range.start))?;
+    let mut fields = Vec::new();
+    let mut at = range.start;
+    while at < range.end {
+        let key = read_varint(reader)?;
+        let value = match key & 7 {
+            0 => Wire::Varint(read_varint(reader)?),
+            1 => {
+                reader.read_exact(&mut [0; 8])?;
+                Wire::Fixed
+            }
+            2 => {
+                let len = read_varint(reader)?;
+                let start = reader.stream_position()?;
+                let Some(end) = start.checked_add(len).filter(|&end| end <= range.end) else {
+                    bail!("protobuf message is truncated");
+                };
+                if len <= SKIP_READ_LIMIT {
+                    io::copy(&mut (&mut *reader).take(len), &mut io::sink())?;
+                } else {
+                    reader.seek(SeekFrom::Start(end))?;
+                }
+                Wire::Bytes(start..end)
+            }
+            5 => {
+                reader.read_exact(&mut [0; 4])?;
+                Wire::Fixed
+            }
+            other => bail!("unsupported protobuf wire type {other}"),
+        };
+        fields.push((key >> 3, value));
+        at = reader.stream_position()?;
+    }
+    if at != range.end {
+        bail!("protobuf message runs past its end");
+    }
+    Ok(fields)
+}
+
+pub(crate) fn read_string(
+    reader: &mut (impl Read + Seek + ?Sized),
+    range: &Range<u64>,
+) -> Result<String> {
+    reader.seek(SeekFrom::Start(range.start))?;
+    let mut bytes = Vec::new();
+    (&mut *reader)
+        .take(range.end - range.start)
+        .read_to_end(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Reads the values of a repeated varint field, whether packed or not
+pub(crate) fn read_varints(
+    reader: &mut (impl Read + Seek + ?Sized),
+    fields: &[(u64, Wire)],
+    number: u64,
+) -> Result<Vec<u64>> {
+    let mut values = Vec::new();
+    for (_, value) in fields.iter().filter(|(n, _)| *n == number) {
+        match value {
+            Wire::Varint(value) => values.push(*value),
+            Wire::Bytes(range) => {
+                reader.seek(SeekFrom::Start(range.start))?;
+                while reader.stream_position()? < range.end {
+                    values.push(read_varint(reader)?);
+                }
+            }
+            Wire::Fixed => bail!("field {number} is not a varint"),
+        }
+    }
+    Ok(values)
+}
+
+/// Key-value pairs from repeated `StringStringEntryProto` fields, or a `map<string, string>`
+pub(crate) fn read_entries(
+    reader: &mut (impl Read + Seek + ?Sized),
+    fields: &[(u64, Wire)],
+    number: u64,
+) -> Result<Vec<(String, String)>> {
+    let mut entries = Vec::new();
+    for (_, value) in fields.iter().filter(|(n, _)| *n == number) {
+        let Wire::Bytes(range) = value else {
+            bail!("field {number} is not a message");
+        };
+        let (mut key, mut text) = (String::new(), String::new());
+        for (n, value) in read_fields(reader, range.clone())? {
+            match (n, value) {
+                (1, Wire::Bytes(range)) => key = read_string(reader, &range)?,
+                (2, Wire::Bytes(range)) => text = read_string(reader, &range)?,
+                _ => {}
+            }
+        }
+        entries.push((key, text));
+    }
+    Ok(entries)
+}
+
+/// Entries of a `map<string, Message>`, with each message left unread
+pub(crate) fn read_map(
+    reader: &mut (impl Read + Seek + ?Sized),
+    fields: &[(u64, Wire)],
+    number: u64,
+) -> Result<Vec<(String, Range<u64>)>> {
+    let mut entries = Vec::new();
+    for (_, value) in fields.iter().filter(|(n, _)| *n == number) {
+        let Wire::Bytes(range) = value else {
+            bail!("field {number} is not a message");
+        };
+        let mut key = String::new();
+        let mut message = 0..0;
+        for (n, value) in read_fields(reader, range.clone())? {
+            match (n, value) {
+                (1, Wire::Bytes(range)) => key = read_string(reader, &range)?,
+                (2, Wire::Bytes(range)) => message = range,
+                _ => {}
+            }
+        }
+        entries.push((key, message));
+    }
+    Ok(entries)
+}
+
+/// Payloads of every length-delimited field numbered `number`
+pub(crate) fn messages(fields: &[(u64, Wire)], number: u64) -> impl Iterator<Item = Range<u64>> {
+    fields.iter().filter_map(move |(n, value)| match value {
+        Wire::Bytes(range) if *n == number => Some(range.clone()),
+        _ => None,
+    })
+}
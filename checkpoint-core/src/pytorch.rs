@@ -0,0 +1,758 @@
+//! Reads `torch.save` checkpoints, which are zip archives holding a pickled object tree in
+//! `data.pkl` and one stored member per tensor storage. Only enough of the pickle machine is
+//! implemented to rebuild dicts, lists, and tensors; any other object is kept as an opaque
+//! value, and nothing is ever imported or executed.
+
+use crate::bundle::Bundle;
+use crate::integrity::Problem;
+use crate::model::{TensorInfo, TensorTy};
+use crate::storage::Storage;
+use anyhow::{Result, anyhow, bail};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// How deeply nested containers are followed when flattening, in case of reference cycles
+const MAX_DEPTH: usize = 64;
+
+/// Opens a `.pt`, `.pth`, `.bin`, or Lightning `.ckpt` file. Tensors are named by their path
+/// through nested dicts and lists joined with `/`, so a Lightning checkpoint has
+/// `state_dict/model.fc.weight`, and every other leaf becomes metadata.
+pub fn open<S: Storage>(mut storage: S) -> Result<Bundle<S>> {
+    let pickle = TorchPickle::read(&mut storage)?;
+    let Flattened {
+        tensors,
+        metadata,
+        problems,
+    } = pickle.flatten();
+    Ok(Bundle::new(storage, "pytorch", tensors, metadata, true).with_problems(problems))
+}
+
+/// The tensors of a checkpoint, separated from the rest of its contents
+pub(crate) struct Flattened {
+    pub tensors: Vec<(String, TensorInfo)>,
+    pub metadata: Map<String, Value>,
+    pub problems: Vec<Problem>,
+}
+
+/// One value in the unpickled object tree, with children referred to by their index so
+/// that shared and memoized values stay shared
+#[derive(Debug, Clone)]
+enum Node {
+    None,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Bytes(usize),
+    List(Vec<usize>),
+    Tuple(Vec<usize>),
+    Dict(Vec<(usize, usize)>),
+    Global(String),
+    /// A tensor storage from a persistent id, named by its key in the archive
+    Storage {
+        key: String,
+        ty: TensorTy,
+    },
+    Tensor {
+        storage: usize,
+        offset: u64,
+        shape: Vec<u64>,
+        stride: Vec<u64>,
+    },
+    /// Any other object, as the callable which would build it and its arguments
+    Object {
+        callable: usize,
+        args: usize,
+        state: Option<usize>,
+    },
+}
+
+/// The unpickled contents of a `torch.save` archive, plus where each storage's bytes are
+pub(crate) struct TorchPickle {
+    nodes: Vec<Node>,
+    root: usize,
+    /// Byte range of each storage member within the file
+    storages: HashMap<String, Range<u64>>,
+}
+
+impl TorchPickle {
+    pub(crate) fn read(storage: &mut impl Storage) -> Result<Self> {
+        let path = storage.display();
+        let mut pickle = None;
+        let mut byteorder = None;
+        let mut storages = HashMap::new();
+        {
+            let mut zip = zip::ZipArchive::new(&mut *storage.reader()?).map_err(|_| {
+                anyhow!("{path} is not a zip archive, so it may have been saved with _use_new_zipfile_serialization=False, which is not supported")
+            })?;
+            for i in 0..zip.len() {
+                let entry = zip.by_index_raw(i)?;
+                let name = entry.name().to_string();
+                let range = entry.data_start()..entry.data_start() + entry.size();
+                if name.ends_with("/data.pkl") || name == "data.pkl" {
+                    if entry.compression() != zip::CompressionMethod::Stored {
+                        bail!("{name} is compressed inside {path}");
+                    }
+                    pickle = Some(range);
+                } else if let Some((_, key)) = name.rsplit_once("/data/") {
+                    if entry.compression() != zip::CompressionMethod::Stored {
+                        bail!("tensor data {name} is compressed inside {path}");
+                    }
+                    storages.insert(key.to_string(), range);
+                } else if name.ends_with("/byteorder")
+                    && entry.compression() == zip::CompressionMethod::Stored
+                {
+                    byteorder = Some(range);
+                }
+            }
+        }
+        if let Some(range) = byteorder {
+            let bytes = storage.read_range(range.start, (range.end - range.start) as usize)?;
+            if bytes.trim_ascii() != b"little" {
+                bail!("{path} was saved on a big-endian machine, which is not supported");
+            }
+        }
+        let Some(range) = pickle else {
+            bail!("{path} has no data.pkl, so it is not a PyTorch checkpoint");
+        };
+        let bytes = storage.read_range(range.start, (range.end - range.start) as usize)?;
+        let mut machine = Machine::default();
+        let root = machine.run(&bytes)?;
+        Ok(TorchPickle {
+            nodes: machine.nodes,
+            root,
+            storages,
+        })
+    }
+
+    pub(crate) fn root(&self) -> usize {
+        self.root
+    }
+
+    /// The entries of a dict, in order, with keys as strings. Objects built from a dict, like
+    /// `argparse.Namespace`, give the entries of their state.
+    pub(crate) fn items(&self, node: usize) -> Vec<(String, usize)> {
+        match &self.nodes[node] {
+            Node::Dict(items) => items
+                .iter()
+                .map(|&(key, value)| (self.key_string(key), value))
+                .collect(),
+            Node::Object {
+                state: Some(state), ..
+            } => self.items(*state),
+            _ => Vec::new(),
+        }
+    }
+
+    pub(crate) fn get(&self, node: usize, key: &str) -> Option<usize> {
+        self.items(node)
+            .into_iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+
+    pub(crate) fn elements(&self, node: usize) -> &[usize] {
+        match &self.nodes[node] {
+            Node::List(items) | Node::Tuple(items) => items,
+            _ => &[],
+        }
+    }
+
+    pub(crate) fn int(&self, node: usize) -> Option<i64> {
+        match self.nodes[node] {
+            Node::Int(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// A `torch.Size` or other sequence of dimensions
+    pub(crate) fn shape(&self, node: usize) -> Option<Vec<u64>> {
+        self.elements(node)
+            .iter()
+            .map(|&dim| self.int(dim).and_then(|dim| u64::try_from(dim).ok()))
+            .collect()
+    }
+
+    fn key_string(&self, key: usize) -> String {
+        match &self.nodes[key] {
+            Node::Str(key) => key.clone(),
+            Node::Int(key) => key.to_string(),
+            _ => self.to_json(key, 0).to_string(),
+        }
+    }
+
+    /// Where a tensor's bytes are, if it is a contiguous view of a known storage
+    pub(crate) fn tensor(&self, node: usize) -> Result<TensorInfo> {
+        let Node::Tensor {
+            storage,
+            offset,
+            shape,
+            stride,
+        } = &self.nodes[node]
+        else {
+            bail!("not a tensor");
+        };
+        let Node::Storage { key, ty } = &self.nodes[*storage] else {
+            bail!("has no storage");
+        };
+        let range = self
+            .storages
+            .get(key)
+            .ok_or_else(|| anyhow!("storage {key} is missing from the archive"))?;
+        let Some(element_size) = ty.element_size() else {
+            bail!("has an unsupported storage type {ty}");
+        };
+        let too_large = || anyhow!("is too large to fit in storage {key}");
+        let mut expected: u64 = 1;
+        let mut contiguous = true;
+        for (&dim, &step) in shape.iter().zip(stride).rev() {
+            if dim > 1 && step != expected {
+                contiguous = false;
+            }
+            expected = expected.checked_mul(dim).ok_or_else(too_large)?;
+        }
+        if !contiguous {
+            bail!("is a non-contiguous view of its storage, which can't be read");
+        }
+        let numel = shape
+            .iter()
+            .try_fold(1u64, |acc, &dim| acc.checked_mul(dim))
+            .ok_or_else(too_large)?;
+        let start = offset
+            .checked_mul(element_size as u64)
+            .and_then(|offset| range.start.checked_add(offset))
+            .ok_or_else(too_large)?;
+        let size = numel
+            .checked_mul(element_size as u64)
+            .ok_or_else(too_large)?;
+        if start.checked_add(size).is_none_or(|end| end > range.end) {
+            bail!("runs past the end of storage {key}");
+        }
+        Ok(TensorInfo {
+            ty: ty.clone(),
+            shape: shape.clone(),
+            size: size as usize,
+            offset: start,
+        })
+    }
+
+    fn contains_tensor(&self, node: usize, depth: usize) -> bool {
+        if depth > MAX_DEPTH {
+            return false;
+        }
+        match &self.nodes[node] {
+            Node::Tensor { .. } => true,
+            Node::List(items) | Node::Tuple(items) => items
+                .iter()
+                .any(|&item| self.contains_tensor(item, depth + 1)),
+            Node::Dict(items) => items
+                .iter()
+                .any(|&(_, value)| self.contains_tensor(value, depth + 1)),
+            Node::Object {
+                state: Some(state), ..
+            } => self.contains_tensor(*state, depth + 1),
+            _ => false,
+        }
+    }
+
+    fn to_json(&self, node: usize, depth: usize) -> Value {
+        if depth > MAX_DEPTH {
+            return Value::Null;
+        }
+        match &self.nodes[node] {
+            Node::None => Value::Null,
+            Node::Bool(value) => (*value).into(),
+            Node::Int(value) => (*value).into(),
+            Node::Float(value) => (*value).into(),
+            Node::Str(value) => value.clone().into(),
+            Node::Bytes(len) => format!("<{len} bytes>").into(),
+            Node::List(items) | Node::Tuple(items) => items
+                .iter()
+                .map(|&item| self.to_json(item, depth + 1))
+                .collect(),
+            Node::Dict(items) => {
+                let mut map = Map::new();
+                for &(key, value) in items {
+                    map.insert(self.key_string(key), self.to_json(value, depth + 1));
+                }
+                map.into()
+            }
+            Node::Global(name) => name.clone().into(),
+            Node::Storage { key, ty } => format!("<{ty} storage {key}>").into(),
+            Node::Tensor { shape, .. } => format!("<tensor {shape:?}>").into(),
+            Node::Object {
+                state: Some(state), ..
+            } => self.to_json(*state, depth + 1),
+            Node::Object { callable, args, .. } => {
+                let args = self.to_json(*args, depth + 1);
+                format!("{}{args}", self.key_string(*callable)).into()
+            }
+        }
+    }
+
+    /// Splits the tree into tensors and everything else, which becomes metadata
+    pub(crate) fn flatten(&self) -> Flattened {
+        let mut tensors = Vec::new();
+        let mut metadata = Map::new();
+        let mut problems = Vec::new();
+        let mut stack = vec![(String::new(), self.root, 0)];
+        while let Some((path, node, depth)) = stack.pop() {
+            let children: Vec<(String, usize)> = match &self.nodes[node] {
+                Node::Tensor { .. } => {
+                    let name = match path.as_str() {
+                        "" => "tensor".to_string(),
+                        _ => path,
+                    };
+                    match self.tensor(node) {
+                        Ok(tensor) => tensors.push((name, tensor)),
+                        Err(err) => problems.push(Problem::tensor(&name, err.to_string())),
+                    }
+                    continue;
+                }
+                // A top-level dict is always spread out, so its entries become separate metadata
+                Node::Dict(_) if path.is_empty() => self.items(node),
+                _ if depth > MAX_DEPTH || !self.contains_tensor(node, 0) => {
+                    let key = match path.as_str() {
+                        "" => "value".to_string(),
+                        _ => path,
+                    };
+                    metadata.insert(key, self.to_json(node, 0));
+                    continue;
+                }
+                Node::List(items) | Node::Tuple(items) => items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &item)| (i.to_string(), item))
+                    .collect(),
+                _ => self.items(node),
+            };
+            for (key, child) in children.into_iter().rev() {
+                let child_path = match path.as_str() {
+                    "" => key,
+                    _ => format!("{path}/{key}"),
+                };
+                stack.push((child_path, child, depth + 1));
+            }
+        }
+        // Tensors were found depth-first, which is already the order they were saved in
+        Flattened {
+            tensors,
+            metadata,
+            problems,
+        }
+    }
+}
+
+/// The tensor type stored by a legacy storage class like `torch.FloatStorage`
+fn storage_ty(class: &str) -> TensorTy {
+    use TensorTy::*;
+    match class.rsplit('.').next().unwrap_or(class) {
+        "FloatStorage" => F32,
+        "DoubleStorage" => F64,
+        "HalfStorage" => F16,
+        "BFloat16Storage" => BF16,
+        "LongStorage" => I64,
+        "IntStorage" => I32,
+        "ShortStorage" => I16,
+        "CharStorage" => I8,
+        "ByteStorage" => U8,
+        "BoolStorage" => BOOL,
+        "Float8_e4m3fnStorage" => F8_E4M3,
+        "Float8_e5m2Storage" => F8_E5M2,
+        other => Unknown(other.to_string()),
+    }
+}
+
+/// The pickle virtual machine, restricted to building [`Node`]s
+#[derive(Default)]
+struct Machine {
+    nodes: Vec<Node>,
+    stack: Vec<usize>,
+    /// Stack lengths saved by MARK
+    marks: Vec<usize>,
+    memo: HashMap<u32, usize>,
+}
+
+struct Input<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Input<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.pos..)
+            .and_then(|rest| rest.get(..n))
+            .ok_or_else(|| anyhow!("pickle is truncated"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn uint(&mut self, n: usize) -> Result<u64> {
+        let mut value = [0; 8];
+        value[..n].copy_from_slice(self.take(n)?);
+        Ok(u64::from_le_bytes(value))
+    }
+
+    fn line(&mut self) -> Result<&'a str> {
+        let rest = &self.bytes[self.pos.min(self.bytes.len())..];
+        let len = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| anyhow!("pickle is truncated"))?;
+        self.pos += len + 1;
+        Ok(std::str::from_utf8(&rest[..len])?)
+    }
+}
+
+impl Machine {
+    fn push(&mut self, node: Node) {
+        self.nodes.push(node);
+        self.stack.push(self.nodes.len() - 1);
+    }
+
+    fn pop(&mut self) -> Result<usize> {
+        self.stack
+            .pop()
+            .ok_or_else(|| anyhow!("pickle stack underflow"))
+    }
+
+    fn pop_mark(&mut self) -> Result<Vec<usize>> {
+        let mark = self
+            .marks
+            .pop()
+            .ok_or_else(|| anyhow!("pickle has no mark"))?;
+        if mark > self.stack.len() {
+            bail!("pickle stack underflow");
+        }
+        Ok(self.stack.split_off(mark))
+    }
+
+    fn top(&self) -> Result<usize> {
+        self.stack
+            .last()
+            .copied()
+            .ok_or_else(|| anyhow!("pickle stack underflow"))
+    }
+
+    fn global_name(&self, node: usize) -> &str {
+        match &self.nodes[node] {
+            Node::Global(name) => name,
+            _ => "",
+        }
+    }
+
+    fn extend_dict(&mut self, dict: usize, values: Vec<usize>) -> Result<()> {
+        let pairs = values.chunks_exact(2).map(|pair| (pair[0], pair[1]));
+        match &mut self.nodes[dict] {
+            Node::Dict(items) => items.extend(pairs),
+            // Dict subclasses such as AttributeDict are kept as plain dicts
+            node @ Node::Object { .. } => *node = Node::Dict(pairs.collect()),
+            _ => bail!("pickle sets items on something that is not a dict"),
+        }
+        Ok(())
+    }
+
+    fn extend_list(&mut self, list: usize, values: Vec<usize>) -> Result<()> {
+        match &mut self.nodes[list] {
+            Node::List(items) => items.extend(values),
+            node @ Node::Object { .. } => *node = Node::List(values),
+            _ => bail!("pickle appends to something that is not a list"),
+        }
+        Ok(())
+    }
+
+    /// Calls a global with a tuple of arguments, for the few that matter to checkpoints
+    fn call(&mut self, callable: usize, args: usize) -> Result<Node> {
+        let arg_list = match &self.nodes[args] {
+            Node::Tuple(items) => items.clone(),
+            _ => Vec::new(),
+        };
+        let int = |nodes: &[Node], i: Option<&usize>| match i.map(|&i| &nodes[i]) {
+            Some(&Node::Int(value)) if value >= 0 => Ok(value as u64),
+            _ => bail!("tensor has an invalid offset"),
+        };
+        let dims = |nodes: &[Node], i: Option<&usize>| -> Result<Vec<u64>> {
+            match i.map(|&i| &nodes[i]) {
+                Some(Node::Tuple(dims) | Node::List(dims)) => {
+                    dims.iter().map(|&dim| int(nodes, Some(&dim))).collect()
+                }
+                _ => bail!("tensor has an invalid shape"),
+            }
+        };
+        Ok(match self.global_name(callable) {
+            "torch._utils._rebuild_tensor" | "torch._utils._rebuild_tensor_v2" => Node::Tensor {
+                storage: *arg_list
+                    .first()
+                    .ok_or_else(|| anyhow!("tensor has no storage"))?,
+                offset: int(&self.nodes, arg_list.get(1))?,
+                shape: dims(&self.nodes, arg_list.get(2))?,
+                stride: dims(&self.nodes, arg_list.get(3))?,
+            },
+            "torch._utils._rebuild_parameter" | "torch._utils._rebuild_parameter_with_state" => {
+                match arg_list.first() {
+                    Some(&data) => self.nodes[data].clone(),
+                    None => bail!("parameter has no data"),
+                }
+            }
+            "collections.OrderedDict" | "builtins.dict" | "__builtin__.dict" => {
+                Node::Dict(Vec::new())
+            }
+            "torch.Size" | "builtins.list" | "builtins.tuple" | "builtins.set"
+            | "builtins.frozenset" | "__builtin__.set" => match arg_list.first() {
+                Some(&items) => match &self.nodes[items] {
+                    Node::List(items) | Node::Tuple(items) => Node::Tuple(items.clone()),
+                    _ => Node::Tuple(Vec::new()),
+                },
+                None => Node::Tuple(Vec::new()),
+            },
+            "torch.device" => match arg_list.first().map(|&i| &self.nodes[i]) {
+                Some(Node::Str(device)) => Node::Str(device.clone()),
+                _ => Node::Str("device".into()),
+            },
+            _ => Node::Object {
+                callable,
+                args,
+                state: None,
+            },
+        })
+    }
+
+    fn run(&mut self, bytes: &[u8]) -> Result<usize> {
+        let mut input = Input { bytes, pos: 0 };
+        loop {
+            let op = input.take(1)?[0];
+            match op {
+                // PROTO, FRAME
+                0x80 => {
+                    input.take(1)?;
+                }
+                0x95 => {
+                    input.take(8)?;
+                }
+                // STOP
+                b'.' => return self.pop(),
+                b'(' => self.marks.push(self.stack.len()),
+                b'0' => {
+                    self.pop()?;
+                }
+                b'1' => {
+                    self.pop_mark()?;
+                }
+                b'2' => self.stack.push(self.top()?),
+                b'N' => self.push(Node::None),
+                0x88 => self.push(Node::Bool(true)),
+                0x89 => self.push(Node::Bool(false)),
+                b'J' => {
+                    let value = input.uint(4)? as u32 as i32;
+                    self.push(Node::Int(value as i64));
+                }
+                b'K' => {
+                    let value = input.uint(1)?;
+                    self.push(Node::Int(value as i64));
+                }
+                b'M' => {
+                    let value = input.uint(2)?;
+                    self.push(Node::Int(value as i64));
+                }
+                // LONG1, LONG4: little-endian two's complement of the given length
+                0x8a | 0x8b => {
+                    let len = input.uint(if op == 0x8a { 1 } else { 4 })? as usize;
+                    let digits = input.take(len)?;
+                    if len > 8 {
+                        self.push(Node::Str(format!("<{}-byte integer>", len)));
+                    } else {
+                        let mut value = [0; 8];
+                        value[..len].copy_from_slice(digits);
+                        let shift = 64 - 8 * len as u32;
+                        let value = match len {
+                            0 => 0,
+                            _ => (i64::from_le_bytes(value) << shift) >> shift,
+                        };
+                        self.push(Node::Int(value));
+                    }
+                }
+                // INT, LONG
+                b'I' | b'L' => {
+                    let text = input.line()?.trim_end_matches('L');
+                    self.push(match text {
+                        "01" => Node::Bool(true),
+                        "00" => Node::Bool(false),
+                        _ => Node::Int(text.parse()?),
+                    });
+                }
+                b'G' => {
+                    let value = f64::from_be_bytes(input.take(8)?.try_into()?);
+                    self.push(Node::Float(value));
+                }
+                b'F' => {
+                    let value = input.line()?.parse()?;
+                    self.push(Node::Float(value));
+                }
+                // BINUNICODE, SHORT_BINUNICODE, BINUNICODE8, and the old byte strings
+                b'X' | 0x8c | 0x8d | b'T' | b'U' => {
+                    let len = match op {
+                        0x8c | b'U' => input.uint(1)?,
+                        0x8d => input.uint(8)?,
+                        _ => input.uint(4)?,
+                    } as usize;
+                    let text = String::from_utf8_lossy(input.take(len)?).into_owned();
+                    self.push(Node::Str(text));
+                }
+                // BINBYTES, SHORT_BINBYTES, BINBYTES8, BYTEARRAY8
+                b'B' | b'C' | 0x8e | 0x96 => {
+                    let len = match op {
+                        b'C' => input.uint(1)?,
+                        b'B' => input.uint(4)?,
+                        _ => input.uint(8)?,
+                    } as usize;
+                    input.take(len)?;
+                    self.push(Node::Bytes(len));
+                }
+                b'V' | b'S' => {
+                    let text = input.line()?.to_string();
+                    self.push(Node::Str(text));
+                }
+                b'}' => self.push(Node::Dict(Vec::new())),
+                b']' => self.push(Node::List(Vec::new())),
+                b')' => self.push(Node::Tuple(Vec::new())),
+                0x8f => self.push(Node::List(Vec::new())),
+                b't' => {
+                    let items = self.pop_mark()?;
+                    self.push(Node::Tuple(items));
+                }
+                0x85..=0x87 => {
+                    let len = (op - 0x84) as usize;
+                    if self.stack.len() < len {
+                        bail!("pickle stack underflow");
+                    }
+                    let items = self.stack.split_off(self.stack.len() - len);
+                    self.push(Node::Tuple(items));
+                }
+                b'l' => {
+                    let items = self.pop_mark()?;
+                    self.push(Node::List(items));
+                }
+                b'd' => {
+                    let items = self.pop_mark()?;
+                    self.push(Node::Dict(Vec::new()));
+                    let dict = self.top()?;
+                    self.extend_dict(dict, items)?;
+                }
+                0x91 => {
+                    let items = self.pop_mark()?;
+                    self.push(Node::List(items));
+                }
+                b'a' => {
+                    let item = self.pop()?;
+                    let list = self.top()?;
+                    self.extend_list(list, vec![item])?;
+                }
+                b'e' | 0x90 => {
+                    let items = self.pop_mark()?;
+                    let list = self.top()?;
+                    self.extend_list(list, items)?;
+                }
+                b's' => {
+                    let value = self.pop()?;
+                    let key = self.pop()?;
+                    let dict = self.top()?;
+                    self.extend_dict(dict, vec![key, value])?;
+                }
+                b'u' => {
+                    let items = self.pop_mark()?;
+                    let dict = self.top()?;
+                    self.extend_dict(dict, items)?;
+                }
+                // PUT, BINPUT, LONG_BINPUT, MEMOIZE
+                b'p' | b'q' | b'r' | 0x94 => {
+                    let key = match op {
+                        b'p' => input.line()?.parse()?,
+                        b'q' => input.uint(1)? as u32,
+                        b'r' => input.uint(4)? as u32,
+                        _ => self.memo.len() as u32,
+                    };
+                    let top = self.top()?;
+                    self.memo.insert(key, top);
+                }
+                // GET, BINGET, LONG_BINGET
+                b'g' | b'h' | b'j' => {
+                    let key: u32 = match op {
+                        b'g' => input.line()?.parse()?,
+                        b'h' => input.uint(1)? as u32,
+                        _ => input.uint(4)? as u32,
+                    };
+                    let node = *self
+                        .memo
+                        .get(&key)
+                        .ok_or_else(|| anyhow!("pickle memo {key} is missing"))?;
+                    self.stack.push(node);
+                }
+                b'c' => {
+                    let module = input.line()?;
+                    let name = input.line()?;
+                    self.push(Node::Global(format!("{module}.{name}")));
+                }
+                0x93 => {
+                    let name = self.pop()?;
+                    let module = self.pop()?;
+                    let text = |node: usize| match &self.nodes[node] {
+                        Node::Str(text) => text.clone(),
+                        _ => String::new(),
+                    };
+                    let global = format!("{}.{}", text(module), text(name));
+                    self.push(Node::Global(global));
+                }
+                // REDUCE, NEWOBJ
+                b'R' | 0x81 => {
+                    let args = self.pop()?;
+                    let callable = self.pop()?;
+                    let node = self.call(callable, args)?;
+                    self.push(node);
+                }
+                // NEWOBJ_EX
+                0x92 => {
+                    self.pop()?;
+                    let args = self.pop()?;
+                    let callable = self.pop()?;
+                    let node = self.call(callable, args)?;
+                    self.push(node);
+                }
+                b'b' => {
+                    let state = self.pop()?;
+                    let target = self.top()?;
+                    let items = match &self.nodes[state] {
+                        Node::Dict(items) => items.clone(),
+                        _ => Vec::new(),
+                    };
+                    match &mut self.nodes[target] {
+                        Node::Object { state: slot, .. } => *slot = Some(state),
+                        Node::Dict(dict) => dict.extend(items),
+                        _ => {}
+                    }
+                }
+                // BINPERSID: torch saves ('storage', storage_type, key, location, numel)
+                b'Q' => {
+                    let pid = self.pop()?;
+                    let parts = match &self.nodes[pid] {
+                        Node::Tuple(parts) => parts.clone(),
+                        _ => Vec::new(),
+                    };
+                    let key = parts.get(2).map(|&key| match &self.nodes[key] {
+                        Node::Str(key) => key.clone(),
+                        Node::Int(key) => key.to_string(),
+                        _ => String::new(),
+                    });
+                    let (Some(&class), Some(key)) = (parts.get(1), key) else {
+                        bail!("pickle has an unsupported persistent id");
+                    };
+                    let ty = storage_ty(self.global_name(class));
+                    self.push(Node::Storage { key, ty });
+                }
+                op => bail!("unsupported pickle opcode 0x{op:02x}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,508 @@
+use anyhow::{Error, bail};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::coreml;
+use crate::deepspeed;
+use crate::flax;
+use crate::gguf::Gguf;
+use crate::model::{ModuleSource, PathSplit};
+use crate::npz;
+use crate::onnx;
+use crate::pytorch;
+use crate::safetensors::Safetensors;
+use crate::storage::{AnyStorage, Storage};
+use crate::tensorrt;
+
+/// Matches the limit in `safetensors::read_metadata`, to avoid mistaking other files
+const HEADER_MAX_BYTES: u64 = 100 * 1024 * 1024;
+
+/// How many bytes from the start of the file are passed to [`SourceFormat::probe`]
+pub const PROBE_BYTES: usize = 64;
+
+/// How many levels of subdirectories [`SourceRegistry::scan_directory`] looks through
+const SCAN_DEPTH: usize = 3;
+
+/// Suffix of the JSON file listing which shard holds each tensor of a sharded checkpoint
+const INDEX_SUFFIX: &str = ".index.json";
+
+/// A checkpoint found by [`SourceRegistry::scan_directory`]
+pub struct FoundFile {
+    pub path: PathBuf,
+    /// Name of the format, or `sharded` for an index of shards
+    pub format: &'static str,
+    /// Size on disk, summed over the shards of a sharded checkpoint
+    pub bytes: u64,
+    /// How many tensors the checkpoint has, or why it couldn't be read
+    pub tensors: Result<u64, String>,
+}
+
+/// A checkpoint format which can be opened as a [`ModuleSource`]
+pub trait SourceFormat: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// File extensions, without the dot, which are assumed to be this format
+    fn extensions(&self) -> &'static [&'static str];
+    /// Checks the first [`PROBE_BYTES`] of a file (or fewer, if it is short)
+    fn probe(&self, header: &[u8]) -> bool;
+    fn open(&self, storage: AnyStorage) -> Result<Arc<Mutex<dyn ModuleSource + Send>>, Error>;
+}
+
+/// The formats available when opening a file, tried in the order they were registered
+pub struct SourceRegistry {
+    formats: Vec<Box<dyn SourceFormat>>,
+    /// Passed on to every opened [`crate::storage::FileStorage`]
+    pub backup: bool,
+}
+
+impl Default for SourceRegistry {
+    fn default() -> Self {
+        let mut registry = SourceRegistry::empty();
+        registry.register(SafetensorsFormat);
+        registry.register(GgufFormat);
+        registry.register(NpzFormat);
+        registry.register(FlaxFormat);
+        registry.register(Hdf5Format);
+        registry.register(PytorchFormat);
+        registry.register(OnnxFormat);
+        registry.register(TensorrtFormat);
+        registry.register(CoremlFormat);
+        registry
+    }
+}
+
+impl SourceRegistry {
+    pub fn empty() -> Self {
+        SourceRegistry {
+            formats: Vec::new(),
+            backup: false,
+        }
+    }
+
+    pub fn register(&mut self, format: impl SourceFormat + 'static) {
+        self.formats.push(Box::new(format));
+    }
+
+    pub fn formats(&self) -> impl Iterator<Item = &dyn SourceFormat> {
+        self.formats.iter().map(|format| &**format)
+    }
+
+    pub fn by_name(&self, name: &str) -> Result<&dyn SourceFormat, Error> {
+        match self.formats().find(|format| format.name() == name) {
+            Some(format) => Ok(format),
+            None => {
+                let names: Vec<_> = self.formats().map(|format| format.name()).collect();
+                bail!(
+                    "unknown format {name:?} (expected one of {})",
+                    names.join(", ")
+                )
+            }
+        }
+    }
+
+    /// Picks a format by probing the file contents, falling back to the extension
+    pub fn detect(
+        &self,
+        storage: &mut impl Storage,
+        path: &Path,
+    ) -> Result<&dyn SourceFormat, Error> {
+        let mut header = Vec::with_capacity(PROBE_BYTES);
+        let reader = storage.reader()?;
+        (&mut *reader)
+            .take(PROBE_BYTES as u64)
+            .read_to_end(&mut header)?;
+        reader.seek(SeekFrom::Start(0))?;
+        if let Some(format) = self.formats().find(|format| format.probe(&header)) {
+            return Ok(format);
+        }
+        let ext = path.extension().and_then(|ext| ext.to_str());
+        match self
+            .formats()
+            .find(|format| ext.is_some_and(|ext| format.extensions().contains(&ext)))
+        {
+            Some(format) => Ok(format),
+            None => bail!("could not infer file type of {}", path.display()),
+        }
+    }
+
+    /// Lists every checkpoint under `dir` with its size and tensor count, reading each header
+    pub fn scan_directory(&self, dir: &Path, split: &PathSplit) -> Result<Vec<FoundFile>, Error> {
+        let mut found = Vec::new();
+        self.scan_into(dir, split, SCAN_DEPTH, &mut found)?;
+        found.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(found)
+    }
+
+    fn scan_into(
+        &self,
+        dir: &Path,
+        split: &PathSplit,
+        depth: usize,
+        found: &mut Vec<FoundFile>,
+    ) -> Result<(), Error> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') {
+                continue;
+            }
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                if let Some(format) = directory_format(&path) {
+                    found.push(read_checkpoint_dir(self, path, format, split));
+                } else if depth > 0 {
+                    // Unreadable subdirectories shouldn't hide everything else
+                    let _ = self.scan_into(&path, split, depth - 1, found);
+                }
+                continue;
+            }
+            if name.ends_with(INDEX_SUFFIX) {
+                found.extend(read_shard_index(&path));
+                continue;
+            }
+            let ext = path.extension().and_then(|ext| ext.to_str());
+            let Some(format) = self
+                .formats()
+                .find(|format| ext.is_some_and(|ext| format.extensions().contains(&ext)))
+            else {
+                continue;
+            };
+            let tensors = self
+                .open(&path, Some(format.name()))
+                .and_then(|source| source.lock().unwrap().module(split))
+                .map(|module| module.total_tensors)
+                .map_err(|err| err.to_string());
+            found.push(FoundFile {
+                format: format.name(),
+                bytes: entry.metadata()?.len(),
+                path,
+                tensors,
+            });
+        }
+        Ok(())
+    }
+
+    /// Opens the file as the named format, or detects it if `format` is `None`
+    pub fn open(
+        &self,
+        path: &Path,
+        format: Option<&str>,
+    ) -> Result<Arc<Mutex<dyn ModuleSource + Send>>, Error> {
+        if path.is_dir() {
+            return match (directory_format(path), format) {
+                (Some("coreml"), None | Some("coreml")) => {
+                    Ok(Arc::new(Mutex::new(coreml::open_package(path)?)))
+                }
+                (Some("deepspeed"), None | Some("deepspeed")) => {
+                    Ok(Arc::new(Mutex::new(deepspeed::open(path)?)))
+                }
+                (_, Some(name)) => bail!("{} is a directory, not a {name} file", path.display()),
+                (_, None) => bail!("{} is not a checkpoint directory", path.display()),
+            };
+        }
+        let mut storage = AnyStorage::open(path, self.backup)?;
+        let format = match format {
+            Some(name) => self.by_name(name)?,
+            None => self.detect(&mut storage, path)?,
+        };
+        format.open(storage)
+    }
+}
+
+/// The format of a directory holding one checkpoint, which opens as a whole rather than being
+/// browsed: a DeepSpeed checkpoint or a Core ML `.mlpackage`
+pub fn directory_format(dir: &Path) -> Option<&'static str> {
+    if coreml::is_package(dir) {
+        Some("coreml")
+    } else if deepspeed::checkpoint_dir(dir).is_some() {
+        Some("deepspeed")
+    } else {
+        None
+    }
+}
+
+/// Total size of the files under `dir`
+fn directory_bytes(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => directory_bytes(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Lists a checkpoint directory as one entry, since its files only make sense together
+fn read_checkpoint_dir(
+    registry: &SourceRegistry,
+    path: PathBuf,
+    format: &'static str,
+    split: &PathSplit,
+) -> FoundFile {
+    let bytes = directory_bytes(&path);
+    let tensors = registry
+        .open(&path, Some(format))
+        .and_then(|source| source.lock().unwrap().module(split))
+        .map(|module| module.total_tensors)
+        .map_err(|err| err.to_string());
+    FoundFile {
+        path,
+        format,
+        bytes,
+        tensors,
+    }
+}
+
+/// Reads a `model.safetensors.index.json` as written by Hugging Face, or `None` if it isn't one
+fn read_shard_index(path: &Path) -> Option<FoundFile> {
+    let index: Value = serde_json::from_slice(&fs::read(path).ok()?).ok()?;
+    let weight_map = index.get("weight_map")?.as_object()?;
+    let shards: BTreeSet<&str> = weight_map.values().filter_map(Value::as_str).collect();
+    let dir = path.parent()?;
+    let bytes = shards
+        .iter()
+        .filter_map(|shard| fs::metadata(dir.join(shard)).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    Some(FoundFile {
+        path: path.to_path_buf(),
+        format: "sharded",
+        bytes,
+        tensors: Ok(weight_map.len() as u64),
+    })
+}
+
+struct SafetensorsFormat;
+
+impl SourceFormat for SafetensorsFormat {
+    fn name(&self) -> &'static str {
+        "safetensors"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["safetensors"]
+    }
+
+    fn probe(&self, header: &[u8]) -> bool {
+        // A little-endian header length, then the JSON header itself
+        let Some((len, rest)) = header.split_first_chunk::<8>() else {
+            return false;
+        };
+        let len = u64::from_le_bytes(*len);
+        (2..=HEADER_MAX_BYTES).contains(&len) && rest.first() == Some(&b'{')
+    }
+
+    fn open(&self, storage: AnyStorage) -> Result<Arc<Mutex<dyn ModuleSource + Send>>, Error> {
+        Ok(Arc::new(Mutex::new(Safetensors::open(storage)?)))
+    }
+}
+
+struct GgufFormat;
+
+impl SourceFormat for GgufFormat {
+    fn name(&self) -> &'static str {
+        "gguf"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["gguf"]
+    }
+
+    fn probe(&self, header: &[u8]) -> bool {
+        header.starts_with(b"GGUF")
+    }
+
+    fn open(&self, storage: AnyStorage) -> Result<Arc<Mutex<dyn ModuleSource + Send>>, Error> {
+        Ok(Arc::new(Mutex::new(Gguf::open(storage)?)))
+    }
+}
+
+struct NpzFormat;
+
+impl SourceFormat for NpzFormat {
+    fn name(&self) -> &'static str {
+        "npz"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["npz"]
+    }
+
+    fn probe(&self, header: &[u8]) -> bool {
+        // A zip whose first member, named after the fixed 30 bytes of its local header, is
+        // an .npy array. Long names are cut off by the probe, so those fall back on the extension.
+        let Some(name_len) = header.get(26..28) else {
+            return false;
+        };
+        let name_len = u16::from_le_bytes([name_len[0], name_len[1]]) as usize;
+        header.starts_with(b"PK\x03\x04")
+            && header
+                .get(30..30 + name_len)
+                .is_some_and(|name| name.ends_with(b".npy"))
+    }
+
+    fn open(&self, storage: AnyStorage) -> Result<Arc<Mutex<dyn ModuleSource + Send>>, Error> {
+        Ok(Arc::new(Mutex::new(npz::open(storage)?)))
+    }
+}
+
+struct FlaxFormat;
+
+impl SourceFormat for FlaxFormat {
+    fn name(&self) -> &'static str {
+        "flax"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["msgpack"]
+    }
+
+    fn probe(&self, header: &[u8]) -> bool {
+        // A msgpack map whose first key is a string
+        let key = match header.first() {
+            Some(0x80..=0x8f) => header.get(1),
+            Some(0xde) => header.get(3),
+            Some(0xdf) => header.get(5),
+            _ => None,
+        };
+        matches!(key, Some(0xa0..=0xbf | 0xd9..=0xdb))
+    }
+
+    fn open(&self, storage: AnyStorage) -> Result<Arc<Mutex<dyn ModuleSource + Send>>, Error> {
+        Ok(Arc::new(Mutex::new(flax::open(storage)?)))
+    }
+}
+
+/// Registered even without the `hdf5` feature, so that `.h5` files get a clear error
+struct Hdf5Format;
+
+impl SourceFormat for Hdf5Format {
+    fn name(&self) -> &'static str {
+        "hdf5"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["h5", "hdf5"]
+    }
+
+    fn probe(&self, header: &[u8]) -> bool {
+        header.starts_with(b"\x89HDF\r\n\x1a\n")
+    }
+
+    #[cfg(feature = "hdf5")]
+    fn open(&self, storage: AnyStorage) -> Result<Arc<Mutex<dyn ModuleSource + Send>>, Error> {
+        Ok(Arc::new(Mutex::new(crate::hdf5::open(storage)?)))
+    }
+
+    #[cfg(not(feature = "hdf5"))]
+    fn open(&self, storage: AnyStorage) -> Result<Arc<Mutex<dyn ModuleSource + Send>>, Error> {
+        bail!(
+            "can't open {}: checkpointui was built without the hdf5 feature",
+            storage.display()
+        )
+    }
+}
+
+/// Checkpoints written by `torch.save`, including Lightning `.ckpt` files
+struct PytorchFormat;
+
+impl SourceFormat for PytorchFormat {
+    fn name(&self) -> &'static str {
+        "pytorch"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["pt", "pth", "bin", "ckpt"]
+    }
+
+    fn probe(&self, header: &[u8]) -> bool {
+        // torch.save writes a zip archive
+        header.starts_with(b"PK\x03\x04")
+    }
+
+    fn open(&self, storage: AnyStorage) -> Result<Arc<Mutex<dyn ModuleSource + Send>>, Error> {
+        Ok(Arc::new(Mutex::new(pytorch::open(storage)?)))
+    }
+}
+
+/// ONNX models, with any external data files next to them
+struct OnnxFormat;
+
+impl SourceFormat for OnnxFormat {
+    fn name(&self) -> &'static str {
+        "onnx"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["onnx"]
+    }
+
+    fn probe(&self, header: &[u8]) -> bool {
+        // ModelProto starts with a small ir_version, then usually a short producer_name. A Core
+        // ML spec starts the same way but follows with a message rather than text.
+        match header {
+            [0x08, 1..=32, 0x12, 1..=0x7f, first, ..] => first.is_ascii_graphic(),
+            [0x08, 1..=32, 0x1a | 0x22 | 0x28 | 0x32 | 0x3a | 0x42, ..] => true,
+            _ => false,
+        }
+    }
+
+    fn open(&self, storage: AnyStorage) -> Result<Arc<Mutex<dyn ModuleSource + Send>>, Error> {
+        Ok(Arc::new(Mutex::new(onnx::open(storage)?)))
+    }
+}
+
+/// Serialized TensorRT engines, which show only their size
+struct TensorrtFormat;
+
+impl SourceFormat for TensorrtFormat {
+    fn name(&self) -> &'static str {
+        "tensorrt"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["engine", "plan", "trt"]
+    }
+
+    fn probe(&self, header: &[u8]) -> bool {
+        header.starts_with(tensorrt::ENGINE_MAGIC)
+    }
+
+    fn open(&self, storage: AnyStorage) -> Result<Arc<Mutex<dyn ModuleSource + Send>>, Error> {
+        Ok(Arc::new(Mutex::new(tensorrt::open(storage)?)))
+    }
+}
+
+/// Core ML `.mlmodel` specs, and the `weight.bin` files inside `.mlpackage` directories
+struct CoremlFormat;
+
+impl SourceFormat for CoremlFormat {
+    fn name(&self) -> &'static str {
+        "coreml"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["mlmodel"]
+    }
+
+    fn probe(&self, header: &[u8]) -> bool {
+        // Specs look too much like other protobufs, so only weight files are probed for
+        coreml::is_weight_file(header)
+    }
+
+    fn open(&self, storage: AnyStorage) -> Result<Arc<Mutex<dyn ModuleSource + Send>>, Error> {
+        let header = storage.read_range(0, PROBE_BYTES).unwrap_or_default();
+        if coreml::is_weight_file(&header) {
+            Ok(Arc::new(Mutex::new(coreml::open_weights(storage)?)))
+        } else {
+            Ok(Arc::new(Mutex::new(coreml::open_model(storage)?)))
+        }
+    }
+}
@@ -0,0 +1,508 @@
+use crate::integrity::{Problem, Region, check_regions};
+use crate::model::{LE, MetadataType, ModuleInfo, ModuleSource, PathSplit, TensorInfo, TensorTy};
+use crate::storage::{Storage, read_with_progress};
+use anyhow::{Error, Result, bail};
+use safetensors::{SafeTensorError, tensor::Metadata};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::sync::atomic::AtomicU64;
+use weakref::{Own, Ref};
+
+/// Headers which have to grow are padded to a multiple of this, which also keeps the tensor
+/// data aligned
+const HEADER_PADDING: usize = 4096;
+
+pub struct Safetensors<S> {
+    storage: S,
+    data_offset: u64,
+    header: Header,
+}
+
+/// The header as written, without the checks `Metadata` makes on the way in, so a file with
+/// gaps, overlaps, or missized tensors still opens for `check` to describe. `Metadata` is only
+/// built to write a new header.
+#[derive(Deserialize)]
+struct Header {
+    #[serde(rename = "__metadata__")]
+    metadata: Option<HashMap<String, String>>,
+    #[serde(flatten)]
+    tensors: HashMap<String, safetensors::tensor::TensorInfo>,
+}
+
+impl Header {
+    fn new(
+        metadata: Option<HashMap<String, String>>,
+        tensors: Vec<(String, safetensors::tensor::TensorInfo)>,
+    ) -> Self {
+        Header {
+            metadata,
+            tensors: tensors.into_iter().collect(),
+        }
+    }
+}
+
+impl<S: Storage> Safetensors<S> {
+    pub fn open(mut storage: S) -> Result<Self> {
+        let path = storage.display();
+        let (header, data_offset) = read_metadata(storage.reader()?, &path)?;
+        let data_offset = data_offset as u64;
+        Ok(Safetensors {
+            storage,
+            data_offset,
+            header,
+        })
+    }
+
+    fn tensor_bytes(
+        &mut self,
+        start: u64,
+        nbytes: usize,
+        progress: Ref<AtomicU64>,
+    ) -> Result<Vec<u8>> {
+        read_with_progress(&self.storage, start + self.data_offset, nbytes, progress)
+    }
+}
+
+impl<S: Storage> Safetensors<S> {
+    fn sorted_tensors(&self) -> Vec<(String, safetensors::tensor::TensorInfo)> {
+        let mut tensors: Vec<_> = self
+            .header
+            .tensors
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        // the safetensors crate needlessly scrambles the order
+        tensors.sort_by_key(|(_, info)| info.data_offsets);
+        tensors
+    }
+
+    fn write_header(
+        &mut self,
+        metadata: Option<HashMap<String, String>>,
+        tensors: Vec<(String, safetensors::tensor::TensorInfo)>,
+    ) -> Result<()> {
+        self.storage.check_writable()?;
+        let mut new_header = encode_header(&Metadata::new(metadata.clone(), tensors.clone())?)?;
+        let old_len = self.data_offset as usize;
+        if new_header.len() > old_len {
+            // Leave room so the next few edits can be made in place
+            let padded_len = new_header.len().next_multiple_of(HEADER_PADDING);
+            pad_header(&mut new_header, padded_len);
+            self.storage.splice(0..old_len, &new_header)?;
+            self.data_offset = new_header.len() as u64;
+        } else {
+            // The format allows spaces after the JSON, so a header that fits can be padded out
+            // and written over the old one, leaving gigabytes of tensor data where they are
+            pad_header(&mut new_header, old_len);
+            self.storage.overwrite(0, &new_header)?;
+        }
+        self.header = Header::new(metadata, tensors);
+        Ok(())
+    }
+}
+
+/// Builds the header for a new file whose tensors are laid out back-to-back in the given order
+pub fn new_header(
+    metadata: Option<HashMap<String, String>>,
+    tensors: &[(String, TensorTy, Vec<u64>)],
+) -> Result<Vec<u8>> {
+    let mut offset = 0;
+    let mut infos = Vec::with_capacity(tensors.len());
+    for (name, ty, shape) in tensors {
+        let dtype = safetensors::Dtype::try_from(ty)?;
+        let shape: Vec<usize> = shape.iter().map(|&x| x as usize).collect();
+        let size = shape.iter().product::<usize>() * dtype.bitsize() / 8;
+        infos.push((
+            name.clone(),
+            safetensors::tensor::TensorInfo {
+                dtype,
+                shape,
+                data_offsets: (offset, offset + size),
+            },
+        ));
+        offset += size;
+    }
+    encode_header(&Metadata::new(metadata, infos)?)
+}
+
+/// Serializes the header, including its 8-byte length prefix
+fn encode_header(metadata: &Metadata) -> Result<Vec<u8>> {
+    let mut header = serde_json::ser::to_vec(metadata)?;
+    let n = header.len() as u64;
+    header.splice(0..0, u64::to_le_bytes(n));
+    Ok(header)
+}
+
+/// Pads an encoded header with spaces to `len` bytes, including the length prefix
+fn pad_header(header: &mut Vec<u8>, len: usize) {
+    header.resize(len, b' ');
+    header[..8].copy_from_slice(&u64::to_le_bytes(len as u64 - 8));
+}
+
+unsafe impl<I: Storage> Send for Safetensors<I> where I: Send {}
+
+/// Tools like kohya-ss store JSON objects and arrays inside metadata strings
+fn parse_json_blob(text: &str) -> Option<Value> {
+    let trimmed = text.trim_start();
+    if !trimmed.starts_with(['{', '[']) {
+        return None;
+    }
+    serde_json::from_str(text).ok()
+}
+
+/// Re-encodes a JSON blob, reusing the original text when nothing inside it changed so that
+/// formatting and key order survive
+fn encode_json_blob(original: Option<&String>, value: &Value) -> Result<String> {
+    match original {
+        Some(text) if parse_json_blob(text).as_ref() == Some(value) => Ok(text.clone()),
+        _ => Ok(serde_json::to_string(value)?),
+    }
+}
+
+fn flatten_value(path: String, value: &Value, map: &mut HashMap<String, String>) {
+    match value {
+        Value::Null => {
+            map.insert(path, "null".into());
+        }
+        Value::Bool(true) => {
+            map.insert(path, "true".into());
+        }
+        Value::Bool(false) => {
+            map.insert(path, "false".into());
+        }
+        Value::Number(number) => {
+            map.insert(path, number.to_string());
+        }
+        Value::String(string) => {
+            map.insert(path, string.clone());
+        }
+        Value::Array(array) => {
+            for (k, v) in array.iter().enumerate() {
+                let k = if path.is_empty() {
+                    k.to_string()
+                } else {
+                    format!("{path}.{k}")
+                };
+                flatten_value(k, v, map);
+            }
+        }
+        Value::Object(object) => {
+            for (k, v) in object.iter() {
+                let k = if path.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{path}.{k}")
+                };
+                flatten_value(k, v, map);
+            }
+        }
+    }
+}
+
+impl<S: Storage> ModuleSource for Safetensors<S> {
+    fn module(&mut self, split: &PathSplit) -> Result<ModuleInfo> {
+        Ok(ModuleInfo::build_from_tensors(
+            self.header
+                .tensors
+                .iter()
+                .map(|(name, info)| (name.clone(), info.into())),
+            split,
+        ))
+    }
+
+    fn metadata(&mut self) -> Result<Value> {
+        let mut map = serde_json::value::Map::new();
+        if let Some(meta) = &self.header.metadata {
+            for (k, v) in meta {
+                let v = parse_json_blob(v).unwrap_or_else(|| v.as_str().into());
+                map.insert(k.clone(), v);
+            }
+        }
+        Ok(map.into())
+    }
+
+    fn metadata_array(&mut self, key: &str, _range: Range<usize>) -> Result<Vec<Value>> {
+        bail!("{key} is not an array")
+    }
+
+    fn string_metadata(&mut self) -> Result<HashMap<String, String>> {
+        Ok(self.header.metadata.clone().unwrap_or_default())
+    }
+
+    fn metadata_types(&mut self) -> Result<HashMap<String, MetadataType>> {
+        // The format only has string values, whatever JSON they hold
+        let keys = self.header.metadata.iter().flatten();
+        Ok(keys
+            .map(|(k, _)| (k.clone(), MetadataType::String))
+            .collect())
+    }
+
+    fn write_metadata(&mut self, metadata: &Value) -> std::result::Result<(), Error> {
+        let original = self.header.metadata.clone().unwrap_or_default();
+        let mut new_metadata = HashMap::new();
+        match metadata {
+            Value::Object(map) => {
+                for (k, v) in map {
+                    if v.is_object() || v.is_array() {
+                        new_metadata.insert(k.clone(), encode_json_blob(original.get(k), v)?);
+                    } else {
+                        flatten_value(k.clone(), v, &mut new_metadata);
+                    }
+                }
+            }
+            other => flatten_value("".into(), other, &mut new_metadata),
+        }
+        let tensors = self.sorted_tensors();
+        self.write_header(Some(new_metadata), tensors)
+    }
+
+    fn rename_tensors(&mut self, renames: &[(String, String)]) -> std::result::Result<(), Error> {
+        let renames: HashMap<&str, &str> = renames
+            .iter()
+            .map(|(old, new)| (old.as_str(), new.as_str()))
+            .collect();
+        let mut tensors = self.sorted_tensors();
+        for (name, _) in &mut tensors {
+            if let Some(new) = renames.get(name.as_str()) {
+                *name = new.to_string();
+            }
+        }
+        let mut seen = HashSet::new();
+        for (name, _) in &tensors {
+            if name.is_empty() || name == "__metadata__" {
+                bail!("{name:?} is not a valid tensor name");
+            }
+            if !seen.insert(name) {
+                bail!("multiple tensors would be named {name}");
+            }
+        }
+        let metadata = self.header.metadata.clone();
+        self.write_header(metadata, tensors)
+    }
+
+    fn delete_tensors(&mut self, names: &[String]) -> std::result::Result<(), Error> {
+        self.storage.check_writable()?;
+        let names: HashSet<&str> = names.iter().map(String::as_str).collect();
+
+        // Pack the remaining tensors together, preserving their order in the file
+        let mut ranges = Vec::new();
+        let mut new_len = 0;
+        let mut tensors = Vec::new();
+        for (name, mut info) in self.sorted_tensors() {
+            if names.contains(name.as_str()) {
+                continue;
+            }
+            let (start, end) = info.data_offsets;
+            if end < start {
+                bail!("tensor {name} ends before it starts");
+            }
+            ranges.push(self.data_offset + start as u64..self.data_offset + end as u64);
+            info.data_offsets = (new_len, new_len + end - start);
+            new_len += end - start;
+            tensors.push((name, info));
+        }
+
+        let metadata = self.header.metadata.clone();
+        let header = encode_header(&Metadata::new(metadata.clone(), tensors.clone())?)?;
+        // The storage checks every range is in the file before copying any of them
+        self.storage.rebuild(&header, &ranges)?;
+        self.data_offset = header.len() as u64;
+        self.header = Header::new(metadata, tensors);
+        Ok(())
+    }
+
+    fn tensor_data(
+        &mut self,
+        tensor: TensorInfo,
+        progress: Ref<AtomicU64>,
+    ) -> std::result::Result<Vec<u8>, Error> {
+        self.tensor_bytes(tensor.offset, tensor.size, progress)
+    }
+
+    fn tensor_byte_range(
+        &mut self,
+        tensor: &TensorInfo,
+        range: Range<usize>,
+    ) -> std::result::Result<Vec<u8>, Error> {
+        let progress = Own::new_box(AtomicU64::new(0));
+        self.tensor_bytes(
+            tensor.offset + range.start as u64,
+            range.len(),
+            progress.refer(),
+        )
+    }
+
+    fn tensor_f32(
+        &mut self,
+        tensor: TensorInfo,
+        progress: Ref<AtomicU64>,
+    ) -> std::result::Result<Vec<f32>, Error> {
+        tensor.read_f32::<LE>(&self.tensor_bytes(tensor.offset, tensor.size as usize, progress)?)
+    }
+
+    fn tensor_f64(
+        &mut self,
+        tensor: TensorInfo,
+        progress: Ref<AtomicU64>,
+    ) -> std::result::Result<Vec<f64>, Error> {
+        tensor.read_f64::<LE>(&self.tensor_bytes(tensor.offset, tensor.size as usize, progress)?)
+    }
+
+    fn check(&mut self) -> Result<Vec<Problem>> {
+        let file_len = self.storage.reader()?.seek(SeekFrom::End(0))?;
+        let Some(data_len) = file_len.checked_sub(self.data_offset) else {
+            return Ok(vec![Problem::file(format!(
+                "the header says it takes {} bytes, but the file only has {file_len}",
+                self.data_offset
+            ))]);
+        };
+        let regions = self
+            .header
+            .tensors
+            .iter()
+            .map(|(name, info)| {
+                let (start, end) = info.data_offsets;
+                let expected_size = info
+                    .shape
+                    .iter()
+                    .try_fold(info.dtype.bitsize(), |size, &dim| size.checked_mul(dim))
+                    .map(|bits| bits.div_ceil(8) as u64);
+                Region {
+                    name: name.clone(),
+                    bytes: start as u64..end as u64,
+                    expected_size,
+                }
+            })
+            .collect();
+        Ok(check_regions(regions, data_len, 1))
+    }
+
+    fn tensor_chunks_f32(
+        &mut self,
+        tensor: TensorInfo,
+        progress: Ref<AtomicU64>,
+        visit: &mut dyn FnMut(&[f32]) -> std::result::Result<(), Error>,
+    ) -> std::result::Result<(), Error> {
+        let offset = tensor.offset + self.data_offset;
+        tensor.read_chunks_f32::<LE>(&self.storage, offset, progress, visit)
+    }
+}
+
+impl From<safetensors::Dtype> for TensorTy {
+    fn from(value: safetensors::Dtype) -> Self {
+        use TensorTy::*;
+        use safetensors::Dtype;
+        match value {
+            Dtype::BOOL => BOOL,
+            Dtype::U8 => U8,
+            Dtype::I8 => I8,
+            Dtype::F8_E5M2 => F8_E5M2,
+            Dtype::F8_E4M3 => F8_E4M3,
+            Dtype::I16 => I16,
+            Dtype::U16 => U16,
+            Dtype::F16 => F16,
+            Dtype::BF16 => BF16,
+            Dtype::F32 => F32,
+            Dtype::F64 => F64,
+            Dtype::I64 => I64,
+            Dtype::U64 => U64,
+            _ => Unknown(format!("{:?}", value)),
+        }
+    }
+}
+
+impl TryFrom<&'_ TensorTy> for safetensors::Dtype {
+    type Error = Error;
+
+    fn try_from(value: &TensorTy) -> Result<Self> {
+        use TensorTy::*;
+        use safetensors::Dtype;
+        Ok(match value {
+            BOOL => Dtype::BOOL,
+            U8 => Dtype::U8,
+            I8 => Dtype::I8,
+            F8_E5M2 => Dtype::F8_E5M2,
+            F8_E4M3 => Dtype::F8_E4M3,
+            I16 => Dtype::I16,
+            U16 => Dtype::U16,
+            I32 => Dtype::I32,
+            U32 => Dtype::U32,
+            F16 => Dtype::F16,
+            BF16 => Dtype::BF16,
+            F32 => Dtype::F32,
+            F64 => Dtype::F64,
+            I64 => Dtype::I64,
+            U64 => Dtype::U64,
+            other => bail!("{other} tensors cannot be stored in safetensors files"),
+        })
+    }
+}
+
+impl From<&'_ safetensors::tensor::TensorInfo> for TensorInfo {
+    fn from(value: &'_ safetensors::tensor::TensorInfo) -> TensorInfo {
+        TensorInfo {
+            ty: value.dtype.into(),
+            shape: value.shape.iter().map(|&x| x as u64).collect(),
+            size: value.data_offsets.1.saturating_sub(value.data_offsets.0),
+            offset: value.data_offsets.0 as u64,
+        }
+    }
+}
+
+const HEADER_MIB_LIMIT: usize = 100;
+
+fn read_metadata<I: Read + ?Sized>(io: &mut I, path: &str) -> Result<(Header, usize), Error> {
+    let mut header_size_bytes = [0u8; 8];
+    io.read_exact(&mut header_size_bytes)?;
+    let n = u64::from_le_bytes(header_size_bytes) as usize;
+
+    if n > HEADER_MIB_LIMIT * 1024 * 1024 {
+        bail!("Header is larger than {HEADER_MIB_LIMIT}MiB. Is {path} a safetensors file?",);
+    }
+
+    let mut metadata_bytes = vec![0u8; n];
+    io.read_exact(&mut metadata_bytes)?;
+
+    let metadata_str =
+        std::str::from_utf8(&metadata_bytes).map_err(|err| SafeTensorError::InvalidHeader(err))?;
+
+    let metadata: Header = serde_json::from_str(metadata_str)
+        .map_err(|err| SafeTensorError::InvalidHeaderDeserialization(err))?;
+
+    Ok((metadata, n + 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FileStorage;
+    use std::fs;
+
+    #[test]
+    fn check_reports_gaps_and_overlaps() {
+        // `b` leaves a gap after `a`, and `c` starts inside `b`
+        let header = br#"{"a":{"dtype":"F32","shape":[1],"data_offsets":[0,4]},"b":{"dtype":"F32","shape":[1],"data_offsets":[8,12]},"c":{"dtype":"F32","shape":[1],"data_offsets":[10,14]}}"#;
+        let mut bytes = (header.len() as u64).to_le_bytes().to_vec();
+        bytes.extend_from_slice(header);
+        bytes.extend_from_slice(&[0; 14]);
+        let path = std::env::temp_dir().join(format!("gaps.{}.safetensors", std::process::id()));
+        fs::write(&path, bytes).unwrap();
+
+        let problems =
+            Safetensors::open(FileStorage::new(path.clone())).and_then(|mut file| file.check());
+        fs::remove_file(&path).unwrap();
+        let problems = problems.unwrap();
+        let messages: Vec<_> = problems.iter().map(ToString::to_string).collect();
+        assert!(
+            messages.iter().any(|m| m.starts_with("b: follows a gap")),
+            "{messages:?}"
+        );
+        assert!(
+            messages.iter().any(|m| m.starts_with("c: overlaps b")),
+            "{messages:?}"
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use anyhow::{Error, anyhow, bail};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed};
+use std::sync::{Arc, Mutex, OnceLock};
+use weakref::{Ref, pin};
+
+use crate::model::{ModuleSource, TensorInfo};
+use crate::notify;
+
+/// At most this many values are held in memory at once, so comparing every layer of a large
+/// model reads some tensors more than once instead
+const SIMILARITY_BUDGET: u64 = 1 << 28;
+
+/// Pairwise cosine similarity between the flattened weights of several tensors, computed in
+/// the background
+pub struct Similarity {
+    pub tensors: Vec<(String, TensorInfo)>,
+    /// Tensors read so far, counting rereads
+    pub done: AtomicUsize,
+    /// How many tensor reads the whole comparison takes
+    pub total: usize,
+    /// Percent read of the current tensor
+    pub progress: AtomicU64,
+    /// Row-major `n × n` matrix, with NaN for pairs whose sizes differ
+    pub matrix: OnceLock<Vec<f32>>,
+    pub error: OnceLock<Error>,
+}
+
+/// Splits `tensors` into runs which fit in the memory budget together
+fn blocks(tensors: &[(String, TensorInfo)]) -> Vec<std::ops::Range<usize>> {
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    let mut used = 0;
+    for (i, (_, tensor)) in tensors.iter().enumerate() {
+        let numel = tensor.shape.iter().product::<u64>();
+        if i > start && used + numel > SIMILARITY_BUDGET / 2 {
+            blocks.push(start..i);
+            start = i;
+            used = 0;
+        }
+        used += numel;
+    }
+    if start < tensors.len() {
+        blocks.push(start..tensors.len());
+    }
+    blocks
+}
+
+impl Similarity {
+    pub fn new(tensors: Vec<(String, TensorInfo)>) -> Self {
+        // Each block is read once, then compared against every tensor after it
+        let total = blocks(&tensors)
+            .into_iter()
+            .map(|block| tensors.len() - block.start)
+            .sum();
+        Similarity {
+            tensors,
+            done: AtomicUsize::new(0),
+            total,
+            progress: AtomicU64::new(0),
+            matrix: OnceLock::new(),
+            error: OnceLock::new(),
+        }
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> Option<f32> {
+        let n = self.tensors.len();
+        let value = *self.matrix.get()?.get(i * n + j)?;
+        (!value.is_nan()).then_some(value)
+    }
+
+    /// The distinct pairs, most similar first
+    pub fn ranked_pairs(&self) -> Vec<(usize, usize, f32)> {
+        let n = self.tensors.len();
+        let mut pairs: Vec<_> = (0..n)
+            .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+            .filter_map(|(i, j)| Some((i, j, self.get(i, j)?)))
+            .collect();
+        pairs.sort_by(|a, b| b.2.total_cmp(&a.2));
+        pairs
+    }
+}
+
+fn cosine(a: &[f32], a_norm: f64, b: &[f32], b_norm: f64) -> f32 {
+    if a.len() != b.len() {
+        return f32::NAN;
+    }
+    if a_norm == 0.0 || b_norm == 0.0 {
+        return 0.0;
+    }
+    let dot: f64 = a.iter().zip(b).map(|(&x, &y)| x as f64 * y as f64).sum();
+    (dot / (a_norm * b_norm)) as f32
+}
+
+fn norm(data: &[f32]) -> f64 {
+    data.iter().map(|&x| (x as f64).powi(2)).sum::<f64>().sqrt()
+}
+
+fn do_similarity(
+    source: &Mutex<dyn ModuleSource + Send>,
+    request: Ref<Similarity>,
+) -> Result<(), Error> {
+    let tensors = request
+        .inspect(|req| req.tensors.clone())
+        .ok_or(anyhow!("cancelled"))?;
+    if tensors.len() < 2 {
+        bail!("need at least two tensors to compare");
+    }
+    let progress = request.map(|req| &req.progress);
+    let read = |tensor: &TensorInfo| -> Result<Vec<f32>, Error> {
+        let data = source
+            .lock()
+            .unwrap()
+            .tensor_f32(tensor.clone(), progress)?;
+        request
+            .inspect(|req| req.done.fetch_add(1, Relaxed))
+            .ok_or(anyhow!("cancelled"))?;
+        notify::notify();
+        Ok(data)
+    };
+
+    let n = tensors.len();
+    let mut matrix = vec![f32::NAN; n * n];
+    for block in blocks(&tensors) {
+        let loaded = tensors[block.clone()]
+            .iter()
+            .map(|(_, tensor)| {
+                let data = read(tensor)?;
+                let norm = norm(&data);
+                Ok((data, norm))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        for (a, (a_data, a_norm)) in block.clone().zip(&loaded) {
+            for (b, (b_data, b_norm)) in block.clone().zip(&loaded).skip(a - block.start) {
+                let value = cosine(a_data, *a_norm, b_data, *b_norm);
+                matrix[a * n + b] = value;
+                matrix[b * n + a] = value;
+            }
+        }
+        for b in block.end..n {
+            let b_data = read(&tensors[b].1)?;
+            let b_norm = norm(&b_data);
+            for (a, (a_data, a_norm)) in block.clone().zip(&loaded) {
+                let value = cosine(a_data, *a_norm, &b_data, b_norm);
+                matrix[a * n + b] = value;
+                matrix[b * n + a] = value;
+            }
+        }
+    }
+    {
+        let _ = request
+            .map(|req| &req.matrix)
+            .get(&pin())
+            .ok_or(anyhow!("cancelled"))?
+            .set(matrix);
+    }
+    Ok(())
+}
+
+pub fn start_similarity(source: Arc<Mutex<dyn ModuleSource + Send>>, similarity: Ref<Similarity>) {
+    notify::spawn(move || {
+        if let Err(err) = do_similarity(&*source, similarity) {
+            similarity.inspect(|s| {
+                let _ = s.error.set(err);
+            });
+        }
+    });
+}
@@ -0,0 +1,229 @@
+use anyhow::{Error, anyhow, bail};
+use std::fmt;
+use std::ops::Range;
+
+use crate::model::{LE, ModuleSource, TensorInfo, TensorTy};
+
+/// Part of a tensor chosen with NumPy-style indexing, like `3, :` for a row or `:, 10:20` for
+/// a run of columns
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TensorSlice {
+    /// The range taken along each axis of the tensor
+    pub ranges: Vec<Range<u64>>,
+    /// Axes given a single index, which are dropped from the slice's shape
+    pub indexed: Vec<bool>,
+}
+
+impl TensorSlice {
+    /// Parses comma-separated indices (`i`, `a:b`, `a:`, `:b`, or `:`) for the leading axes of
+    /// `shape`, where negative numbers count from the end and missing axes are taken whole
+    pub fn parse(spec: &str, shape: &[u64]) -> Result<Self, Error> {
+        let spec = spec.trim();
+        let spec = spec
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .unwrap_or(spec);
+        let parts: Vec<&str> = spec.split(',').map(str::trim).collect();
+        if parts.len() > shape.len() {
+            bail!(
+                "{} indices given for a tensor with {} axes",
+                parts.len(),
+                shape.len()
+            );
+        }
+        let mut slice = TensorSlice::whole(shape);
+        for (axis, part) in parts.into_iter().enumerate() {
+            let len = shape[axis];
+            let index = |text: &str, default: u64| -> Result<u64, Error> {
+                if text.is_empty() {
+                    return Ok(default);
+                }
+                let i: i64 = text
+                    .parse()
+                    .map_err(|_| anyhow!("{text:?} is not an index"))?;
+                let i = if i < 0 { len as i64 + i } else { i };
+                if i < 0 || i as u64 > len {
+                    bail!("index {text} is out of bounds for axis {axis} of length {len}");
+                }
+                Ok(i as u64)
+            };
+            match part.split_once(':') {
+                Some((start, end)) => {
+                    let range = index(start, 0)?..index(end, len)?;
+                    if range.is_empty() {
+                        bail!("{part} selects nothing along axis {axis}");
+                    }
+                    slice.ranges[axis] = range;
+                }
+                None => {
+                    let i = index(part, 0)?;
+                    if i >= len {
+                        bail!("index {part} is out of bounds for axis {axis} of length {len}");
+                    }
+                    slice.ranges[axis] = i..i + 1;
+                    slice.indexed[axis] = true;
+                }
+            }
+        }
+        Ok(slice)
+    }
+
+    /// Every value of a tensor with this `shape`
+    pub fn whole(shape: &[u64]) -> Self {
+        TensorSlice {
+            ranges: shape.iter().map(|&len| 0..len).collect(),
+            indexed: vec![false; shape.len()],
+        }
+    }
+
+    /// The axis the slice steps along, which is the first one given a single index
+    pub fn axis(&self) -> usize {
+        self.indexed
+            .iter()
+            .position(|&indexed| indexed)
+            .unwrap_or(0)
+    }
+
+    /// Moves to the next or previous index along [`Self::axis`], wrapping around at either end
+    pub fn step(&mut self, shape: &[u64], forward: bool) {
+        let axis = self.axis();
+        let Some(&len) = shape.get(axis).filter(|&&len| len > 0) else {
+            return;
+        };
+        let i = if !self.indexed[axis] {
+            0
+        } else if forward {
+            (self.ranges[axis].start + 1) % len
+        } else {
+            (self.ranges[axis].start + len - 1) % len
+        };
+        self.ranges[axis] = i..i + 1;
+        self.indexed[axis] = true;
+    }
+
+    /// The shape of the values read by [`read_slice`]
+    pub fn shape(&self) -> Vec<u64> {
+        self.ranges
+            .iter()
+            .zip(&self.indexed)
+            .filter(|(_, indexed)| !**indexed)
+            .map(|(range, _)| range.end - range.start)
+            .collect()
+    }
+
+    /// The slice as a tensor of its own, for analysis which looks at the shape
+    pub fn tensor(&self, tensor: &TensorInfo) -> TensorInfo {
+        let shape = self.shape();
+        let numel = shape.iter().product::<u64>() as usize;
+        TensorInfo {
+            ty: tensor.ty.clone(),
+            size: numel * 4,
+            shape,
+            offset: tensor.offset,
+        }
+    }
+}
+
+impl fmt::Display for TensorSlice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .ranges
+            .iter()
+            .zip(&self.indexed)
+            .map(|(range, &indexed)| {
+                if indexed {
+                    range.start.to_string()
+                } else {
+                    format!("{}:{}", range.start, range.end)
+                }
+            })
+            .collect();
+        write!(f, "[{}]", parts.join(", "))
+    }
+}
+
+/// Reads just the values of `tensor` inside `slice`, one contiguous run of storage at a time.
+/// Quantized tensors are read in whole rows, since their blocks can't be split.
+pub fn read_slice(
+    source: &mut dyn ModuleSource,
+    tensor: &TensorInfo,
+    slice: &TensorSlice,
+) -> Result<Vec<f32>, Error> {
+    let Some((columns, outer)) = slice.ranges.split_last() else {
+        // A scalar is its own slice
+        let bytes = source.tensor_byte_range(tensor, 0..tensor.size)?;
+        return tensor.read_f32::<LE>(&bytes);
+    };
+    let row_len = *tensor.shape.last().unwrap_or(&1);
+    let numel = tensor.shape.iter().product::<u64>();
+    if numel == 0 {
+        return Ok(Vec::new());
+    }
+    let row_bytes = tensor.size as u64 / (numel / row_len);
+    let element = row_bytes / row_len;
+    let quantized = matches!(tensor.ty, TensorTy::Ggml(_));
+    // The bytes read from each row, relative to its start
+    let within = if quantized {
+        0..row_bytes
+    } else {
+        columns.start * element..columns.end * element
+    };
+
+    // Visit every selected row in storage order, merging runs which touch
+    let mut runs: Vec<(Range<u64>, u64)> = Vec::new();
+    let mut index: Vec<u64> = outer.iter().map(|range| range.start).collect();
+    'rows: loop {
+        let row = index
+            .iter()
+            .zip(&tensor.shape)
+            .fold(0, |row, (&i, &len)| row * len + i);
+        let start = row * row_bytes + within.start;
+        let end = row * row_bytes + within.end;
+        match runs.last_mut() {
+            Some((run, rows)) if run.end == start => {
+                run.end = end;
+                *rows += 1;
+            }
+            _ => runs.push((start..end, 1)),
+        }
+        // Advance like an odometer over the outer axes
+        let mut axis = outer.len();
+        loop {
+            if axis == 0 {
+                break 'rows;
+            }
+            axis -= 1;
+            index[axis] += 1;
+            if index[axis] < outer[axis].end {
+                break;
+            }
+            index[axis] = outer[axis].start;
+        }
+    }
+
+    let mut values = Vec::new();
+    for (range, rows) in runs {
+        let bytes = source.tensor_byte_range(tensor, range.start as usize..range.end as usize)?;
+        let shape = if quantized {
+            vec![rows, row_len]
+        } else {
+            vec![bytes.len() as u64 / element]
+        };
+        let run = TensorInfo {
+            ty: tensor.ty.clone(),
+            shape,
+            size: bytes.len(),
+            offset: 0,
+        };
+        let decoded = run.read_f32::<LE>(&bytes)?;
+        if quantized {
+            // Whole rows were read, so keep only the selected columns
+            for row in decoded.chunks(row_len as usize) {
+                values.extend_from_slice(&row[columns.start as usize..columns.end as usize]);
+            }
+        } else {
+            values.extend(decoded);
+        }
+    }
+    Ok(values)
+}
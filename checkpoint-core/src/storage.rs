@@ -0,0 +1,537 @@
+use anyhow::{Error, anyhow, bail};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use weakref::Ref;
+
+use crate::archive::{MemberStorage, split_member_path};
+use crate::notify;
+
+const READ_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Reads `nbytes` from `offset` in chunks, reporting percent progress and stopping once
+/// `progress` is dropped.
+pub fn read_with_progress(
+    storage: &impl Storage,
+    offset: u64,
+    nbytes: usize,
+    progress: Ref<AtomicU64>,
+) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::with_capacity(nbytes);
+    for chunk in storage.read_chunks(offset..offset + nbytes as u64, READ_CHUNK_SIZE) {
+        if !progress.is_alive() {
+            return Err(anyhow!("cancelled"));
+        }
+        data.extend_from_slice(&chunk?);
+        progress.inspect(|p| p.store((data.len() * 100 / nbytes) as u64, Relaxed));
+        notify::notify();
+    }
+    Ok(data)
+}
+
+/// Whether `path` names an object in cloud storage rather than a local file
+pub fn is_object_url(path: &str) -> bool {
+    ["s3://", "gs://"]
+        .iter()
+        .any(|scheme| path.starts_with(scheme))
+}
+
+pub trait Storage {
+    type Reader: io::Read + io::Seek + ?Sized;
+
+    fn display(&self) -> String;
+    /// A shared sequential reader, for parsing headers
+    fn reader(&mut self) -> Result<&mut Self::Reader, Error>;
+    /// Reads exactly `len` bytes at `offset`. Takes `&self` and keeps no cursor, so reads can
+    /// be made from several threads at once.
+    fn read_range(&self, offset: u64, len: usize) -> Result<Vec<u8>, Error>;
+    /// Reads `range` in pieces of at most `chunk_size` bytes
+    fn read_chunks(&self, range: Range<u64>, chunk_size: usize) -> ReadChunks<'_, Self>
+    where
+        Self: Sized,
+    {
+        ReadChunks {
+            storage: self,
+            range,
+            chunk_size: chunk_size.max(1),
+        }
+    }
+    fn read(&mut self) -> Result<Vec<u8>, Error>;
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error>;
+    fn splice(&mut self, range: Range<usize>, bytes: &[u8]) -> Result<(), Error>;
+    /// Replaces the contents with `header` followed by `ranges` of the old contents, in order,
+    /// without reading them all into memory
+    fn rebuild(&mut self, header: &[u8], ranges: &[Range<u64>]) -> Result<(), Error>;
+    /// Replaces bytes without changing the length of the file. Much faster than `splice` for
+    /// large files, but a crash partway through can leave a mix of old and new bytes.
+    fn overwrite(&mut self, offset: usize, bytes: &[u8]) -> Result<(), Error>;
+    /// Fails if `write` or `splice` would, checked up front so an edit never stops halfway
+    fn check_writable(&self) -> Result<(), Error>;
+}
+
+/// Iterator returned by [`Storage::read_chunks`]
+pub struct ReadChunks<'a, S> {
+    storage: &'a S,
+    range: Range<u64>,
+    chunk_size: usize,
+}
+
+impl<S: Storage> Iterator for ReadChunks<'_, S> {
+    type Item = Result<Vec<u8>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.range.is_empty() {
+            return None;
+        }
+        let len = (self.range.end - self.range.start).min(self.chunk_size as u64);
+        let chunk = self.storage.read_range(self.range.start, len as usize);
+        // Stop after an error rather than skipping ahead
+        self.range.start = if chunk.is_ok() {
+            self.range.start + len
+        } else {
+            self.range.end
+        };
+        Some(chunk)
+    }
+}
+
+/// The reader of whichever backend an [`AnyStorage`] holds
+pub trait ReadSeek: io::Read + io::Seek {}
+
+impl<R: io::Read + io::Seek> ReadSeek for R {}
+
+/// Any of the storage backends, so formats can be opened wherever the file lives
+pub enum AnyStorage {
+    File(FileStorage),
+    #[cfg(feature = "object-store")]
+    Object(crate::object_storage::ObjectStorage),
+    Member(crate::archive::MemberStorage),
+}
+
+macro_rules! delegate {
+    ($self:ident, $storage:ident => $body:expr) => {
+        match $self {
+            AnyStorage::File($storage) => $body,
+            #[cfg(feature = "object-store")]
+            AnyStorage::Object($storage) => $body,
+            AnyStorage::Member($storage) => $body,
+        }
+    };
+}
+
+impl AnyStorage {
+    /// Opens a local path, a member of an archive as `archive.zip!/member`, or an `s3://` or
+    /// `gs://` URL if built with object storage. `backup` is passed on to
+    /// [`FileStorage::backup`].
+    pub fn open(path: &Path, backup: bool) -> Result<Self, Error> {
+        if let Some((archive, member)) = split_member_path(path) {
+            return Ok(AnyStorage::Member(MemberStorage::open(archive, member)?));
+        }
+        let Some(url) = path.to_str().filter(|path| is_object_url(path)) else {
+            let mut storage = FileStorage::new(path.to_path_buf());
+            storage.backup = backup;
+            return Ok(AnyStorage::File(storage));
+        };
+        #[cfg(feature = "object-store")]
+        return Ok(AnyStorage::Object(
+            crate::object_storage::ObjectStorage::open(url)?,
+        ));
+        #[cfg(not(feature = "object-store"))]
+        bail!("can't open {url}: checkpointui was built without the object-store feature")
+    }
+}
+
+impl Storage for AnyStorage {
+    type Reader = dyn ReadSeek;
+
+    fn display(&self) -> String {
+        delegate!(self, storage => storage.display())
+    }
+
+    fn reader(&mut self) -> Result<&mut Self::Reader, Error> {
+        delegate!(self, storage => Ok(storage.reader()?))
+    }
+
+    fn read_range(&self, offset: u64, len: usize) -> Result<Vec<u8>, Error> {
+        delegate!(self, storage => storage.read_range(offset, len))
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>, Error> {
+        delegate!(self, storage => storage.read())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        delegate!(self, storage => storage.write(bytes))
+    }
+
+    fn splice(&mut self, range: Range<usize>, bytes: &[u8]) -> Result<(), Error> {
+        delegate!(self, storage => storage.splice(range, bytes))
+    }
+
+    fn rebuild(&mut self, header: &[u8], ranges: &[Range<u64>]) -> Result<(), Error> {
+        delegate!(self, storage => storage.rebuild(header, ranges))
+    }
+
+    fn overwrite(&mut self, offset: usize, bytes: &[u8]) -> Result<(), Error> {
+        delegate!(self, storage => storage.overwrite(offset, bytes))
+    }
+
+    fn check_writable(&self) -> Result<(), Error> {
+        delegate!(self, storage => storage.check_writable())
+    }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &fs::File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &fs::File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+pub struct FileStorage {
+    path: PathBuf,
+    reader: Option<io::BufReader<fs::File>>,
+    /// Handle for positioned reads, reopened after the file is replaced
+    file: OnceLock<fs::File>,
+    /// Keep the previous contents as `<path>.bak` whenever the file is rewritten
+    pub backup: bool,
+}
+
+impl FileStorage {
+    pub fn new(path: PathBuf) -> Self {
+        FileStorage {
+            path,
+            reader: None,
+            file: OnceLock::new(),
+            backup: false,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Fills a temporary file next to the original and renames it into place, so a crash
+    /// leaves either the old contents or the new ones but never a mix. The file doesn't need
+    /// to exist yet, and is left untouched if `fill` fails.
+    pub fn replace_with(
+        &mut self,
+        fill: impl FnOnce(&mut fs::File) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.reader = None;
+        self.file = OnceLock::new();
+        let name = self
+            .path
+            .file_name()
+            .ok_or_else(|| anyhow!("{} is not a file", self.path.display()))?;
+        let mut temp_name = OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(".{}.tmp", std::process::id()));
+        let temp_path = self.path.with_file_name(temp_name);
+
+        let result = (|| {
+            let mut temp = fs::File::create_new(&temp_path)?;
+            fill(&mut temp)?;
+            if let Ok(metadata) = fs::metadata(&self.path) {
+                temp.set_permissions(metadata.permissions())?;
+            }
+            temp.sync_all()?;
+            if self.backup {
+                let mut backup = self.path.clone().into_os_string();
+                backup.push(".bak");
+                let _ = fs::remove_file(&backup);
+                // A hard link costs nothing, but needs the filesystem to support it
+                if fs::hard_link(&self.path, &backup).is_err() {
+                    fs::copy(&self.path, &backup)?;
+                }
+            }
+            fs::rename(&temp_path, &self.path)?;
+            Ok(())
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result
+    }
+}
+
+impl Storage for FileStorage {
+    type Reader = io::BufReader<fs::File>;
+
+    fn display(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn reader(&mut self) -> Result<&mut Self::Reader, Error> {
+        if self.reader.is_none() {
+            let file = fs::File::open(&self.path)?;
+            // Read tensors from the same file the header was parsed from, so a handle opened
+            // before the file is replaced keeps reading the data its header describes
+            if self.file.get().is_none() {
+                let _ = self.file.set(file.try_clone()?);
+            }
+            self.reader = Some(io::BufReader::new(file));
+        }
+        Ok(self.reader.as_mut().unwrap())
+    }
+
+    fn read_range(&self, offset: u64, len: usize) -> Result<Vec<u8>, Error> {
+        let file = match self.file.get() {
+            Some(file) => file,
+            None => {
+                let file = fs::File::open(&self.path)?;
+                self.file.get_or_init(|| file)
+            }
+        };
+        let mut bytes = vec![0; len];
+        read_exact_at(file, &mut bytes, offset)?;
+        Ok(bytes)
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>, Error> {
+        Ok(fs::read(&self.path)?)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.replace_with(|temp| Ok(temp.write_all(bytes)?))
+    }
+
+    fn splice(&mut self, range: Range<usize>, bytes: &[u8]) -> Result<(), Error> {
+        let mut original = fs::File::open(&self.path)?;
+        if range.end as u64 > original.metadata()?.len() {
+            bail!("can't splice past the end of {}", self.path.display());
+        }
+        self.replace_with(|temp| {
+            // Copying file to file lets the kernel move the data (copy_file_range on Linux)
+            // instead of pulling the whole checkpoint through memory
+            io::copy(
+                &mut Read::by_ref(&mut original).take(range.start as u64),
+                temp,
+            )?;
+            temp.write_all(bytes)?;
+            original.seek(io::SeekFrom::Start(range.end as u64))?;
+            io::copy(&mut original, temp)?;
+            Ok(())
+        })
+    }
+
+    fn rebuild(&mut self, header: &[u8], ranges: &[Range<u64>]) -> Result<(), Error> {
+        let mut original = fs::File::open(&self.path)?;
+        let len = original.metadata()?.len();
+        if ranges
+            .iter()
+            .any(|range| range.start > range.end || range.end > len)
+        {
+            bail!("can't copy past the end of {}", self.path.display());
+        }
+        self.replace_with(|temp| {
+            temp.write_all(header)?;
+            for range in ranges {
+                original.seek(io::SeekFrom::Start(range.start))?;
+                io::copy(
+                    &mut Read::by_ref(&mut original).take(range.end - range.start),
+                    temp,
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    fn overwrite(&mut self, offset: usize, bytes: &[u8]) -> Result<(), Error> {
+        // A backup needs the old contents left intact, so take the slow path
+        if self.backup {
+            return self.splice(offset..offset + bytes.len(), bytes);
+        }
+        self.reader = None;
+        let mut file = fs::File::options().write(true).open(&self.path)?;
+        if (offset + bytes.len()) as u64 > file.metadata()?.len() {
+            bail!("can't overwrite past the end of {}", self.path.display());
+        }
+        file.seek(io::SeekFrom::Start(offset as u64))?;
+        file.write_all(bytes)?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    fn check_writable(&self) -> Result<(), Error> {
+        // Opening for write also catches read-only mounts, which the permission bits don't show
+        fs::File::options()
+            .write(true)
+            .open(&self.path)
+            .map_err(|err| anyhow!("{} is not writable: {err}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+/// Places a run of bytes from one of the files of a [`SegmentStorage`]
+struct Segment {
+    start: u64,
+    len: u64,
+    file: usize,
+    offset: u64,
+}
+
+/// Several files laid end to end in one read-only address space, for checkpoints spread over
+/// more than one file. Ranges already mapped can be mapped again at the end, so a tensor
+/// whose bytes are scattered across the files can still be read as one contiguous range.
+pub struct SegmentStorage {
+    name: String,
+    files: Vec<AnyStorage>,
+    /// Sorted by `start`, without gaps
+    segments: Vec<Segment>,
+}
+
+impl SegmentStorage {
+    /// An empty address space, shown as `name`
+    pub fn new(name: String) -> Self {
+        SegmentStorage {
+            name,
+            files: Vec::new(),
+            segments: Vec::new(),
+        }
+    }
+
+    fn end(&self) -> u64 {
+        self.segments.last().map_or(0, |last| last.start + last.len)
+    }
+
+    /// Maps `len` bytes of `file` to the end of the address space, returning where they start
+    fn append(&mut self, file: usize, offset: u64, len: u64) -> u64 {
+        let start = self.end();
+        if len > 0 {
+            self.segments.push(Segment {
+                start,
+                len,
+                file,
+                offset,
+            });
+        }
+        start
+    }
+
+    /// Maps the whole of `file` to the end of the address space, returning where it lands
+    pub fn push_file(&mut self, mut file: AnyStorage) -> Result<Range<u64>, Error> {
+        let len = file.reader()?.seek(io::SeekFrom::End(0))?;
+        self.files.push(file);
+        let start = self.append(self.files.len() - 1, 0, len);
+        Ok(start..start + len)
+    }
+
+    /// Maps copies of existing ranges to the end of the address space, back to back,
+    /// returning where the first one starts
+    pub fn append_ranges(&mut self, ranges: &[Range<u64>]) -> Result<u64, Error> {
+        let start = self.end();
+        for range in ranges {
+            let segment = self.locate(range.start)?;
+            if range.end > segment.start + segment.len {
+                bail!("can't map a range spanning two files of {}", self.name);
+            }
+            let (file, offset) = (segment.file, segment.offset + range.start - segment.start);
+            self.append(file, offset, range.end - range.start);
+        }
+        Ok(start)
+    }
+
+    fn segment_index(&self, at: u64) -> usize {
+        self.segments
+            .partition_point(|segment| segment.start + segment.len <= at)
+    }
+
+    /// The segment holding the byte at `at`
+    fn locate(&self, at: u64) -> Result<&Segment, Error> {
+        match self.segments.get(self.segment_index(at)) {
+            Some(segment) if segment.start <= at => Ok(segment),
+            _ => bail!("can't read past the end of {}", self.name),
+        }
+    }
+
+    fn read_only(&self) -> Error {
+        anyhow!(
+            "{} is spread over several files, so it can't be edited",
+            self.name
+        )
+    }
+}
+
+impl Storage for SegmentStorage {
+    type Reader = io::Empty;
+
+    fn display(&self) -> String {
+        self.name.clone()
+    }
+
+    fn reader(&mut self) -> Result<&mut Self::Reader, Error> {
+        bail!(
+            "{} is spread over several files, so it has no single reader",
+            self.name
+        )
+    }
+
+    fn read_range(&self, offset: u64, len: usize) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::with_capacity(len);
+        let end = offset + len as u64;
+        let mut at = offset;
+        for segment in &self.segments[self.segment_index(offset)..] {
+            if at >= end {
+                break;
+            }
+            if segment.start > at {
+                bail!("can't read past the end of {}", self.name);
+            }
+            let take = (segment.start + segment.len).min(end) - at;
+            let file_offset = segment.offset + at - segment.start;
+            bytes.extend(self.files[segment.file].read_range(file_offset, take as usize)?);
+            at += take;
+        }
+        if at < end {
+            bail!("can't read past the end of {}", self.name);
+        }
+        Ok(bytes)
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>, Error> {
+        self.read_range(0, self.end() as usize)
+    }
+
+    fn write(&mut self, _bytes: &[u8]) -> Result<(), Error> {
+        Err(self.read_only())
+    }
+
+    fn splice(&mut self, _range: Range<usize>, _bytes: &[u8]) -> Result<(), Error> {
+        Err(self.read_only())
+    }
+
+    fn rebuild(&mut self, _header: &[u8], _ranges: &[Range<u64>]) -> Result<(), Error> {
+        Err(self.read_only())
+    }
+
+    fn overwrite(&mut self, _offset: usize, _bytes: &[u8]) -> Result<(), Error> {
+        Err(self.read_only())
+    }
+
+    fn check_writable(&self) -> Result<(), Error> {
+        Err(self.read_only())
+    }
+}
@@ -0,0 +1,28 @@
+//! Recognizes serialized TensorRT engines (`.engine` and `.plan` files). The plan format is
+//! private to TensorRT and changes with every release, so without linking the TensorRT runtime
+//! only the header can be checked; the engine opens with its size and no tensors.
+
+use crate::bundle::Bundle;
+use crate::integrity::Problem;
+use crate::storage::Storage;
+use anyhow::{Result, bail};
+use serde_json::Map;
+use std::io::{Seek, SeekFrom};
+
+/// Every serialized engine starts with these bytes
+pub(crate) const ENGINE_MAGIC: &[u8] = b"ftrt";
+
+pub fn open<S: Storage>(mut storage: S) -> Result<Bundle<S>> {
+    let len = storage.reader()?.seek(SeekFrom::End(0))?;
+    let header = storage.read_range(0, ENGINE_MAGIC.len().min(len as usize))?;
+    if header != ENGINE_MAGIC {
+        bail!("{} is not a TensorRT engine", storage.display());
+    }
+    let mut metadata = Map::new();
+    metadata.insert("engine_bytes".into(), len.into());
+    let problems = vec![Problem::file(
+        "TensorRT engines can only be fully read by the TensorRT runtime that built them, so \
+         the bindings are not shown; inspect the ONNX model the engine was built from instead",
+    )];
+    Ok(Bundle::new(storage, "tensorrt", Vec::new(), metadata, false).with_problems(problems))
+}
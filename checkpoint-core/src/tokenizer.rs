@@ -0,0 +1,219 @@
+use anyhow::{Error, anyhow, bail};
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+
+/// The kinds of token in `tokenizer.ggml.token_type`
+pub fn token_type_name(ty: i32) -> &'static str {
+    match ty {
+        1 => "normal",
+        2 => "unknown",
+        3 => "control",
+        4 => "user",
+        5 => "unused",
+        6 => "byte",
+        _ => "?",
+    }
+}
+
+/// How text is split into tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Model {
+    /// Byte-level BPE driven by a merge list, as in GPT-2 (`gpt2`)
+    Bpe,
+    /// SentencePiece BPE driven by token scores, as in LLaMA (`llama`)
+    SentencePiece,
+}
+
+/// The tokenizer embedded in a GGUF file's `tokenizer.ggml.*` metadata
+pub struct Vocab {
+    pub model: String,
+    pub tokens: Vec<String>,
+    pub scores: Option<Vec<f32>>,
+    pub types: Option<Vec<i32>>,
+    pub merges: Vec<String>,
+    pub bos: Option<u32>,
+    pub eos: Option<u32>,
+    pub unknown: Option<u32>,
+    add_bos: bool,
+    add_space_prefix: bool,
+    ids: HashMap<String, u32>,
+    ranks: HashMap<(String, String), usize>,
+}
+
+fn parse<T: DeserializeOwned>(metadata: &HashMap<String, String>, key: &str) -> Option<T> {
+    serde_json::from_str(metadata.get(key)?).ok()
+}
+
+impl Vocab {
+    /// Reads the vocabulary from string metadata, where arrays are stored as JSON, or returns
+    /// `None` when the file has no tokenizer
+    pub fn from_metadata(metadata: &HashMap<String, String>) -> Result<Option<Self>, Error> {
+        let Some(tokens) = metadata.get("tokenizer.ggml.tokens") else {
+            return Ok(None);
+        };
+        let tokens: Vec<String> = serde_json::from_str(tokens)
+            .map_err(|err| anyhow!("could not read tokenizer.ggml.tokens: {err}"))?;
+        let model = metadata
+            .get("tokenizer.ggml.model")
+            .cloned()
+            .unwrap_or_default();
+        let merges: Vec<String> = parse(metadata, "tokenizer.ggml.merges").unwrap_or_default();
+        let ranks = merges
+            .iter()
+            .enumerate()
+            .filter_map(|(rank, merge)| {
+                let (left, right) = merge.split_once(' ')?;
+                Some(((left.to_string(), right.to_string()), rank))
+            })
+            .collect();
+        let ids = tokens
+            .iter()
+            .enumerate()
+            .map(|(id, token)| (token.clone(), id as u32))
+            .collect();
+        Ok(Some(Vocab {
+            add_bos: parse(metadata, "tokenizer.ggml.add_bos_token").unwrap_or(model == "llama"),
+            add_space_prefix: parse(metadata, "tokenizer.ggml.add_space_prefix").unwrap_or(true),
+            scores: parse(metadata, "tokenizer.ggml.scores"),
+            types: parse(metadata, "tokenizer.ggml.token_type"),
+            bos: parse(metadata, "tokenizer.ggml.bos_token_id"),
+            eos: parse(metadata, "tokenizer.ggml.eos_token_id"),
+            unknown: parse(metadata, "tokenizer.ggml.unknown_token_id"),
+            model,
+            tokens,
+            merges,
+            ids,
+            ranks,
+        }))
+    }
+
+    pub fn id(&self, token: &str) -> Option<u32> {
+        self.ids.get(token).copied()
+    }
+
+    pub fn score(&self, id: u32) -> Option<f32> {
+        self.scores.as_ref()?.get(id as usize).copied()
+    }
+
+    pub fn token_type(&self, id: u32) -> Option<i32> {
+        self.types.as_ref()?.get(id as usize).copied()
+    }
+
+    /// Ids of tokens containing `query`, or the token with that id if `query` is a number
+    pub fn search(&self, query: &str) -> Vec<u32> {
+        match query.parse::<u32>() {
+            Ok(id) if (id as usize) < self.tokens.len() => return vec![id],
+            _ => {}
+        }
+        self.tokens
+            .iter()
+            .enumerate()
+            .filter(|(_, token)| token.contains(query))
+            .map(|(id, _)| id as u32)
+            .collect()
+    }
+
+    fn kind(&self) -> Result<Model, Error> {
+        match self.model.as_str() {
+            "gpt2" => Ok(Model::Bpe),
+            "llama" => Ok(Model::SentencePiece),
+            _ if !self.ranks.is_empty() => Ok(Model::Bpe),
+            _ if self.scores.is_some() => Ok(Model::SentencePiece),
+            other => bail!("can't encode with the {other:?} tokenizer"),
+        }
+    }
+
+    /// Splits `text` into token ids the way the embedded tokenizer would, minus any special
+    /// pre-tokenizer rules beyond GPT-2's
+    pub fn encode(&self, text: &str) -> Result<Vec<u32>, Error> {
+        let mut ids = Vec::new();
+        if self.add_bos {
+            ids.extend(self.bos);
+        }
+        match self.kind()? {
+            Model::Bpe => self.encode_bpe(text, &mut ids)?,
+            Model::SentencePiece => self.encode_spm(text, &mut ids),
+        }
+        Ok(ids)
+    }
+
+    fn encode_bpe(&self, text: &str, ids: &mut Vec<u32>) -> Result<(), Error> {
+        let pattern =
+            Regex::new(r"'s|'t|'re|'ve|'m|'ll|'d| ?\p{L}+| ?\p{N}+| ?[^\s\p{L}\p{N}]+|\s+")?;
+        let byte_chars = gpt2_byte_chars();
+        for word in pattern.find_iter(text) {
+            let mut pieces: Vec<String> = word
+                .as_str()
+                .bytes()
+                .map(|b| byte_chars[b as usize].to_string())
+                .collect();
+            // Apply the lowest-ranked merge until none apply
+            loop {
+                let best = pieces
+                    .windows(2)
+                    .enumerate()
+                    .filter_map(|(i, pair)| {
+                        let rank = self.ranks.get(&(pair[0].clone(), pair[1].clone()))?;
+                        Some((*rank, i))
+                    })
+                    .min();
+                let Some((_, i)) = best else { break };
+                let right = pieces.remove(i + 1);
+                pieces[i].push_str(&right);
+            }
+            for piece in pieces {
+                ids.extend(self.id(&piece).or(self.unknown));
+            }
+        }
+        Ok(())
+    }
+
+    fn encode_spm(&self, text: &str, ids: &mut Vec<u32>) {
+        let mut text = text.replace(' ', "▁");
+        if self.add_space_prefix && !text.is_empty() {
+            text.insert(0, '▁');
+        }
+        let mut pieces: Vec<String> = text.chars().map(String::from).collect();
+        // Merge the adjacent pair that forms the highest-scoring token until none do
+        loop {
+            let best = pieces
+                .windows(2)
+                .enumerate()
+                .filter_map(|(i, pair)| {
+                    let id = self.id(&format!("{}{}", pair[0], pair[1]))?;
+                    Some((self.score(id).unwrap_or(0.0), i))
+                })
+                .max_by(|a, b| a.0.total_cmp(&b.0).then(b.1.cmp(&a.1)));
+            let Some((_, i)) = best else { break };
+            let right = pieces.remove(i + 1);
+            pieces[i].push_str(&right);
+        }
+        for piece in pieces {
+            if let Some(id) = self.id(&piece) {
+                ids.push(id);
+                continue;
+            }
+            // Fall back to byte tokens like <0x0A>, then to the unknown token
+            for byte in piece.bytes() {
+                ids.extend(self.id(&format!("<0x{byte:02X}>")).or(self.unknown));
+            }
+        }
+    }
+}
+
+/// GPT-2's reversible mapping from bytes to printable characters
+fn gpt2_byte_chars() -> Vec<char> {
+    let printable = |b: u8| matches!(b, b'!'..=b'~' | 0xA1..=0xAC | 0xAE..=0xFF);
+    let mut next = 256;
+    (0..=255u8)
+        .map(|b| {
+            if printable(b) {
+                char::from(b)
+            } else {
+                next += 1;
+                char::from_u32(next - 1).unwrap()
+            }
+        })
+        .collect()
+}
@@ -1,8 +1,11 @@
-use std::{env, path::PathBuf};
+#[cfg(not(feature = "ffi"))]
+pub fn main() {}
 
+#[cfg(feature = "ffi")]
 pub fn main() {
     use cmake;
     use cmake::Config;
+    use std::{env, path::PathBuf};
 
     let dst = Config::new("ggml")
         .build_target("ggml-base")
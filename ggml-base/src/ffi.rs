@@ -0,0 +1,95 @@
+//! Type traits and (de)quantization from the ggml C library, which covers every type
+
+use std::ffi::CStr;
+
+use crate::TypeTraits;
+
+pub mod sys {
+    #![allow(warnings)]
+    include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+}
+
+pub type GgmlTypeId = sys::ggml_type;
+
+pub const I8: GgmlTypeId = sys::ggml_type_GGML_TYPE_I8;
+pub const I16: GgmlTypeId = sys::ggml_type_GGML_TYPE_I16;
+pub const I32: GgmlTypeId = sys::ggml_type_GGML_TYPE_I32;
+pub const I64: GgmlTypeId = sys::ggml_type_GGML_TYPE_I64;
+pub const F16: GgmlTypeId = sys::ggml_type_GGML_TYPE_F16;
+pub const BF16: GgmlTypeId = sys::ggml_type_GGML_TYPE_BF16;
+pub const F32: GgmlTypeId = sys::ggml_type_GGML_TYPE_F32;
+pub const F64: GgmlTypeId = sys::ggml_type_GGML_TYPE_F64;
+pub const Q4_0: GgmlTypeId = sys::ggml_type_GGML_TYPE_Q4_0;
+pub const Q4_1: GgmlTypeId = sys::ggml_type_GGML_TYPE_Q4_1;
+pub const Q5_0: GgmlTypeId = sys::ggml_type_GGML_TYPE_Q5_0;
+pub const Q5_1: GgmlTypeId = sys::ggml_type_GGML_TYPE_Q5_1;
+pub const Q8_0: GgmlTypeId = sys::ggml_type_GGML_TYPE_Q8_0;
+pub const Q2_K: GgmlTypeId = sys::ggml_type_GGML_TYPE_Q2_K;
+pub const Q3_K: GgmlTypeId = sys::ggml_type_GGML_TYPE_Q3_K;
+pub const Q4_K: GgmlTypeId = sys::ggml_type_GGML_TYPE_Q4_K;
+pub const Q5_K: GgmlTypeId = sys::ggml_type_GGML_TYPE_Q5_K;
+pub const Q6_K: GgmlTypeId = sys::ggml_type_GGML_TYPE_Q6_K;
+
+fn get_type_traits(ty: GgmlTypeId) -> Option<&'static sys::ggml_type_traits> {
+    if ty >= sys::ggml_type_GGML_TYPE_COUNT {
+        return None;
+    }
+    let traits = unsafe { sys::ggml_get_type_traits(ty) };
+    if traits.is_null() {
+        return None;
+    }
+    let traits: &'static _ = unsafe { &*traits };
+    Some(traits)
+}
+
+pub(crate) fn type_traits(ty: GgmlTypeId) -> Option<TypeTraits> {
+    let traits = get_type_traits(ty)?;
+    let name: &'static _ = unsafe { CStr::from_ptr(traits.type_name) };
+    Some(TypeTraits {
+        name: name.to_str().ok()?,
+        blck_size: traits.blck_size as u64,
+        type_size: unsafe { sys::ggml_type_size(ty) } as u64,
+    })
+}
+
+pub(crate) fn can_quantize(ty: GgmlTypeId) -> bool {
+    get_type_traits(ty).is_some_and(|traits| traits.from_float_ref.is_some())
+        && !unsafe { sys::ggml_quantize_requires_imatrix(ty) }
+}
+
+/// Fills `bytes` with `nrows` rows of `n_per_row` floats, returning how many bytes were written
+pub(crate) fn quantize_rows(
+    ty: GgmlTypeId,
+    floats: &[f32],
+    bytes: &mut [u8],
+    nrows: i64,
+    n_per_row: i64,
+) -> usize {
+    // ggml_quantize_chunk dispatches to the quantize_row implementation for each type
+    unsafe {
+        sys::ggml_quantize_chunk(
+            ty,
+            floats.as_ptr(),
+            bytes.as_mut_ptr() as _,
+            0,
+            nrows,
+            n_per_row,
+            std::ptr::null(),
+        )
+    }
+}
+
+/// Fills `floats` from whole blocks of `bytes`, or returns false if ggml can't read `ty`
+pub(crate) fn to_float(ty: GgmlTypeId, bytes: &[u8], floats: &mut [f32]) -> bool {
+    let Some(to_float) = get_type_traits(ty).and_then(|traits| traits.to_float) else {
+        return false;
+    };
+    unsafe {
+        to_float(
+            bytes.as_ptr() as _,
+            floats.as_mut_ptr(),
+            floats.len() as i64,
+        )
+    };
+    true
+}
@@ -1,21 +1,57 @@
 use anyhow::{Error, anyhow, bail, ensure};
-use byteorder::{ByteOrder, LE, ReadBytesExt};
+use byteorder::{ByteOrder, LE, ReadBytesExt, WriteBytesExt};
 use std::collections::HashMap;
-use std::ffi::CStr;
-use std::io::Read;
+use std::io::{Read, Write};
 
-pub mod sys {
-    #![allow(warnings)]
-    include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+// The C library is used whenever it's enabled, since it covers every type
+#[cfg(feature = "ffi")]
+mod ffi;
+#[cfg(feature = "ffi")]
+use ffi as backend;
+#[cfg(feature = "ffi")]
+pub use ffi::sys;
+#[cfg(all(feature = "pure-rust", not(feature = "ffi")))]
+mod native;
+#[cfg(all(feature = "pure-rust", not(feature = "ffi")))]
+use native as backend;
+#[cfg(not(any(feature = "ffi", feature = "pure-rust")))]
+compile_error!("enable `ffi` to build the ggml C library, or `pure-rust` for the native fallback");
+
+pub use backend::{
+    BF16, F16, F32, F64, GgmlTypeId, I8, I16, I32, I64, Q2_K, Q3_K, Q4_0, Q4_1, Q4_K, Q5_0, Q5_1,
+    Q5_K, Q6_K, Q8_0,
+};
+
+/// Most elements or bytes to reserve room for up front. Counts come straight from the file,
+/// so a corrupt one shouldn't be able to exhaust memory before the reads run out of data.
+const PREALLOCATE_LIMIT: u64 = 1 << 16;
+
+/// ggml tensors have at most this many dimensions
+const MAX_DIMS: u32 = 4;
+
+/// How tensors of one ggml type are laid out
+pub(crate) struct TypeTraits {
+    pub name: &'static str,
+    /// Elements in each block
+    pub blck_size: u64,
+    /// Bytes in each block
+    pub type_size: u64,
 }
 
 fn read_gguf_string<O: ByteOrder>(read: &mut impl Read) -> Result<String, Error> {
     let len = read.read_u64::<O>()?;
-    let mut string = String::with_capacity(len as usize);
+    let mut string = String::with_capacity(len.min(PREALLOCATE_LIMIT) as usize);
     read.take(len).read_to_string(&mut string)?;
+    ensure!(string.len() as u64 == len, "gguf string is truncated");
     Ok(string)
 }
 
+fn write_gguf_string<O: ByteOrder>(write: &mut impl Write, string: &str) -> Result<(), Error> {
+    write.write_u64::<O>(string.len() as u64)?;
+    write.write_all(string.as_bytes())?;
+    Ok(())
+}
+
 pub struct GgufFile {
     pub metadata: HashMap<String, GgufValue>,
     pub tensors: Vec<GgmlTensorInfo>,
@@ -56,40 +92,91 @@ impl GgufFile {
 
         let tensor_count = read.read_u64::<O>()?;
         let kv_count = read.read_u64::<O>()?;
-        let mut metadata = HashMap::with_capacity(kv_count as usize);
+        let mut metadata = HashMap::with_capacity(kv_count.min(PREALLOCATE_LIMIT) as usize);
         for _ in 0..kv_count {
             let k = read_gguf_string::<O>(&mut read)?;
             let v = GgufValue::read::<O>(&mut read)?;
             metadata.insert(k, v);
         }
 
-        let mut tensors = Vec::with_capacity(tensor_count as usize);
+        let mut tensors = Vec::with_capacity(tensor_count.min(PREALLOCATE_LIMIT) as usize);
         for _ in 0..tensor_count {
             tensors.push(GgmlTensorInfo::read::<O>(&mut read)?);
         }
 
-        let alignment = match metadata.get("general.alignment") {
-            Some(GgufValue::Uint32(a)) => *a as u64,
-            _ => 32,
+        let mut file = GgufFile {
+            metadata,
+            tensors,
+            data_start: 0,
         };
-        let padding = (alignment - read.pos % alignment) % alignment;
+        file.data_start = read.pos.next_multiple_of(file.alignment());
+        Ok(file)
+    }
 
-        Ok(GgufFile {
+    /// Lays out the given tensors back-to-back, each starting on an aligned offset
+    pub fn new(
+        metadata: HashMap<String, GgufValue>,
+        tensors: Vec<(String, GgmlTypeId, Vec<u64>)>,
+    ) -> Result<GgufFile, Error> {
+        let mut file = GgufFile {
             metadata,
-            tensors,
-            data_start: read.pos + padding,
-        })
+            tensors: Vec::with_capacity(tensors.len()),
+            data_start: 0,
+        };
+        let alignment = file.alignment();
+        let mut offset = 0;
+        for (name, ty, shape) in tensors {
+            let mut tensor = GgmlTensorInfo {
+                name,
+                ty,
+                ty_name: "",
+                shape,
+                nbytes: 0,
+                offset,
+            };
+            tensor.update_from_ggml()?;
+            offset = (offset + tensor.nbytes as u64).next_multiple_of(alignment);
+            file.tensors.push(tensor);
+        }
+        Ok(file)
     }
-}
 
-pub const I8: GgmlTypeId = sys::ggml_type_GGML_TYPE_I8;
-pub const I16: GgmlTypeId = sys::ggml_type_GGML_TYPE_I16;
-pub const I32: GgmlTypeId = sys::ggml_type_GGML_TYPE_I32;
-pub const I64: GgmlTypeId = sys::ggml_type_GGML_TYPE_I64;
-pub const F16: GgmlTypeId = sys::ggml_type_GGML_TYPE_F16;
-pub const BF16: GgmlTypeId = sys::ggml_type_GGML_TYPE_BF16;
-pub const F32: GgmlTypeId = sys::ggml_type_GGML_TYPE_F32;
-pub const F64: GgmlTypeId = sys::ggml_type_GGML_TYPE_F64;
+    /// The declared alignment, or the default of 32 if it's missing or isn't a power of two
+    pub fn alignment(&self) -> u64 {
+        match self.metadata.get("general.alignment") {
+            Some(GgufValue::Uint32(a)) if a.is_power_of_two() => *a as u64,
+            _ => 32,
+        }
+    }
+
+    /// Writes everything before the tensor data, including padding, and updates `data_start`
+    pub fn write_header(&mut self, write: &mut impl Write) -> Result<(), Error> {
+        self.write_header_ordered::<LE>(write)
+    }
+
+    pub fn write_header_ordered<O: ByteOrder>(
+        &mut self,
+        write: &mut impl Write,
+    ) -> Result<(), Error> {
+        let mut header = Vec::new();
+        header.write_all(b"GGUF")?;
+        header.write_u32::<O>(3)?;
+        header.write_u64::<O>(self.tensors.len() as u64)?;
+        header.write_u64::<O>(self.metadata.len() as u64)?;
+        for (k, v) in &self.metadata {
+            write_gguf_string::<O>(&mut header, k)?;
+            v.write::<O>(&mut header)?;
+        }
+        for tensor in &self.tensors {
+            tensor.write::<O>(&mut header)?;
+        }
+        let data_start = (header.len() as u64).next_multiple_of(self.alignment());
+        header.resize(data_start as usize, 0);
+        write.write_all(&header)?;
+        self.data_start = data_start;
+        Ok(())
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum GgufValue {
@@ -124,7 +211,7 @@ impl GgufValue {
             9 => Array({
                 let el_ty = read.read_u32::<O>()?;
                 let len = read.read_u64::<O>()?;
-                let mut vec = Vec::with_capacity(len as usize);
+                let mut vec = Vec::with_capacity(len.min(PREALLOCATE_LIMIT) as usize);
                 for _ in 0..len {
                     vec.push(Self::read_ty::<O>(el_ty, read)?);
                 }
@@ -140,9 +227,62 @@ impl GgufValue {
     pub fn read<O: ByteOrder>(read: &mut impl Read) -> Result<GgufValue, Error> {
         Self::read_ty::<O>(read.read_u32::<O>()?, read)
     }
-}
 
-pub type GgmlTypeId = sys::ggml_type;
+    fn ty(&self) -> u32 {
+        use GgufValue::*;
+        match self {
+            Uint8(_) => 0,
+            Int8(_) => 1,
+            Uint16(_) => 2,
+            Int16(_) => 3,
+            Uint32(_) => 4,
+            Int32(_) => 5,
+            Float32(_) => 6,
+            Bool(_) => 7,
+            String(_) => 8,
+            Array(_) => 9,
+            Uint64(_) => 10,
+            Int64(_) => 11,
+            Float64(_) => 12,
+        }
+    }
+
+    fn write_untyped<O: ByteOrder>(&self, write: &mut impl Write) -> Result<(), Error> {
+        use GgufValue::*;
+        match self {
+            Uint8(x) => write.write_u8(*x)?,
+            Int8(x) => write.write_i8(*x)?,
+            Uint16(x) => write.write_u16::<O>(*x)?,
+            Int16(x) => write.write_i16::<O>(*x)?,
+            Uint32(x) => write.write_u32::<O>(*x)?,
+            Int32(x) => write.write_i32::<O>(*x)?,
+            Float32(x) => write.write_f32::<O>(*x)?,
+            Bool(x) => write.write_u8(*x as u8)?,
+            String(x) => write_gguf_string::<O>(write, x)?,
+            Array(vec) => {
+                let el_ty = vec.first().map_or(0, GgufValue::ty);
+                ensure!(
+                    vec.iter().all(|v| v.ty() == el_ty),
+                    "gguf arrays must have a single element type"
+                );
+                write.write_u32::<O>(el_ty)?;
+                write.write_u64::<O>(vec.len() as u64)?;
+                for v in vec {
+                    v.write_untyped::<O>(write)?;
+                }
+            }
+            Uint64(x) => write.write_u64::<O>(*x)?,
+            Int64(x) => write.write_i64::<O>(*x)?,
+            Float64(x) => write.write_f64::<O>(*x)?,
+        }
+        Ok(())
+    }
+
+    pub fn write<O: ByteOrder>(&self, write: &mut impl Write) -> Result<(), Error> {
+        write.write_u32::<O>(self.ty())?;
+        self.write_untyped::<O>(write)
+    }
+}
 
 pub struct GgmlTensorInfo {
     pub name: String,
@@ -157,6 +297,10 @@ impl GgmlTensorInfo {
     pub fn read<O: ByteOrder>(read: &mut impl Read) -> Result<Self, Error> {
         let name = read_gguf_string::<O>(read)?;
         let ndimensions = read.read_u32::<O>()?;
+        ensure!(
+            ndimensions <= MAX_DIMS,
+            "tensor {name} has {ndimensions} dimensions, more than ggml supports"
+        );
         let mut shape = Vec::with_capacity(4);
         for _ in 0..ndimensions {
             shape.push(read.read_u64::<O>()?);
@@ -176,8 +320,19 @@ impl GgmlTensorInfo {
         Ok(this)
     }
 
+    pub fn write<O: ByteOrder>(&self, write: &mut impl Write) -> Result<(), Error> {
+        write_gguf_string::<O>(write, &self.name)?;
+        write.write_u32::<O>(self.shape.len() as u32)?;
+        for &ne in self.shape.iter().rev() {
+            write.write_u64::<O>(ne)?;
+        }
+        write.write_u32::<O>(self.ty)?;
+        write.write_u64::<O>(self.offset)?;
+        Ok(())
+    }
+
     fn update_from_ggml(&mut self) -> Result<(), Error> {
-        let (_traits, ty_name, nbytes) = get_type_and_size(self.ty, &self.shape)?;
+        let (ty_name, nbytes) = get_type_and_size(self.ty, &self.shape)?;
         self.ty_name = ty_name;
         self.nbytes = nbytes;
         Ok(())
@@ -188,51 +343,86 @@ impl GgmlTensorInfo {
     }
 }
 
-fn get_type_traits(ty: GgmlTypeId) -> Option<&'static sys::ggml_type_traits> {
-    if ty >= sys::ggml_type_GGML_TYPE_COUNT {
-        return None;
-    }
-    let traits = unsafe { sys::ggml_get_type_traits(ty) };
-    if traits.is_null() {
-        return None;
-    }
-    let traits: &'static _ = unsafe { &*traits };
-    Some(traits)
-}
+fn get_type_and_size(ty: GgmlTypeId, shape: &[u64]) -> Result<(&'static str, usize), Error> {
+    let traits =
+        backend::type_traits(ty).ok_or_else(|| anyhow!("{} is not a a valid ggml type", ty))?;
+    let ty_name = traits.name;
 
-fn get_type_and_size(
-    ty: GgmlTypeId,
-    shape: &[u64],
-) -> Result<(&'static sys::ggml_type_traits, &'static str, usize), Error> {
-    let traits = get_type_traits(ty).ok_or_else(|| anyhow!("{} is not a a valid ggml type", ty))?;
-    let ty_name: &'static _ = unsafe { CStr::from_ptr(traits.type_name) };
-    let ty_name = ty_name.to_str()?;
-
-    let blck_size: u64 = traits.blck_size as u64;
-    let type_size: u64 = traits.type_size as u64;
+    let blck_size = traits.blck_size;
     ensure!(blck_size > 0, ty_name);
-    ensure!(type_size > 0, ty_name);
+    ensure!(traits.type_size > 0, ty_name);
 
-    let mut stride = unsafe { sys::ggml_type_size(ty) } as u64;
+    let mut stride = traits.type_size;
     let mut ne = shape.iter().rev().copied();
-    stride = stride * ne.next().ok_or_else(|| anyhow!("empty shape"))? / blck_size;
+    // A scalar is stored as one row of one element
+    let row = ne.next().unwrap_or(1);
+    ensure!(
+        row % blck_size == 0,
+        "{ty_name} rows must be a multiple of {blck_size} elements (got {row})"
+    );
+    stride = stride
+        .checked_mul(row / blck_size)
+        .ok_or_else(|| anyhow!("tensor size overflowed"))?;
     for ne in ne {
         stride = stride
             .checked_mul(ne)
             .ok_or_else(|| anyhow!("tensor size overflowed"))?;
     }
 
-    Ok((traits, ty_name, stride.try_into()?))
+    Ok((ty_name, stride.try_into()?))
 }
 
 pub fn get_type_name(ty: GgmlTypeId) -> Option<&'static str> {
-    let traits = get_type_traits(ty)?;
-    let ty_name: &'static _ = unsafe { CStr::from_ptr(traits.type_name) };
-    ty_name.to_str().ok()
+    Some(backend::type_traits(ty)?.name)
+}
+
+pub fn get_block_size(ty: GgmlTypeId) -> Option<u64> {
+    Some(backend::type_traits(ty)?.blck_size)
+}
+
+/// Whether floats can be converted to `ty` without an importance matrix. Without the `ffi`
+/// feature, only q4_0 and q8_0 can.
+pub fn can_quantize(ty: GgmlTypeId) -> bool {
+    backend::can_quantize(ty)
+}
+
+/// Checks that a tensor of the given shape can be stored as `ty`
+pub fn validate_shape(ty: GgmlTypeId, shape: &[u64]) -> Result<(), Error> {
+    get_type_and_size(ty, shape)?;
+    Ok(())
+}
+
+/// Converts row-major floats to `ty`, one row (the innermost dimension) at a time
+pub fn quantize(ty: GgmlTypeId, shape: &[u64], floats: &[f32]) -> Result<Vec<u8>, Error> {
+    let (ty_name, nbytes) = get_type_and_size(ty, shape)?;
+    let nelements = shape.iter().copied().product::<u64>();
+    ensure!(
+        floats.len() as u64 == nelements,
+        "buffer has {} elements (expected {})",
+        floats.len(),
+        nelements
+    );
+    let mut bytes = vec![0u8; nbytes];
+    if nelements == 0 {
+        return Ok(bytes);
+    }
+    ensure!(
+        backend::can_quantize(ty),
+        "{ty_name} cannot be quantized without an importance matrix or the ggml C library"
+    );
+
+    let n_per_row = shape[shape.len() - 1] as i64;
+    let nrows = nelements as i64 / n_per_row;
+    let written = backend::quantize_rows(ty, floats, &mut bytes, nrows, n_per_row);
+    ensure!(
+        written == nbytes,
+        "{ty_name} quantization wrote {written} bytes (expected {nbytes})"
+    );
+    Ok(bytes)
 }
 
 pub fn dequantize(ty: GgmlTypeId, shape: &[u64], bytes: &[u8]) -> Result<Vec<f32>, Error> {
-    let (traits, ty_name, nbytes) = get_type_and_size(ty, shape)?;
+    let (ty_name, nbytes) = get_type_and_size(ty, shape)?;
     let nelements = shape.iter().copied().product::<u64>();
     if nelements == 0 {
         return Ok(Vec::new());
@@ -243,11 +433,11 @@ pub fn dequantize(ty: GgmlTypeId, shape: &[u64], bytes: &[u8]) -> Result<Vec<f32
         bytes.len(),
         nbytes
     );
-    let to_float = traits
-        .to_float
-        .ok_or_else(|| anyhow!("{ty_name} has no dequantization method"))?;
     let mut floats = vec![0f32; nelements as usize];
-    unsafe { to_float(bytes.as_ptr() as _, floats.as_mut_ptr(), nelements as i64) };
+    ensure!(
+        backend::to_float(ty, bytes, &mut floats),
+        "{ty_name} has no dequantization method"
+    );
     Ok(floats)
 }
 
@@ -0,0 +1,297 @@
+//! Type traits for every ggml type, and (de)quantization of the common ones, ported from the
+//! reference implementations in ggml-quants.c so nothing needs to be compiled from C
+
+use half::{bf16, f16};
+
+use crate::TypeTraits;
+
+pub type GgmlTypeId = u32;
+
+pub const F32: GgmlTypeId = 0;
+pub const F16: GgmlTypeId = 1;
+pub const Q4_0: GgmlTypeId = 2;
+pub const Q4_1: GgmlTypeId = 3;
+pub const Q5_0: GgmlTypeId = 6;
+pub const Q5_1: GgmlTypeId = 7;
+pub const Q8_0: GgmlTypeId = 8;
+pub const Q2_K: GgmlTypeId = 10;
+pub const Q3_K: GgmlTypeId = 11;
+pub const Q4_K: GgmlTypeId = 12;
+pub const Q5_K: GgmlTypeId = 13;
+pub const Q6_K: GgmlTypeId = 14;
+pub const I8: GgmlTypeId = 24;
+pub const I16: GgmlTypeId = 25;
+pub const I32: GgmlTypeId = 26;
+pub const I64: GgmlTypeId = 27;
+pub const F64: GgmlTypeId = 28;
+pub const BF16: GgmlTypeId = 30;
+
+/// Elements in each super-block of the k-quants
+const QK_K: usize = 256;
+
+/// The id, name, block size, and block bytes of each type, as ggml numbers them in files
+const TYPES: &[(GgmlTypeId, &str, u64, u64)] = &[
+    (F32, "f32", 1, 4),
+    (F16, "f16", 1, 2),
+    (Q4_0, "q4_0", 32, 18),
+    (Q4_1, "q4_1", 32, 20),
+    (Q5_0, "q5_0", 32, 22),
+    (Q5_1, "q5_1", 32, 24),
+    (Q8_0, "q8_0", 32, 34),
+    (9, "q8_1", 32, 36),
+    (Q2_K, "q2_K", 256, 84),
+    (Q3_K, "q3_K", 256, 110),
+    (Q4_K, "q4_K", 256, 144),
+    (Q5_K, "q5_K", 256, 176),
+    (Q6_K, "q6_K", 256, 210),
+    (15, "q8_K", 256, 292),
+    (16, "iq2_xxs", 256, 66),
+    (17, "iq2_xs", 256, 74),
+    (18, "iq3_xxs", 256, 98),
+    (19, "iq1_s", 256, 50),
+    (20, "iq4_nl", 32, 18),
+    (21, "iq3_s", 256, 110),
+    (22, "iq2_s", 256, 82),
+    (23, "iq4_xs", 256, 136),
+    (I8, "i8", 1, 1),
+    (I16, "i16", 1, 2),
+    (I32, "i32", 1, 4),
+    (I64, "i64", 1, 8),
+    (F64, "f64", 1, 8),
+    (29, "iq1_m", 256, 56),
+    (BF16, "bf16", 1, 2),
+    (34, "tq1_0", 256, 54),
+    (35, "tq2_0", 256, 66),
+    (39, "mxfp4", 32, 17),
+];
+
+pub(crate) fn type_traits(ty: GgmlTypeId) -> Option<TypeTraits> {
+    let &(_, name, blck_size, type_size) = TYPES.iter().find(|(id, ..)| *id == ty)?;
+    Some(TypeTraits {
+        name,
+        blck_size,
+        type_size,
+    })
+}
+
+/// Converts one block of a type to floats
+type Dequantize = fn(&[u8], &mut [f32]);
+/// Converts floats to one block of a type
+type Quantize = fn(&[f32], &mut [u8]);
+
+fn half(bytes: &[u8]) -> f32 {
+    f16::from_le_bytes([bytes[0], bytes[1]]).to_f32()
+}
+
+fn dequantize_block(ty: GgmlTypeId) -> Option<Dequantize> {
+    Some(match ty {
+        F32 => |x, y| y[0] = f32::from_le_bytes([x[0], x[1], x[2], x[3]]),
+        F16 => |x, y| y[0] = half(x),
+        BF16 => |x, y| y[0] = bf16::from_le_bytes([x[0], x[1]]).to_f32(),
+        Q4_0 => dequantize_q4_0,
+        Q4_1 => dequantize_q4_1,
+        Q5_0 => dequantize_q5_0,
+        Q5_1 => dequantize_q5_1,
+        Q8_0 => dequantize_q8_0,
+        Q4_K => dequantize_q4_k,
+        Q5_K => dequantize_q5_k,
+        Q6_K => dequantize_q6_k,
+        _ => return None,
+    })
+}
+
+fn quantize_block(ty: GgmlTypeId) -> Option<Quantize> {
+    Some(match ty {
+        Q4_0 => quantize_q4_0,
+        Q8_0 => quantize_q8_0,
+        _ => return None,
+    })
+}
+
+pub(crate) fn can_quantize(ty: GgmlTypeId) -> bool {
+    quantize_block(ty).is_some()
+}
+
+/// Fills `bytes` with rows of floats, returning how many bytes were written. Rows are a whole
+/// number of blocks, so they can be quantized as one run of blocks.
+pub(crate) fn quantize_rows(
+    ty: GgmlTypeId,
+    floats: &[f32],
+    bytes: &mut [u8],
+    _nrows: i64,
+    _n_per_row: i64,
+) -> usize {
+    let (Some(quantize), Some(traits)) = (quantize_block(ty), type_traits(ty)) else {
+        return 0;
+    };
+    let blocks = floats
+        .chunks_exact(traits.blck_size as usize)
+        .zip(bytes.chunks_exact_mut(traits.type_size as usize));
+    let mut written = 0;
+    for (x, y) in blocks {
+        quantize(x, y);
+        written += y.len();
+    }
+    written
+}
+
+/// Fills `floats` from whole blocks of `bytes`, or returns false if `ty` isn't supported
+pub(crate) fn to_float(ty: GgmlTypeId, bytes: &[u8], floats: &mut [f32]) -> bool {
+    let (Some(dequantize), Some(traits)) = (dequantize_block(ty), type_traits(ty)) else {
+        return false;
+    };
+    let blocks = bytes
+        .chunks_exact(traits.type_size as usize)
+        .zip(floats.chunks_exact_mut(traits.blck_size as usize));
+    for (x, y) in blocks {
+        dequantize(x, y);
+    }
+    true
+}
+
+fn dequantize_q4_0(x: &[u8], y: &mut [f32]) {
+    let d = half(x);
+    let qs = &x[2..18];
+    for (j, &q) in qs.iter().enumerate() {
+        y[j] = ((q & 0xF) as i32 - 8) as f32 * d;
+        y[j + 16] = ((q >> 4) as i32 - 8) as f32 * d;
+    }
+}
+
+fn dequantize_q4_1(x: &[u8], y: &mut [f32]) {
+    let d = half(x);
+    let m = half(&x[2..]);
+    let qs = &x[4..20];
+    for (j, &q) in qs.iter().enumerate() {
+        y[j] = (q & 0xF) as f32 * d + m;
+        y[j + 16] = (q >> 4) as f32 * d + m;
+    }
+}
+
+fn dequantize_q5_0(x: &[u8], y: &mut [f32]) {
+    let d = half(x);
+    let qh = u32::from_le_bytes([x[2], x[3], x[4], x[5]]);
+    let qs = &x[6..22];
+    for (j, &q) in qs.iter().enumerate() {
+        let h0 = ((qh >> j) << 4) & 0x10;
+        let h1 = (qh >> (j + 12)) & 0x10;
+        y[j] = (((q & 0xF) as u32 | h0) as i32 - 16) as f32 * d;
+        y[j + 16] = (((q >> 4) as u32 | h1) as i32 - 16) as f32 * d;
+    }
+}
+
+fn dequantize_q5_1(x: &[u8], y: &mut [f32]) {
+    let d = half(x);
+    let m = half(&x[2..]);
+    let qh = u32::from_le_bytes([x[4], x[5], x[6], x[7]]);
+    let qs = &x[8..24];
+    for (j, &q) in qs.iter().enumerate() {
+        let h0 = ((qh >> j) << 4) & 0x10;
+        let h1 = (qh >> (j + 12)) & 0x10;
+        y[j] = ((q & 0xF) as u32 | h0) as f32 * d + m;
+        y[j + 16] = ((q >> 4) as u32 | h1) as f32 * d + m;
+    }
+}
+
+fn dequantize_q8_0(x: &[u8], y: &mut [f32]) {
+    let d = half(x);
+    for (y, &q) in y.iter_mut().zip(&x[2..34]) {
+        *y = q as i8 as f32 * d;
+    }
+}
+
+/// The 6-bit scale and min of sub-block `j`, packed into 12 bytes for each k-quant block
+fn scale_min_k4(j: usize, q: &[u8]) -> (f32, f32) {
+    let (d, m) = if j < 4 {
+        (q[j] & 63, q[j + 4] & 63)
+    } else {
+        (
+            (q[j + 4] & 0xF) | ((q[j - 4] >> 6) << 4),
+            (q[j + 4] >> 4) | ((q[j] >> 6) << 4),
+        )
+    };
+    (d as f32, m as f32)
+}
+
+fn dequantize_q4_k(x: &[u8], y: &mut [f32]) {
+    let d = half(x);
+    let min = half(&x[2..]);
+    let scales = &x[4..16];
+    let qs = &x[16..144];
+    for (n, (q, y)) in qs.chunks_exact(32).zip(y.chunks_exact_mut(64)).enumerate() {
+        let (sc1, m1) = scale_min_k4(2 * n, scales);
+        let (sc2, m2) = scale_min_k4(2 * n + 1, scales);
+        for (l, &q) in q.iter().enumerate() {
+            y[l] = d * sc1 * (q & 0xF) as f32 - min * m1;
+            y[l + 32] = d * sc2 * (q >> 4) as f32 - min * m2;
+        }
+    }
+}
+
+fn dequantize_q5_k(x: &[u8], y: &mut [f32]) {
+    let d = half(x);
+    let min = half(&x[2..]);
+    let scales = &x[4..16];
+    let qh = &x[16..48];
+    let qs = &x[48..176];
+    for (n, (q, y)) in qs.chunks_exact(32).zip(y.chunks_exact_mut(64)).enumerate() {
+        let (sc1, m1) = scale_min_k4(2 * n, scales);
+        let (sc2, m2) = scale_min_k4(2 * n + 1, scales);
+        let (u1, u2) = (1 << (2 * n), 2 << (2 * n));
+        for (l, &q) in q.iter().enumerate() {
+            let h1 = if qh[l] & u1 != 0 { 16 } else { 0 };
+            let h2 = if qh[l] & u2 != 0 { 16 } else { 0 };
+            y[l] = d * sc1 * ((q & 0xF) + h1) as f32 - min * m1;
+            y[l + 32] = d * sc2 * ((q >> 4) + h2) as f32 - min * m2;
+        }
+    }
+}
+
+fn dequantize_q6_k(x: &[u8], y: &mut [f32]) {
+    let ql = &x[..128];
+    let qh = &x[128..192];
+    let scales = &x[192..208];
+    let d = half(&x[208..]);
+    for n in 0..QK_K / 128 {
+        let (ql, qh) = (&ql[64 * n..], &qh[32 * n..]);
+        let sc = |i: usize| scales[8 * n + i] as i8 as f32;
+        let y = &mut y[128 * n..];
+        for l in 0..32 {
+            let is = l / 16;
+            let q1 = ((ql[l] & 0xF) | ((qh[l] & 3) << 4)) as i32 - 32;
+            let q2 = ((ql[l + 32] & 0xF) | (((qh[l] >> 2) & 3) << 4)) as i32 - 32;
+            let q3 = ((ql[l] >> 4) | (((qh[l] >> 4) & 3) << 4)) as i32 - 32;
+            let q4 = ((ql[l + 32] >> 4) | (((qh[l] >> 6) & 3) << 4)) as i32 - 32;
+            y[l] = d * sc(is) * q1 as f32;
+            y[l + 32] = d * sc(is + 2) * q2 as f32;
+            y[l + 64] = d * sc(is + 4) * q3 as f32;
+            y[l + 96] = d * sc(is + 6) * q4 as f32;
+        }
+    }
+}
+
+fn quantize_q4_0(x: &[f32], y: &mut [u8]) {
+    // The value furthest from zero maps to -8, keeping its sign
+    let max = x
+        .iter()
+        .copied()
+        .fold(0.0f32, |max, v| if v.abs() > max.abs() { v } else { max });
+    let d = max / -8.0;
+    let id = if d != 0.0 { 1.0 / d } else { 0.0 };
+    y[..2].copy_from_slice(&f16::from_f32(d).to_le_bytes());
+    for j in 0..16 {
+        let x0 = ((x[j] * id + 8.5) as i8).min(15) as u8;
+        let x1 = ((x[j + 16] * id + 8.5) as i8).min(15) as u8;
+        y[2 + j] = x0 | (x1 << 4);
+    }
+}
+
+fn quantize_q8_0(x: &[f32], y: &mut [u8]) {
+    let amax = x.iter().fold(0.0f32, |max, v| max.max(v.abs()));
+    let d = amax / 127.0;
+    let id = if d != 0.0 { 1.0 / d } else { 0.0 };
+    y[..2].copy_from_slice(&f16::from_f32(d).to_le_bytes());
+    for (q, &v) in y[2..].iter_mut().zip(x) {
+        *q = (v * id).round() as i8 as u8;
+    }
+}
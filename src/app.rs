@@ -1,36 +1,80 @@
-use anyhow::{Error, bail};
+use anyhow::{Error, anyhow, bail};
 use human_format::{Formatter, Scales};
+use image::RgbaImage;
 use lexical_sort::natural_lexical_cmp;
 use owning_ref::ArcRef;
-use ratatui::crossterm::event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode};
+use ratatui::buffer::Buffer;
+use ratatui::crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers, MouseButton,
+    MouseEvent, MouseEventKind,
+};
 use ratatui::crossterm::execute;
 use ratatui::crossterm::terminal::{
     EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
 };
-use ratatui::layout::{Constraint, Direction, Layout, Rect};
-use ratatui::style::{Color, Style, Stylize};
+use ratatui::layout::{Constraint, Direction, Layout, Margin, Position, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::symbols::Marker;
 use ratatui::text::{Line, Text};
 use ratatui::widgets::{
-    Block, Borders, Clear, List, ListItem, ListState, Paragraph, StatefulWidget, Wrap,
+    Axis, Block, Borders, Cell, Chart, Clear, Dataset, GraphType, List, ListItem, ListState,
+    Paragraph, Row, Scrollbar, ScrollbarOrientation, ScrollbarState, StatefulWidget, Table,
+    TableState, Widget, Wrap,
 };
 use ratatui::{Terminal, backend::CrosstermBackend};
+use regex::Regex;
 use serde_json::Value;
 use std::cell::RefCell;
-use std::collections::HashSet;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::hash::Hash;
 use std::io::{Stdout, stdout};
 use std::mem;
-use std::path::PathBuf;
-use std::sync::atomic::Ordering::Relaxed;
-use std::sync::{Arc, Mutex, OnceLock};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering::Relaxed};
+use std::sync::{Arc, Mutex, OnceLock, mpsc};
 use std::time::Duration;
 use weakref::Own;
 
-use crate::analysis::{Analysis, AnalysisCell, start_analysis_thread};
-use crate::gguf::Gguf;
-use crate::model::{Key, ModuleInfo, ModuleSource, PathSplit, shorten_value};
-use crate::safetensors::Safetensors;
-use crate::storage::FileStorage;
+use crate::bookmarks::Bookmarks;
+use crate::cache::{AnalysisCache, CacheEntry, CachedAnalysis};
+use crate::config::{Config, Theme};
+use crate::export::{SaveJob, export_tensor, save_as, start_export_tensor, start_save_as};
+use crate::headless::glob_regex;
+use crate::palette::{Command, matching_commands, matching_names, split_input};
+use crate::recent::RecentFiles;
+use crate::thumbnail::{HalfBlocks, decode_data_uri};
+use checkpoint_core::analysis::{
+    Analysis, AnalysisCell, AnalysisRequest, BarChart, BatchAnalysis, Comparison, HealthScan,
+    ModuleAnalysis, QUANT_ERROR_TYPES, Sampling, ScalarValues, Stats, TensorHealth, is_previewable,
+    set_sampling, start_analysis_thread, start_batch_analysis, start_comparison, start_health_scan,
+    start_scalar_values, start_tensor_analysis,
+};
+use checkpoint_core::arch::{SummaryLine, summarize};
+use checkpoint_core::duplicates::{DuplicateScan, start_duplicate_scan};
+use checkpoint_core::embedding::{
+    EmbeddingAnalysis, EmbeddingStats, Neighbors, RowFlag, find_embedding, start_embedding,
+    start_neighbors,
+};
+use checkpoint_core::heads::{HeadAnalysis, HeadFlag, HeadLayout, attention_heads, start_heads};
+use checkpoint_core::integrity::Problem;
+use checkpoint_core::lora::{
+    LoraAnalysis, LoraPair, find_lora_pairs, load_alphas, start_lora_analysis,
+};
+use checkpoint_core::manifest::{ManifestJob, start_manifest};
+use checkpoint_core::model::{
+    Key, LE, MetadataType, ModuleInfo, ModuleSource, PathSplit, TensorInfo, TensorTy,
+    as_lazy_array, page_lazy_array, path_conflicts, shorten_value,
+};
+use checkpoint_core::notify;
+use checkpoint_core::optim::{OptimizerAnalysis, group_optimizer_state, start_optimizer_analysis};
+use checkpoint_core::progression::{LayerMetric, Progression, start_progression};
+use checkpoint_core::registry::{FoundFile, SourceRegistry, directory_format};
+use checkpoint_core::similarity::{Similarity, start_similarity};
+use checkpoint_core::slice::TensorSlice;
+use checkpoint_core::storage::is_object_url;
+use checkpoint_core::tokenizer::{Vocab, token_type_name};
 
 pub trait TreeData: Send + Sync {
     type Id: Ord + Hash + Clone;
@@ -38,19 +82,124 @@ pub trait TreeData: Send + Sync {
     fn has_children(&self) -> bool;
     fn children(this: ArcRef<Self>) -> Box<dyn Iterator<Item = (String, ArcRef<Self>)>>;
     fn unique_id(&self) -> Self::Id;
+
+    /// Ordering under the given sort mode, or `None` to fall back to name order
+    fn compare(&self, _other: &Self, _mode: SortMode) -> Option<Ordering> {
+        None
+    }
+
+    /// How many tensors at or below this item pass the filter, or `None` if filters don't
+    /// apply to this kind of tree
+    fn matching(&self, _filter: &TensorFilter) -> Option<u64> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortMode {
+    #[default]
+    Name,
+    Params,
+    Bytes,
+    Dtype,
+}
+
+impl SortMode {
+    fn next(self) -> Self {
+        match self {
+            SortMode::Name => SortMode::Params,
+            SortMode::Params => SortMode::Bytes,
+            SortMode::Bytes => SortMode::Dtype,
+            SortMode::Dtype => SortMode::Name,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SortMode::Name => "name",
+            SortMode::Params => "params",
+            SortMode::Bytes => "size",
+            SortMode::Dtype => "dtype",
+        }
+    }
+}
+
+/// Which tensors the module tree shows, set with `:filter`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TensorFilter {
+    /// Tensors stored in a block-quantized ggml type
+    Quantized,
+    /// Tensors stored as this dtype, ignoring case
+    Dtype(String),
+    /// Tensors taking more than this many bytes
+    Larger(u64),
+}
+
+impl TensorFilter {
+    fn matches(&self, tensor: &TensorInfo) -> bool {
+        match self {
+            TensorFilter::Quantized => matches!(tensor.ty, TensorTy::Ggml(_)),
+            TensorFilter::Dtype(name) => tensor.ty.to_string().eq_ignore_ascii_case(name),
+            TensorFilter::Larger(bytes) => tensor.size as u64 > *bytes,
+        }
+    }
+
+    fn label(&self, format_bytes: impl Fn(u64) -> String) -> String {
+        match self {
+            TensorFilter::Quantized => "quantized".to_string(),
+            TensorFilter::Dtype(name) => name.to_uppercase(),
+            TensorFilter::Larger(bytes) => format!("> {}", format_bytes(*bytes)),
+        }
+    }
+}
+
+impl FromStr for TensorFilter {
+    type Err = Error;
+
+    /// Parses `quantized`, a size like `>100MB` or `>1.5GiB`, or the name of a dtype
+    fn from_str(text: &str) -> Result<Self, Error> {
+        if text.eq_ignore_ascii_case("quantized") {
+            return Ok(TensorFilter::Quantized);
+        }
+        let Some(size) = text.strip_prefix('>') else {
+            if text.is_empty() || !text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                bail!("expected quantized, a dtype, or >SIZE, not {text:?}");
+            }
+            return Ok(TensorFilter::Dtype(text.to_string()));
+        };
+        let size = size.trim();
+        let split = size
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(size.len());
+        let (number, unit) = size.split_at(split);
+        let scale: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "k" | "kb" => 1000,
+            "m" | "mb" => 1_000_000,
+            "g" | "gb" => 1_000_000_000,
+            "kib" => 1 << 10,
+            "mib" => 1 << 20,
+            "gib" => 1 << 30,
+            _ => bail!("unknown size unit {unit:?}"),
+        };
+        let number: f64 = number
+            .parse()
+            .map_err(|_| anyhow!("expected a size like >100MB, not {text:?}"))?;
+        Ok(TensorFilter::Larger((number * scale as f64) as u64))
+    }
 }
 
 impl TreeData for ModuleInfo {
     type Id = Key;
 
     fn has_children(&self) -> bool {
-        !self.children.is_empty()
+        ModuleInfo::has_children(self)
     }
 
     fn children(this: ArcRef<Self>) -> Box<dyn Iterator<Item = (String, ArcRef<Self>)>> {
-        let keys: Vec<_> = this.children.keys().cloned().collect();
+        let keys: Vec<_> = this.children().keys().cloned().collect();
         Box::new(keys.into_iter().map(move |key| {
-            let child = this.clone().map(|m| &m.children[&key]);
+            let child = this.clone().map(|m| &m.children()[&key]);
             (key.to_string(), child)
         }))
     }
@@ -58,11 +207,22 @@ impl TreeData for ModuleInfo {
     fn unique_id(&self) -> Self::Id {
         self.full_name.clone()
     }
-}
 
-impl ModuleInfo {
-    pub fn is_tensor(&self) -> bool {
-        self.tensor_info.is_some()
+    fn compare(&self, other: &Self, mode: SortMode) -> Option<Ordering> {
+        match mode {
+            SortMode::Name => None,
+            SortMode::Params => Some(other.total_params.cmp(&self.total_params)),
+            SortMode::Bytes => Some(other.total_bytes.cmp(&self.total_bytes)),
+            SortMode::Dtype => {
+                let dtype = |m: &ModuleInfo| m.tensor_info.as_ref().map(|t| t.ty.to_string());
+                Some(dtype(self).cmp(&dtype(other)))
+            }
+        }
+    }
+
+    fn matching(&self, filter: &TensorFilter) -> Option<u64> {
+        let tensors = self.tensors();
+        Some(tensors.iter().filter(|(_, t)| filter.matches(t)).count() as u64)
     }
 }
 
@@ -70,6 +230,9 @@ impl TreeData for Value {
     type Id = *const Value;
 
     fn has_children(&self) -> bool {
+        if as_lazy_array(self).is_some() {
+            return true;
+        }
         match self {
             Value::Object(map) => !map.is_empty(),
             Value::Array(arr) => !arr.is_empty(),
@@ -79,6 +242,8 @@ impl TreeData for Value {
 
     fn children(this: ArcRef<Self>) -> Box<dyn Iterator<Item = (String, ArcRef<Self>)>> {
         match &*this {
+            // Placeholders have no children until they're paged in
+            value if as_lazy_array(value).is_some() => Box::new(std::iter::empty()),
             Value::Object(map) => {
                 let keys: Vec<_> = map.keys().cloned().collect();
                 let iter: Box<dyn Iterator<Item = (String, ArcRef<Self>)>> =
@@ -115,28 +280,170 @@ impl TreeData for Value {
         // Use the pointer address as a unique identifier for Value items
         self as *const Value
     }
+
+    fn compare(&self, other: &Self, mode: SortMode) -> Option<Ordering> {
+        let len = |v: &Value| match v {
+            Value::Object(map) => map.len(),
+            Value::Array(arr) => arr.len(),
+            _ => 1,
+        };
+        let kind = |v: &Value| match v {
+            Value::Null => "null",
+            Value::Bool(_) => "bool",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+        };
+        match mode {
+            SortMode::Name => None,
+            SortMode::Params => Some(len(other).cmp(&len(self))),
+            SortMode::Bytes => Some(other.to_string().len().cmp(&self.to_string().len())),
+            SortMode::Dtype => Some(kind(self).cmp(kind(other))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum HistogramMode {
+    #[default]
+    Signed,
+    Magnitude,
+    LogMagnitude,
+    RowNorms,
+    ColumnNorms,
+    /// The signed histogram compared against common init distributions
+    Init,
+}
+
+impl HistogramMode {
+    /// Channel norms are only offered for matrices
+    fn next(self, is_matrix: bool) -> Self {
+        match self {
+            HistogramMode::Signed => HistogramMode::Magnitude,
+            HistogramMode::Magnitude => HistogramMode::LogMagnitude,
+            HistogramMode::LogMagnitude if is_matrix => HistogramMode::RowNorms,
+            HistogramMode::RowNorms if is_matrix => HistogramMode::ColumnNorms,
+            HistogramMode::LogMagnitude | HistogramMode::ColumnNorms => HistogramMode::Init,
+            _ => HistogramMode::Signed,
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            HistogramMode::Signed => "Histogram",
+            HistogramMode::Magnitude => "Histogram |x|",
+            HistogramMode::LogMagnitude => "Histogram log10|x|",
+            HistogramMode::RowNorms => "Row Norms",
+            HistogramMode::ColumnNorms => "Column Norms",
+            HistogramMode::Init => "Histogram vs. Init",
+        }
+    }
+
+    /// Labels a value on the histogram's x axis
+    fn format_value(self, x: f32) -> String {
+        if self == HistogramMode::LogMagnitude {
+            format!("1e{x:.1}")
+        } else {
+            format!("{x:.2}")
+        }
+    }
+}
+
+/// Which statistic the module view compares across children
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum ChildMetric {
+    #[default]
+    L2Norm,
+    Mean,
+    Std,
+    Min,
+    Max,
+    Zeros,
+}
+
+impl ChildMetric {
+    fn next(self) -> Self {
+        match self {
+            ChildMetric::L2Norm => ChildMetric::Mean,
+            ChildMetric::Mean => ChildMetric::Std,
+            ChildMetric::Std => ChildMetric::Min,
+            ChildMetric::Min => ChildMetric::Max,
+            ChildMetric::Max => ChildMetric::Zeros,
+            ChildMetric::Zeros => ChildMetric::L2Norm,
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            ChildMetric::L2Norm => "Child L2 Norms",
+            ChildMetric::Mean => "Child Means",
+            ChildMetric::Std => "Child Std Devs",
+            ChildMetric::Min => "Child Minimums",
+            ChildMetric::Max => "Child Maximums",
+            ChildMetric::Zeros => "Child Zero Fractions",
+        }
+    }
+
+    fn of(self, stats: &Stats) -> f64 {
+        match self {
+            ChildMetric::L2Norm => stats.l2_norm,
+            ChildMetric::Mean => stats.mean,
+            ChildMetric::Std => stats.std,
+            ChildMetric::Min => stats.min as f64,
+            ChildMetric::Max => stats.max as f64,
+            ChildMetric::Zeros => stats.zero_fraction,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
 enum Panel {
     #[default]
     Tree,
+    /// The file's bookmarks, under the tree
+    Bookmarks,
     SelectedInfo,
     FileInfo,
     Analysis,
 }
 
+/// What woke the event loop to draw again
+enum Wake {
+    Input(std::io::Result<Event>),
+    /// Background work made progress or finished
+    Work,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum DialogType {
     Edit,
     Delete,
+    /// A new metadata entry, drafted as `key = value`
+    AddMetadata,
+    /// A path to load, with tab completion
+    Open,
+    /// NumPy-style indices picking the part of the selected tensor to analyze
+    Slice,
+    /// The range of values to zoom the histogram into
+    Range,
+    Export,
+    Rename,
+    DeleteTensors(Vec<String>),
+    SaveAs,
+    Saving,
+    Palette,
+    /// Fuzzy finder over every tensor name
+    Jump,
+    Notice(String),
     Error(String),
 }
 
 impl Panel {
-    fn next(self, analysis: bool) -> Self {
+    fn next(self, analysis: bool, bookmarks: bool) -> Self {
         match self {
-            Panel::Tree => Panel::FileInfo,
+            Panel::Tree if bookmarks => Panel::Bookmarks,
+            Panel::Tree | Panel::Bookmarks => Panel::FileInfo,
             Panel::SelectedInfo => Panel::FileInfo,
             Panel::FileInfo if analysis => Panel::Analysis,
             Panel::FileInfo => Panel::Tree,
@@ -144,28 +451,385 @@ impl Panel {
         }
     }
 
-    fn prev(self, analysis: bool) -> Self {
+    fn prev(self, analysis: bool, bookmarks: bool) -> Self {
         match self {
             Panel::Tree if analysis => Panel::Analysis,
             Panel::Tree => Panel::FileInfo,
-            Panel::SelectedInfo => Panel::Tree,
+            Panel::Bookmarks | Panel::SelectedInfo => Panel::Tree,
+            Panel::FileInfo if bookmarks => Panel::Bookmarks,
             Panel::FileInfo => Panel::Tree,
             Panel::Analysis => Panel::FileInfo,
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum TableColumn {
+    #[default]
+    Name,
+    Shape,
+    Dtype,
+    Params,
+    Bytes,
+    Mean,
+    Std,
+    Norm,
+}
+
+impl TableColumn {
+    const ALL: [TableColumn; 8] = [
+        TableColumn::Name,
+        TableColumn::Shape,
+        TableColumn::Dtype,
+        TableColumn::Params,
+        TableColumn::Bytes,
+        TableColumn::Mean,
+        TableColumn::Std,
+        TableColumn::Norm,
+    ];
+
+    fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&c| c == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            TableColumn::Name => "Name",
+            TableColumn::Shape => "Shape",
+            TableColumn::Dtype => "Dtype",
+            TableColumn::Params => "Params",
+            TableColumn::Bytes => "Bytes",
+            TableColumn::Mean => "Mean",
+            TableColumn::Std => "Std",
+            TableColumn::Norm => "L2 Norm",
+        }
+    }
+
+    fn is_numeric(self) -> bool {
+        !matches!(
+            self,
+            TableColumn::Name | TableColumn::Shape | TableColumn::Dtype
+        )
+    }
+}
+
+struct TableView {
+    rows: Vec<(String, TensorInfo)>,
+    sort: TableColumn,
+    descending: bool,
+    state: RefCell<TableState>,
+}
+
+/// Every LoRA adapter in the file, with the spectrum of the selected one's merged update
+struct LoraView {
+    pairs: Vec<LoraPair>,
+    state: RefCell<TableState>,
+    analysis: Option<Own<Box<LoraAnalysis>>>,
+}
+
+/// Cosine similarity between every pair of tensors matching a pattern, as a heatmap
+struct SimilarityView {
+    pattern: String,
+    analysis: Own<Box<Similarity>>,
+    /// The selected cell, as a row and column of the matrix
+    cursor: (usize, usize),
+}
+
+/// One metric of every tensor in a family, charted against layer index
+struct ProgressionView {
+    pattern: String,
+    analysis: Own<Box<Progression>>,
+    /// The selected layer, as an index into the family
+    cursor: usize,
+}
+
+/// What the tokenizer view does with its input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenizerMode {
+    Search,
+    Encode,
+    /// Lists the tokens whose embeddings look untrained, filtered by the input
+    Untrained,
+}
+
+/// Norms and spectra of each head of one attention projection
+struct HeadView {
+    analysis: Own<Box<HeadAnalysis>>,
+    /// The selected head
+    cursor: usize,
+}
+
+/// Searches the embedded vocabulary, or encodes sample text with it
+struct TokenizerView {
+    vocab: Vocab,
+    mode: TokenizerMode,
+    input: String,
+    /// Token ids matching the search, or the encoding of the input
+    rows: Vec<u32>,
+    error: Option<String>,
+    selected: usize,
+    /// First visible row, as of the last render
+    offset: std::cell::Cell<usize>,
+    /// Per-token statistics of the embedding matrix, if the file has one matching the vocabulary
+    embedding: Option<Own<Box<EmbeddingAnalysis>>>,
+    /// The tokens closest to the last one picked with Enter
+    neighbors: Option<Own<Box<Neighbors>>>,
+    /// Whether `rows` should be listed again once the embedding statistics are ready
+    stale: bool,
+}
+
+/// Every checkpoint in a directory, to pick one to open
+struct DirectoryView {
+    dir: PathBuf,
+    files: Vec<FoundFile>,
+    state: RefCell<TableState>,
+}
+
+/// Every integrity problem found in the file's layout
+struct DiagnosticsView {
+    state: RefCell<TableState>,
+}
+
+/// Tensors queued with `A`, kept after they finish so browsing them again is instant
+struct BatchQueue {
+    /// The module the tensors were queued from
+    module: String,
+    analysis: Own<Box<BatchAnalysis>>,
+    /// How many finished results have been written to the analysis cache
+    stored: usize,
+}
+
+struct BatchView {
+    state: RefCell<TableState>,
+}
+
+/// Every tensor in the order it is stored, with the bytes left between each and the last
+struct LayoutView {
+    /// Sorted by offset, each with the gap since the furthest any earlier tensor reached,
+    /// which is negative where they overlap
+    tensors: Vec<(String, TensorInfo, i64)>,
+    state: RefCell<TableState>,
+}
+
+/// Parameters and bytes of one group in the breakdown view
+struct BreakdownRow {
+    label: String,
+    params: u64,
+    bytes: u64,
+}
+
+/// Parameters and bytes grouped by dtype, quantization type, and top-level module, each group
+/// drawn as a bar
+struct BreakdownView {
+    sections: Vec<(&'static str, Vec<BreakdownRow>)>,
+    scroll: u16,
+}
+
+/// One rectangle of the treemap
+struct TreemapCell {
+    area: Rect,
+    /// The node's name within its parent
+    key: Key,
+    full_name: Key,
+    bytes: u64,
+}
+
+/// The module tree drawn as nested rectangles sized by bytes
+#[derive(Default)]
+struct TreemapView {
+    /// Laid out again whenever the view is drawn, parents before their children
+    cells: Vec<TreemapCell>,
+    selected: usize,
+}
+
+impl TreemapView {
+    /// Moves to the nearest rectangle whose center lies in the direction `(dx, dy)`
+    fn step(&mut self, dx: i32, dy: i32) {
+        // Doubled, so the centers of odd-sized rectangles stay whole
+        let center = |area: &Rect| {
+            (
+                area.x as i32 * 2 + area.width as i32,
+                area.y as i32 * 2 + area.height as i32,
+            )
+        };
+        let Some(from) = self.cells.get(self.selected) else {
+            return;
+        };
+        let (x0, y0) = center(&from.area);
+        let nearest = self
+            .cells
+            .iter()
+            .enumerate()
+            .filter_map(|(i, cell)| {
+                let (x, y) = center(&cell.area);
+                let along = (x - x0) * dx + (y - y0) * dy;
+                let across = ((x - x0) * dy - (y - y0) * dx).abs();
+                (along > 0).then_some((along + 2 * across, i))
+            })
+            .min();
+        if let Some((_, i)) = nearest {
+            self.selected = i;
+        }
+    }
+
+    /// The innermost rectangle under the cell at `(x, y)`
+    fn cell_at(&self, x: u16, y: u16) -> Option<usize> {
+        self.cells
+            .iter()
+            .rposition(|cell| cell.area.contains(Position { x, y }))
+    }
+}
+
+/// A `data:image/` metadata value decoded for display
+struct ImageView {
+    key: String,
+    image: RgbaImage,
+}
+
+impl TokenizerView {
+    fn refresh(&mut self) {
+        self.error = None;
+        self.stale = false;
+        self.rows = match self.mode {
+            TokenizerMode::Encode => match self.vocab.encode(&self.input) {
+                Ok(ids) => ids,
+                Err(err) => {
+                    self.error = Some(err.to_string());
+                    Vec::new()
+                }
+            },
+            TokenizerMode::Search if self.input.is_empty() => {
+                (0..self.vocab.tokens.len() as u32).collect()
+            }
+            TokenizerMode::Search => self.vocab.search(&self.input),
+            TokenizerMode::Untrained => {
+                let stats = self.embedding.as_ref().and_then(|e| e.stats.get());
+                self.stale = stats.is_none();
+                let mut ids = stats.map_or_else(Vec::new, EmbeddingStats::flagged);
+                ids.retain(|&id| self.vocab.tokens[id as usize].contains(&self.input));
+                ids
+            }
+        };
+        self.selected = 0;
+        self.offset.set(0);
+    }
+
+    fn select_by(&mut self, delta: isize) {
+        let last = self.rows.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    fn next_mode(&mut self) {
+        self.mode = match self.mode {
+            TokenizerMode::Search => TokenizerMode::Encode,
+            TokenizerMode::Encode if self.embedding.is_some() => TokenizerMode::Untrained,
+            TokenizerMode::Encode | TokenizerMode::Untrained => TokenizerMode::Search,
+        };
+        self.refresh();
+    }
+}
+
+/// The raw bytes of one tensor, read a screenful at a time
+struct ByteView {
+    name: String,
+    tensor: TensorInfo,
+    /// Start of the first visible row, relative to the start of the tensor
+    offset: usize,
+    /// How many rows fit on screen, as of the last render
+    rows: usize,
+    /// The visible bytes, or `None` once the view has moved and they need to be read again
+    page: Option<Result<Vec<u8>, String>>,
+}
+
+impl ByteView {
+    fn scroll_to(&mut self, offset: usize) {
+        let last_row = self.tensor.size.saturating_sub(1) / BYTES_PER_ROW * BYTES_PER_ROW;
+        let offset = offset.min(last_row) / BYTES_PER_ROW * BYTES_PER_ROW;
+        if offset != self.offset {
+            self.offset = offset;
+            self.page = None;
+        }
+    }
+
+    fn scroll_by(&mut self, rows: isize) {
+        let delta = rows.unsigned_abs() * BYTES_PER_ROW;
+        if rows < 0 {
+            self.scroll_to(self.offset.saturating_sub(delta));
+        } else {
+            self.scroll_to(self.offset.saturating_add(delta));
+        }
+    }
+}
+
 pub type Backend = CrosstermBackend<Stdout>;
 
-pub const PANEL_BORDER: Color = Color::White;
-pub const PANEL_BORDER_SECONDARY: Color = Color::White;
-pub const PANEL_BORDER_SELECTED: Color = Color::Yellow;
-pub const MODULE_FG: Color = Color::Blue;
-pub const TENSOR_FG: Color = Color::Cyan;
-pub const SHAPE_FG: Color = Color::White;
-pub const DTYPE_FG: Color = Color::Yellow;
-pub const COUNT_FG: Color = Color::White;
-pub const BYTESIZE_FG: Color = Color::Magenta;
+const BYTES_PER_ROW: usize = 16;
+
+/// Five lines of metrics plus borders
+const COMPARISON_HEIGHT: u16 = 7;
+
+/// Sections of the Analysis panel which share its height are never squashed below this, the
+/// panel scrolls instead
+const ANALYSIS_SECTION_MIN_HEIGHT: u16 = 10;
+
+/// How many levels of modules the treemap nests inside each other
+const TREEMAP_DEPTH: usize = 2;
+
+/// Longer group names are cut off in the breakdown view
+const BREAKDOWN_LABEL_WIDTH: usize = 32;
+
+/// How many similar tokens the tokenizer view lists for the one picked
+const NEIGHBOR_COUNT: usize = 20;
+
+/// Shows one of a tensor's statistics
+type FormatStat = fn(&Stats) -> String;
+
+/// The rows of the split view's statistics table, and how each is shown
+const SPLIT_STATS: [(&str, FormatStat); 9] = [
+    ("Mean", |stats| format!("{:.4}", stats.mean)),
+    ("Std", |stats| format!("{:.4}", stats.std)),
+    ("Min", |stats| format!("{:.4}", stats.min)),
+    ("Max", |stats| format!("{:.4}", stats.max)),
+    ("L2", |stats| format!("{:.4}", stats.l2_norm)),
+    ("Zeros", |stats| {
+        format!("{:.2}%", stats.zero_fraction * 100.0)
+    }),
+    ("NaN", |stats| stats.nan_count.to_string()),
+    ("Inf", |stats| stats.inf_count.to_string()),
+    ("Kurtosis", |stats| format!("{:.3}", stats.excess_kurtosis)),
+];
+
+/// Percent of the screen one `Ctrl-←/→` moves a panel edge
+const PANEL_WIDTH_STEP: i16 = 3;
+
+/// Narrowest a panel can be made, in percent
+const MIN_PANEL_WIDTH: i16 = 10;
+
+/// How often the status line's spinner turns while background work is running
+const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
+
+const SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+const SPINNER_ASCII: [&str; 4] = ["|", "/", "-", "\\"];
+
+/// Names in the tree are never shortened below this many columns
+const MIN_NAME_WIDTH: usize = 12;
+
+/// The bookmarks panel grows with its list up to this many rows, then scrolls
+const BOOKMARK_ROWS: usize = 8;
+
+/// The most histogram bins `+` will double up to
+const MAX_BIN_COUNT: usize = 1024;
+
+/// Target types offered by the save-as dialog
+const SAVE_TYPES: [TensorTy; 5] = [
+    TensorTy::BF16,
+    TensorTy::F16,
+    TensorTy::F32,
+    TensorTy::Ggml(ggml_base::Q8_0),
+    TensorTy::Ggml(ggml_base::Q4_K),
+];
 
 #[derive(Default)]
 pub struct App {
@@ -173,7 +837,12 @@ pub struct App {
     file_path: Option<PathBuf>,
     tree_state: Option<TreeState<ModuleInfo>>,
     meta_tree_state: Option<TreeState<Value>>,
+    /// How each top-level metadata value is stored, which edits keep
+    metadata_types: HashMap<String, MetadataType>,
     source: Option<Arc<Mutex<dyn ModuleSource + Send>>>,
+    /// A second handle onto the open file for background jobs, so their long reads never hold
+    /// the lock that edits and the byte view wait on
+    reader: Option<Arc<Mutex<dyn ModuleSource + Send>>>,
     count_formatter: Formatter,
     bytes_formatter: Formatter,
     selected_panel: Panel,
@@ -181,10 +850,108 @@ pub struct App {
     pub path_split: PathSplit,
     analysis_sender: Option<Own<Box<AnalysisCell>>>,
     current_analysis: Option<Own<Box<Analysis>>>,
+    module_analysis: Option<Own<Box<ModuleAnalysis>>>,
+    pub cache: Option<AnalysisCache>,
+    /// Formats which `load_file` can open
+    pub registry: SourceRegistry,
+    /// Overrides content sniffing when set
+    pub format: Option<String>,
+    /// Where the current tensor's results are saved once the selection moves on
+    cache_entry: Option<CacheEntry>,
+    health_scan: Option<Own<Box<HealthScan>>>,
+    /// The values of tensors with shape `[]`, shown inline in the tree
+    scalar_values: Option<Own<Box<ScalarValues>>>,
+    batch: Option<BatchQueue>,
+    duplicate_scan: Option<Own<Box<DuplicateScan>>>,
+    /// The SHA-256 of one tensor, asked for with the `hash` command
+    tensor_hash: Option<Own<Box<ManifestJob>>>,
+    /// Hashes every tensor, then writes the manifest to the path
+    manifest_job: Option<(PathBuf, Own<Box<ManifestJob>>)>,
+    /// The tensor marked with `m`, which the selected tensor is compared against
+    marked: Option<(String, TensorInfo)>,
+    comparison: Option<Own<Box<Comparison>>>,
+    /// Show the marked and selected tensors side by side, toggled with `|`
+    split_view: bool,
+    /// The marked tensor's own analysis, only run while the split view is on
+    marked_analysis: Option<Own<Box<Analysis>>>,
+    /// Fold optimizer state tensors under their parameters
+    group_optimizer: bool,
+    optimizer_analysis: Option<Own<Box<OptimizerAnalysis>>>,
+    preview_scroll: (u16, u16),
+    analysis_scroll: u16,
+    log_counts: bool,
+    histogram_mode: HistogramMode,
+    child_metric: ChildMetric,
+    /// Hyperparameters read from metadata or guessed from tensor shapes
+    architecture: Vec<SummaryLine>,
+    /// Query and key/value head counts, from `:heads` or else the file's hyperparameters
+    head_counts: Option<(usize, usize)>,
+    /// Fold numbered modules like `layers.0` … `layers.31` into a virtual `layers[*]`
+    pub group_layers: bool,
+    /// Only show tensors passing this in the module tree, set with `:filter`
+    tensor_filter: Option<TensorFilter>,
+    /// Merge chains of modules with one child each into a single item, toggled with `U`
+    flatten: bool,
+    /// How many levels of the tree are left as they are before chains get merged
+    flatten_depth: usize,
+    /// Refuse every edit to the opened file
+    pub read_only: bool,
+    /// Leave shapes, dtypes, and sizes out of the tree to make room for long names
+    hide_tensor_details: bool,
+    table_view: Option<TableView>,
+    byte_view: Option<ByteView>,
+    lora_view: Option<LoraView>,
+    similarity_view: Option<SimilarityView>,
+    progression_view: Option<ProgressionView>,
+    head_view: Option<HeadView>,
+    tokenizer_view: Option<TokenizerView>,
+    directory_view: Option<DirectoryView>,
+    diagnostics_view: Option<DiagnosticsView>,
+    breakdown_view: Option<BreakdownView>,
+    treemap_view: Option<TreemapView>,
+    batch_view: Option<BatchView>,
+    layout_view: Option<LayoutView>,
+    /// Found by checking the layout whenever the file is read
+    problems: Vec<Problem>,
+    image_view: Option<ImageView>,
+    save_job: Option<Own<Box<SaveJob>>>,
+    /// Advances each time a frame is drawn while background work is running
+    spinner_frame: usize,
+    save_type: usize,
+    save_all: bool,
+    /// The part of a tensor analyzed in its place, while that tensor stays selected
+    slice: Option<(String, TensorSlice)>,
+    /// The range of values a tensor's histogram is zoomed into, while it stays selected
+    histogram_range: Option<(String, (f32, f32))>,
+    /// Offered on the start screen when no file is loaded
+    pub recent: RecentFiles,
+    recent_state: RefCell<ListState>,
+    /// Tensors pinned with `b`, listed under the tree for the open file
+    pub bookmarks: Bookmarks,
+    bookmark_state: RefCell<ListState>,
+    palette_selected: usize,
+    /// Every tensor in the file, offered by the jump dialog
+    jump_targets: Vec<Key>,
+    bin_count: usize,
+    theme: Theme,
+    /// Swap every other character for an ASCII lookalike as frames are drawn
+    pub ascii: bool,
+    /// Draw in the terminal's default colors, marking highlights by reversing them instead
+    pub no_color: bool,
+    /// Relative widths of the tree, info, and analysis panels
+    panel_widths: [u16; 3],
+    /// Where `panel_widths` is saved, if not the default config file
+    pub config_path: Option<PathBuf>,
+    /// Keys rebound by the config file
+    keys: HashMap<char, Command>,
     histogram_size_limit: u64,
     spectrum_size_limit: u64,
     dialog_type: Option<DialogType>,
     edit_draft: String,
+    /// Where each panel was last drawn, for mouse clicks and scrolling
+    panel_areas: Vec<(Panel, Rect)>,
+    /// The metadata list within the FileInfo panel
+    meta_list_area: Rect,
 }
 
 struct TreeState<T: TreeData> {
@@ -193,6 +960,13 @@ struct TreeState<T: TreeData> {
     expanded: HashSet<T::Id>,
     visible_items: Vec<TreeItem<T>>,
     list_state: RefCell<ListState>,
+    sort: SortMode,
+    /// The children of each node expanded so far, in `sort` order
+    sorted_children: HashMap<T::Id, Vec<(String, ArcRef<T>)>>,
+    /// Hides items with no tensors passing it
+    filter: Option<TensorFilter>,
+    /// How many tensors under each child listed in `sorted_children` pass the filter
+    shown: HashMap<T::Id, u64>,
 }
 
 #[derive(Clone)]
@@ -217,9 +991,39 @@ impl<T: TreeData> TreeState<T> {
             expanded: HashSet::new(),
             visible_items: Vec::new(),
             list_state: RefCell::new(ListState::default()),
+            sort: SortMode::default(),
+            sorted_children: HashMap::new(),
+            filter: None,
+            shown: HashMap::new(),
         }
     }
 
+    fn sorted_children(&mut self, info: &ArcRef<T>) -> &[(String, ArcRef<T>)] {
+        let sort = self.sort;
+        let filter = self.filter.as_ref();
+        let shown = &mut self.shown;
+        self.sorted_children
+            .entry(info.unique_id())
+            .or_insert_with(|| {
+                let mut children: Vec<_> = T::children(info.clone()).collect();
+                if let Some(filter) = filter {
+                    children.retain(|(_, child)| {
+                        let count = child.matching(filter);
+                        if let Some(count) = count {
+                            shown.insert(child.unique_id(), count);
+                        }
+                        count != Some(0)
+                    });
+                }
+                children.sort_by(|(a_name, a), (b_name, b)| {
+                    T::compare(a, b, sort)
+                        .unwrap_or(Ordering::Equal)
+                        .then_with(|| natural_lexical_cmp(a_name, b_name))
+                });
+                children
+            })
+    }
+
     fn rebuild_visible_items(&mut self) {
         self.visible_items.clear();
         let mut stack = vec![(self.data.clone(), "".to_string(), -1)];
@@ -227,14 +1031,10 @@ impl<T: TreeData> TreeState<T> {
             // Use the unique_id method to get a proper identifier for each item
             let is_expanded = depth < 0 || self.expanded.contains(&info.unique_id());
             if is_expanded {
-                let stack_at = stack.len();
-                for (key, child) in T::children(info.clone()) {
-                    stack.push((child, key, depth + 1));
+                // Reversed, since the stack is popped from the back
+                for (key, child) in self.sorted_children(&info).iter().rev() {
+                    stack.push((child.clone(), key.clone(), depth + 1));
                 }
-                // Sort by name for now - we'll make this more sophisticated later
-                stack[stack_at..].sort_by(|(_, a_name, ..), (_, b_name, ..)| {
-                    natural_lexical_cmp(b_name, a_name)
-                });
             }
             if depth >= 0 {
                 self.visible_items.push(TreeItem {
@@ -265,10 +1065,111 @@ impl<T: TreeData> TreeState<T> {
         }
     }
 
+    fn set_filter(&mut self, filter: Option<TensorFilter>) {
+        self.filter = filter;
+        self.sorted_children.clear();
+        self.shown.clear();
+        self.rebuild_keeping_selection();
+    }
+
+    fn cycle_sort(&mut self) {
+        self.sort = self.sort.next();
+        self.sorted_children.clear();
+        self.rebuild_keeping_selection();
+    }
+
+    /// Expands every item above `depth` below the current root and collapses the rest, or
+    /// expands everything if `depth` is `None`
+    fn expand_to_depth(&mut self, depth: Option<usize>) {
+        self.expanded.clear();
+        let mut stack = vec![(self.data.clone(), 0)];
+        while let Some((info, level)) = stack.pop() {
+            for (_, child) in T::children(info) {
+                if child.has_children() && depth.is_none_or(|depth| level < depth) {
+                    self.expanded.insert(child.unique_id());
+                    stack.push((child, level + 1));
+                }
+            }
+        }
+        self.rebuild_keeping_selection();
+    }
+
+    /// Rebuilds the visible items, following the selected item to its new index, or back to
+    /// the top if it is no longer visible
+    fn rebuild_keeping_selection(&mut self) {
+        let selected = self
+            .list_state
+            .get_mut()
+            .selected()
+            .and_then(|i| self.visible_items.get(i))
+            .map(|item| item.info.unique_id());
+        self.rebuild_visible_items();
+        if let Some(id) = selected {
+            let index = self
+                .visible_items
+                .iter()
+                .position(|i| i.info.unique_id() == id);
+            self.list_state.get_mut().select(index.or(Some(0)));
+        }
+    }
+
     fn move_up(&mut self) {
         self.list_state.get_mut().select_previous();
     }
 
+    /// Returns to the top of the tree, expands every ancestor of `id`, and selects it
+    fn reveal(&mut self, id: &T::Id) {
+        fn find<T: TreeData>(node: ArcRef<T>, id: &T::Id, path: &mut Vec<T::Id>) -> bool {
+            for (_, child) in T::children(node) {
+                if child.unique_id() == *id {
+                    return true;
+                }
+                path.push(child.unique_id());
+                if child.has_children() && find(child, id, path) {
+                    return true;
+                }
+                path.pop();
+            }
+            false
+        }
+
+        if !self.data_history.is_empty() {
+            self.data = self.data_history.swap_remove(0);
+            self.data_history.clear();
+        }
+        let mut path = Vec::new();
+        if !find(self.data.clone(), id, &mut path) {
+            return;
+        }
+        self.expanded.extend(path);
+        self.rebuild_visible_items();
+        let index = self
+            .visible_items
+            .iter()
+            .position(|i| i.info.unique_id() == *id);
+        self.list_state.get_mut().select(index);
+    }
+
+    /// Selects the item drawn on terminal row `row` of a bordered list in `area`, or toggles it
+    /// if it was already selected
+    fn click(&mut self, area: Rect, row: u16) {
+        let inner = area.inner(Margin::new(1, 1));
+        if row < inner.y || row >= inner.bottom() {
+            return;
+        }
+        let state = self.list_state.get_mut();
+        let index = state.offset() + (row - inner.y) as usize;
+        if index >= self.visible_items.len() {
+            return;
+        }
+        if state.selected() == Some(index) {
+            self.toggle_expanded();
+            self.rebuild_visible_items();
+        } else {
+            state.select(Some(index));
+        }
+    }
+
     fn move_down(&mut self) {
         self.list_state.get_mut().select_next();
     }
@@ -320,63 +1221,277 @@ impl App {
         // Lower limit for histogram as it's cheaper to compute
         this.histogram_size_limit = 100 * 1024 * 1024; // 100Mi elements
         this.spectrum_size_limit = 2 * 1024 * 1024; // 2Mi elements (SVD is more expensive)
+        this.bin_count = 20;
+        this.panel_widths = [33, 33, 34];
+        this.flatten = true;
         this
     }
 
+    pub fn apply_config(&mut self, config: &Config) -> Result<(), Error> {
+        self.theme = config.theme()?;
+        self.keys = config.keys()?;
+        if let Some(widths) = config.panel_widths {
+            if widths
+                .iter()
+                .any(|&width| !(MIN_PANEL_WIDTH..=100).contains(&(width as i16)))
+            {
+                bail!("panel_widths must each be between {MIN_PANEL_WIDTH} and 100");
+            }
+            self.panel_widths = widths;
+        }
+        if let Some(limit) = config.histogram_size_limit {
+            self.histogram_size_limit = limit;
+        }
+        if let Some(limit) = config.spectrum_size_limit {
+            self.spectrum_size_limit = limit;
+        }
+        if let Some(backup) = config.backup {
+            self.registry.backup = backup;
+        }
+        if let Some(flatten) = config.flatten {
+            self.flatten = flatten;
+        }
+        if let Some(depth) = config.flatten_depth {
+            self.flatten_depth = depth;
+        }
+        if let Some(bins) = config.bins {
+            if bins == 0 {
+                bail!("bins must be positive");
+            }
+            self.bin_count = bins;
+        }
+        let mut sampling = Sampling {
+            seed: config.sample_seed,
+            ..Sampling::default()
+        };
+        if let Some(limit) = config.exact_quantile_limit {
+            sampling.exact_limit = limit;
+        }
+        set_sampling(sampling)?;
+        Ok(())
+    }
+
     pub fn load_file(&mut self, file_path: PathBuf) -> Result<(), Error> {
-        let ext = file_path.extension().and_then(|ext| ext.to_str());
-        let storage = FileStorage::new(file_path.clone());
-        if ext == Some("safetensors") {
-            self.source = Some(Arc::new(Mutex::new(Safetensors::open(storage)?)));
-        } else if ext == Some("gguf") {
-            self.source = Some(Arc::new(Mutex::new(Gguf::open(storage)?)));
-        } else {
-            bail!("could not infer file type");
+        if file_path.is_dir() && directory_format(&file_path).is_none() {
+            return self.open_directory(file_path);
         }
+        // Keep the old file open if the new one can't be read
+        let source = self.registry.open(&file_path, self.format.as_deref())?;
+        self.close_file();
+        // Failing to remember the file isn't worth failing to open it
+        let _ = self.recent.push(&file_path);
+        self.source = Some(source);
         self.file_path = Some(file_path);
         self.rebuild_module()
     }
 
-    pub fn rebuild_module(&mut self) -> Result<(), Error> {
-        let Some(source) = &self.source else {
-            return Ok(());
-        };
+    /// Drops everything read from the current file. Background jobs hold only weak references
+    /// to their results, so they stop at their next progress check.
+    fn close_file(&mut self) {
+        self.analysis_sender = None;
+        *self.bookmark_state.get_mut() = ListState::default();
+        self.current_analysis = None;
+        self.module_analysis = None;
+        self.health_scan = None;
+        self.scalar_values = None;
+        self.duplicate_scan = None;
+        self.tensor_hash = None;
+        self.manifest_job = None;
+        self.comparison = None;
+        self.optimizer_analysis = None;
+        self.save_job = None;
+        self.marked = None;
+        self.marked_analysis = None;
+        self.table_view = None;
+        self.byte_view = None;
+        self.lora_view = None;
+        self.similarity_view = None;
+        self.progression_view = None;
+        self.head_view = None;
+        self.head_counts = None;
+        self.tokenizer_view = None;
+        self.image_view = None;
+        self.directory_view = None;
+        self.diagnostics_view = None;
+        self.breakdown_view = None;
+        self.treemap_view = None;
+        self.batch = None;
+        self.batch_view = None;
+        self.layout_view = None;
+        self.problems.clear();
+        self.tree_state = None;
+        self.meta_tree_state = None;
+        self.metadata_types.clear();
+        self.source = None;
+        self.reader = None;
+        self.file_path = None;
+    }
 
-        {
-            // Create module tree state
+    /// Lists the checkpoints in `dir` to pick from, keeping any open file until one is picked
+    pub fn open_directory(&mut self, dir: PathBuf) -> Result<(), Error> {
+        let files = self.registry.scan_directory(&dir, &self.path_split)?;
+        if files.is_empty() {
+            bail!("no checkpoints found in {}", dir.display());
+        }
+        let mut state = TableState::default();
+        state.select(Some(0));
+        self.directory_view = Some(DirectoryView {
+            dir,
+            files,
+            state: RefCell::new(state),
+        });
+        Ok(())
+    }
+
+    /// Opens the directory of the current file, or `dir` if given
+    fn browse(&mut self, dir: &str) {
+        let dir = if !dir.is_empty() {
+            PathBuf::from(dir)
+        } else if let Some(view) = &self.directory_view {
+            view.dir.clone()
+        } else {
+            match self.file_path.as_deref().and_then(Path::parent) {
+                Some(parent) if parent.as_os_str().is_empty() => PathBuf::from("."),
+                Some(parent) => parent.to_path_buf(),
+                None => PathBuf::from("."),
+            }
+        };
+        if let Err(err) = self.open_directory(dir) {
+            self.dialog_type = Some(DialogType::Error(err.to_string()));
+        }
+    }
+
+    fn open_selected_file(&mut self) {
+        let Some(view) = &self.directory_view else {
+            return;
+        };
+        let selected = view.state.borrow().selected();
+        let Some(file) = selected.and_then(|i| view.files.get(i)) else {
+            return;
+        };
+        let result = if file.format == "sharded" {
+            Err(anyhow!(
+                "Sharded checkpoints can't be opened as a whole yet, pick one of the shards"
+            ))
+        } else {
+            self.load_file(file.path.clone())
+        };
+        if let Err(err) = result {
+            self.dialog_type = Some(DialogType::Error(err.to_string()));
+        }
+    }
+
+    fn open_recent_file(&mut self) {
+        let selected = self.recent_state.get_mut().selected();
+        let Some(path) = selected.and_then(|i| self.recent.files.get(i)).cloned() else {
+            return;
+        };
+        if let Err(err) = self.load_file(path) {
+            self.dialog_type = Some(DialogType::Error(err.to_string()));
+        }
+    }
+
+    pub fn rebuild_module(&mut self) -> Result<(), Error> {
+        let Some(source) = &self.source else {
+            return Ok(());
+        };
+        if let (Some(cache), Some(path)) = (&mut self.cache, &self.file_path) {
+            cache.set_file(path);
+        }
+
+        {
+            // Create module tree state
             let mut data = source.lock().unwrap();
             let mut module = data.module(&self.path_split)?;
-            module.flatten_single_children();
+            let tensors = module.tensors();
+            if self.group_optimizer && group_optimizer_state(&mut module, &self.path_split) == 0 {
+                self.group_optimizer = false;
+                let message =
+                    "No optimizer state (exp_avg, momentum_buffer, ...) found".to_string();
+                self.dialog_type = Some(DialogType::Notice(message));
+            }
+            if self.flatten {
+                module.flatten_single_children(self.flatten_depth);
+            }
+            if self.group_layers {
+                module.group_numbered_children();
+            }
             let mut state = TreeState::new(Arc::new(module).into());
+            state.filter = self.tensor_filter.clone();
             state.rebuild_visible_items();
             self.tree_state = Some(state);
+            self.problems = data
+                .check()
+                .unwrap_or_else(|err| vec![Problem::file(format!("could not check: {err}"))]);
+            self.problems
+                .extend(path_conflicts(&tensors, &self.path_split));
 
             // Create metadata tree state
             let extra_metadata = data.metadata()?;
+            self.architecture = summarize(&extra_metadata, &tensors, &self.path_split);
+            self.head_counts = self.head_counts.or(attention_heads(&extra_metadata));
             let mut meta_state = TreeState::new(Arc::new(extra_metadata).into());
             meta_state.rebuild_visible_items();
             self.meta_tree_state = Some(meta_state);
+            self.metadata_types = data.metadata_types()?;
         }
 
-        // Now that we have the tree, move the source to the analysis thread
+        // Changing the view leaves the file alone, so only a newly loaded or edited file needs
+        // a fresh handle for background jobs
+        let reader = match &self.reader {
+            Some(reader) => reader.clone(),
+            None => self
+                .file_path
+                .as_ref()
+                .and_then(|path| self.registry.open(path, self.format.as_deref()).ok())
+                // Reading through the same handle still works, it just holds up the UI
+                .unwrap_or_else(|| source.clone()),
+        };
         let sender = self
             .analysis_sender
             .insert(Own::new_box(AnalysisCell::new()))
             .refer();
-        start_analysis_thread(source.clone(), sender);
+        start_analysis_thread(reader.clone(), sender);
+        let scalars: Vec<_> = self
+            .tree_state
+            .iter()
+            .flat_map(|tree| tree.data.tensors())
+            .filter(|(_, tensor)| tensor.shape.is_empty())
+            .collect();
+        self.scalar_values = (!scalars.is_empty()).then(|| {
+            let values = Own::new_box(ScalarValues::default());
+            start_scalar_values(reader.clone(), scalars, values.refer());
+            values
+        });
+        self.reader = Some(reader);
+        self.health_scan = None;
+        self.duplicate_scan = None;
+        self.tensor_hash = None;
+        self.marked = None;
+        self.marked_analysis = None;
 
         // Start analysis for the initially selected tensor
         self.update_analysis_for_selected_tensor();
         Ok(())
     }
 
-    pub fn handle_events(&mut self) -> Result<(), Error> {
-        if let Event::Key(key) = event::read()? {
+    pub fn handle_event(&mut self, event: Event) -> Result<(), Error> {
+        if let Event::Mouse(mouse) = event {
+            self.handle_mouse(mouse);
+            return Ok(());
+        }
+        if let Event::Key(key) = event {
+            if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                self.cancel_background_work();
+                return Ok(());
+            }
+
             // Handle dialog events first
             if let Some(dialog_type) = &self.dialog_type {
                 match key.code {
                     KeyCode::Esc => {
-                        // Cancel dialog
+                        // Cancel dialog, dropping any save in progress
+                        self.save_job = None;
                         self.dialog_type = None;
                         self.edit_draft.clear();
                     }
@@ -388,7 +1503,13 @@ impl App {
                                 self.dialog_type = None;
                                 let new_value = self.parse_edit_draft();
                                 self.edit_draft.clear();
-                                self.update_selected_metadata(Some(new_value));
+                                match new_value {
+                                    Ok(value) => self.update_selected_metadata(Some(value)),
+                                    Err(err) => {
+                                        let message = err.to_string();
+                                        self.dialog_type = Some(DialogType::Error(message));
+                                    }
+                                }
                             }
                             DialogType::Delete => {
                                 // Delete the metadata
@@ -396,34 +1517,455 @@ impl App {
                                 self.edit_draft.clear();
                                 self.update_selected_metadata(None);
                             }
-                            DialogType::Error(_) => {
-                                // Close error dialog
+                            DialogType::AddMetadata => {
+                                self.dialog_type = None;
+                                let input = mem::take(&mut self.edit_draft);
+                                self.add_metadata(&input);
+                            }
+                            DialogType::Open => {
+                                self.dialog_type = None;
+                                let path = PathBuf::from(self.edit_draft.trim());
+                                self.edit_draft.clear();
+                                if let Err(err) = self.load_file(path) {
+                                    self.dialog_type = Some(DialogType::Error(err.to_string()));
+                                }
+                            }
+                            DialogType::Slice => {
+                                self.dialog_type = None;
+                                let spec = mem::take(&mut self.edit_draft);
+                                self.set_slice(&spec);
+                            }
+                            DialogType::Range => {
+                                self.dialog_type = None;
+                                let range = mem::take(&mut self.edit_draft);
+                                self.set_histogram_range(&range);
+                            }
+                            DialogType::Export => {
+                                // Write the selected tensor to the drafted path
+                                self.dialog_type = None;
+                                let path = PathBuf::from(self.edit_draft.trim());
+                                self.edit_draft.clear();
+                                self.export_selected_tensor(&path);
+                            }
+                            DialogType::Rename => {
+                                // Rename the selected tensor or module
+                                self.dialog_type = None;
+                                let new_name = self.edit_draft.trim().to_string();
+                                self.edit_draft.clear();
+                                self.rename_selected(&new_name);
+                            }
+                            DialogType::DeleteTensors(names) => {
+                                // Rewrite the file without the selected tensors
+                                let names = names.clone();
+                                self.dialog_type = None;
+                                self.delete_tensors(&names);
+                            }
+                            DialogType::SaveAs => {
+                                // Write the converted copy in the background
+                                let path = PathBuf::from(self.edit_draft.trim());
+                                self.edit_draft.clear();
+                                self.start_save_as(path);
+                            }
+                            DialogType::Saving => {}
+                            DialogType::Jump => {
+                                self.dialog_type = None;
+                                let matches = matching_names(&self.edit_draft, &self.jump_targets);
+                                let selected =
+                                    self.palette_selected.min(matches.len().saturating_sub(1));
+                                if let Some(&index) = matches.get(selected) {
+                                    let target = self.jump_targets[index].clone();
+                                    self.jump_to(&target);
+                                }
+                                self.edit_draft.clear();
+                            }
+                            DialogType::Palette => {
+                                self.dialog_type = None;
+                                let input = mem::take(&mut self.edit_draft);
+                                self.run_palette_command(&input);
+                            }
+                            DialogType::Notice(_) | DialogType::Error(_) => {
+                                // Close message dialog
                                 self.dialog_type = None;
                             }
                         }
                     }
-                    KeyCode::Char(c) if matches!(dialog_type, DialogType::Edit) => {
+                    KeyCode::Tab if matches!(dialog_type, DialogType::Open) => {
+                        self.edit_draft = complete_path(&self.edit_draft);
+                    }
+                    KeyCode::Tab if matches!(dialog_type, DialogType::SaveAs) => {
+                        self.save_type = (self.save_type + 1) % SAVE_TYPES.len();
+                    }
+                    KeyCode::BackTab if matches!(dialog_type, DialogType::SaveAs) => {
+                        self.save_all = !self.save_all;
+                    }
+                    KeyCode::Up
+                        if matches!(dialog_type, DialogType::Palette | DialogType::Jump) =>
+                    {
+                        self.palette_selected = self.palette_selected.saturating_sub(1);
+                    }
+                    KeyCode::Down
+                        if matches!(dialog_type, DialogType::Palette | DialogType::Jump) =>
+                    {
+                        self.palette_selected = self.palette_selected.saturating_add(1);
+                    }
+                    KeyCode::Char(c)
+                        if matches!(
+                            dialog_type,
+                            DialogType::Edit
+                                | DialogType::AddMetadata
+                                | DialogType::Open
+                                | DialogType::Slice
+                                | DialogType::Range
+                                | DialogType::Export
+                                | DialogType::Rename
+                                | DialogType::SaveAs
+                                | DialogType::Palette
+                                | DialogType::Jump
+                        ) =>
+                    {
                         // Add character to edit draft
                         self.edit_draft.push(c);
+                        self.palette_selected = 0;
                     }
-                    KeyCode::Backspace if matches!(dialog_type, DialogType::Edit) => {
+                    KeyCode::Backspace
+                        if matches!(
+                            dialog_type,
+                            DialogType::Edit
+                                | DialogType::AddMetadata
+                                | DialogType::Open
+                                | DialogType::Slice
+                                | DialogType::Range
+                                | DialogType::Export
+                                | DialogType::Rename
+                                | DialogType::SaveAs
+                                | DialogType::Palette
+                                | DialogType::Jump
+                        ) =>
+                    {
                         // Remove last character from edit draft
                         self.edit_draft.pop();
+                        self.palette_selected = 0;
+                    }
+                    _ => {}
+                }
+                return Ok(());
+            }
+
+            // So does the byte view
+            if let Some(view) = &mut self.byte_view {
+                let page = view.rows.max(1) as isize;
+                match key.code {
+                    KeyCode::Char('v') | KeyCode::Esc => self.byte_view = None,
+                    KeyCode::Char('q') => self.should_quit = true,
+                    KeyCode::Up => view.scroll_by(-1),
+                    KeyCode::Down => view.scroll_by(1),
+                    KeyCode::PageUp => view.scroll_by(-page),
+                    KeyCode::PageDown => view.scroll_by(page),
+                    KeyCode::Home => view.scroll_to(0),
+                    KeyCode::End => view.scroll_to(usize::MAX),
+                    _ => {}
+                }
+                return Ok(());
+            }
+
+            if self.image_view.is_some() {
+                match key.code {
+                    KeyCode::Char('i') | KeyCode::Esc => self.image_view = None,
+                    KeyCode::Char('q') => self.should_quit = true,
+                    _ => {}
+                }
+                return Ok(());
+            }
+
+            // Every printable key goes to the input, so only Esc closes the tokenizer
+            if let Some(view) = &mut self.tokenizer_view {
+                let page = 20;
+                match key.code {
+                    KeyCode::Esc => self.tokenizer_view = None,
+                    KeyCode::Tab => view.next_mode(),
+                    KeyCode::Enter => {
+                        let (Some(embedding), Some(source)) = (&view.embedding, &self.reader)
+                        else {
+                            return Ok(());
+                        };
+                        let Some(&id) = view.rows.get(view.selected) else {
+                            return Ok(());
+                        };
+                        let neighbors = Own::new_box(Neighbors::new(id, NEIGHBOR_COUNT));
+                        start_neighbors(
+                            source.clone(),
+                            embedding.tensor.clone(),
+                            neighbors.refer(),
+                        );
+                        view.neighbors = Some(neighbors);
+                    }
+                    KeyCode::Backspace => {
+                        view.input.pop();
+                        view.refresh();
+                    }
+                    KeyCode::Char(c) => {
+                        view.input.push(c);
+                        view.refresh();
+                    }
+                    KeyCode::Up => view.select_by(-1),
+                    KeyCode::Down => view.select_by(1),
+                    KeyCode::PageUp => view.select_by(-page),
+                    KeyCode::PageDown => view.select_by(page),
+                    _ => {}
+                }
+                return Ok(());
+            }
+
+            if let Some(view) = &mut self.head_view {
+                let last = view.analysis.layout.labels.len().saturating_sub(1);
+                match key.code {
+                    KeyCode::Char('h') | KeyCode::Esc => self.head_view = None,
+                    KeyCode::Char('q') => self.should_quit = true,
+                    KeyCode::Up => view.cursor = view.cursor.saturating_sub(1),
+                    KeyCode::Down => view.cursor = (view.cursor + 1).min(last),
+                    KeyCode::PageUp => view.cursor = view.cursor.saturating_sub(20),
+                    KeyCode::PageDown => view.cursor = (view.cursor + 20).min(last),
+                    KeyCode::Home => view.cursor = 0,
+                    KeyCode::End => view.cursor = last,
+                    _ => {}
+                }
+                return Ok(());
+            }
+
+            if let Some(view) = &mut self.progression_view {
+                let last = view.analysis.tensors.len().saturating_sub(1);
+                match key.code {
+                    KeyCode::Char('P') | KeyCode::Esc => self.progression_view = None,
+                    KeyCode::Char('q') => self.should_quit = true,
+                    KeyCode::Char('M') => self.cycle_progression_metric(),
+                    KeyCode::Left | KeyCode::Up => view.cursor = view.cursor.saturating_sub(1),
+                    KeyCode::Right | KeyCode::Down => view.cursor = (view.cursor + 1).min(last),
+                    KeyCode::Home => view.cursor = 0,
+                    KeyCode::End => view.cursor = last,
+                    _ => {}
+                }
+                return Ok(());
+            }
+
+            if let Some(view) = &mut self.similarity_view {
+                let last = view.analysis.tensors.len().saturating_sub(1);
+                let (row, column) = &mut view.cursor;
+                match key.code {
+                    KeyCode::Esc => self.similarity_view = None,
+                    KeyCode::Char('q') => self.should_quit = true,
+                    KeyCode::Up => *row = row.saturating_sub(1),
+                    KeyCode::Down => *row = (*row + 1).min(last),
+                    KeyCode::Left => *column = column.saturating_sub(1),
+                    KeyCode::Right => *column = (*column + 1).min(last),
+                    _ => {}
+                }
+                return Ok(());
+            }
+
+            if let Some(view) = &mut self.batch_view {
+                match key.code {
+                    KeyCode::Char('A') | KeyCode::Esc => self.batch_view = None,
+                    KeyCode::Char('q') => self.should_quit = true,
+                    KeyCode::Up => view.state.get_mut().select_previous(),
+                    KeyCode::Down => view.state.get_mut().select_next(),
+                    KeyCode::PageUp => view.state.get_mut().scroll_up_by(10),
+                    KeyCode::PageDown => view.state.get_mut().scroll_down_by(10),
+                    KeyCode::Enter => self.show_batch_selection(),
+                    _ => {}
+                }
+                return Ok(());
+            }
+
+            if let Some(view) = &mut self.layout_view {
+                match key.code {
+                    KeyCode::Char('F') | KeyCode::Esc => self.layout_view = None,
+                    KeyCode::Char('q') => self.should_quit = true,
+                    KeyCode::Up => view.state.get_mut().select_previous(),
+                    KeyCode::Down => view.state.get_mut().select_next(),
+                    KeyCode::PageUp => view.state.get_mut().scroll_up_by(10),
+                    KeyCode::PageDown => view.state.get_mut().scroll_down_by(10),
+                    KeyCode::Enter => self.show_layout_selection(),
+                    _ => {}
+                }
+                return Ok(());
+            }
+
+            if let Some(view) = &mut self.diagnostics_view {
+                match key.code {
+                    KeyCode::Esc => self.diagnostics_view = None,
+                    KeyCode::Char('q') => self.should_quit = true,
+                    KeyCode::Up => view.state.get_mut().select_previous(),
+                    KeyCode::Down => view.state.get_mut().select_next(),
+                    KeyCode::PageUp => view.state.get_mut().scroll_up_by(10),
+                    KeyCode::PageDown => view.state.get_mut().scroll_down_by(10),
+                    _ => {}
+                }
+                return Ok(());
+            }
+
+            if let Some(view) = &mut self.treemap_view {
+                match key.code {
+                    KeyCode::Char('t') | KeyCode::Esc => self.treemap_view = None,
+                    KeyCode::Char('q') => self.should_quit = true,
+                    KeyCode::Up => view.step(0, -1),
+                    KeyCode::Down => view.step(0, 1),
+                    KeyCode::Left => view.step(-1, 0),
+                    KeyCode::Right => view.step(1, 0),
+                    KeyCode::Enter => self.show_treemap_selection(),
+                    _ => {}
+                }
+                return Ok(());
+            }
+
+            if let Some(view) = &mut self.breakdown_view {
+                match key.code {
+                    KeyCode::Char('%') | KeyCode::Esc => self.breakdown_view = None,
+                    KeyCode::Char('q') => self.should_quit = true,
+                    KeyCode::Up => view.scroll = view.scroll.saturating_sub(1),
+                    KeyCode::Down => view.scroll = view.scroll.saturating_add(1),
+                    KeyCode::PageUp => view.scroll = view.scroll.saturating_sub(10),
+                    KeyCode::PageDown => view.scroll = view.scroll.saturating_add(10),
+                    _ => {}
+                }
+                return Ok(());
+            }
+
+            if let Some(view) = &mut self.lora_view {
+                match key.code {
+                    KeyCode::Char('L') | KeyCode::Esc => self.lora_view = None,
+                    KeyCode::Char('q') => self.should_quit = true,
+                    KeyCode::Up => {
+                        view.state.get_mut().select_previous();
+                        self.analyze_selected_lora();
+                    }
+                    KeyCode::Down => {
+                        view.state.get_mut().select_next();
+                        self.analyze_selected_lora();
+                    }
+                    _ => {}
+                }
+                return Ok(());
+            }
+
+            if let Some(view) = &mut self.directory_view {
+                match key.code {
+                    // Closing only makes sense with a file to go back to
+                    KeyCode::Char('B') | KeyCode::Esc if self.source.is_some() => {
+                        self.directory_view = None
+                    }
+                    KeyCode::Char('q') => self.should_quit = true,
+                    KeyCode::Up => view.state.get_mut().select_previous(),
+                    KeyCode::Down => view.state.get_mut().select_next(),
+                    KeyCode::PageUp => view.state.get_mut().scroll_up_by(10),
+                    KeyCode::PageDown => view.state.get_mut().scroll_down_by(10),
+                    KeyCode::Enter => self.open_selected_file(),
+                    _ => {}
+                }
+                return Ok(());
+            }
+
+            // The table view replaces the panels while it is open
+            if let Some(table) = &mut self.table_view {
+                match key.code {
+                    KeyCode::Char('T') | KeyCode::Esc => self.table_view = None,
+                    KeyCode::Char('q') => self.should_quit = true,
+                    KeyCode::Char('H') => self.start_health_scan(),
+                    KeyCode::Up => table.state.get_mut().select_previous(),
+                    KeyCode::Down => table.state.get_mut().select_next(),
+                    KeyCode::PageUp => table.state.get_mut().scroll_up_by(10),
+                    KeyCode::PageDown => table.state.get_mut().scroll_down_by(10),
+                    KeyCode::Char('s') => {
+                        table.sort = table.sort.next();
+                        table.descending = table.sort.is_numeric();
+                        self.sort_table();
+                    }
+                    KeyCode::Char('S') => {
+                        table.descending = !table.descending;
+                        self.sort_table();
                     }
                     _ => {}
                 }
                 return Ok(());
             }
 
+            // The start screen picks from recent files, other keys work as usual
+            if self.source.is_none() && !self.recent.files.is_empty() {
+                let state = self.recent_state.get_mut();
+                let handled = match key.code {
+                    KeyCode::Up => {
+                        state.select_previous();
+                        true
+                    }
+                    KeyCode::Down => {
+                        state.select_next();
+                        true
+                    }
+                    KeyCode::PageUp => {
+                        state.scroll_up_by(10);
+                        true
+                    }
+                    KeyCode::PageDown => {
+                        state.scroll_down_by(10);
+                        true
+                    }
+                    KeyCode::Enter => {
+                        self.open_recent_file();
+                        true
+                    }
+                    _ => false,
+                };
+                if handled {
+                    return Ok(());
+                }
+            }
+
+            if key.code == KeyCode::Char('p') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                self.open_jump_dialog();
+                return Ok(());
+            }
+
+            if key.modifiers.contains(KeyModifiers::CONTROL) && self.tree_state.is_some() {
+                let step = match key.code {
+                    KeyCode::Left => Some(-PANEL_WIDTH_STEP),
+                    KeyCode::Right => Some(PANEL_WIDTH_STEP),
+                    _ => None,
+                };
+                if let Some(step) = step {
+                    self.resize_panel(step);
+                    return Ok(());
+                }
+            }
+
+            if let KeyCode::Char(c) = key.code {
+                if let Some(&command) = self.keys.get(&c) {
+                    self.run_command(command, "");
+                    return Ok(());
+                }
+                // A rebound command no longer answers to its default key
+                let c = c.to_string();
+                if self.keys.values().any(|command| command.key() == Some(&c)) {
+                    return Ok(());
+                }
+            }
+
             match (key.code, self.selected_panel, &mut self.tree_state) {
                 (KeyCode::Char('q') | KeyCode::Esc, _, _) => self.should_quit = true,
+                (KeyCode::Char(':'), _, _) => {
+                    self.edit_draft.clear();
+                    self.palette_selected = 0;
+                    self.dialog_type = Some(DialogType::Palette);
+                }
                 (KeyCode::Tab, _, _) => {
-                    self.selected_panel =
-                        self.selected_panel.next(self.should_show_analysis_panel())
+                    self.selected_panel = self.selected_panel.next(
+                        self.should_show_analysis_panel(),
+                        !self.file_bookmarks().is_empty(),
+                    )
                 }
                 (KeyCode::BackTab, _, _) => {
-                    self.selected_panel =
-                        self.selected_panel.prev(self.should_show_analysis_panel())
+                    self.selected_panel = self.selected_panel.prev(
+                        self.should_show_analysis_panel(),
+                        !self.file_bookmarks().is_empty(),
+                    )
                 }
                 // Tree panel controls
                 (KeyCode::Up, Panel::Tree, Some(s)) => {
@@ -447,9 +1989,101 @@ impl App {
                     s.rebuild_visible_items();
                     self.update_analysis_for_selected_tensor();
                 }
+                (KeyCode::Char('x'), Panel::Tree, Some(_)) => {
+                    self.open_export_dialog();
+                }
+                (KeyCode::Char('r'), Panel::Tree, Some(_)) => {
+                    self.open_rename_dialog();
+                }
+                (KeyCode::Char('D'), Panel::Tree, Some(_)) => {
+                    self.open_delete_dialog();
+                }
+                (KeyCode::Char('S'), Panel::Tree, Some(_)) => {
+                    self.open_save_as_dialog();
+                }
+                (KeyCode::Char('v'), Panel::Tree, Some(_)) => {
+                    self.open_byte_view();
+                }
+                (KeyCode::Char('['), Panel::Tree, Some(_)) => {
+                    self.open_slice_dialog();
+                }
+                (KeyCode::Char('{'), _, Some(_)) => {
+                    self.step_slice(false);
+                }
+                (KeyCode::Char('}'), _, Some(_)) => {
+                    self.step_slice(true);
+                }
+                (KeyCode::Char('m'), Panel::Tree, Some(_)) => {
+                    self.toggle_mark();
+                }
+                (KeyCode::Char('|'), _, Some(_)) => self.toggle_split_view(),
+                (KeyCode::Char('b'), Panel::Tree, Some(_)) => {
+                    self.toggle_bookmark();
+                }
+
+                // Bookmarks panel controls
+                (KeyCode::Up, Panel::Bookmarks, _) => self.step_bookmark(false),
+                (KeyCode::Down, Panel::Bookmarks, _) => self.step_bookmark(true),
+                (KeyCode::Enter, Panel::Bookmarks, _) => self.selected_panel = Panel::Tree,
+                (KeyCode::Char('b' | 'd') | KeyCode::Delete, Panel::Bookmarks, _) => {
+                    self.remove_selected_bookmark();
+                }
+                (KeyCode::Char('O'), _, Some(_)) => {
+                    self.toggle_optimizer_grouping()?;
+                }
+                (KeyCode::Char('%'), _, Some(_)) => self.open_breakdown_view(),
+                (KeyCode::Char('A'), _, Some(_)) => self.queue_batch_analysis(),
+                (KeyCode::Char('F'), _, Some(_)) => self.open_layout_view(),
+                (KeyCode::Char('P'), _, Some(_)) => {
+                    self.open_progression_view("", LayerMetric::default())
+                }
+                (KeyCode::Char('h'), _, Some(_)) => self.open_head_view(None),
+                (KeyCode::Char('t'), _, Some(_)) => {
+                    self.treemap_view = Some(TreemapView::default())
+                }
+                (KeyCode::Char('G'), _, Some(_)) => {
+                    self.group_layers = !self.group_layers;
+                    self.rebuild_module()?;
+                }
+                (KeyCode::Char('w'), _, Some(_)) => {
+                    self.hide_tensor_details = !self.hide_tensor_details;
+                }
+                (KeyCode::Char('U'), _, Some(_)) => {
+                    self.flatten = !self.flatten;
+                    self.rebuild_module()?;
+                }
                 (KeyCode::Char('y'), _, _) => {
                     self.handle_y_key();
                 }
+                (KeyCode::Char('Q'), _, _) => {
+                    self.request_quant_errors();
+                }
+                (KeyCode::Char('s'), Panel::Tree, Some(s)) => {
+                    s.cycle_sort();
+                }
+                (KeyCode::Char('E'), _, _) => {
+                    self.expand_focused_tree(None);
+                }
+                (KeyCode::Char('C'), _, _) => {
+                    self.expand_focused_tree(Some(0));
+                }
+                (KeyCode::Char(c @ '1'..='9'), _, _) => {
+                    self.expand_focused_tree(c.to_digit(10).map(|depth| depth as usize));
+                }
+                (KeyCode::Char('H'), _, _) => {
+                    self.start_health_scan();
+                }
+                (KeyCode::Char('B'), _, _) => self.browse(""),
+                (KeyCode::Char('o'), _, _) => self.open_open_dialog(),
+                (KeyCode::Char('T'), _, Some(_)) => {
+                    self.open_table_view();
+                }
+                (KeyCode::Char('L'), _, Some(_)) => {
+                    self.open_lora_view();
+                }
+                (KeyCode::Char('K'), _, Some(_)) => {
+                    self.open_tokenizer_view();
+                }
 
                 // FileInfo panel controls (metadata tree)
                 (KeyCode::Up, Panel::FileInfo, _) => {
@@ -468,31 +2102,97 @@ impl App {
                     }
                 }
                 (KeyCode::Right, Panel::FileInfo, _) => {
+                    if self.page_in_selected_metadata() {
+                        return Ok(());
+                    }
                     if let Some(s) = &mut self.meta_tree_state {
                         s.move_right();
                     }
                 }
                 (KeyCode::Char(' ') | KeyCode::Enter, Panel::FileInfo, _) => {
+                    if self.page_in_selected_metadata() {
+                        return Ok(());
+                    }
                     if let Some(s) = &mut self.meta_tree_state {
                         s.toggle_expanded();
                         s.rebuild_visible_items();
                     }
                 }
+                (KeyCode::Char('s'), Panel::FileInfo, _) => {
+                    if let Some(s) = &mut self.meta_tree_state {
+                        s.cycle_sort();
+                    }
+                }
                 (KeyCode::Char('e'), Panel::FileInfo, _) => {
                     // Open edit dialog for selected metadata item
+                    if !self.allow_writes() {
+                        return Ok(());
+                    }
                     if let Some(value_str) = self.get_selected_metadata_value_string() {
                         self.edit_draft = value_str;
                         self.dialog_type = Some(DialogType::Edit);
                     }
                 }
+                (KeyCode::Char('a'), Panel::FileInfo, _) => {
+                    self.open_add_metadata_dialog();
+                }
+                (KeyCode::Char('i'), Panel::FileInfo, _) => {
+                    self.open_image_view();
+                }
                 (KeyCode::Char('d'), Panel::FileInfo, _) => {
                     // Open delete dialog for selected metadata item
-                    if self.is_metadata_item_selected() {
+                    if self.is_metadata_item_selected() && self.allow_writes() {
                         self.dialog_type = Some(DialogType::Delete);
                     }
                 }
 
-                // Analysis panel controls (currently read-only)
+                // Analysis panel controls (panel and value preview scrolling)
+                (KeyCode::Up, Panel::Analysis, _)
+                    if key.modifiers.contains(KeyModifiers::SHIFT) =>
+                {
+                    self.preview_scroll.0 = self.preview_scroll.0.saturating_sub(1);
+                }
+                (KeyCode::Down, Panel::Analysis, _)
+                    if key.modifiers.contains(KeyModifiers::SHIFT) =>
+                {
+                    self.preview_scroll.0 = self.preview_scroll.0.saturating_add(1);
+                }
+                (KeyCode::Up, Panel::Analysis, _) => {
+                    self.analysis_scroll = self.analysis_scroll.saturating_sub(1);
+                }
+                (KeyCode::Down, Panel::Analysis, _) => {
+                    self.analysis_scroll = self.analysis_scroll.saturating_add(1);
+                }
+                (KeyCode::PageUp, Panel::Analysis, _) => {
+                    self.analysis_scroll = self.analysis_scroll.saturating_sub(10);
+                }
+                (KeyCode::PageDown, Panel::Analysis, _) => {
+                    self.analysis_scroll = self.analysis_scroll.saturating_add(10);
+                }
+                (KeyCode::Left, Panel::Analysis, _) => {
+                    self.preview_scroll.1 = self.preview_scroll.1.saturating_sub(1);
+                }
+                (KeyCode::Right, Panel::Analysis, _) => {
+                    self.preview_scroll.1 = self.preview_scroll.1.saturating_add(1);
+                }
+                (KeyCode::Char('l'), Panel::Analysis, _) => {
+                    self.log_counts = !self.log_counts;
+                }
+                (KeyCode::Char('a'), Panel::Analysis, _) => {
+                    self.cycle_histogram_mode();
+                }
+                (KeyCode::Char('M'), Panel::Analysis, _) => {
+                    self.child_metric = self.child_metric.next();
+                }
+                (KeyCode::Char('+') | KeyCode::Char('='), Panel::Analysis, _) => {
+                    self.set_bin_count(self.bin_count.saturating_mul(2).min(MAX_BIN_COUNT));
+                }
+                (KeyCode::Char('-'), Panel::Analysis, _) => {
+                    self.set_bin_count((self.bin_count / 2).max(1));
+                }
+                (KeyCode::Char('z'), Panel::Analysis, _) => {
+                    self.open_range_dialog();
+                }
                 (_, Panel::Analysis, _) => {}
                 _ => {}
             }
@@ -500,611 +2200,4988 @@ impl App {
         Ok(())
     }
 
-    pub fn run(&mut self, terminal: &mut Terminal<Backend>) -> Result<(), Error> {
-        while !self.should_quit {
-            terminal.draw(|f| self.render_ui(f))?;
-            if event::poll(Duration::from_millis(100))? {
-                self.handle_events()?;
+    fn handle_mouse(&mut self, mouse: MouseEvent) {
+        if self.dialog_type.is_some() {
+            return;
+        }
+        if let Some(view) = &mut self.byte_view {
+            match mouse.kind {
+                MouseEventKind::ScrollUp => view.scroll_by(-3),
+                MouseEventKind::ScrollDown => view.scroll_by(3),
+                _ => {}
             }
+            return;
         }
-        Ok(())
-    }
-
-    fn render_ui(&mut self, f: &mut ratatui::Frame) {
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([
-                Constraint::Length(3), // Top bar
-                Constraint::Min(1),    // Main content
-                Constraint::Length(3), // Bottom bar
-            ])
+        if let Some(view) = &mut self.tokenizer_view {
+            match mouse.kind {
+                MouseEventKind::ScrollUp => view.select_by(-3),
+                MouseEventKind::ScrollDown => view.select_by(3),
+                _ => {}
+            }
+            return;
+        }
+        if let Some(view) = &mut self.head_view {
+            let last = view.analysis.layout.labels.len().saturating_sub(1);
+            match mouse.kind {
+                MouseEventKind::ScrollUp => view.cursor = view.cursor.saturating_sub(1),
+                MouseEventKind::ScrollDown => view.cursor = (view.cursor + 1).min(last),
+                _ => {}
+            }
+            return;
+        }
+        if let Some(view) = &mut self.progression_view {
+            let last = view.analysis.tensors.len().saturating_sub(1);
+            match mouse.kind {
+                MouseEventKind::ScrollUp => view.cursor = view.cursor.saturating_sub(1),
+                MouseEventKind::ScrollDown => view.cursor = (view.cursor + 1).min(last),
+                _ => {}
+            }
+            return;
+        }
+        if let Some(view) = &mut self.similarity_view {
+            let last = view.analysis.tensors.len().saturating_sub(1);
+            match mouse.kind {
+                MouseEventKind::ScrollUp => view.cursor.0 = view.cursor.0.saturating_sub(1),
+                MouseEventKind::ScrollDown => view.cursor.0 = (view.cursor.0 + 1).min(last),
+                _ => {}
+            }
+            return;
+        }
+        if let Some(view) = &mut self.batch_view {
+            match mouse.kind {
+                MouseEventKind::ScrollUp => view.state.get_mut().select_previous(),
+                MouseEventKind::ScrollDown => view.state.get_mut().select_next(),
+                _ => {}
+            }
+            return;
+        }
+        if let Some(view) = &mut self.layout_view {
+            match mouse.kind {
+                MouseEventKind::ScrollUp => view.state.get_mut().select_previous(),
+                MouseEventKind::ScrollDown => view.state.get_mut().select_next(),
+                _ => {}
+            }
+            return;
+        }
+        if let Some(view) = &mut self.diagnostics_view {
+            match mouse.kind {
+                MouseEventKind::ScrollUp => view.state.get_mut().select_previous(),
+                MouseEventKind::ScrollDown => view.state.get_mut().select_next(),
+                _ => {}
+            }
+            return;
+        }
+        if let Some(view) = &mut self.treemap_view {
+            let clicked = match mouse.kind {
+                MouseEventKind::Down(MouseButton::Left) => view.cell_at(mouse.column, mouse.row),
+                _ => None,
+            };
+            if let Some(i) = clicked {
+                view.selected = i;
+                self.show_treemap_selection();
+            }
+            return;
+        }
+        if let Some(view) = &mut self.breakdown_view {
+            match mouse.kind {
+                MouseEventKind::ScrollUp => view.scroll = view.scroll.saturating_sub(1),
+                MouseEventKind::ScrollDown => view.scroll = view.scroll.saturating_add(1),
+                _ => {}
+            }
+            return;
+        }
+        if let Some(view) = &mut self.lora_view {
+            match mouse.kind {
+                MouseEventKind::ScrollUp => view.state.get_mut().select_previous(),
+                MouseEventKind::ScrollDown => view.state.get_mut().select_next(),
+                _ => return,
+            }
+            self.analyze_selected_lora();
+            return;
+        }
+        if let Some(view) = &mut self.directory_view {
+            match mouse.kind {
+                MouseEventKind::ScrollUp => view.state.get_mut().select_previous(),
+                MouseEventKind::ScrollDown => view.state.get_mut().select_next(),
+                _ => {}
+            }
+            return;
+        }
+        if let Some(table) = &mut self.table_view {
+            match mouse.kind {
+                MouseEventKind::ScrollUp => table.state.get_mut().select_previous(),
+                MouseEventKind::ScrollDown => table.state.get_mut().select_next(),
+                _ => {}
+            }
+            return;
+        }
+        if self.source.is_none() {
+            match mouse.kind {
+                MouseEventKind::ScrollUp => self.recent_state.get_mut().select_previous(),
+                MouseEventKind::ScrollDown => self.recent_state.get_mut().select_next(),
+                _ => {}
+            }
+            return;
+        }
+        let Some(&(panel, area)) = self
+            .panel_areas
+            .iter()
+            .find(|(_, area)| area.contains(Position::new(mouse.column, mouse.row)))
+        else {
+            return;
+        };
+
+        match (mouse.kind, panel) {
+            (MouseEventKind::Down(MouseButton::Left), _) => {
+                self.selected_panel = panel;
+                match panel {
+                    Panel::Tree => {
+                        if let Some(s) = &mut self.tree_state {
+                            s.click(area, mouse.row);
+                            self.update_analysis_for_selected_tensor();
+                        }
+                    }
+                    Panel::FileInfo => {
+                        if let Some(s) = &mut self.meta_tree_state {
+                            s.click(self.meta_list_area, mouse.row);
+                        }
+                    }
+                    Panel::Bookmarks => {
+                        let inner = area.inner(Margin::new(1, 1));
+                        if mouse.row < inner.y || mouse.row >= inner.bottom() {
+                            return;
+                        }
+                        let state = self.bookmark_state.get_mut();
+                        let index = state.offset() + (mouse.row - inner.y) as usize;
+                        if index < self.file_bookmarks().len() {
+                            self.bookmark_state.get_mut().select(Some(index));
+                            self.show_bookmark(index);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            (MouseEventKind::ScrollUp, Panel::Bookmarks) => self.step_bookmark(false),
+            (MouseEventKind::ScrollDown, Panel::Bookmarks) => self.step_bookmark(true),
+            (MouseEventKind::ScrollUp, Panel::Tree) => {
+                if let Some(s) = &mut self.tree_state {
+                    s.move_up();
+                    self.update_analysis_for_selected_tensor();
+                }
+            }
+            (MouseEventKind::ScrollDown, Panel::Tree) => {
+                if let Some(s) = &mut self.tree_state {
+                    s.move_down();
+                    self.update_analysis_for_selected_tensor();
+                }
+            }
+            (MouseEventKind::ScrollUp, Panel::FileInfo) => {
+                if let Some(s) = &mut self.meta_tree_state {
+                    s.move_up();
+                }
+            }
+            (MouseEventKind::ScrollDown, Panel::FileInfo) => {
+                if let Some(s) = &mut self.meta_tree_state {
+                    s.move_down();
+                }
+            }
+            (MouseEventKind::ScrollUp, Panel::Analysis) => {
+                self.analysis_scroll = self.analysis_scroll.saturating_sub(1);
+            }
+            (MouseEventKind::ScrollDown, Panel::Analysis) => {
+                self.analysis_scroll = self.analysis_scroll.saturating_add(1);
+            }
+            _ => {}
+        }
+    }
+
+    /// Applies [`TreeState::expand_to_depth`] to the metadata tree if it has focus, otherwise
+    /// the module tree
+    fn expand_focused_tree(&mut self, depth: Option<usize>) {
+        if self.selected_panel == Panel::FileInfo {
+            if let Some(s) = &mut self.meta_tree_state {
+                s.expand_to_depth(depth);
+            }
+        } else if let Some(s) = &mut self.tree_state {
+            s.expand_to_depth(depth);
+            self.update_analysis_for_selected_tensor();
+        }
+    }
+
+    fn open_jump_dialog(&mut self) {
+        let Some(tree) = &self.tree_state else {
+            return;
+        };
+        let root = tree.data_history.first().unwrap_or(&tree.data);
+        // Listed without building the whole tree
+        self.jump_targets = root
+            .tensors()
+            .into_iter()
+            .map(|(name, _)| {
+                let full: Arc<str> = name.into();
+                Key::new(full.clone(), 0..full.len())
+            })
+            .collect();
+        self.jump_targets.sort();
+        self.edit_draft.clear();
+        self.palette_selected = 0;
+        self.dialog_type = Some(DialogType::Jump);
+    }
+
+    fn jump_to(&mut self, target: &Key) {
+        if let Some(s) = &mut self.tree_state {
+            s.reveal(target);
+            self.selected_panel = Panel::Tree;
+            self.update_analysis_for_selected_tensor();
+        }
+    }
+
+    fn open_export_dialog(&mut self) {
+        // Open export dialog for selected tensor
+        if let Some(tensor) = self.selected_tensor_name() {
+            self.edit_draft = format!("{tensor}.npy");
+            self.dialog_type = Some(DialogType::Export);
+        }
+    }
+
+    /// Shows why not and returns false when the file was opened with `--read-only`
+    fn allow_writes(&mut self) -> bool {
+        if self.read_only {
+            let message = "The file was opened read-only".to_string();
+            self.dialog_type = Some(DialogType::Error(message));
+        }
+        !self.read_only
+    }
+
+    fn open_rename_dialog(&mut self) {
+        // Open rename dialog for selected tensor or module
+        if !self.allow_writes() {
+            return;
+        }
+        let Some(s) = &mut self.tree_state else {
+            return;
+        };
+        let selected = s.list_state.get_mut().selected();
+        if let Some(item) = selected.and_then(|i| s.visible_items.get(i)) {
+            self.edit_draft = item.info.full_name.to_string();
+            self.dialog_type = Some(DialogType::Rename);
+        }
+    }
+
+    fn open_delete_dialog(&mut self) {
+        // Confirm deletion of the selected tensor or module
+        if !self.allow_writes() {
+            return;
+        }
+        let Some(s) = &mut self.tree_state else {
+            return;
+        };
+        let selected = s.list_state.get_mut().selected();
+        if let Some(item) = selected.and_then(|i| s.visible_items.get(i)) {
+            let names = item.info.tensors().into_iter().map(|(n, _)| n).collect();
+            self.dialog_type = Some(DialogType::DeleteTensors(names));
+        }
+    }
+
+    fn open_add_metadata_dialog(&mut self) {
+        if self.meta_tree_state.is_some() && self.allow_writes() {
+            self.edit_draft.clear();
+            self.dialog_type = Some(DialogType::AddMetadata);
+        }
+    }
+
+    fn open_open_dialog(&mut self) {
+        // Start from the current file's directory
+        self.edit_draft = match self.file_path.as_deref().and_then(Path::parent) {
+            Some(dir) if !dir.as_os_str().is_empty() => {
+                format!("{}{}", dir.display(), std::path::MAIN_SEPARATOR)
+            }
+            _ => String::new(),
+        };
+        self.dialog_type = Some(DialogType::Open);
+    }
+
+    fn open_slice_dialog(&mut self) {
+        let Some(name) = self.selected_tensor_name() else {
+            return;
+        };
+        self.edit_draft = match &self.slice {
+            Some((sliced, slice)) if *sliced == name => {
+                let spec = slice.to_string();
+                spec[1..spec.len() - 1].to_string()
+            }
+            _ => String::new(),
+        };
+        self.dialog_type = Some(DialogType::Slice);
+    }
+
+    /// Restricts analysis of the selected tensor to `spec`, or the whole tensor if it's empty
+    fn set_slice(&mut self, spec: &str) {
+        let Some((name, tensor)) = self.selected_tensor() else {
+            return;
+        };
+        if spec.trim().is_empty() {
+            self.slice = None;
+        } else {
+            match TensorSlice::parse(spec, &tensor.shape) {
+                Ok(slice) => self.slice = Some((name, slice)),
+                Err(err) => {
+                    self.dialog_type = Some(DialogType::Error(err.to_string()));
+                    return;
+                }
+            }
+        }
+        self.update_analysis_for_selected_tensor();
+    }
+
+    fn set_bin_count(&mut self, count: usize) {
+        if count != self.bin_count {
+            self.bin_count = count;
+            self.update_analysis_for_selected_tensor();
+        }
+    }
+
+    fn open_range_dialog(&mut self) {
+        let Some(name) = self.selected_tensor_name() else {
+            return;
+        };
+        self.edit_draft = match &self.histogram_range {
+            Some((zoomed, (min, max))) if *zoomed == name => format!("{min}:{max}"),
+            _ => String::new(),
+        };
+        self.dialog_type = Some(DialogType::Range);
+    }
+
+    /// Zooms the histogram of the selected tensor into `min:max`, or back out if it's empty
+    fn set_histogram_range(&mut self, range: &str) {
+        let Some(name) = self.selected_tensor_name() else {
+            return;
+        };
+        if range.trim().is_empty() {
+            self.histogram_range = None;
+        } else {
+            match parse_range(range) {
+                Ok(range) => self.histogram_range = Some((name, range)),
+                Err(err) => {
+                    self.dialog_type = Some(DialogType::Error(err.to_string()));
+                    return;
+                }
+            }
+        }
+        self.update_analysis_for_selected_tensor();
+    }
+
+    /// Analyzes the next or previous slice of the selected tensor along the sliced axis
+    fn step_slice(&mut self, forward: bool) {
+        let Some((name, tensor)) = self.selected_tensor() else {
+            return;
+        };
+        let mut slice = match self.slice.take() {
+            Some((sliced, slice)) if sliced == name => slice,
+            _ => TensorSlice::whole(&tensor.shape),
+        };
+        slice.step(&tensor.shape, forward);
+        self.slice = Some((name, slice));
+        self.update_analysis_for_selected_tensor();
+    }
+
+    /// `tensor` shaped like the part of it being analyzed
+    fn analyzed_tensor(&self, tensor: &TensorInfo) -> TensorInfo {
+        match self
+            .current_analysis
+            .as_ref()
+            .and_then(|a| a.slice.as_ref())
+        {
+            Some(slice) => slice.tensor(tensor),
+            None => tensor.clone(),
+        }
+    }
+
+    fn open_save_as_dialog(&mut self) {
+        // Open save-as dialog, suggesting a sibling of the current file
+        if let Some(path) = &self.file_path {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            let draft = path.with_file_name(format!("{stem}.converted.safetensors"));
+            self.edit_draft = draft.to_string_lossy().into_owned();
+            // Quantized gguf tensors can only be written once dequantized
+            if path.extension().is_some_and(|ext| ext == "gguf") {
+                self.save_all = true;
+            }
+            self.dialog_type = Some(DialogType::SaveAs);
+        }
+    }
+
+    /// Marks the selected tensor for comparison, or unmarks it if it was already marked
+    fn toggle_mark(&mut self) {
+        let Some(selected) = self.selected_tensor() else {
+            return;
+        };
+        if self
+            .marked
+            .as_ref()
+            .is_some_and(|(name, _)| *name == selected.0)
+        {
+            self.marked = None;
+        } else {
+            self.marked = Some(selected);
+        }
+        self.update_comparison();
+        self.update_marked_analysis();
+    }
+
+    /// Shows the marked and selected tensors side by side, or goes back to the usual panels
+    fn toggle_split_view(&mut self) {
+        self.split_view = !self.split_view;
+        self.update_marked_analysis();
+    }
+
+    /// Starts analyzing the marked tensor if the split view needs it, or stops
+    fn update_marked_analysis(&mut self) {
+        self.marked_analysis = None;
+        let (true, Some((_, marked)), Some(source)) = (self.split_view, &self.marked, &self.reader)
+        else {
+            return;
+        };
+        let analysis = Own::new_box(self.new_analysis(marked, None, None));
+        start_tensor_analysis(source.clone(), analysis.refer());
+        self.marked_analysis = Some(analysis);
+    }
+
+    /// Whether the split view replaces the info and analysis panels right now
+    fn shows_split_view(&self) -> bool {
+        self.marked_analysis.is_some() && self.current_analysis.is_some()
+    }
+
+    fn file_bookmarks(&self) -> &[String] {
+        match &self.file_path {
+            Some(path) => self.bookmarks.get(path),
+            None => &[],
+        }
+    }
+
+    /// Pins the selected tensor or module to the bookmarks panel, or unpins it
+    fn toggle_bookmark(&mut self) {
+        let (Some(path), Some(tree)) = (&self.file_path, &self.tree_state) else {
+            return;
+        };
+        let selected = tree.list_state.borrow().selected();
+        let Some(item) = selected.and_then(|i| tree.visible_items.get(i)) else {
+            return;
+        };
+        let name = item.info.full_name.to_string();
+        match self.bookmarks.toggle(path, &name) {
+            Ok(true) => {
+                let last = self.file_bookmarks().len() - 1;
+                self.bookmark_state.get_mut().select(Some(last));
+            }
+            Ok(false) => self.clamp_bookmark_selection(),
+            Err(err) => {
+                let message = format!("could not save bookmarks: {err}");
+                self.dialog_type = Some(DialogType::Error(message));
+            }
+        }
+    }
+
+    /// Widens the selected panel by `step` percent, taking the room from its neighbor
+    fn resize_panel(&mut self, step: i16) {
+        let (grow, shrink) = match self.selected_panel {
+            Panel::Tree | Panel::Bookmarks => (0, 1),
+            Panel::SelectedInfo | Panel::FileInfo if self.should_show_analysis_panel() => (1, 2),
+            Panel::SelectedInfo | Panel::FileInfo => (1, 0),
+            Panel::Analysis => (2, 1),
+        };
+        let mut widths = self.panel_widths.map(|width| width as i16);
+        let step = step
+            .min(widths[shrink] - MIN_PANEL_WIDTH)
+            .max(MIN_PANEL_WIDTH - widths[grow]);
+        widths[grow] += step;
+        widths[shrink] -= step;
+        if step == 0 || widths.iter().any(|&width| width < MIN_PANEL_WIDTH) {
+            return;
+        }
+        self.panel_widths = widths.map(|width| width as u16);
+        if let Err(err) = Config::save_panel_widths(self.config_path.as_deref(), self.panel_widths)
+        {
+            let message = format!("could not save panel widths: {err}");
+            self.dialog_type = Some(DialogType::Error(message));
+        }
+    }
+
+    fn remove_selected_bookmark(&mut self) {
+        let Some(path) = self.file_path.clone() else {
+            return;
+        };
+        let selected = self.bookmark_state.get_mut().selected();
+        let Some(name) = selected.and_then(|i| self.file_bookmarks().get(i)).cloned() else {
+            return;
+        };
+        if let Err(err) = self.bookmarks.toggle(&path, &name) {
+            let message = format!("could not save bookmarks: {err}");
+            self.dialog_type = Some(DialogType::Error(message));
+        }
+        self.clamp_bookmark_selection();
+    }
+
+    fn clamp_bookmark_selection(&mut self) {
+        let count = self.file_bookmarks().len();
+        let state = self.bookmark_state.get_mut();
+        if count == 0 {
+            state.select(None);
+            if self.selected_panel == Panel::Bookmarks {
+                self.selected_panel = Panel::Tree;
+            }
+        } else if state.selected().is_none_or(|i| i >= count) {
+            state.select(Some(count - 1));
+        }
+    }
+
+    /// Moves through the bookmarks, showing each one in the tree as it is selected
+    fn step_bookmark(&mut self, forward: bool) {
+        let count = self.file_bookmarks().len();
+        if count == 0 {
+            return;
+        }
+        let state = self.bookmark_state.get_mut();
+        let index = match (state.selected(), forward) {
+            (Some(i), true) => (i + 1).min(count - 1),
+            (Some(i), false) => i.saturating_sub(1),
+            (None, _) => 0,
+        };
+        state.select(Some(index));
+        self.show_bookmark(index);
+    }
+
+    fn show_bookmark(&mut self, index: usize) {
+        let Some(name) = self.file_bookmarks().get(index).cloned() else {
+            return;
+        };
+        self.reveal_tensor(&name);
+    }
+
+    /// Selects the named tensor in the tree, even if it is outside the module the tree is
+    /// focused on
+    fn reveal_tensor(&mut self, name: &str) {
+        let Some(tree) = &mut self.tree_state else {
+            return;
+        };
+        let root = tree.data_history.first().unwrap_or(&tree.data);
+        let Some(target) = root.find(name).map(|module| module.full_name.clone()) else {
+            return;
+        };
+        tree.reveal(&target);
+        self.update_analysis_for_selected_tensor();
+    }
+
+    fn toggle_optimizer_grouping(&mut self) -> Result<(), Error> {
+        self.group_optimizer = !self.group_optimizer;
+        self.rebuild_module()
+    }
+
+    /// Compares the selected parameter against its optimizer state, when it was grouped with any
+    fn update_optimizer_analysis(&mut self) {
+        self.optimizer_analysis = None;
+        let (Some(tree), Some(source)) = (&self.tree_state, &self.reader) else {
+            return;
+        };
+        let selected_item = tree
+            .list_state
+            .borrow()
+            .selected()
+            .and_then(|i| tree.visible_items.get(i));
+        let Some(item) = selected_item else { return };
+        let Some(param) = &item.info.tensor_info else {
+            return;
+        };
+        let states: Vec<_> = item
+            .info
+            .children()
+            .iter()
+            .filter_map(|(key, child)| Some((key.to_string(), child.tensor_info.clone()?)))
+            .collect();
+        if states.is_empty() {
+            return;
+        }
+        let analysis = Own::new_box(OptimizerAnalysis::new(param.clone(), states));
+        start_optimizer_analysis(source.clone(), analysis.refer());
+        self.optimizer_analysis = Some(analysis);
+    }
+
+    /// Compares the selected tensor against the marked one, if both exist and differ
+    fn update_comparison(&mut self) {
+        self.comparison = None;
+        let (Some((marked_name, marked)), Some((name, tensor)), Some(source)) =
+            (&self.marked, self.selected_tensor(), &self.reader)
+        else {
+            return;
+        };
+        if *marked_name == name {
+            return;
+        }
+        let comparison = Own::new_box(Comparison::new(marked.clone(), tensor));
+        start_comparison(source.clone(), comparison.refer());
+        self.comparison = Some(comparison);
+    }
+
+    fn selected_tensor(&self) -> Option<(String, TensorInfo)> {
+        let name = self.selected_tensor_name()?;
+        let tensor = self
+            .tree_state
+            .as_ref()?
+            .data
+            .find(&name)?
+            .tensor_info
+            .clone()?;
+        Some((name, tensor))
+    }
+
+    fn open_byte_view(&mut self) {
+        let Some((name, tensor)) = self.selected_tensor() else {
+            return;
+        };
+        self.byte_view = Some(ByteView {
+            name,
+            tensor,
+            offset: 0,
+            rows: 0,
+            page: None,
+        });
+    }
+
+    /// Reads the bytes on screen if the byte view has moved since they were last read
+    fn load_byte_page(&mut self) {
+        let (Some(view), Some(source)) = (&mut self.byte_view, &self.source) else {
+            return;
+        };
+        if view.page.is_some() {
+            return;
+        }
+        let end = view
+            .offset
+            .saturating_add(view.rows * BYTES_PER_ROW)
+            .min(view.tensor.size);
+        let page = source
+            .lock()
+            .unwrap()
+            .tensor_byte_range(&view.tensor, view.offset..end);
+        view.page = Some(page.map_err(|err| err.to_string()));
+    }
+
+    fn open_image_view(&mut self) {
+        let Some(state) = &self.meta_tree_state else {
+            return;
+        };
+        let Some(index) = state.list_state.borrow().selected() else {
+            return;
+        };
+        let Some(item) = state.visible_items.get(index) else {
+            return;
+        };
+        let Value::String(uri) = &*item.info else {
+            return;
+        };
+        if !is_image(&item.info) {
+            let message = "The selected value is not a data:image/ URI".to_string();
+            self.dialog_type = Some(DialogType::Notice(message));
+            return;
+        }
+        match decode_data_uri(uri) {
+            Ok(image) => {
+                self.image_view = Some(ImageView {
+                    key: item.name.clone(),
+                    image,
+                })
+            }
+            Err(err) => self.dialog_type = Some(DialogType::Error(err.to_string())),
+        }
+    }
+
+    fn open_tokenizer_view(&mut self) {
+        let Some(source) = &self.source else {
+            return;
+        };
+        let vocab = source
+            .lock()
+            .unwrap()
+            .string_metadata()
+            .and_then(|metadata| Vocab::from_metadata(&metadata));
+        match vocab {
+            Ok(Some(vocab)) => {
+                let embedding = match (&self.tree_state, &self.reader) {
+                    (Some(tree), Some(reader)) => {
+                        let root = tree.data_history.first().unwrap_or(&tree.data);
+                        find_embedding(&root.tensors(), vocab.tokens.len()).map(|(name, tensor)| {
+                            let analysis = Own::new_box(EmbeddingAnalysis::new(name, tensor));
+                            start_embedding(reader.clone(), analysis.refer());
+                            analysis
+                        })
+                    }
+                    _ => None,
+                };
+                let mut view = TokenizerView {
+                    vocab,
+                    mode: TokenizerMode::Search,
+                    input: String::new(),
+                    rows: Vec::new(),
+                    error: None,
+                    selected: 0,
+                    offset: std::cell::Cell::new(0),
+                    embedding,
+                    neighbors: None,
+                    stale: false,
+                };
+                view.refresh();
+                self.tokenizer_view = Some(view);
+            }
+            Ok(None) => {
+                let message = "No tokenizer.ggml.tokens in this file".to_string();
+                self.dialog_type = Some(DialogType::Notice(message));
+            }
+            Err(err) => self.dialog_type = Some(DialogType::Error(err.to_string())),
+        }
+    }
+
+    /// Compares every tensor matching the glob `pattern`, or tensors named like the selected
+    /// one with any layer numbers replaced by `*`
+    fn open_similarity_view(&mut self, pattern: &str) {
+        let (Some(tree), Some(source)) = (&self.tree_state, &self.reader) else {
+            return;
+        };
+        let pattern = if pattern.is_empty() {
+            let Some(name) = self.selected_tensor_name() else {
+                let message = "Select a tensor or give a pattern to compare".to_string();
+                self.dialog_type = Some(DialogType::Notice(message));
+                return;
+            };
+            Regex::new(r"\d+")
+                .unwrap()
+                .replace_all(&name, "*")
+                .into_owned()
+        } else {
+            pattern.to_string()
+        };
+        let regex = match glob_regex(&pattern) {
+            Ok(regex) => regex,
+            Err(err) => {
+                self.dialog_type = Some(DialogType::Error(err.to_string()));
+                return;
+            }
+        };
+        let root = tree.data_history.first().unwrap_or(&tree.data);
+        let tensors: Vec<_> = root
+            .tensors()
+            .into_iter()
+            .filter(|(name, _)| regex.is_match(name))
+            .collect();
+        if tensors.len() < 2 {
+            let message = format!(
+                "{} tensors match {pattern}, need at least two",
+                tensors.len()
+            );
+            self.dialog_type = Some(DialogType::Notice(message));
+            return;
+        }
+        let analysis = Own::new_box(Similarity::new(tensors));
+        start_similarity(source.clone(), analysis.refer());
+        self.similarity_view = Some(SimilarityView {
+            pattern,
+            analysis,
+            cursor: (0, 1),
+        });
+    }
+
+    /// Charts `metric` of every float tensor matching the glob `pattern` against the layer
+    /// number in place of its first `*`, by default for the family of the selected tensor
+    fn open_progression_view(&mut self, pattern: &str, metric: LayerMetric) {
+        let (Some(tree), Some(source)) = (&self.tree_state, &self.reader) else {
+            return;
+        };
+        let pattern = if pattern.is_empty() {
+            let Some(name) = self.selected_tensor_name() else {
+                let message = "Select a tensor or give a pattern to chart".to_string();
+                self.dialog_type = Some(DialogType::Notice(message));
+                return;
+            };
+            Regex::new(r"\d+")
+                .unwrap()
+                .replace_all(&name, "*")
+                .into_owned()
+        } else {
+            pattern.to_string()
+        };
+        let regex = match layer_regex(&pattern) {
+            Ok(regex) => regex,
+            Err(err) => {
+                self.dialog_type = Some(DialogType::Error(err.to_string()));
+                return;
+            }
+        };
+        let root = tree.data_history.first().unwrap_or(&tree.data);
+        let tensors: Vec<_> = root
+            .tensors()
+            .into_iter()
+            .filter(|(_, tensor)| tensor.ty.is_float())
+            .filter_map(|(name, tensor)| {
+                let captures = regex.captures(&name)?;
+                let layer = captures
+                    .iter()
+                    .skip(1)
+                    .flatten()
+                    .find_map(|group| group.as_str().parse().ok())?;
+                Some((layer, name, tensor))
+            })
+            .collect();
+        if tensors.len() < 2 {
+            let message = format!(
+                "{} numbered tensors match {pattern}, need at least two",
+                tensors.len()
+            );
+            self.dialog_type = Some(DialogType::Notice(message));
+            return;
+        }
+        let analysis = Own::new_box(Progression::new(metric, tensors));
+        start_progression(source.clone(), analysis.refer());
+        self.progression_view = Some(ProgressionView {
+            pattern,
+            analysis,
+            cursor: 0,
+        });
+    }
+
+    /// Splits the selected projection into heads, by `counts` or else the head counts last given
+    /// or read from the file
+    fn open_head_view(&mut self, counts: Option<(usize, usize)>) {
+        let Some(source) = self.reader.clone() else {
+            return;
+        };
+        if counts.is_some() {
+            self.head_counts = counts;
+        }
+        let Some((heads, kv_heads)) = self.head_counts else {
+            let message =
+                "No head count in this file, give one with :heads <count>[/<kv count>]".to_string();
+            self.dialog_type = Some(DialogType::Notice(message));
+            return;
+        };
+        let Some((name, tensor)) = self.selected_tensor() else {
+            let message = "Select an attention projection to split into heads".to_string();
+            self.dialog_type = Some(DialogType::Notice(message));
+            return;
+        };
+        match HeadLayout::new(&name, &tensor.shape, heads, kv_heads) {
+            Ok(layout) => {
+                let analysis = Own::new_box(HeadAnalysis::new(name, tensor, layout));
+                start_heads(source, analysis.refer());
+                self.head_view = Some(HeadView {
+                    analysis,
+                    cursor: 0,
+                });
+            }
+            Err(err) => self.dialog_type = Some(DialogType::Error(err.to_string())),
+        }
+    }
+
+    fn cycle_progression_metric(&mut self) {
+        let Some(view) = self.progression_view.take() else {
+            return;
+        };
+        self.open_progression_view(&view.pattern, view.analysis.metric.next());
+        if let Some(new) = &mut self.progression_view {
+            new.cursor = view.cursor;
+        }
+    }
+
+    fn open_lora_view(&mut self) {
+        let (Some(tree), Some(source)) = (&self.tree_state, &self.source) else {
+            return;
+        };
+        let root = tree.data_history.first().unwrap_or(&tree.data);
+        let mut pairs = find_lora_pairs(&root.tensors());
+        if pairs.is_empty() {
+            let message = "No LoRA pairs (lora_A/lora_B or lora_down/lora_up) found".to_string();
+            self.dialog_type = Some(DialogType::Notice(message));
+            return;
+        }
+        let progress = Own::new_box(AtomicU64::new(0));
+        if let Err(err) = load_alphas(&mut *source.lock().unwrap(), &mut pairs, progress.refer()) {
+            self.dialog_type = Some(DialogType::Error(err.to_string()));
+            return;
+        }
+        let mut state = TableState::default();
+        state.select(Some(0));
+        self.lora_view = Some(LoraView {
+            pairs,
+            state: RefCell::new(state),
+            analysis: None,
+        });
+        self.analyze_selected_lora();
+    }
+
+    /// Starts computing the merged spectrum of the selected adapter
+    fn analyze_selected_lora(&mut self) {
+        let (Some(view), Some(source)) = (&mut self.lora_view, &self.reader) else {
+            return;
+        };
+        let Some(pair) = view
+            .state
+            .get_mut()
+            .selected()
+            .and_then(|i| view.pairs.get(i))
+        else {
+            return;
+        };
+        if view
+            .analysis
+            .as_ref()
+            .is_some_and(|analysis| analysis.pair.base == pair.base)
+        {
+            return;
+        }
+        let analysis = Own::new_box(LoraAnalysis::new(pair.clone(), self.bin_count));
+        start_lora_analysis(source.clone(), analysis.refer());
+        view.analysis = Some(analysis);
+    }
+
+    fn open_table_view(&mut self) {
+        let Some(s) = &self.tree_state else {
+            return;
+        };
+        let rows = s.data.tensors();
+        let mut state = TableState::default();
+        state.select(Some(0));
+        self.table_view = Some(TableView {
+            rows,
+            sort: TableColumn::Name,
+            descending: false,
+            state: RefCell::new(state),
+        });
+        self.sort_table();
+    }
+
+    fn request_quant_errors(&mut self) {
+        // Request the quantization error table for the selected tensor
+        if let Some(analysis) = &self.current_analysis
+            && analysis.tensor.ty.is_float()
+        {
+            analysis.quant_error_go.store(true, Relaxed);
+        }
+    }
+
+    fn cycle_histogram_mode(&mut self) {
+        let is_matrix = self
+            .current_analysis
+            .as_ref()
+            .is_some_and(|a| a.tensor.shape.len() == 2);
+        self.histogram_mode = self.histogram_mode.next(is_matrix);
+    }
+
+    fn run_palette_command(&mut self, input: &str) {
+        let (name, argument) = split_input(input);
+        let commands = matching_commands(name);
+        let Some(&command) = commands.get(self.palette_selected.min(commands.len().max(1) - 1))
+        else {
+            self.dialog_type = Some(DialogType::Error(format!("no command matches {name:?}")));
+            return;
+        };
+        self.run_command(command, argument);
+    }
+
+    fn run_command(&mut self, command: Command, argument: &str) {
+        match command {
+            Command::Open if argument.is_empty() => self.open_open_dialog(),
+            Command::Bins if argument.is_empty() => {
+                // Ask for the argument in the palette
+                self.edit_draft = format!("{} ", command.name());
+                self.palette_selected = 0;
+                self.dialog_type = Some(DialogType::Palette);
+            }
+            Command::Open => {
+                if let Err(err) = self.load_file(PathBuf::from(argument)) {
+                    self.dialog_type = Some(DialogType::Error(err.to_string()));
+                }
+            }
+            Command::Browse => self.browse(argument),
+            Command::Slice if argument.is_empty() => self.open_slice_dialog(),
+            Command::Slice => self.set_slice(argument),
+            Command::Range if argument.is_empty() => self.open_range_dialog(),
+            Command::Range => self.set_histogram_range(argument),
+            Command::Export if argument.is_empty() => self.open_export_dialog(),
+            Command::Export => self.export_selected_tensor(Path::new(argument)),
+            Command::SaveAs => self.open_save_as_dialog(),
+            Command::Rename => self.open_rename_dialog(),
+            Command::Delete => self.open_delete_dialog(),
+            Command::AddMetadata if argument.is_empty() => self.open_add_metadata_dialog(),
+            Command::AddMetadata => self.add_metadata(argument),
+            Command::Sort => {
+                if let Some(s) = &mut self.tree_state {
+                    s.cycle_sort();
+                }
+            }
+            Command::Expand if argument.is_empty() => self.expand_focused_tree(None),
+            Command::Expand => match argument.parse::<usize>() {
+                Ok(depth) => self.expand_focused_tree(Some(depth)),
+                Err(_) => {
+                    let message = format!("expected a depth, not {argument:?}");
+                    self.dialog_type = Some(DialogType::Error(message));
+                }
+            },
+            Command::Collapse => self.expand_focused_tree(Some(0)),
+            Command::Table => self.open_table_view(),
+            Command::Lora => self.open_lora_view(),
+            Command::Similarity => self.open_similarity_view(argument),
+            Command::Progression => self.open_progression_view(argument, LayerMetric::default()),
+            Command::Heads if argument.is_empty() => self.open_head_view(None),
+            Command::Heads => {
+                let counts = match argument.split_once('/') {
+                    Some((heads, kv)) => heads.trim().parse().ok().zip(kv.trim().parse().ok()),
+                    None => argument.parse().ok().map(|heads| (heads, heads)),
+                };
+                match counts {
+                    Some(counts) => self.open_head_view(Some(counts)),
+                    None => {
+                        let message =
+                            format!("expected a head count like 32 or 32/8, not {argument:?}");
+                        self.dialog_type = Some(DialogType::Error(message));
+                    }
+                }
+            }
+            Command::Tokenizer => self.open_tokenizer_view(),
+            Command::Image => self.open_image_view(),
+            Command::Bytes => self.open_byte_view(),
+            Command::Mark => self.toggle_mark(),
+            Command::Split => self.toggle_split_view(),
+            Command::Bookmark => self.toggle_bookmark(),
+            Command::Optimizer => {
+                if let Err(err) = self.toggle_optimizer_grouping() {
+                    self.dialog_type = Some(DialogType::Error(err.to_string()));
+                }
+            }
+            Command::Compute => self.handle_y_key(),
+            Command::HistogramMode => self.cycle_histogram_mode(),
+            Command::ChildMetric => self.child_metric = self.child_metric.next(),
+            Command::GroupLayers => {
+                self.group_layers = !self.group_layers;
+                if let Err(err) = self.rebuild_module() {
+                    self.dialog_type = Some(DialogType::Error(err.to_string()));
+                }
+            }
+            Command::Flatten => {
+                match argument {
+                    "" => self.flatten = !self.flatten,
+                    "off" => self.flatten = false,
+                    depth => match depth.parse() {
+                        Ok(depth) => {
+                            self.flatten = true;
+                            self.flatten_depth = depth;
+                        }
+                        Err(_) => {
+                            let message = format!("expected a depth or off, not {argument:?}");
+                            self.dialog_type = Some(DialogType::Error(message));
+                            return;
+                        }
+                    },
+                }
+                if let Err(err) = self.rebuild_module() {
+                    self.dialog_type = Some(DialogType::Error(err.to_string()));
+                }
+            }
+            Command::Filter => {
+                let filter = match argument {
+                    "" | "off" => None,
+                    text => match text.parse::<TensorFilter>() {
+                        Ok(filter) => Some(filter),
+                        Err(err) => {
+                            self.dialog_type = Some(DialogType::Error(err.to_string()));
+                            return;
+                        }
+                    },
+                };
+                if let Some(tree) = &mut self.tree_state {
+                    tree.set_filter(filter.clone());
+                }
+                self.tensor_filter = filter;
+            }
+            Command::Details => self.hide_tensor_details = !self.hide_tensor_details,
+            Command::QuantError => self.request_quant_errors(),
+            Command::LogScale => self.log_counts = !self.log_counts,
+            Command::Bins => match argument.parse::<usize>() {
+                Ok(count) if count > 0 => self.set_bin_count(count),
+                _ => {
+                    let message = format!("expected a positive bin count, not {argument:?}");
+                    self.dialog_type = Some(DialogType::Error(message));
+                }
+            },
+            Command::HealthScan => self.start_health_scan(),
+            Command::Duplicates => self.start_duplicate_scan(),
+            Command::Hash => self.hash_selected_tensor(),
+            Command::Check => self.open_diagnostics_view(),
+            Command::Breakdown => self.open_breakdown_view(),
+            Command::Treemap => self.treemap_view = Some(TreemapView::default()),
+            Command::Batch => self.queue_batch_analysis(),
+            Command::Layout => self.open_layout_view(),
+            Command::Manifest => self.start_manifest(PathBuf::from(argument)),
+            Command::Quit => self.should_quit = true,
+        }
+    }
+
+    /// Draws only when something may have changed: after input, including terminal resizes, or
+    /// when background work sends a notification
+    pub fn run(&mut self, terminal: &mut Terminal<Backend>) -> Result<(), Error> {
+        let (sender, receiver) = mpsc::channel();
+        let work_sender = sender.clone();
+        // Notifications sent faster than frames are drawn collapse into one
+        let work_pending = Arc::new(AtomicBool::new(false));
+        let pending = work_pending.clone();
+        notify::set_waker(move || {
+            if !pending.swap(true, Relaxed) {
+                let _ = work_sender.send(Wake::Work);
+            }
+        });
+        std::thread::spawn(move || {
+            loop {
+                let event = event::read();
+                let failed = event.is_err();
+                if sender.send(Wake::Input(event)).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        while !self.should_quit {
+            self.poll_save_job();
+            self.poll_manifest_job();
+            self.poll_batch();
+            self.poll_tokenizer_view();
+            terminal.draw(|f| {
+                self.render_ui(f);
+                self.simplify_frame(f.buffer_mut());
+            })?;
+            // Keep the spinner turning while work runs without reporting progress
+            let wake = if self.background_activity().is_some() {
+                self.spinner_frame += 1;
+                match receiver.recv_timeout(SPINNER_INTERVAL) {
+                    Ok(wake) => wake,
+                    Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                    Err(err) => return Err(err.into()),
+                }
+            } else {
+                receiver.recv()?
+            };
+            match wake {
+                Wake::Input(event) => self.handle_event(event?)?,
+                Wake::Work => work_pending.store(false, Relaxed),
+            }
+        }
+        self.store_cached_analysis();
+        Ok(())
+    }
+
+    /// Describes the first background job still running, for the status line
+    fn background_activity(&self) -> Option<String> {
+        if let Some(job) = &self.save_job
+            && job.result.get().is_none()
+        {
+            let done = job.done.load(Relaxed);
+            let progress = job.progress.load(Relaxed);
+            let path = job.path.display();
+            return Some(format!(
+                "writing {path}… {done}/{} ({progress}%)",
+                job.total
+            ));
+        }
+        for analysis in [&self.current_analysis, &self.marked_analysis]
+            .into_iter()
+            .flatten()
+        {
+            let Some(stage) = analysis.timings.running() else {
+                continue;
+            };
+            return Some(match stage {
+                "read" => {
+                    let size = self.format_bytes(analysis.tensor.size as u64);
+                    match analysis.progress.load(Relaxed) {
+                        // Statistics are computed as part of reading, once the data is in
+                        100.. => "computing statistics…".to_string(),
+                        progress => format!("reading tensor {size}… {progress}%"),
+                    }
+                }
+                "spectrum" => {
+                    let progress = analysis.progress.load(Relaxed);
+                    format!("computing SVD… {progress}%")
+                }
+                "quantization" => {
+                    let progress = analysis.quant_error_progress.load(Relaxed);
+                    format!("re-quantizing… {progress}%")
+                }
+                "slice norms" => {
+                    let progress = analysis.slice_norms_progress.load(Relaxed);
+                    format!("computing slice norms… {progress}%")
+                }
+                stage => format!("computing {stage}…"),
+            });
+        }
+        if let Some(analysis) = &self.module_analysis
+            && analysis.stats.get().is_none()
+            && analysis.error.get().is_none()
+        {
+            let done = analysis.done.load(Relaxed);
+            let progress = analysis.progress.load(Relaxed);
+            return Some(format!(
+                "reading module… {done}/{} ({progress}%)",
+                analysis.total
+            ));
+        }
+        if let Some(comparison) = &self.comparison
+            && comparison.metrics.get().is_none()
+            && comparison.error.get().is_none()
+        {
+            let progress = comparison.progress.load(Relaxed);
+            return Some(format!("comparing with marked tensor… {progress}%"));
+        }
+        if let Some(batch) = &self.batch {
+            let done = batch.analysis.done.load(Relaxed);
+            let total = batch.analysis.tensors.len();
+            if done < total {
+                return Some(format!("analyzing {}… {done}/{total}", batch.module));
+            }
+        }
+        if let Some(scan) = &self.health_scan
+            && !scan.is_finished()
+        {
+            let done = scan.done.load(Relaxed);
+            let progress = scan.progress.load(Relaxed);
+            return Some(format!(
+                "scanning health… {done}/{} ({progress}%)",
+                scan.total
+            ));
+        }
+        if let Some(scan) = &self.duplicate_scan
+            && scan.groups.get().is_none()
+            && scan.error.get().is_none()
+        {
+            let done = scan.done.load(Relaxed);
+            let progress = scan.progress.load(Relaxed);
+            return Some(format!(
+                "hashing for duplicates… {done}/{} ({progress}%)",
+                scan.total
+            ));
+        }
+        let manifest = self.manifest_job.as_ref().map(|(_, job)| job);
+        for job in [manifest, self.tensor_hash.as_ref()].into_iter().flatten() {
+            if job.entries.get().is_none() && job.error.get().is_none() {
+                let done = job.done.load(Relaxed);
+                let progress = job.progress.load(Relaxed);
+                return Some(format!(
+                    "hashing… {done}/{} ({progress}%)",
+                    job.tensors.len()
+                ));
+            }
+        }
+        None
+    }
+
+    /// Drops every background job which hasn't finished, which stops its thread at the next
+    /// chunk it reads
+    fn cancel_background_work(&mut self) {
+        if self.save_job.is_some() {
+            self.save_job = None;
+            self.dialog_type = None;
+        }
+        let running = |analysis: &Option<Own<Box<Analysis>>>| {
+            analysis
+                .as_ref()
+                .is_some_and(|analysis| analysis.timings.running().is_some())
+        };
+        if running(&self.current_analysis) {
+            self.current_analysis = None;
+        }
+        if running(&self.marked_analysis) {
+            self.marked_analysis = None;
+        }
+        if self
+            .module_analysis
+            .as_ref()
+            .is_some_and(|analysis| analysis.stats.get().is_none())
+        {
+            self.module_analysis = None;
+        }
+        if self
+            .comparison
+            .as_ref()
+            .is_some_and(|comparison| comparison.metrics.get().is_none())
+        {
+            self.comparison = None;
+        }
+        if self
+            .batch
+            .as_ref()
+            .is_some_and(|batch| batch.analysis.done.load(Relaxed) < batch.analysis.tensors.len())
+        {
+            self.poll_batch();
+            self.batch = None;
+            self.batch_view = None;
+        }
+        if self
+            .health_scan
+            .as_ref()
+            .is_some_and(|scan| !scan.is_finished())
+        {
+            self.health_scan = None;
+        }
+        if self
+            .duplicate_scan
+            .as_ref()
+            .is_some_and(|scan| scan.groups.get().is_none())
+        {
+            self.duplicate_scan = None;
+        }
+        if self
+            .manifest_job
+            .as_ref()
+            .is_some_and(|(_, job)| job.entries.get().is_none())
+        {
+            self.manifest_job = None;
+        }
+        if self
+            .tensor_hash
+            .as_ref()
+            .is_some_and(|job| job.entries.get().is_none())
+        {
+            self.tensor_hash = None;
+        }
+    }
+
+    /// Applies `--ascii` and `NO_COLOR` after the fact, so no view has to handle them itself
+    fn simplify_frame(&self, buf: &mut Buffer) {
+        if !self.ascii && !self.no_color {
+            return;
+        }
+        for cell in &mut buf.content {
+            if self.ascii && !cell.symbol().is_ascii() {
+                cell.set_char(ascii_glyph(cell.symbol()));
+            }
+            if self.no_color {
+                if cell.bg != Color::Reset {
+                    cell.modifier |= Modifier::REVERSED;
+                }
+                cell.fg = Color::Reset;
+                cell.bg = Color::Reset;
+            }
+        }
+    }
+
+    fn render_ui(&mut self, f: &mut ratatui::Frame) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3), // Top bar
+                Constraint::Min(1),    // Main content
+                Constraint::Length(3), // Bottom bar
+            ])
             .split(f.area());
+        self.panel_areas.clear();
+
+        // Top bar
+        let title = if let Some(path) = &self.file_path {
+            let mode = if self.read_only { " [read-only]" } else { "" };
+            let problems = match self.problems.len() {
+                0 => String::new(),
+                1 => " - 1 integrity problem (:check)".to_string(),
+                n => format!(" - {n} integrity problems (:check)"),
+            };
+            format!("CheckpoinTUI - {}{mode}{problems}", path.display())
+        } else {
+            "CheckpoinTUI - No file loaded".to_string()
+        };
+
+        let top_bar = Paragraph::new(title)
+            .block(Block::default().borders(Borders::ALL))
+            .style(Style::default().fg(self.theme.border_secondary));
+        f.render_widget(top_bar, chunks[0]);
+
+        // Main content area
+        if self.directory_view.is_some() {
+            self.render_directory_view(f, chunks[1]);
+        } else if self.image_view.is_some() {
+            self.render_image_view(f, chunks[1]);
+        } else if self.tokenizer_view.is_some() {
+            self.render_tokenizer_view(f, chunks[1]);
+        } else if self.diagnostics_view.is_some() {
+            self.render_diagnostics_view(f, chunks[1]);
+        } else if self.batch_view.is_some() {
+            self.render_batch_view(f, chunks[1]);
+        } else if self.layout_view.is_some() {
+            self.render_layout_view(f, chunks[1]);
+        } else if self.breakdown_view.is_some() {
+            self.render_breakdown_view(f, chunks[1]);
+        } else if self.treemap_view.is_some() {
+            self.render_treemap_view(f, chunks[1]);
+        } else if self.progression_view.is_some() {
+            self.render_progression_view(f, chunks[1]);
+        } else if self.head_view.is_some() {
+            self.render_head_view(f, chunks[1]);
+        } else if self.similarity_view.is_some() {
+            self.render_similarity_view(f, chunks[1]);
+        } else if self.lora_view.is_some() {
+            self.render_lora_view(f, chunks[1]);
+        } else if self.byte_view.is_some() {
+            self.render_byte_view(f, chunks[1]);
+        } else if self.table_view.is_some() {
+            self.render_table_view(f, chunks[1]);
+        } else if self.tree_state.is_some() {
+            let should_show_analysis = self.should_show_analysis_panel();
+
+            if self.shows_split_view() {
+                // The marked and selected tensors side by side, in place of the info panels
+                let [tree, info, analysis] = self.panel_widths;
+                let [tree_area, split_area] =
+                    Layout::horizontal([Constraint::Fill(tree), Constraint::Fill(info + analysis)])
+                        .areas(chunks[1]);
+                self.render_tree_column(f, tree_area);
+                self.render_split_view(f.buffer_mut(), split_area);
+                self.panel_areas.push((Panel::Analysis, split_area));
+            } else if should_show_analysis {
+                // Three-panel layout when tensor is selected
+                let [tree, info, analysis] = self.panel_widths;
+                let main_chunks = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([
+                        Constraint::Fill(tree),     // Tree panel
+                        Constraint::Fill(info),     // Info panel
+                        Constraint::Fill(analysis), // Analysis panel
+                    ])
+                    .split(chunks[1]);
+
+                self.render_tree_column(f, main_chunks[0]);
+
+                let file_info_area = self.render_info_panels(f, main_chunks[1]);
+                self.render_analysis_panel(f, main_chunks[2]);
+                self.panel_areas.extend([
+                    (Panel::FileInfo, file_info_area),
+                    (Panel::Analysis, main_chunks[2]),
+                ]);
+            } else {
+                // Two-panel layout when module is selected
+                let [tree, info, _] = self.panel_widths;
+                let main_chunks = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([
+                        Constraint::Fill(tree), // Tree panel
+                        Constraint::Fill(info), // Info panel
+                    ])
+                    .split(chunks[1]);
+
+                self.render_tree_column(f, main_chunks[0]);
+
+                let file_info_area = self.render_info_panels(f, main_chunks[1]);
+                self.panel_areas.push((Panel::FileInfo, file_info_area));
+            }
+        } else {
+            let help_area = self.render_recent_files(f, chunks[1]);
+            let help = Paragraph::new(self.helptext.as_str())
+                .block(Block::default().borders(Borders::ALL).title("Help"))
+                .style(Style::default().fg(self.theme.text));
+            f.render_widget(help, help_area);
+        }
+
+        // Bottom bar
+        let help_text = if self.directory_view.is_some() {
+            "↑/↓/PgUp/PgDn: Select | Enter: Open | B/Esc: Close | q: Quit"
+        } else if self.image_view.is_some() {
+            "i/Esc: Close Image | q: Quit"
+        } else if self.tokenizer_view.is_some() {
+            "Type: Search/Encode | Tab: Switch Search/Encode/Untrained | Enter: Nearest Tokens | ↑/↓/PgUp/PgDn: Navigate | Esc: Close Tokenizer"
+        } else if self.diagnostics_view.is_some() {
+            "↑/↓/PgUp/PgDn: Scroll | Esc: Close Diagnostics | q: Quit"
+        } else if self.batch_view.is_some() {
+            "↑/↓/PgUp/PgDn: Select | Enter: Go To Tree | A/Esc: Close Batch | q: Quit"
+        } else if self.layout_view.is_some() {
+            "↑/↓/PgUp/PgDn: Select | Enter: Go To Tree | F/Esc: Close Layout | q: Quit"
+        } else if self.breakdown_view.is_some() {
+            "↑/↓/PgUp/PgDn: Scroll | %/Esc: Close Breakdown | q: Quit"
+        } else if self.treemap_view.is_some() {
+            "↑/↓/←/→: Select | Enter/Click: Go To Tree | t/Esc: Close Treemap | q: Quit"
+        } else if self.progression_view.is_some() {
+            "←/→: Select Layer | M: Metric | P/Esc: Close Progression | q: Quit"
+        } else if self.head_view.is_some() {
+            "↑/↓/PgUp/PgDn: Select Head | h/Esc: Close Heads | q: Quit"
+        } else if self.similarity_view.is_some() {
+            "↑/↓/←/→: Select Pair | Esc: Close Similarity | q: Quit"
+        } else if self.lora_view.is_some() {
+            "↑/↓: Select Adapter | L/Esc: Close LoRA View | q: Quit"
+        } else if self.byte_view.is_some() {
+            "↑/↓/PgUp/PgDn/Home/End: Scroll | v/Esc: Close Bytes | q: Quit"
+        } else if self.table_view.is_some() {
+            "↑/↓/PgUp/PgDn: Navigate | s: Sort Column | S: Reverse | H: Compute Stats | T/Esc: Close Table | q: Quit"
+        } else if self.tree_state.is_some() {
+            if self.selected_panel == Panel::FileInfo && self.is_metadata_item_selected() {
+                "↑/↓: Navigate | ←/→: Enter/Exit | Space: Expand/Collapse | s: Sort | E/C/1-9: Expand/Collapse | e: Edit | d: Delete | a: Add | Tab: Switch Panel | :: Commands | q: Quit"
+            } else if self.selected_panel == Panel::Bookmarks {
+                "↑/↓: Flip Between Bookmarks | Enter: Go To Tree | b/d: Unpin | Tab/Shift+Tab: Switch Panel | :: Commands | q/Esc: Quit"
+            } else if self.selected_panel == Panel::Analysis {
+                "↑/↓/PgUp/PgDn: Scroll | Shift+↑/↓/←/→: Scroll Values | y: Compute | a: Histogram Mode | +/-: Bins | z: Zoom | M: Child Metric | Q: Quant Error | l: Log Scale | Tab/Shift+Tab: Switch Panel | :: Commands | q/Esc: Quit"
+            } else {
+                "↑/↓: Navigate | ←/→: Enter/Exit Module | Space/Enter: Expand/Collapse | E/C/1-9: Expand All/Collapse All/To Depth | s: Sort | w: Hide Details | r: Rename | D: Delete | x: Export | v: Bytes | [: Slice | {/}: Step Slice | m: Mark/Compare | |: Split View | b: Bookmark | L: LoRA | K: Tokenizer | O: Group Optimizer State | G: Group Layers | U: Flatten | S: Save As | o: Open | H: Health Scan | A: Analyze All | F: Layout | P: Progression | h: Heads | %: Breakdown | t: Treemap | Tab/Shift+Tab: Switch Panel | Ctrl+←/→: Resize Panel | Ctrl-p: Jump | :: Commands | q/Esc: Quit"
+            }
+        } else if !self.recent.files.is_empty() {
+            "↑/↓: Select | Enter: Open Recent | o: Open | B: Browse | q/Esc: Quit"
+        } else {
+            "o: Open | q/Esc: Quit"
+        };
+
+        let mut bottom_block = Block::default().borders(Borders::ALL);
+        if let Some(activity) = self.background_activity() {
+            let spinner = if self.ascii {
+                &SPINNER_ASCII[..]
+            } else {
+                &SPINNER[..]
+            };
+            let frame = spinner[self.spinner_frame % spinner.len()];
+            bottom_block = bottom_block.title(Line::from(vec![
+                format!("{frame} {activity}").fg(self.theme.accent),
+                " Ctrl-c: Cancel".fg(self.theme.muted),
+            ]));
+        }
+        let bottom_bar = Paragraph::new(help_text)
+            .block(bottom_block)
+            .style(Style::default().fg(self.theme.muted));
+        f.render_widget(bottom_bar, chunks[2]);
+
+        // Render dialog overlay if open
+        if self.dialog_type.is_some() {
+            self.render_dialog(f, f.area());
+        }
+    }
+
+    fn render_tree_panel(&mut self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(tree) = &self.tree_state else {
+            return;
+        };
+
+        let bookmarks = self.file_bookmarks();
+        let width = area.width.saturating_sub(2) as usize;
+        let lines: Vec<Line> = tree
+            .visible_items
+            .iter()
+            .map(|item| {
+                let mut spans = Vec::new();
+
+                // Indentation
+                if item.depth > 0 {
+                    spans.push("  ".repeat(item.depth as usize).into());
+                }
+
+                // Icon
+                let health = self.tensor_health(&item.info);
+                let is_marked = self.marked.as_ref().is_some_and(|(name, _)| {
+                    item.info.is_tensor() && *name == item.info.full_name.to_string()
+                });
+                let icon_span = if health.as_ref().is_some_and(|h| !h.is_ok()) {
+                    "⚠ ".fg(self.theme.warning)
+                } else if is_marked {
+                    "◆ ".fg(self.theme.accent)
+                } else if item.has_children() {
+                    if item.is_expanded { "▼ " } else { "▶ " }.into()
+                } else if item.info.is_tensor() {
+                    "📄 ".into()
+                } else {
+                    "  ".into()
+                };
+                spans.push(icon_span);
+                let name_at = spans.len();
+
+                // Value, for scalars
+                let scalar = item
+                    .info
+                    .tensor_info
+                    .as_ref()
+                    .filter(|tensor| tensor.shape.is_empty())
+                    .zip(self.scalar_values.as_ref())
+                    .and_then(|(_, scalars)| {
+                        let values = scalars.values.lock().unwrap();
+                        values.get(&item.info.full_name.to_string()).copied()
+                    });
+                if let Some(value) = scalar {
+                    spans.push(format!(" = {}", format_scalar(value)).fg(self.theme.literal));
+                }
+
+                // Parameter count
+                let param_text = format!(" ({})", self.format_count(item.info.total_params));
+                spans.push(param_text.fg(self.theme.count));
+                if let Some(&shown) = tree.shown.get(&item.info.unique_id()) {
+                    let hidden = item.info.total_tensors - shown;
+                    if hidden > 0 && !item.is_expanded {
+                        spans.push(format!(" +{hidden} hidden").fg(self.theme.muted));
+                    }
+                }
+
+                // Tensor details
+                if let Some(tensor_info) = item
+                    .info
+                    .tensor_info
+                    .as_ref()
+                    .filter(|_| !self.hide_tensor_details)
+                {
+                    spans.push(format!(" {:?}", tensor_info.shape).fg(self.theme.shape));
+                    spans.push(format!(" {}", tensor_info.ty).fg(self.theme.dtype));
+                    let size = self.format_bytes(tensor_info.size as u64);
+                    spans.push(format!(" {size}").fg(self.theme.bytesize));
+                }
+                if bookmarks.contains(&item.info.full_name.to_string()) {
+                    spans.push(" ★".fg(self.theme.accent));
+                }
+                if let Some((others, _)) = self.duplicates(&item.info) {
+                    let mut text = format!(" == {}", others[0]);
+                    if others.len() > 1 {
+                        text += &format!(" (+{})", others.len() - 1);
+                    }
+                    spans.push(text.fg(self.theme.warning));
+                }
+
+                // Name, shortened in the middle when the rest of the line leaves too little room
+                let rest: usize = spans.iter().map(|span| span.width()).sum();
+                let name = truncate_middle(&item.name, width.saturating_sub(rest));
+                let name_span = if item.info.is_tensor() {
+                    name.fg(self.theme.tensor)
+                } else if item.has_children() {
+                    name.fg(self.theme.module).bold()
+                } else {
+                    name.white()
+                };
+                spans.insert(name_at, name_span);
+
+                Line::from(spans)
+            })
+            .collect();
+
+        let mut title: Line = "Module Tree".into();
+        if !tree.data.full_name.is_empty() {
+            title += " - ".into();
+            title += tree.data.full_name.fg(self.theme.module);
+        }
+        if tree.sort != SortMode::Name {
+            title += format!(" (by {})", tree.sort.label()).into();
+        }
+        if let Some(filter) = &tree.filter {
+            let label = filter.label(|bytes| self.format_bytes(bytes));
+            title += format!(" - only {label} (:filter)").fg(self.theme.accent);
+        }
+        if let Some(scan) = &self.health_scan
+            && !scan.is_finished()
+        {
+            title += format!(" - scanning {}/{}", scan.done.load(Relaxed), scan.total)
+                .fg(self.theme.warning);
+        }
+        if let Some(scan) = &self.duplicate_scan {
+            if let Some(error) = scan.error.get() {
+                title += format!(" - duplicate scan failed: {error}").fg(self.theme.error);
+            } else if let Some(groups) = scan.groups.get() {
+                title += format!(" - {} duplicate groups", groups.len()).fg(self.theme.muted);
+            } else {
+                let done = scan.done.load(Relaxed);
+                title += format!(" - hashing {done}/{}", scan.total).fg(self.theme.warning);
+            }
+        }
+        if let Some((_, job)) = &self.manifest_job {
+            let done = job.done.load(Relaxed);
+            title += format!(" - manifest {done}/{}", job.tensors.len()).fg(self.theme.warning);
+        }
+
+        let items: Vec<ListItem> = lines.into_iter().map(ListItem::new).collect();
+
+        let list = List::new(items)
+            .block(self.format_block(title, Panel::Tree))
+            .style(Style::default().fg(self.theme.text))
+            .highlight_style(
+                Style::default()
+                    .bg(self.theme.selection)
+                    .fg(self.theme.text),
+            );
+        StatefulWidget::render(
+            list,
+            area,
+            f.buffer_mut(),
+            &mut *tree.list_state.borrow_mut(),
+        );
+    }
+
+    /// Draws the tree, with the file's bookmarks listed under it once there are any
+    fn render_tree_column(&mut self, f: &mut ratatui::Frame, area: Rect) {
+        let count = self.file_bookmarks().len();
+        if count == 0 {
+            self.render_tree_panel(f, area);
+            self.panel_areas.push((Panel::Tree, area));
+            return;
+        }
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(3),
+                Constraint::Length(count.min(BOOKMARK_ROWS) as u16 + 2),
+            ])
+            .split(area);
+        self.render_tree_panel(f, chunks[0]);
+        self.render_bookmarks_panel(f, chunks[1]);
+        self.panel_areas
+            .extend([(Panel::Tree, chunks[0]), (Panel::Bookmarks, chunks[1])]);
+    }
+
+    fn render_bookmarks_panel(&self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(tree) = &self.tree_state else {
+            return;
+        };
+        let root = tree.data_history.first().unwrap_or(&tree.data);
+        let items: Vec<ListItem> = self
+            .file_bookmarks()
+            .iter()
+            .map(|name| {
+                let line = match root.find(name) {
+                    Some(module) if module.is_tensor() => {
+                        Line::from(name.as_str().fg(self.theme.tensor))
+                    }
+                    Some(_) => Line::from(name.as_str().fg(self.theme.module).bold()),
+                    // Renamed or deleted since it was pinned
+                    None => Line::from(vec![
+                        name.as_str().fg(self.theme.muted),
+                        " (missing)".fg(self.theme.error),
+                    ]),
+                };
+                ListItem::new(line)
+            })
+            .collect();
+        let title = format!("Bookmarks ({})", items.len());
+        let list = List::new(items)
+            .block(self.format_block(title, Panel::Bookmarks))
+            .style(Style::default().fg(self.theme.text))
+            .highlight_style(
+                Style::default()
+                    .bg(self.theme.selection)
+                    .fg(self.theme.text),
+            );
+        StatefulWidget::render(
+            list,
+            area,
+            f.buffer_mut(),
+            &mut *self.bookmark_state.borrow_mut(),
+        );
+    }
+
+    fn render_selected_info_panel(&self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(tree) = &self.tree_state else { return };
+        let selected_item = tree
+            .list_state
+            .borrow()
+            .selected()
+            .and_then(|i| tree.visible_items.get(i));
+
+        let mut text = Text::default();
+        let title = if let Some(item) = selected_item {
+            if let Some(tensor_info) = &item.info.tensor_info {
+                text.push_line(vec![
+                    "Path: ".bold(),
+                    item.info.full_name.fg(self.theme.tensor),
+                ]);
+                text.push_line(vec![
+                    "Shape: ".bold(),
+                    format!("{:?}", tensor_info.shape).fg(self.theme.shape),
+                ]);
+                text.push_line(vec![
+                    "Data Type: ".bold(),
+                    format!("{}", tensor_info.ty).fg(self.theme.dtype),
+                ]);
+                text.push_line(vec![
+                    "Parameters: ".bold(),
+                    self.format_count(item.info.total_params)
+                        .fg(self.theme.count),
+                ]);
+                text.push_line(vec![
+                    "Size: ".bold(),
+                    self.format_bytes(tensor_info.size as u64)
+                        .fg(self.theme.bytesize),
+                ]);
+                let end = tensor_info.offset + tensor_info.size as u64;
+                text.push_line(vec![
+                    "Offset: ".bold(),
+                    format!("{}..{end}", tensor_info.offset).into(),
+                ]);
+                if let Some(health) = self.tensor_health(&item.info) {
+                    let color = if health.is_ok() {
+                        self.theme.success
+                    } else {
+                        self.theme.warning
+                    };
+                    text.push_line(vec!["Health: ".bold(), health.to_string().fg(color)]);
+                }
+                if let Some((others, shared)) = self.duplicates(&item.info) {
+                    let label = if shared {
+                        "Shares storage with: "
+                    } else {
+                        "Same bytes as: "
+                    };
+                    text.push_line(vec![label.bold(), others.join(", ").fg(self.theme.warning)]);
+                }
+                let name = item.info.full_name.to_string();
+                for problem in &self.problems {
+                    if problem.tensor.as_ref() == Some(&name) {
+                        text.push_line(vec![
+                            "Integrity: ".bold(),
+                            problem.message.clone().fg(self.theme.error),
+                        ]);
+                    }
+                }
+                if let Some((hash, color)) = self.tensor_hash_line(&item.info) {
+                    text.push_line(vec!["SHA-256: ".bold(), hash.fg(color)]);
+                }
+                "Tensor Info"
+            } else {
+                text.push_line(vec![
+                    "Path: ".bold(),
+                    item.info.full_name.fg(self.theme.module),
+                ]);
+                text.push_line(vec![
+                    "Tensors: ".bold(),
+                    item.info.total_tensors.to_string().fg(self.theme.count),
+                ]);
+                text.push_line(vec![
+                    "Parameters: ".bold(),
+                    self.format_count(item.info.total_params)
+                        .fg(self.theme.count),
+                ]);
+                "Module Info"
+            }
+        } else {
+            text.extend(Text::from("No item selected".gray()));
+            "Selection Info"
+        };
+
+        let info = Paragraph::new(text)
+            .block(self.format_block(title, Panel::SelectedInfo))
+            .style(Style::default().fg(self.theme.text))
+            .wrap(Wrap { trim: false });
+
+        f.render_widget(info, area);
+    }
+
+    /// Stacks the selected item, architecture summary, and file metadata, returning the area
+    /// of the metadata panel
+    fn render_info_panels(&mut self, f: &mut ratatui::Frame, area: Rect) -> Rect {
+        let architecture_height = if self.architecture.is_empty() {
+            0
+        } else {
+            self.architecture.len() as u16 + 2
+        };
+        let [selected_area, architecture_area, file_info_area] = Layout::vertical([
+            Constraint::Percentage(25), // Selected item info
+            Constraint::Length(architecture_height),
+            Constraint::Fill(1), // File info
+        ])
+        .areas(area);
+        self.render_selected_info_panel(f, selected_area);
+        if !self.architecture.is_empty() {
+            self.render_architecture_panel(f, architecture_area);
+        }
+        self.render_file_meta_tree_panel(f, file_info_area);
+        file_info_area
+    }
+
+    fn render_architecture_panel(&self, f: &mut ratatui::Frame, area: Rect) {
+        let width = self
+            .architecture
+            .iter()
+            .map(|(label, _)| label.len())
+            .max()
+            .unwrap_or(0);
+        let text: Vec<Line> = self
+            .architecture
+            .iter()
+            .map(|(label, value)| {
+                Line::from(vec![
+                    format!("{label:<width$} ").bold(),
+                    value.as_str().fg(self.theme.literal),
+                ])
+            })
+            .collect();
+        let widget = Paragraph::new(text)
+            .block(self.format_block("Architecture", Panel::SelectedInfo))
+            .style(Style::default().fg(self.theme.text));
+        f.render_widget(widget, area);
+    }
+
+    fn render_file_meta_tree_panel(&mut self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(module_tree) = &self.tree_state else {
+            return;
+        };
+
+        // Split the area into file info and metadata tree
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(5), // File info (3 lines + 2 for borders)
+                Constraint::Min(1),    // Metadata tree
+            ])
+            .split(area);
+        self.meta_list_area = chunks[1];
+
+        // Render file info in top section
+        let mut file_info = Text::default();
+        file_info.push_line(vec![
+            "Path: ".bold(),
+            self.file_path
+                .as_ref()
+                .unwrap()
+                .display()
+                .to_string()
+                .fg(self.theme.tensor),
+        ]);
+        file_info.push_line(vec![
+            "Total Tensors: ".bold(),
+            module_tree
+                .data
+                .total_tensors
+                .to_string()
+                .fg(self.theme.count),
+        ]);
+        file_info.push_line(vec![
+            "Total Parameters: ".bold(),
+            self.format_count(module_tree.data.total_params)
+                .fg(self.theme.count),
+        ]);
+
+        let file_info_widget = Paragraph::new(file_info)
+            .block(Block::default().borders(Borders::ALL).title("File Info"))
+            .style(Style::default().fg(self.theme.text));
+        f.render_widget(file_info_widget, chunks[0]);
+
+        // Render metadata tree in bottom section
+        if let Some(tree) = &self.meta_tree_state {
+            let lines: Vec<Line> = tree
+                .visible_items
+                .iter()
+                .map(|item| {
+                    let mut spans = Vec::new();
+
+                    // Indentation
+                    if item.depth > 0 {
+                        spans.push("  ".repeat(item.depth as usize).into());
+                    }
+
+                    // Icon
+                    let icon_span = if item.has_children() {
+                        if item.is_expanded { "▼ " } else { "▶ " }
+                    } else {
+                        "📄 "
+                    }
+                    .into();
+                    spans.push(icon_span);
+
+                    // Name
+                    let name_span = if item.has_children() {
+                        item.name.as_str().fg(self.theme.module).bold()
+                    } else {
+                        item.name.as_str().fg(self.theme.tensor)
+                    };
+                    spans.push(name_span);
+
+                    // Declared type, where it says more than the value does
+                    let ty = (item.depth == 0)
+                        .then(|| self.metadata_types.get(&item.name))
+                        .flatten();
+                    if let Some(ty) = ty.filter(|ty| **ty != MetadataType::String) {
+                        spans.push(format!(": {ty}").fg(self.theme.dtype));
+                    }
+
+                    // Value (for leaf nodes)
+                    if let Some((_, range)) = as_lazy_array(&item.info) {
+                        let label = format!(" = [{} values, Enter to load]", range.len());
+                        spans.push(label.fg(self.theme.muted));
+                    } else if is_image(&item.info) {
+                        spans.push(" = [image, i to preview]".fg(self.theme.muted));
+                    } else if shorten_value(&*item.info) {
+                        spans.push(format!(" = ...").fg(self.theme.muted));
+                    } else {
+                        match &*item.info {
+                            Value::Null => {
+                                spans.push(format!(" = null").fg(self.theme.muted));
+                            }
+                            Value::Bool(bool) => {
+                                spans.push(format!(" = {bool}").fg(self.theme.literal));
+                            }
+                            Value::Number(number) => {
+                                spans.push(format!(" = {number}").fg(self.theme.literal));
+                            }
+                            Value::String(string) => {
+                                spans.push(format!(" = {string}").fg(self.theme.text));
+                            }
+                            Value::Array(_) => {}
+                            Value::Object(_) => {}
+                        }
+                    }
+
+                    Line::from(spans)
+                })
+                .collect();
+
+            let items: Vec<ListItem> = lines.into_iter().map(ListItem::new).collect();
+
+            let mut title: Line = "Metadata".into();
+            if tree.sort != SortMode::Name {
+                title += format!(" (by {})", tree.sort.label()).into();
+            }
+            let list = List::new(items)
+                .block(self.format_block(title, Panel::FileInfo))
+                .style(Style::default().fg(self.theme.text))
+                .highlight_style(
+                    Style::default()
+                        .bg(self.theme.selection)
+                        .fg(self.theme.text),
+                );
+            StatefulWidget::render(
+                list,
+                chunks[1],
+                f.buffer_mut(),
+                &mut *tree.list_state.borrow_mut(),
+            );
+        } else {
+            let no_metadata = Paragraph::new("No metadata available")
+                .block(self.format_block("Metadata", Panel::FileInfo))
+                .style(Style::default().fg(self.theme.muted));
+            f.render_widget(no_metadata, chunks[1]);
+        }
+    }
+
+    fn format_block<'a>(&self, title: impl Into<Line<'a>>, panel: Panel) -> Block<'a> {
+        let mut title: Line = title.into();
+        let border_style = if self.selected_panel == panel {
+            title += "*".into();
+            Style::default().fg(self.theme.border_selected)
+        } else {
+            Style::default().fg(self.theme.border)
+        };
+        title = title.bold();
+
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(border_style)
+            .title(title)
+    }
+
+    fn format_count(&self, count: u64) -> String {
+        if count < 1000 {
+            count.to_string()
+        } else {
+            self.count_formatter.format(count as f64)
+        }
+    }
+
+    fn format_bytes(&self, bytes: u64) -> String {
+        if bytes < 1000 {
+            format!("{bytes} Bytes")
+        } else {
+            self.bytes_formatter.format(bytes as f64)
+        }
+    }
+
+    fn should_show_analysis_panel(&self) -> bool {
+        let Some(tree) = &self.tree_state else {
+            return false;
+        };
+        tree.list_state
+            .borrow()
+            .selected()
+            .and_then(|i| tree.visible_items.get(i))
+            .is_some_and(|item| item.info.is_tensor() || item.has_children())
+    }
+
+    fn render_analysis_panel(&mut self, f: &mut ratatui::Frame, area: Rect) {
+        let content_height = area.height.max(self.analysis_content_height());
+        let max_scroll = content_height - area.height;
+        self.analysis_scroll = self.analysis_scroll.min(max_scroll);
+        if max_scroll == 0 {
+            self.render_analysis_content(f.buffer_mut(), area);
+            return;
+        }
+
+        // Draw everything off-screen at full height, then copy out the visible rows
+        let content = Rect {
+            height: content_height,
+            ..area
+        };
+        let mut buf = Buffer::empty(content);
+        self.render_analysis_content(&mut buf, content);
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                f.buffer_mut()[(x, y)] = buf[(x, y + self.analysis_scroll)].clone();
+            }
+        }
+        let mut scrollbar =
+            ScrollbarState::new(max_scroll as usize).position(self.analysis_scroll as usize);
+        f.render_stateful_widget(
+            Scrollbar::new(ScrollbarOrientation::VerticalRight),
+            area,
+            &mut scrollbar,
+        );
+    }
+
+    /// The height the Analysis panel needs to show every section without squashing them
+    fn analysis_content_height(&self) -> u16 {
+        let Some(tree) = &self.tree_state else {
+            return 0;
+        };
+        let selected_item = tree
+            .list_state
+            .borrow()
+            .selected()
+            .and_then(|i| tree.visible_items.get(i));
+        let Some(item) = selected_item else {
+            return 0;
+        };
+        let Some(tensor_info) = &item.info.tensor_info else {
+            let types: HashSet<_> = self
+                .module_analysis
+                .iter()
+                .flat_map(|analysis| &analysis.children)
+                .flat_map(|(_, tensors)| tensors)
+                .map(|(_, tensor)| tensor.ty.to_string())
+                .collect();
+            return types.len() as u16 + 2 + 7 + 2 * ANALYSIS_SECTION_MIN_HEIGHT;
+        };
+        let tensor_info = &self.analyzed_tensor(tensor_info);
+        let elements = tensor_info.shape.iter().product::<u64>();
+        if elements == 0 {
+            return 3;
+        }
+
+        let show_preview = is_previewable(tensor_info);
+        let show_spectrum = elements > 1 && (tensor_info.shape.len() == 2 || !show_preview);
+        let show_quant_errors = self
+            .current_analysis
+            .as_ref()
+            .is_some_and(|a| a.quant_error_go.load(Relaxed));
+        let mut height = 7;
+        if elements > 1 {
+            height += ANALYSIS_SECTION_MIN_HEIGHT;
+        }
+        if self.shows_slice_norms() {
+            height += ANALYSIS_SECTION_MIN_HEIGHT;
+        }
+        if self.comparison.is_some() {
+            height += COMPARISON_HEIGHT;
+        }
+        if let Some(analysis) = &self.optimizer_analysis {
+            height += analysis.states.len() as u16 + 4;
+        }
+        if show_quant_errors {
+            height += QUANT_ERROR_TYPES.len() as u16 + 3;
+        }
+        if show_preview {
+            height += ANALYSIS_SECTION_MIN_HEIGHT;
+        }
+        if show_spectrum {
+            height += ANALYSIS_SECTION_MIN_HEIGHT;
+        }
+        height
+    }
+
+    fn render_analysis_content(&mut self, buf: &mut Buffer, area: Rect) {
+        let tensor_info = {
+            let Some(tree) = &self.tree_state else { return };
+            let selected_item = tree
+                .list_state
+                .borrow()
+                .selected()
+                .and_then(|i| tree.visible_items.get(i));
+
+            let Some(item) = selected_item else {
+                return;
+            };
+
+            let Some(tensor_info) = &item.info.tensor_info else {
+                self.render_module_analysis_panel(buf, area);
+                return;
+            };
+
+            self.analyzed_tensor(tensor_info)
+        };
+
+        let elements = tensor_info.shape.iter().product::<u64>();
+        if elements == 0 {
+            let message = format!(
+                "Empty tensor: shape {:?} holds no values",
+                tensor_info.shape
+            );
+            Paragraph::new(message)
+                .block(self.format_block("Statistics", Panel::Analysis))
+                .style(Style::default().fg(self.theme.muted))
+                .render(area, buf);
+            return;
+        }
+        // A single value has no distribution or spectrum to chart
+        let show_histogram = elements > 1;
+        let show_preview = is_previewable(&tensor_info);
+        let show_spectrum = show_histogram && (tensor_info.shape.len() == 2 || !show_preview);
+        let show_quant_errors = self
+            .current_analysis
+            .as_ref()
+            .is_some_and(|a| a.quant_error_go.load(Relaxed));
+        let show_comparison = self.comparison.is_some();
+        let optimizer_states = self.optimizer_analysis.as_ref().map(|a| a.states.len());
+        let mut constraints = vec![Constraint::Length(7)]; // Statistics (5 lines + 2 for borders)
+        if show_comparison {
+            constraints.push(Constraint::Length(COMPARISON_HEIGHT)); // Comparison
+        }
+        if let Some(states) = optimizer_states {
+            constraints.push(Constraint::Length(states as u16 + 4)); // Optimizer state
+        }
+        if show_histogram {
+            constraints.push(Constraint::Fill(1)); // Histogram
+        }
+        let show_slice_norms = self.shows_slice_norms();
+        if show_slice_norms {
+            constraints.push(Constraint::Fill(1)); // Slice norms (if 3D+)
+        }
+        if show_quant_errors {
+            constraints.push(Constraint::Length(QUANT_ERROR_TYPES.len() as u16 + 3)); // Quantization error
+        }
+        if show_preview {
+            constraints.push(Constraint::Fill(1)); // Values
+        }
+        if show_spectrum {
+            constraints.push(Constraint::Fill(1)); // Singular values (if 2D)
+        }
+        let analysis_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(constraints)
+            .split(area);
+
+        self.render_stats(buf, analysis_chunks[0]);
+        let mut next_chunk = 1;
+        if show_comparison {
+            self.render_comparison(buf, analysis_chunks[next_chunk]);
+            next_chunk += 1;
+        }
+        if optimizer_states.is_some() {
+            self.render_optimizer_state(buf, analysis_chunks[next_chunk]);
+            next_chunk += 1;
+        }
+        if show_histogram {
+            self.render_histogram(buf, analysis_chunks[next_chunk]);
+            next_chunk += 1;
+        }
+        if show_slice_norms {
+            self.render_slice_norms(buf, analysis_chunks[next_chunk]);
+            next_chunk += 1;
+        }
+        if show_quant_errors {
+            self.render_quant_errors(buf, analysis_chunks[next_chunk], &tensor_info);
+            next_chunk += 1;
+        }
+        if show_preview {
+            self.render_preview(buf, analysis_chunks[next_chunk], &tensor_info.shape);
+        }
+
+        if show_spectrum {
+            let spectrum_area = analysis_chunks[analysis_chunks.len() - 1];
+            if tensor_info.shape.len() == 2 {
+                self.render_spectrum(buf, spectrum_area);
+            } else {
+                let placeholder = Paragraph::new("SVD only possible on 2D tensors")
+                    .block(self.format_block("Matrix Spectrum", Panel::Analysis))
+                    .style(Style::default().fg(self.theme.muted));
+                placeholder.render(spectrum_area, buf);
+            }
+        }
+    }
+
+    fn shows_slice_norms(&self) -> bool {
+        self.current_analysis
+            .as_ref()
+            .is_some_and(|a| a.tensor.shape.len() >= 3 && !a.streaming)
+    }
+
+    fn render_slice_norms(&self, buf: &mut Buffer, area: Rect) {
+        let Some(analysis) = self.current_analysis.as_ref() else {
+            return;
+        };
+        let mut text = Text::default();
+        let mut title = "Slice Norms".to_string();
+        match analysis.slice_norms.get() {
+            Some(slice_norms) => {
+                let norms = &slice_norms.norms;
+                let axis = slice_norms.axis;
+                title = self.timed_title(
+                    format!("Slice Norms along Axis {axis} ({{/}}: step)"),
+                    "slice norms",
+                );
+                let current = analysis
+                    .slice
+                    .as_ref()
+                    .filter(|slice| slice.indexed[axis])
+                    .map(|slice| slice.ranges[axis].start as usize);
+                let mut sorted = norms.clone();
+                sorted.sort_unstable_by(f32::total_cmp);
+                let median = sorted.get(sorted.len() / 2).copied().unwrap_or(0.0);
+                let max_value = sorted.last().copied().unwrap_or(0.0);
+                text.push_line(vec![
+                    "Slices: ".bold(),
+                    norms.len().to_string().into(),
+                    "  Median: ".bold(),
+                    format!("{median:.3e}").into(),
+                    "  Max: ".bold(),
+                    format!("{max_value:.3e}").into(),
+                ]);
+
+                // Show the window of slices around the one being analyzed
+                let rows = (area.height as usize).saturating_sub(3).max(1);
+                let first = current
+                    .unwrap_or(0)
+                    .saturating_sub(rows / 2)
+                    .min(norms.len().saturating_sub(rows));
+                let index_width = norms.len().saturating_sub(1).to_string().len();
+                let bar_width = area.width.saturating_sub(index_width as u16 + 15).max(1) as f32;
+                for (i, &norm) in norms.iter().enumerate().skip(first).take(rows) {
+                    let bar = if max_value > 0.0 {
+                        (norm / max_value * bar_width).round() as usize
+                    } else {
+                        0
+                    };
+                    let color = if Some(i) == current {
+                        self.theme.accent
+                    } else {
+                        self.theme.chart
+                    };
+                    text.push_line(vec![
+                        format!("{i:>index_width$} ").fg(color),
+                        "█".repeat(bar).fg(color),
+                        format!(" {norm:.3e}").fg(self.theme.muted),
+                    ]);
+                }
+            }
+            None if analysis.slice.is_some() && analysis.histogram_go.load(Relaxed) => {
+                let progress = analysis.slice_norms_progress.load(Relaxed);
+                text.push_line(
+                    format!("🔄 Computing slice norms... {progress}%").fg(self.theme.accent),
+                );
+            }
+            None if analysis.histogram_go.load(Relaxed) => {
+                text.push_line("🔄 Computing slice norms...".fg(self.theme.accent));
+            }
+            None => {
+                text.push_line("Press \"y\" to compute slice norms".fg(self.theme.error));
+            }
+        }
+        let widget = Paragraph::new(text).block(self.format_block(title, Panel::Analysis));
+        widget.render(area, buf);
+    }
+
+    fn render_module_analysis_panel(&mut self, buf: &mut Buffer, area: Rect) {
+        let Some(analysis) = self.module_analysis.as_ref() else {
+            return;
+        };
+
+        // Parameter and byte counts are known from the header alone
+        let mut by_type: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+        for (_, tensors) in &analysis.children {
+            for (_, tensor) in tensors {
+                let entry = by_type.entry(tensor.ty.to_string()).or_default();
+                entry.0 += tensor.shape.iter().product::<u64>();
+                entry.1 += tensor.size as u64;
+            }
+        }
+        let [types_area, stats_area, histogram_area, norms_area] = Layout::vertical([
+            Constraint::Length(by_type.len() as u16 + 2),
+            Constraint::Length(7),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+        ])
+        .areas(area);
+
+        let mut text = Text::default();
+        for (ty, (params, bytes)) in &by_type {
+            text.push_line(vec![
+                format!("{ty:<8}").fg(self.theme.dtype),
+                format!("{:>10}", self.count_formatter.format(*params as f64)).fg(self.theme.count),
+                format!("  {}", self.format_bytes(*bytes)).fg(self.theme.bytesize),
+            ]);
+        }
+        let widget =
+            Paragraph::new(text).block(self.format_block("Parameters by Type", Panel::Analysis));
+        widget.render(types_area, buf);
+
+        let mut text = Text::default();
+        let done = analysis.done.load(Relaxed);
+        let progress = analysis.progress.load(Relaxed);
+        if let Some(error) = analysis.error.get() {
+            text.push_line(vec![
+                "Error: ".fg(self.theme.error),
+                format!("{error}").into(),
+            ]);
+        } else if let Some(stats) = analysis.stats.get() {
+            push_stats_lines(&mut text, stats, &self.theme);
+        } else {
+            text.push_line(
+                format!(
+                    "🔄 Reading tensors... {done}/{} ({progress}%)",
+                    analysis.total
+                )
+                .fg(self.theme.accent),
+            );
+        }
+        let widget = Paragraph::new(text)
+            .block(self.format_block("Statistics", Panel::Analysis))
+            .wrap(Wrap { trim: false });
+        widget.render(stats_area, buf);
+
+        let mut text = Text::default();
+        let chart = match analysis.histogram.get() {
+            Some(histogram) => {
+                text.push_line(vec![
+                    "Data range: ".bold(),
+                    format!("{:.3} to {:.3}", histogram.min, histogram.max).into(),
+                ]);
+                Some((histogram.chart.clone(), Vec::new()))
+            }
+            None if analysis.error.get().is_some() => None,
+            None if analysis.histogram_go.load(Relaxed) && analysis.stats.get().is_some() => {
+                text.push_line(
+                    format!(
+                        "🔄 Computing histogram... {done}/{} ({progress}%)",
+                        analysis.total
+                    )
+                    .fg(self.theme.accent),
+                );
+                None
+            }
+            None if analysis.histogram_go.load(Relaxed) => {
+                text.push_line("🔄 Computing histogram...".fg(self.theme.accent));
+                None
+            }
+            None => {
+                text.push_line("Press \"y\" to compute histogram".fg(self.theme.error));
+                None
+            }
+        };
+        self.render_chart_panel(buf, histogram_area, "Histogram", text, chart, |x| {
+            format!("{x:.2}")
+        });
+
+        let Some(analysis) = self.module_analysis.as_ref() else {
+            return;
+        };
+        let mut text = Text::default();
+        let metric = self.child_metric;
+        match analysis.child_stats.get() {
+            Some(children) => {
+                let name_width = children
+                    .iter()
+                    .map(|(name, _)| name.len())
+                    .max()
+                    .unwrap_or(0);
+                // Bars show magnitude, since means and minimums can be negative
+                let max_value = children
+                    .iter()
+                    .filter_map(|(_, stats)| stats.as_ref())
+                    .map(|stats| metric.of(stats).abs())
+                    .fold(0.0, f64::max);
+                let bar_width = norms_area
+                    .width
+                    .saturating_sub(name_width as u16 + 15)
+                    .max(1) as f64;
+                for (name, stats) in children {
+                    let Some(stats) = stats else {
+                        text.push_line(vec![
+                            format!("{name:<name_width$} ").into(),
+                            "no float tensors".fg(self.theme.muted),
+                        ]);
+                        continue;
+                    };
+                    let value = metric.of(stats);
+                    let bar = if max_value > 0.0 {
+                        (value.abs() / max_value * bar_width).round() as usize
+                    } else {
+                        0
+                    };
+                    let color = if value < 0.0 {
+                        self.theme.warning
+                    } else {
+                        self.theme.chart
+                    };
+                    text.push_line(vec![
+                        format!("{name:<name_width$} ").into(),
+                        "█".repeat(bar).fg(color),
+                        format!(" {value:.3e}").fg(self.theme.muted),
+                    ]);
+                }
+            }
+            None => text.push_line("Waiting for tensor data...".fg(self.theme.muted)),
+        }
+        let title = format!("{} (M: change)", metric.title());
+        let widget = Paragraph::new(text).block(self.format_block(title, Panel::Analysis));
+        widget.render(norms_area, buf);
+    }
+
+    /// The marked tensor beside the selected one, with their statistics in one table and their
+    /// histograms next to each other
+    fn render_split_view(&self, buf: &mut Buffer, area: Rect) {
+        let comparison_height = match self.comparison {
+            Some(_) => COMPARISON_HEIGHT,
+            None => 0,
+        };
+        let [stats_area, comparison_area, histograms_area] = Layout::vertical([
+            Constraint::Length(SPLIT_STATS.len() as u16 + 3),
+            Constraint::Length(comparison_height),
+            Constraint::Fill(1),
+        ])
+        .areas(area);
+        self.render_split_stats(buf, stats_area);
+        self.render_comparison(buf, comparison_area);
+
+        let [marked_area, selected_area] =
+            Layout::horizontal([Constraint::Fill(1); 2]).areas(histograms_area);
+        let mode = self.histogram_mode;
+        for (analysis, area, label) in [
+            (&self.marked_analysis, marked_area, "Marked"),
+            (&self.current_analysis, selected_area, "Selected"),
+        ] {
+            let mut text = Text::default();
+            let chart = self.render_histogram_into(analysis.as_deref(), &mut text);
+            let title = format!("{} of {label}", mode.title());
+            self.render_chart_panel(buf, area, title, text, chart, |x| mode.format_value(x));
+        }
+    }
+
+    fn render_split_stats(&self, buf: &mut Buffer, area: Rect) {
+        // Each column is the tensor's statistics, or why there are none yet
+        let column = |analysis: &Option<Own<Box<Analysis>>>| {
+            let Some(analysis) = analysis else {
+                return Err(Line::from("No analysis running"));
+            };
+            if let Some(error) = analysis.error.get() {
+                return Err(format!("Error: {error}").fg(self.theme.error).into());
+            }
+            match analysis.stats.get() {
+                Some(stats) => Ok(stats.clone()),
+                None => {
+                    let progress = analysis.progress.load(Relaxed);
+                    Err(format!("🔄 Reading tensor... {progress}%")
+                        .fg(self.theme.accent)
+                        .into())
+                }
+            }
+        };
+        let columns = [
+            column(&self.marked_analysis),
+            column(&self.current_analysis),
+        ];
+        let rows = SPLIT_STATS.iter().enumerate().map(|(i, (label, value))| {
+            let mut cells = vec![Cell::from(label.bold())];
+            for column in &columns {
+                cells.push(match column {
+                    Ok(stats) => Cell::from(value(stats)),
+                    Err(status) if i == 0 => Cell::from(status.clone()),
+                    Err(_) => Cell::default(),
+                });
+            }
+            Row::new(cells)
+        });
+        let marked = self.marked.as_ref().map(|(name, _)| name.clone());
+        let header = Row::new(
+            [None, marked, self.selected_tensor_name()]
+                .map(|name| Cell::from(name.unwrap_or_default().fg(self.theme.tensor))),
+        )
+        .bold();
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(9),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(header)
+        .block(self.format_block("Statistics: Marked vs Selected", Panel::Analysis));
+        Widget::render(table, area, buf);
+    }
+
+    fn render_comparison(&self, buf: &mut Buffer, area: Rect) {
+        let (Some(comparison), Some((marked_name, _))) = (&self.comparison, &self.marked) else {
+            return;
+        };
+        let mut text = Text::default();
+        text.push_line(vec![
+            "Marked: ".bold(),
+            marked_name.as_str().fg(self.theme.tensor),
+            format!(" {:?}", comparison.left.shape).fg(self.theme.shape),
+            format!(" {}", comparison.left.ty).fg(self.theme.dtype),
+        ]);
+        text.push_line(vec![
+            "Shapes: ".bold(),
+            comparison.shapes.label().fg(self.theme.text),
+        ]);
+        if let Some(error) = comparison.error.get() {
+            text.push_line(vec![
+                "Error: ".fg(self.theme.error),
+                format!("{error}").into(),
+            ]);
+        } else if let Some(metrics) = comparison.metrics.get() {
+            text.push_line(vec![
+                "Cosine Similarity: ".bold(),
+                format!("{:.6}", metrics.cosine).into(),
+            ]);
+            text.push_line(vec![
+                "L2 Distance: ".bold(),
+                format!(
+                    "{:.4e} ({:.2}% of marked norm)",
+                    metrics.l2_distance,
+                    metrics.relative_distance * 100.0
+                )
+                .into(),
+            ]);
+            text.push_line(vec![
+                "Max |Δ|: ".bold(),
+                format!("{:.4e}", metrics.max_abs_diff).into(),
+            ]);
+        } else {
+            let progress = comparison.progress.load(Relaxed);
+            text.push_line(format!("🔄 Comparing... {progress}%").fg(self.theme.accent));
+        }
+        Paragraph::new(text)
+            .block(self.format_block("Comparison", Panel::Analysis))
+            .style(Style::default().fg(self.theme.text))
+            .render(area, buf);
+    }
 
-        // Top bar
-        let title = if let Some(path) = &self.file_path {
-            format!("CheckpoinTUI - {}", path.display())
+    fn render_optimizer_state(&self, buf: &mut Buffer, area: Rect) {
+        let Some(analysis) = &self.optimizer_analysis else {
+            return;
+        };
+        let mut text = Text::default();
+        if let Some(error) = analysis.error.get() {
+            text.push_line(vec![
+                "Error: ".fg(self.theme.error),
+                format!("{error}").into(),
+            ]);
+        } else if let Some(report) = analysis.report.get() {
+            let norms = |label: &str, norm: f64, rms: f64| {
+                Line::from(vec![
+                    format!("{label:<16}").bold(),
+                    format!("‖·‖ {norm:<12.4e}").into(),
+                    format!("RMS {rms:.4e}").into(),
+                ])
+            };
+            text.push_line(norms("parameter", report.weight_norm, report.weight_rms));
+            for state in &report.states {
+                text.push_line(norms(&state.state, state.norm, state.rms));
+            }
+            text.push_line(vec![
+                "‖update‖/‖W‖: ".bold(),
+                match report.update_ratio {
+                    Some(ratio) => format!("{ratio:.4e} × lr").into(),
+                    None => "no exp_avg/exp_avg_sq or momentum_buffer".fg(self.theme.muted),
+                },
+            ]);
         } else {
-            "CheckpoinTUI - No file loaded".to_string()
+            let progress = analysis.progress.load(Relaxed);
+            text.push_line(
+                format!("🔄 Reading optimizer state... {progress}%").fg(self.theme.accent),
+            );
+        }
+        Paragraph::new(text)
+            .block(self.format_block("Optimizer State", Panel::Analysis))
+            .style(Style::default().fg(self.theme.text))
+            .render(area, buf);
+    }
+
+    fn render_quant_errors(&self, buf: &mut Buffer, area: Rect, tensor: &TensorInfo) {
+        let title = self.timed_title("Quantization Error", "quantization");
+        let block = self.format_block(title, Panel::Analysis);
+        let Some(analysis) = self.current_analysis.as_ref() else {
+            return;
+        };
+        let Some(errors) = analysis.quant_errors.get() else {
+            let text = match analysis.error.get() {
+                Some(error) => vec!["Error: ".fg(self.theme.error), format!("{error}").into()],
+                None => {
+                    let progress = analysis.quant_error_progress.load(Relaxed);
+                    vec![format!("🔄 Re-quantizing... {progress}%").fg(self.theme.accent)]
+                }
+            };
+            Paragraph::new(Line::from(text))
+                .block(block)
+                .render(area, buf);
+            return;
         };
 
-        let top_bar = Paragraph::new(title)
-            .block(Block::default().borders(Borders::ALL))
-            .style(Style::default().fg(PANEL_BORDER_SECONDARY));
-        f.render_widget(top_bar, chunks[0]);
+        let rows = errors.iter().map(|e| {
+            let name = ggml_base::get_type_name(e.ty).unwrap_or("?");
+            let row = Row::new(vec![
+                Cell::from(name.to_string()).fg(self.theme.dtype),
+                Cell::from(format!("{:.2}", e.bits_per_weight)),
+                Cell::from(format!("{:.3e}", e.rmse)),
+                Cell::from(format!("{:.3e}", e.max_error)),
+            ]);
+            // Mark the type the tensor is currently stored as
+            if matches!(tensor.ty, TensorTy::Ggml(ty) if ty == e.ty) {
+                row.bold().reversed()
+            } else {
+                row
+            }
+        });
+        let header = Row::new(vec!["Type", "Bits/w", "RMSE", "Max Error"]).bold();
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(8),
+                Constraint::Length(7),
+                Constraint::Length(10),
+                Constraint::Length(10),
+            ],
+        )
+        .header(header)
+        .block(block);
+        Widget::render(table, area, buf);
+    }
 
-        // Main content area
-        if self.tree_state.is_some() {
-            let should_show_analysis = self.should_show_analysis_panel();
+    fn render_preview(&mut self, buf: &mut Buffer, area: Rect, shape: &[u64]) {
+        const CELL_WIDTH: usize = 9;
+        let mut text = Text::default();
+        match self.current_analysis.as_ref().and_then(|a| a.preview.get()) {
+            Some(values) => {
+                let max_abs = values
+                    .iter()
+                    .copied()
+                    .filter(|x| x.is_finite())
+                    .fold(0.0f32, |m, x| m.max(x.abs()));
+                // 1-D tensors wrap to the panel width, higher ranks use their last dimension
+                let columns = match shape {
+                    [] | [_] => (area.width.saturating_sub(8) as usize / CELL_WIDTH).max(1),
+                    [.., w] => (*w as usize).max(1),
+                };
+                let skip = self.preview_scroll.1 as usize;
+                for (row, chunk) in values.chunks(columns).enumerate() {
+                    let mut spans = vec![format!("{:>6}: ", row * columns).fg(self.theme.muted)];
+                    for &x in chunk.iter().skip(skip) {
+                        spans.push(format!("{x:>8.3} ").fg(heatmap_color(x, max_abs, &self.theme)));
+                    }
+                    text.push_line(spans);
+                }
+            }
+            None => text.push_line("Waiting for tensor data...".fg(self.theme.muted)),
+        }
 
-            if should_show_analysis {
-                // Three-panel layout when tensor is selected
-                let main_chunks = Layout::default()
-                    .direction(Direction::Horizontal)
-                    .constraints([
-                        Constraint::Percentage(33), // Tree panel
-                        Constraint::Percentage(33), // Info panel
-                        Constraint::Percentage(34), // Analysis panel
-                    ])
-                    .split(chunks[1]);
+        let max_scroll = text.lines.len().saturating_sub(1) as u16;
+        self.preview_scroll.0 = self.preview_scroll.0.min(max_scroll);
+        let preview = Paragraph::new(text)
+            .block(self.format_block("Values", Panel::Analysis))
+            .style(Style::default().fg(self.theme.text))
+            .scroll((self.preview_scroll.0, 0));
+        preview.render(area, buf);
+    }
 
-                self.render_tree_panel(f, main_chunks[0]);
+    fn render_stats_into(&mut self, text: &mut Text) {
+        let Some(analysis) = self.current_analysis.as_ref() else {
+            text.push_line("No analysis running");
+            return;
+        };
 
-                // Split info panel into two vertical sections
-                let info_chunks = Layout::default()
-                    .direction(Direction::Vertical)
-                    .constraints([
-                        Constraint::Percentage(25), // Selected item info
-                        Constraint::Percentage(75), // File info
-                    ])
-                    .split(main_chunks[1]);
+        if let Some(error) = analysis.error.get() {
+            text.push_line(vec![
+                "Error: ".fg(self.theme.error),
+                format!("{error}").into(),
+            ]);
+            return;
+        }
 
-                self.render_selected_info_panel(f, info_chunks[0]);
-                self.render_file_meta_tree_panel(f, info_chunks[1]);
-                self.render_analysis_panel(f, main_chunks[2]);
-            } else {
-                // Two-panel layout when module is selected
-                let main_chunks = Layout::default()
-                    .direction(Direction::Horizontal)
-                    .constraints([
-                        Constraint::Percentage(50), // Tree panel
-                        Constraint::Percentage(50), // Info panel
-                    ])
-                    .split(chunks[1]);
+        let Some(stats) = analysis.stats.get() else {
+            let progress = analysis.progress.load(Relaxed);
+            let mut line = format!("🔄 Reading tensor... {progress}%");
+            if let Some(elapsed) = analysis.timings.elapsed() {
+                line += &format!(" ({})", format_duration(elapsed));
+            }
+            text.push_line(line.fg(self.theme.accent));
+            return;
+        };
+        push_stats_lines(text, stats, &self.theme);
+    }
 
-                self.render_tree_panel(f, main_chunks[0]);
+    fn render_stats(&mut self, buf: &mut Buffer, area: Rect) {
+        let mut text = Text::default();
+        self.render_stats_into(&mut text);
+        let title = match self
+            .current_analysis
+            .as_ref()
+            .and_then(|a| a.slice.as_ref())
+        {
+            Some(slice) => format!("Statistics of {slice}"),
+            None => "Statistics".to_string(),
+        };
+        let title = self.timed_title(title, "read");
+        let stats_widget = Paragraph::new(text)
+            .block(self.format_block(title, Panel::Analysis))
+            .style(Style::default().fg(self.theme.text))
+            .wrap(Wrap { trim: false });
 
-                // Split info panel into two vertical sections
-                let info_chunks = Layout::default()
-                    .direction(Direction::Vertical)
-                    .constraints([
-                        Constraint::Percentage(25), // Selected item info
-                        Constraint::Percentage(75), // File info
-                    ])
-                    .split(main_chunks[1]);
+        stats_widget.render(area, buf);
+    }
+
+    /// `title` followed by how long the current analysis spent on `stage`, once it has finished
+    fn timed_title(&self, title: impl Into<String>, stage: &str) -> String {
+        let title = title.into();
+        let time = self
+            .current_analysis
+            .as_ref()
+            .and_then(|analysis| analysis.timings.stage(stage));
+        match time {
+            Some(time) => format!("{title} · {}", format_duration(time)),
+            None => title,
+        }
+    }
+
+    fn render_bar_chart(
+        buf: &mut Buffer,
+        area: Rect,
+        chart: &checkpoint_core::analysis::BarChart,
+        theme: &Theme,
+        log_counts: bool,
+        markers: &[(&str, f32)],
+        format_value: impl Fn(f32) -> String,
+    ) {
+        if chart.bins.is_empty() || area.width < 2 || area.height < 2 {
+            return;
+        }
 
-                self.render_selected_info_panel(f, info_chunks[0]);
-                self.render_file_meta_tree_panel(f, info_chunks[1]);
+        let max_count = chart.bins.iter().max().copied().unwrap_or(1).max(1);
+        let scale_count = |count: usize| {
+            if log_counts {
+                (count as f64).ln_1p()
+            } else {
+                count as f64
             }
+        };
+        let left = chart.left as f64;
+        let span = if chart.right > chart.left {
+            (chart.right - chart.left) as f64
         } else {
-            let help = Paragraph::new(self.helptext.as_str())
-                .block(Block::default().borders(Borders::ALL).title("Help"))
-                .style(Style::default().fg(Color::White));
-            f.render_widget(help, chunks[1]);
+            1.0
+        };
+
+        // Sample each braille column so bars fill the full panel width
+        let samples = area.width as usize * 2;
+        let points: Vec<(f64, f64)> = (0..samples)
+            .map(|s| {
+                let t = (s as f64 + 0.5) / samples as f64;
+                let bin = ((t * chart.bins.len() as f64) as usize).min(chart.bins.len() - 1);
+                (left + t * span, scale_count(chart.bins[bin]))
+            })
+            .collect();
+
+        let mut left_label = format_value(chart.left);
+        if chart.continues_past_left {
+            left_label.insert(0, '<');
+        }
+        let mid_label = format_value(chart.left + (span / 2.0) as f32);
+        let mut right_label = format_value(chart.left + span as f32);
+        if chart.continues_past_right {
+            right_label.push('>');
+        }
+
+        let dataset = Dataset::default()
+            .marker(Marker::Braille)
+            .graph_type(GraphType::Bar)
+            .style(Style::default().fg(theme.chart))
+            .data(&points);
+
+        // Vertical rules at each marked value
+        let top = scale_count(max_count);
+        let marker_points: Vec<[(f64, f64); 2]> = markers
+            .iter()
+            .map(|&(_, x)| [(x as f64, 0.0), (x as f64, top)])
+            .collect();
+        let mut datasets = vec![dataset];
+        datasets.extend(marker_points.iter().map(|line| {
+            Dataset::default()
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(theme.accent))
+                .data(line)
+        }));
+
+        let widget = Chart::new(datasets)
+            .x_axis(
+                Axis::default()
+                    .bounds([left, left + span])
+                    .labels([left_label, mid_label, right_label])
+                    .style(Style::default().fg(theme.muted)),
+            )
+            .y_axis(
+                Axis::default()
+                    .bounds([0.0, scale_count(max_count)])
+                    .labels(["0".to_string(), max_count.to_string()])
+                    .style(Style::default().fg(theme.muted)),
+            );
+        widget.render(area, buf);
+    }
+
+    fn render_histogram_into(
+        &self,
+        analysis: Option<&Analysis>,
+        text: &mut Text,
+    ) -> Option<(BarChart, Vec<(&'static str, f32)>)> {
+        let Some(analysis) = analysis else {
+            text.push_line("No analysis running");
+            return None;
+        };
+
+        if let Some(error) = analysis.error.get() {
+            text.push_line(vec![
+                "Error: ".fg(self.theme.error),
+                format!("{error}").into(),
+            ]);
+            return None;
+        }
+
+        match (
+            analysis.histogram.get(),
+            analysis.histogram_go.load(Relaxed),
+        ) {
+            (Some(histogram), _) if self.histogram_mode == HistogramMode::Signed => {
+                let range = if histogram.integer {
+                    format!("{} to {}", histogram.min, histogram.max)
+                } else {
+                    format!("{:.3} to {:.3}", histogram.min, histogram.max)
+                };
+                text.push_line(vec![
+                    "Data range: ".bold(),
+                    range.into(),
+                    "  Bins: ".bold(),
+                    histogram.chart.bins.len().to_string().into(),
+                ]);
+                if histogram.zoomed {
+                    text.push_line(vec![
+                        "Zoomed to: ".bold(),
+                        format!("{} to {}", histogram.chart.left, histogram.chart.right)
+                            .fg(self.theme.accent),
+                    ]);
+                }
+                if let Some(counts) = histogram.value_counts() {
+                    let total = counts.iter().map(|&(_, count)| count).sum::<usize>().max(1);
+                    for (value, count) in counts {
+                        let share = count as f64 / total as f64 * 100.0;
+                        text.push_line(vec![
+                            format!("{value:>6}").fg(self.theme.literal),
+                            format!(": {count} ({share:.1}%)").into(),
+                        ]);
+                    }
+                }
+                return Some((histogram.chart.clone(), Vec::new()));
+            }
+            (Some(_), _)
+                if matches!(
+                    self.histogram_mode,
+                    HistogramMode::RowNorms | HistogramMode::ColumnNorms
+                ) =>
+            {
+                let Some(channels) = analysis.channels.get() else {
+                    let message = if analysis.streaming {
+                        "Tensor is too large for channel norms"
+                    } else {
+                        "Channel norms are only computed for matrices"
+                    };
+                    text.push_line(message.fg(self.theme.muted));
+                    return None;
+                };
+                let norms = if self.histogram_mode == HistogramMode::RowNorms {
+                    &channels.rows
+                } else {
+                    &channels.columns
+                };
+                let indices = |channels: &[usize]| {
+                    let mut list: Vec<_> = channels.iter().take(8).map(|i| i.to_string()).collect();
+                    if channels.len() > 8 {
+                        list.push("…".into());
+                    }
+                    list.join(", ")
+                };
+                let highlight = |count: usize| {
+                    if count > 0 {
+                        count.to_string().fg(self.theme.warning)
+                    } else {
+                        count.to_string().into()
+                    }
+                };
+                text.push_line(vec![
+                    "Median: ".bold(),
+                    format!("{:.3e}", norms.median).into(),
+                    "  Dead: ".bold(),
+                    highlight(norms.dead.len()),
+                    "  Outliers: ".bold(),
+                    highlight(norms.outliers.len()),
+                ]);
+                if !norms.dead.is_empty() {
+                    text.push_line(vec![
+                        "Dead: ".bold(),
+                        indices(&norms.dead).fg(self.theme.warning),
+                    ]);
+                }
+                if !norms.outliers.is_empty() {
+                    text.push_line(vec![
+                        "Outliers: ".bold(),
+                        indices(&norms.outliers).fg(self.theme.warning),
+                    ]);
+                }
+                return Some((
+                    norms.histogram.chart.clone(),
+                    vec![("median", norms.median)],
+                ));
+            }
+            (Some(histogram), _) if self.histogram_mode == HistogramMode::Init => {
+                let Some(fit) = analysis.init_fit.get() else {
+                    let message = if analysis.streaming {
+                        "Tensor is too large to compare against init"
+                    } else if !analysis.tensor.ty.is_float() {
+                        "Only float tensors are compared against init"
+                    } else if analysis.magnitude.get().is_some() {
+                        "Every value is zero"
+                    } else {
+                        text.push_line(vec!["🔄 Fitting distributions...".fg(self.theme.accent)]);
+                        return None;
+                    };
+                    text.push_line(message.fg(self.theme.muted));
+                    return None;
+                };
+                text.push_line(vec![
+                    "Gaussian fit: ".bold(),
+                    format!("σ = {:.3e}", fit.rms).into(),
+                    "  KL: ".bold(),
+                    format!("{:.4}", fit.gaussian_kl).into(),
+                ]);
+                let Some(closest) = fit.closest() else {
+                    text.push_line(
+                        "Init distributions need two or more axes to take fans from"
+                            .fg(self.theme.muted),
+                    );
+                    return Some((histogram.chart.clone(), vec![("σ", fit.rms as f32)]));
+                };
+                if fit.at_init() {
+                    text.push_line(
+                        format!("Still at init: matches {}", closest.name).fg(self.theme.warning),
+                    );
+                } else {
+                    text.push_line(
+                        format!(
+                            "Moved from init: nearest is {} at {:.2}×",
+                            closest.name, closest.scale_ratio
+                        )
+                        .fg(self.theme.success),
+                    );
+                }
+                for reference in &fit.references {
+                    let name = format!("{:<16}", reference.name);
+                    text.push_line(vec![
+                        if std::ptr::eq(reference, closest) {
+                            name.fg(self.theme.accent).bold()
+                        } else {
+                            name.bold()
+                        },
+                        format!(" σ {:.3e}", reference.std).into(),
+                        format!("  ×{:.2}", reference.scale_ratio).into(),
+                        format!("  KL {:.4}", reference.kl).into(),
+                    ]);
+                }
+                let markers = vec![("σ", fit.rms as f32), ("init σ", closest.std as f32)];
+                return Some((histogram.chart.clone(), markers));
+            }
+            (Some(_), _) => {
+                let Some(magnitude) = analysis.magnitude.get() else {
+                    if analysis.streaming {
+                        text.push_line("Tensor is too large for magnitudes".fg(self.theme.muted));
+                    } else {
+                        text.push_line(vec!["🔄 Computing magnitudes...".fg(self.theme.accent)]);
+                    }
+                    return None;
+                };
+                text.push_line(vec![
+                    "p50: ".bold(),
+                    format!("{:.3e}", magnitude.p50).into(),
+                    "  p99: ".bold(),
+                    format!("{:.3e}", magnitude.p99).into(),
+                    "  p99.9: ".bold(),
+                    format!("{:.3e}", magnitude.p999).into(),
+                ]);
+                let percentiles = [
+                    ("p50", magnitude.p50),
+                    ("p99", magnitude.p99),
+                    ("p99.9", magnitude.p999),
+                ];
+                if self.histogram_mode == HistogramMode::Magnitude {
+                    return Some((magnitude.histogram.chart.clone(), percentiles.to_vec()));
+                }
+                let Some(log_histogram) = &magnitude.log_histogram else {
+                    text.push_line("Every value is zero".fg(self.theme.muted));
+                    return None;
+                };
+                let markers = percentiles
+                    .into_iter()
+                    .filter(|&(_, x)| x > 0.0)
+                    .map(|(name, x)| (name, x.log10()))
+                    .collect();
+                return Some((log_histogram.chart.clone(), markers));
+            }
+            (None, true) if analysis.streaming && analysis.stats.get().is_some() => {
+                let progress = analysis.progress.load(Relaxed);
+                text.push_line(vec![
+                    format!("🔄 Computing histogram... {progress}%").fg(self.theme.accent),
+                ]);
+            }
+            (None, true) => {
+                text.push_line(vec!["🔄 Computing histogram...".fg(self.theme.accent)]);
+            }
+            (None, false) => {
+                text.push_line(vec![
+                    "Press \"y\" to compute histogram".fg(self.theme.error),
+                ]);
+            }
+        }
+        None
+    }
+
+    fn render_histogram(&mut self, buf: &mut Buffer, area: Rect) {
+        let mut text = Text::default();
+        let chart = self.render_histogram_into(self.current_analysis.as_deref(), &mut text);
+        let mode = self.histogram_mode;
+        let title = self.timed_title(mode.title(), "histogram");
+        self.render_chart_panel(buf, area, title, text, chart, |x| mode.format_value(x));
+    }
+
+    fn render_spectrum_into(&mut self, text: &mut Text) -> Option<BarChart> {
+        let Some(analysis) = self.current_analysis.as_ref() else {
+            text.push_line("No analysis running");
+            return None;
+        };
+
+        if let Some(error) = analysis.error.get() {
+            text.push_line(vec![
+                "Error: ".fg(self.theme.error),
+                format!("{error}").into(),
+            ]);
+            return None;
+        }
+
+        if analysis.streaming {
+            text.push_line("Tensor is too large for SVD".fg(self.theme.muted));
+            return None;
+        }
+
+        match (analysis.spectrum.get(), analysis.spectrum_go.load(Relaxed)) {
+            (Some(spectrum), _) => {
+                let summary = &spectrum.summary;
+                text.push_line(vec![
+                    "σ₁: ".bold(),
+                    format!("{:.4}", summary.top).into(),
+                    "  Cond: ".bold(),
+                    format!("{:.3e}", summary.condition_number).into(),
+                ]);
+                text.push_line(vec![
+                    "Stable rank: ".bold(),
+                    format!("{:.2}", summary.stable_rank).into(),
+                    "  Effective rank: ".bold(),
+                    format!("{:.2}", summary.effective_rank).into(),
+                ]);
+                return Some(spectrum.chart.clone());
+            }
+            (None, true) if analysis.histogram.get().is_some() => {
+                let progress = analysis.progress.load(Relaxed);
+                text.push_line(vec![
+                    format!("🔄 Computing SVD decomposition... {progress}%").fg(self.theme.accent),
+                ]);
+            }
+            (None, true) => {
+                text.push_line(vec![
+                    "🔄 Computing SVD decomposition...".fg(self.theme.accent),
+                ]);
+            }
+            (None, false) => {
+                text.push_line(vec![
+                    "Press \"y\" to compute SVD decomposition".fg(self.theme.error),
+                ]);
+            }
+        }
+        None
+    }
+
+    fn render_spectrum(&mut self, buf: &mut Buffer, area: Rect) {
+        let mut text = Text::default();
+        let chart = self.render_spectrum_into(&mut text);
+        let chart = chart.map(|chart| (chart, Vec::new()));
+        let title = self.timed_title("Matrix Spectrum", "spectrum");
+        self.render_chart_panel(buf, area, title, text, chart, |x| format!("{x:.2}"));
+    }
+
+    fn render_chart_panel<'a>(
+        &self,
+        buf: &mut Buffer,
+        area: Rect,
+        title: impl Into<Line<'a>>,
+        text: Text,
+        chart: Option<(BarChart, Vec<(&str, f32)>)>,
+        format_value: impl Fn(f32) -> String,
+    ) {
+        let mut title: Line = title.into();
+        if self.log_counts {
+            title += " (log)".into();
+        }
+        let block = self.format_block(title, Panel::Analysis);
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let [text_area, chart_area] = Layout::vertical([
+            Constraint::Length(text.lines.len() as u16),
+            Constraint::Fill(1),
+        ])
+        .areas(inner);
+        let paragraph = Paragraph::new(text)
+            .style(Style::default().fg(self.theme.text))
+            .wrap(Wrap { trim: false });
+        paragraph.render(text_area, buf);
+
+        if let Some((chart, markers)) = chart {
+            Self::render_bar_chart(
+                buf,
+                chart_area,
+                &chart,
+                &self.theme,
+                self.log_counts,
+                &markers,
+                format_value,
+            );
+        }
+    }
+
+    fn store_cached_analysis(&mut self) {
+        let (Some(entry), Some(analysis)) = (self.cache_entry.take(), &self.current_analysis)
+        else {
+            return;
+        };
+        if analysis.stats.get().is_none() {
+            return;
+        }
+        let _ = entry.store(&CachedAnalysis {
+            stats: analysis.stats.get().cloned(),
+            histogram: analysis.histogram.get().cloned(),
+            spectrum: analysis.spectrum.get().cloned(),
+        });
+    }
+
+    /// An analysis of the tensor, or the slice of it, which runs what the size limits allow
+    /// right away
+    fn new_analysis(
+        &self,
+        tensor: &TensorInfo,
+        slice: Option<TensorSlice>,
+        range: Option<(f32, f32)>,
+    ) -> Analysis {
+        let total_elements = match &slice {
+            Some(slice) => slice.shape(),
+            None => tensor.shape.clone(),
+        }
+        .iter()
+        .product::<u64>();
+        Analysis {
+            tensor: tensor.clone(),
+            // Slices are read whole, since they're assumed to be small
+            streaming: slice.is_none() && total_elements > self.histogram_size_limit,
+            slice,
+            range,
+            progress: 0.into(),
+            quant_error_progress: 0.into(),
+            stats: OnceLock::new(),
+            preview: OnceLock::new(),
+            histogram: OnceLock::new(),
+            magnitude: OnceLock::new(),
+            init_fit: OnceLock::new(),
+            channels: OnceLock::new(),
+            slice_norms: OnceLock::new(),
+            slice_norms_progress: 0.into(),
+            // Nothing to chart for empty tensors and scalars
+            histogram_go: (1 < total_elements && total_elements <= self.histogram_size_limit)
+                .into(),
+            spectrum: OnceLock::new(),
+            spectrum_go: (1 < total_elements && total_elements <= self.spectrum_size_limit).into(),
+            quant_error_go: false.into(),
+            quant_errors: OnceLock::new(),
+            error: std::sync::OnceLock::new(),
+            batch: false,
+            timings: Default::default(),
+            max_bin_count: self.bin_count,
+        }
+    }
+
+    fn update_analysis_for_selected_tensor(&mut self) {
+        self.store_cached_analysis();
+        let Some(tree) = &self.tree_state else { return };
+        let selected_item = tree
+            .list_state
+            .borrow()
+            .selected()
+            .and_then(|i| tree.visible_items.get(i));
+
+        let Some(item) = selected_item else { return };
+        let Some(tensor_info) = &item.info.tensor_info else {
+            let children = item
+                .info
+                .children()
+                .iter()
+                .map(|(key, child)| (key.to_string(), child.tensors()))
+                .collect();
+            let analysis = Own::new_box(ModuleAnalysis::new(children, self.bin_count, false));
+            if let Some(sender) = self.analysis_sender.as_ref() {
+                sender.set(AnalysisRequest::Module(analysis.refer()));
+            }
+            self.current_analysis = None;
+            self.module_analysis = Some(analysis);
+            self.comparison = None;
+            self.optimizer_analysis = None;
+            return;
+        };
+
+        let full_name = item.info.full_name.to_string();
+        let slice = match &self.slice {
+            Some((name, slice)) if *name == full_name => Some(slice.clone()),
+            _ => None,
+        };
+        let range = match &self.histogram_range {
+            Some((name, range)) if *name == full_name => Some(*range),
+            _ => None,
+        };
+        // The shape of the tensor, or the part of it analyzed
+        let shape = match &slice {
+            Some(slice) => slice.shape(),
+            None => tensor_info.shape.clone(),
+        };
+        let analysis = Own::new_box(self.new_analysis(tensor_info, slice.clone(), range));
+        // Only whole tensors with the usual histogram range are cached
+        let cache_entry = self
+            .cache
+            .as_ref()
+            .filter(|_| slice.is_none() && range.is_none())
+            .and_then(|cache| cache.entry(&full_name, tensor_info, self.bin_count));
+        if let Some(cached) = cache_entry.as_ref().and_then(CacheEntry::load) {
+            if let Some(stats) = cached.stats {
+                let _ = analysis.stats.set(stats);
+            }
+            if let Some(histogram) = cached.histogram {
+                let _ = analysis.histogram.set(histogram);
+            }
+            if let Some(spectrum) = cached.spectrum {
+                let _ = analysis.spectrum.set(spectrum);
+            }
+        }
+        self.cache_entry = cache_entry;
+        // Tensors the batch queue got to show their results without waiting
+        let batched = self
+            .batch
+            .as_ref()
+            .filter(|_| slice.is_none() && range.is_none())
+            .and_then(|batch| {
+                let tensors = &batch.analysis.tensors;
+                tensors.iter().find(|(name, _)| *name == full_name)
+            })
+            .filter(|(_, batched)| batched.max_bin_count == self.bin_count);
+        if let Some((_, batched)) = batched {
+            analysis.copy_results(batched);
+        }
+        if let Some(sender) = self.analysis_sender.as_ref() {
+            sender.set(AnalysisRequest::Tensor(analysis.refer()));
+        }
+        self.current_analysis = Some(analysis);
+        self.module_analysis = None;
+        self.preview_scroll = (0, 0);
+        self.analysis_scroll = 0;
+        if shape.len() != 2
+            && matches!(
+                self.histogram_mode,
+                HistogramMode::RowNorms | HistogramMode::ColumnNorms
+            )
+        {
+            self.histogram_mode = HistogramMode::Signed;
         }
+        self.update_comparison();
+        self.update_optimizer_analysis();
+    }
+
+    fn start_duplicate_scan(&mut self) {
+        let (Some(source), Some(tree)) = (&self.reader, &self.tree_state) else {
+            return;
+        };
+        let root = tree.data_history.first().unwrap_or(&tree.data);
+        let scan = Own::new_box(DuplicateScan::new(root.tensors()));
+        start_duplicate_scan(source.clone(), scan.refer());
+        self.duplicate_scan = Some(scan);
+    }
 
-        // Bottom bar
-        let help_text = if self.tree_state.is_some() {
-            if self.selected_panel == Panel::FileInfo && self.is_metadata_item_selected() {
-                "↑/↓: Navigate | ←/→: Enter/Exit | Space: Expand/Collapse | e: Edit | d: Delete | Tab: Switch Panel | q: Quit"
-            } else {
-                "↑/↓: Navigate | ←/→: Enter/Exit Module | Space/Enter: Expand/Collapse | Tab/Shift+Tab: Switch Panel | q/Esc: Quit"
-            }
-        } else {
-            "q/Esc: Quit"
+    fn hash_selected_tensor(&mut self) {
+        let (Some(source), Some(tensor)) = (&self.reader, self.selected_tensor()) else {
+            return;
         };
+        let job = Own::new_box(ManifestJob::new(vec![tensor]));
+        start_manifest(source.clone(), job.refer());
+        self.tensor_hash = Some(job);
+    }
 
-        let bottom_bar = Paragraph::new(help_text)
-            .block(Block::default().borders(Borders::ALL))
-            .style(Style::default().fg(Color::Gray));
-        f.render_widget(bottom_bar, chunks[2]);
+    /// The hash of `module` if it was asked for, or how far along it is
+    fn tensor_hash_line(&self, module: &ModuleInfo) -> Option<(String, Color)> {
+        let job = self.tensor_hash.as_ref()?;
+        let name = module.full_name.to_string();
+        if job.tensors.first()?.0 != name {
+            return None;
+        }
+        Some(if let Some(error) = job.error.get() {
+            (format!("failed: {error}"), self.theme.error)
+        } else if let Some(hash) = job.sha256(&name) {
+            (hash.to_string(), self.theme.text)
+        } else {
+            let progress = job.progress.load(Relaxed);
+            (format!("hashing {progress}%"), self.theme.warning)
+        })
+    }
 
-        // Render dialog overlay if open
-        if self.dialog_type.is_some() {
-            self.render_dialog(f, f.area());
+    fn start_manifest(&mut self, path: PathBuf) {
+        if path.as_os_str().is_empty() {
+            self.dialog_type = Some(DialogType::Error("expected a path".to_string()));
+            return;
         }
+        let (Some(source), Some(tree)) = (&self.reader, &self.tree_state) else {
+            return;
+        };
+        let root = tree.data_history.first().unwrap_or(&tree.data);
+        let job = Own::new_box(ManifestJob::new(root.tensors()));
+        start_manifest(source.clone(), job.refer());
+        self.manifest_job = Some((path, job));
     }
 
-    fn render_tree_panel(&mut self, f: &mut ratatui::Frame, area: Rect) {
-        let Some(tree) = &self.tree_state else {
+    fn poll_manifest_job(&mut self) {
+        let Some((path, job)) = &self.manifest_job else {
+            return;
+        };
+        let result = if let Some(error) = job.error.get() {
+            Err(error.to_string())
+        } else if let Some(entries) = job.entries.get() {
+            serde_json::to_string_pretty(entries)
+                .map_err(Error::from)
+                .and_then(|json| Ok(std::fs::write(path, json + "\n")?))
+                .map_err(|err| err.to_string())
+        } else {
             return;
         };
+        self.dialog_type = Some(match result {
+            Ok(()) => DialogType::Notice(format!("Wrote manifest to {}", path.display())),
+            Err(err) => DialogType::Error(err),
+        });
+        self.manifest_job = None;
+    }
 
-        let lines: Vec<Line> = tree
-            .visible_items
+    /// The other tensors holding the same bytes as `module`, and whether they share storage
+    fn duplicates(&self, module: &ModuleInfo) -> Option<(Vec<String>, bool)> {
+        if !module.is_tensor() {
+            return None;
+        }
+        let name = module.full_name.to_string();
+        let group = self.duplicate_scan.as_ref()?.group(&name)?;
+        let others = group
+            .names
             .iter()
-            .map(|item| {
-                let mut spans = Vec::new();
-
-                // Indentation
-                if item.depth > 0 {
-                    spans.push("  ".repeat(item.depth as usize).into());
-                }
-
-                // Icon
-                let icon_span = if item.has_children() {
-                    if item.is_expanded { "▼ " } else { "▶ " }
-                } else if item.info.is_tensor() {
-                    "📄 "
-                } else {
-                    "  "
-                }
-                .into();
-                spans.push(icon_span);
+            .filter(|other| **other != name)
+            .cloned()
+            .collect();
+        Some((others, group.shared))
+    }
 
-                // Name
-                let name_span = if item.info.is_tensor() {
-                    item.name.as_str().fg(TENSOR_FG)
-                } else if item.has_children() {
-                    item.name.as_str().fg(MODULE_FG).bold()
-                } else {
-                    item.name.as_str().white()
-                };
-                spans.push(name_span);
+    pub fn start_health_scan(&mut self) {
+        let (Some(source), Some(tree)) = (&self.reader, &self.tree_state) else {
+            return;
+        };
+        let tensors = tree.data.tensors();
+        let scan = Own::new_box(HealthScan::new(tensors.len()));
+        start_health_scan(source.clone(), tensors, scan.refer());
+        self.health_scan = Some(scan);
+    }
 
-                // Parameter count
-                let param_text = format!(" ({})", self.format_count(item.info.total_params));
-                spans.push(param_text.fg(COUNT_FG));
+    fn tensor_stats(&self, name: &str) -> Option<Stats> {
+        self.health_scan.as_ref()?.stats(name)
+    }
 
-                // Tensor details
-                if let Some(tensor_info) = &item.info.tensor_info {
-                    spans.push(format!(" {:?}", tensor_info.shape).fg(SHAPE_FG));
-                    spans.push(format!(" {}", tensor_info.ty).fg(DTYPE_FG));
-                    let size = self.format_bytes(tensor_info.size as u64);
-                    spans.push(format!(" {size}").fg(BYTESIZE_FG));
+    fn sort_table(&mut self) {
+        let Some(mut table) = self.table_view.take() else {
+            return;
+        };
+        let stat = |name: &str, column: TableColumn| {
+            let stats = self.tensor_stats(name)?;
+            match column {
+                TableColumn::Mean => Some(stats.mean),
+                TableColumn::Std => Some(stats.std),
+                TableColumn::Norm => Some(stats.l2_norm),
+                _ => None,
+            }
+        };
+        let sort = table.sort;
+        table.rows.sort_by(|(a_name, a), (b_name, b)| {
+            let order = match sort {
+                TableColumn::Name => Ordering::Equal,
+                TableColumn::Shape => a.shape.cmp(&b.shape),
+                TableColumn::Dtype => a.ty.to_string().cmp(&b.ty.to_string()),
+                TableColumn::Params => {
+                    let params = |t: &TensorInfo| t.shape.iter().product::<u64>();
+                    params(a).cmp(&params(b))
                 }
+                TableColumn::Bytes => a.size.cmp(&b.size),
+                column => stat(a_name, column)
+                    .partial_cmp(&stat(b_name, column))
+                    .unwrap_or(Ordering::Equal),
+            };
+            let order = order.then_with(|| natural_lexical_cmp(a_name, b_name));
+            if table.descending {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+        self.table_view = Some(table);
+    }
 
-                Line::from(spans)
-            })
-            .collect();
-
-        let mut title: Line = "Module Tree".into();
-        if !tree.data.full_name.is_empty() {
-            title += " - ".into();
-            title += tree.data.full_name.fg(MODULE_FG);
+    fn open_diagnostics_view(&mut self) {
+        if self.source.is_none() {
+            return;
         }
-
-        let items: Vec<ListItem> = lines.into_iter().map(ListItem::new).collect();
-
-        let list = List::new(items)
-            .block(self.format_block(title, Panel::Tree))
-            .style(Style::default().fg(Color::White))
-            .highlight_style(Style::default().bg(Color::Blue).fg(Color::White));
-        list.render(area, f.buffer_mut(), &mut *tree.list_state.borrow_mut());
+        if self.problems.is_empty() {
+            let message = "No integrity problems found".to_string();
+            self.dialog_type = Some(DialogType::Notice(message));
+            return;
+        }
+        let mut state = TableState::default();
+        state.select(Some(0));
+        self.diagnostics_view = Some(DiagnosticsView {
+            state: RefCell::new(state),
+        });
     }
 
-    fn render_selected_info_panel(&self, f: &mut ratatui::Frame, area: Rect) {
-        let Some(tree) = &self.tree_state else { return };
+    /// Queues every tensor under the selected item for analysis, or shows the queue again if it
+    /// was already started from there
+    fn queue_batch_analysis(&mut self) {
+        let (Some(tree), Some(source)) = (&self.tree_state, &self.reader) else {
+            return;
+        };
         let selected_item = tree
             .list_state
             .borrow()
             .selected()
             .and_then(|i| tree.visible_items.get(i));
-
-        let mut text = Text::default();
-        let title = if let Some(item) = selected_item {
-            if let Some(tensor_info) = &item.info.tensor_info {
-                text.push_line(vec!["Path: ".bold(), item.info.full_name.fg(TENSOR_FG)]);
-                text.push_line(vec![
-                    "Shape: ".bold(),
-                    format!("{:?}", tensor_info.shape).fg(SHAPE_FG),
-                ]);
-                text.push_line(vec![
-                    "Data Type: ".bold(),
-                    format!("{}", tensor_info.ty).fg(DTYPE_FG),
-                ]);
-                text.push_line(vec![
-                    "Parameters: ".bold(),
-                    self.format_count(item.info.total_params).fg(COUNT_FG),
-                ]);
-                text.push_line(vec![
-                    "Size: ".bold(),
-                    self.format_bytes(tensor_info.size as u64).fg(BYTESIZE_FG),
-                ]);
-                "Tensor Info"
-            } else {
-                text.push_line(vec!["Path: ".bold(), item.info.full_name.fg(MODULE_FG)]);
-                text.push_line(vec![
-                    "Tensors: ".bold(),
-                    item.info.total_tensors.to_string().fg(COUNT_FG),
-                ]);
-                text.push_line(vec![
-                    "Parameters: ".bold(),
-                    self.format_count(item.info.total_params).fg(COUNT_FG),
-                ]);
-                "Module Info"
-            }
-        } else {
-            text.extend(Text::from("No item selected".gray()));
-            "Selection Info"
+        let Some(item) = selected_item else {
+            return;
         };
-
-        let info = Paragraph::new(text)
-            .block(self.format_block(title, Panel::SelectedInfo))
-            .style(Style::default().fg(Color::White))
-            .wrap(Wrap { trim: false });
-
-        f.render_widget(info, area);
+        let module = item.info.full_name.to_string();
+        if self
+            .batch
+            .as_ref()
+            .is_none_or(|batch| batch.module != module)
+        {
+            let mut tensors = item.info.tensors();
+            tensors.sort_by(|(a, _), (b, _)| natural_lexical_cmp(a, b));
+            let tensors = tensors
+                .into_iter()
+                .map(|(name, tensor)| {
+                    let mut analysis = self.new_analysis(&tensor, None, None);
+                    analysis.batch = true;
+                    // Tensors too large to hold are streamed, so every one gets a histogram
+                    analysis.histogram_go = true.into();
+                    (name, analysis)
+                })
+                .collect();
+            let analysis = Own::new_box(BatchAnalysis {
+                tensors,
+                done: 0.into(),
+            });
+            start_batch_analysis(source.clone(), analysis.refer());
+            self.batch = Some(BatchQueue {
+                module,
+                analysis,
+                stored: 0,
+            });
+        }
+        let mut state = TableState::default();
+        state.select(Some(0));
+        self.batch_view = Some(BatchView {
+            state: RefCell::new(state),
+        });
     }
 
-    fn render_file_meta_tree_panel(&mut self, f: &mut ratatui::Frame, area: Rect) {
-        let Some(module_tree) = &self.tree_state else {
+    /// Lists untrained tokens once the embedding statistics they come from are ready
+    fn poll_tokenizer_view(&mut self) {
+        let Some(view) = &mut self.tokenizer_view else {
             return;
         };
+        let ready = view
+            .embedding
+            .as_ref()
+            .is_some_and(|embedding| embedding.stats.get().is_some());
+        if view.stale && ready {
+            view.refresh();
+        }
+    }
 
-        // Split the area into file info and metadata tree
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([
-                Constraint::Length(5), // File info (3 lines + 2 for borders)
-                Constraint::Min(1),    // Metadata tree
-            ])
-            .split(area);
-
-        // Render file info in top section
-        let mut file_info = Text::default();
-        file_info.push_line(vec![
-            "Path: ".bold(),
-            self.file_path
-                .as_ref()
-                .unwrap()
-                .display()
-                .to_string()
-                .fg(TENSOR_FG),
-        ]);
-        file_info.push_line(vec![
-            "Total Tensors: ".bold(),
-            module_tree.data.total_tensors.to_string().fg(COUNT_FG),
-        ]);
-        file_info.push_line(vec![
-            "Total Parameters: ".bold(),
-            self.format_count(module_tree.data.total_params)
-                .fg(COUNT_FG),
-        ]);
-
-        let file_info_widget = Paragraph::new(file_info)
-            .block(Block::default().borders(Borders::ALL).title("File Info"))
-            .style(Style::default().fg(Color::White));
-        f.render_widget(file_info_widget, chunks[0]);
+    /// Saves each tensor the batch queue has finished to the analysis cache
+    fn poll_batch(&mut self) {
+        let (Some(batch), Some(cache)) = (&mut self.batch, &self.cache) else {
+            return;
+        };
+        let done = batch.analysis.done.load(Relaxed);
+        for (name, analysis) in &batch.analysis.tensors[batch.stored..done] {
+            let Some(stats) = analysis.stats.get() else {
+                continue;
+            };
+            if let Some(entry) = cache.entry(name, &analysis.tensor, analysis.max_bin_count) {
+                let _ = entry.store(&CachedAnalysis {
+                    stats: Some(stats.clone()),
+                    histogram: analysis.histogram.get().cloned(),
+                    spectrum: analysis.spectrum.get().cloned(),
+                });
+            }
+        }
+        batch.stored = done;
+    }
 
-        // Render metadata tree in bottom section
-        if let Some(tree) = &self.meta_tree_state {
-            let lines: Vec<Line> = tree
-                .visible_items
-                .iter()
-                .map(|item| {
-                    let mut spans = Vec::new();
+    fn show_batch_selection(&mut self) {
+        let (Some(batch), Some(view)) = (&self.batch, &self.batch_view) else {
+            return;
+        };
+        let selected = view.state.borrow().selected();
+        let Some((name, _)) = selected.and_then(|i| batch.analysis.tensors.get(i)) else {
+            return;
+        };
+        let name = name.clone();
+        self.batch_view = None;
+        self.selected_panel = Panel::Tree;
+        self.reveal_tensor(&name);
+    }
 
-                    // Indentation
-                    if item.depth > 0 {
-                        spans.push("  ".repeat(item.depth as usize).into());
-                    }
+    fn open_layout_view(&mut self) {
+        let Some(tree) = &self.tree_state else {
+            return;
+        };
+        let root = tree.data_history.first().unwrap_or(&tree.data);
+        let mut tensors = root.tensors();
+        tensors.sort_by_key(|(_, tensor)| (tensor.offset, tensor.size));
+        let mut reached = 0;
+        let tensors = tensors
+            .into_iter()
+            .map(|(name, tensor)| {
+                let gap = tensor.offset as i64 - reached as i64;
+                reached = reached.max(tensor.offset + tensor.size as u64);
+                (name, tensor, gap)
+            })
+            .collect();
+        let mut state = TableState::default();
+        state.select(Some(0));
+        self.layout_view = Some(LayoutView {
+            tensors,
+            state: RefCell::new(state),
+        });
+    }
 
-                    // Icon
-                    let icon_span = if item.has_children() {
-                        if item.is_expanded { "▼ " } else { "▶ " }
-                    } else {
-                        "📄 "
-                    }
-                    .into();
-                    spans.push(icon_span);
+    fn show_layout_selection(&mut self) {
+        let Some(view) = &self.layout_view else {
+            return;
+        };
+        let selected = view.state.borrow().selected();
+        let Some((name, _, _)) = selected.and_then(|i| view.tensors.get(i)) else {
+            return;
+        };
+        let name = name.clone();
+        self.layout_view = None;
+        self.selected_panel = Panel::Tree;
+        self.reveal_tensor(&name);
+    }
 
-                    // Name
-                    let name_span = if item.has_children() {
-                        item.name.as_str().fg(MODULE_FG).bold()
-                    } else {
-                        item.name.as_str().fg(TENSOR_FG)
-                    };
-                    spans.push(name_span);
+    fn open_breakdown_view(&mut self) {
+        let Some(tree) = &self.tree_state else {
+            return;
+        };
+        // Always the whole file, even when the tree is focused on one module
+        let root = tree.data_history.first().unwrap_or(&tree.data);
+        let mut dtypes: BTreeMap<String, BreakdownRow> = BTreeMap::new();
+        let mut quants: BTreeMap<String, BreakdownRow> = BTreeMap::new();
+        let add =
+            |rows: &mut BTreeMap<String, BreakdownRow>, label: String, tensor: &TensorInfo| {
+                let row = rows.entry(label.clone()).or_insert(BreakdownRow {
+                    label,
+                    params: 0,
+                    bytes: 0,
+                });
+                row.params += tensor.shape.iter().product::<u64>();
+                row.bytes += tensor.size as u64;
+            };
+        for (_, tensor) in root.tensors() {
+            // Block-quantized types are counted together here, and apart below
+            if let TensorTy::Ggml(_) = tensor.ty {
+                add(&mut dtypes, "quantized".to_string(), &tensor);
+                add(&mut quants, tensor.ty.to_string(), &tensor);
+            } else {
+                add(&mut dtypes, tensor.ty.to_string(), &tensor);
+            }
+        }
+        for row in quants.values_mut() {
+            let bits = row.bytes as f64 * 8.0 / row.params.max(1) as f64;
+            row.label = format!("{} ({bits:.2} bpw)", row.label);
+        }
+        let modules: Vec<_> = root
+            .children()
+            .iter()
+            .map(|(key, child)| BreakdownRow {
+                label: key.to_string(),
+                params: child.total_params,
+                bytes: child.total_bytes,
+            })
+            .collect();
 
-                    // Value (for leaf nodes)
-                    if shorten_value(&*item.info) {
-                        spans.push(format!(" = ...").fg(Color::Gray));
-                    } else {
-                        match &*item.info {
-                            Value::Null => {
-                                spans.push(format!(" = null").fg(Color::Gray));
-                            }
-                            Value::Bool(bool) => {
-                                spans.push(format!(" = {bool}").fg(Color::Blue));
-                            }
-                            Value::Number(number) => {
-                                spans.push(format!(" = {number}").fg(Color::Blue));
-                            }
-                            Value::String(string) => {
-                                spans.push(format!(" = {string}").fg(Color::White));
-                            }
-                            Value::Array(_) => {}
-                            Value::Object(_) => {}
-                        }
-                    }
+        let mut sections = vec![("By Data Type", dtypes.into_values().collect())];
+        if !quants.is_empty() {
+            sections.push(("By Quantization Type", quants.into_values().collect()));
+        }
+        sections.push(("By Top-Level Module", modules));
+        for (_, rows) in &mut sections {
+            rows.sort_by_key(|row| Reverse(row.params));
+        }
+        self.breakdown_view = Some(BreakdownView {
+            sections,
+            scroll: 0,
+        });
+    }
 
-                    Line::from(spans)
-                })
-                .collect();
+    fn render_breakdown_view(&mut self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(view) = &self.breakdown_view else {
+            return;
+        };
+        let theme = &self.theme;
+        let palette = [
+            theme.chart,
+            theme.accent,
+            theme.dtype,
+            theme.shape,
+            theme.literal,
+            theme.count,
+            theme.bytesize,
+            theme.tensor,
+        ];
+        let block = self.format_block("Breakdown (parameters, then bytes)", Panel::Tree);
+        let inner = block.inner(area);
+        let label_width = view
+            .sections
+            .iter()
+            .flat_map(|(_, rows)| rows)
+            .map(|row| row.label.chars().count())
+            .max()
+            .unwrap_or(0)
+            .min(BREAKDOWN_LABEL_WIDTH);
+        // The counts after each bar take 36 columns
+        let bar_width = (inner.width as usize)
+            .saturating_sub(label_width + 37)
+            .max(1);
 
-            let items: Vec<ListItem> = lines.into_iter().map(ListItem::new).collect();
+        let mut text = Text::default();
+        for (title, rows) in &view.sections {
+            let params = rows.iter().map(|row| row.params).sum::<u64>().max(1) as f64;
+            let bytes = rows.iter().map(|row| row.bytes).sum::<u64>().max(1) as f64;
+            text.push_line(title.bold().fg(theme.accent));
+
+            // Every group stacked in one bar, in the colors of the rows below
+            let mut stacked = vec![" ".repeat(label_width + 1).into()];
+            for (i, row) in rows.iter().enumerate() {
+                let width = (row.params as f64 / params * bar_width as f64).round() as usize;
+                stacked.push("█".repeat(width).fg(palette[i % palette.len()]));
+            }
+            text.push_line(stacked);
+
+            for (i, row) in rows.iter().enumerate() {
+                let color = palette[i % palette.len()];
+                let param_share = row.params as f64 / params;
+                let byte_share = row.bytes as f64 / bytes;
+                let bar = (param_share * bar_width as f64).round() as usize;
+                let label: String = row.label.chars().take(label_width).collect();
+                text.push_line(vec![
+                    format!("{label:<label_width$} ").fg(color),
+                    "█".repeat(bar).fg(color),
+                    " ".repeat(bar_width - bar.min(bar_width)).into(),
+                    format!(" {:>8}", self.format_count(row.params)).fg(theme.count),
+                    format!(" {:>6.1}%", param_share * 100.0).into(),
+                    format!("  {:>11}", self.format_bytes(row.bytes)).fg(theme.bytesize),
+                    format!(" {:>6.1}%", byte_share * 100.0).into(),
+                ]);
+            }
+            text.push_line("");
+        }
 
-            let list = List::new(items)
-                .block(self.format_block("Metadata", Panel::FileInfo))
-                .style(Style::default().fg(Color::White))
-                .highlight_style(Style::default().bg(Color::Blue).fg(Color::White));
-            list.render(
-                chunks[1],
-                f.buffer_mut(),
-                &mut *tree.list_state.borrow_mut(),
-            );
-        } else {
-            let no_metadata = Paragraph::new("No metadata available")
-                .block(self.format_block("Metadata", Panel::FileInfo))
-                .style(Style::default().fg(Color::Gray));
-            f.render_widget(no_metadata, chunks[1]);
+        let max_scroll = (text.lines.len() as u16).saturating_sub(inner.height);
+        let scroll = view.scroll.min(max_scroll);
+        let widget = Paragraph::new(text)
+            .block(block)
+            .style(Style::default().fg(self.theme.text))
+            .scroll((scroll, 0));
+        f.render_widget(widget, area);
+        if let Some(view) = &mut self.breakdown_view {
+            view.scroll = scroll;
         }
     }
 
-    fn format_block<'a>(&self, title: impl Into<Line<'a>>, panel: Panel) -> Block<'a> {
-        let mut title: Line = title.into();
-        let border_style = if self.selected_panel == panel {
-            title += "*".into();
-            Style::default().fg(PANEL_BORDER_SELECTED)
-        } else {
-            Style::default().fg(PANEL_BORDER)
+    /// Closes the treemap and selects its selected rectangle in the tree
+    fn show_treemap_selection(&mut self) {
+        let Some(view) = self.treemap_view.take() else {
+            return;
         };
-        title = title.bold();
-
-        Block::default()
-            .borders(Borders::ALL)
-            .border_style(border_style)
-            .title(title)
+        let (Some(cell), Some(tree)) = (view.cells.get(view.selected), &mut self.tree_state) else {
+            return;
+        };
+        tree.reveal(&cell.full_name);
+        self.selected_panel = Panel::Tree;
+        self.update_analysis_for_selected_tensor();
     }
 
-    fn format_count(&self, count: u64) -> String {
-        if count < 1000 {
-            count.to_string()
-        } else {
-            self.count_formatter.format(count as f64)
+    fn render_treemap_view(&mut self, f: &mut ratatui::Frame, area: Rect) {
+        let (Some(tree), Some(view)) = (&self.tree_state, &self.treemap_view) else {
+            return;
+        };
+        // Always the whole file, even when the tree is focused on one module
+        let root = tree.data_history.first().unwrap_or(&tree.data);
+        let title = format!("Treemap by Size ({})", self.format_bytes(root.total_bytes));
+        let block = self.format_block(title, Panel::Tree);
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+        let mut cells = Vec::new();
+        layout_treemap(root, inner, 0, &mut cells);
+
+        let palette = [
+            self.theme.chart,
+            self.theme.dtype,
+            self.theme.shape,
+            self.theme.literal,
+            self.theme.count,
+            self.theme.bytesize,
+            self.theme.tensor,
+            self.theme.module,
+        ];
+        let selected = view.selected.min(cells.len().saturating_sub(1));
+        let buf = f.buffer_mut();
+        for (i, cell) in cells.iter().enumerate() {
+            let color = palette[i % palette.len()];
+            let fill = if i == selected { "▒" } else { "█" };
+            let area = cell.area;
+            for y in area.top()..area.bottom() {
+                for x in area.left()..area.right() {
+                    // A half block on the right edge leaves a gap between neighbors
+                    let symbol = if x + 1 == area.right() && area.width > 1 {
+                        "▌"
+                    } else {
+                        fill
+                    };
+                    buf[(x, y)].set_symbol(symbol).set_fg(color);
+                }
+            }
+            let label = format!("{} {}", cell.key, self.format_bytes(cell.bytes));
+            let mut style = Style::default().fg(Color::Black).bg(color);
+            if i == selected {
+                style = style.bg(self.theme.selection).fg(self.theme.text).bold();
+            }
+            let width = area.width.saturating_sub(1).max(1) as usize;
+            buf.set_stringn(area.x, area.y, label, width, style);
         }
-    }
-
-    fn format_bytes(&self, bytes: u64) -> String {
-        if bytes < 1000 {
-            format!("{bytes} Bytes")
-        } else {
-            self.bytes_formatter.format(bytes as f64)
+        if let Some(view) = &mut self.treemap_view {
+            view.cells = cells;
+            view.selected = selected;
         }
     }
 
-    fn should_show_analysis_panel(&self) -> bool {
-        let Some(tree) = &self.tree_state else {
-            return false;
+    fn render_diagnostics_view(&self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(view) = &self.diagnostics_view else {
+            return;
         };
-        tree.list_state
-            .borrow()
-            .selected()
-            .and_then(|i| tree.visible_items.get(i))
-            .is_some_and(|item| item.info.is_tensor())
+        let header = Row::new(["Tensor", "Problem"]).style(Style::default().bold());
+        let rows = self.problems.iter().map(|problem| {
+            let tensor = match &problem.tensor {
+                Some(name) => name.clone().fg(self.theme.tensor),
+                None => "(file)".fg(self.theme.muted),
+            };
+            Row::new(vec![
+                Cell::from(tensor),
+                Cell::from(problem.message.clone().fg(self.theme.error)),
+            ])
+        });
+        let title = format!("Integrity ({} problems)", self.problems.len());
+        let widget = Table::new(rows, [Constraint::Fill(1), Constraint::Fill(2)])
+            .header(header)
+            .block(self.format_block(title, Panel::Tree))
+            .row_highlight_style(
+                Style::default()
+                    .bg(self.theme.selection)
+                    .fg(self.theme.text),
+            );
+        StatefulWidget::render(widget, area, f.buffer_mut(), &mut *view.state.borrow_mut());
     }
 
-    fn render_analysis_panel(&mut self, f: &mut ratatui::Frame, area: Rect) {
-        let tensor_info = {
-            let Some(tree) = &self.tree_state else { return };
-            let selected_item = tree
-                .list_state
-                .borrow()
-                .selected()
-                .and_then(|i| tree.visible_items.get(i));
-
-            let Some(item) = selected_item else {
-                return;
-            };
+    fn render_batch_view(&self, f: &mut ratatui::Frame, area: Rect) {
+        let (Some(view), Some(batch)) = (&self.batch_view, &self.batch) else {
+            return;
+        };
+        let done = batch.analysis.done.load(Relaxed);
+        let header =
+            Row::new(["Status", "Tensor", "Mean", "Std", "Health"]).style(Style::default().bold());
+        let rows = batch
+            .analysis
+            .tensors
+            .iter()
+            .enumerate()
+            .map(|(i, (name, analysis))| {
+                let status = if analysis.error.get().is_some() {
+                    "failed".fg(self.theme.error)
+                } else if i < done {
+                    "done".fg(self.theme.success)
+                } else if i == done {
+                    let progress = analysis.progress.load(Relaxed);
+                    format!("{progress}%").fg(self.theme.accent)
+                } else {
+                    "queued".fg(self.theme.muted)
+                };
+                let mut cells = vec![
+                    Cell::from(status),
+                    Cell::from(name.clone().fg(self.theme.tensor)),
+                ];
+                if let Some(error) = analysis.error.get() {
+                    cells.extend([Cell::default(), Cell::default()]);
+                    cells.push(Cell::from(error.to_string().fg(self.theme.error)));
+                } else if let Some(stats) = analysis.stats.get() {
+                    let health = TensorHealth::from_stats(stats);
+                    let color = if health.is_ok() {
+                        self.theme.text
+                    } else {
+                        self.theme.warning
+                    };
+                    cells.push(Cell::from(format!("{:.4}", stats.mean)));
+                    cells.push(Cell::from(format!("{:.4}", stats.std)));
+                    cells.push(Cell::from(health.to_string().fg(color)));
+                }
+                Row::new(cells)
+            });
+        let module = match batch.module.as_str() {
+            "" => "File",
+            module => module,
+        };
+        let total = batch.analysis.tensors.len();
+        let title = format!("Batch Analysis of {module} ({done}/{total} done)");
+        let widget = Table::new(
+            rows,
+            [
+                Constraint::Length(8),
+                Constraint::Fill(3),
+                Constraint::Length(10),
+                Constraint::Length(10),
+                Constraint::Fill(2),
+            ],
+        )
+        .header(header)
+        .block(self.format_block(title, Panel::Tree))
+        .row_highlight_style(
+            Style::default()
+                .bg(self.theme.selection)
+                .fg(self.theme.text),
+        );
+        StatefulWidget::render(widget, area, f.buffer_mut(), &mut *view.state.borrow_mut());
+    }
 
-            let Some(tensor_info) = &item.info.tensor_info else {
-                return;
+    fn render_layout_view(&self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(view) = &self.layout_view else {
+            return;
+        };
+        let header = Row::new(["Offset", "End", "Size", "Gap", "Tensor", "Type"])
+            .style(Style::default().bold());
+        let rows = view.tensors.iter().map(|(name, tensor, gap)| {
+            let gap = match *gap {
+                0 => Cell::default(),
+                gap if gap > 0 => Cell::from(format!("+{gap}").fg(self.theme.muted)),
+                gap => Cell::from(format!("overlaps {}", -gap).fg(self.theme.warning)),
             };
+            Row::new(vec![
+                Cell::from(tensor.offset.to_string()),
+                Cell::from((tensor.offset + tensor.size as u64).to_string()),
+                Cell::from(
+                    self.format_bytes(tensor.size as u64)
+                        .fg(self.theme.bytesize),
+                ),
+                gap,
+                Cell::from(name.clone().fg(self.theme.tensor)),
+                Cell::from(tensor.ty.to_string().fg(self.theme.dtype)),
+            ])
+        });
+        let data: u64 = view.tensors.iter().map(|(_, t, _)| t.size as u64).sum();
+        let padding = view
+            .tensors
+            .iter()
+            .map(|&(_, _, gap)| gap.max(0))
+            .sum::<i64>() as u64;
+        let share = padding as f64 / (data + padding).max(1) as f64 * 100.0;
+        let title = format!(
+            "File Layout ({} tensors, {} of data, {} between them, {share:.2}%)",
+            view.tensors.len(),
+            self.format_bytes(data),
+            self.format_bytes(padding),
+        );
+        let widget = Table::new(
+            rows,
+            [
+                Constraint::Length(14),
+                Constraint::Length(14),
+                Constraint::Length(12),
+                Constraint::Length(16),
+                Constraint::Fill(1),
+                Constraint::Length(8),
+            ],
+        )
+        .header(header)
+        .block(self.format_block(title, Panel::Tree))
+        .row_highlight_style(
+            Style::default()
+                .bg(self.theme.selection)
+                .fg(self.theme.text),
+        );
+        StatefulWidget::render(widget, area, f.buffer_mut(), &mut *view.state.borrow_mut());
+    }
 
-            tensor_info.clone()
+    fn render_directory_view(&self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(view) = &self.directory_view else {
+            return;
         };
-
-        let analysis_chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([
-                Constraint::Percentage(50), // Histogram
-                Constraint::Percentage(50), // Singular values (if 2D)
+        let header = Row::new(["File", "Format", "Size", "Tensors"]).style(Style::default().bold());
+        let rows = view.files.iter().map(|file| {
+            let name = file.path.strip_prefix(&view.dir).unwrap_or(&file.path);
+            let tensors = match &file.tensors {
+                Ok(count) => self.format_count(*count).fg(self.theme.count),
+                Err(err) => err.clone().fg(self.theme.error),
+            };
+            Row::new(vec![
+                Cell::from(name.display().to_string().fg(self.theme.tensor)),
+                Cell::from(file.format.fg(self.theme.dtype)),
+                Cell::from(self.format_bytes(file.bytes).fg(self.theme.bytesize)),
+                Cell::from(tensors),
             ])
-            .split(area);
-
-        self.render_histogram(f, analysis_chunks[0]);
-
-        if tensor_info.shape.len() == 2 {
-            self.render_spectrum(f, analysis_chunks[1]);
-        } else {
-            let placeholder = Paragraph::new("SVD only possible on 2D tensors")
-                .block(self.format_block("Matrix Spectrum", Panel::Analysis))
-                .style(Style::default().fg(Color::Gray));
-            f.render_widget(placeholder, analysis_chunks[1]);
-        }
+        });
+        let title = format!("{} ({} checkpoints)", view.dir.display(), view.files.len());
+        let widget = Table::new(
+            rows,
+            [
+                Constraint::Fill(3),
+                Constraint::Length(12),
+                Constraint::Length(11),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(header)
+        .block(self.format_block(title, Panel::Tree))
+        .row_highlight_style(
+            Style::default()
+                .bg(self.theme.selection)
+                .fg(self.theme.text),
+        );
+        StatefulWidget::render(widget, area, f.buffer_mut(), &mut *view.state.borrow_mut());
     }
 
-    fn render_bar_chart(
-        chart: &crate::analysis::BarChart,
-        max_width: usize,
-        color: Color,
-        format_value: impl Fn(f32) -> String,
-    ) -> Vec<Line<'static>> {
-        let mut lines = Vec::new();
-
-        if chart.bins.is_empty() {
-            return lines;
+    /// Lists recent files at the top of the start screen, returning the area left for help
+    fn render_recent_files(&self, f: &mut ratatui::Frame, area: Rect) -> Rect {
+        if self.recent.files.is_empty() {
+            return area;
         }
-
-        let max_count = chart.bins.iter().max().cloned().unwrap_or(1) as f32;
-        let bin_width = (chart.right - chart.left) / chart.bins.len() as f32;
-
-        for (i, &count) in chart.bins.iter().enumerate() {
-            let range_start = chart.left + i as f32 * bin_width;
-            let range_end = chart.left + (i + 1) as f32 * bin_width;
-            let bar_len = (count as f32 / max_count * max_width as f32) as usize;
-            let bar = "█".repeat(bar_len);
-
-            let label = if chart.continues_past_left && i == 0 {
-                format!("       {}: ", format_value(range_end))
-            } else if chart.continues_past_right && i + 1 == chart.bins.len() {
-                format!("{}       : ", format_value(range_start))
+        let height = (self.recent.files.len() as u16 + 2).min(area.height / 2);
+        let [list_area, rest] =
+            Layout::vertical([Constraint::Length(height), Constraint::Fill(1)]).areas(area);
+        let items = self.recent.files.iter().map(|path| {
+            let style = if path.exists() || path.to_str().is_some_and(is_object_url) {
+                Style::default().fg(self.theme.tensor)
             } else {
-                format!(
-                    "{} {}: ",
-                    format_value(range_start),
-                    format_value(range_end)
-                )
+                Style::default().fg(self.theme.muted)
             };
-
-            lines.push(Line::from(vec![
-                label.into(),
-                bar.fg(color),
-                format!(" ({count})").into(),
-            ]));
+            ListItem::new(path.display().to_string()).style(style)
+        });
+        let list = List::new(items)
+            .block(self.format_block("Recent Files".to_string(), Panel::Tree))
+            .highlight_style(
+                Style::default()
+                    .bg(self.theme.selection)
+                    .fg(self.theme.text),
+            );
+        let mut state = self.recent_state.borrow_mut();
+        if state.selected().is_none() {
+            state.select(Some(0));
         }
-
-        lines
+        StatefulWidget::render(list, list_area, f.buffer_mut(), &mut *state);
+        rest
     }
 
-    fn render_histogram_into(&mut self, text: &mut Text) {
-        let Some(analysis) = self.current_analysis.as_ref() else {
-            text.push_line("No analysis running");
+    fn render_image_view(&self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(view) = &self.image_view else {
             return;
         };
+        let (width, height) = view.image.dimensions();
+        let title = format!("{} ({width}×{height})", view.key);
+        let block = self.format_block(title, Panel::FileInfo);
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+        f.render_widget(
+            HalfBlocks {
+                image: &view.image,
+                background: Color::Reset,
+            },
+            inner,
+        );
+    }
 
-        if let Some(error) = analysis.error.get() {
-            text.push_line(vec!["Error: ".fg(Color::Red), format!("{error}").into()]);
+    fn render_tokenizer_view(&self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(view) = &self.tokenizer_view else {
             return;
+        };
+        let area = match &view.embedding {
+            Some(embedding) => {
+                let [area, embedding_area] =
+                    Layout::horizontal([Constraint::Fill(1), Constraint::Length(44)]).areas(area);
+                self.render_embedding_panel(f, embedding_area, view, embedding);
+                area
+            }
+            None => area,
+        };
+        let [input_area, table_area] =
+            Layout::vertical([Constraint::Length(3), Constraint::Fill(1)]).areas(area);
+
+        let title = match view.mode {
+            TokenizerMode::Search => "Search Tokens or Ids (Tab: Encode)",
+            TokenizerMode::Encode if view.embedding.is_some() => "Encode Text (Tab: Untrained)",
+            TokenizerMode::Encode => "Encode Text (Tab: Search)",
+            TokenizerMode::Untrained => "Filter Untrained Tokens (Tab: Search)",
+        };
+        let input = Paragraph::new(Line::from(vec![
+            view.input.as_str().fg(self.theme.text),
+            "█".fg(self.theme.accent),
+        ]))
+        .block(self.format_block(title, Panel::Tree));
+        f.render_widget(input, input_area);
+
+        // Only build rows for what's on screen, since vocabularies run to 100k+ tokens
+        let visible = table_area.height.saturating_sub(3).max(1) as usize;
+        let mut offset = view.offset.get().min(view.selected);
+        if view.selected >= offset + visible {
+            offset = view.selected + 1 - visible;
+        }
+        view.offset.set(offset);
+        let stats = view.embedding.as_ref().and_then(|e| e.stats.get());
+        let rows = view.rows[offset.min(view.rows.len())..]
+            .iter()
+            .take(visible)
+            .map(|&id| {
+                let token = view
+                    .vocab
+                    .tokens
+                    .get(id as usize)
+                    .map_or("", String::as_str);
+                let mut cells = vec![
+                    Cell::from(id.to_string().fg(self.theme.count)),
+                    Cell::from(format!("{token:?}").fg(self.theme.literal)),
+                    Cell::from(match view.vocab.score(id) {
+                        Some(score) => format!("{score:.3}").into(),
+                        None => "-".fg(self.theme.muted),
+                    }),
+                    Cell::from(match view.vocab.token_type(id) {
+                        Some(ty) => token_type_name(ty).into(),
+                        None => "-".fg(self.theme.muted),
+                    }),
+                ];
+                if view.embedding.is_some() {
+                    let norm = stats.and_then(|stats| stats.norms.get(id as usize));
+                    let flag = stats.and_then(|stats| stats.flags.get(id as usize)?.as_ref());
+                    cells.push(Cell::from(match norm {
+                        Some(norm) => format!("{norm:.3}").into(),
+                        None => "-".fg(self.theme.muted),
+                    }));
+                    cells.push(Cell::from(match flag {
+                        Some(flag) => flag.label().fg(self.theme.warning),
+                        None => "".into(),
+                    }));
+                }
+                Row::new(cells)
+            });
+        let mut header = vec!["Id", "Token", "Score", "Type"];
+        let mut widths = vec![
+            Constraint::Length(8),
+            Constraint::Fill(1),
+            Constraint::Length(10),
+            Constraint::Length(8),
+        ];
+        if view.embedding.is_some() {
+            header.extend(["Norm", "Untrained"]);
+            widths.extend([Constraint::Length(8), Constraint::Length(16)]);
         }
+        let header = Row::new(header).style(Style::default().bold());
+        let mut title = match (&view.error, view.mode) {
+            (Some(error), _) => Line::from(format!("Error: {error}").fg(self.theme.error)),
+            (None, TokenizerMode::Encode) => format!("{} tokens", view.rows.len()).into(),
+            (None, TokenizerMode::Untrained) if view.stale => {
+                "Measuring embeddings...".fg(self.theme.accent).into()
+            }
+            (None, TokenizerMode::Untrained) => {
+                format!("{} untrained tokens", view.rows.len()).into()
+            }
+            (None, TokenizerMode::Search) => {
+                format!("{} of {} tokens", view.rows.len(), view.vocab.tokens.len()).into()
+            }
+        };
+        if !view.vocab.model.is_empty() {
+            title += format!(" ({})", view.vocab.model).into();
+        }
+        let widget = Table::new(rows, widths)
+            .header(header)
+            .block(self.format_block(title, Panel::Analysis))
+            .row_highlight_style(
+                Style::default()
+                    .bg(self.theme.selection)
+                    .fg(self.theme.text),
+            );
+        let mut state = TableState::default();
+        if !view.rows.is_empty() {
+            state.select(Some(view.selected - offset));
+        }
+        StatefulWidget::render(widget, table_area, f.buffer_mut(), &mut state);
+    }
 
-        match (
-            analysis.histogram.get(),
-            analysis.histogram_go.load(Relaxed),
-        ) {
-            (Some(histogram), _) => {
+    /// Norms and untrained rows of the embedding, and the nearest neighbors of the picked token
+    fn render_embedding_panel(
+        &self,
+        f: &mut ratatui::Frame,
+        area: Rect,
+        view: &TokenizerView,
+        embedding: &EmbeddingAnalysis,
+    ) {
+        let token = |id: u32| {
+            let token = view
+                .vocab
+                .tokens
+                .get(id as usize)
+                .map_or("", String::as_str);
+            format!("{token:?}")
+        };
+        let mut text = Text::default();
+        text.push_line(embedding.name.as_str().fg(self.theme.literal));
+        text.push_line(
+            format!(
+                "{} tokens × {} dims",
+                embedding.tensor.shape[0],
+                embedding.dim()
+            )
+            .fg(self.theme.muted),
+        );
+        text.push_line("");
+        let stats = embedding.stats.get();
+        match (stats, embedding.error.get()) {
+            (_, Some(error)) => text.push_line(vec![
+                "Error: ".fg(self.theme.error),
+                format!("{error}").into(),
+            ]),
+            (None, None) => text.push_line(
+                format!("🔄 Measuring rows... {}%", embedding.progress.load(Relaxed))
+                    .fg(self.theme.accent),
+            ),
+            (Some(stats), None) => {
                 text.push_line(vec![
-                    "Data range: ".bold(),
-                    format!("{:.3} to {:.3}", histogram.min, histogram.max).into(),
+                    "Median norm: ".bold(),
+                    format!("{:.3}", stats.median).into(),
                 ]);
-                text.push_line(Line::from(""));
+                let counts = [
+                    ("Zero rows: ", stats.count(|f| *f == RowFlag::Zero)),
+                    ("Duplicate rows: ", {
+                        stats.count(|f| matches!(f, RowFlag::Duplicate { .. }))
+                    }),
+                    ("Low norm rows: ", stats.count(|f| *f == RowFlag::LowNorm)),
+                    ("NaN/inf rows: ", stats.count(|f| *f == RowFlag::NonFinite)),
+                ];
+                for (label, count) in counts {
+                    let value = count.to_string();
+                    text.push_line(vec![
+                        label.bold(),
+                        if count > 0 {
+                            value.fg(self.theme.warning)
+                        } else {
+                            value.into()
+                        },
+                    ]);
+                }
+            }
+        }
 
-                let chart_lines = Self::render_bar_chart(
-                    &histogram.chart,
-                    30, // max_width
-                    Color::Blue,
-                    |x| format!("{x:6.2}"),
-                );
-                text.extend(chart_lines);
+        if let Some(&id) = view.rows.get(view.selected) {
+            text.push_line("");
+            let mut line = Line::from(vec!["Selected: ".bold(), token(id).fg(self.theme.literal)]);
+            if let Some(norm) = stats.and_then(|stats| stats.norms.get(id as usize)) {
+                line += format!(" norm {norm:.3}").into();
             }
-            (None, true) => {
-                text.push_line(vec!["🔄 Computing histogram...".fg(Color::Yellow)]);
+            text.push_line(line);
+            if let Some(Some(flag)) = stats.and_then(|stats| stats.flags.get(id as usize)) {
+                text.push_line(format!("⚠ Untrained: {}", flag.label()).fg(self.theme.warning));
             }
-            (None, false) => {
-                text.push_line(vec!["Press \"y\" to compute histogram".fg(Color::Red)]);
+        }
+
+        text.push_line("");
+        match &view.neighbors {
+            None => text.push_line("Enter: find the nearest tokens".fg(self.theme.muted)),
+            Some(neighbors) => {
+                text.push_line(vec![
+                    "Nearest to ".bold(),
+                    token(neighbors.token).fg(self.theme.literal),
+                ]);
+                let progress = neighbors.progress.load(Relaxed);
+                match (neighbors.nearest.get(), neighbors.error.get()) {
+                    (_, Some(error)) => text.push_line(vec![
+                        "Error: ".fg(self.theme.error),
+                        format!("{error}").into(),
+                    ]),
+                    (None, None) if neighbors.pass.load(Relaxed) == 1 => text.push_line(
+                        format!("🔄 Finding its row... {progress}%").fg(self.theme.accent),
+                    ),
+                    (None, None) => text.push_line(
+                        format!("🔄 Comparing rows... {progress}%").fg(self.theme.accent),
+                    ),
+                    (Some(nearest), None) => {
+                        for &(id, similarity) in nearest {
+                            text.push_line(vec![
+                                format!("{similarity:>6.3} ").fg(self.theme.count),
+                                format!("{id:>7} ").fg(self.theme.muted),
+                                token(id).fg(self.theme.literal),
+                            ]);
+                        }
+                    }
+                }
             }
         }
+
+        let widget = Paragraph::new(text)
+            .block(self.format_block("Embedding", Panel::SelectedInfo))
+            .wrap(Wrap { trim: false });
+        f.render_widget(widget, area);
     }
 
-    fn render_histogram(&mut self, f: &mut ratatui::Frame, area: Rect) {
+    fn render_similarity_view(&self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(view) = &self.similarity_view else {
+            return;
+        };
+        let analysis = &view.analysis;
+        let n = analysis.tensors.len();
+        let [matrix_area, info_area] =
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .areas(area);
+
+        let block = self.format_block(
+            format!("Cosine Similarity of {} ({n} tensors)", view.pattern),
+            Panel::Tree,
+        );
+        let inner = block.inner(matrix_area);
+        block.render(matrix_area, f.buffer_mut());
         let mut text = Text::default();
-        self.render_histogram_into(&mut text);
-        let histogram_widget = Paragraph::new(text)
-            .block(self.format_block("Histogram", Panel::Analysis))
-            .style(Style::default().fg(Color::White))
-            .wrap(Wrap { trim: false });
+        if let Some(error) = analysis.error.get() {
+            text.push_line(vec![
+                "Error: ".fg(self.theme.error),
+                format!("{error}").into(),
+            ]);
+        } else if analysis.matrix.get().is_none() {
+            let done = analysis.done.load(Relaxed);
+            let progress = analysis.progress.load(Relaxed);
+            text.push_line(
+                format!(
+                    "🔄 Reading tensors... {done}/{} ({progress}%)",
+                    analysis.total
+                )
+                .fg(self.theme.accent),
+            );
+        } else {
+            // Two columns per cell keeps them roughly square, scrolled to keep the cursor visible
+            let label_width = n.saturating_sub(1).to_string().len();
+            let rows = inner.height as usize;
+            let columns = (inner.width as usize).saturating_sub(label_width + 1) / 2;
+            let first = |cursor: usize, visible: usize| (cursor + 1).saturating_sub(visible.max(1));
+            let (first_row, first_column) =
+                (first(view.cursor.0, rows), first(view.cursor.1, columns));
+            for i in (first_row..n).take(rows) {
+                let mut spans = vec![format!("{i:>label_width$} ").fg(self.theme.muted)];
+                for j in (first_column..n).take(columns) {
+                    let cell = if (i, j) == view.cursor {
+                        "[]"
+                    } else {
+                        "██"
+                    };
+                    let color = match analysis.get(i, j) {
+                        Some(value) => heatmap_color(value, 1.0, &self.theme),
+                        None => self.theme.muted,
+                    };
+                    spans.push(cell.fg(color));
+                }
+                text.push_line(spans);
+            }
+        }
+        Paragraph::new(text).render(inner, f.buffer_mut());
 
-        f.render_widget(histogram_widget, area);
+        let mut text = Text::default();
+        let (row, column) = view.cursor;
+        let name = |i: usize| analysis.tensors[i].0.as_str();
+        text.push_line(vec![
+            format!("{row}: ").bold(),
+            name(row).fg(self.theme.tensor),
+        ]);
+        text.push_line(vec![
+            format!("{column}: ").bold(),
+            name(column).fg(self.theme.tensor),
+        ]);
+        if let Some(value) = analysis.get(row, column) {
+            text.push_line(vec![
+                "Cosine: ".bold(),
+                format!("{value:.6}").fg(self.theme.literal),
+            ]);
+        } else if analysis.matrix.get().is_some() {
+            text.push_line("Shapes differ".fg(self.theme.muted));
+        }
+        if analysis.matrix.get().is_some() {
+            text.push_line("");
+            text.push_line("Most similar pairs".bold());
+            for (i, j, value) in analysis.ranked_pairs().into_iter().take(20) {
+                let color = if value > 0.999 {
+                    self.theme.warning
+                } else {
+                    self.theme.text
+                };
+                text.push_line(vec![
+                    format!("{value:.4} ").fg(color),
+                    format!("{i} ~ {j}").into(),
+                ]);
+            }
+        }
+        let widget = Paragraph::new(text)
+            .block(self.format_block("Selected Pair", Panel::Analysis))
+            .wrap(Wrap { trim: false });
+        widget.render(info_area, f.buffer_mut());
     }
 
-    fn render_spectrum_into(&mut self, text: &mut Text) {
-        let Some(analysis) = self.current_analysis.as_ref() else {
-            text.push_line("No analysis running");
+    fn render_progression_view(&self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(view) = &self.progression_view else {
             return;
         };
-
+        let analysis = &view.analysis;
+        let values = analysis.values.lock().unwrap();
+        let [chart_area, info_area] =
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .areas(area);
+
+        let title = format!(
+            "Layer Progression: {} of {} ({} tensors)",
+            analysis.metric.label(),
+            view.pattern,
+            analysis.tensors.len()
+        );
+        let block = self.format_block(title, Panel::Tree);
+        let inner = block.inner(chart_area);
+        block.render(chart_area, f.buffer_mut());
+        let mut text = Text::default();
         if let Some(error) = analysis.error.get() {
-            text.push_line(vec!["Error: ".fg(Color::Red), format!("{error}").into()]);
-            return;
+            text.push_line(vec![
+                "Error: ".fg(self.theme.error),
+                format!("{error}").into(),
+            ]);
+        } else if !analysis.is_finished() {
+            let done = analysis.done.load(Relaxed);
+            let progress = analysis.progress.load(Relaxed);
+            text.push_line(
+                format!(
+                    "🔄 Reading tensors... {done}/{} ({progress}%)",
+                    analysis.tensors.len()
+                )
+                .fg(self.theme.accent),
+            );
         }
+        let [text_area, plot_area] = Layout::vertical([
+            Constraint::Length(text.lines.len() as u16),
+            Constraint::Fill(1),
+        ])
+        .areas(inner);
+        Paragraph::new(text).render(text_area, f.buffer_mut());
+
+        let layer = |i: usize| analysis.tensors[i].0 as f64;
+        let points: Vec<(f64, f64)> = values
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| (layer(i), value))
+            .collect();
+        let (first, last) = (layer(0), layer(analysis.tensors.len() - 1));
+        let top = values
+            .iter()
+            .copied()
+            .fold(0.0, f64::max)
+            .max(f64::MIN_POSITIVE);
+        let cursor = [(layer(view.cursor), 0.0), (layer(view.cursor), top)];
+        let datasets = vec![
+            Dataset::default()
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(self.theme.chart))
+                .data(&points),
+            Dataset::default()
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(self.theme.accent))
+                .data(&cursor),
+        ];
+        let widget = Chart::new(datasets)
+            .x_axis(
+                Axis::default()
+                    .bounds([first, last.max(first + 1.0)])
+                    .labels([
+                        format!("{first}"),
+                        format!("{}", ((first + last) / 2.0).round()),
+                        format!("{last}"),
+                    ])
+                    .style(Style::default().fg(self.theme.muted)),
+            )
+            .y_axis(
+                Axis::default()
+                    .bounds([0.0, top])
+                    .labels(["0".to_string(), format!("{top:.3e}")])
+                    .style(Style::default().fg(self.theme.muted)),
+            );
+        widget.render(plot_area, f.buffer_mut());
 
-        match (analysis.spectrum.get(), analysis.spectrum_go.load(Relaxed)) {
-            (Some(spectrum), _) => {
-                text.push_line(Line::from(""));
-
-                let chart_lines = Self::render_bar_chart(
-                    &spectrum.chart,
-                    30, // max_width
-                    Color::Blue,
-                    |x| format!("{x:6.2}"),
-                );
-                text.extend(chart_lines);
-            }
-            (None, true) => {
-                text.push_line(vec!["🔄 Computing SVD decomposition...".fg(Color::Yellow)]);
+        let mut text = Text::default();
+        let (layer, name, _) = &analysis.tensors[view.cursor];
+        text.push_line(vec![
+            format!("Layer {layer}: ").bold(),
+            name.as_str().fg(self.theme.tensor),
+        ]);
+        match values.get(view.cursor) {
+            Some(value) => text.push_line(vec![
+                format!("{}: ", analysis.metric.label()).bold(),
+                format!("{value:.4e}").fg(self.theme.literal),
+            ]),
+            None => text.push_line("Not read yet".fg(self.theme.muted)),
+        }
+        // Merges gone wrong tend to show up as a step between neighboring layers
+        let mut jumps: Vec<(usize, f64)> = values
+            .windows(2)
+            .enumerate()
+            .filter(|(_, pair)| pair[0] > 0.0 && pair[1] > 0.0)
+            .map(|(i, pair)| (i, pair[1] / pair[0]))
+            .collect();
+        jumps.sort_by(|a, b| b.1.ln().abs().total_cmp(&a.1.ln().abs()));
+        if !jumps.is_empty() {
+            text.push_line("");
+            text.push_line("Largest steps between layers".bold());
+            for (i, ratio) in jumps.into_iter().take(10) {
+                let color = if !(0.5..=2.0).contains(&ratio) {
+                    self.theme.warning
+                } else {
+                    self.theme.text
+                };
+                text.push_line(vec![
+                    format!("×{ratio:.3} ").fg(color),
+                    format!("{} → {}", analysis.tensors[i].0, analysis.tensors[i + 1].0).into(),
+                ]);
             }
-            (None, false) => {
+        }
+        let widget = Paragraph::new(text)
+            .block(self.format_block("Selected Layer", Panel::Analysis))
+            .wrap(Wrap { trim: false });
+        widget.render(info_area, f.buffer_mut());
+    }
+
+    fn render_head_view(&self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(view) = &self.head_view else {
+            return;
+        };
+        let analysis = &view.analysis;
+        let layout = &analysis.layout;
+        let [table_area, info_area] =
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .areas(area);
+
+        let title = format!(
+            "Attention Heads of {} ({} × {} on axis {})",
+            analysis.name,
+            layout.labels.len(),
+            layout.head_dim,
+            layout.axis
+        );
+        let heads = analysis.heads.get();
+        let Some(heads) = heads else {
+            let mut text = Text::default();
+            if let Some(error) = analysis.error.get() {
                 text.push_line(vec![
-                    "Press \"y\" to compute SVD decomposition".fg(Color::Red),
+                    "Error: ".fg(self.theme.error),
+                    format!("{error}").into(),
                 ]);
+            } else {
+                let done = analysis.done.load(Relaxed);
+                let progress = analysis.progress.load(Relaxed);
+                let status = match done {
+                    0 => format!("🔄 Reading tensor... {progress}%"),
+                    _ => format!(
+                        "🔄 Decomposing heads... {done}/{} ({progress}%)",
+                        layout.labels.len()
+                    ),
+                };
+                text.push_line(status.fg(self.theme.accent));
             }
+            let widget = Paragraph::new(text)
+                .block(self.format_block(title, Panel::Tree))
+                .wrap(Wrap { trim: false });
+            widget.render(area, f.buffer_mut());
+            return;
+        };
+
+        let header = Row::new(["Head", "Norm", "σ₁", "Stable rank", "Eff. rank", ""])
+            .style(Style::default().bold());
+        let rows = heads.iter().enumerate().map(|(i, head)| {
+            let flag = match analysis.flag(i) {
+                Some(HeadFlag::Dead) => "dead".fg(self.theme.warning),
+                Some(HeadFlag::Dominant) => "dominant".fg(self.theme.warning),
+                None => "".into(),
+            };
+            Row::new(vec![
+                Cell::from(layout.labels[i].as_str().fg(self.theme.count)),
+                Cell::from(format!("{:.4}", head.norm)),
+                Cell::from(format!("{:.4}", head.spectrum.top)),
+                Cell::from(format!("{:.2}", head.spectrum.stable_rank)),
+                Cell::from(format!("{:.2}", head.spectrum.effective_rank)),
+                Cell::from(flag),
+            ])
+        });
+        let widget = Table::new(
+            rows,
+            [
+                Constraint::Length(6),
+                Constraint::Length(10),
+                Constraint::Length(10),
+                Constraint::Length(12),
+                Constraint::Length(10),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(header)
+        .block(self.format_block(title, Panel::Tree))
+        .row_highlight_style(
+            Style::default()
+                .bg(self.theme.selection)
+                .fg(self.theme.text),
+        );
+        let mut state = TableState::default().with_selected(Some(view.cursor));
+        StatefulWidget::render(widget, table_area, f.buffer_mut(), &mut state);
+
+        let block = self.format_block("Selected Head", Panel::Analysis);
+        let inner = block.inner(info_area);
+        block.render(info_area, f.buffer_mut());
+        let head = &heads[view.cursor];
+        let median = analysis.median_norm();
+        let mut text = Text::default();
+        text.push_line(vec![
+            "Head ".bold(),
+            layout.labels[view.cursor].as_str().fg(self.theme.count),
+            format!(": norm {:.4}", head.norm).into(),
+        ]);
+        if median > 0.0 {
+            text.push_line(format!("{:.2}× the median head", head.norm / median));
         }
+        let count = |flag| {
+            (0..heads.len())
+                .filter(|&i| analysis.flag(i) == Some(flag))
+                .count()
+        };
+        let (dead, dominant) = (count(HeadFlag::Dead), count(HeadFlag::Dominant));
+        let summary = format!("{dead} dead, {dominant} dominant of {}", heads.len());
+        text.push_line(if dead + dominant > 0 {
+            summary.fg(self.theme.warning)
+        } else {
+            summary.fg(self.theme.muted)
+        });
+        text.push_line("");
+        text.push_line("Singular values".bold());
+        let [text_area, plot_area] = Layout::vertical([
+            Constraint::Length(text.lines.len() as u16),
+            Constraint::Fill(1),
+        ])
+        .areas(inner);
+        Paragraph::new(text).render(text_area, f.buffer_mut());
+
+        let points: Vec<(f64, f64)> = head
+            .singular_values
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| (i as f64, value as f64))
+            .collect();
+        let last = points.len().saturating_sub(1) as f64;
+        // A dead head's values are all zero, which would leave the axis with no height
+        let top = match head.spectrum.top as f64 {
+            top if top > 0.0 => top,
+            _ => 1.0,
+        };
+        let datasets = vec![
+            Dataset::default()
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(self.theme.chart))
+                .data(&points),
+        ];
+        let widget = Chart::new(datasets)
+            .x_axis(
+                Axis::default()
+                    .bounds([0.0, last.max(1.0)])
+                    .labels(["0".to_string(), format!("{last}")])
+                    .style(Style::default().fg(self.theme.muted)),
+            )
+            .y_axis(
+                Axis::default()
+                    .bounds([0.0, top])
+                    .labels(["0".to_string(), format!("{top:.3e}")])
+                    .style(Style::default().fg(self.theme.muted)),
+            );
+        widget.render(plot_area, f.buffer_mut());
     }
 
-    fn render_spectrum(&mut self, f: &mut ratatui::Frame, area: Rect) {
+    fn render_lora_view(&self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(view) = &self.lora_view else {
+            return;
+        };
+        let [table_area, spectrum_area] =
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .areas(area);
+
+        let header = Row::new(["Adapter", "Rank", "Alpha", "Scale", "A", "B", "ΔW"])
+            .style(Style::default().bold());
+        let shape = |shape: &[u64]| format!("{shape:?}");
+        let rows = view.pairs.iter().map(|pair| {
+            Row::new(vec![
+                Cell::from(pair.base.as_str().fg(self.theme.tensor)),
+                Cell::from(pair.rank().to_string().fg(self.theme.count)),
+                Cell::from(match pair.alpha {
+                    Some(alpha) => alpha.to_string().into(),
+                    None => "-".fg(self.theme.muted),
+                }),
+                Cell::from(format!("{:.3}", pair.scale())),
+                Cell::from(shape(&pair.down.1.shape).fg(self.theme.shape)),
+                Cell::from(shape(&pair.up.1.shape).fg(self.theme.shape)),
+                Cell::from(shape(&pair.delta_shape()).fg(self.theme.shape)),
+            ])
+        });
+        let title = format!("LoRA Adapters ({} pairs)", view.pairs.len());
+        let widget = Table::new(
+            rows,
+            [
+                Constraint::Fill(3),
+                Constraint::Length(6),
+                Constraint::Length(8),
+                Constraint::Length(8),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(header)
+        .block(self.format_block(title, Panel::Tree))
+        .row_highlight_style(
+            Style::default()
+                .bg(self.theme.selection)
+                .fg(self.theme.text),
+        );
+        StatefulWidget::render(
+            widget,
+            table_area,
+            f.buffer_mut(),
+            &mut *view.state.borrow_mut(),
+        );
+
         let mut text = Text::default();
-        self.render_spectrum_into(&mut text);
+        let mut chart = None;
+        match &view.analysis {
+            Some(analysis) => {
+                if let Some(error) = analysis.error.get() {
+                    text.push_line(vec![
+                        "Error: ".fg(self.theme.error),
+                        format!("{error}").into(),
+                    ]);
+                } else if let Some(spectrum) = analysis.spectrum.get() {
+                    let summary = &spectrum.summary;
+                    text.push_line(vec![
+                        "σ₁: ".bold(),
+                        format!("{:.4}", summary.top).into(),
+                        "  Rank: ".bold(),
+                        analysis.pair.rank().to_string().into(),
+                    ]);
+                    text.push_line(vec![
+                        "Stable rank: ".bold(),
+                        format!("{:.2}", summary.stable_rank).into(),
+                        "  Effective rank: ".bold(),
+                        format!("{:.2}", summary.effective_rank).into(),
+                    ]);
+                    chart = Some((spectrum.chart.clone(), Vec::new()));
+                } else {
+                    let progress = analysis.progress.load(Relaxed);
+                    text.push_line(
+                        format!("🔄 Computing merged spectrum... {progress}%")
+                            .fg(self.theme.accent),
+                    );
+                }
+            }
+            None => text.push_line("No adapter selected".fg(self.theme.muted)),
+        }
+        self.render_chart_panel(
+            f.buffer_mut(),
+            spectrum_area,
+            "Spectrum of ΔW",
+            text,
+            chart,
+            |x| format!("{x:.2}"),
+        );
+    }
 
-        let svd_widget = Paragraph::new(text)
-            .block(self.format_block("Matrix Spectrum", Panel::Analysis))
-            .style(Style::default().fg(Color::White))
-            .wrap(Wrap { trim: false });
+    fn render_byte_view(&mut self, f: &mut ratatui::Frame, area: Rect) {
+        let rows = area.height.saturating_sub(3) as usize;
+        if let Some(view) = &mut self.byte_view
+            && view.rows != rows
+        {
+            view.rows = rows;
+            view.page = None;
+        }
+        self.load_byte_page();
+        let Some(view) = &self.byte_view else {
+            return;
+        };
+
+        let mut text = Text::default();
+        text.push_line(format!("{:<10}{:<50}{}", "Offset", "Bytes", view.tensor.ty).bold());
+        match &view.page {
+            Some(Ok(page)) => {
+                for (i, row) in page.chunks(BYTES_PER_ROW).enumerate() {
+                    let mut hex = String::new();
+                    for j in 0..BYTES_PER_ROW {
+                        match row.get(j) {
+                            Some(byte) => hex += &format!("{byte:02x} "),
+                            None => hex += "   ",
+                        }
+                        if j == BYTES_PER_ROW / 2 - 1 {
+                            hex.push(' ');
+                        }
+                    }
+                    let values = view
+                        .tensor
+                        .ty
+                        .element_size()
+                        .filter(|size| row.len() % size == 0)
+                        .and_then(|_| view.tensor.ty.format_elements::<LE>(row))
+                        .map(|values| values.join(" "))
+                        .unwrap_or_default();
+                    text.push_line(vec![
+                        format!("{:08x}  ", view.offset + i * BYTES_PER_ROW).fg(self.theme.muted),
+                        format!("{hex:<50}").fg(self.theme.text),
+                        values.fg(self.theme.literal),
+                    ]);
+                }
+            }
+            Some(Err(error)) => {
+                text.push_line(vec!["Error: ".fg(self.theme.error), error.clone().into()]);
+            }
+            None => {}
+        }
 
-        f.render_widget(svd_widget, area);
+        let mut title = format!(
+            "Bytes of {} ({} of {})",
+            view.name,
+            self.format_bytes(view.offset as u64),
+            self.format_bytes(view.tensor.size as u64),
+        );
+        if view.tensor.ty.element_size().is_none() {
+            title += " — quantized blocks are not decoded";
+        }
+        let widget = Paragraph::new(text).block(self.format_block(title, Panel::Tree));
+        f.render_widget(widget, area);
     }
 
-    fn update_analysis_for_selected_tensor(&mut self) {
-        let Some(tree) = &self.tree_state else { return };
-        let selected_item = tree
-            .list_state
-            .borrow()
-            .selected()
-            .and_then(|i| tree.visible_items.get(i));
+    fn render_table_view(&self, f: &mut ratatui::Frame, area: Rect) {
+        let Some(table) = &self.table_view else {
+            return;
+        };
+
+        let header = Row::new(TableColumn::ALL.map(|column| {
+            let mut title = column.title().to_string();
+            if column == table.sort {
+                title += if table.descending { " ▼" } else { " ▲" };
+                Cell::from(title.fg(self.theme.border_selected))
+            } else {
+                Cell::from(title)
+            }
+        }))
+        .style(Style::default().bold());
 
-        let Some(item) = selected_item else { return };
-        let Some(tensor_info) = &item.info.tensor_info else {
-            return;
+        let stat = |value: Option<f64>| match value {
+            Some(value) => format!("{value:.4}").into(),
+            None => "-".gray(),
         };
+        let rows = table.rows.iter().map(|(name, info)| {
+            let stats = self.tensor_stats(name);
+            Row::new(vec![
+                Cell::from(name.as_str().fg(self.theme.tensor)),
+                Cell::from(format!("{:?}", info.shape).fg(self.theme.shape)),
+                Cell::from(info.ty.to_string().fg(self.theme.dtype)),
+                Cell::from(
+                    self.format_count(info.shape.iter().product())
+                        .fg(self.theme.count),
+                ),
+                Cell::from(self.format_bytes(info.size as u64).fg(self.theme.bytesize)),
+                Cell::from(stat(stats.as_ref().map(|s| s.mean))),
+                Cell::from(stat(stats.as_ref().map(|s| s.std))),
+                Cell::from(stat(stats.as_ref().map(|s| s.l2_norm))),
+            ])
+        });
 
-        // Calculate total number of elements in the tensor
-        let total_elements = tensor_info.shape.iter().copied().product::<u64>();
+        let mut title: Line = format!("Tensor Table ({} tensors)", table.rows.len()).into();
+        if self.health_scan.is_none() {
+            title += " - press H to compute stats".gray();
+        }
+        let widget = Table::new(
+            rows,
+            [
+                Constraint::Fill(3),
+                Constraint::Fill(1),
+                Constraint::Length(8),
+                Constraint::Length(8),
+                Constraint::Length(11),
+                Constraint::Length(10),
+                Constraint::Length(10),
+                Constraint::Length(10),
+            ],
+        )
+        .header(header)
+        .block(self.format_block(title, Panel::Tree))
+        .row_highlight_style(
+            Style::default()
+                .bg(self.theme.selection)
+                .fg(self.theme.text),
+        );
+        StatefulWidget::render(widget, area, f.buffer_mut(), &mut *table.state.borrow_mut());
+    }
 
-        let analysis = Own::new(Box::new(Analysis {
-            tensor: tensor_info.clone(),
-            histogram: OnceLock::new(),
-            histogram_go: (total_elements <= self.histogram_size_limit).into(),
-            spectrum: OnceLock::new(),
-            spectrum_go: (total_elements <= self.spectrum_size_limit).into(),
-            error: std::sync::OnceLock::new(),
-            max_bin_count: 20,
-        }));
-        if let Some(sender) = self.analysis_sender.as_ref() {
-            sender.set(analysis.refer());
+    fn tensor_health(&self, module: &ModuleInfo) -> Option<TensorHealth> {
+        if !module.is_tensor() {
+            return None;
         }
-        self.current_analysis = Some(analysis);
+        self.health_scan.as_ref()?.health(&module.full_name)
     }
 
     fn handle_y_key(&mut self) {
+        if let Some(analysis) = &self.module_analysis {
+            analysis.histogram_go.store(true, Relaxed);
+        }
+        if let Some(analysis) = &self.marked_analysis {
+            analysis.histogram_go.store(true, Relaxed);
+        }
         let Some(analysis) = &self.current_analysis else {
             return;
         };
@@ -1120,10 +7197,163 @@ impl App {
         }
     }
 
-    fn update_selected_metadata(&mut self, new_value: Option<Value>) {
+    fn selected_tensor_name(&self) -> Option<String> {
+        let tree = self.tree_state.as_ref()?;
+        let index = tree.list_state.borrow().selected()?;
+        let item = tree.visible_items.get(index)?;
+        if !item.info.is_tensor() {
+            return None;
+        }
+        Some(item.info.full_name.to_string())
+    }
+
+    pub fn export_tensor(&self, name: &str, path: &Path) -> Result<(), Error> {
+        let (Some(source), Some(tree)) = (&self.source, &self.tree_state) else {
+            bail!("no file loaded");
+        };
+        let Some(tensor) = tree.data.find(name).and_then(|m| m.tensor_info.as_ref()) else {
+            bail!("no tensor named {name}");
+        };
+        export_tensor(source, tensor, path)
+    }
+
+    /// Exports in the background, through the save dialog, so a large tensor doesn't freeze
+    /// the UI while it's dequantized and written
+    fn export_selected_tensor(&mut self, path: &Path) {
+        let (Some(source), Some((_, tensor))) = (&self.reader, self.selected_tensor()) else {
+            return;
+        };
+        let job = Own::new_box(SaveJob::new(path.to_owned(), 1));
+        start_export_tensor(source.clone(), tensor, job.refer());
+        self.save_job = Some(job);
+        self.dialog_type = Some(DialogType::Saving);
+    }
+
+    fn rename_selected(&mut self, new_name: &str) {
+        let (Some(source), Some(tree)) = (&self.source, &self.tree_state) else {
+            return;
+        };
+        let Some(index) = tree.list_state.borrow().selected() else {
+            return;
+        };
+        let Some(item) = tree.visible_items.get(index) else {
+            return;
+        };
+        let old_name = item.info.full_name.to_string();
+        if old_name == new_name {
+            return;
+        }
+
+        // Renaming a module replaces the prefix of every tensor inside it
+        let tensors = item.info.tensors();
+        if tensors.iter().any(|(name, _)| !name.starts_with(&old_name)) {
+            let message = "Grouped modules can't be renamed, ungroup them first".to_string();
+            self.dialog_type = Some(DialogType::Error(message));
+            return;
+        }
+        let renames: Vec<_> = tensors
+            .into_iter()
+            .map(|(name, _)| {
+                let mut at = old_name.len();
+                if new_name.is_empty() {
+                    // Drop the delimiter when stripping a prefix entirely
+                    at = self.path_split.next_part(&name, at);
+                }
+                let suffix = &name[at..];
+                let renamed = format!("{new_name}{suffix}");
+                (name, renamed)
+            })
+            .collect();
+
+        let result = source.lock().unwrap().rename_tensors(&renames);
+        // Tensors may have moved, so background jobs need a handle which has seen the changes
+        self.reader = None;
+        match result.and_then(|_| self.rebuild_module()) {
+            Ok(()) => {}
+            Err(err) => self.dialog_type = Some(DialogType::Error(err.to_string())),
+        }
+    }
+
+    fn delete_tensors(&mut self, names: &[String]) {
         let Some(source) = &self.source else {
             return;
         };
+        let result = source.lock().unwrap().delete_tensors(names);
+        self.reader = None;
+        match result.and_then(|_| self.rebuild_module()) {
+            Ok(()) => {}
+            Err(err) => self.dialog_type = Some(DialogType::Error(err.to_string())),
+        }
+    }
+
+    pub fn save_as(&self, path: &Path, ty: TensorTy) -> Result<(), Error> {
+        let (Some(source), Some(tree)) = (&self.source, &self.tree_state) else {
+            bail!("no file loaded");
+        };
+        let tensors = tree.data.tensors();
+        let convert = tensors.iter().map(|(name, _)| name.clone()).collect();
+        let job = Own::new_box(SaveJob::new(path.to_owned(), tensors.len()));
+        let source_path = self.file_path.as_deref();
+        save_as(
+            source,
+            source_path,
+            tensors,
+            &convert,
+            &ty,
+            path,
+            job.refer(),
+        )
+    }
+
+    fn start_save_as(&mut self, path: PathBuf) {
+        let (Some(source), Some(tree)) = (&self.reader, &self.tree_state) else {
+            return;
+        };
+        let tensors = tree.data.tensors();
+        let convert = if self.save_all {
+            tensors.iter().map(|(name, _)| name.clone()).collect()
+        } else {
+            let selected = tree.list_state.borrow().selected();
+            let Some(item) = selected.and_then(|i| tree.visible_items.get(i)) else {
+                return;
+            };
+            item.info
+                .tensors()
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect()
+        };
+
+        let job = Own::new_box(SaveJob::new(path, tensors.len()));
+        let ty = SAVE_TYPES[self.save_type].clone();
+        let source_path = self.file_path.clone();
+        start_save_as(
+            source.clone(),
+            source_path,
+            tensors,
+            convert,
+            ty,
+            job.refer(),
+        );
+        self.save_job = Some(job);
+        self.dialog_type = Some(DialogType::Saving);
+    }
+
+    fn poll_save_job(&mut self) {
+        let Some(job) = &self.save_job else {
+            return;
+        };
+        let Some(result) = job.result.get() else {
+            return;
+        };
+        self.dialog_type = Some(match result {
+            Ok(()) => DialogType::Notice(format!("Saved to {}", job.path.display())),
+            Err(err) => DialogType::Error(err.clone()),
+        });
+        self.save_job = None;
+    }
+
+    fn update_selected_metadata(&mut self, new_value: Option<Value>) {
         let Some(state) = &mut self.meta_tree_state else {
             return;
         };
@@ -1136,20 +7366,119 @@ impl App {
         let root = &*state.data;
         let replace = &*item.info;
         let new_meta = clone_with_replacement(root, replace, new_value.as_ref()).unwrap();
+        self.write_metadata(&new_meta);
+    }
+
+    /// Inserts a `key = value` entry, where a dotted key nests inside (or creates) objects
+    fn add_metadata(&mut self, input: &str) {
+        let Some(state) = &self.meta_tree_state else {
+            return;
+        };
+        let Some((path, value)) = input.split_once('=') else {
+            let message = format!("expected `key = value`, got {input:?}");
+            self.dialog_type = Some(DialogType::Error(message));
+            return;
+        };
+        let mut new_meta = (*state.data).clone();
+        match insert_metadata_path(&mut new_meta, path.trim(), parse_value(value.trim())) {
+            Ok(()) => self.write_metadata(&new_meta),
+            Err(err) => self.dialog_type = Some(DialogType::Error(err.to_string())),
+        }
+    }
 
+    /// Writes the whole metadata tree to the file and reloads it
+    fn write_metadata(&mut self, new_meta: &Value) {
+        if !self.allow_writes() {
+            return;
+        }
+        let (Some(source), Some(state)) = (&self.source, &mut self.meta_tree_state) else {
+            return;
+        };
         let mut data = source.lock().unwrap();
-        match data.write_metadata(&new_meta).and_then(|_| data.metadata()) {
+        match data.write_metadata(new_meta).and_then(|_| data.metadata()) {
             Err(err) => {
                 // Display error dialog
                 self.dialog_type = Some(DialogType::Error(err.to_string()));
             }
             Ok(reloaded_meta) => {
+                self.metadata_types = data.metadata_types().unwrap_or_default();
+                let sort = state.sort;
                 *state = TreeState::new(Arc::new(reloaded_meta).into());
+                state.sort = sort;
                 state.rebuild_visible_items();
             }
         }
     }
 
+    /// Reads the selected placeholder for a long metadata array, keeping the tree's expanded
+    /// nodes and selection. Returns false if a placeholder wasn't selected.
+    fn page_in_selected_metadata(&mut self) -> bool {
+        let (Some(source), Some(state)) = (&self.source, &mut self.meta_tree_state) else {
+            return false;
+        };
+        let Some(index) = state.list_state.borrow().selected() else {
+            return false;
+        };
+        let Some(item) = state.visible_items.get(index) else {
+            return false;
+        };
+        let Some((key, range)) = as_lazy_array(&item.info) else {
+            return false;
+        };
+        let page = match page_lazy_array(&mut *source.lock().unwrap(), key, range) {
+            Ok(page) => page,
+            Err(err) => {
+                self.dialog_type = Some(DialogType::Error(err.to_string()));
+                return true;
+            }
+        };
+        let root = &*state.data;
+        let replace: *const Value = &*item.info;
+        let new_root: Arc<Value> =
+            Arc::new(clone_with_replacement(root, &item.info, Some(&page)).unwrap());
+
+        // Nodes keep their addresses once inside the Arc, so map the old ones onto the new
+        fn remap(
+            old: &Value,
+            new: &Value,
+            expanded: &HashSet<*const Value>,
+            into: &mut HashSet<*const Value>,
+        ) {
+            if !expanded.contains(&(old as *const Value)) {
+                return;
+            }
+            into.insert(new);
+            match (old, new) {
+                (Value::Object(old), Value::Object(new)) => {
+                    for (key, value) in old {
+                        if let Some(new_value) = new.get(key) {
+                            remap(value, new_value, expanded, into);
+                        }
+                    }
+                }
+                (Value::Array(old), Value::Array(new)) => {
+                    for (value, new_value) in old.iter().zip(new) {
+                        remap(value, new_value, expanded, into);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut expanded = HashSet::new();
+        // Count the root and the placeholder as expanded so the new page opens in place
+        let mut old_expanded = state.expanded.clone();
+        old_expanded.insert(root);
+        old_expanded.insert(replace);
+        remap(root, &new_root, &old_expanded, &mut expanded);
+        let sort = state.sort;
+        *state = TreeState::new(new_root.into());
+        state.sort = sort;
+        state.expanded = expanded;
+        state.rebuild_visible_items();
+        state.list_state.get_mut().select(Some(index));
+        true
+    }
+
     fn get_selected_metadata_value_string(&self) -> Option<String> {
         let state = self.meta_tree_state.as_ref()?;
         let index = state.list_state.borrow().selected()?;
@@ -1172,8 +7501,22 @@ impl App {
         state.list_state.borrow().selected().is_some()
     }
 
-    fn parse_edit_draft(&self) -> Value {
+    /// The declared type of the selected metadata value, if it's a top-level entry
+    fn selected_metadata_type(&self) -> Option<&MetadataType> {
+        let state = self.meta_tree_state.as_ref()?;
+        let index = state.list_state.borrow().selected()?;
+        let item = state.visible_items.get(index)?;
+        if item.depth != 0 {
+            return None;
+        }
+        self.metadata_types.get(&item.name)
+    }
+
+    fn parse_edit_draft(&self) -> Result<Value, Error> {
         let draft = self.edit_draft.trim();
+        if let Some(ty) = self.selected_metadata_type() {
+            return ty.parse(draft);
+        }
 
         // Keep as a string
         let force_string = (|| {
@@ -1183,34 +7526,113 @@ impl App {
             Some(matches!(&*item.info, Value::String(_)))
         })();
         if force_string == Some(true) {
-            return Value::String(draft.to_string());
-        }
-
-        // Try to parse as different types
-        if draft == "null" {
-            Value::Null
-        } else if draft == "true" {
-            Value::Bool(true)
-        } else if draft == "false" {
-            Value::Bool(false)
-        } else if let Ok(num) = draft.parse::<i64>() {
-            Value::Number(num.into())
-        } else if let Some(num) = draft
-            .parse::<f64>()
-            .ok()
-            .and_then(serde_json::Number::from_f64)
-        {
-            Value::Number(num)
-        } else {
-            // Treat as string
-            Value::String(draft.to_string())
+            return Ok(Value::String(draft.to_string()));
+        }
+        Ok(parse_value(draft))
+    }
+
+    fn render_palette(&self, f: &mut ratatui::Frame, area: Rect) {
+        let (name, _) = split_input(&self.edit_draft);
+        let commands = matching_commands(name);
+        let selected = self.palette_selected.min(commands.len().saturating_sub(1));
+
+        let width = 70.min(area.width);
+        let height = (commands.len() as u16 + 4).min(area.height);
+        let palette_area = Rect {
+            x: area.x + area.width.saturating_sub(width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 3,
+            width,
+            height,
+        };
+        f.render_widget(Clear, palette_area);
+
+        let mut text = Text::default();
+        text.push_line(vec![
+            ":".bold(),
+            self.edit_draft.clone().fg(self.theme.text),
+        ]);
+        text.push_line("");
+        if commands.is_empty() {
+            text.push_line("No matching commands".fg(self.theme.muted));
+        }
+        for (i, command) in commands.into_iter().enumerate() {
+            let name = match command.argument() {
+                Some(argument) => format!("{} {argument}", command.name()),
+                None => command.name().to_string(),
+            };
+            let key = command
+                .key()
+                .map(|key| format!(" ({key})"))
+                .unwrap_or_default();
+            let line = Line::from(vec![
+                format!("{name:<22}").fg(self.theme.accent),
+                command.description().fg(self.theme.text),
+                key.fg(self.theme.muted),
+            ]);
+            text.push_line(if i == selected { line.reversed() } else { line });
+        }
+
+        let palette = Paragraph::new(text).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(self.theme.accent))
+                .title("Commands"),
+        );
+        f.render_widget(palette, palette_area);
+    }
+
+    fn render_jump(&self, f: &mut ratatui::Frame, area: Rect) {
+        let matches = matching_names(&self.edit_draft, &self.jump_targets);
+        let selected = self.palette_selected.min(matches.len().saturating_sub(1));
+
+        let width = 80.min(area.width);
+        let height = (matches.len() as u16 + 4).clamp(5, 24).min(area.height);
+        let jump_area = Rect {
+            x: area.x + area.width.saturating_sub(width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 3,
+            width,
+            height,
+        };
+        f.render_widget(Clear, jump_area);
+
+        let mut text = Text::default();
+        text.push_line(vec![
+            "> ".bold(),
+            self.edit_draft.clone().fg(self.theme.text),
+        ]);
+        text.push_line("");
+        if matches.is_empty() {
+            text.push_line("No matching tensors".fg(self.theme.muted));
+        }
+        // Keep the selection on screen
+        let rows = height.saturating_sub(4).max(1) as usize;
+        let skip = selected.saturating_sub(rows - 1);
+        for (i, &index) in matches.iter().enumerate().skip(skip).take(rows) {
+            let line = Line::from(self.jump_targets[index].to_string().fg(self.theme.tensor));
+            text.push_line(if i == selected { line.reversed() } else { line });
         }
+
+        let jump = Paragraph::new(text).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(self.theme.accent))
+                .title(format!("Jump to Tensor ({})", matches.len())),
+        );
+        f.render_widget(jump, jump_area);
     }
 
     fn render_dialog(&self, f: &mut ratatui::Frame, area: Rect) {
         let Some(dialog_type) = &self.dialog_type else {
             return;
         };
+        if matches!(dialog_type, DialogType::Palette) {
+            self.render_palette(f, area);
+            return;
+        }
+        if matches!(dialog_type, DialogType::Jump) {
+            self.render_jump(f, area);
+            return;
+        }
 
         // Create a centered dialog
         let dialog_width = 60;
@@ -1232,31 +7654,171 @@ impl App {
         let mut text = Text::default();
         let (title, border_color) = match dialog_type {
             DialogType::Edit => {
-                text.push_line("Edit Value".bold().fg(Color::Yellow));
+                text.push_line("Edit Value".bold().fg(self.theme.accent));
                 text.push_line("");
+                if let Some(ty) = self.selected_metadata_type() {
+                    text.push_line(vec!["Type: ".bold(), ty.to_string().fg(self.theme.dtype)]);
+                }
                 text.push_line(vec![
                     "Value: ".bold(),
-                    self.edit_draft.clone().fg(Color::White),
+                    self.edit_draft.clone().fg(self.theme.text),
+                ]);
+                text.push_line("");
+                text.push_line("Enter: Confirm | Esc: Cancel".fg(self.theme.muted));
+                ("Metadata Editor", self.theme.accent)
+            }
+            DialogType::AddMetadata => {
+                text.push_line("Add Value".bold().fg(self.theme.accent));
+                text.push_line("");
+                text.push_line(vec![
+                    "Key = Value: ".bold(),
+                    self.edit_draft.clone().fg(self.theme.text),
                 ]);
                 text.push_line("");
-                text.push_line("Enter: Confirm | Esc: Cancel".fg(Color::Gray));
-                ("Metadata Editor", Color::Yellow)
+                text.push_line("Enter: Confirm (a.b = 1 nests) | Esc: Cancel".fg(self.theme.muted));
+                ("Metadata Editor", self.theme.accent)
             }
             DialogType::Delete => {
-                text.push_line("Delete Value".bold().fg(Color::Red));
+                text.push_line("Delete Value".bold().fg(self.theme.error));
+                text.push_line("");
+                text.push_line("Are you sure you want to delete this value?".fg(self.theme.text));
+                text.push_line("");
+                text.push_line("Enter: Confirm | Esc: Cancel".fg(self.theme.muted));
+                ("Metadata Editor", self.theme.accent)
+            }
+            DialogType::Open => {
+                text.push_line("Open File".bold().fg(self.theme.accent));
+                text.push_line("");
+                text.push_line(vec![
+                    "Path: ".bold(),
+                    self.edit_draft.clone().fg(self.theme.text),
+                ]);
+                text.push_line("");
+                text.push_line("Tab: Complete | Enter: Open | Esc: Cancel".fg(self.theme.muted));
+                ("Open", self.theme.accent)
+            }
+            DialogType::Range => {
+                text.push_line("Zoom Histogram".bold().fg(self.theme.accent));
+                text.push_line("");
+                text.push_line(vec![
+                    "Range: ".bold(),
+                    self.edit_draft.clone().fg(self.theme.text),
+                ]);
+                text.push_line("");
+                text.push_line(
+                    "e.g. -0.1:0.1 | Enter: Zoom (empty for full range) | Esc: Cancel"
+                        .fg(self.theme.muted),
+                );
+                ("Range", self.theme.accent)
+            }
+            DialogType::Slice => {
+                text.push_line("Analyze Slice".bold().fg(self.theme.accent));
+                text.push_line("");
+                text.push_line(vec![
+                    "Indices: ".bold(),
+                    self.edit_draft.clone().fg(self.theme.text),
+                ]);
+                text.push_line("");
+                text.push_line(
+                    "e.g. 3, : or :, 10:20 | Enter: Analyze (empty for all) | Esc: Cancel"
+                        .fg(self.theme.muted),
+                );
+                ("Slice", self.theme.accent)
+            }
+            DialogType::Export => {
+                text.push_line("Export Tensor".bold().fg(self.theme.accent));
+                text.push_line("");
+                text.push_line(vec![
+                    "Path: ".bold(),
+                    self.edit_draft.clone().fg(self.theme.text),
+                ]);
+                text.push_line("");
+                text.push_line(
+                    "Enter: Confirm (.npy/.raw/.csv) | Esc: Cancel".fg(self.theme.muted),
+                );
+                ("Export", self.theme.accent)
+            }
+            DialogType::Rename => {
+                text.push_line("Rename".bold().fg(self.theme.accent));
+                text.push_line("");
+                text.push_line(vec![
+                    "Name: ".bold(),
+                    self.edit_draft.clone().fg(self.theme.text),
+                ]);
+                text.push_line("");
+                text.push_line("Enter: Confirm | Esc: Cancel".fg(self.theme.muted));
+                ("Rename", self.theme.accent)
+            }
+            DialogType::DeleteTensors(names) => {
+                text.push_line("Delete Tensors".bold().fg(self.theme.error));
+                text.push_line("");
+                let message = match names.as_slice() {
+                    [name] => format!("Delete {name} and rewrite the file?"),
+                    names => format!("Delete {} tensors and rewrite the file?", names.len()),
+                };
+                text.push_line(message.fg(self.theme.text));
+                text.push_line("");
+                text.push_line("Enter: Confirm | Esc: Cancel".fg(self.theme.muted));
+                ("Delete", self.theme.error)
+            }
+            DialogType::SaveAs => {
+                text.push_line(vec![
+                    "Path: ".bold(),
+                    self.edit_draft.clone().fg(self.theme.text),
+                ]);
+                text.push_line("");
+                let scope = if self.save_all {
+                    "all tensors"
+                } else {
+                    "selection"
+                };
+                text.push_line(vec![
+                    "Convert: ".bold(),
+                    scope.fg(self.theme.text),
+                    " to ".into(),
+                    SAVE_TYPES[self.save_type].to_string().fg(self.theme.dtype),
+                ]);
+                text.push_line("");
+                text.push_line(
+                    "Enter: Confirm | Tab: Type | Shift+Tab: Scope | Esc: Cancel"
+                        .fg(self.theme.muted),
+                );
+                ("Save As", self.theme.accent)
+            }
+            DialogType::Saving => {
+                text.push_line("Saving".bold().fg(self.theme.accent));
                 text.push_line("");
-                text.push_line("Are you sure you want to delete this value?".fg(Color::White));
+                if let Some(job) = &self.save_job {
+                    text.push_line(
+                        format!(
+                            "Tensor {}/{} ({}%)",
+                            job.done.load(Relaxed),
+                            job.total,
+                            job.progress.load(Relaxed),
+                        )
+                        .fg(self.theme.text),
+                    );
+                }
+                text.push_line("");
+                text.push_line("Esc: Cancel".fg(self.theme.muted));
+                ("Save As", self.theme.accent)
+            }
+            DialogType::Palette | DialogType::Jump => unreachable!(),
+            DialogType::Notice(msg) => {
+                text.push_line("Done".bold().fg(self.theme.success));
+                text.push_line("");
+                text.push_line(msg.clone().fg(self.theme.text));
                 text.push_line("");
-                text.push_line("Enter: Confirm | Esc: Cancel".fg(Color::Gray));
-                ("Metadata Editor", Color::Yellow)
+                text.push_line("Enter/Esc: Close".fg(self.theme.muted));
+                ("Notice", self.theme.success)
             }
             DialogType::Error(err) => {
-                text.push_line("Error".bold().fg(Color::Red));
+                text.push_line("Error".bold().fg(self.theme.error));
                 text.push_line("");
-                text.push_line(err.clone().fg(Color::White));
+                text.push_line(err.clone().fg(self.theme.text));
                 text.push_line("");
-                text.push_line("Enter/Esc: Close".fg(Color::Gray));
-                ("Error", Color::Red)
+                text.push_line("Enter/Esc: Close".fg(self.theme.muted));
+                ("Error", self.theme.error)
             }
         };
 
@@ -1267,13 +7829,368 @@ impl App {
                     .border_style(Style::default().fg(border_color))
                     .title(title),
             )
-            .style(Style::default().fg(Color::White))
+            .style(Style::default().fg(self.theme.text))
             .wrap(Wrap { trim: false });
 
         f.render_widget(dialog, dialog_area);
     }
 }
 
+/// The closest ASCII character to a cell's symbol, keeping one column per cell
+fn ascii_glyph(symbol: &str) -> char {
+    let Some(c) = symbol.chars().next() else {
+        return ' ';
+    };
+    match c {
+        '─' | '━' | '═' | '┄' | '┅' | '┈' | '┉' | '╌' | '╍' | '—' | '–' => {
+            '-'
+        }
+        '│' | '┃' | '║' | '┆' | '┇' | '┊' | '┋' | '╎' | '╏' | '‖' | '▌' | '▐' => {
+            '|'
+        }
+        '\u{2500}'..='\u{257f}' => '+',
+        '▒' | '▓' => '=',
+        '░' => ':',
+        '\u{2580}'..='\u{259f}' => '#',
+        '\u{2800}' => ' ',
+        '\u{2801}'..='\u{28ff}' => '*',
+        '▶' | '►' | '→' => '>',
+        '◀' | '◄' | '←' => '<',
+        '▼' | '↓' => 'v',
+        '▲' | '↑' => '^',
+        '📄' => '-',
+        '🔄' => '~',
+        '⚠' => '!',
+        '◆' | '★' | '•' => '*',
+        '·' | '…' => '.',
+        '×' => 'x',
+        '₁' => '1',
+        '₂' | '²' => '2',
+        'ᵀ' => 'T',
+        'Δ' => 'd',
+        'σ' => 's',
+        'ε' => 'e',
+        '√' => 'r',
+        _ => '?',
+    }
+}
+
+/// Replaces the middle of `name` with `…` so it fits in `width` columns, keeping both ends
+/// since they tell apart names like `layers.3.self_attn.q_proj.weight`
+fn truncate_middle(name: &str, width: usize) -> String {
+    let width = width.max(MIN_NAME_WIDTH);
+    let chars: Vec<char> = name.chars().collect();
+    if chars.len() <= width {
+        return name.to_string();
+    }
+    let head = (width - 1) / 2;
+    let tail = width - 1 - head;
+    let mut short: String = chars[..head].iter().collect();
+    short.push('…');
+    short.extend(&chars[chars.len() - tail..]);
+    short
+}
+
+/// Like [`glob_regex`], but with a capture group for each `*`, so the layer number standing in
+/// for one can be read back out
+fn layer_regex(pattern: &str) -> Result<Regex, Error> {
+    let mut regex = String::from("^");
+    for c in pattern.chars() {
+        match c {
+            '*' => regex.push_str("(.*)"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Ok(Regex::new(&regex)?)
+}
+
+/// Short enough for the tree, without turning small or huge values into zeros
+fn format_scalar(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{value}")
+    } else if value != 0.0 && !(1e-4..1e9).contains(&value.abs()) {
+        format!("{value:.4e}")
+    } else {
+        let text = format!("{value:.6}");
+        text.trim_end_matches('0').to_string()
+    }
+}
+
+fn format_duration(time: Duration) -> String {
+    if time < Duration::from_secs(1) {
+        format!("{} ms", time.as_millis())
+    } else {
+        format!("{:.1} s", time.as_secs_f64())
+    }
+}
+
+fn push_stats_lines(text: &mut Text, stats: &Stats, theme: &Theme) {
+    text.push_line(vec![
+        "Mean: ".bold(),
+        format!("{:.4}", stats.mean).into(),
+        "  Std: ".bold(),
+        format!("{:.4}", stats.std).into(),
+    ]);
+    text.push_line(vec![
+        "Min: ".bold(),
+        format!("{:.4}", stats.min).into(),
+        "  Max: ".bold(),
+        format!("{:.4}", stats.max).into(),
+    ]);
+    text.push_line(vec![
+        "L1: ".bold(),
+        format!("{:.4}", stats.l1_norm).into(),
+        "  L2: ".bold(),
+        format!("{:.4}", stats.l2_norm).into(),
+    ]);
+    let nonfinite = |count: usize| {
+        if count > 0 {
+            count.to_string().fg(theme.error)
+        } else {
+            count.to_string().into()
+        }
+    };
+    text.push_line(vec![
+        "Zeros: ".bold(),
+        format!("{:.2}%", stats.zero_fraction * 100.0).into(),
+        "  NaN: ".bold(),
+        nonfinite(stats.nan_count),
+        "  Inf: ".bold(),
+        nonfinite(stats.inf_count),
+    ]);
+    let tails = if stats.is_heavy_tailed() {
+        theme.warning
+    } else {
+        theme.text
+    };
+    text.push_line(vec![
+        "Skew: ".bold(),
+        format!("{:.3}", stats.skewness).into(),
+        "  Kurtosis: ".bold(),
+        format!("{:.3}", stats.excess_kurtosis).fg(tails),
+        "  QQ: ".bold(),
+        format!("{:.3}", stats.qq_deviation).fg(tails),
+    ]);
+}
+
+/// Lays out the children of `module` in `area`, largest first, with the children of those big
+/// enough drawn inside them under a row for their name
+fn layout_treemap(module: &ModuleInfo, area: Rect, depth: usize, out: &mut Vec<TreemapCell>) {
+    let mut children: Vec<_> = module
+        .children()
+        .iter()
+        .filter(|(_, child)| child.total_bytes > 0)
+        .collect();
+    children.sort_by_key(|(_, child)| Reverse(child.total_bytes));
+    let weights: Vec<_> = children
+        .iter()
+        .map(|(_, child)| child.total_bytes)
+        .collect();
+    let mut areas = Vec::new();
+    split_treemap(&weights, area, 0, &mut areas);
+    for (i, area) in areas {
+        let (key, child) = children[i];
+        out.push(TreemapCell {
+            area,
+            key: key.clone(),
+            full_name: child.full_name.clone(),
+            bytes: child.total_bytes,
+        });
+        if depth + 1 < TREEMAP_DEPTH && area.width >= 8 && area.height >= 4 {
+            let inner = Rect {
+                y: area.y + 1,
+                height: area.height - 1,
+                ..area
+            };
+            layout_treemap(child, inner, depth + 1, out);
+        }
+    }
+}
+
+/// Divides `area` among `weights`, which are sorted largest first, by cutting it in two along
+/// its longer side with about half the weight on each side, so the pieces stay roughly square.
+/// Each piece is pushed with its weight's index, offset by `first`.
+fn split_treemap(weights: &[u64], area: Rect, first: usize, out: &mut Vec<(usize, Rect)>) {
+    if weights.is_empty() || area.is_empty() {
+        return;
+    }
+    if weights.len() == 1 {
+        out.push((first, area));
+        return;
+    }
+    let total = weights.iter().sum::<u64>().max(1);
+    let mut split = 0;
+    let mut half = 0;
+    while split < weights.len() - 1 && half * 2 < total {
+        half += weights[split];
+        split += 1;
+    }
+    let share = half as f64 / total as f64;
+    // Cells are about twice as tall as they are wide
+    let (a, b) = if area.width >= area.height * 2 {
+        let width = (area.width as f64 * share).round() as u16;
+        (
+            Rect { width, ..area },
+            Rect {
+                x: area.x + width,
+                width: area.width - width,
+                ..area
+            },
+        )
+    } else {
+        let height = (area.height as f64 * share).round() as u16;
+        (
+            Rect { height, ..area },
+            Rect {
+                y: area.y + height,
+                height: area.height - height,
+                ..area
+            },
+        )
+    };
+    split_treemap(&weights[..split], a, first, out);
+    split_treemap(&weights[split..], b, first + split, out);
+}
+
+fn heatmap_color(x: f32, max_abs: f32, theme: &Theme) -> Color {
+    if !x.is_finite() {
+        return theme.warning;
+    }
+    let t = if max_abs > 0.0 { x / max_abs } else { 0.0 };
+    if theme.light {
+        // Fade from gray rather than white, which would vanish on a light background
+        let fade = 90 - (t.abs() * 90.0) as u8;
+        let strong = 90 + (t.abs() * 130.0) as u8;
+        if t >= 0.0 {
+            Color::Rgb(strong, fade, fade)
+        } else {
+            Color::Rgb(fade, fade, strong)
+        }
+    } else {
+        let fade = 255 - (t.abs() * 200.0) as u8;
+        if t >= 0.0 {
+            Color::Rgb(255, fade, fade)
+        } else {
+            Color::Rgb(fade, fade, 255)
+        }
+    }
+}
+
+/// Reads a histogram range written as `min:max`, `min..max`, or `min max`
+fn parse_range(text: &str) -> Result<(f32, f32), Error> {
+    let text = text.trim();
+    let (min, max) = text
+        .split_once("..")
+        .or_else(|| text.split_once(':'))
+        .or_else(|| text.split_once(char::is_whitespace))
+        .ok_or_else(|| anyhow!("expected a range like -0.1:0.1, not {text:?}"))?;
+    let bound = |bound: &str| {
+        bound
+            .trim()
+            .parse::<f32>()
+            .map_err(|_| anyhow!("{bound:?} is not a number"))
+    };
+    let (min, max) = (bound(min)?, bound(max)?);
+    if !min.is_finite() || !max.is_finite() || min >= max {
+        bail!("the range {min} to {max} is empty");
+    }
+    Ok((min, max))
+}
+
+/// Reads a drafted metadata value as null, a bool, a number, a JSON object or array, or
+/// failing those, a string
+pub fn parse_value(draft: &str) -> Value {
+    if draft == "null" {
+        Value::Null
+    } else if draft == "true" {
+        Value::Bool(true)
+    } else if draft == "false" {
+        Value::Bool(false)
+    } else if let Ok(num) = draft.parse::<i64>() {
+        Value::Number(num.into())
+    } else if let Some(num) = draft
+        .parse::<f64>()
+        .ok()
+        .and_then(serde_json::Number::from_f64)
+    {
+        Value::Number(num)
+    } else if draft.starts_with(['{', '[']) {
+        serde_json::from_str(draft).unwrap_or_else(|_| Value::String(draft.to_string()))
+    } else {
+        // Treat as string
+        Value::String(draft.to_string())
+    }
+}
+
+/// Sets `root.a.b.c` for the key `a.b.c`, creating objects along the way. Existing entries are
+/// left for the edit dialog rather than overwritten.
+pub fn insert_metadata_path(root: &mut Value, path: &str, value: Value) -> Result<(), Error> {
+    if path.is_empty() || path.split('.').any(str::is_empty) {
+        bail!("{path:?} is not a valid key");
+    }
+    let mut current = root;
+    let mut parts = path.split('.').peekable();
+    while let Some(part) = parts.next() {
+        let Value::Object(map) = current else {
+            bail!("can't add {path}: {part:?} is inside a value that isn't an object");
+        };
+        if parts.peek().is_none() {
+            if map.contains_key(part) {
+                bail!("{path} already exists, use e to edit it");
+            }
+            map.insert(part.to_string(), value);
+            return Ok(());
+        }
+        current = map
+            .entry(part)
+            .or_insert_with(|| Value::Object(Default::default()));
+    }
+    Ok(())
+}
+
+/// Extends the last component of a drafted path as far as every matching entry agrees, adding
+/// a separator once it names a single directory
+fn complete_path(draft: &str) -> String {
+    let (dir, prefix) = match draft.rfind(std::path::is_separator) {
+        Some(at) => (&draft[..=at], &draft[at + 1..]),
+        None => ("", draft),
+    };
+    let Ok(entries) = std::fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return draft.to_string();
+    };
+    let matches: Vec<(String, bool)> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let is_dir = entry.path().is_dir();
+            name.starts_with(prefix).then_some((name, is_dir))
+        })
+        .collect();
+    let Some((first, _)) = matches.first() else {
+        return draft.to_string();
+    };
+    let common = matches.iter().fold(first.as_str(), |common, (name, _)| {
+        let len = common
+            .char_indices()
+            .zip(name.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((at, a), _)| at + a.len_utf8());
+        &common[..len]
+    });
+    match matches.as_slice() {
+        [(name, true)] => format!("{dir}{name}{}", std::path::MAIN_SEPARATOR),
+        _ => format!("{dir}{common}"),
+    }
+}
+
+/// Whether a metadata value is an embedded image, like the sample thumbnails LoRA trainers save
+fn is_image(value: &Value) -> bool {
+    matches!(value, Value::String(text) if text.starts_with("data:image/"))
+}
+
 fn clone_with_replacement(value: &Value, replace: &Value, with: Option<&Value>) -> Option<Value> {
     if (value as *const Value) == (replace as *const Value) {
         return with.cloned();
@@ -0,0 +1,83 @@
+use anyhow::Error;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use checkpoint_core::storage::is_object_url;
+
+/// Tensors pinned with `b`, saved for each file in `~/.config/checkpointui/bookmarks.json`
+#[derive(Default)]
+pub struct Bookmarks {
+    path: Option<PathBuf>,
+    /// Bookmarked names in the order they were pinned, by absolute file path
+    files: BTreeMap<String, Vec<String>>,
+}
+
+impl Bookmarks {
+    /// Reads the bookmarks, treating a missing or unreadable file as empty
+    pub fn load() -> Self {
+        let Some(path) = Config::default_path().map(|path| path.with_file_name("bookmarks.json"))
+        else {
+            return Bookmarks::default();
+        };
+        let files = fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        Bookmarks {
+            path: Some(path),
+            files,
+        }
+    }
+
+    /// The same file opened through a different relative path shares its bookmarks
+    fn key(file: &Path) -> String {
+        match file.to_str() {
+            Some(url) if is_object_url(url) => url.to_string(),
+            _ => std::path::absolute(file)
+                .unwrap_or_else(|_| file.to_path_buf())
+                .display()
+                .to_string(),
+        }
+    }
+
+    pub fn get(&self, file: &Path) -> &[String] {
+        self.files
+            .get(&Self::key(file))
+            .map_or(&[], |names| names.as_slice())
+    }
+
+    /// Pins `name`, or unpins it if it was already pinned, and saves. Returns whether it is
+    /// pinned now.
+    pub fn toggle(&mut self, file: &Path, name: &str) -> Result<bool, Error> {
+        let key = Self::key(file);
+        let names = self.files.entry(key.clone()).or_default();
+        let pinned = match names.iter().position(|other| other == name) {
+            Some(index) => {
+                names.remove(index);
+                false
+            }
+            None => {
+                names.push(name.to_string());
+                true
+            }
+        };
+        if names.is_empty() {
+            self.files.remove(&key);
+        }
+        self.save()?;
+        Ok(pinned)
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(&self.files)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use checkpoint_core::analysis::{Histogram, Spectrum, Stats};
+use checkpoint_core::model::TensorInfo;
+
+/// Oldest entries are evicted on startup once the cache grows past this
+const CACHE_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// How much of each end of the file goes into its fingerprint
+const FINGERPRINT_BYTES: u64 = 1024 * 1024;
+
+/// Analysis results saved in `~/.cache/checkpointui`, so reopening a checkpoint is instant
+pub struct AnalysisCache {
+    dir: PathBuf,
+    file: Option<u64>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct CachedAnalysis {
+    pub stats: Option<Stats>,
+    pub histogram: Option<Histogram>,
+    pub spectrum: Option<Spectrum>,
+}
+
+pub struct CacheEntry(PathBuf);
+
+impl AnalysisCache {
+    pub fn open() -> Option<Self> {
+        let dir = match std::env::var_os("XDG_CACHE_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+        }
+        .join("checkpointui");
+        fs::create_dir_all(&dir).ok()?;
+        let cache = AnalysisCache { dir, file: None };
+        let _ = cache.prune();
+        Some(cache)
+    }
+
+    /// Identifies the file by its contents, so edits invalidate old results
+    pub fn set_file(&mut self, path: &Path) {
+        self.file = fingerprint(path).ok();
+    }
+
+    pub fn entry(
+        &self,
+        name: &str,
+        tensor: &TensorInfo,
+        max_bin_count: usize,
+    ) -> Option<CacheEntry> {
+        let mut hasher = DefaultHasher::new();
+        self.file?.hash(&mut hasher);
+        name.hash(&mut hasher);
+        tensor.ty.to_string().hash(&mut hasher);
+        tensor.shape.hash(&mut hasher);
+        tensor.offset.hash(&mut hasher);
+        max_bin_count.hash(&mut hasher);
+        let key = hasher.finish();
+        Some(CacheEntry(self.dir.join(format!("{key:016x}.json"))))
+    }
+
+    fn prune(&self) -> Result<(), Error> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            entries.push((metadata.modified()?, metadata.len(), entry.path()));
+        }
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort();
+        for (_, len, path) in entries {
+            if total <= CACHE_MAX_BYTES {
+                break;
+            }
+            fs::remove_file(path)?;
+            total -= len;
+        }
+        Ok(())
+    }
+}
+
+impl CacheEntry {
+    /// Any unreadable or outdated entry is treated as a miss
+    pub fn load(&self) -> Option<CachedAnalysis> {
+        serde_json::from_slice(&fs::read(&self.0).ok()?).ok()
+    }
+
+    pub fn store(&self, analysis: &CachedAnalysis) -> Result<(), Error> {
+        fs::write(&self.0, serde_json::to_vec(analysis)?)?;
+        Ok(())
+    }
+}
+
+/// Hashes the length and both ends of the file, which covers the header without reading
+/// every tensor
+fn fingerprint(path: &Path) -> Result<u64, Error> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut hasher = DefaultHasher::new();
+    len.hash(&mut hasher);
+    let mut buf = Vec::new();
+    (&mut file).take(FINGERPRINT_BYTES).read_to_end(&mut buf)?;
+    file.seek(SeekFrom::Start(len.saturating_sub(FINGERPRINT_BYTES)))?;
+    file.read_to_end(&mut buf)?;
+    buf.hash(&mut hasher);
+    Ok(hasher.finish())
+}
@@ -0,0 +1,265 @@
+use anyhow::{Error, anyhow, bail};
+use ratatui::style::Color;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml_edit::{Array, DocumentMut, value};
+
+use crate::palette::Command;
+
+/// Settings read from `~/.config/checkpointui/config.toml`, all optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub module_delim: Option<char>,
+    /// Any form accepted by `--module-split`, used when `module_delim` isn't set
+    pub module_split: Option<String>,
+    /// Tensors with more elements than this only get a histogram on request
+    pub histogram_size_limit: Option<u64>,
+    /// Tensors with more elements than this only get an SVD on request
+    pub spectrum_size_limit: Option<u64>,
+    pub bins: Option<usize>,
+    /// Sample values for histogram ranges from this seed, so reruns show identical ranges
+    pub sample_seed: Option<u64>,
+    /// Tensors with at most this many elements get exact quantiles instead of a sample
+    pub exact_quantile_limit: Option<usize>,
+    /// Keep `<file>.bak` when editing a checkpoint
+    pub backup: Option<bool>,
+    /// Merge chains of modules with one child each, as `U` toggles
+    pub flatten: Option<bool>,
+    /// Leave this many levels of the module tree unmerged when flattening
+    pub flatten_depth: Option<usize>,
+    /// Relative widths of the tree, info, and analysis panels in percent, each at least 10,
+    /// saved by `Ctrl-←/→`
+    pub panel_widths: Option<[u16; 3]>,
+    /// One of [`Theme::NAMES`]
+    pub theme: Option<String>,
+    /// Color names or `#rrggbb` overriding fields of the theme
+    pub colors: HashMap<String, String>,
+    /// Command names from the `:` palette mapped to a single key
+    pub keys: HashMap<String, char>,
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(dir.join("checkpointui").join("config.toml"))
+    }
+
+    /// Reads the config file, which is fine to be missing unless it was given explicitly
+    pub fn load(path: Option<&Path>) -> Result<Self, Error> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match Config::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !required => {
+                return Ok(Config::default());
+            }
+            Err(err) => bail!("could not read {}: {err}", path.display()),
+        };
+        toml::from_str(&text).map_err(|err| anyhow!("invalid config {}: {err}", path.display()))
+    }
+
+    /// Writes `panel_widths` into the config file, leaving the rest of it as it was
+    pub fn save_panel_widths(path: Option<&Path>, widths: [u16; 3]) -> Result<(), Error> {
+        let Some(path) = path.map(Path::to_path_buf).or_else(Config::default_path) else {
+            return Ok(());
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => bail!("could not read {}: {err}", path.display()),
+        };
+        let mut document: DocumentMut = text
+            .parse()
+            .map_err(|err| anyhow!("invalid config {}: {err}", path.display()))?;
+        document["panel_widths"] = value(Array::from_iter(widths.map(i64::from)));
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, document.to_string())?;
+        Ok(())
+    }
+
+    pub fn theme(&self) -> Result<Theme, Error> {
+        let mut theme = match &self.theme {
+            Some(name) => Theme::builtin(name).ok_or_else(|| {
+                anyhow!(
+                    "unknown theme {name:?} (expected one of {})",
+                    Theme::NAMES.join(", ")
+                )
+            })?,
+            None => Theme::default(),
+        };
+        for (name, value) in &self.colors {
+            let color = Color::from_str(value)
+                .map_err(|_| anyhow!("invalid color {value:?} for {name}"))?;
+            *theme
+                .get_mut(name)
+                .ok_or_else(|| anyhow!("unknown color {name:?}"))? = color;
+        }
+        Ok(theme)
+    }
+
+    pub fn keys(&self) -> Result<HashMap<char, Command>, Error> {
+        let mut keys = HashMap::new();
+        for (name, &key) in &self.keys {
+            let command = Command::ALL
+                .into_iter()
+                .find(|command| command.name() == name)
+                .ok_or_else(|| anyhow!("unknown command {name:?} in [keys]"))?;
+            if let Some(other) = keys.insert(key, command) {
+                bail!("{key:?} is bound to both {} and {name}", other.name());
+            }
+        }
+        Ok(keys)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    /// Whether the terminal background is light, which decides how data is shaded
+    pub light: bool,
+    pub text: Color,
+    pub muted: Color,
+    /// Titles, progress messages, and other things which should stand out
+    pub accent: Color,
+    pub error: Color,
+    pub success: Color,
+    /// Background of the selected row
+    pub selection: Color,
+    pub chart: Color,
+    /// Numbers and booleans in the metadata tree
+    pub literal: Color,
+    pub module: Color,
+    pub tensor: Color,
+    pub shape: Color,
+    pub dtype: Color,
+    pub count: Color,
+    pub bytesize: Color,
+    pub warning: Color,
+    pub border: Color,
+    pub border_secondary: Color,
+    pub border_selected: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::DARK
+    }
+}
+
+impl Theme {
+    pub const DARK: Theme = Theme {
+        light: false,
+        text: Color::White,
+        muted: Color::Gray,
+        accent: Color::Yellow,
+        error: Color::Red,
+        success: Color::Green,
+        selection: Color::Blue,
+        chart: Color::Blue,
+        literal: Color::Blue,
+        module: Color::Blue,
+        tensor: Color::Cyan,
+        shape: Color::White,
+        dtype: Color::Yellow,
+        count: Color::White,
+        bytesize: Color::Magenta,
+        warning: Color::Red,
+        border: Color::White,
+        border_secondary: Color::White,
+        border_selected: Color::Yellow,
+    };
+
+    pub const LIGHT: Theme = Theme {
+        light: true,
+        text: Color::Black,
+        muted: Color::Rgb(100, 100, 100),
+        accent: Color::Rgb(150, 90, 0),
+        error: Color::Rgb(190, 0, 0),
+        success: Color::Rgb(0, 130, 0),
+        selection: Color::Rgb(190, 210, 255),
+        chart: Color::Rgb(30, 80, 200),
+        literal: Color::Rgb(30, 80, 200),
+        module: Color::Rgb(30, 80, 200),
+        tensor: Color::Rgb(0, 120, 130),
+        shape: Color::Black,
+        dtype: Color::Rgb(150, 90, 0),
+        count: Color::Black,
+        bytesize: Color::Rgb(150, 0, 150),
+        warning: Color::Rgb(190, 0, 0),
+        border: Color::Rgb(100, 100, 100),
+        border_secondary: Color::Rgb(160, 160, 160),
+        border_selected: Color::Rgb(150, 90, 0),
+    };
+
+    /// The dark variant of Ethan Schoonover's Solarized palette
+    pub const SOLARIZED: Theme = Theme {
+        light: false,
+        text: Color::Rgb(0x93, 0xa1, 0xa1),
+        muted: Color::Rgb(0x58, 0x6e, 0x75),
+        accent: Color::Rgb(0xb5, 0x89, 0x00),
+        error: Color::Rgb(0xdc, 0x32, 0x2f),
+        success: Color::Rgb(0x85, 0x99, 0x00),
+        selection: Color::Rgb(0x07, 0x36, 0x42),
+        chart: Color::Rgb(0x26, 0x8b, 0xd2),
+        literal: Color::Rgb(0x6c, 0x71, 0xc4),
+        module: Color::Rgb(0x26, 0x8b, 0xd2),
+        tensor: Color::Rgb(0x2a, 0xa1, 0x98),
+        shape: Color::Rgb(0x83, 0x94, 0x96),
+        dtype: Color::Rgb(0xb5, 0x89, 0x00),
+        count: Color::Rgb(0x83, 0x94, 0x96),
+        bytesize: Color::Rgb(0xd3, 0x36, 0x82),
+        warning: Color::Rgb(0xcb, 0x4b, 0x16),
+        border: Color::Rgb(0x58, 0x6e, 0x75),
+        border_secondary: Color::Rgb(0x58, 0x6e, 0x75),
+        border_selected: Color::Rgb(0xb5, 0x89, 0x00),
+    };
+
+    pub const NAMES: [&str; 3] = ["dark", "light", "solarized"];
+
+    pub fn builtin(name: &str) -> Option<Theme> {
+        match name {
+            "dark" => Some(Theme::DARK),
+            "light" => Some(Theme::LIGHT),
+            "solarized" => Some(Theme::SOLARIZED),
+            _ => None,
+        }
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut Color> {
+        Some(match name {
+            "text" => &mut self.text,
+            "muted" => &mut self.muted,
+            "accent" => &mut self.accent,
+            "error" => &mut self.error,
+            "success" => &mut self.success,
+            "selection" => &mut self.selection,
+            "chart" => &mut self.chart,
+            "literal" => &mut self.literal,
+            "module" => &mut self.module,
+            "tensor" => &mut self.tensor,
+            "shape" => &mut self.shape,
+            "dtype" => &mut self.dtype,
+            "count" => &mut self.count,
+            "bytesize" => &mut self.bytesize,
+            "warning" => &mut self.warning,
+            "border" => &mut self.border,
+            "border_secondary" => &mut self.border_secondary,
+            "border_selected" => &mut self.border_selected,
+            _ => return None,
+        })
+    }
+}
@@ -0,0 +1,326 @@
+use anyhow::{Error, bail};
+use ggml_base::{GgmlTypeId, GgufFile};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::atomic::{AtomicU64, AtomicUsize};
+use std::sync::{Arc, Mutex, OnceLock};
+use weakref::{Own, Ref};
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+
+use checkpoint_core::gguf::{ggml_type, gguf_metadata};
+use checkpoint_core::model::{LE, ModuleSource, TensorInfo, TensorTy};
+use checkpoint_core::notify;
+use checkpoint_core::safetensors::new_header;
+use checkpoint_core::storage::FileStorage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Npy,
+    Raw,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("npy") => Ok(ExportFormat::Npy),
+            Some("raw" | "bin" | "f32") => Ok(ExportFormat::Raw),
+            Some("csv") => Ok(ExportFormat::Csv),
+            _ => bail!(
+                "could not infer export format of {} (expected .npy, .raw, or .csv)",
+                path.display()
+            ),
+        }
+    }
+}
+
+pub fn export_tensor(
+    source: &Mutex<dyn ModuleSource + Send>,
+    tensor: &TensorInfo,
+    path: &Path,
+) -> Result<(), Error> {
+    let progress = Own::new_box(AtomicU64::new(0));
+    write_tensor(source, tensor, path, progress.refer())
+}
+
+/// Exports `tensor` to the job's path in the background, reporting progress like a save
+pub fn start_export_tensor(
+    source: Arc<Mutex<dyn ModuleSource + Send>>,
+    tensor: TensorInfo,
+    job: Ref<SaveJob>,
+) {
+    notify::spawn(move || {
+        let Some(path) = job.inspect(|job| job.path.clone()) else {
+            return;
+        };
+        let result = write_tensor(&source, &tensor, &path, job.map(|job| &job.progress));
+        job.inspect(|job| {
+            job.done.store(1, Relaxed);
+            let _ = job.result.set(result.map_err(|err| err.to_string()));
+        });
+    });
+}
+
+fn write_tensor(
+    source: &Mutex<dyn ModuleSource + Send>,
+    tensor: &TensorInfo,
+    path: &Path,
+    progress: Ref<AtomicU64>,
+) -> Result<(), Error> {
+    let format = ExportFormat::from_path(path)?;
+    let data = source
+        .lock()
+        .unwrap()
+        .tensor_f32(tensor.clone(), progress)?;
+    // Anything already at `path` stays as it was until the export is complete
+    FileStorage::new(path.to_owned()).replace_with(|temp| {
+        let mut out = io::BufWriter::new(temp);
+        match format {
+            ExportFormat::Npy => write_npy(&mut out, &tensor.shape, &data)?,
+            ExportFormat::Raw => write_raw(&mut out, &data)?,
+            ExportFormat::Csv => write_csv(&mut out, &tensor.shape, &data)?,
+        }
+        out.flush()?;
+        Ok(())
+    })
+}
+
+/// Writes each tensor as `NAME.npy` in an uncompressed archive, the layout `numpy.load`
+/// expects of a `.npz` file
+pub fn export_npz(
+    source: &Mutex<dyn ModuleSource + Send>,
+    tensors: &[(String, TensorInfo)],
+    path: &Path,
+) -> Result<(), Error> {
+    let progress = Own::new_box(AtomicU64::new(0));
+    FileStorage::new(path.to_owned()).replace_with(|temp| {
+        let mut zip = ZipWriter::new(io::BufWriter::new(temp));
+        for (name, tensor) in tensors {
+            let data = {
+                let mut source = source.lock().unwrap();
+                source.tensor_f32(tensor.clone(), progress.refer())?
+            };
+            let options = SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Stored)
+                .large_file(data.len() * 4 >= u32::MAX as usize);
+            zip.start_file(format!("{name}.npy"), options)?;
+            write_npy(&mut zip, &tensor.shape, &data)?;
+        }
+        zip.finish()?.flush()?;
+        Ok(())
+    })
+}
+
+fn write_npy(out: &mut impl Write, shape: &[u64], data: &[f32]) -> Result<(), Error> {
+    let shape = match shape {
+        [] => "()".to_string(),
+        [n] => format!("({n},)"),
+        dims => {
+            let dims: Vec<_> = dims.iter().map(|d| d.to_string()).collect();
+            format!("({})", dims.join(", "))
+        }
+    };
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': {shape}, }}");
+    // magic (6) + version (2) + header length (2) + header must be a multiple of 64
+    let unpadded = 10 + header.len() + 1;
+    header.extend(std::iter::repeat_n(
+        ' ',
+        unpadded.next_multiple_of(64) - unpadded,
+    ));
+    header.push('\n');
+
+    out.write_all(b"\x93NUMPY\x01\x00")?;
+    out.write_all(&u16::try_from(header.len())?.to_le_bytes())?;
+    out.write_all(header.as_bytes())?;
+    write_raw(out, data)
+}
+
+fn write_raw(out: &mut impl Write, data: &[f32]) -> Result<(), Error> {
+    for x in data {
+        out.write_all(&x.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn write_csv(out: &mut impl Write, shape: &[u64], data: &[f32]) -> Result<(), Error> {
+    // the innermost dimension becomes columns, all others are flattened into rows
+    let columns = shape.last().copied().unwrap_or(1).max(1) as usize;
+    for row in data.chunks(columns) {
+        for (i, x) in row.iter().enumerate() {
+            if i > 0 {
+                out.write_all(b",")?;
+            }
+            write!(out, "{x}")?;
+        }
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// A copy of the checkpoint being written to a new safetensors or gguf file in the background
+#[derive(Default)]
+pub struct SaveJob {
+    pub path: PathBuf,
+    pub total: usize,
+    pub done: AtomicUsize,
+    /// Percent read of the tensor currently being written
+    pub progress: AtomicU64,
+    pub result: OnceLock<Result<(), String>>,
+}
+
+impl SaveJob {
+    pub fn new(path: PathBuf, total: usize) -> Self {
+        SaveJob {
+            path,
+            total,
+            ..Default::default()
+        }
+    }
+}
+
+/// Writes every tensor to a new file, converting the float tensors named in `convert`
+///
+/// The output format is chosen by extension: `.gguf` or anything else for safetensors.
+pub fn start_save_as(
+    source: Arc<Mutex<dyn ModuleSource + Send>>,
+    source_path: Option<PathBuf>,
+    tensors: Vec<(String, TensorInfo)>,
+    convert: HashSet<String>,
+    ty: TensorTy,
+    job: Ref<SaveJob>,
+) {
+    notify::spawn(move || {
+        let Some(path) = job.inspect(|job| job.path.clone()) else {
+            return;
+        };
+        let source_path = source_path.as_deref();
+        let result = save_as(&source, source_path, tensors, &convert, &ty, &path, job);
+        job.inspect(|job| {
+            let _ = job.result.set(result.map_err(|err| err.to_string()));
+        });
+    });
+}
+
+/// Refuses to write over the file being read from, which would truncate it while the
+/// conversion still needs its data
+fn check_not_source(path: &Path, source_path: Option<&Path>) -> Result<(), Error> {
+    let Some(source_path) = source_path else {
+        return Ok(());
+    };
+    match (fs::canonicalize(path), fs::canonicalize(source_path)) {
+        (Ok(path), Ok(source_path)) if path == source_path => bail!(
+            "can't save over {}, which is being read from; save to another path",
+            path.display()
+        ),
+        _ => Ok(()),
+    }
+}
+
+pub fn save_as(
+    source: &Mutex<dyn ModuleSource + Send>,
+    source_path: Option<&Path>,
+    tensors: Vec<(String, TensorInfo)>,
+    convert: &HashSet<String>,
+    ty: &TensorTy,
+    path: &Path,
+    job: Ref<SaveJob>,
+) -> Result<(), Error> {
+    check_not_source(path, source_path)?;
+    match ty {
+        TensorTy::Ggml(q) if !ggml_base::can_quantize(*q) => {
+            let name = ty.to_string().to_lowercase();
+            bail!("{name} quantization is not supported by this build")
+        }
+        _ => {}
+    }
+    // Anything already at `path` stays as it was until the new file is complete
+    FileStorage::new(path.to_owned())
+        .replace_with(|temp| write_converted(source, tensors, convert, ty, path, temp, job))
+}
+
+fn write_converted(
+    source: &Mutex<dyn ModuleSource + Send>,
+    mut tensors: Vec<(String, TensorInfo)>,
+    convert: &HashSet<String>,
+    ty: &TensorTy,
+    path: &Path,
+    temp: &mut fs::File,
+    job: Ref<SaveJob>,
+) -> Result<(), Error> {
+    let to_gguf = path.extension().is_some_and(|ext| ext == "gguf");
+
+    // Read tensors in file order
+    tensors.sort_by_key(|(_, tensor)| tensor.offset);
+    let targets: Vec<Option<TensorTy>> = tensors
+        .iter()
+        .map(|(name, tensor)| {
+            if !convert.contains(name) || !tensor.ty.is_float() {
+                return None;
+            }
+            match ty {
+                // Vectors and ragged rows stay at full precision
+                TensorTy::Ggml(q) if !can_quantize(*q, &tensor.shape) => Some(TensorTy::F32),
+                ty => Some(ty.clone()),
+            }
+        })
+        .collect();
+
+    let layout: Vec<_> = tensors
+        .iter()
+        .zip(&targets)
+        .map(|((name, tensor), target)| {
+            let ty = target.clone().unwrap_or_else(|| tensor.ty.clone());
+            (name.clone(), ty, tensor.shape.clone())
+        })
+        .collect();
+
+    let mut out = io::BufWriter::new(temp);
+    let alignment = if to_gguf {
+        let metadata = gguf_metadata(&mut *source.lock().unwrap())?;
+        let tensors = layout
+            .into_iter()
+            .map(|(name, ty, shape)| Ok((name, ggml_type(&ty)?, shape)))
+            .collect::<Result<_, Error>>()?;
+        let mut file = GgufFile::new(metadata, tensors)?;
+        file.write_header(&mut out)?;
+        file.alignment() as usize
+    } else {
+        let metadata = source.lock().unwrap().string_metadata()?;
+        out.write_all(&new_header(Some(metadata), &layout)?)?;
+        1
+    };
+
+    for ((name, tensor), target) in tensors.into_iter().zip(targets) {
+        let progress = job.map(|job| &job.progress);
+        let shape = tensor.shape.clone();
+        let bytes = {
+            let mut source = source.lock().unwrap();
+            match target {
+                Some(TensorTy::Ggml(q)) => {
+                    ggml_base::quantize(q, &shape, &source.tensor_f32(tensor, progress)?)?
+                }
+                Some(ty) => ty.write_f32::<LE>(&source.tensor_f32(tensor, progress)?)?,
+                None => source.tensor_data(tensor, progress)?,
+            }
+        };
+        out.write_all(&bytes)?;
+        // gguf tensors each start on an aligned offset
+        let padding = bytes.len().next_multiple_of(alignment) - bytes.len();
+        out.write_all(&vec![0; padding])?;
+        if job.inspect(|job| job.done.fetch_add(1, Relaxed)).is_none() {
+            bail!("cancelled while writing {name}");
+        }
+        notify::notify();
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Only matrices whose rows are a whole number of blocks can be quantized
+fn can_quantize(ty: GgmlTypeId, shape: &[u64]) -> bool {
+    shape.len() >= 2 && ggml_base::validate_shape(ty, shape).is_ok()
+}
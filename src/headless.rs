@@ -0,0 +1,484 @@
+use anyhow::{Error, anyhow, bail};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicU64;
+use std::thread::sleep;
+use std::time::Duration;
+use weakref::Own;
+
+use crate::app::{insert_metadata_path, parse_value};
+use crate::export::{export_npz, export_tensor};
+use checkpoint_core::analysis::{
+    HealthScan, ShapeMatch, Stats, TensorHealth, pair_metrics, start_health_scan,
+};
+use checkpoint_core::manifest::{ManifestJob, start_manifest};
+use checkpoint_core::model::{ModuleSource, PathSplit, TensorInfo};
+use checkpoint_core::registry::SourceRegistry;
+
+/// How one tensor was stored in a compared file
+#[derive(Serialize)]
+struct TensorSummary {
+    dtype: String,
+    shape: Vec<u64>,
+}
+
+impl From<&TensorInfo> for TensorSummary {
+    fn from(tensor: &TensorInfo) -> Self {
+        TensorSummary {
+            dtype: tensor.ty.to_string(),
+            shape: tensor.shape.clone(),
+        }
+    }
+}
+
+/// One line of `checkpointui diff`
+#[derive(Serialize)]
+struct TensorDiff {
+    name: String,
+    /// `None` when the tensor is missing from the first file
+    a: Option<TensorSummary>,
+    /// `None` when the tensor is missing from the second file
+    b: Option<TensorSummary>,
+    max_abs_diff: Option<f64>,
+    /// ‖b - a‖ / ‖a‖
+    relative_l2: Option<f64>,
+    /// Why the tensor counts as different, if it does
+    problem: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DiffReport {
+    a: String,
+    b: String,
+    threshold: f64,
+    different: usize,
+    tensors: Vec<TensorDiff>,
+}
+
+fn read_tensors(
+    source: &Mutex<dyn ModuleSource + Send>,
+    split: &PathSplit,
+) -> Result<BTreeMap<String, TensorInfo>, Error> {
+    Ok(source
+        .lock()
+        .unwrap()
+        .module(split)?
+        .tensors()
+        .into_iter()
+        .collect())
+}
+
+fn diff_tensor(
+    a: &Mutex<dyn ModuleSource + Send>,
+    b: &Mutex<dyn ModuleSource + Send>,
+    left: &TensorInfo,
+    right: &TensorInfo,
+    threshold: f64,
+) -> Result<(f64, f64, Option<String>), Error> {
+    let progress = Own::new_box(AtomicU64::new(0));
+    let x = a
+        .lock()
+        .unwrap()
+        .tensor_f32(left.clone(), progress.refer())?;
+    let y = b
+        .lock()
+        .unwrap()
+        .tensor_f32(right.clone(), progress.refer())?;
+    let metrics = pair_metrics(&x, y, ShapeMatch::Same, &right.shape);
+    // Two all-zero tensors are identical rather than infinitely far apart
+    let relative = if metrics.l2_distance == 0.0 {
+        0.0
+    } else {
+        metrics.relative_distance
+    };
+    let problem = if relative.is_nan() {
+        Some("values are not finite".to_string())
+    } else if relative > threshold {
+        Some(format!("relative L2 {relative:.3e} above {threshold:.0e}"))
+    } else {
+        None
+    };
+    Ok((metrics.max_abs_diff, relative, problem))
+}
+
+/// Compares every tensor of two checkpoints, printing a table or JSON, and returns whether
+/// any differ by more than `threshold` in relative L2 distance, or are missing or reshaped
+pub fn diff(
+    registry: &SourceRegistry,
+    format: Option<&str>,
+    split: &PathSplit,
+    paths: [&Path; 2],
+    threshold: f64,
+    json: bool,
+) -> Result<bool, Error> {
+    let a = registry.open(paths[0], format)?;
+    let b = registry.open(paths[1], format)?;
+    let left = read_tensors(&a, split)?;
+    let right = read_tensors(&b, split)?;
+
+    let mut names: Vec<&String> = left.keys().chain(right.keys()).collect();
+    names.sort();
+    names.dedup();
+    let mut tensors = Vec::new();
+    for name in names {
+        let mut diff = TensorDiff {
+            name: name.clone(),
+            a: left.get(name).map(TensorSummary::from),
+            b: right.get(name).map(TensorSummary::from),
+            max_abs_diff: None,
+            relative_l2: None,
+            problem: None,
+        };
+        diff.problem = match (left.get(name), right.get(name)) {
+            (None, _) => Some(format!("missing from {}", paths[0].display())),
+            (_, None) => Some(format!("missing from {}", paths[1].display())),
+            (Some(l), Some(r)) if l.shape != r.shape => {
+                Some(format!("shape {:?} vs {:?}", l.shape, r.shape))
+            }
+            (Some(l), Some(r)) => match diff_tensor(&a, &b, l, r, threshold) {
+                Ok((max_abs_diff, relative, problem)) => {
+                    diff.max_abs_diff = Some(max_abs_diff);
+                    diff.relative_l2 = Some(relative);
+                    problem
+                }
+                Err(err) => Some(format!("could not read: {err}")),
+            },
+        };
+        tensors.push(diff);
+    }
+
+    let report = DiffReport {
+        a: paths[0].display().to_string(),
+        b: paths[1].display().to_string(),
+        threshold,
+        different: tensors.iter().filter(|t| t.problem.is_some()).count(),
+        tensors,
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_diff(&report);
+    }
+    Ok(report.different > 0)
+}
+
+fn print_diff(report: &DiffReport) {
+    let width = report
+        .tensors
+        .iter()
+        .map(|t| t.name.len())
+        .max()
+        .unwrap_or(0);
+    let dtype = |summary: &Option<TensorSummary>| {
+        summary
+            .as_ref()
+            .map_or("-".to_string(), |s| s.dtype.clone())
+    };
+    for tensor in &report.tensors {
+        let (left, right) = (dtype(&tensor.a), dtype(&tensor.b));
+        let dtypes = if left == right {
+            left
+        } else {
+            format!("{left}→{right}")
+        };
+        let metric = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{v:.3e}"));
+        println!(
+            "{:width$}  {dtypes:12}  max |Δ| {:>10}  rel L2 {:>10}  {}",
+            tensor.name,
+            metric(tensor.max_abs_diff),
+            metric(tensor.relative_l2),
+            tensor.problem.as_deref().unwrap_or("ok"),
+        );
+    }
+    println!(
+        "{} of {} tensors differ between {} and {}",
+        report.different,
+        report.tensors.len(),
+        report.a,
+        report.b
+    );
+}
+
+/// A regex matching whole tensor names against a shell-style pattern, where `*` matches any
+/// run of characters and `?` any one character
+pub fn glob_regex(pattern: &str) -> Result<Regex, Error> {
+    let mut regex = String::from("^");
+    for c in pattern.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Ok(Regex::new(&regex)?)
+}
+
+/// Writes the tensors matching any of `patterns` to `out`: a single tensor to a `.npy`,
+/// `.raw`, or `.csv` file, several to a `.npz` archive or a directory of `.npy` files
+pub fn extract(
+    registry: &SourceRegistry,
+    format: Option<&str>,
+    split: &PathSplit,
+    path: &Path,
+    patterns: &[String],
+    out: &Path,
+) -> Result<(), Error> {
+    let source = registry.open(path, format)?;
+    let tensors = read_tensors(&source, split)?;
+    let mut selected = Vec::new();
+    for pattern in patterns {
+        let regex = glob_regex(pattern)?;
+        let before = selected.len();
+        selected.extend(
+            tensors
+                .iter()
+                .filter(|(name, _)| regex.is_match(name))
+                .map(|(name, tensor)| (name.clone(), tensor.clone())),
+        );
+        if selected.len() == before {
+            bail!("no tensor in {} matches {pattern:?}", path.display());
+        }
+    }
+    selected.sort_by(|a, b| a.0.cmp(&b.0));
+    selected.dedup_by(|a, b| a.0 == b.0);
+
+    if out.extension().is_some_and(|ext| ext == "npz") {
+        export_npz(&source, &selected, out)?;
+        println!("{}", out.display());
+    } else if let ([(_, tensor)], false) = (&selected[..], out.is_dir()) {
+        export_tensor(&source, tensor, out)?;
+        println!("{}", out.display());
+    } else {
+        fs::create_dir_all(out)?;
+        for (name, tensor) in &selected {
+            // Flax names use `/`, which can't be part of a file name
+            let file = out.join(format!("{}.npy", name.replace('/', ".")));
+            export_tensor(&source, tensor, &file)?;
+            println!("{}", file.display());
+        }
+    }
+    Ok(())
+}
+
+/// One line of `checkpointui stats`
+#[derive(Serialize)]
+struct TensorStats {
+    name: String,
+    #[serde(flatten)]
+    summary: TensorSummary,
+    /// `None` when the tensor couldn't be read as floats
+    stats: Option<Stats>,
+    health: String,
+}
+
+/// Prints statistics of every tensor whose name matches `filter`, computed the same way as
+/// the health scan, and returns whether any have NaN or Inf values or couldn't be read
+pub fn stats(
+    registry: &SourceRegistry,
+    format: Option<&str>,
+    split: &PathSplit,
+    path: &Path,
+    filter: Option<&Regex>,
+    json: bool,
+) -> Result<bool, Error> {
+    let source = registry.open(path, format)?;
+    let tensors: Vec<_> = read_tensors(&source, split)?
+        .into_iter()
+        .filter(|(name, _)| filter.is_none_or(|filter| filter.is_match(name)))
+        .collect();
+    if tensors.is_empty() {
+        bail!("no tensors in {} match the filter", path.display());
+    }
+
+    let scan = Own::new_box(HealthScan::new(tensors.len()));
+    start_health_scan(source, tensors.clone(), scan.refer());
+    while !scan.is_finished() {
+        sleep(Duration::from_millis(50));
+    }
+
+    let mut broken = false;
+    let mut rows = Vec::new();
+    for (name, tensor) in tensors {
+        let health = scan.health(&name).unwrap_or(TensorHealth::Ok);
+        broken |= matches!(
+            health,
+            TensorHealth::NonFinite { .. } | TensorHealth::Error(_)
+        );
+        rows.push(TensorStats {
+            stats: scan.stats(&name),
+            summary: TensorSummary::from(&tensor),
+            health: health.to_string(),
+            name,
+        });
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+    } else {
+        print_stats(&rows);
+    }
+    Ok(broken)
+}
+
+fn print_stats(rows: &[TensorStats]) {
+    let width = rows.iter().map(|row| row.name.len()).max().unwrap_or(0);
+    println!(
+        "{:width$}  {:8}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>8}  {:>6}  health",
+        "tensor", "dtype", "mean", "std", "min", "max", "L2 norm", "kurtosis", "NaN",
+    );
+    for row in rows {
+        let columns = match &row.stats {
+            Some(stats) => format!(
+                "{:>10.3e}  {:>10.3e}  {:>10.3e}  {:>10.3e}  {:>10.3e}  {:>8.2}  {:>6}",
+                stats.mean,
+                stats.std,
+                stats.min,
+                stats.max,
+                stats.l2_norm,
+                stats.excess_kurtosis,
+                stats.nan_count,
+            ),
+            None => format!(
+                "{:>10}  {0:>10}  {0:>10}  {0:>10}  {0:>10}  {0:>8}  {0:>6}",
+                "-"
+            ),
+        };
+        println!(
+            "{:width$}  {:8}  {columns}  {}",
+            row.name, row.summary.dtype, row.health
+        );
+    }
+}
+
+/// The entry named `key`, either a top-level key (which may itself contain dots, as in
+/// `general.name`) or a dotted path into nested objects
+fn metadata_entry<'a>(root: &'a mut Value, key: &str) -> Option<&'a mut Value> {
+    if root.get(key).is_some() {
+        return root.get_mut(key);
+    }
+    let mut current = root;
+    for part in key.split('.') {
+        current = current.as_object_mut()?.get_mut(part)?;
+    }
+    Some(current)
+}
+
+/// Prints every integrity problem in the file's layout and returns whether there were any
+pub fn check(
+    registry: &SourceRegistry,
+    format: Option<&str>,
+    path: &Path,
+    json: bool,
+) -> Result<bool, Error> {
+    let source = registry.open(path, format)?;
+    let problems = source.lock().unwrap().check()?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&problems)?);
+    } else if problems.is_empty() {
+        println!("{}: ok", path.display());
+    } else {
+        for problem in &problems {
+            println!("{problem}");
+        }
+    }
+    Ok(!problems.is_empty())
+}
+
+/// Writes the name, dtype, shape, and SHA-256 of every tensor as JSON to `out`, or prints it
+pub fn manifest(
+    registry: &SourceRegistry,
+    format: Option<&str>,
+    split: &PathSplit,
+    path: &Path,
+    out: Option<&Path>,
+) -> Result<(), Error> {
+    let source = registry.open(path, format)?;
+    let tensors = read_tensors(&source, split)?.into_iter().collect();
+    let job = Own::new_box(ManifestJob::new(tensors));
+    start_manifest(source, job.refer());
+    while !job.is_finished() {
+        sleep(Duration::from_millis(50));
+    }
+    if let Some(err) = job.error.get() {
+        bail!("could not hash {}: {err}", path.display());
+    }
+    let json = serde_json::to_string_pretty(job.entries.get().unwrap())?;
+    match out {
+        Some(out) => fs::write(out, json + "\n")?,
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
+fn print_metadata(value: &Value) -> Result<(), Error> {
+    match value {
+        Value::String(text) => println!("{text}"),
+        other => println!("{}", serde_json::to_string_pretty(other)?),
+    }
+    Ok(())
+}
+
+/// Prints the whole metadata tree as JSON, or just the entry `key`, with strings unquoted
+pub fn meta_get(
+    registry: &SourceRegistry,
+    format: Option<&str>,
+    path: &Path,
+    key: Option<&str>,
+) -> Result<(), Error> {
+    let source = registry.open(path, format)?;
+    let mut metadata = source.lock().unwrap().metadata()?;
+    match key {
+        Some(key) => print_metadata(
+            metadata_entry(&mut metadata, key)
+                .ok_or_else(|| anyhow!("{} has no metadata key {key}", path.display()))?,
+        ),
+        None => print_metadata(&metadata),
+    }
+}
+
+/// Sets the entry `key`, parsing `value` the same way as the metadata dialogs, or deletes it
+/// when `value` is `None`
+pub fn meta_set(
+    registry: &SourceRegistry,
+    format: Option<&str>,
+    path: &Path,
+    key: &str,
+    value: Option<&str>,
+) -> Result<(), Error> {
+    let source = registry.open(path, format)?;
+    let mut source = source.lock().unwrap();
+    let mut metadata = source.metadata()?;
+    let declared = source.metadata_types()?.remove(key);
+    match value {
+        Some(value) => match metadata_entry(&mut metadata, key) {
+            // Existing values keep the type they're declared with
+            Some(entry) => {
+                *entry = match declared {
+                    Some(ty) => ty.parse(value)?,
+                    None => parse_value(value),
+                }
+            }
+            None => insert_metadata_path(&mut metadata, key, parse_value(value))?,
+        },
+        None => {
+            let (parent, last) = match key.rsplit_once('.') {
+                Some((parent, last)) if metadata.get(key).is_none() => (parent, last),
+                _ => ("", key),
+            };
+            let map = if parent.is_empty() {
+                metadata.as_object_mut()
+            } else {
+                metadata_entry(&mut metadata, parent).and_then(Value::as_object_mut)
+            };
+            if map.and_then(|map| map.remove(last)).is_none() {
+                bail!("{} has no metadata key {key}", path.display());
+            }
+        }
+    }
+    source.write_metadata(&metadata)
+}
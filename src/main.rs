@@ -1,26 +1,248 @@
-mod analysis;
 mod app;
-pub mod gguf;
-pub mod model;
-pub mod safetensors;
-pub mod storage;
+mod bookmarks;
+mod cache;
+mod config;
+pub mod export;
+mod headless;
+mod palette;
+mod recent;
+mod report;
+mod thumbnail;
 
-use clap::{CommandFactory as _, Parser};
+use checkpoint_core::model;
+use clap::{CommandFactory as _, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "checkpointui")]
 #[command(about = "TUI for inspecting safetensors files")]
 struct Cli {
-    #[arg(help = "Path to the safetensors file")]
+    #[command(subcommand)]
+    command: Option<Headless>,
+    #[arg(
+        help = "Path to the checkpoint or a directory of them, a member of a zip or tar archive as ARCHIVE!/MEMBER, or an s3:// or gs:// URL when built with the object-store feature"
+    )]
     file_path: Option<PathBuf>,
     #[arg(
-        help = "The character which separates modules in tensor paths",
+        help = "The character which separates modules in tensor paths [default: .]",
         short = 'd',
+        long
+    )]
+    module_delim: Option<char>,
+    #[arg(
+        help = "How to split tensor paths into modules: pytorch (.), flax (/), tf (/ and :), a single character, or regex:PATTERN, optionally followed by +digits to also split between letters and numbers",
+        long,
+        value_name = "SPLIT",
+        conflicts_with = "module_delim"
+    )]
+    module_split: Option<model::PathSplit>,
+    #[arg(
+        help = "Read settings from PATH instead of ~/.config/checkpointui/config.toml",
+        long,
+        value_name = "PATH"
+    )]
+    config: Option<PathBuf>,
+    #[arg(
+        help = "Color theme: dark, light, or solarized",
+        long,
+        value_name = "THEME"
+    )]
+    theme: Option<String>,
+    #[arg(
+        help = "Draw with plain ASCII characters and no color, for terminals and logs which mangle the rest",
+        long,
+        visible_alias = "no-unicode"
+    )]
+    ascii: bool,
+    #[arg(
+        help = "Open the file as FORMAT (safetensors, gguf, npz, flax, hdf5, pytorch, deepspeed, onnx, tensorrt, or coreml) instead of detecting it from its contents",
+        long,
+        value_name = "FORMAT"
+    )]
+    format: Option<String>,
+    #[arg(
+        help = "Export the named tensor instead of launching the TUI",
+        long,
+        value_name = "TENSOR",
+        requires = "output"
+    )]
+    export: Option<String>,
+    #[arg(
+        help = "Convert every float tensor to DTYPE and save a new safetensors or gguf file instead of launching the TUI",
         long,
-        default_value_t = '.'
+        value_name = "DTYPE",
+        requires = "output",
+        conflicts_with = "export"
+    )]
+    convert: Option<ConvertType>,
+    #[arg(
+        help = "Where to write an exported tensor (.npy, .raw, or .csv) or converted file",
+        short = 'o',
+        long
+    )]
+    output: Option<PathBuf>,
+    #[arg(
+        help = "Scan every tensor for NaN, Inf, or all-zero data on startup",
+        long
     )]
-    module_delim: char,
+    scan: bool,
+    #[arg(
+        help = "Fold numbered modules like layers.0 ... layers.31 into a virtual layers[*] module",
+        long
+    )]
+    group_layers: bool,
+    #[arg(help = "Don't read or write cached analysis results", long)]
+    no_cache: bool,
+    #[arg(
+        help = "Never modify the opened file: metadata edits, renames, and deletes are disabled",
+        long
+    )]
+    read_only: bool,
+    #[arg(
+        help = "Keep the previous version of the file as FILE.bak whenever it is edited",
+        long,
+        conflicts_with = "read_only"
+    )]
+    backup: bool,
+}
+
+/// Commands which print their results instead of launching the TUI
+#[derive(Subcommand)]
+enum Headless {
+    #[command(
+        about = "Compare two checkpoints tensor by tensor, exiting with status 1 if any are missing, reshaped, or differ by more than the threshold"
+    )]
+    Diff {
+        a: PathBuf,
+        b: PathBuf,
+        #[arg(help = "Print the comparison as JSON", long)]
+        json: bool,
+        #[arg(
+            help = "Largest relative L2 distance ‖b - a‖ / ‖a‖ allowed between matching tensors",
+            long,
+            value_name = "DISTANCE",
+            default_value_t = 1e-3
+        )]
+        threshold: f64,
+    },
+    #[command(
+        about = "Write tensors to .npy, .raw, or .csv files, or several to a .npz archive or directory"
+    )]
+    Extract {
+        file_path: PathBuf,
+        #[arg(
+            help = "Name of a tensor to extract, where * and ? match any characters, may be repeated",
+            long,
+            value_name = "NAME",
+            required = true
+        )]
+        tensor: Vec<String>,
+        #[arg(
+            help = "Output file, or a directory when several tensors match",
+            long,
+            value_name = "PATH"
+        )]
+        out: PathBuf,
+    },
+    #[command(
+        about = "Print the mean, std, min, max, L2 norm, and NaN count of each tensor, exiting with status 1 if any have NaN or Inf values"
+    )]
+    Stats {
+        file_path: PathBuf,
+        #[arg(
+            help = "Only include tensors whose names match REGEX",
+            long,
+            value_name = "REGEX"
+        )]
+        filter: Option<regex::Regex>,
+        #[arg(help = "Print the statistics as JSON", long)]
+        json: bool,
+    },
+    #[command(
+        about = "Check that tensors are in bounds, aligned, and don't overlap, and that required metadata is present, exiting with status 1 if not"
+    )]
+    Check {
+        file_path: PathBuf,
+        #[arg(help = "Print the problems as JSON", long)]
+        json: bool,
+    },
+    #[command(about = "List the name, dtype, shape, and SHA-256 hash of every tensor as JSON")]
+    Manifest {
+        file_path: PathBuf,
+        #[arg(
+            help = "Write the manifest to PATH instead of printing it",
+            long,
+            value_name = "PATH"
+        )]
+        out: Option<PathBuf>,
+    },
+    #[command(
+        about = "Write a static HTML or Markdown report with the module tree, parameters by module, and a dtype chart, plus histograms of chosen tensors"
+    )]
+    Report {
+        file_path: PathBuf,
+        #[arg(
+            help = "Where to write the report, as Markdown if it ends in .md and HTML otherwise",
+            short = 'o',
+            long,
+            value_name = "PATH"
+        )]
+        out: PathBuf,
+        #[arg(
+            help = "Name of a tensor to draw a histogram of, where * and ? match any characters, may be repeated",
+            long,
+            value_name = "NAME"
+        )]
+        histogram: Vec<String>,
+    },
+    #[command(about = "Read or edit a checkpoint's metadata")]
+    Meta {
+        #[command(subcommand)]
+        action: MetaAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum MetaAction {
+    #[command(about = "Print all metadata as JSON, or the value of KEY")]
+    Get {
+        file_path: PathBuf,
+        #[arg(help = "A top-level key or a dotted path into nested values")]
+        key: Option<String>,
+    },
+    #[command(
+        about = "Set KEY to VALUE, which is read as null, a bool, a number, JSON, or a string"
+    )]
+    Set {
+        file_path: PathBuf,
+        key: String,
+        value: String,
+    },
+    #[command(about = "Remove KEY")]
+    Delete { file_path: PathBuf, key: String },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ConvertType {
+    F32,
+    F16,
+    Bf16,
+    #[value(name = "q8_0")]
+    Q8_0,
+    #[value(name = "q4_k")]
+    Q4K,
+}
+
+impl From<ConvertType> for model::TensorTy {
+    fn from(value: ConvertType) -> Self {
+        match value {
+            ConvertType::F32 => model::TensorTy::F32,
+            ConvertType::F16 => model::TensorTy::F16,
+            ConvertType::Bf16 => model::TensorTy::BF16,
+            ConvertType::Q8_0 => model::TensorTy::Ggml(ggml_base::Q8_0),
+            ConvertType::Q4K => model::TensorTy::Ggml(ggml_base::Q4_K),
+        }
+    }
 }
 
 fn main() -> Result<(), anyhow::Error> {
@@ -28,7 +250,142 @@ fn main() -> Result<(), anyhow::Error> {
 
     let mut app = app::App::new();
     app.helptext = Cli::command().render_long_help().to_string();
-    app.path_split = model::PathSplit::Delim(cli.module_delim);
+    let mut config = config::Config::load(cli.config.as_deref())?;
+    app.config_path = cli.config.clone();
+    if let Some(theme) = cli.theme {
+        config.theme = Some(theme);
+    }
+    app.apply_config(&config)?;
+    app.path_split = match (cli.module_split, cli.module_delim, &config.module_split) {
+        (Some(split), _, _) => split,
+        (None, Some(delim), _) => model::PathSplit::Delim(delim),
+        (None, None, Some(split)) if config.module_delim.is_none() => split.parse()?,
+        _ => model::PathSplit::Delim(config.module_delim.unwrap_or('.')),
+    };
+    app.format = cli.format;
+    app.ascii = cli.ascii;
+    // See https://no-color.org
+    app.no_color = cli.ascii || std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    app.group_layers = cli.group_layers;
+    app.read_only = cli.read_only;
+    if cli.backup {
+        app.registry.backup = true;
+    }
+    if !cli.no_cache {
+        app.cache = cache::AnalysisCache::open();
+    }
+    app.recent = recent::RecentFiles::load();
+    app.bookmarks = bookmarks::Bookmarks::load();
+
+    match cli.command {
+        Some(Headless::Diff {
+            a,
+            b,
+            json,
+            threshold,
+        }) => {
+            let paths = [a.as_path(), b.as_path()];
+            let format = app.format.as_deref();
+            if headless::diff(
+                &app.registry,
+                format,
+                &app.path_split,
+                paths,
+                threshold,
+                json,
+            )? {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Headless::Extract {
+            file_path,
+            tensor,
+            out,
+        }) => {
+            let format = app.format.as_deref();
+            return headless::extract(
+                &app.registry,
+                format,
+                &app.path_split,
+                &file_path,
+                &tensor,
+                &out,
+            );
+        }
+        Some(Headless::Stats {
+            file_path,
+            filter,
+            json,
+        }) => {
+            let format = app.format.as_deref();
+            let split = &app.path_split;
+            if headless::stats(
+                &app.registry,
+                format,
+                split,
+                &file_path,
+                filter.as_ref(),
+                json,
+            )? {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Headless::Check { file_path, json }) => {
+            let format = app.format.as_deref();
+            if headless::check(&app.registry, format, &file_path, json)? {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Headless::Manifest { file_path, out }) => {
+            let format = app.format.as_deref();
+            return headless::manifest(
+                &app.registry,
+                format,
+                &app.path_split,
+                &file_path,
+                out.as_deref(),
+            );
+        }
+        Some(Headless::Report {
+            file_path,
+            out,
+            histogram,
+        }) => {
+            let format = app.format.as_deref();
+            return report::report(
+                &app.registry,
+                format,
+                &app.path_split,
+                &file_path,
+                &out,
+                &histogram,
+            );
+        }
+        Some(Headless::Meta { action }) => {
+            let format = app.format.as_deref();
+            let registry = &app.registry;
+            if app.read_only && !matches!(action, MetaAction::Get { .. }) {
+                anyhow::bail!("can't edit metadata with --read-only");
+            }
+            return match action {
+                MetaAction::Get { file_path, key } => {
+                    headless::meta_get(registry, format, &file_path, key.as_deref())
+                }
+                MetaAction::Set {
+                    file_path,
+                    key,
+                    value,
+                } => headless::meta_set(registry, format, &file_path, &key, Some(&value)),
+                MetaAction::Delete { file_path, key } => {
+                    headless::meta_set(registry, format, &file_path, &key, None)
+                }
+            };
+        }
+        None => {}
+    }
 
     if let Some(file_path) = cli.file_path {
         if let Err(e) = app.load_file(file_path) {
@@ -37,6 +394,18 @@ fn main() -> Result<(), anyhow::Error> {
         }
     }
 
+    if let (Some(tensor), Some(output)) = (&cli.export, &cli.output) {
+        return app.export_tensor(tensor, output);
+    }
+
+    if let (Some(ty), Some(output)) = (cli.convert, &cli.output) {
+        return app.save_as(output, ty.into());
+    }
+
+    if cli.scan {
+        app.start_health_scan();
+    }
+
     let mut terminal = app::setup_terminal()?;
     let result = app.run(&mut terminal);
     app::restore_terminal(&mut terminal)?;
@@ -0,0 +1,334 @@
+use std::ops::Deref;
+
+/// An action which can be run from the `:` command palette
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Open,
+    Browse,
+    Export,
+    SaveAs,
+    Rename,
+    Delete,
+    AddMetadata,
+    Sort,
+    Expand,
+    Collapse,
+    Table,
+    Lora,
+    Similarity,
+    Progression,
+    Heads,
+    Duplicates,
+    Hash,
+    Manifest,
+    Check,
+    Breakdown,
+    Treemap,
+    Batch,
+    Layout,
+    Tokenizer,
+    Image,
+    Bytes,
+    Slice,
+    Range,
+    Mark,
+    Split,
+    Bookmark,
+    Optimizer,
+    Compute,
+    HistogramMode,
+    ChildMetric,
+    GroupLayers,
+    Flatten,
+    Filter,
+    Details,
+    QuantError,
+    LogScale,
+    Bins,
+    HealthScan,
+    Quit,
+}
+
+impl Command {
+    pub const ALL: [Command; 44] = [
+        Command::Open,
+        Command::Browse,
+        Command::Export,
+        Command::SaveAs,
+        Command::Rename,
+        Command::Delete,
+        Command::AddMetadata,
+        Command::Sort,
+        Command::Expand,
+        Command::Collapse,
+        Command::Table,
+        Command::Lora,
+        Command::Similarity,
+        Command::Progression,
+        Command::Heads,
+        Command::Duplicates,
+        Command::Hash,
+        Command::Manifest,
+        Command::Check,
+        Command::Breakdown,
+        Command::Treemap,
+        Command::Batch,
+        Command::Layout,
+        Command::Tokenizer,
+        Command::Image,
+        Command::Bytes,
+        Command::Slice,
+        Command::Range,
+        Command::Mark,
+        Command::Split,
+        Command::Bookmark,
+        Command::Optimizer,
+        Command::Compute,
+        Command::HistogramMode,
+        Command::ChildMetric,
+        Command::GroupLayers,
+        Command::Flatten,
+        Command::Filter,
+        Command::Details,
+        Command::QuantError,
+        Command::LogScale,
+        Command::Bins,
+        Command::HealthScan,
+        Command::Quit,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::Open => "open",
+            Command::Browse => "browse",
+            Command::Export => "export",
+            Command::SaveAs => "save-as",
+            Command::Rename => "rename",
+            Command::Delete => "delete",
+            Command::AddMetadata => "add-metadata",
+            Command::Sort => "sort",
+            Command::Expand => "expand",
+            Command::Collapse => "collapse",
+            Command::Table => "table",
+            Command::Lora => "lora",
+            Command::Similarity => "similarity",
+            Command::Progression => "progression",
+            Command::Heads => "heads",
+            Command::Duplicates => "duplicates",
+            Command::Hash => "hash",
+            Command::Manifest => "manifest",
+            Command::Check => "check",
+            Command::Breakdown => "breakdown",
+            Command::Treemap => "treemap",
+            Command::Batch => "batch",
+            Command::Layout => "layout",
+            Command::Tokenizer => "tokenizer",
+            Command::Image => "image",
+            Command::Bytes => "bytes",
+            Command::Slice => "slice",
+            Command::Range => "range",
+            Command::Mark => "mark",
+            Command::Split => "split",
+            Command::Bookmark => "bookmark",
+            Command::Optimizer => "optimizer",
+            Command::Compute => "compute",
+            Command::HistogramMode => "histogram-mode",
+            Command::ChildMetric => "child-metric",
+            Command::GroupLayers => "group-layers",
+            Command::Flatten => "flatten",
+            Command::Filter => "filter",
+            Command::Details => "details",
+            Command::QuantError => "quant-error",
+            Command::LogScale => "log-scale",
+            Command::Bins => "bins",
+            Command::HealthScan => "health-scan",
+            Command::Quit => "quit",
+        }
+    }
+
+    /// Placeholder shown after the name of commands which take an argument
+    pub fn argument(self) -> Option<&'static str> {
+        match self {
+            Command::Open | Command::Export | Command::Manifest => Some("<path>"),
+            Command::Bins => Some("<count>"),
+            Command::Browse => Some("[dir]"),
+            Command::Expand => Some("[depth]"),
+            Command::Slice => Some("[index]"),
+            Command::Similarity | Command::Progression => Some("[pattern]"),
+            Command::Range => Some("[min:max]"),
+            Command::Flatten => Some("[depth|off]"),
+            Command::Filter => Some("[quantized|dtype|>size]"),
+            Command::Heads => Some("[count[/kv count]]"),
+            _ => None,
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Command::Open => "Open another checkpoint",
+            Command::Browse => "Pick a checkpoint from a directory, by default the current one's",
+            Command::Export => "Export the selected tensor (.npy/.raw/.csv)",
+            Command::SaveAs => "Save a converted copy of the file",
+            Command::Rename => "Rename the selected tensor or module",
+            Command::Delete => "Delete the selected tensor or module",
+            Command::AddMetadata => "Add a metadata key, written as `key = value`",
+            Command::Sort => "Cycle the sort order of the module tree",
+            Command::Expand => "Expand the focused tree, entirely or to a depth",
+            Command::Collapse => "Collapse the focused tree",
+            Command::Table => "Show every tensor in a sortable table",
+            Command::Lora => "Pair up LoRA adapters and show their merged spectra",
+            Command::Duplicates => "Find tensors which are byte-identical or share storage",
+            Command::Hash => "Compute the SHA-256 of the selected tensor's stored bytes",
+            Command::Manifest => "Save every tensor's name, dtype, shape, and SHA-256 as JSON",
+            Command::Check => {
+                "List truncated, overlapping, or misaligned tensors and missing metadata"
+            }
+            Command::Breakdown => {
+                "Break down parameters and bytes by dtype, quantization type, and top-level module"
+            }
+            Command::Treemap => "Draw the module tree as rectangles sized by bytes",
+            Command::Batch => "Analyze every tensor under the selected module, one after another",
+            Command::Layout => {
+                "List tensors in file order with their byte ranges and the gaps between"
+            }
+            Command::Similarity => {
+                "Compare tensors matching a pattern like `*.mlp.down_proj.weight` by cosine similarity"
+            }
+            Command::Progression => {
+                "Chart the norm of tensors matching a pattern like `layers.*.mlp.up_proj.weight` by layer"
+            }
+            Command::Heads => {
+                "Split the selected attention projection into heads and compare their norms and spectra"
+            }
+            Command::Tokenizer => {
+                "Search the embedded vocabulary, test-encode text, and check token embeddings"
+            }
+            Command::Image => "Preview the image embedded in the selected metadata value",
+            Command::Bytes => "View the raw bytes of the selected tensor",
+            Command::Slice => "Analyze part of the selected tensor, like `3, :` for one row",
+            Command::Range => "Zoom the histogram into a range of values, or back out",
+            Command::Mark => "Mark the selected tensor to compare others against",
+            Command::Split => "Show the marked and selected tensors side by side",
+            Command::Bookmark => "Pin the selected tensor to the bookmarks panel, or unpin it",
+            Command::Optimizer => "Fold optimizer state (exp_avg, ...) under each parameter",
+            Command::Compute => "Compute the histogram, then the spectrum",
+            Command::HistogramMode => "Cycle the histogram mode",
+            Command::ChildMetric => "Cycle the statistic compared across a module's children",
+            Command::GroupLayers => "Fold numbered layers into a virtual layers[*] module",
+            Command::Flatten => {
+                "Merge chains of single modules into one item, below a depth if given, or stop"
+            }
+            Command::Filter => {
+                "Only show quantized tensors, one dtype, or tensors over a size like >100MB"
+            }
+            Command::Details => "Show or hide tensor shapes, dtypes, and sizes in the module tree",
+            Command::QuantError => "Compare quantization error across ggml types",
+            Command::LogScale => "Toggle log-scaled histogram counts",
+            Command::Bins => "Set the number of histogram bins",
+            Command::HealthScan => "Scan every tensor for NaN, Inf, or all-zero data",
+            Command::Quit => "Quit",
+        }
+    }
+
+    /// The keybinding for the same action, if it has one
+    pub fn key(self) -> Option<&'static str> {
+        match self {
+            Command::Open => Some("o"),
+            Command::Browse => Some("B"),
+            Command::Export => Some("x"),
+            Command::SaveAs => Some("S"),
+            Command::Rename => Some("r"),
+            Command::Delete => Some("D"),
+            Command::AddMetadata => Some("a"),
+            Command::Sort => Some("s"),
+            Command::Expand => Some("E"),
+            Command::Collapse => Some("C"),
+            Command::Table => Some("T"),
+            Command::Lora => Some("L"),
+            Command::Similarity => None,
+            Command::Progression => Some("P"),
+            Command::Heads => Some("h"),
+            Command::Duplicates => None,
+            Command::Hash => None,
+            Command::Manifest => None,
+            Command::Check => None,
+            Command::Breakdown => Some("%"),
+            Command::Treemap => Some("t"),
+            Command::Batch => Some("A"),
+            Command::Layout => Some("F"),
+            Command::Tokenizer => Some("K"),
+            Command::Image => Some("i"),
+            Command::Bytes => Some("v"),
+            Command::Slice => Some("["),
+            Command::Range => Some("z"),
+            Command::Mark => Some("m"),
+            Command::Split => Some("|"),
+            Command::Bookmark => Some("b"),
+            Command::Optimizer => Some("O"),
+            Command::Compute => Some("y"),
+            Command::HistogramMode => Some("a"),
+            Command::ChildMetric => Some("M"),
+            Command::GroupLayers => Some("G"),
+            Command::Flatten => Some("U"),
+            Command::Details => Some("w"),
+            Command::QuantError => Some("Q"),
+            Command::LogScale => Some("l"),
+            Command::HealthScan => Some("H"),
+            Command::Quit => Some("q"),
+            Command::Bins => None,
+            Command::Filter => None,
+        }
+    }
+}
+
+/// Splits palette input into the command being searched for and its argument
+pub fn split_input(input: &str) -> (&str, &str) {
+    let input = input.trim_start();
+    match input.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (input, ""),
+    }
+}
+
+/// Commands whose names fuzzily match `query`, best first
+pub fn matching_commands(query: &str) -> Vec<Command> {
+    let mut scored: Vec<_> = Command::ALL
+        .into_iter()
+        .filter_map(|command| Some((fuzzy_score(query, command.name())?, command)))
+        .collect();
+    // Stable, so ties keep the order of `Command::ALL`
+    scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+    scored.into_iter().map(|(_, command)| command).collect()
+}
+
+/// Indices of the `names` which fuzzily match `query`, best first
+pub fn matching_names<S: Deref<Target = str>>(query: &str, names: &[S]) -> Vec<usize> {
+    let mut scored: Vec<_> = names
+        .iter()
+        .enumerate()
+        .filter_map(|(i, name)| Some((fuzzy_score(query, name)?, i)))
+        .collect();
+    scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+    scored.into_iter().map(|(_, i)| i).collect()
+}
+
+/// Scores `text` if every character of `query` appears in it in order, favoring matches which
+/// are consecutive or start a word
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i32> {
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+    let mut chars = text.char_indices();
+    for q in query.chars() {
+        let (i, _) = chars.by_ref().find(|(_, t)| t.eq_ignore_ascii_case(&q))?;
+        score += 1;
+        if previous.is_some_and(|p| p + 1 == i) {
+            score += 4;
+        }
+        if i == 0 || text[..i].ends_with(['-', '_', '.', ' ']) {
+            score += 3;
+        }
+        previous = Some(i);
+    }
+    // Prefer shorter names when the match is otherwise equal
+    Some(score * 8 - text.len() as i32)
+}
@@ -0,0 +1,66 @@
+use anyhow::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use checkpoint_core::storage::is_object_url;
+
+/// How many files the start screen remembers
+const RECENT_MAX: usize = 20;
+
+/// Recently opened files, newest first, saved one per line in
+/// `~/.config/checkpointui/recent`
+#[derive(Default)]
+pub struct RecentFiles {
+    path: Option<PathBuf>,
+    pub files: Vec<PathBuf>,
+}
+
+impl RecentFiles {
+    /// Reads the list, treating a missing or unreadable file as empty
+    pub fn load() -> Self {
+        let Some(path) = Config::default_path().map(|path| path.with_file_name("recent")) else {
+            return RecentFiles::default();
+        };
+        let files = fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(PathBuf::from)
+            .take(RECENT_MAX)
+            .collect();
+        RecentFiles {
+            path: Some(path),
+            files,
+        }
+    }
+
+    /// Moves `file` to the top of the list and saves it
+    pub fn push(&mut self, file: &Path) -> Result<(), Error> {
+        // Relative paths would point somewhere else on the next launch
+        let file = match file.to_str() {
+            Some(url) if is_object_url(url) => file.to_path_buf(),
+            _ => std::path::absolute(file)?,
+        };
+        self.files.retain(|other| *other != file);
+        self.files.insert(0, file);
+        self.files.truncate(RECENT_MAX);
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut text = String::new();
+        for file in &self.files {
+            // A path with a newline in it can't be written to the list
+            if let Some(line) = file.to_str().filter(|line| !line.contains('\n')) {
+                text.push_str(line);
+                text.push('\n');
+            }
+        }
+        fs::write(path, text)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,509 @@
+//! `checkpointui report`: a static HTML or Markdown page describing a checkpoint, for sharing
+//! with people who won't open it in the TUI
+
+use anyhow::{Error, bail};
+use base64::Engine;
+use human_format::{Formatter, Scales};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::f64::consts::TAU;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::AtomicU64;
+use weakref::Own;
+
+use crate::headless::glob_regex;
+use checkpoint_core::analysis::{BarChart, Histogram, Stats};
+use checkpoint_core::model::{ModuleInfo, PathSplit, TensorInfo};
+use checkpoint_core::registry::SourceRegistry;
+
+/// Histograms in a report can be wider than in a terminal panel
+const REPORT_BIN_COUNT: usize = 64;
+
+/// How many levels of modules the parameter breakdown lists
+const BREAKDOWN_DEPTH: usize = 2;
+
+/// Colors of the dtype pie's slices, reused in order
+const PIE_COLORS: [&str; 8] = [
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#9c755f",
+];
+
+const HTML_STYLE: &str = "body { font-family: sans-serif; max-width: 960px; margin: 2em auto; }
+table { border-collapse: collapse; }
+th, td { padding: 2px 12px; text-align: left; }
+td.number { text-align: right; font-variant-numeric: tabular-nums; }
+tr:nth-child(even) { background: #f4f4f4; }
+details { margin-left: 1.2em; }
+.tensor { margin-left: 1.2em; font-family: monospace; }
+.muted { color: #777; }";
+
+/// One row of the parameter breakdown
+struct Share {
+    depth: usize,
+    name: String,
+    params: u64,
+    bytes: u64,
+}
+
+/// A histogram asked for with `--histogram`, or why it couldn't be made
+struct TensorHistogram {
+    name: String,
+    tensor: TensorInfo,
+    result: Result<(Stats, Histogram), Error>,
+}
+
+struct Report {
+    title: String,
+    root: ModuleInfo,
+    breakdown: Vec<Share>,
+    /// Parameters and bytes of each dtype, largest first
+    dtypes: Vec<(String, u64, u64)>,
+    histograms: Vec<TensorHistogram>,
+    count: Formatter,
+    bytes: Formatter,
+}
+
+/// Writes a report on the checkpoint at `path` to `out`, as Markdown if it ends in `.md` and
+/// as HTML otherwise, with histograms of the tensors matching any of `patterns`
+pub fn report(
+    registry: &SourceRegistry,
+    format: Option<&str>,
+    split: &PathSplit,
+    path: &Path,
+    out: &Path,
+    patterns: &[String],
+) -> Result<(), Error> {
+    let source = registry.open(path, format)?;
+    let root = source.lock().unwrap().module(split)?;
+    let tensors = root.tensors();
+
+    let mut selected = Vec::new();
+    for pattern in patterns {
+        let regex = glob_regex(pattern)?;
+        let before = selected.len();
+        selected.extend(tensors.iter().filter(|(name, _)| regex.is_match(name)));
+        if selected.len() == before {
+            bail!("no tensor in {} matches {pattern:?}", path.display());
+        }
+    }
+    selected.sort_by(|a, b| a.0.cmp(&b.0));
+    selected.dedup_by(|a, b| a.0 == b.0);
+
+    let mut histograms = Vec::with_capacity(selected.len());
+    for (name, tensor) in selected {
+        let progress = Own::new_box(AtomicU64::new(0));
+        let result = source
+            .lock()
+            .unwrap()
+            .tensor_f32(tensor.clone(), progress.refer())
+            .and_then(|data| {
+                let histogram = if tensor.ty.is_float() {
+                    let cancel = progress.refer().map(|_| &());
+                    Histogram::new(&data, REPORT_BIN_COUNT, false, cancel)?
+                } else {
+                    Histogram::integer(&data, REPORT_BIN_COUNT)?
+                };
+                Ok((Stats::new(&data), histogram))
+            });
+        histograms.push(TensorHistogram {
+            name: name.clone(),
+            tensor: tensor.clone(),
+            result,
+        });
+    }
+
+    let mut dtypes: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+    for (_, tensor) in &tensors {
+        let entry = dtypes.entry(tensor.ty.to_string()).or_default();
+        entry.0 += tensor.shape.iter().product::<u64>();
+        entry.1 += tensor.size as u64;
+    }
+    let mut dtypes: Vec<_> = dtypes
+        .into_iter()
+        .map(|(ty, (params, bytes))| (ty, params, bytes))
+        .collect();
+    dtypes.sort_by_key(|&(_, params, _)| Reverse(params));
+
+    let mut breakdown = Vec::new();
+    push_breakdown(&root, 0, &mut breakdown);
+
+    let mut count_scales = Scales::new();
+    count_scales
+        .with_base(1000)
+        .with_suffixes(vec!["", "K", "M", "B", "T"]);
+    let mut count = Formatter::new();
+    count.with_separator("").with_scales(count_scales);
+    let mut bytes = Formatter::new();
+    bytes.with_scales(Scales::Binary()).with_units("B");
+
+    let report = Report {
+        title: path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy()
+            .into_owned(),
+        root,
+        breakdown,
+        dtypes,
+        histograms,
+        count,
+        bytes,
+    };
+    let markdown = out
+        .extension()
+        .is_some_and(|ext| ext == "md" || ext == "markdown");
+    let text = if markdown {
+        report.markdown()?
+    } else {
+        report.html()?
+    };
+    fs::write(out, text)?;
+    println!("{}", out.display());
+    Ok(())
+}
+
+/// Lists the children of `module`, largest first, and theirs down to `BREAKDOWN_DEPTH`
+fn push_breakdown(module: &ModuleInfo, depth: usize, shares: &mut Vec<Share>) {
+    if depth == BREAKDOWN_DEPTH {
+        return;
+    }
+    let mut children: Vec<_> = module.children().values().collect();
+    children.sort_by_key(|child| Reverse(child.total_params));
+    for child in children {
+        shares.push(Share {
+            depth,
+            name: child.full_name.to_string(),
+            params: child.total_params,
+            bytes: child.total_bytes,
+        });
+        push_breakdown(child, depth + 1, shares);
+    }
+}
+
+impl Report {
+    fn format_count(&self, count: u64) -> String {
+        if count < 1000 {
+            count.to_string()
+        } else {
+            self.count.format(count as f64)
+        }
+    }
+
+    fn format_bytes(&self, bytes: u64) -> String {
+        if bytes < 1000 {
+            format!("{bytes} Bytes")
+        } else {
+            self.bytes.format(bytes as f64)
+        }
+    }
+
+    fn share(&self, params: u64) -> String {
+        let total = self.root.total_params.max(1);
+        format!("{:.1}%", params as f64 / total as f64 * 100.0)
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "{} tensors, {} parameters, {}",
+            self.root.total_tensors,
+            self.format_count(self.root.total_params),
+            self.format_bytes(self.root.total_bytes)
+        )
+    }
+
+    fn pie(&self) -> Vec<(String, u64)> {
+        self.dtypes
+            .iter()
+            .map(|(ty, params, _)| (ty.clone(), *params))
+            .collect()
+    }
+
+    fn html(&self) -> Result<String, Error> {
+        let mut out = String::new();
+        writeln!(
+            out,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">"
+        )?;
+        writeln!(out, "<title>{}</title>", escape(&self.title))?;
+        writeln!(out, "<style>\n{HTML_STYLE}\n</style>\n</head>\n<body>")?;
+        writeln!(out, "<h1>{}</h1>", escape(&self.title))?;
+        writeln!(out, "<p>{}</p>", self.summary())?;
+
+        writeln!(out, "<h2>Parameters by Module</h2>")?;
+        writeln!(
+            out,
+            "<table>\n<tr><th>Module</th><th>Parameters</th><th>Share</th><th>Size</th></tr>"
+        )?;
+        for share in &self.breakdown {
+            writeln!(
+                out,
+                "<tr><td style=\"padding-left: {}em\"><code>{}</code></td><td class=\"number\">{}</td><td class=\"number\">{}</td><td class=\"number\">{}</td></tr>",
+                0.8 + share.depth as f64 * 1.5,
+                escape(&share.name),
+                self.format_count(share.params),
+                self.share(share.params),
+                self.format_bytes(share.bytes),
+            )?;
+        }
+        writeln!(out, "</table>")?;
+
+        writeln!(out, "<h2>Data Types</h2>")?;
+        writeln!(out, "{}", pie_svg(&self.pie()))?;
+        writeln!(
+            out,
+            "<table>\n<tr><th>Type</th><th>Parameters</th><th>Share</th><th>Size</th></tr>"
+        )?;
+        for (ty, params, bytes) in &self.dtypes {
+            writeln!(
+                out,
+                "<tr><td>{ty}</td><td class=\"number\">{}</td><td class=\"number\">{}</td><td class=\"number\">{}</td></tr>",
+                self.format_count(*params),
+                self.share(*params),
+                self.format_bytes(*bytes),
+            )?;
+        }
+        writeln!(out, "</table>")?;
+
+        if !self.histograms.is_empty() {
+            writeln!(out, "<h2>Histograms</h2>")?;
+        }
+        for histogram in &self.histograms {
+            writeln!(
+                out,
+                "<h3><code>{}</code></h3>\n<p class=\"muted\">{} {:?}</p>",
+                escape(&histogram.name),
+                histogram.tensor.ty,
+                histogram.tensor.shape
+            )?;
+            match &histogram.result {
+                Ok((stats, chart)) => {
+                    writeln!(out, "<p>{}</p>", stats_line(stats))?;
+                    writeln!(out, "{}", histogram_svg(&chart.chart))?;
+                }
+                Err(err) => writeln!(out, "<p>Couldn't be read: {}</p>", escape(&err.to_string()))?,
+            }
+        }
+
+        writeln!(out, "<h2>Tensors</h2>")?;
+        for (key, child) in self.root.children() {
+            self.html_tree(key, child, &mut out)?;
+        }
+        writeln!(out, "</body>\n</html>")?;
+        Ok(out)
+    }
+
+    /// Each module folds open to its children, with its size in the summary
+    fn html_tree(&self, key: &str, module: &ModuleInfo, out: &mut String) -> Result<(), Error> {
+        let name = escape(key);
+        if let Some(tensor) = &module.tensor_info {
+            writeln!(
+                out,
+                "<div class=\"tensor\">{name} <span class=\"muted\">{} {:?}</span></div>",
+                tensor.ty, tensor.shape
+            )?;
+        }
+        if module.children().is_empty() {
+            return Ok(());
+        }
+        writeln!(
+            out,
+            "<details><summary><code>{name}</code> <span class=\"muted\">{} tensors, {} parameters</span></summary>",
+            module.total_tensors,
+            self.format_count(module.total_params)
+        )?;
+        for (key, child) in module.children() {
+            self.html_tree(key, child, out)?;
+        }
+        writeln!(out, "</details>")?;
+        Ok(())
+    }
+
+    fn markdown(&self) -> Result<String, Error> {
+        let mut out = String::new();
+        writeln!(out, "# {}\n\n{}\n", self.title, self.summary())?;
+
+        writeln!(out, "## Parameters by Module\n")?;
+        writeln!(out, "| Module | Parameters | Share | Size |")?;
+        writeln!(out, "| --- | ---: | ---: | ---: |")?;
+        for share in &self.breakdown {
+            writeln!(
+                out,
+                "| {}`{}` | {} | {} | {} |",
+                "&emsp;".repeat(share.depth),
+                share.name,
+                self.format_count(share.params),
+                self.share(share.params),
+                self.format_bytes(share.bytes),
+            )?;
+        }
+
+        writeln!(out, "\n## Data Types\n")?;
+        writeln!(out, "{}\n", svg_image("Data types", &pie_svg(&self.pie())))?;
+        writeln!(out, "| Type | Parameters | Share | Size |")?;
+        writeln!(out, "| --- | ---: | ---: | ---: |")?;
+        for (ty, params, bytes) in &self.dtypes {
+            writeln!(
+                out,
+                "| {ty} | {} | {} | {} |",
+                self.format_count(*params),
+                self.share(*params),
+                self.format_bytes(*bytes),
+            )?;
+        }
+
+        if !self.histograms.is_empty() {
+            writeln!(out, "\n## Histograms")?;
+        }
+        for histogram in &self.histograms {
+            writeln!(
+                out,
+                "\n### `{}`\n\n{} {:?}\n",
+                histogram.name, histogram.tensor.ty, histogram.tensor.shape
+            )?;
+            match &histogram.result {
+                Ok((stats, chart)) => {
+                    writeln!(out, "{}\n", stats_line(stats))?;
+                    let svg = histogram_svg(&chart.chart);
+                    writeln!(out, "{}", svg_image(&histogram.name, &svg))?;
+                }
+                Err(err) => writeln!(out, "Couldn't be read: {err}")?,
+            }
+        }
+
+        writeln!(out, "\n## Tensors\n")?;
+        for (key, child) in self.root.children() {
+            self.markdown_tree(key, child, 0, &mut out)?;
+        }
+        Ok(out)
+    }
+
+    fn markdown_tree(
+        &self,
+        key: &str,
+        module: &ModuleInfo,
+        depth: usize,
+        out: &mut String,
+    ) -> Result<(), Error> {
+        let indent = "  ".repeat(depth);
+        match &module.tensor_info {
+            Some(tensor) => writeln!(out, "{indent}- `{key}` {} {:?}", tensor.ty, tensor.shape)?,
+            None => writeln!(
+                out,
+                "{indent}- `{key}` ({} parameters)",
+                self.format_count(module.total_params)
+            )?,
+        }
+        for (key, child) in module.children() {
+            self.markdown_tree(key, child, depth + 1, out)?;
+        }
+        Ok(())
+    }
+}
+
+fn stats_line(stats: &Stats) -> String {
+    format!(
+        "mean {:.4e}, std {:.4e}, min {:.4e}, max {:.4e}, L2 norm {:.4e}, {:.2}% zeros",
+        stats.mean,
+        stats.std,
+        stats.min,
+        stats.max,
+        stats.l2_norm,
+        stats.zero_fraction * 100.0
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Markdown can't hold an SVG inline, so it is embedded as a data URI
+fn svg_image(alt: &str, svg: &str) -> String {
+    let data = base64::engine::general_purpose::STANDARD.encode(svg);
+    format!("![{alt}](data:image/svg+xml;base64,{data})")
+}
+
+/// Draws the bins as bars, with the range of values they span labeled underneath
+fn histogram_svg(chart: &BarChart) -> String {
+    const WIDTH: f64 = 640.0;
+    const HEIGHT: f64 = 200.0;
+    const LABELS: f64 = 20.0;
+    let max = chart.bins.iter().copied().max().unwrap_or(0).max(1) as f64;
+    let bar_width = WIDTH / chart.bins.len().max(1) as f64;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" height=\"{}\" viewBox=\"0 0 {WIDTH} {}\" font-family=\"sans-serif\" font-size=\"12\">",
+        HEIGHT + LABELS,
+        HEIGHT + LABELS
+    );
+    for (i, &count) in chart.bins.iter().enumerate() {
+        let height = count as f64 / max * HEIGHT;
+        let _ = write!(
+            svg,
+            "<rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{height:.2}\" fill=\"{}\"><title>{count}</title></rect>",
+            i as f64 * bar_width,
+            HEIGHT - height,
+            bar_width,
+            PIE_COLORS[0],
+        );
+    }
+    let left = if chart.continues_past_left { "<" } else { "" };
+    let right = if chart.continues_past_right { ">" } else { "" };
+    let _ = write!(
+        svg,
+        "<text x=\"0\" y=\"{y}\">{}{:.3}</text><text x=\"{WIDTH}\" y=\"{y}\" text-anchor=\"end\">{:.3}{}</text></svg>",
+        escape(left),
+        chart.left,
+        chart.right,
+        escape(right),
+        y = HEIGHT + LABELS - 4.0,
+    );
+    svg
+}
+
+/// Draws each slice's share of the total, with a legend beside the pie
+fn pie_svg(slices: &[(String, u64)]) -> String {
+    const RADIUS: f64 = 80.0;
+    let total = slices.iter().map(|(_, value)| value).sum::<u64>().max(1) as f64;
+    let height = (RADIUS * 2.0 + 8.0).max(slices.len() as f64 * 20.0 + 8.0);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"420\" height=\"{height}\" viewBox=\"0 0 420 {height}\" font-family=\"sans-serif\" font-size=\"12\">"
+    );
+    let (cx, cy) = (RADIUS + 4.0, RADIUS + 4.0);
+    let mut angle = 0.0;
+    for (i, (label, value)) in slices.iter().enumerate() {
+        let color = PIE_COLORS[i % PIE_COLORS.len()];
+        let sweep = *value as f64 / total * TAU;
+        if sweep >= TAU - 1e-9 {
+            // An arc can't start and end at the same point, so a lone slice is a circle
+            let _ = write!(
+                svg,
+                "<circle cx=\"{cx}\" cy=\"{cy}\" r=\"{RADIUS}\" fill=\"{color}\"/>"
+            );
+        } else if sweep > 0.0 {
+            let point = |angle: f64| (cx + RADIUS * angle.sin(), cy - RADIUS * angle.cos());
+            let (x0, y0) = point(angle);
+            let (x1, y1) = point(angle + sweep);
+            let large = u8::from(sweep > TAU / 2.0);
+            let _ = write!(
+                svg,
+                "<path d=\"M{cx},{cy} L{x0:.2},{y0:.2} A{RADIUS},{RADIUS} 0 {large} 1 {x1:.2},{y1:.2} Z\" fill=\"{color}\"/>"
+            );
+        }
+        angle += sweep;
+        let y = 8.0 + i as f64 * 20.0;
+        let _ = write!(
+            svg,
+            "<rect x=\"{}\" y=\"{y}\" width=\"12\" height=\"12\" fill=\"{color}\"/><text x=\"{}\" y=\"{}\">{} ({:.1}%)</text>",
+            RADIUS * 2.0 + 24.0,
+            RADIUS * 2.0 + 42.0,
+            y + 11.0,
+            escape(label),
+            *value as f64 / total * 100.0
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
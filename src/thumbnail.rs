@@ -0,0 +1,69 @@
+use anyhow::{Error, anyhow, bail};
+use base64::Engine;
+use image::RgbaImage;
+use image::imageops::{FilterType, resize};
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::style::Color;
+use ratatui::widgets::Widget;
+
+/// Decodes a `data:image/...;base64,` URI, as embedded in LoRA metadata by training tools
+pub fn decode_data_uri(uri: &str) -> Result<RgbaImage, Error> {
+    let Some(rest) = uri.strip_prefix("data:image/") else {
+        bail!("not an image data URI");
+    };
+    let (header, data) = rest
+        .split_once(',')
+        .ok_or_else(|| anyhow!("data URI has no payload"))?;
+    if !header.ends_with(";base64") {
+        bail!("only base64 image data URIs are supported");
+    }
+    let bytes = base64::engine::general_purpose::STANDARD.decode(data.trim())?;
+    Ok(image::load_from_memory(&bytes)?.to_rgba8())
+}
+
+/// Draws an image with `▀` characters, two pixels per cell, which any truecolor terminal
+/// can show
+pub struct HalfBlocks<'a> {
+    pub image: &'a RgbaImage,
+    pub background: Color,
+}
+
+impl Widget for HalfBlocks<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let (width, height) = self.image.dimensions();
+        if width == 0 || height == 0 || area.is_empty() {
+            return;
+        }
+        // Fit inside the area, keeping the aspect ratio with cells two pixels tall
+        let scale = f64::min(
+            area.width as f64 / width as f64,
+            area.height as f64 * 2.0 / height as f64,
+        );
+        let fit_width = ((width as f64 * scale) as u32).max(1);
+        let fit_height = ((height as f64 * scale) as u32).max(1);
+        let fitted = resize(self.image, fit_width, fit_height, FilterType::Triangle);
+        let left = area.x + (area.width - fit_width as u16) / 2;
+        let top = area.y + (area.height - fit_height.div_ceil(2) as u16) / 2;
+
+        let color = |x: u32, y: u32| {
+            if y >= fit_height {
+                return self.background;
+            }
+            let [r, g, b, a] = fitted.get_pixel(x, y).0;
+            if a < 128 {
+                self.background
+            } else {
+                Color::Rgb(r, g, b)
+            }
+        };
+        for row in 0..fit_height.div_ceil(2) {
+            for x in 0..fit_width {
+                let cell = &mut buf[(left + x as u16, top + row as u16)];
+                cell.set_char('▀')
+                    .set_fg(color(x, row * 2))
+                    .set_bg(color(x, row * 2 + 1));
+            }
+        }
+    }
+}